@@ -0,0 +1,66 @@
+//! Benchmarks applying small patches to large files
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use patch::{
+    ApplyOptions, CompiledPatch, Patch, apply, apply_cow, apply_with_options, find_replace_apply,
+};
+
+// A file of the given number of lines, with a patch changing one line every `every` lines
+fn sample(lines: usize, every: usize) -> (String, String) {
+    let content: String = (1..=lines)
+        .map(|line| format!("line {} of a large file that is being patched\n", line))
+        .collect();
+    let mut patch = String::from("--- a/large.txt\n+++ b/large.txt\n");
+    for line in (every..lines).step_by(every) {
+        patch.push_str(&format!(
+            "@@ -{},3 +{},3 @@\n line {} of a large file that is being patched\n\
+             -line {} of a large file that is being patched\n\
+             +line {} of a large file that was patched\n \
+             line {} of a large file that is being patched\n",
+            line - 1,
+            line - 1,
+            line - 1,
+            line,
+            line,
+            line + 1
+        ));
+    }
+    (content, patch)
+}
+
+fn bench_apply(c: &mut Criterion) {
+    let mut group = c.benchmark_group("apply");
+    for lines in [10_000, 100_000] {
+        let (content, patch_text) = sample(lines, 1_000);
+        let patch = Patch::from_single(&patch_text).unwrap();
+        group.throughput(Throughput::Bytes(content.len() as u64));
+        group.bench_with_input(BenchmarkId::new("apply", lines), &content, |b, content| {
+            b.iter(|| apply(&patch, content).unwrap())
+        });
+        let compiled = CompiledPatch::new(&patch);
+        group.bench_with_input(
+            BenchmarkId::new("compiled_apply", lines),
+            &content,
+            |b, content| b.iter(|| compiled.apply(content).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("apply_cow", lines),
+            &content,
+            |b, content| b.iter(|| apply_cow(&patch, content).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("apply_with_options", lines),
+            &content,
+            |b, content| b.iter(|| apply_with_options(&patch, content, &ApplyOptions::default())),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("find_replace_apply", lines),
+            &content,
+            |b, content| b.iter(|| find_replace_apply(&patch, content).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_apply);
+criterion_main!(benches);
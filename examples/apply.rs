@@ -6,13 +6,13 @@ fn apply(diff: Patch, old: &str) -> String {
     let old_lines = old.lines().collect::<Vec<&str>>();
     let mut out: Vec<&str> = vec![];
     let mut old_line = 0;
-    for hunk in diff.hunks {
+    for hunk in &diff.hunks {
         while old_line < hunk.old_range.start - 1 {
             out.push(old_lines[old_line as usize]);
             old_line += 1;
         }
         old_line += hunk.old_range.count;
-        for line in hunk.lines {
+        for line in &hunk.lines {
             match line {
                 Line::Add(s) | Line::Context(s) => out.push(s),
                 Line::Remove(_) => {}
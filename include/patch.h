@@ -0,0 +1,98 @@
+#ifndef PATCH_H
+#define PATCH_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The function succeeded
+#define PATCH_OK 0
+
+// A pointer was null, a string wasn't valid UTF-8, an index was out of bounds or the result
+// contains a NUL character
+#define PATCH_INVALID_ARGUMENT -1
+
+// The patch couldn't be applied to the text
+#define PATCH_APPLY_ERROR 1
+
+// A set of patches, like the ones of a file produced by `git diff`
+typedef struct PatchSet PatchSet;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Parse the patches of a file, given as `len` bytes of UTF-8 text, which don't need to end with
+// a NUL character
+//
+// The text is copied, so it can be freed once this returns. Returns null if the text can't be
+// parsed, and then stores the message of the error in `*error` unless `error` is null.
+//
+// # Safety
+//
+// `text` must point to `len` readable bytes, and `error` must be null or point to writable
+// memory for a pointer.
+PatchSet *patch_set_parse(const char *text, size_t len, char **error);
+
+// Free a patch set returned by [`patch_set_parse`], which does nothing for null
+//
+// # Safety
+//
+// `set` must be null or a pointer returned by [`patch_set_parse`] that hasn't been freed.
+void patch_set_free(PatchSet *set);
+
+// The number of patches in a set, or 0 for null
+//
+// # Safety
+//
+// `set` must be null or a valid pointer returned by [`patch_set_parse`].
+size_t patch_set_len(const PatchSet *set);
+
+// Write the patches in the Unified Format, or return null for null or if the patches contain a
+// NUL character
+//
+// # Safety
+//
+// `set` must be null or a valid pointer returned by [`patch_set_parse`].
+char *patch_set_to_string(const PatchSet *set);
+
+// Write the patches as JSON in the schema of [`PatchSet::to_json`], or return null for null
+//
+// # Safety
+//
+// `set` must be null or a valid pointer returned by [`patch_set_parse`].
+char *patch_set_to_json(const PatchSet *set);
+
+// Apply the patch at `index` in a set to a file's content, given as `len` bytes of UTF-8 text
+//
+// On success, returns [`PATCH_OK`] and stores the patched text in `*output`. Otherwise returns
+// [`PATCH_INVALID_ARGUMENT`] or [`PATCH_APPLY_ERROR`], and stores the message of the error in
+// `*error` unless `error` is null. Patched text containing a NUL character is an invalid
+// argument, as it can't be returned.
+//
+// # Safety
+//
+// `set` must be a valid pointer returned by [`patch_set_parse`], `text` must point to `len`
+// readable bytes, `output` must point to writable memory for a pointer, and `error` must be
+// null or point to writable memory for a pointer.
+int patch_set_apply(const PatchSet *set,
+                    size_t index,
+                    const char *text,
+                    size_t len,
+                    char **output,
+                    char **error);
+
+// Free a string returned by one of these functions, which does nothing for null
+//
+// # Safety
+//
+// `string` must be null or a string returned by one of these functions that hasn't been freed.
+void patch_string_free(char *string);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PATCH_H */
@@ -1,7 +1,23 @@
-use std::error::Error;
-use std::fmt;
+use alloc::borrow::{Cow, ToOwned};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::error::Error;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::{ControlFlow, RangeInclusive};
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+#[cfg(feature = "std")]
+use std::time::Instant;
 
-use crate::ast::{Line, Patch};
+use crate::ast::{Hunk, Line, Patch, Range, overlap};
+use crate::cancel::{CancellationToken, cancelled};
+use crate::generator::{DiffAlgorithm, Edit, edit_script};
+use crate::hash::FnvHasher;
+use crate::merge::{Conflict, MergeRegion, MergeResult, merge_regions};
+use crate::text::Text;
 
 /// Error that can occur while applying a patch
 #[derive(Debug)]
@@ -28,6 +44,47 @@ pub enum ApplyError {
         expected: String,
         /// The actual block of lines
         file_contents: String,
+        /// The block of lines most similar to the expected one, if the input text has enough
+        /// lines for one
+        closest: Option<ClosestMatch>,
+    },
+    /// The patch changes a binary file, which needs `apply_binary`, or only says that one
+    /// changed, and can't be applied at all
+    Binary,
+    /// The output could not be written
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    /// A hunk has no context lines to locate it by, and [`ApplyOptions::unidiff_zero`] isn't
+    /// set
+    NoContext {
+        /// The line number the patch gives for the hunk
+        line: u64,
+    },
+    /// Searching for a hunk took more steps or time than [`FindReplaceOptions::max_steps`] or
+    /// `FindReplaceOptions::deadline` allow
+    SearchLimit {
+        /// The line number the patch gives for the hunk
+        line: u64,
+    },
+    /// A hunk starts before the end of the hunk before it in the old file, so both change some
+    /// of the same lines. See [`Patch::merge_overlapping_hunks`].
+    OverlappingHunks {
+        /// The index of the hunk in the patch, counting from 0
+        hunk: usize,
+        /// The line of the old file the hunk starts at
+        line: u64,
+    },
+    /// An [`ApplyObserver`] stopped applying the patch, or the search for a hunk was stopped
+    /// by [`ApplyOptions::cancel`] or [`FindReplaceOptions::cancel`]
+    Cancelled,
+    /// The content isn't the file the patch was made for: the id of its blob doesn't match the
+    /// old id of the patch's `index` line. Only returned with the `sha1` feature, by
+    /// `verify_index` and with [`ApplyOptions::verify_index`].
+    IndexMismatch {
+        /// The old id from the `index` line, which may be abbreviated
+        expected: String,
+        /// The id of the content's blob
+        actual: String,
     },
 }
 
@@ -52,18 +109,88 @@ impl fmt::Display for ApplyError {
                     line, expected, actual
                 )
             }
-            ApplyError::HunkNotFound { expected, file_contents } => {
+            ApplyError::HunkNotFound {
+                expected,
+                file_contents,
+                closest,
+            } => {
                 write!(
                     f,
                     "Hunk not found: expected to find '{}', file has '{}'",
                     expected, file_contents
+                )?;
+                if let Some(closest) = closest {
+                    write!(
+                        f,
+                        "\nThe closest match is at line {}, {:.0}% similar:\n{}",
+                        closest.line,
+                        closest.similarity * 100.0,
+                        closest.diff
+                    )?;
+                }
+                Ok(())
+            }
+            ApplyError::Binary => write!(f, "Binary patches can't be applied to text"),
+            #[cfg(feature = "std")]
+            ApplyError::Io(error) => write!(f, "Failed to write the output: {}", error),
+            ApplyError::NoContext { line } => {
+                write!(
+                    f,
+                    "Hunk at line {} has no context lines to locate it by",
+                    line
                 )
             }
+            ApplyError::SearchLimit { line } => {
+                write!(
+                    f,
+                    "Gave up searching for the hunk at line {} after reaching the search limit",
+                    line
+                )
+            }
+            ApplyError::OverlappingHunks { hunk, line } => {
+                write!(
+                    f,
+                    "Hunks {} and {} overlap at line {}",
+                    hunk,
+                    hunk + 1,
+                    line
+                )
+            }
+            ApplyError::Cancelled => write!(f, "Applying the patch was cancelled"),
+            ApplyError::IndexMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "The patch was made for blob {}, but the content is blob {}",
+                    expected, actual
+                )
+            }
+        }
+    }
+}
+
+impl Error for ApplyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            ApplyError::Io(error) => Some(error),
+            _ => None,
         }
     }
 }
 
-impl Error for ApplyError {}
+#[cfg(feature = "std")]
+impl From<io::Error> for ApplyError {
+    fn from(error: io::Error) -> Self {
+        ApplyError::Io(error)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl From<Infallible> for ApplyError {
+    fn from(error: Infallible) -> Self {
+        match error {}
+    }
+}
 
 /// Apply a patch to the given text content
 ///
@@ -97,364 +224,3552 @@ impl Error for ApplyError {}
 /// assert_eq!(result, "line 1\nnew line 2\nline 3\n");
 /// ```
 pub fn apply(patch: &Patch, content: &str) -> Result<String, ApplyError> {
-    let lines: Vec<&str> = content.lines().collect();
-    let mut result = Vec::new();
-    let mut current_line = 0;
-
-    for hunk in &patch.hunks {
-        // Add unchanged lines before the hunk
-        let start = if hunk.old_range.start > 0 {
-            hunk.old_range.start - 1
-        } else {
-            0
-        };
-
-        while current_line < start as usize {
-            if current_line >= lines.len() {
-                return Err(ApplyError::LineOutOfBounds {
-                    line: current_line as u64 + 1,
-                    total_lines: lines.len(),
-                });
-            }
-            result.push(lines[current_line].to_string());
-            current_line += 1;
-        }
-
-        let mut hunk_old_line = current_line;
-        for line in &hunk.lines {
-            match line {
-                Line::Context(text) => {
-                    if hunk_old_line >= lines.len() {
-                        return Err(ApplyError::LineOutOfBounds {
-                            line: hunk_old_line as u64 + 1,
-                            total_lines: lines.len(),
-                        });
-                    }
-                    if lines[hunk_old_line] != *text {
-                        return Err(ApplyError::ContextMismatch {
-                            line: hunk_old_line as u64 + 1,
-                            expected: text.to_string(),
-                            actual: lines[hunk_old_line].to_string(),
-                        });
-                    }
-                    result.push(text.to_string());
-                    hunk_old_line += 1;
-                }
-                Line::Add(text) => {
-                    result.push(text.to_string());
-                }
-                Line::Remove(text) => {
-                    if hunk_old_line >= lines.len() {
-                        return Err(ApplyError::LineOutOfBounds {
-                            line: hunk_old_line as u64 + 1,
-                            total_lines: lines.len(),
-                        });
-                    }
-                    if lines[hunk_old_line] != *text {
-                        return Err(ApplyError::ContextMismatch {
-                            line: hunk_old_line as u64 + 1,
-                            expected: text.to_string(),
-                            actual: lines[hunk_old_line].to_string(),
-                        });
-                    }
-                    hunk_old_line += 1;
-                }
-            }
-        }
-        current_line = hunk_old_line;
-    }
+    let mut output = String::with_capacity(content.len());
+    apply_hunks(
+        patch,
+        content,
+        Search::Exact,
+        OnFailure::Error,
+        None,
+        &mut output,
+    )?;
+    Ok(output)
+}
 
-    // Add any remaining lines after the last hunk
-    while current_line < lines.len() {
-        result.push(lines[current_line].to_string());
-        current_line += 1;
-    }
+/// Apply a patch to the given text content, writing the output as it is produced
+///
+/// This works like [`apply`], without holding all of the output in memory: each line is written
+/// to `writer` once the lines before it are known, which suits large files and servers streaming
+/// the result out. If the patch can't be applied, the output up to the failing hunk may already
+/// have been written.
+///
+/// Writes are not buffered, so a [`BufWriter`](std::io::BufWriter) is worth using for writers
+/// that are expensive to write to, like files and sockets.
+///
+/// # Example
+///
+/// ```
+/// use patch::{Patch, apply_to_writer};
+///
+/// let content = "line 1\nline 2\nline 3\n";
+/// let patch_text = "\
+/// --- old.txt
+/// +++ new.txt
+/// @@ -1,3 +1,3 @@
+///  line 1
+/// -line 2
+/// +new line 2
+///  line 3
+/// ";
+///
+/// let patch = Patch::from_single(patch_text).unwrap();
+/// let mut output = Vec::new();
+/// apply_to_writer(&patch, content, &mut output).unwrap();
+/// assert_eq!(output, b"line 1\nnew line 2\nline 3\n");
+/// ```
+#[cfg(feature = "std")]
+pub fn apply_to_writer(
+    patch: &Patch,
+    content: &str,
+    writer: &mut impl Write,
+) -> Result<(), ApplyError> {
+    apply_hunks(
+        patch,
+        content,
+        Search::Exact,
+        OnFailure::Error,
+        None,
+        &mut WriteSink(writer),
+    )?;
+    Ok(())
+}
 
-    // Handle the end newline based on the patch's end_newline flag
-    let mut output = result.join("\n");
-    if !output.is_empty() && patch.end_newline {
-        output.push('\n');
-    }
+/// Apply a patch to the given text content, returning the output as segments that borrow the
+/// unchanged parts of the content
+///
+/// This works like [`apply`], for callers that only pass the result on, to a writer or to a
+/// network connection: the lines between the hunks are borrowed from the content in as few
+/// pieces as possible, and only the added lines are allocated. Joining the segments gives the
+/// same text as [`apply`].
+///
+/// # Example
+///
+/// ```
+/// use std::borrow::Cow;
+///
+/// use patch::{Patch, apply_cow};
+///
+/// let content = "line 1\nline 2\nline 3\n";
+/// let patch_text = "\
+/// --- old.txt
+/// +++ new.txt
+/// @@ -1,3 +1,3 @@
+///  line 1
+/// -line 2
+/// +new line 2
+///  line 3
+/// ";
+///
+/// let patch = Patch::from_single(patch_text).unwrap();
+/// let segments = apply_cow(&patch, content).unwrap();
+/// assert_eq!(
+///     segments,
+///     [
+///         Cow::Borrowed("line 1\n"),
+///         Cow::Owned("new line 2\n".to_string()),
+///         Cow::Borrowed("line 3\n"),
+///     ]
+/// );
+/// assert_eq!(segments.concat(), "line 1\nnew line 2\nline 3\n");
+/// ```
+pub fn apply_cow<'c>(patch: &Patch, content: &'c str) -> Result<Vec<Cow<'c, str>>, ApplyError> {
+    let mut segments = Segments {
+        content,
+        segments: Vec::new(),
+    };
+    apply_hunks(
+        patch,
+        content,
+        Search::Exact,
+        OnFailure::Error,
+        None,
+        &mut segments,
+    )?;
+    Ok(segments.segments)
+}
 
+/// Apply a patch to the given bytes, which don't have to be valid UTF-8
+///
+/// This works like [`apply`], for patches parsed with [`Patch::from_bytes`]. Lines are
+/// compared byte by byte, so content in any encoding is patched without losing information.
+///
+/// # Example
+///
+/// ```
+/// use patch::{Patch, apply_bytes};
+///
+/// // "caf\xe9" is "café" in Latin-1
+/// let content = b"tea\ncaf\xe9\n";
+/// let patch_text = b"--- menu.txt\n+++ menu.txt\n@@ -1,2 +1,2 @@\n-tea\n+the\xe9\n caf\xe9\n";
+///
+/// let patch = Patch::from_bytes(patch_text).unwrap();
+/// let result = apply_bytes(&patch, content).unwrap();
+/// assert_eq!(result, b"the\xe9\ncaf\xe9\n");
+/// ```
+pub fn apply_bytes(patch: &Patch<'_, [u8]>, content: &[u8]) -> Result<Vec<u8>, ApplyError> {
+    let mut output = Vec::with_capacity(content.len());
+    apply_hunks(
+        patch,
+        content,
+        Search::Exact,
+        OnFailure::Error,
+        None,
+        &mut output,
+    )?;
     Ok(output)
 }
 
-/// Applies a patch to content using a find-and-replace strategy.
+/// Apply only the hunks of a patch that `select` picks, like `git add -p` does with the hunks a
+/// user answers yes to
 ///
-/// Unlike the standard `apply` function, this method doesn't rely on exact line numbers.
-/// Instead, it searches for blocks of context and removed lines that match the patch,
-/// and replaces them with the corresponding new lines.
+/// `select` is called with every hunk and its index in [`Patch::hunks`], in order. The other
+/// hunks are left out like [`Patch::retain_hunks`] leaves them out, so the new ranges of the
+/// hunks after them are moved by the lines they would have added or removed, and the rest is
+/// applied like [`apply`] does.
 ///
-/// # Arguments
-/// * `patch` - The patch to apply
-/// * `content` - The content to apply the patch to
+/// # Example
 ///
-/// # Returns
-/// * `Ok(String)` - The patched content
-/// * `Err(ApplyError)` - If the patch couldn't be applied
-pub fn find_replace_apply(patch: &Patch, content: &str) -> Result<String, ApplyError> {
-    // Split the content into lines.
-    let mut content_lines: Vec<&str> = content.lines().collect();
+/// ```
+/// use patch::{Patch, apply_selected};
+///
+/// let content = "a\nb\nc\nd\n";
+/// let patch_text = "\
+/// --- list.txt
+/// +++ list.txt
+/// @@ -1 +1,2 @@
+///  a
+/// +a2
+/// @@ -3,2 +4,2 @@
+///  c
+/// -d
+/// +D
+/// ";
+///
+/// let patch = Patch::from_single(patch_text).unwrap();
+/// let result = apply_selected(&patch, content, |_, index| index == 1).unwrap();
+/// assert_eq!(result, "a\nb\nc\nD\n");
+/// ```
+pub fn apply_selected(
+    patch: &Patch,
+    content: &str,
+    mut select: impl FnMut(&Hunk, usize) -> bool,
+) -> Result<String, ApplyError> {
+    let mut selected = patch.clone();
+    let mut index = 0;
+    selected.retain_hunks(|hunk| {
+        index += 1;
+        select(hunk, index - 1)
+    });
+    apply(&selected, content)
+}
 
-    // Process each hunk in the patch.
-    for hunk in &patch.hunks {
-        // Gather the "old" lines: context and removed lines.
-        let old_lines: Vec<&str> = hunk
-            .lines
-            .iter()
-            .filter_map(|line| match line {
-                Line::Context(text) | Line::Remove(text) => Some(*text),
-                _ => None,
-            })
-            .collect();
+/// The block of lines most similar to a hunk that wasn't found, see [`ApplyError::HunkNotFound`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosestMatch {
+    /// The line of the input text where the block starts, counting from 1
+    pub line: u64,
+    /// How similar the block is to the expected one, between 0 and 1, see
+    /// [`FindReplaceOptions::min_similarity`]
+    pub similarity: f64,
+    /// The differences between the expected block and this one, one line per line of either
+    ///
+    /// Lines are prefixed like the lines of a hunk: lines starting with `-` are only in the
+    /// expected block, lines starting with `+` are only in this one and lines starting with a
+    /// space are in both.
+    pub diff: String,
+}
 
-        // Gather the "new" lines: context and added lines.
-        let new_lines: Vec<&str> = hunk
-            .lines
-            .iter()
-            .filter_map(|line| match line {
-                Line::Context(text) | Line::Add(text) => Some(*text),
-                _ => None,
-            })
-            .collect();
+/// Options for [`apply_with_options`]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ApplyOptions {
+    /// The maximum number of context lines that may be ignored at the start and at the end of a
+    /// hunk that doesn't match, like the `-F` option of GNU patch
+    ///
+    /// With a fuzz factor of 0, every context line of a hunk has to match.
+    pub fuzz: u8,
+    /// The number of leading components to remove from the file paths in a patch before looking
+    /// up the files to patch, like the `-p` option of GNU patch
+    ///
+    /// Patches produced by git prefix paths with `a/` and `b/`, which a strip level of 1
+    /// removes. This only matters when applying patches to files by name, for example with
+    /// `PatchSet::apply_to`. See `strip_path`.
+    pub strip: usize,
+    /// The line ending to use for every line of the output
+    ///
+    /// By default, lines taken from the content keep their own line ending and added lines take
+    /// the line ending of the line before them, so files with `\r\n` or mixed line endings keep
+    /// them. Set this to normalize the line endings of the whole output instead.
+    pub line_ending: Option<LineEnding>,
+    /// Whether to write the hunks that can't be applied to a `.rej` file next to the file they
+    /// were meant for, and apply the rest, instead of failing, like GNU patch does
+    ///
+    /// This only matters when applying patches to a directory with `apply_to_dir`. See
+    /// [`reject_patch`].
+    pub reject_files: bool,
+    /// The suffix of the backups to make of files before they are changed, like the `-b` and
+    /// `-z` options of GNU patch, usually `.orig`
+    ///
+    /// No backups are made by default. This only matters when applying patches to a directory
+    /// with `apply_to_dir`.
+    pub backup_suffix: Option<String>,
+    /// Which differences in whitespace are allowed between the lines of a hunk and the lines of
+    /// the content, like the `--ignore-whitespace` option of git apply
+    ///
+    /// Context lines that match despite such differences keep the whitespace of the content.
+    pub ignore_whitespace: Whitespace,
+    /// Whether to apply hunks without any context lines, like the ones of `diff -U0`, the way
+    /// the `--unidiff-zero` option of git apply does
+    ///
+    /// Nothing around the changes of such a hunk tells whether it is in the right place, so they
+    /// are never searched for. They are applied exactly at the line numbers of the patch, shifted
+    /// by the offset at which the hunks before them were found. By default, this is only allowed
+    /// at the start or at the end of the content, and other hunks without context fail with
+    /// [`ApplyError::NoContext`].
+    pub unidiff_zero: bool,
+    /// Whether to refuse to create, change or patch through symbolic links, instead of handling
+    /// them like git does, for directories whose links can't be trusted
+    ///
+    /// This only matters when applying patches to a directory with `apply_to_dir`, which then
+    /// fails with `FsError::Symlink` before changing anything.
+    pub forbid_symlinks: bool,
+    /// A token that stops the search for hunks when it is cancelled from another thread, after
+    /// which applying fails with [`ApplyError::Cancelled`]
+    ///
+    /// It is checked before trying every position of a hunk, so searching a large file for hunks
+    /// that aren't there can be given up on. [`apply_partial`] rejects the hunks it couldn't
+    /// search for with that error instead.
+    pub cancel: Option<CancellationToken>,
+    /// Whether to check that the content is the file a git patch was made for, by the old id of
+    /// its `index` line, before applying it, like `verify_index` does
+    ///
+    /// Applying then fails with [`ApplyError::IndexMismatch`] instead of applying the patch to
+    /// another version of the file, possibly with offsets or fuzz. [`apply_partial`] can't fail
+    /// and doesn't check the content, but `apply_to_dir` does when writing `.rej` files. This is
+    /// only checked with the `sha1` feature, and ignored without it.
+    pub verify_index: bool,
+}
 
-        // Find the occurrence of old_lines in content_lines that is closest to hunk.old_range.start.
-        let mut best_index: Option<usize> = None;
-        let mut best_distance: Option<usize> = None;
-        // Here we assume hunk.old_range.start is a 0-indexed line number.
-        let target_index = hunk.old_range.start;
+/// Which differences in whitespace are ignored when looking for the lines of a hunk in some
+/// content
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Whitespace {
+    /// Lines have to match exactly
+    #[default]
+    Exact,
+    /// Whitespace at the end of lines is ignored
+    Trailing,
+    /// All whitespace is ignored, so `a(b, c)` matches `a (b,c)`
+    All,
+    /// Lines have to match exactly, but blank lines may be missing from the content or present in
+    /// it where the hunk doesn't have them
+    ///
+    /// Blank lines of the content that are not in the hunk are kept, even among the lines the
+    /// hunk removes.
+    BlankLines,
+}
 
-        for i in 0..=content_lines.len().saturating_sub(old_lines.len()) {
-            // Skip if there aren't enough lines left to match the pattern
-            if i + old_lines.len() > content_lines.len() {
-                
-                continue;
-            }
-            
-            if content_lines[i..i + old_lines.len()] == old_lines[..] {
-                let distance = if i >= target_index as usize {
-                    i - target_index as usize
-                } else {
-                    target_index as usize - i
-                };
-                if best_distance.is_none() || distance < best_distance.unwrap() {
-                    best_distance = Some(distance);
-                    best_index = Some(i);
-                }
-            }
-        }
+/// The characters that end a line
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LineEnding {
+    /// `\n`, used on Unix-like systems
+    Lf,
+    /// `\r\n`, used on Windows
+    CrLf,
+}
 
-        // If we found an occurrence, replace that block of lines.
-        if let Some(index) = best_index {
-            content_lines.splice(index..index + old_lines.len(), new_lines.iter().cloned());
-        } else {
-            // If the expected block is not found, return an error.
-            return Err(ApplyError::HunkNotFound {
-                expected: old_lines.join("\n"),
-                file_contents: content_lines.join("\n"),
-            });
+impl LineEnding {
+    /// The characters of this line ending
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
         }
     }
+}
 
-    // Join the updated lines into a single string.
-    let new_content = content_lines.join("\n");
-    Ok(new_content)
+/// Apply a patch to the given text content, tolerating hunks that moved or whose context changed
+///
+/// Unlike [`apply`], hunks don't have to be found at exactly the line numbers given in the patch.
+/// Each hunk is searched for starting at its expected position and moving outwards, the same way
+/// GNU patch does it. Once a hunk is found at an offset, the following hunks are expected to be
+/// displaced by the same amount.
+///
+/// If a hunk can't be found anywhere, up to [`ApplyOptions::fuzz`] context lines are dropped from
+/// its start and end and the search is repeated. Dropped context lines are left untouched in the
+/// output.
+///
+/// # Example
+///
+/// ```
+/// use patch::{ApplyOptions, Patch, apply_with_options};
+///
+/// // The first line of the context doesn't match, and the hunk moved down by a line
+/// let content = "header\nline one\nline 2\nline 3\n";
+/// let patch_text = "\
+/// --- old.txt
+/// +++ new.txt
+/// @@ -1,3 +1,3 @@
+///  line 1
+/// -line 2
+/// +new line 2
+///  line 3
+/// ";
+///
+/// let patch = Patch::from_single(patch_text).unwrap();
+/// let options = ApplyOptions {
+///     fuzz: 1,
+///     ..ApplyOptions::default()
+/// };
+/// let result = apply_with_options(&patch, content, &options).unwrap();
+/// assert_eq!(result, "header\nline one\nnew line 2\nline 3\n");
+/// ```
+pub fn apply_with_options(
+    patch: &Patch,
+    content: &str,
+    options: &ApplyOptions,
+) -> Result<String, ApplyError> {
+    check_index(patch, content.as_bytes(), options)?;
+    let search = Search::nearest(options);
+    let mut output = String::with_capacity(content.len());
+    apply_hunks(
+        patch,
+        content,
+        search,
+        OnFailure::Error,
+        options.line_ending,
+        &mut output,
+    )?;
+    Ok(output)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{File, Hunk, Line, Patch, Range};
-    use std::borrow::Cow;
-    // Test 1: A simple replacement of a single line.
-    #[test]
-    fn test_simple_replace() {
-        let content = "line1\nline2\nline3";
-        let patch = Patch {
-            old: File {
-                path: Cow::Borrowed(""),
-                meta: None,
-            },
-            new: File {
-                path: Cow::Borrowed(""),
-                meta: None,
-            },
-            end_newline: true,
-            hunks: vec![Hunk {
-                old_range: Range { start: 1, count: 1 },
-                new_range: Range { start: 1, count: 1 },
-                range_hint: "",
-                lines: vec![
-                    // In the patch, we expect to remove "line2" and replace it.
-                    Line::Remove("line2"),
-                    Line::Add("line2 modified"),
-                ],
-            }],
-        };
+/// Apply a patch like [`apply_with_options`], also reporting where each hunk was applied
+///
+/// Every hunk in the returned report is [`HunkStatus::Applied`], with the position, offset and
+/// fuzz it was applied with. Formatting the report with `Display` describes the hunks that
+/// didn't apply cleanly the way GNU patch does it.
+///
+/// # Example
+///
+/// ```
+/// use patch::{ApplyOptions, Patch, apply_with_report};
+///
+/// let content = "header\nline 1\nline 2\nline 3\n";
+/// let patch_text = "\
+/// --- old.txt
+/// +++ new.txt
+/// @@ -1,3 +1,3 @@
+///  line 1
+/// -line 2
+/// +new line 2
+///  line 3
+/// ";
+///
+/// let patch = Patch::from_single(patch_text).unwrap();
+/// let (result, report) = apply_with_report(&patch, content, &ApplyOptions::default()).unwrap();
+/// assert_eq!(result, "header\nline 1\nnew line 2\nline 3\n");
+/// assert_eq!(report.to_string(), "Hunk #1 succeeded at 2 (offset 1 line).\n");
+/// ```
+pub fn apply_with_report(
+    patch: &Patch,
+    content: &str,
+    options: &ApplyOptions,
+) -> Result<(String, ApplyReport), ApplyError> {
+    check_index(patch, content.as_bytes(), options)?;
+    let search = Search::nearest(options);
+    let mut output = String::with_capacity(content.len());
+    let applied = apply_hunks(
+        patch,
+        content,
+        search,
+        OnFailure::Error,
+        options.line_ending,
+        &mut output,
+    )?;
+    Ok((
+        output,
+        ApplyReport {
+            hunks: applied.hunks,
+        },
+    ))
+}
 
-        let result = find_replace_apply(&patch, content);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "line1\nline2 modified\nline3".to_string());
+/// Callbacks that [`apply_with_observer`] calls while it applies a patch, for progress bars,
+/// logging, or stopping early
+///
+/// Every method does nothing by default. Returning [`ControlFlow::Break`] from any of them stops
+/// applying the patch, which then fails with [`ApplyError::Cancelled`].
+pub trait ApplyObserver<T: ?Sized + ToOwned = str> {
+    /// Called before the hunk at `index` in [`Patch::hunks`] is looked for in the content
+    fn on_hunk_start(&mut self, index: usize, hunk: &Hunk<'_, T>) -> ControlFlow<()> {
+        let _ = (index, hunk);
+        ControlFlow::Continue(())
     }
 
-    // Test 2: When the content contains multiple occurrences of the target block,
-    // the hunk should be applied to the occurrence closest to the specified starting index.
-    #[test]
-    fn test_multiple_occurrences_choose_closest() {
-        let content = "line1\nline2\nline3\nline2\nline3";
-        let patch = Patch {
-            old: File {
-                path: Cow::Borrowed(""),
-                meta: None,
-            },
-            new: File {
-                path: Cow::Borrowed(""),
-                meta: None,
-            },
-            end_newline: true,
-            hunks: vec![Hunk {
-                // The target index is 1.
-                old_range: Range { start: 1, count: 2 },
-                new_range: Range { start: 1, count: 2 },
-                range_hint: "",
-                lines: vec![
-                    // Old lines to match: "line2" followed by "line3"
-                    Line::Remove("line2"),
-                    Line::Remove("line3"),
-                    // New lines to replace with.
-                    Line::Add("new2"),
-                    Line::Add("new3"),
-                ],
-            }],
+    /// Called once the hunk at `index` has been applied, with the
+    /// [`HunkStatus::Applied`] that says where
+    fn on_hunk_applied(&mut self, index: usize, status: &HunkStatus) -> ControlFlow<()> {
+        let _ = (index, status);
+        ControlFlow::Continue(())
+    }
+
+    /// Called with every line of the output, without its line ending, right before it is
+    /// written
+    ///
+    /// Lines are only written once the line after them is known, so the last line a hunk
+    /// outputs comes after [`on_hunk_applied`](ApplyObserver::on_hunk_applied) for it.
+    fn on_line_emitted(&mut self, line: &T) -> ControlFlow<()> {
+        let _ = line;
+        ControlFlow::Continue(())
+    }
+}
+
+/// Apply a patch like [`apply_with_options`], telling an [`ApplyObserver`] about every hunk and
+/// every line of the output as it goes
+///
+/// # Example
+///
+/// ```
+/// use std::ops::ControlFlow;
+///
+/// use patch::{ApplyError, ApplyObserver, ApplyOptions, Patch, apply_with_observer};
+///
+/// // Stops after the given number of lines
+/// struct Limit(usize);
+///
+/// impl ApplyObserver for Limit {
+///     fn on_line_emitted(&mut self, _: &str) -> ControlFlow<()> {
+///         match self.0.checked_sub(1) {
+///             Some(left) => {
+///                 self.0 = left;
+///                 ControlFlow::Continue(())
+///             }
+///             None => ControlFlow::Break(()),
+///         }
+///     }
+/// }
+///
+/// let content = "line 1\nline 2\nline 3\n";
+/// let patch_text = "\
+/// --- old.txt
+/// +++ new.txt
+/// @@ -1,3 +1,3 @@
+///  line 1
+/// -line 2
+/// +new line 2
+///  line 3
+/// ";
+///
+/// let patch = Patch::from_single(patch_text).unwrap();
+/// let options = ApplyOptions::default();
+/// let result = apply_with_observer(&patch, content, &options, &mut Limit(3));
+/// assert_eq!(result.unwrap(), "line 1\nnew line 2\nline 3\n");
+/// assert!(matches!(
+///     apply_with_observer(&patch, content, &options, &mut Limit(2)),
+///     Err(ApplyError::Cancelled)
+/// ));
+/// ```
+pub fn apply_with_observer(
+    patch: &Patch,
+    content: &str,
+    options: &ApplyOptions,
+    observer: &mut impl ApplyObserver,
+) -> Result<String, ApplyError> {
+    check_index(patch, content.as_bytes(), options)?;
+    let search = Search::nearest(options);
+    let mut output = Observed {
+        sink: String::with_capacity(content.len()),
+        observer,
+    };
+    apply_hunks(
+        patch,
+        content,
+        search,
+        OnFailure::Error,
+        options.line_ending,
+        &mut output,
+    )?;
+    Ok(output.sink)
+}
+
+// Check the content against the `index` line of the patch, if the options ask for it
+#[cfg(feature = "sha1")]
+pub(crate) fn check_index(
+    patch: &Patch,
+    content: &[u8],
+    options: &ApplyOptions,
+) -> Result<(), ApplyError> {
+    match options.verify_index {
+        true => crate::blob::verify_index(patch, content),
+        false => Ok(()),
+    }
+}
+
+#[cfg(not(feature = "sha1"))]
+pub(crate) fn check_index(_: &Patch, _: &[u8], _: &ApplyOptions) -> Result<(), ApplyError> {
+    Ok(())
+}
+
+/// A hunk that could not be applied by [`apply_partial`]
+#[derive(Debug)]
+pub struct RejectedHunk<'a, T: ?Sized + ToOwned = str> {
+    /// The position of the hunk within [`Patch::hunks`]
+    pub index: usize,
+    /// The hunk that was rejected
+    pub hunk: Hunk<'a, T>,
+    /// Why the hunk could not be applied
+    pub error: ApplyError,
+}
+
+/// Apply as many hunks of a patch as possible, collecting the ones that fail instead of stopping
+/// at the first error
+///
+/// Hunks are located the same way [`apply_with_options`] does it. Every hunk that can't be found
+/// is left out of the output and returned as a [`RejectedHunk`], in the order they appear in the
+/// patch. Use [`reject_patch`] to turn them into the contents of a `.rej` file.
+///
+/// A binary patch can't be applied to text, so the content is returned unchanged and the patch
+/// is rejected as a whole, with an empty hunk at index 0 failing with [`ApplyError::Binary`].
+///
+/// # Example
+///
+/// ```
+/// use patch::{ApplyOptions, Patch, apply_partial};
+///
+/// let content = "A\nB\nC\nD\nE\nF\n";
+/// let patch_text = "\
+/// --- old.txt
+/// +++ new.txt
+/// @@ -1,2 +1,2 @@
+///  A
+/// -B
+/// +X
+/// @@ -5,2 +5,2 @@
+///  E
+/// -Z
+/// +Y
+/// ";
+///
+/// let patch = Patch::from_single(patch_text).unwrap();
+/// let (result, rejected) = apply_partial(&patch, content, &ApplyOptions::default());
+/// assert_eq!(result, "A\nX\nC\nD\nE\nF\n");
+/// assert_eq!(rejected.len(), 1);
+/// assert_eq!(rejected[0].index, 1);
+/// ```
+pub fn apply_partial<'a>(
+    patch: &Patch<'a>,
+    content: &str,
+    options: &ApplyOptions,
+) -> (String, Vec<RejectedHunk<'a>>) {
+    let search = Search::nearest(options);
+    let mut output = String::with_capacity(content.len());
+    let applied = apply_hunks(
+        patch,
+        content,
+        search,
+        OnFailure::Reject,
+        options.line_ending,
+        &mut output,
+    )
+    .expect("bug: partial application should reject hunks instead of failing");
+
+    let rejected = applied
+        .hunks
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, status)| match status {
+            HunkStatus::Failed(error) => Some(RejectedHunk {
+                index,
+                // A binary patch has no hunks, and fails as a whole
+                hunk: patch.hunks.get(index).cloned().unwrap_or_else(|| Hunk {
+                    old_range: Range { start: 0, count: 0 },
+                    new_range: Range { start: 0, count: 0 },
+                    range_hint: "".into(),
+                    lines: Vec::new(),
+                }),
+                error,
+            }),
+            HunkStatus::Applied { .. } => None,
+        })
+        .collect();
+    (output, rejected)
+}
+
+/// Apply a patch, marking up the hunks that can't be applied as conflicts instead of failing
+///
+/// Hunks are located the same way [`apply_with_options`] does it. When a hunk can't be found, the
+/// lines at its expected position are merged with the hunk the same way [`merge`](crate::merge)
+/// merges two sides of a change, like the fallback of `git apply -3`: the hunk's old lines are the
+/// base, the lines in the content are "ours" and the hunk's new lines are "theirs". Whatever
+/// can't be merged cleanly is surrounded by `<<<<<<< ours`, `=======` and `>>>>>>> theirs`
+/// markers.
+///
+/// In the returned [`Conflict`]s, `base` counts lines of the file the patch was made against,
+/// `ours` counts lines of `content` and `theirs` counts lines of the file the patch produces.
+///
+/// A binary patch can't be merged into text: the content is returned unchanged, without
+/// markers, as a single conflict covering all of it with empty `base` and `theirs` ranges.
+///
+/// # Example
+///
+/// ```
+/// use patch::{ApplyOptions, Patch, apply_with_conflicts};
+///
+/// let content = "A\nchanged\nC\n";
+/// let patch_text = "\
+/// --- old.txt
+/// +++ new.txt
+/// @@ -1,3 +1,3 @@
+///  A
+/// -B
+/// +X
+///  C
+/// ";
+///
+/// let patch = Patch::from_single(patch_text).unwrap();
+/// let result = apply_with_conflicts(&patch, content, &ApplyOptions::default());
+/// assert_eq!(result.conflicts.len(), 1);
+/// assert_eq!(
+///     result.text,
+///     "A\n<<<<<<< ours\nchanged\n=======\nX\n>>>>>>> theirs\nC\n",
+/// );
+/// ```
+pub fn apply_with_conflicts(patch: &Patch, content: &str, options: &ApplyOptions) -> MergeResult {
+    let search = Search::nearest(options);
+    let mut output = String::with_capacity(content.len());
+    let applied = apply_hunks(
+        patch,
+        content,
+        search,
+        OnFailure::Conflict,
+        options.line_ending,
+        &mut output,
+    )
+    .expect("bug: conflict application should mark up hunks instead of failing");
+    MergeResult {
+        text: output,
+        conflicts: applied.conflicts,
+    }
+}
+
+/// Build a patch out of the hunks that were rejected while applying `patch`
+///
+/// The result has the same file headers as `patch` and only contains the rejected hunks, or
+/// the binary change of a binary patch, which is rejected as a whole.
+/// Formatting it with `Display` produces the contents of a `.rej` file, like the ones GNU patch
+/// leaves behind for hunks it couldn't apply.
+///
+/// The ranges of the hunks are recalculated so the result is a valid patch on its own: the
+/// line counts are taken from the lines of each hunk, and the start of the new range only
+/// accounts for the lines added and removed by the rejected hunks before it.
+///
+/// # Example
+///
+/// ```
+/// use patch::{ApplyOptions, Patch, apply_partial, reject_patch};
+///
+/// let patch_text = "\
+/// --- old.txt
+/// +++ new.txt
+/// @@ -1,2 +1,2 @@
+///  A
+/// -B
+/// +X
+/// ";
+///
+/// let patch = Patch::from_single(patch_text).unwrap();
+/// let (_, rejected) = apply_partial(&patch, "C\nD\n", &ApplyOptions::default());
+/// assert_eq!(format!("{}\n", reject_patch(&patch, &rejected)), patch_text);
+/// ```
+pub fn reject_patch<'a>(patch: &Patch<'a>, rejected: &[RejectedHunk<'a>]) -> Patch<'a> {
+    let last_rejected = rejected
+        .last()
+        .is_some_and(|reject| reject.index + 1 == patch.hunks.len());
+    Patch {
+        old: patch.old.clone(),
+        new: patch.new.clone(),
+        operation: patch.operation.clone(),
+        git: patch.git.clone(),
+        svn: None,
+        hunks: match patch.is_binary() {
+            true => Vec::new(),
+            false => rejected_hunks(rejected),
+        },
+        binary: patch.binary.clone(),
+        opaque_binary: patch.opaque_binary,
+        preamble: None,
+        raw: None,
+        old_end_newline: !last_rejected || patch.old_end_newline,
+        new_end_newline: !last_rejected || patch.new_end_newline,
+    }
+}
+
+// The rejected hunks, with their ranges as if they were the only hunks of their patch
+fn rejected_hunks<'a>(rejected: &[RejectedHunk<'a>]) -> Vec<Hunk<'a>> {
+    // The number of lines the rejected hunks so far add to the new file
+    let mut offset = 0i64;
+    rejected
+        .iter()
+        .map(|reject| {
+            let mut hunk = reject.hunk.clone();
+            let old_count = hunk_old_lines(&hunk).len() as u64;
+            let new_count = hunk_new_lines(&hunk).len() as u64;
+
+            // An empty range starts at the line before the hunk instead of its first line
+            let first_line = hunk.old_range.start + u64::from(old_count == 0);
+            let new_first_line = (first_line as i64 + offset).max(1) as u64;
+            hunk.old_range.count = old_count;
+            hunk.new_range = Range {
+                start: new_first_line - u64::from(new_count == 0),
+                count: new_count,
+            };
+
+            offset += new_count as i64 - old_count as i64;
+            hunk
+        })
+        .collect()
+}
+
+/// How each hunk of a patch applies to some content, see [`check`] and [`apply_with_report`]
+#[derive(Debug)]
+pub struct ApplyReport {
+    /// The status of every hunk, in the order they appear in the patch
+    pub hunks: Vec<HunkStatus>,
+}
+
+impl ApplyReport {
+    /// True if every hunk can be applied, with or without an offset or fuzz
+    pub fn succeeded(&self) -> bool {
+        self.hunks
+            .iter()
+            .all(|status| matches!(status, HunkStatus::Applied { .. }))
+    }
+}
+
+impl fmt::Display for ApplyReport {
+    // One line for every hunk that doesn't apply cleanly, like the messages of GNU patch
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, status) in self.hunks.iter().enumerate() {
+            let number = i + 1;
+            match *status {
+                HunkStatus::Applied {
+                    offset: 0, fuzz: 0, ..
+                } => {}
+                HunkStatus::Applied { line, offset, fuzz } => {
+                    write!(f, "Hunk #{} succeeded at {}", number, line)?;
+                    if fuzz > 0 {
+                        write!(f, " with fuzz {}", fuzz)?;
+                    }
+                    match offset {
+                        0 => {}
+                        1 | -1 => write!(f, " (offset {} line)", offset)?,
+                        _ => write!(f, " (offset {} lines)", offset)?,
+                    }
+                    writeln!(f, ".")?;
+                }
+                HunkStatus::Failed(ref error) => writeln!(f, "Hunk #{} FAILED: {}", number, error)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a hunk applies to some content
+#[derive(Debug)]
+pub enum HunkStatus {
+    /// The hunk can be applied
+    ///
+    /// It applies cleanly if both `offset` and `fuzz` are 0.
+    Applied {
+        /// The line of the content where the first line of the hunk was found, counting from 1
+        line: u64,
+        /// How many lines after the position given by the patch the hunk was found, which is
+        /// negative if it was found before it
+        offset: i64,
+        /// The number of context lines that had to be ignored at the start or at the end of the
+        /// hunk, see [`ApplyOptions::fuzz`]
+        fuzz: usize,
+    },
+    /// The hunk can't be applied, for the given reason
+    Failed(ApplyError),
+}
+
+/// Check how a patch applies to the given text content, without producing any output
+///
+/// Every hunk is located the same way [`apply_partial`] does it, so a hunk that can't be found
+/// doesn't stop the others from being checked, and the offsets of the hunks that follow it are
+/// the same as if it had been left out. A binary patch, which can't be applied to text, is
+/// reported as a single hunk that failed with [`ApplyError::Binary`].
+///
+/// # Example
+///
+/// ```
+/// use patch::{ApplyOptions, HunkStatus, Patch, check};
+///
+/// let content = "header\nA\nB\nC\nD\nE\nF\n";
+/// let patch_text = "\
+/// --- old.txt
+/// +++ new.txt
+/// @@ -1,2 +1,2 @@
+///  A
+/// -B
+/// +X
+/// @@ -5,2 +5,2 @@
+///  E
+/// -Z
+/// +Y
+/// ";
+///
+/// let patch = Patch::from_single(patch_text).unwrap();
+/// let report = check(&patch, content, &ApplyOptions::default());
+/// assert!(!report.succeeded());
+/// assert!(matches!(report.hunks[0], HunkStatus::Applied { line: 2, offset: 1, fuzz: 0 }));
+/// assert!(matches!(report.hunks[1], HunkStatus::Failed(_)));
+/// ```
+pub fn check(patch: &Patch, content: &str, options: &ApplyOptions) -> ApplyReport {
+    let search = Search::nearest(options);
+    let applied = apply_hunks(
+        patch,
+        content,
+        search,
+        OnFailure::Reject,
+        options.line_ending,
+        &mut Discard,
+    )
+    .expect("bug: partial application should reject hunks instead of failing");
+    ApplyReport {
+        hunks: applied.hunks,
+    }
+}
+
+/// Whether the changes of a patch are already in some content, see [`Patch::is_applied`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum AppliedState {
+    /// Every hunk of the patch has already been applied
+    Applied,
+    /// None of the hunks of the patch have been applied
+    NotApplied,
+    /// Some of the hunks of the patch have been applied, but not all of them
+    Partial,
+}
+
+impl Patch<'_> {
+    /// Check whether this patch has already been applied to the given content
+    ///
+    /// Like `git apply --check -R`, a hunk counts as applied if it can be applied in reverse,
+    /// that is if its new lines are found in the content, in order with the other hunks and at
+    /// any offset, with every context line matching. A patch without hunks is always applied,
+    /// and a binary patch, which can't be checked against text, never is.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::{AppliedState, Patch};
+    ///
+    /// let patch = Patch::from_single(
+    ///     "\
+    /// --- old.txt
+    /// +++ new.txt
+    /// @@ -1,2 +1,2 @@
+    ///  A
+    /// -B
+    /// +X
+    /// @@ -5,2 +5,2 @@
+    ///  E
+    /// -F
+    /// +Y
+    /// ",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(patch.is_applied("A\nB\nC\nD\nE\nF\n"), AppliedState::NotApplied);
+    /// assert_eq!(patch.is_applied("A\nX\nC\nD\nE\nF\n"), AppliedState::Partial);
+    /// assert_eq!(patch.is_applied("A\nX\nC\nD\nE\nY\n"), AppliedState::Applied);
+    /// ```
+    pub fn is_applied(&self, content: &str) -> AppliedState {
+        let applied = apply_hunks(
+            &self.reverse(),
+            content,
+            Search::Nearest {
+                fuzz: 0,
+                whitespace: Whitespace::Exact,
+                unidiff_zero: true,
+                cancel: None,
+            },
+            OnFailure::Reject,
+            None,
+            &mut Discard,
+        )
+        .expect("bug: partial application should reject hunks instead of failing");
+
+        let failed = applied.failed();
+        if failed == 0 {
+            AppliedState::Applied
+        } else if failed == applied.hunks.len() {
+            AppliedState::NotApplied
+        } else {
+            AppliedState::Partial
+        }
+    }
+}
+
+/// How hunks are located in the content
+#[derive(Debug, Clone, Copy)]
+enum Search<'o> {
+    /// Hunks must match at the line numbers given in the patch
+    Exact,
+    /// Hunks may be found at any offset, with up to `fuzz` lines of context ignored, until
+    /// `cancel` is cancelled. Hunks without context are only applied if `unidiff_zero` is set,
+    /// at their line numbers.
+    Nearest {
+        fuzz: usize,
+        whitespace: Whitespace,
+        unidiff_zero: bool,
+        cancel: Option<&'o CancellationToken>,
+    },
+}
+
+impl Search<'_> {
+    fn nearest(options: &ApplyOptions) -> Search<'_> {
+        Search::Nearest {
+            fuzz: options.fuzz as usize,
+            whitespace: options.ignore_whitespace,
+            unidiff_zero: options.unidiff_zero,
+            cancel: options.cancel.as_ref(),
+        }
+    }
+
+    fn whitespace(self) -> Whitespace {
+        match self {
+            Search::Exact => Whitespace::Exact,
+            Search::Nearest { whitespace, .. } => whitespace,
+        }
+    }
+}
+
+/// What to do with a hunk that can't be applied
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum OnFailure {
+    /// Stop and return the error
+    Error,
+    /// Leave the hunk out and collect it as rejected
+    Reject,
+    /// Merge the hunk into the content, marking up the conflicts
+    Conflict,
+}
+
+/// Where a hunk was found in the content
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Placement {
+    /// The index of the content line matching the first line of the hunk that was not ignored
+    start: usize,
+    /// The number of context lines ignored at the start of the hunk
+    prefix: usize,
+    /// The number of context lines ignored at the end of the hunk
+    suffix: usize,
+}
+
+/// What happened to the hunks of a patch while running it against some content
+struct Applied {
+    hunks: Vec<HunkStatus>,
+    conflicts: Vec<Conflict>,
+}
+
+impl Applied {
+    /// The number of hunks that could not be applied
+    fn failed(&self) -> usize {
+        self.hunks
+            .iter()
+            .filter(|status| matches!(status, HunkStatus::Failed(_)))
+            .count()
+    }
+}
+
+// The error writing the output can fail with, which only writers can produce
+#[cfg(feature = "std")]
+type SinkError = io::Error;
+#[cfg(not(feature = "std"))]
+type SinkError = Infallible;
+
+/// Where the output of applying a patch is written to
+trait Sink<T: ?Sized + ToOwned> {
+    fn write(&mut self, text: &T) -> Result<(), SinkError>;
+
+    // The observer to tell about the hunks and the lines of the output, if any
+    fn observer(&mut self) -> Option<&mut dyn ApplyObserver<T>> {
+        None
+    }
+}
+
+impl Sink<str> for String {
+    fn write(&mut self, text: &str) -> Result<(), SinkError> {
+        self.push_str(text);
+        Ok(())
+    }
+}
+
+impl Sink<[u8]> for Vec<u8> {
+    fn write(&mut self, text: &[u8]) -> Result<(), SinkError> {
+        self.extend_from_slice(text);
+        Ok(())
+    }
+}
+
+/// Writes the output of applying a patch to an [`io::Write`]
+#[cfg(feature = "std")]
+struct WriteSink<W>(W);
+
+#[cfg(feature = "std")]
+impl<T: ?Sized + Text, W: Write> Sink<T> for WriteSink<W> {
+    fn write(&mut self, text: &T) -> Result<(), SinkError> {
+        self.0.write_all(text.as_bytes())
+    }
+}
+
+/// Collects the output as segments, borrowing the ones that are part of the content
+struct Segments<'c> {
+    content: &'c str,
+    segments: Vec<Cow<'c, str>>,
+}
+
+impl Sink<str> for Segments<'_> {
+    fn write(&mut self, text: &str) -> Result<(), SinkError> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        // Only text that lies within the content can be borrowed from it
+        let base = self.content.as_ptr() as usize;
+        let start = (text.as_ptr() as usize).wrapping_sub(base);
+        let borrowed = (start.checked_add(text.len()) <= Some(self.content.len()))
+            .then(|| start..start + text.len());
+        match (self.segments.last_mut(), borrowed) {
+            // The next part of the content extends the segment before it
+            (Some(Cow::Borrowed(last)), Some(range))
+                if last.as_ptr() as usize + last.len() == base + range.start =>
+            {
+                let last_start = last.as_ptr() as usize - base;
+                *last = &self.content[last_start..range.end];
+            }
+            // A line ending after an added line is copied into it rather than borrowed alone
+            (Some(Cow::Owned(last)), Some(_)) if text == "\n" || text == "\r\n" => {
+                last.push_str(text)
+            }
+            (_, Some(range)) => self.segments.push(Cow::Borrowed(&self.content[range])),
+            (Some(Cow::Owned(last)), None) => last.push_str(text),
+            (_, None) => self.segments.push(Cow::Owned(text.to_string())),
+        }
+        Ok(())
+    }
+}
+
+/// Throws away the output, for when only what happens to the hunks matters
+struct Discard;
+
+impl<T: ?Sized + ToOwned> Sink<T> for Discard {
+    fn write(&mut self, _: &T) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+/// Writes the output of applying a patch to another sink, telling an observer about it
+struct Observed<'o, S, T: ?Sized + ToOwned> {
+    sink: S,
+    observer: &'o mut dyn ApplyObserver<T>,
+}
+
+impl<T: ?Sized + ToOwned, S: Sink<T>> Sink<T> for Observed<'_, S, T> {
+    fn write(&mut self, text: &T) -> Result<(), SinkError> {
+        self.sink.write(text)
+    }
+
+    fn observer(&mut self) -> Option<&mut dyn ApplyObserver<T>> {
+        Some(&mut *self.observer)
+    }
+}
+
+// The error to stop applying a patch with if an observer says so
+fn observed(flow: ControlFlow<()>) -> Result<(), ApplyError> {
+    match flow {
+        ControlFlow::Continue(()) => Ok(()),
+        ControlFlow::Break(()) => Err(ApplyError::Cancelled),
+    }
+}
+
+/// The lines of the output, which are written to a sink as soon as their line ending is known
+struct Output<'x, 's, T: ?Sized, S> {
+    sink: &'s mut S,
+    /// The content the patch is applied to, which the lines of the output are usually part of
+    content: &'x T,
+    /// The last line and the line ending it had in the content, if any. It only gets a line
+    /// ending once another line follows it, or if the output ends in a newline character.
+    pending: Option<(&'x T, Option<&'x T>)>,
+    /// The line ending given to lines that don't have their own
+    previous: &'x T,
+    /// The line ending given to every line instead of their own
+    forced: Option<&'x T>,
+    /// The number of lines in the output so far
+    len: usize,
+}
+
+impl<'x, T: ?Sized + Text, S: Sink<T>> Output<'x, '_, T, S> {
+    fn push(&mut self, line: &'x T, ending: Option<&'x T>) -> Result<(), ApplyError> {
+        if let Some(pending) = self.pending.replace((line, ending)) {
+            self.write(pending, true)?;
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    // Lines of the content, which are copied in one piece when they keep their own line endings
+    fn extend(&mut self, lines: &[(&'x T, Option<&'x T>)]) -> Result<(), ApplyError> {
+        let Some(((last, last_ending), run)) = lines.split_last() else {
+            return Ok(());
+        };
+        match (self.forced, run) {
+            (None, [(first, _), .., (_, Some(ending))]) => {
+                if let Some(pending) = self.pending.take() {
+                    self.write(pending, true)?;
+                }
+                // Only the last line of the content has no line ending, so the lines of the run
+                // follow each other in the content
+                if let Some(observer) = self.sink.observer() {
+                    for (line, _) in run {
+                        observed(observer.on_line_emitted(line))?;
+                    }
+                }
+                let start = offset(self.content, first);
+                let end = offset(self.content, ending) + ending.as_bytes().len();
+                self.sink.write(self.content.slice(start..end))?;
+                self.previous = ending;
+                self.len += run.len();
+            }
+            _ => {
+                for &(line, ending) in run {
+                    self.push(line, ending)?;
+                }
+            }
+        }
+        self.push(last, *last_ending)
+    }
+
+    // Lines without an ending of their own take the one of the line before them
+    fn write(
+        &mut self,
+        (line, ending): (&'x T, Option<&'x T>),
+        with_ending: bool,
+    ) -> Result<(), ApplyError> {
+        let ending = self.forced.or(ending).unwrap_or(self.previous);
+        self.previous = ending;
+        if let Some(observer) = self.sink.observer() {
+            observed(observer.on_line_emitted(line))?;
+        }
+        self.sink.write(line)?;
+        if with_ending {
+            self.sink.write(ending)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, end_newline: bool) -> Result<(), ApplyError> {
+        match self.pending.take() {
+            Some(pending) => self.write(pending, end_newline),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The lines of a hunk as the applier uses them, which [`CompiledPatch`](crate::CompiledPatch)
+/// keeps between applications
+#[derive(Debug)]
+pub(crate) struct PreparedHunk<'h, T: ?Sized> {
+    /// The lines the hunk expects to find in the content, in order
+    pub(crate) old_lines: Vec<&'h T>,
+    /// The lines the hunk produces, in order
+    pub(crate) new_lines: Vec<&'h T>,
+}
+
+pub(crate) fn prepare_hunks<'h, T: ?Sized + ToOwned>(
+    patch: &'h Patch<'_, T>,
+) -> Vec<PreparedHunk<'h, T>> {
+    patch
+        .hunks
+        .iter()
+        .map(|hunk| PreparedHunk {
+            old_lines: hunk_old_lines(hunk),
+            new_lines: hunk_new_lines(hunk),
+        })
+        .collect()
+}
+
+fn apply_hunks<'a, T: ?Sized + Text, S: Sink<T>>(
+    patch: &Patch<'a, T>,
+    content: &T,
+    search: Search<'_>,
+    on_failure: OnFailure,
+    line_ending: Option<LineEnding>,
+    sink: &mut S,
+) -> Result<Applied, ApplyError> {
+    let prepared = prepare_hunks(patch);
+    apply_prepared(
+        patch,
+        &prepared,
+        content,
+        search,
+        on_failure,
+        line_ending,
+        sink,
+    )
+}
+
+/// Apply a patch to text content like [`apply`], or like [`apply_with_options`] if options are
+/// given, with the lines of its hunks already prepared
+#[cfg(feature = "std")]
+pub(crate) fn apply_prepared_str(
+    patch: &Patch,
+    prepared: &[PreparedHunk<str>],
+    content: &str,
+    options: Option<&ApplyOptions>,
+) -> Result<String, ApplyError> {
+    let mut output = String::with_capacity(content.len());
+    apply_prepared(
+        patch,
+        prepared,
+        content,
+        options.map_or(Search::Exact, Search::nearest),
+        OnFailure::Error,
+        options.and_then(|options| options.line_ending),
+        &mut output,
+    )?;
+    Ok(output)
+}
+
+fn apply_prepared<'a, T: ?Sized + Text, S: Sink<T>>(
+    patch: &Patch<'a, T>,
+    prepared: &[PreparedHunk<T>],
+    content: &T,
+    search: Search<'_>,
+    on_failure: OnFailure,
+    line_ending: Option<LineEnding>,
+    sink: &mut S,
+) -> Result<Applied, ApplyError> {
+    if patch.is_binary() && on_failure == OnFailure::Error {
+        return Err(ApplyError::Binary);
+    }
+
+    let split = content.lines_with_endings();
+    let lines: Vec<&T> = split.iter().map(|(line, _)| *line).collect();
+    let mut result = Output {
+        sink,
+        content,
+        pending: None,
+        previous: split
+            .first()
+            .and_then(|(_, ending)| *ending)
+            .unwrap_or(T::literal("\n")),
+        forced: line_ending.map(|ending| T::literal(ending.as_str())),
+        len: 0,
+    };
+    let mut statuses = Vec::with_capacity(patch.hunks.len());
+    let mut conflicts = Vec::new();
+    // A binary change can't be made to text, so the content is kept as it is and the change is
+    // reported like a hunk that failed, or as a conflict over all of the content
+    if patch.is_binary() {
+        statuses.push(HunkStatus::Failed(ApplyError::Binary));
+        if on_failure == OnFailure::Conflict {
+            conflicts.push(Conflict {
+                merged: 0..lines.len(),
+                base: 0..0,
+                ours: 0..lines.len(),
+                theirs: 0..0,
+            });
+        }
+    }
+    // Whether the last hunk was applied up to the end of the content
+    let mut reached_end = false;
+    let mut current_line = 0;
+    let mut offset: isize = 0;
+
+    for (i, (hunk, prepared)) in patch.hunks.iter().zip(prepared).enumerate() {
+        if let Some(observer) = result.sink.observer() {
+            observed(observer.on_hunk_start(i, hunk))?;
+        }
+        let old_lines = prepared.old_lines.as_slice();
+        let start = range_index(&hunk.old_range);
+
+        let found = check_overlap(patch, i).and_then(|()| {
+            find_placement(hunk, old_lines, &lines, start, current_line, offset, search)
+        });
+        let placement = match found {
+            Ok(placement) => placement,
+            Err(error) if on_failure == OnFailure::Reject => {
+                statuses.push(HunkStatus::Failed(error));
+                reached_end = false;
+                continue;
+            }
+            Err(error) if on_failure == OnFailure::Conflict => {
+                statuses.push(HunkStatus::Failed(error));
+                // Take the lines where the hunk should have been as our side of the conflict
+                let ours_start =
+                    ((start as isize + offset).max(0) as usize).clamp(current_line, lines.len());
+                let ours_end = (ours_start + old_lines.len()).min(lines.len());
+                result.extend(&split[current_line..ours_start])?;
+                current_line = ours_end;
+
+                let new_lines = prepared.new_lines.as_slice();
+                let ours = &lines[ours_start..ours_end];
+                let base_start = start;
+                let theirs_start = range_index(&hunk.new_range);
+                for region in merge_regions(old_lines, ours, new_lines) {
+                    match region {
+                        MergeRegion::Resolved { lines } => {
+                            for line in lines {
+                                result.push(line, None)?;
+                            }
+                        }
+                        MergeRegion::Conflict {
+                            base,
+                            ours: our_range,
+                            theirs,
+                        } => {
+                            let merged_start = result.len;
+                            result.push(T::literal("<<<<<<< ours"), None)?;
+                            result.extend(
+                                &split[ours_start + our_range.start..ours_start + our_range.end],
+                            )?;
+                            result.push(T::literal("======="), None)?;
+                            for line in &new_lines[theirs.clone()] {
+                                result.push(line, None)?;
+                            }
+                            result.push(T::literal(">>>>>>> theirs"), None)?;
+                            conflicts.push(Conflict {
+                                merged: merged_start..result.len,
+                                base: base_start + base.start..base_start + base.end,
+                                ours: ours_start + our_range.start..ours_start + our_range.end,
+                                theirs: theirs_start + theirs.start..theirs_start + theirs.end,
+                            });
+                        }
+                    }
+                }
+                reached_end = false;
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        offset = (placement.start - placement.prefix) as isize - start as isize;
+        statuses.push(HunkStatus::Applied {
+            line: (placement.start - placement.prefix) as u64 + 1,
+            offset: offset as i64,
+            fuzz: placement.prefix.max(placement.suffix),
+        });
+
+        result.extend(&split[current_line..placement.start])?;
+        current_line = placement.start;
+
+        let applied = &hunk.lines[placement.prefix..hunk.lines.len() - placement.suffix];
+        current_line =
+            walk_hunk(
+                &lines,
+                current_line,
+                applied,
+                search.whitespace(),
+                |line| match line {
+                    OutputLine::Content(index) => {
+                        let (line, ending) = split[index];
+                        result.push(line, ending)
+                    }
+                    OutputLine::Added(text) => result.push(text, None),
+                },
+            )?;
+        reached_end = placement.suffix == 0 && current_line == lines.len();
+        if let (Some(observer), Some(status)) = (result.sink.observer(), statuses.last()) {
+            observed(observer.on_hunk_applied(i, status))?;
+        }
+    }
+
+    // Add any remaining lines after the last hunk
+    result.extend(&split[current_line..])?;
+
+    // The patch decides whether the output ends in a newline character if its last hunk reached
+    // the end of the content, otherwise the content keeps its own ending
+    let end_newline = if reached_end {
+        patch.new_end_newline
+    } else {
+        content.ends_with_newline()
+    };
+    result.finish(end_newline)?;
+
+    Ok(Applied {
+        hunks: statuses,
+        conflicts,
+    })
+}
+
+// The byte offset of a part of the content from its start
+fn offset<T: ?Sized + Text>(content: &T, part: &T) -> usize {
+    part.as_bytes().as_ptr() as usize - content.as_bytes().as_ptr() as usize
+}
+
+fn find_placement<T: ?Sized + Text>(
+    hunk: &Hunk<'_, T>,
+    old_lines: &[&T],
+    lines: &[&T],
+    start: usize,
+    current_line: usize,
+    offset: isize,
+    search: Search<'_>,
+) -> Result<Placement, ApplyError> {
+    match search {
+        Search::Exact => {
+            // Hunks can't go back to lines that have already been processed
+            let start = start.max(current_line);
+            if start > lines.len() {
+                return Err(ApplyError::LineOutOfBounds {
+                    line: lines.len() as u64 + 1,
+                    total_lines: lines.len(),
+                });
+            }
+            if !matches_at(lines, start, old_lines, Whitespace::Exact) {
+                return Err(mismatch(lines, start, old_lines));
+            }
+            Ok(Placement {
+                start,
+                prefix: 0,
+                suffix: 0,
+            })
+        }
+        Search::Nearest {
+            whitespace,
+            unidiff_zero,
+            ..
+        } if !hunk
+            .lines
+            .iter()
+            .any(|line| matches!(line, Line::Context(_))) =>
+        {
+            // Without context to search by, only the offset of the hunks before can be trusted
+            let expected = (start as isize + offset).max(0) as usize;
+            // Like git, hunks at the start or at the end of the content are anchored there
+            let anchored = expected == 0 || expected + old_lines.len() == lines.len();
+            if !unidiff_zero && !anchored {
+                return Err(ApplyError::NoContext {
+                    line: hunk.old_range.start,
+                });
+            }
+            if expected < current_line || expected > lines.len() {
+                return Err(ApplyError::LineOutOfBounds {
+                    line: expected as u64 + 1,
+                    total_lines: lines.len(),
+                });
+            }
+            if !matches_at(lines, expected, old_lines, whitespace) {
+                return Err(mismatch(lines, expected, old_lines));
+            }
+            Ok(Placement {
+                start: expected,
+                prefix: 0,
+                suffix: 0,
+            })
+        }
+        Search::Nearest {
+            fuzz,
+            whitespace,
+            cancel,
+            ..
+        } => {
+            let expected = (start as isize + offset).max(0) as usize;
+            let found = locate(
+                hunk,
+                old_lines,
+                lines,
+                expected,
+                current_line,
+                fuzz,
+                whitespace,
+                cancel,
+            );
+            match found {
+                Some(placement) => Ok(placement),
+                None if cancelled(cancel) => Err(ApplyError::Cancelled),
+                None => Err(mismatch(lines, expected.min(lines.len()), old_lines)),
+            }
+        }
+    }
+}
+
+// The index of the first line of a range, or of the line an empty range inserts before
+// Refuse the hunk at index `i` if it starts before the end of the hunk before it. The lines they
+// share have already been written by then, so it would be applied after them, or not at all.
+pub(crate) fn check_overlap<T: ?Sized + ToOwned>(
+    patch: &Patch<'_, T>,
+    i: usize,
+) -> Result<(), ApplyError> {
+    let previous = i.checked_sub(1).map(|previous| &patch.hunks[previous]);
+    match previous.and_then(|previous| overlap(previous, &patch.hunks[i])) {
+        Some(line) => Err(ApplyError::OverlappingHunks { hunk: i, line }),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn range_index(range: &Range) -> usize {
+    if range.count == 0 {
+        range.start as usize
+    } else {
+        range.start.saturating_sub(1) as usize
+    }
+}
+
+// The lines a hunk expects to find in the content, in order
+fn hunk_old_lines<'h, T: ?Sized + ToOwned>(hunk: &'h Hunk<'_, T>) -> Vec<&'h T> {
+    hunk.lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::Context(text) | Line::Remove(text) => Some(&**text),
+            Line::Add(_) => None,
+        })
+        .collect()
+}
+
+// The lines a hunk produces, in order
+fn hunk_new_lines<'h, T: ?Sized + ToOwned>(hunk: &'h Hunk<'_, T>) -> Vec<&'h T> {
+    hunk.lines
+        .iter()
+        .filter_map(|line| match line {
+            Line::Context(text) | Line::Add(text) => Some(&**text),
+            Line::Remove(_) => None,
+        })
+        .collect()
+}
+
+fn matches_at<T: ?Sized + Text>(
+    lines: &[&T],
+    start: usize,
+    expected: &[&T],
+    whitespace: Whitespace,
+) -> bool {
+    match whitespace {
+        Whitespace::Exact => lines
+            .get(start..start + expected.len())
+            .is_some_and(|found| found == expected),
+        _ => matched_len(lines, start, expected, whitespace).is_some(),
+    }
+}
+
+/// How an expected line lines up with a line of the content
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Step {
+    /// The lines match
+    Match,
+    /// The content line is a blank line the expected lines don't have
+    SkipContent,
+    /// The expected line is a blank line the content doesn't have
+    SkipExpected,
+    /// The lines don't match
+    Mismatch,
+}
+
+fn step<T: ?Sized + Text>(actual: Option<&T>, expected: &T, whitespace: Whitespace) -> Step {
+    let matches = actual.is_some_and(|actual| match whitespace {
+        Whitespace::Exact | Whitespace::BlankLines => actual == expected,
+        Whitespace::Trailing => actual.trim_end_whitespace() == expected.trim_end_whitespace(),
+        Whitespace::All => actual.eq_ignoring_whitespace(expected),
+    });
+    if matches {
+        Step::Match
+    } else if whitespace == Whitespace::BlankLines && actual.is_some_and(T::is_blank) {
+        Step::SkipContent
+    } else if whitespace == Whitespace::BlankLines && expected.is_blank() {
+        Step::SkipExpected
+    } else {
+        Step::Mismatch
+    }
+}
+
+/// Where a line of the output of a hunk comes from
+enum OutputLine<'h, T: ?Sized> {
+    /// The line of the content at the given index
+    Content(usize),
+    /// A line added by the hunk
+    Added(&'h T),
+}
+
+// Walk the lines of a hunk over the content from `start`, the same way `matched_len` matched
+// them, calling `output` with every line of the result. Returns the index of the content line
+// after the hunk.
+fn walk_hunk<'h, T: ?Sized + Text, E>(
+    lines: &[&T],
+    start: usize,
+    hunk_lines: &'h [Line<'_, T>],
+    whitespace: Whitespace,
+    mut output: impl FnMut(OutputLine<'h, T>) -> Result<(), E>,
+) -> Result<usize, E> {
+    let mut current = start;
+    for line in hunk_lines {
+        let text = match line {
+            Line::Add(text) => {
+                output(OutputLine::Added(text))?;
+                continue;
+            }
+            Line::Context(text) | Line::Remove(text) => text,
+        };
+        loop {
+            match step(lines.get(current).copied(), text, whitespace) {
+                Step::Match => {
+                    if let Line::Context(_) = line {
+                        output(OutputLine::Content(current))?;
+                    }
+                    current += 1;
+                    break;
+                }
+                Step::SkipContent => {
+                    output(OutputLine::Content(current))?;
+                    current += 1;
+                }
+                Step::SkipExpected => break,
+                Step::Mismatch => unreachable!("bug: hunk doesn't match where it was found"),
+            }
+        }
+    }
+    Ok(current)
+}
+
+// The number of content lines the expected lines match from `start`, if they do
+fn matched_len<T: ?Sized + Text>(
+    lines: &[&T],
+    start: usize,
+    expected: &[&T],
+    whitespace: Whitespace,
+) -> Option<usize> {
+    let mut current = start;
+    for text in expected {
+        loop {
+            match step(lines.get(current).copied(), text, whitespace) {
+                Step::Match => {
+                    current += 1;
+                    break;
+                }
+                Step::SkipContent => current += 1,
+                Step::SkipExpected => break,
+                Step::Mismatch => return None,
+            }
+        }
+    }
+    Some(current - start)
+}
+
+// Build the error describing why the expected lines don't match at the given position
+fn mismatch<T: ?Sized + Text>(lines: &[&T], start: usize, expected: &[&T]) -> ApplyError {
+    for (i, text) in expected.iter().enumerate() {
+        let line = start + i;
+        match lines.get(line) {
+            None => {
+                return ApplyError::LineOutOfBounds {
+                    line: line as u64 + 1,
+                    total_lines: lines.len(),
+                };
+            }
+            Some(actual) if actual != text => {
+                return ApplyError::ContextMismatch {
+                    line: line as u64 + 1,
+                    expected: text.to_str_lossy().into_owned(),
+                    actual: actual.to_str_lossy().into_owned(),
+                };
+            }
+            Some(_) => {}
+        }
+    }
+    unreachable!("bug: expected lines match at line {}", start + 1)
+}
+
+// Search for a hunk in the content, first with all of its context and then with less and less,
+// trying positions closest to where the hunk is expected first. Gives up once `cancel` is
+// cancelled.
+#[allow(clippy::too_many_arguments)]
+fn locate<T: ?Sized + Text>(
+    hunk: &Hunk<'_, T>,
+    old_lines: &[&T],
+    lines: &[&T],
+    expected: usize,
+    earliest: usize,
+    max_fuzz: usize,
+    whitespace: Whitespace,
+    cancel: Option<&CancellationToken>,
+) -> Option<Placement> {
+    let leading = hunk
+        .lines
+        .iter()
+        .take_while(|line| matches!(line, Line::Context(_)))
+        .count();
+    let trailing = hunk
+        .lines
+        .iter()
+        .rev()
+        .take_while(|line| matches!(line, Line::Context(_)))
+        .count()
+        .min(old_lines.len() - leading);
+
+    for fuzz in 0..=max_fuzz {
+        let prefix = fuzz.min(leading);
+        let suffix = fuzz.min(trailing);
+        if fuzz > 0 && prefix < fuzz && suffix < fuzz {
+            // There is no context left to ignore
+            break;
+        }
+
+        let expected_lines = &old_lines[prefix..old_lines.len() - suffix];
+        if expected_lines.is_empty() && !old_lines.is_empty() {
+            // Never apply a hunk without checking any of its lines
+            break;
+        }
+
+        let target = expected + prefix;
+        // The fewest content lines the expected lines can match
+        let min_len = match whitespace {
+            Whitespace::BlankLines => expected_lines
+                .iter()
+                .filter(|line| !line.is_blank())
+                .count(),
+            _ => expected_lines.len(),
+        };
+        let Some(latest) = lines.len().checked_sub(min_len) else {
+            break;
+        };
+        if earliest > latest {
+            break;
+        }
+        let found = nearest_first(target.clamp(earliest, latest), earliest, latest)
+            .take_while(|_| !cancelled(cancel))
+            .find(|&start| matches_at(lines, start, expected_lines, whitespace));
+        if let Some(start) = found {
+            return Some(Placement {
+                start,
+                prefix,
+                suffix,
+            });
+        }
+    }
+    None
+}
+
+// Iterate over the positions between `earliest` and `latest`, ordered by their distance to
+// `target`, preferring later positions on ties
+fn nearest_first(target: usize, earliest: usize, latest: usize) -> impl Iterator<Item = usize> {
+    let reach = (target - earliest).max(latest - target);
+    (0..=reach).flat_map(move |distance| {
+        let after = target
+            .checked_add(distance)
+            .filter(|&position| position <= latest);
+        let before = target
+            .checked_sub(distance)
+            .filter(|&position| distance > 0 && position >= earliest);
+        after.into_iter().chain(before)
+    })
+}
+
+/// Apply a patch in reverse to the given text content, undoing its changes
+///
+/// This is equivalent to applying [`Patch::reverse`], like `patch -R` does.
+///
+/// # Example
+///
+/// ```
+/// use patch::{Patch, apply_reverse};
+///
+/// let content = "line 1\nnew line 2\nline 3\n";
+/// let patch_text = "\
+/// --- old.txt
+/// +++ new.txt
+/// @@ -1,3 +1,3 @@
+///  line 1
+/// -line 2
+/// +new line 2
+///  line 3
+/// ";
+///
+/// let patch = Patch::from_single(patch_text).unwrap();
+/// let result = apply_reverse(&patch, content).unwrap();
+/// assert_eq!(result, "line 1\nline 2\nline 3\n");
+/// ```
+pub fn apply_reverse(patch: &Patch, content: &str) -> Result<String, ApplyError> {
+    apply(&patch.reverse(), content)
+}
+
+/// Applies a patch to content using a find-and-replace strategy.
+///
+/// Unlike the standard `apply` function, this method doesn't rely on exact line numbers.
+/// Instead, it searches for blocks of context and removed lines that match the patch,
+/// and replaces them with the corresponding new lines.
+///
+/// # Arguments
+/// * `patch` - The patch to apply
+/// * `content` - The content to apply the patch to
+///
+/// # Returns
+/// * `Ok(String)` - The patched content
+/// * `Err(ApplyError)` - If the patch couldn't be applied
+pub fn find_replace_apply(patch: &Patch, content: &str) -> Result<String, ApplyError> {
+    find_replace_apply_with_options(patch, content, &FindReplaceOptions::default())
+        .map(|result| result.text)
+}
+
+/// Options for [`find_replace_apply_with_options`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FindReplaceOptions {
+    /// Which differences in whitespace are allowed between the lines of a hunk and the lines of
+    /// the content
+    ///
+    /// Context lines that match despite such differences keep the whitespace of the content.
+    pub ignore_whitespace: Whitespace,
+    /// The similarity, between 0 and 1, above which a block of the content is replaced by a hunk
+    /// whose lines it doesn't match exactly
+    ///
+    /// When a hunk isn't found, every block of the content with as many lines as the hunk expects
+    /// is compared with them: the similarity of two lines is one minus their Levenshtein distance
+    /// divided by the length of the longest one, and the similarity of two blocks is the average
+    /// of the similarity of their lines. The most similar block replaces the hunk if it reaches
+    /// this threshold. By default, hunks have to match exactly.
+    pub min_similarity: Option<f64>,
+    /// How many lines away from the line the patch gives for a hunk it may be found, both
+    /// exactly and by similarity
+    ///
+    /// Hunks are looked for in the whole content by default.
+    pub max_distance: Option<usize>,
+    /// The number of line comparisons all the hunks of the patch may take to be found, after
+    /// which applying fails with [`ApplyError::SearchLimit`]
+    ///
+    /// Every block of the content a hunk is compared with costs as many steps as the hunk has
+    /// old lines. Together with [`max_distance`](Self::max_distance), this bounds the work done
+    /// for patches that can't be trusted.
+    pub max_steps: Option<usize>,
+    /// The instant after which applying fails with [`ApplyError::SearchLimit`], which is checked
+    /// before comparing every block of the content with a hunk
+    #[cfg(feature = "std")]
+    pub deadline: Option<Instant>,
+    /// A token that stops the search when it is cancelled from another thread, after which
+    /// applying fails with [`ApplyError::Cancelled`]
+    ///
+    /// It is checked as often as `deadline`, so a user can give up on a slow search by
+    /// similarity without a limit having to be chosen beforehand.
+    pub cancel: Option<CancellationToken>,
+}
+
+/// The result of [`find_replace_apply_with_options`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FindReplaceResult {
+    /// The patched content
+    pub text: String,
+    /// The similarity between each hunk and the block of the content it replaced, in the order
+    /// the hunks appear in the patch, which is 1 for hunks found exactly
+    ///
+    /// See [`FindReplaceOptions::min_similarity`].
+    pub similarity: Vec<f64>,
+}
+
+/// Applies a patch to content using a find-and-replace strategy, like [`find_replace_apply`],
+/// with the given options.
+///
+/// # Example
+/// ```
+/// use patch::{FindReplaceOptions, Patch, find_replace_apply_with_options};
+///
+/// let content = "fn main() {\n    run(false);\n}";
+/// let patch_text = "\
+/// --- old.rs
+/// +++ new.rs
+/// @@ -1,3 +1,3 @@
+///  fn main() {
+/// -    run();
+/// +    run(true);
+///  }
+/// ";
+///
+/// let patch = Patch::from_single(patch_text).unwrap();
+/// let options = FindReplaceOptions {
+///     min_similarity: Some(0.8),
+///     ..FindReplaceOptions::default()
+/// };
+/// let result = find_replace_apply_with_options(&patch, content, &options).unwrap();
+/// assert_eq!(result.text, "fn main() {\n    run(true);\n}");
+/// assert!(result.similarity[0] > 0.8 && result.similarity[0] < 1.0);
+/// ```
+pub fn find_replace_apply_with_options(
+    patch: &Patch,
+    content: &str,
+    options: &FindReplaceOptions,
+) -> Result<FindReplaceResult, ApplyError> {
+    let prepared = prepare_hunks(patch);
+    let block_hashes: Vec<_> = prepared
+        .iter()
+        .map(|hunk| block_hash(&hunk.old_lines, options.ignore_whitespace))
+        .collect();
+    find_replace_prepared(patch, &prepared, &block_hashes, content, options)
+}
+
+/// Apply a patch like [`find_replace_apply_with_options`], with the lines of its hunks already
+/// prepared and the [`block_hash`] of their old lines for the whitespace option
+pub(crate) fn find_replace_prepared(
+    patch: &Patch,
+    prepared: &[PreparedHunk<str>],
+    block_hashes: &[Option<u64>],
+    content: &str,
+    options: &FindReplaceOptions,
+) -> Result<FindReplaceResult, ApplyError> {
+    let whitespace = options.ignore_whitespace;
+
+    // Split the content into lines, and hash them to find the candidate positions of hunks.
+    let mut content_lines: Vec<&str> = content.lines().collect();
+    let mut hashes: Vec<u64> = content_lines
+        .iter()
+        .map(|line| line_hash(line, whitespace))
+        .collect();
+    let mut similarity = Vec::with_capacity(patch.hunks.len());
+    let mut budget = Budget {
+        steps: options.max_steps,
+        #[cfg(feature = "std")]
+        deadline: options.deadline,
+        cancel: options.cancel.as_ref(),
+    };
+
+    // Process each hunk in the patch, with its "old" lines: context and removed lines.
+    for ((hunk, prepared), block_hash) in patch.hunks.iter().zip(prepared).zip(block_hashes) {
+        let old_lines = prepared.old_lines.as_slice();
+        let limit = |stopped| match stopped {
+            Stopped::LimitReached => ApplyError::SearchLimit {
+                line: hunk.old_range.start,
+            },
+            Stopped::Cancelled => ApplyError::Cancelled,
+        };
+
+        // Find the occurrence of old_lines in content_lines that is closest to hunk.old_range.start.
+        let mut best: Option<(usize, usize)> = None;
+        let mut best_distance: Option<usize> = None;
+        // Here we assume hunk.old_range.start is a 0-indexed line number.
+        let target_index = hunk.old_range.start as usize;
+        // The positions the hunk may start at, within the maximum distance of the target
+        let window = match options.max_distance {
+            Some(distance) => {
+                let first = target_index
+                    .saturating_sub(distance)
+                    .min(content_lines.len());
+                first
+                    ..=target_index
+                        .saturating_add(distance)
+                        .min(content_lines.len())
+            }
+            None => 0..=content_lines.len(),
+        };
+        budget.spend(0).map_err(limit)?;
+
+        // Only the lines of the window are hashed, so blocks fully in it are the candidates
+        let hashed = &hashes[*window.start()..(window.end() + old_lines.len()).min(hashes.len())];
+        for i in candidate_starts(hashed, old_lines.len(), *block_hash) {
+            let i = window.start() + i;
+            if i > *window.end() {
+                break;
+            }
+            budget.spend(old_lines.len()).map_err(limit)?;
+            if let Some(len) = matched_len(&content_lines, i, old_lines, whitespace) {
+                let distance = i.abs_diff(target_index);
+                if best_distance.is_none_or(|best_distance| distance < best_distance) {
+                    best_distance = Some(distance);
+                    best = Some((i, len));
+                }
+            }
+        }
+
+        if let Some((index, len)) = best {
+            // Build the replacement, keeping the content's version of the context lines.
+            let mut replacement = Vec::with_capacity(len);
+            let Ok(_) = walk_hunk(&content_lines, index, &hunk.lines, whitespace, |line| {
+                replacement.push(match line {
+                    OutputLine::Content(index) => content_lines[index],
+                    OutputLine::Added(text) => text,
+                });
+                Ok::<_, Infallible>(())
+            });
+            let replaced_hashes = replacement.iter().map(|line| line_hash(line, whitespace));
+            hashes.splice(index..index + len, replaced_hashes);
+            content_lines.splice(index..index + len, replacement);
+            similarity.push(1.0);
+            continue;
+        }
+
+        // Otherwise look for the most similar block, and use it if that's allowed.
+        let closest =
+            most_similar_block(&content_lines, old_lines, target_index, window, &mut budget)
+                .map_err(limit)?;
+        let accepted = closest.filter(|&(_, score)| {
+            options
+                .min_similarity
+                .is_some_and(|min_similarity| score >= min_similarity)
+        });
+
+        // If the expected block is not found, return an error.
+        let Some((index, score)) = accepted else {
+            let closest = closest.map(|(index, similarity)| ClosestMatch {
+                line: index as u64 + 1,
+                similarity,
+                diff: mini_diff(old_lines, &content_lines[index..index + old_lines.len()]),
+            });
+            return Err(ApplyError::HunkNotFound {
+                expected: old_lines.join("\n"),
+                file_contents: content_lines.join("\n"),
+                closest,
+            });
+        };
+
+        // The block lines up with the old lines one to one.
+        let mut replacement = Vec::with_capacity(old_lines.len());
+        let mut current = index;
+        for line in &hunk.lines {
+            match line {
+                Line::Context(_) => {
+                    replacement.push(content_lines[current]);
+                    current += 1;
+                }
+                Line::Remove(_) => current += 1,
+                Line::Add(text) => replacement.push(text),
+            }
+        }
+        let replaced_hashes = replacement.iter().map(|line| line_hash(line, whitespace));
+        hashes.splice(index..current, replaced_hashes);
+        content_lines.splice(index..current, replacement);
+        similarity.push(score);
+    }
+
+    // Join the updated lines into a single string.
+    let text = content_lines.join("\n");
+    Ok(FindReplaceResult { text, similarity })
+}
+
+// The hash of a line, which is the same for lines that match with the given whitespace option
+fn line_hash(line: &str, whitespace: Whitespace) -> u64 {
+    let mut hasher = FnvHasher::default();
+    match whitespace {
+        Whitespace::Exact | Whitespace::BlankLines => line.hash(&mut hasher),
+        Whitespace::Trailing => line.trim_end().hash(&mut hasher),
+        Whitespace::All => line
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .for_each(|c| c.hash(&mut hasher)),
+    }
+    hasher.finish()
+}
+
+// The multiplier of the rolling hash of blocks of lines
+const BASE: u64 = 0x0100_0000_01b3;
+
+// Add the hash of the next line to the rolling hash of a block
+fn roll(hash: u64, line: u64) -> u64 {
+    hash.wrapping_mul(BASE).wrapping_add(line)
+}
+
+// The rolling hash of a block of lines, which `candidate_starts` looks for in the content. Blocks
+// of any length can match when blank lines are ignored, so they have no hash.
+pub(crate) fn block_hash(lines: &[&str], whitespace: Whitespace) -> Option<u64> {
+    (whitespace != Whitespace::BlankLines).then(|| {
+        lines
+            .iter()
+            .map(|line| line_hash(line, whitespace))
+            .fold(0, roll)
+    })
+}
+
+// The positions of the content where a block of `len` lines with the given hash may start, from
+// the hashes of the lines of the content. A rolling hash of every window of the content is
+// compared with the hash of the block, so only the windows whose hash matches have to be compared
+// line by line. Without a hash, every position is a candidate.
+fn candidate_starts(hashes: &[u64], len: usize, target: Option<u64>) -> Vec<usize> {
+    let Some(target) = target.filter(|_| len > 0) else {
+        return (0..=hashes.len()).collect();
+    };
+    let Some(last) = hashes.len().checked_sub(len) else {
+        return Vec::new();
+    };
+    // The weight of the line leaving the window
+    let weight = (1..len).fold(1u64, |weight, _| weight.wrapping_mul(BASE));
+
+    let mut starts = Vec::new();
+    let mut window = hashes[..len].iter().copied().fold(0, roll);
+    for start in 0..=last {
+        if window == target {
+            starts.push(start);
+        }
+        if start < last {
+            window = roll(
+                window.wrapping_sub(hashes[start].wrapping_mul(weight)),
+                hashes[start + len],
+            );
+        }
+    }
+    starts
+}
+
+// What is left of the search limits of the find-and-replace options
+struct Budget<'o> {
+    steps: Option<usize>,
+    #[cfg(feature = "std")]
+    deadline: Option<Instant>,
+    cancel: Option<&'o CancellationToken>,
+}
+
+// Why the search stopped early
+enum Stopped {
+    // The search limits ran out
+    LimitReached,
+    // The token of the options was cancelled
+    Cancelled,
+}
+
+impl Budget<'_> {
+    // Take the steps of comparing a block of lines, unless the steps or the time ran out or the
+    // search was cancelled
+    fn spend(&mut self, steps: usize) -> Result<(), Stopped> {
+        if cancelled(self.cancel) {
+            return Err(Stopped::Cancelled);
+        }
+        if let Some(left) = &mut self.steps {
+            *left = left.checked_sub(steps).ok_or(Stopped::LimitReached)?;
+        }
+        #[cfg(feature = "std")]
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(Stopped::LimitReached);
+        }
+        Ok(())
+    }
+}
+
+// The start of the block of the content most similar to the expected lines, and its similarity,
+// preferring blocks closest to `target` on ties. Only blocks starting in the window are compared.
+fn most_similar_block(
+    lines: &[&str],
+    expected: &[&str],
+    target: usize,
+    window: RangeInclusive<usize>,
+    budget: &mut Budget,
+) -> Result<Option<(usize, f64)>, Stopped> {
+    let Some(latest) = lines.len().checked_sub(expected.len()) else {
+        return Ok(None);
+    };
+    let mut best: Option<(usize, f64)> = None;
+    for start in *window.start()..=latest.min(*window.end()) {
+        budget.spend(expected.len())?;
+        let total: f64 = lines[start..start + expected.len()]
+            .iter()
+            .zip(expected)
+            .map(|(line, expected)| line_similarity(line, expected))
+            .sum();
+        let score = total / expected.len().max(1) as f64;
+        let better = best.is_none_or(|(best_start, best_score)| {
+            score > best_score
+                || (score == best_score && start.abs_diff(target) < best_start.abs_diff(target))
+        });
+        if better {
+            best = Some((start, score));
+        }
+    }
+    Ok(best)
+}
+
+// The lines of both blocks, prefixed like the lines of a hunk turning `expected` into `actual`
+fn mini_diff(expected: &[&str], actual: &[&str]) -> String {
+    let mut diff = String::new();
+    for edit in edit_script(expected, actual, DiffAlgorithm::Myers) {
+        let (prefix, line) = match edit {
+            Edit::Equal(i, _) => (' ', expected[i]),
+            Edit::Delete(i) => ('-', expected[i]),
+            Edit::Insert(j) => ('+', actual[j]),
+        };
+        diff.push(prefix);
+        diff.push_str(line);
+        diff.push('\n');
+    }
+    diff
+}
+
+// One minus the Levenshtein distance between the lines, divided by the length of the longest one
+fn line_similarity(a: &str, b: &str) -> f64 {
+    let longest = a.chars().count().max(b.chars().count());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(a, b) as f64 / longest as f64
+}
+
+// The number of characters to insert, remove or replace to turn `a` into `b`
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        core::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{File, FileOp, Hunk, Line, Patch, Range};
+    use std::borrow::Cow;
+    // Test 1: A simple replacement of a single line.
+    #[test]
+    fn test_simple_replace() {
+        let content = "line1\nline2\nline3";
+        let patch = Patch {
+            old: File {
+                path: Cow::Borrowed(""),
+                meta: None,
+            },
+            new: File {
+                path: Cow::Borrowed(""),
+                meta: None,
+            },
+            operation: FileOp::Modify,
+            git: None,
+            svn: None,
+            binary: None,
+            opaque_binary: false,
+            preamble: None,
+            raw: None,
+            old_end_newline: true,
+            new_end_newline: true,
+            hunks: vec![Hunk {
+                old_range: Range { start: 1, count: 1 },
+                new_range: Range { start: 1, count: 1 },
+                range_hint: "".into(),
+                lines: vec![
+                    // In the patch, we expect to remove "line2" and replace it.
+                    Line::Remove("line2".into()),
+                    Line::Add("line2 modified".into()),
+                ],
+            }],
+        };
+
+        let result = find_replace_apply(&patch, content);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), "line1\nline2 modified\nline3".to_string());
+    }
+
+    // Test 2: When the content contains multiple occurrences of the target block,
+    // the hunk should be applied to the occurrence closest to the specified starting index.
+    #[test]
+    fn test_multiple_occurrences_choose_closest() {
+        let content = "line1\nline2\nline3\nline2\nline3";
+        let patch = Patch {
+            old: File {
+                path: Cow::Borrowed(""),
+                meta: None,
+            },
+            new: File {
+                path: Cow::Borrowed(""),
+                meta: None,
+            },
+            operation: FileOp::Modify,
+            git: None,
+            svn: None,
+            binary: None,
+            opaque_binary: false,
+            preamble: None,
+            raw: None,
+            old_end_newline: true,
+            new_end_newline: true,
+            hunks: vec![Hunk {
+                // The target index is 1.
+                old_range: Range { start: 1, count: 2 },
+                new_range: Range { start: 1, count: 2 },
+                range_hint: "".into(),
+                lines: vec![
+                    // Old lines to match: "line2" followed by "line3"
+                    Line::Remove("line2".into()),
+                    Line::Remove("line3".into()),
+                    // New lines to replace with.
+                    Line::Add("new2".into()),
+                    Line::Add("new3".into()),
+                ],
+            }],
+        };
+
+        let result = find_replace_apply(&patch, content);
+        assert!(result.is_ok());
+        let expected = "line1\nnew2\nnew3\nline2\nline3".to_string();
+        assert_eq!(result.unwrap(), expected);
+    }
+
+    // Test 3: When no matching block is found, the function should return an error.
+    #[test]
+    fn test_hunk_not_found_error() {
+        let content = "line1\nline2\nline3";
+        let patch = Patch {
+            old: File {
+                path: Cow::Borrowed(""),
+                meta: None,
+            },
+            new: File {
+                path: Cow::Borrowed(""),
+                meta: None,
+            },
+            operation: FileOp::Modify,
+            git: None,
+            svn: None,
+            binary: None,
+            opaque_binary: false,
+            preamble: None,
+            raw: None,
+            old_end_newline: true,
+            new_end_newline: true,
+            hunks: vec![Hunk {
+                old_range: Range { start: 1, count: 1 },
+                new_range: Range { start: 1, count: 1 },
+                range_hint: "".into(),
+                lines: vec![
+                    // This hunk expects a block that doesn't exist in the content.
+                    Line::Remove("lineX".into()),
+                    Line::Add("lineX modified".into()),
+                ],
+            }],
+        };
+
+        let result = find_replace_apply(&patch, content);
+        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err(),
+            ApplyError::HunkNotFound { .. }
+        ));
+    }
+
+    // Test 4: Applying a hunk that includes context lines.
+    #[test]
+    fn test_context_lines() {
+        let content = "line1\nline2\nline3\nline4";
+        let patch = Patch {
+            old: File {
+                path: Cow::Borrowed(""),
+                meta: None,
+            },
+            new: File {
+                path: Cow::Borrowed(""),
+                meta: None,
+            },
+            operation: FileOp::Modify,
+            git: None,
+            svn: None,
+            binary: None,
+            opaque_binary: false,
+            preamble: None,
+            raw: None,
+            old_end_newline: true,
+            new_end_newline: true,
+            hunks: vec![Hunk {
+                old_range: Range { start: 1, count: 2 },
+                new_range: Range { start: 1, count: 2 },
+                range_hint: "".into(),
+                lines: vec![
+                    // The context line ("line2") should appear in both old and new lines.
+                    Line::Context("line2".into()),
+                    // "line3" is to be removed and replaced.
+                    Line::Remove("line3".into()),
+                    Line::Add("line3 modified".into()),
+                ],
+            }],
+        };
+
+        let result = find_replace_apply(&patch, content);
+        assert!(result.is_ok());
+        let expected = "line1\nline2\nline3 modified\nline4".to_string();
+        assert_eq!(result.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_apply_simple_patch() {
+        let content = "line 1\nline 2\nline 3\n";
+        let patch_text = "\
+--- old.txt
++++ new.txt
+@@ -1,3 +1,3 @@
+ line 1
+-line 2
++new line 2
+ line 3
+";
+        let patch = Patch::from_single(patch_text).unwrap();
+        let result = apply(&patch, content).unwrap();
+        assert_eq!(result, "line 1\nnew line 2\nline 3\n");
+    }
+
+    #[test]
+    fn test_apply_patch_with_additions() {
+        let content = "A\nB\nC\n";
+        let patch_text = "\
+--- old.txt
++++ new.txt
+@@ -1,3 +1,5 @@
+ A
++X
+ B
++Y
+ C
+";
+        let patch = Patch::from_single(patch_text).unwrap();
+        let result = apply(&patch, content).unwrap();
+        assert_eq!(result, "A\nX\nB\nY\nC\n");
+    }
+
+    #[test]
+    fn test_apply_patch_with_removals() {
+        let content = "A\nB\nC\nD\n";
+        let patch_text = "\
+--- old.txt
++++ new.txt
+@@ -1,4 +1,2 @@
+ A
+-B
+-C
+ D
+";
+        let patch = Patch::from_single(patch_text).unwrap();
+        let result = apply(&patch, content).unwrap();
+        assert_eq!(result, "A\nD\n");
+    }
+
+    #[test]
+    fn test_apply_reverse_undoes_apply() {
+        let content = "A\nB\nC\nD\n";
+        let patch_text = "\
+--- old.txt
++++ new.txt
+@@ -1,4 +1,4 @@
+ A
+-B
+-C
++X
+ D
++E
+";
+        let patch = Patch::from_single(patch_text).unwrap();
+        let patched = apply(&patch, content).unwrap();
+        assert_eq!(patched, "A\nX\nD\nE\n");
+        assert_eq!(apply_reverse(&patch, &patched).unwrap(), content);
+        assert_eq!(patch.reverse().reverse(), patch);
+    }
+
+    #[test]
+    fn test_apply_with_options_finds_offset() {
+        let content = "new 1\nnew 2\nA\nB\nC\nD\nE\nF\nG\n";
+        let patch_text = "\
+--- old.txt
++++ new.txt
+@@ -1,3 +1,3 @@
+ A
+-B
++X
+ C
+@@ -5,3 +5,3 @@
+ E
+-F
++Y
+ G
+";
+        let patch = Patch::from_single(patch_text).unwrap();
+        assert!(apply(&patch, content).is_err());
+        let result = apply_with_options(&patch, content, &ApplyOptions::default()).unwrap();
+        assert_eq!(result, "new 1\nnew 2\nA\nX\nC\nD\nE\nY\nG\n");
+    }
+
+    #[test]
+    fn test_apply_zero_context() {
+        let content = "new\nA\nB\nC\nD\nE\n";
+        let patch_text = "\
+--- old.txt
++++ new.txt
+@@ -1,2 +1,2 @@
+ A
+-B
++X
+@@ -3,0 +4 @@
++c
+@@ -4 +5 @@
+-D
++Y
+";
+        let patch = Patch::from_single(patch_text).unwrap();
+        assert!(matches!(
+            apply_with_options(&patch, content, &ApplyOptions::default()),
+            Err(ApplyError::NoContext { line: 3 })
+        ));
+
+        // The hunks without context follow the offset of the first one
+        let options = ApplyOptions {
+            unidiff_zero: true,
+            ..ApplyOptions::default()
+        };
+        let result = apply_with_options(&patch, content, &options).unwrap();
+        assert_eq!(result, "new\nA\nX\nC\nc\nY\nE\n");
+
+        // Hunks at the start or at the end of the content are allowed without the option
+        let patch_text = "--- a\n+++ a\n@@ -0,0 +1 @@\n+start\n@@ -5 +6 @@\n-E\n+end\n";
+        let patch = Patch::from_single(patch_text).unwrap();
+        let result = apply_with_options(&patch, "A\nB\nC\nD\nE\n", &ApplyOptions::default());
+        assert_eq!(result.unwrap(), "start\nA\nB\nC\nD\nend\n");
+
+        // An empty range inserts after the line it names
+        let patch = Patch::from_single("--- a\n+++ a\n@@ -2,0 +3 @@\n+x\n").unwrap();
+        assert_eq!(apply(&patch, "a\nb\nc\n").unwrap(), "a\nb\nx\nc\n");
+    }
+
+    #[test]
+    fn test_apply_with_options_fuzz() {
+        let content = "A\nB\nC\nD\nchanged\n";
+        let patch_text = "\
+--- old.txt
++++ new.txt
+@@ -1,5 +1,5 @@
+ A
+ B
+-C
++X
+ D
+ E
+";
+        let patch = Patch::from_single(patch_text).unwrap();
+        let err = apply_with_options(&patch, content, &ApplyOptions::default()).unwrap_err();
+        assert!(matches!(err, ApplyError::ContextMismatch { line: 5, .. }));
+
+        let options = ApplyOptions {
+            fuzz: 1,
+            ..ApplyOptions::default()
+        };
+        let result = apply_with_options(&patch, content, &options).unwrap();
+        assert_eq!(result, "A\nB\nX\nD\nchanged\n");
+    }
+
+    #[test]
+    fn test_apply_with_options_fuzz_keeps_changed_lines() {
+        // Fuzz only ever ignores context lines, never the lines being removed
+        let content = "A\nB\nchanged\nD\n";
+        let patch_text = "\
+--- old.txt
++++ new.txt
+@@ -1,4 +1,4 @@
+ A
+ B
+-C
++X
+ D
+";
+        let patch = Patch::from_single(patch_text).unwrap();
+        let options = ApplyOptions {
+            fuzz: 3,
+            ..ApplyOptions::default()
+        };
+        let err = apply_with_options(&patch, content, &options).unwrap_err();
+        assert!(matches!(err, ApplyError::ContextMismatch { line: 3, .. }));
+    }
+
+    #[test]
+    fn test_apply_partial_keeps_going() {
+        let content = "A\nB\nC\nD\nE\nF";
+        let patch_text = "\
+--- old.txt
++++ new.txt
+@@ -1,2 +1,2 @@
+ A
+-Q
++X
+@@ -5,2 +5,2 @@
+ E
+-F
++Y
+\\ No newline at end of file
+";
+        let patch = Patch::from_single(patch_text).unwrap();
+        let (result, rejected) = apply_partial(&patch, content, &ApplyOptions::default());
+        assert_eq!(result, "A\nB\nC\nD\nE\nY");
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].index, 0);
+        assert!(matches!(
+            rejected[0].error,
+            ApplyError::ContextMismatch { line: 2, .. }
+        ));
+
+        let rejects = reject_patch(&patch, &rejected);
+        assert_eq!(rejects.hunks, [patch.hunks[0].clone()]);
+        assert!(rejects.new_end_newline);
+    }
+
+    #[test]
+    fn test_reject_patch_recalculates_ranges() {
+        let patch = Patch::from_single(
+            "\
+--- old.txt
++++ new.txt
+@@ -1,2 +1,3 @@
+ A
++B
+ C
+@@ -5,2 +6,2 @@
+ E
+-Q
++X
+@@ -7 +8,2 @@
+ G
++H
+@@ -9 +11,0 @@
+-I
+",
+        )
+        .unwrap();
+        let (_, rejected) = apply_partial(
+            &patch,
+            "A\nC\nD\nD\nE\nF\nZ\nZ\nZ\n",
+            &ApplyOptions::default(),
+        );
+        assert_eq!(
+            rejected
+                .iter()
+                .map(|reject| reject.index)
+                .collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert_eq!(
+            reject_patch(&patch, &rejected).to_string(),
+            "\
+--- old.txt
++++ new.txt
+@@ -5,2 +5,2 @@
+ E
+-Q
++X
+@@ -7,1 +7,2 @@
+ G
++H
+@@ -9,1 +9,0 @@
+-I"
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let patch = Patch::from_single(
+            "\
+--- old.txt
++++ new.txt
+@@ -1,3 +1,3 @@
+ A
+-B
++X
+ C
+@@ -6,3 +6,3 @@
+ F
+-G
++Y
+ H
+@@ -10,2 +10,2 @@
+ J
+-Q
++Z
+",
+        )
+        .unwrap();
+        let content = "A\nB\nC\nD\nE\n0\nF\nG\nchanged\nJ\nK\n";
+        let options = ApplyOptions {
+            fuzz: 1,
+            ..ApplyOptions::default()
+        };
+        let report = check(&patch, content, &options);
+        assert!(!report.succeeded());
+        assert!(matches!(
+            report.hunks[0],
+            HunkStatus::Applied {
+                line: 1,
+                offset: 0,
+                fuzz: 0
+            }
+        ));
+        assert!(matches!(
+            report.hunks[1],
+            HunkStatus::Applied {
+                line: 7,
+                offset: 1,
+                fuzz: 1
+            }
+        ));
+        assert!(matches!(
+            report.hunks[2],
+            HunkStatus::Failed(ApplyError::ContextMismatch { line: 11, .. })
+        ));
+
+        // Checking agrees with applying
+        let (_, rejected) = apply_partial(&patch, content, &options);
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].index, 2);
+
+        // Every hunk gets a status, even after the content runs out
+        let report = check(&patch, "A\nB\nC\n", &ApplyOptions::default());
+        assert_eq!(report.hunks.len(), 3);
+    }
+
+    #[test]
+    fn test_apply_with_report() {
+        let patch = Patch::from_single(
+            "\
+--- old.txt
++++ new.txt
+@@ -1,2 +1,2 @@
+-A
++X
+ B
+@@ -4,3 +4,3 @@
+ D
+-E
++Y
+ F
+@@ -9,2 +9,2 @@
+ I
+-J
++Z
+",
+        )
+        .unwrap();
+        let options = ApplyOptions {
+            fuzz: 1,
+            ..ApplyOptions::default()
+        };
+        let content = "A\nB\nC\n0\n1\nchanged\nE\nF\nG\nH\nI\nJ\n";
+        let (result, report) = apply_with_report(&patch, content, &options).unwrap();
+        assert_eq!(result, "X\nB\nC\n0\n1\nchanged\nY\nF\nG\nH\nI\nZ\n");
+        assert!(report.succeeded());
+        assert_eq!(
+            report.to_string(),
+            "\
+Hunk #2 succeeded at 6 with fuzz 1 (offset 2 lines).
+Hunk #3 succeeded at 11 (offset 2 lines).
+"
+        );
+
+        let report = check(&patch, "A\nB\n", &options);
+        assert_eq!(
+            report.to_string(),
+            "\
+Hunk #2 FAILED: Line 3 is out of bounds (file has 2 lines)
+Hunk #3 FAILED: Line 3 is out of bounds (file has 2 lines)
+"
+        );
+
+        assert!(apply_with_report(&patch, "A\nB\n", &options).is_err());
+    }
+
+    #[test]
+    fn test_apply_ignoring_whitespace() {
+        let patch = Patch::from_single(
+            "\
+--- old.txt
++++ new.txt
+@@ -1,4 +1,4 @@
+ fn main() {
+-    let x = 1;
++    let x = 2;
+     run(x);
+ }
+",
+        )
+        .unwrap();
+        let with = |ignore_whitespace| ApplyOptions {
+            ignore_whitespace,
+            ..ApplyOptions::default()
+        };
+
+        let content = "fn main() {  \n    let x = 1;\t\n    run(x);\n}\n";
+        assert!(apply_with_options(&patch, content, &with(Whitespace::Exact)).is_err());
+        assert_eq!(
+            apply_with_options(&patch, content, &with(Whitespace::Trailing)).unwrap(),
+            "fn main() {  \n    let x = 2;\n    run(x);\n}\n"
+        );
+
+        let content = "fn main(){\n  let x=1;\n  run( x );\n}\n";
+        assert!(apply_with_options(&patch, content, &with(Whitespace::Trailing)).is_err());
+        assert_eq!(
+            apply_with_options(&patch, content, &with(Whitespace::All)).unwrap(),
+            "fn main(){\n    let x = 2;\n  run( x );\n}\n"
+        );
+
+        let content = "fn main() {\n\n    let x = 1;\n\n    run(x);\n}\n";
+        assert!(apply_with_options(&patch, content, &with(Whitespace::All)).is_err());
+        assert_eq!(
+            apply_with_options(&patch, content, &with(Whitespace::BlankLines)).unwrap(),
+            "fn main() {\n\n    let x = 2;\n\n    run(x);\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_ignoring_missing_blank_lines() {
+        let patch = Patch::from_single(
+            "\
+--- old.txt
++++ new.txt
+@@ -1,5 +1,4 @@
+ a
+ 
+-b
+-
++c
+ d
+",
+        )
+        .unwrap();
+        let options = ApplyOptions {
+            ignore_whitespace: Whitespace::BlankLines,
+            ..ApplyOptions::default()
+        };
+        assert_eq!(
+            apply_with_options(&patch, "x\na\nb\nd\n", &options).unwrap(),
+            "x\na\nc\nd\n"
+        );
+        assert_eq!(
+            find_replace_apply_with_options(
+                &patch,
+                "x\na\nb\nd",
+                &FindReplaceOptions {
+                    ignore_whitespace: Whitespace::BlankLines,
+                    ..FindReplaceOptions::default()
+                }
+            )
+            .unwrap()
+            .text,
+            "x\na\nc\nd"
+        );
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("héllo", "hello"), 1);
+        assert_eq!(line_similarity("", ""), 1.0);
+        assert_eq!(line_similarity("abcd", "abce"), 0.75);
+    }
+
+    #[test]
+    fn test_candidate_starts() {
+        let content = ["a", "b ", "c", "a", "b", "c", "a"];
+        let starts = |expected: &[&str], whitespace| {
+            let hashes: Vec<u64> = content
+                .iter()
+                .map(|line| line_hash(line, whitespace))
+                .collect();
+            candidate_starts(&hashes, expected.len(), block_hash(expected, whitespace))
         };
+        assert_eq!(starts(&["a", "b"], Whitespace::Exact), [3]);
+        assert_eq!(starts(&["a"], Whitespace::Exact), [0, 3, 6]);
+        assert_eq!(starts(&["x"], Whitespace::Exact), []);
+        assert_eq!(starts(&["a"; 8], Whitespace::Exact), []);
+        assert_eq!(starts(&["a", "b", "c"], Whitespace::Trailing), [0, 3]);
+        assert_eq!(starts(&["c", " a"], Whitespace::All), [2, 5]);
+        // Blocks of any length can match when blank lines are ignored
+        assert_eq!(
+            starts(&["a", "b"], Whitespace::BlankLines),
+            (0..=7).collect::<Vec<_>>()
+        );
+    }
 
-        let result = find_replace_apply(&patch, content);
-        assert!(result.is_ok());
-        let expected = "line1\nnew2\nnew3\nline2\nline3".to_string();
-        assert_eq!(result.unwrap(), expected);
+    #[test]
+    fn test_find_replace_apply_similarity() {
+        let patch = Patch::from_single(
+            "\
+--- old.txt
++++ new.txt
+@@ -2,3 +2,3 @@
+ let total = price * count;
+-print(total);
++print(total + tax);
+ return total;
+",
+        )
+        .unwrap();
+        let content = "start\nlet total = price * qty;\nprint(total);\nreturn total;\nend";
+        assert!(find_replace_apply(&patch, content).is_err());
+
+        let options = FindReplaceOptions {
+            min_similarity: Some(0.9),
+            ..FindReplaceOptions::default()
+        };
+        let result = find_replace_apply_with_options(&patch, content, &options).unwrap();
+        // The context keeps the content's version of the line
+        assert_eq!(
+            result.text,
+            "start\nlet total = price * qty;\nprint(total + tax);\nreturn total;\nend"
+        );
+        let expected =
+            (line_similarity("let total = price * qty;", "let total = price * count;") + 2.0) / 3.0;
+        assert_eq!(result.similarity, [expected]);
+
+        // Below the threshold, the hunk isn't found
+        let options = FindReplaceOptions {
+            min_similarity: Some(0.99),
+            ..FindReplaceOptions::default()
+        };
+        assert!(matches!(
+            find_replace_apply_with_options(&patch, content, &options),
+            Err(ApplyError::HunkNotFound { .. })
+        ));
     }
 
-    // Test 3: When no matching block is found, the function should return an error.
     #[test]
-    fn test_hunk_not_found_error() {
-        let content = "line1\nline2\nline3";
-        let patch = Patch {
-            old: File {
-                path: Cow::Borrowed(""),
-                meta: None,
-            },
-            new: File {
-                path: Cow::Borrowed(""),
-                meta: None,
-            },
-            end_newline: true,
-            hunks: vec![Hunk {
-                old_range: Range { start: 1, count: 1 },
-                new_range: Range { start: 1, count: 1 },
-                range_hint: "",
-                lines: vec![
-                    // This hunk expects a block that doesn't exist in the content.
-                    Line::Remove("lineX"),
-                    Line::Add("lineX modified"),
-                ],
-            }],
+    fn test_find_replace_apply_limits() {
+        let patch =
+            Patch::from_single("--- a.txt\n+++ a.txt\n@@ -2,2 +2,2 @@\n b\n-c\n+C\n").unwrap();
+        let mut lines = vec!["x"; 20];
+        lines[14] = "b";
+        lines[15] = "c";
+        let content = lines.join("\n");
+        let apply = |options: FindReplaceOptions| {
+            find_replace_apply_with_options(&patch, &content, &options).map(|result| result.text)
         };
 
-        let result = find_replace_apply(&patch, content);
-        assert!(result.is_err());
+        // The hunk is 12 lines away from where the patch puts it
+        let options = FindReplaceOptions {
+            max_distance: Some(12),
+            ..FindReplaceOptions::default()
+        };
+        assert_eq!(apply(options).unwrap(), content.replace("c", "C"));
+        let options = FindReplaceOptions {
+            max_distance: Some(11),
+            min_similarity: Some(0.9),
+            ..FindReplaceOptions::default()
+        };
+        let Err(ApplyError::HunkNotFound { closest, .. }) = apply(options) else {
+            panic!("hunk should not be found");
+        };
+        // The most similar block is only looked for within the distance too
+        assert!(closest.unwrap().line <= 14);
+
+        // Comparing the block found takes as many steps as the hunk has old lines
+        let options = FindReplaceOptions {
+            max_steps: Some(2),
+            ..FindReplaceOptions::default()
+        };
+        assert!(apply(options).is_ok());
+        let options = FindReplaceOptions {
+            max_steps: Some(1),
+            ..FindReplaceOptions::default()
+        };
         assert!(matches!(
-            result.unwrap_err(),
-            ApplyError::HunkNotFound {
-                expected: expected,
-                file_contents: file_contents,
-            }
+            apply(options),
+            Err(ApplyError::SearchLimit { line: 2 })
+        ));
+        // Looking for similar blocks compares every block of the content
+        let options = FindReplaceOptions {
+            max_steps: Some(20),
+            min_similarity: Some(0.5),
+            ..FindReplaceOptions::default()
+        };
+        let missing = content.replace("b", "B");
+        assert!(matches!(
+            find_replace_apply_with_options(&patch, &missing, &options),
+            Err(ApplyError::SearchLimit { line: 2 })
         ));
+
+        #[cfg(feature = "std")]
+        {
+            let options = FindReplaceOptions {
+                deadline: Some(Instant::now()),
+                ..FindReplaceOptions::default()
+            };
+            assert!(matches!(
+                apply(options),
+                Err(ApplyError::SearchLimit { line: 2 })
+            ));
+        }
+
+        // A cancelled search stops before comparing anything
+        let cancel = CancellationToken::new();
+        let options = FindReplaceOptions {
+            cancel: Some(cancel.clone()),
+            ..FindReplaceOptions::default()
+        };
+        assert!(apply(options.clone()).is_ok());
+        cancel.cancel();
+        assert!(matches!(apply(options), Err(ApplyError::Cancelled)));
     }
 
-    // Test 4: Applying a hunk that includes context lines.
     #[test]
-    fn test_context_lines() {
-        let content = "line1\nline2\nline3\nline4";
-        let patch = Patch {
-            old: File {
-                path: Cow::Borrowed(""),
-                meta: None,
-            },
-            new: File {
-                path: Cow::Borrowed(""),
-                meta: None,
-            },
-            end_newline: true,
-            hunks: vec![Hunk {
-                old_range: Range { start: 1, count: 2 },
-                new_range: Range { start: 1, count: 2 },
-                range_hint: "",
-                lines: vec![
-                    // The context line ("line2") should appear in both old and new lines.
-                    Line::Context("line2"),
-                    // "line3" is to be removed and replaced.
-                    Line::Remove("line3"),
-                    Line::Add("line3 modified"),
-                ],
-            }],
+    fn test_hunk_not_found_closest_match() {
+        let patch = Patch::from_single(
+            "\
+--- old.txt
++++ new.txt
+@@ -1,3 +1,3 @@
+ alpha
+-beta
++BETA
+ gamma
+",
+        )
+        .unwrap();
+        let content = "one\ntwo\nalpha\nbeta!\ngamma\nthree";
+        let Err(ApplyError::HunkNotFound { closest, .. }) = find_replace_apply(&patch, content)
+        else {
+            panic!("hunk should not be found");
         };
+        let closest = closest.unwrap();
+        assert_eq!(closest.line, 3);
+        assert_eq!(
+            closest.similarity,
+            (2.0 + line_similarity("beta", "beta!")) / 3.0
+        );
+        assert_eq!(closest.diff, " alpha\n-beta\n+beta!\n gamma\n");
 
-        let result = find_replace_apply(&patch, content);
-        assert!(result.is_ok());
-        let expected = "line1\nline2\nline3 modified\nline4".to_string();
-        assert_eq!(result.unwrap(), expected);
+        // Too short for any block to be compared
+        let Err(ApplyError::HunkNotFound { closest, .. }) = find_replace_apply(&patch, "alpha")
+        else {
+            panic!("hunk should not be found");
+        };
+        assert_eq!(closest, None);
     }
 
     #[test]
-    fn test_apply_simple_patch() {
-        let content = "line 1\nline 2\nline 3\n";
+    fn test_is_applied() {
+        let patch = Patch::from_single(
+            "\
+--- old.txt
++++ new.txt
+@@ -2,3 +2,4 @@
+ B
+-C
++X
++Y
+ D
+",
+        )
+        .unwrap();
+        let content = "A\nB\nC\nD\n";
+        assert_eq!(patch.is_applied(content), AppliedState::NotApplied);
+
+        // Found at an offset, as long as all of the context matches
+        let patched = apply(&patch, content).unwrap();
+        assert_eq!(patch.is_applied(&patched), AppliedState::Applied);
+        assert_eq!(
+            patch.is_applied(&format!("0\n1\n{}", patched)),
+            AppliedState::Applied
+        );
+        assert_eq!(
+            patch.is_applied("A\nZ\nX\nY\nD\n"),
+            AppliedState::NotApplied
+        );
+
+        // The reverse patch is applied when the original patch isn't
+        assert_eq!(patch.reverse().is_applied(content), AppliedState::Applied);
+    }
+
+    // A binary patch can't be applied to text in any mode, and leaves the content as it is
+    fn assert_binary_fails(patch: &Patch<'_, str>) {
+        let content = "A\nB\n";
+        let options = ApplyOptions::default();
+        assert!(matches!(apply(patch, content), Err(ApplyError::Binary)));
+
+        let (result, rejected) = apply_partial(patch, content, &options);
+        assert_eq!(result, content);
+        assert_eq!(rejected.len(), 1);
+        assert!(matches!(rejected[0].error, ApplyError::Binary));
+        assert!(reject_patch(patch, &rejected).is_binary());
+
+        let merged = apply_with_conflicts(patch, content, &options);
+        assert_eq!(merged.text, content);
+        assert_eq!(
+            merged.conflicts,
+            [Conflict {
+                merged: 0..2,
+                base: 0..0,
+                ours: 0..2,
+                theirs: 0..0,
+            }]
+        );
+
+        let report = check(patch, content, &options);
+        assert!(!report.succeeded());
+        assert_eq!(patch.is_applied(content), AppliedState::NotApplied);
+    }
+
+    #[test]
+    fn test_binary_patch_fails() {
+        let patch = Patch::from_single(
+            "\
+diff --git a/x.bin b/x.bin
+index 17b9c9ac90f32576adf666e462c83142810ab065..ba3f43b001c3fb7d8e8dca7a4b73b9a011de263b 100644
+GIT binary patch
+delta 30
+kcmdlXzDImQ3X7wYv&&>d?)J?Om~XHHiH}SyC5f3i0I?AZrvLx|
+
+delta 16
+YcmdlZzC(OM%H{x;4ED_rnBOo1069Yjk^lez
+
+",
+        )
+        .unwrap();
+        assert_binary_fails(&patch);
+    }
+
+    #[test]
+    fn test_opaque_binary_patch_fails() {
+        let patch = Patch::from_single(
+            "diff --git a/x.bin b/x.bin\nBinary files a/x.bin and b/x.bin differ\n",
+        )
+        .unwrap();
+        assert!(patch.opaque_binary);
+        assert_binary_fails(&patch);
+    }
+
+    #[test]
+    fn test_apply_partial_last_hunk_rejected() {
+        let content = "A\nB\n";
         let patch_text = "\
 --- old.txt
 +++ new.txt
-@@ -1,3 +1,3 @@
- line 1
--line 2
-+new line 2
- line 3
+@@ -1,2 +1,2 @@
+ A
+-Q
++X
+\\ No newline at end of file
 ";
         let patch = Patch::from_single(patch_text).unwrap();
-        let result = apply(&patch, content).unwrap();
-        assert_eq!(result, "line 1\nnew line 2\nline 3\n");
+        let (result, rejected) = apply_partial(&patch, content, &ApplyOptions::default());
+        assert_eq!(result, content);
+        assert_eq!(rejected.len(), 1);
+        assert!(!reject_patch(&patch, &rejected).new_end_newline);
     }
 
     #[test]
-    fn test_apply_patch_with_additions() {
-        let content = "A\nB\nC\n";
+    fn test_apply_with_conflicts() {
+        let content = "A\nB\nC\nD\nE\nF\nG\n";
         let patch_text = "\
 --- old.txt
 +++ new.txt
-@@ -1,3 +1,5 @@
+@@ -1,2 +1,2 @@
  A
+-B
 +X
- B
+@@ -4,4 +4,4 @@
+ D
+-Q
 +Y
+ F
+ G
+";
+        let patch = Patch::from_single(patch_text).unwrap();
+        let result = apply_with_conflicts(&patch, content, &ApplyOptions::default());
+        assert_eq!(
+            result.text,
+            "A\nX\nC\nD\n<<<<<<< ours\nE\n=======\nY\n>>>>>>> theirs\nF\nG\n"
+        );
+        assert_eq!(
+            result.conflicts,
+            [Conflict {
+                merged: 4..9,
+                base: 4..5,
+                ours: 4..5,
+                theirs: 4..5,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_apply_with_conflicts_merges_changed_context() {
+        // Only a context line differs, which doesn't conflict with the change
+        let content = "A\nB\nC\nchanged\nE\n";
+        let patch_text = "\
+--- old.txt
++++ new.txt
+@@ -1,5 +1,5 @@
+ A
+-B
++X
  C
+ D
+ E
 ";
         let patch = Patch::from_single(patch_text).unwrap();
-        let result = apply(&patch, content).unwrap();
-        assert_eq!(result, "A\nX\nB\nY\nC\n");
+        let result = apply_with_conflicts(&patch, content, &ApplyOptions::default());
+        assert!(result.is_clean());
+        assert_eq!(result.text, "A\nX\nC\nchanged\nE\n");
     }
 
     #[test]
-    fn test_apply_patch_with_removals() {
-        let content = "A\nB\nC\nD\n";
+    fn test_apply_bytes() {
+        let patch_text = b"--- a\n+++ b\n@@ -1,3 +1,3 @@\n \xff\xfe\n-\xe9t\xe9\n+summer\n \x80\n";
+        let patch = Patch::from_bytes(patch_text).unwrap();
+        let result = apply_bytes(&patch, b"\xff\xfe\n\xe9t\xe9\n\x80\n").unwrap();
+        assert_eq!(result, b"\xff\xfe\nsummer\n\x80\n");
+
+        let err = apply_bytes(&patch, b"\xff\xfe\nhiver\n\x80\n").unwrap_err();
+        match err {
+            ApplyError::ContextMismatch {
+                line,
+                expected,
+                actual,
+            } => {
+                assert_eq!(line, 2);
+                assert_eq!(expected, "\u{fffd}t\u{fffd}");
+                assert_eq!(actual, "hiver");
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_apply_to_writer() {
+        let patch = Patch::from_single(
+            "\
+--- old.txt
++++ new.txt
+@@ -1,3 +1,3 @@
+ A
+-B
++X
+ C
+\\ No newline at end of file
+",
+        )
+        .unwrap();
+        for content in ["A\nB\nC", "A\r\nB\r\nC"] {
+            let mut output = Vec::new();
+            apply_to_writer(&patch, content, &mut output).unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                apply(&patch, content).unwrap()
+            );
+        }
+
+        let mut output = Vec::new();
+        let error = apply_to_writer(&patch, "A\nZ\nC", &mut output).unwrap_err();
+        assert!(matches!(error, ApplyError::ContextMismatch { .. }));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_apply_to_writer_io_error() {
+        struct Full;
+
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::StorageFull.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let patch = Patch::from_single("--- a\n+++ b\n@@ -1 +1 @@\n-a\n+b\n").unwrap();
+        let error = apply_to_writer(&patch, "a\n", &mut Full).unwrap_err();
+        match error {
+            ApplyError::Io(error) => assert_eq!(error.kind(), io::ErrorKind::StorageFull),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_apply_cow() {
+        let patch = Patch::from_single(
+            "\
+--- old.txt
++++ new.txt
+@@ -1,3 +1,4 @@
+ A
+-B
++X
++Y
+ C
+\\ No newline at end of file
+",
+        )
+        .unwrap();
+        for content in ["A\nB\nC", "A\r\nB\r\nC"] {
+            let segments = apply_cow(&patch, content).unwrap();
+            assert_eq!(segments.concat(), apply(&patch, content).unwrap());
+            let borrowed: Vec<_> = segments
+                .iter()
+                .map(|segment| matches!(segment, Cow::Borrowed(_)))
+                .collect();
+            assert_eq!(borrowed, [true, false, true]);
+        }
+
+        // Unchanged content is borrowed in one piece
+        let content = "A\nB\nC\n";
+        let patch = Patch::from_single("--- a\n+++ b\n@@ -3 +3 @@\n-C\n+D\n").unwrap();
+        assert_eq!(
+            apply_cow(&patch, content).unwrap(),
+            [Cow::Borrowed("A\nB\n"), Cow::Owned("D\n".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_apply_selected() {
+        let patch = Patch::from_single(
+            "\
+--- old.txt
++++ new.txt
+@@ -1,2 +1,3 @@
+ A
++A2
+ B
+@@ -4,2 +5 @@
+ D
+-E
+@@ -6 +6 @@
+-F
++G
+\\ No newline at end of file
+",
+        )
+        .unwrap();
+        let content = "A\nB\nC\nD\nE\nF\n";
+        let mut seen = Vec::new();
+        let result = apply_selected(&patch, content, |hunk, index| {
+            seen.push((index, hunk.old_range.start));
+            index != 1
+        });
+        assert_eq!(seen, [(0, 1), (1, 4), (2, 6)]);
+        assert_eq!(result.unwrap(), "A\nA2\nB\nC\nD\nE\nG");
+
+        assert_eq!(
+            apply_selected(&patch, content, |_, _| true).unwrap(),
+            apply(&patch, content).unwrap()
+        );
+        // Leaving out the last hunk leaves the end of the content alone
+        assert_eq!(
+            apply_selected(&patch, content, |_, index| index < 2).unwrap(),
+            "A\nA2\nB\nC\nD\nF\n"
+        );
+        assert_eq!(
+            apply_selected(&patch, content, |_, _| false).unwrap(),
+            content
+        );
+    }
+
+    #[test]
+    fn test_apply_with_observer() {
+        // Records what it is told, and stops at the hunk it is given
+        struct Recorder {
+            events: Vec<String>,
+            stop_at: Option<usize>,
+        }
+
+        impl ApplyObserver for Recorder {
+            fn on_hunk_start(&mut self, index: usize, _: &Hunk) -> ControlFlow<()> {
+                self.events.push(format!("start {}", index));
+                match self.stop_at == Some(index) {
+                    true => ControlFlow::Break(()),
+                    false => ControlFlow::Continue(()),
+                }
+            }
+
+            fn on_hunk_applied(&mut self, index: usize, status: &HunkStatus) -> ControlFlow<()> {
+                let HunkStatus::Applied { line, offset, .. } = status else {
+                    panic!("{:?}", status);
+                };
+                self.events
+                    .push(format!("applied {} at {} ({})", index, line, offset));
+                ControlFlow::Continue(())
+            }
+
+            fn on_line_emitted(&mut self, line: &str) -> ControlFlow<()> {
+                self.events.push(line.to_string());
+                ControlFlow::Continue(())
+            }
+        }
+
+        let patch = Patch::from_single(
+            "--- a\n+++ b\n@@ -1,2 +1,2 @@\n-A\n+X\n B\n@@ -5,2 +5,2 @@\n-E\n+Y\n F\n",
+        )
+        .unwrap();
+        let content = "0\nA\nB\nC\nD\nE\nF\n";
+        let mut recorder = Recorder {
+            events: Vec::new(),
+            stop_at: None,
+        };
+        let options = ApplyOptions::default();
+        let result = apply_with_observer(&patch, content, &options, &mut recorder).unwrap();
+        assert_eq!(
+            result,
+            apply_with_options(&patch, content, &options).unwrap()
+        );
+        assert_eq!(
+            recorder.events,
+            [
+                "start 0",
+                "0",
+                "X",
+                "applied 0 at 2 (1)",
+                "start 1",
+                "B",
+                "C",
+                "D",
+                "Y",
+                "applied 1 at 6 (1)",
+                "F",
+            ]
+        );
+
+        let mut recorder = Recorder {
+            events: Vec::new(),
+            stop_at: Some(1),
+        };
+        assert!(matches!(
+            apply_with_observer(&patch, content, &options, &mut recorder),
+            Err(ApplyError::Cancelled)
+        ));
+        assert_eq!(recorder.events.last().unwrap(), "start 1");
+    }
+
+    #[test]
+    fn test_apply_cancelled() {
+        let patch = Patch::from_single("--- a\n+++ b\n@@ -1,2 +1,2 @@\n a\n-b\n+B\n").unwrap();
+        let content = "0\na\nb\n";
+        let cancel = CancellationToken::new();
+        let options = ApplyOptions {
+            cancel: Some(cancel.clone()),
+            ..ApplyOptions::default()
+        };
+        assert_eq!(
+            apply_with_options(&patch, content, &options).unwrap(),
+            "0\na\nB\n"
+        );
+
+        // A cancelled search stops before trying any position
+        cancel.cancel();
+        assert!(matches!(
+            apply_with_options(&patch, content, &options),
+            Err(ApplyError::Cancelled)
+        ));
+        let (result, rejected) = apply_partial(&patch, content, &options);
+        assert_eq!(result, content);
+        assert!(matches!(rejected[0].error, ApplyError::Cancelled));
+    }
+
+    #[test]
+    fn test_apply_keeps_line_endings() {
         let patch_text = "\
 --- old.txt
 +++ new.txt
-@@ -1,4 +1,2 @@
+@@ -1,4 +1,5 @@
  A
 -B
--C
++X
++Y
+ C
  D
 ";
         let patch = Patch::from_single(patch_text).unwrap();
-        let result = apply(&patch, content).unwrap();
-        assert_eq!(result, "A\nD\n");
+
+        // Added lines take the ending of the line before them
+        let result = apply(&patch, "A\r\nB\r\nC\nD\r\n").unwrap();
+        assert_eq!(result, "A\r\nX\r\nY\r\nC\nD\r\n");
+
+        let result = apply_bytes(
+            &Patch::from_bytes(patch_text.as_bytes()).unwrap(),
+            b"A\nB\r\nC\r\nD",
+        )
+        .unwrap();
+        assert_eq!(result, b"A\nX\nY\nC\r\nD\r\n");
+
+        let options = ApplyOptions {
+            line_ending: Some(LineEnding::CrLf),
+            ..ApplyOptions::default()
+        };
+        let result = apply_with_options(&patch, "A\nB\r\nC\nD\n", &options).unwrap();
+        assert_eq!(result, "A\r\nX\r\nY\r\nC\r\nD\r\n");
+    }
+
+    #[test]
+    fn test_apply_missing_newline_per_side() {
+        let patch_text = "\
+--- old.txt
++++ new.txt
+@@ -2,2 +2,2 @@
+ B
+-C
+\\ No newline at end of file
++C
+";
+        let patch = Patch::from_single(patch_text).unwrap();
+        assert_eq!(apply(&patch, "A\nB\nC").unwrap(), "A\nB\nC\n");
+        assert_eq!(apply_reverse(&patch, "A\nB\nC\n").unwrap(), "A\nB\nC");
+
+        // Hunks that don't reach the end of the content leave its ending alone
+        let patch_text = "\
+--- old.txt
++++ new.txt
+@@ -1,2 +1,2 @@
+-A
++X
+ B
+";
+        let patch = Patch::from_single(patch_text).unwrap();
+        assert_eq!(apply(&patch, "A\nB\nC").unwrap(), "X\nB\nC");
+    }
+
+    #[test]
+    fn test_nearest_first() {
+        let order: Vec<usize> = nearest_first(3, 1, 5).collect();
+        assert_eq!(order, [3, 4, 2, 5, 1]);
+        let order: Vec<usize> = nearest_first(0, 0, 2).collect();
+        assert_eq!(order, [0, 1, 2]);
     }
 
     #[test]
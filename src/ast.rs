@@ -1,40 +1,197 @@
-use std::borrow::Cow;
-use std::fmt;
+use alloc::borrow::{Cow, ToOwned};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
 
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, Timelike};
 
-use crate::parser::{ParseError, parse_multiple_patches, parse_single_patch};
+use crate::applier::ApplyError;
+use crate::generator::hunk_range;
+use crate::parser::{
+    ParseError, ParseOptions, ParseWarning, parse_multiple_patches, parse_multiple_patches_bytes,
+    parse_multiple_patches_with_options, parse_single_patch, parse_single_patch_bytes, reparse,
+};
+
+/// The path diff programs use in place of a file that doesn't exist on one side of the diff
+pub(crate) const DEV_NULL: &str = "/dev/null";
 
 /// A complete patch summarizing the differences between two files
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub struct Patch<'a> {
+///
+/// The lines of the patch are `str`s by default. Patches parsed with [`Patch::from_bytes`] hold
+/// byte slices instead, for content that isn't valid UTF-8.
+#[derive(Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "T: serde::Serialize",
+        deserialize = "T::Owned: serde::Deserialize<'de>"
+    ))
+)]
+pub struct Patch<'a, T: ?Sized + ToOwned = str> {
     /// The file information of the `-` side of the diff, line prefix: `---`
     pub old: File<'a>,
     /// The file information of the `+` side of the diff, line prefix: `+++`
     pub new: File<'a>,
+    /// The kind of change this patch makes to the file, as described by git's extended header
+    /// lines
+    ///
+    /// This is always [`FileOp::Modify`] for patches without a `diff --git` header.
+    pub operation: FileOp<'a>,
+    /// The `diff --git` line and the extended header lines following it, if the patch had them
+    pub git: Option<GitHeader<'a>>,
+    /// The `Index:` line and the property changes Subversion writes around the file headers and
+    /// hunks, if the patch had them
+    pub svn: Option<SvnHeader<'a>>,
     /// hunks of differences; each hunk shows one area where the files differ
-    pub hunks: Vec<Hunk<'a>>,
-    /// true if the last line of the file ends in a newline character
+    pub hunks: Vec<Hunk<'a, T>>,
+    /// The changes to a binary file, line prefix: `GIT binary patch`
+    ///
+    /// Binary patches have no hunks. See `apply_binary`.
+    pub binary: Option<BinaryPatch<'a>>,
+    /// true if the patch only says that a binary file changed, without the changes, line:
+    /// `Binary files a/x and b/x differ`
+    ///
+    /// These patches have no hunks and can't be applied, neither to text nor with `apply_binary`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub opaque_binary: bool,
+    /// The text before the start of the patch, such as a commit message or notes between the
+    /// files of a diff, with its line endings
+    ///
+    /// Parsing keeps it and writing the patch writes it back, but it isn't part of the changes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub preamble: Option<Cow<'a, str>>,
+    /// The exact text the patch was parsed from, kept by [`ParseOptions::lossless`] parsing
+    ///
+    /// As long as the rest of the patch is unchanged, writing the patch writes this text back
+    /// byte for byte. It isn't serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub raw: Option<Cow<'a, str>>,
+    /// true if the last line of the old file ends in a newline character
+    ///
+    /// This will only be false if the last hunk reaches the end of the old file and its last
+    /// removed or context line is followed by the text: `\ No newline at end of file`
+    pub old_end_newline: bool,
+    /// true if the last line of the new file ends in a newline character
     ///
-    /// This will only be false if at the end of the patch we encounter the text:
-    /// `\ No newline at end of file`
-    pub end_newline: bool,
+    /// This will only be false if the last hunk reaches the end of the new file and its last
+    /// added or context line is followed by the text: `\ No newline at end of file`
+    pub new_end_newline: bool,
 }
 
 impl fmt::Display for Patch<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_marking_wide(f, None)
+    }
+}
+
+/// Writes a patch with the lines of its hunks that are wider than a maximum marked, see
+/// [`FormatOptions::max_width`](crate::FormatOptions::max_width)
+#[cfg(feature = "std")]
+pub(crate) struct MarkWide<'p, 'a>(pub(crate) &'p Patch<'a>, pub(crate) usize);
+
+#[cfg(feature = "std")]
+impl fmt::Display for MarkWide<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt_marking_wide(f, Some(self.1))
+    }
+}
+
+impl Patch<'_> {
+    fn fmt_marking_wide(&self, f: &mut fmt::Formatter, max_width: Option<usize>) -> fmt::Result {
         // Display implementations typically hold up the invariant that there is no trailing
         // newline. This isn't enforced, but it allows them to work well with `println!`
 
-        write!(f, "--- {}", self.old)?;
-        write!(f, "\n+++ {}", self.new)?;
-        for hunk in &self.hunks {
-            write!(f, "\n{}", hunk)?;
+        // Patches parsed without loss are written as they were, unless they were changed since
+        if let Some(raw) = self.raw.as_ref().filter(|_| max_width.is_none()) {
+            let unchanged = |lenient| reparse(raw, lenient).as_ref() == Some(self);
+            if unchanged(false) || unchanged(true) {
+                return write!(f, "{}", raw);
+            }
+        }
+
+        if let Some(preamble) = &self.preamble {
+            write!(f, "{}", preamble)?;
         }
-        if !self.end_newline {
-            write!(f, "\n\\ No newline at end of file")?;
+        if let Some(index) = self.svn.as_ref().and_then(|svn| svn.index.as_ref()) {
+            writeln!(f, "Index: {}\n{}", index, "=".repeat(SVN_RULE_WIDTH))?;
+        }
+
+        let mut file_headers = true;
+        if let Some(git) = &self.git {
+            write!(f, "diff --git ")?;
+            maybe_escape_quote(f, &git.old_path)?;
+            write!(f, " ")?;
+            maybe_escape_quote(f, &git.new_path)?;
+            git.fmt_extended(f, &self.operation)?;
+
+            if let Some(binary) = &self.binary {
+                write!(f, "\n{}", binary)?;
+                file_headers = false;
+            } else if self.opaque_binary {
+                writeln!(f)?;
+                file_headers = false;
+            } else if self.hunks.is_empty() {
+                // Patches that only rename a file or change its mode have no hunks, and git
+                // leaves out the file headers for them
+                file_headers = false;
+            } else {
+                writeln!(f)?;
+            }
+        }
+
+        if self.opaque_binary && self.binary.is_none() {
+            write!(f, "Binary files ")?;
+            maybe_escape_quote(f, &self.old.path)?;
+            write!(f, " and ")?;
+            maybe_escape_quote(f, &self.new.path)?;
+            write!(f, " differ")?;
+        } else if file_headers {
+            write!(f, "--- {}", self.old)?;
+            write!(f, "\n+++ {}", self.new)?;
+            for (i, hunk) in self.hunks.iter().enumerate() {
+                writeln!(f)?;
+                if i + 1 == self.hunks.len() {
+                    hunk.fmt_with_markers(
+                        f,
+                        self.old_end_newline,
+                        self.new_end_newline,
+                        max_width,
+                    )?;
+                } else {
+                    hunk.fmt_with_markers(f, true, true, max_width)?;
+                }
+            }
+        }
+
+        match &self.svn {
+            Some(svn) if !svn.properties.is_empty() => {
+                let path = svn.index.as_deref().unwrap_or(&self.new.path);
+                svn.fmt_properties(f, path)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<T: ?Sized + ToOwned> Clone for Patch<'_, T> {
+    fn clone(&self) -> Self {
+        Patch {
+            old: self.old.clone(),
+            new: self.new.clone(),
+            operation: self.operation.clone(),
+            git: self.git.clone(),
+            svn: self.svn.clone(),
+            hunks: self.hunks.clone(),
+            binary: self.binary.clone(),
+            opaque_binary: self.opaque_binary,
+            preamble: self.preamble.clone(),
+            raw: self.raw.clone(),
+            old_end_newline: self.old_end_newline,
+            new_end_newline: self.new_end_newline,
         }
-        Ok(())
     }
 }
 
@@ -72,7 +229,8 @@ impl<'a> Patch<'a> {
     /// let patch = Patch::from_single(sample)?;
     /// assert_eq!(&patch.old.path, "lao");
     /// assert_eq!(&patch.new.path, "tzu");
-    /// assert_eq!(patch.end_newline, false);
+    /// assert_eq!(patch.old_end_newline, true);
+    /// assert_eq!(patch.new_end_newline, false);
     /// # Ok(())
     /// # }
     /// ```
@@ -133,25 +291,830 @@ impl<'a> Patch<'a> {
     pub fn from_multiple(s: &'a str) -> Result<Vec<Self>, ParseError<'a>> {
         parse_multiple_patches(s)
     }
+
+    /// Parse as many patches as possible from the given string, as chosen by [`ParseOptions`].
+    ///
+    /// With [`ParseOptions::lenient`], the parts of the input that can't be parsed are skipped
+    /// and returned as warnings along with the patches, so a partially corrupted diff can still
+    /// be salvaged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), patch::ParseError<'static>> {
+    /// # use patch::{ParseOptions, Patch, Skipped};
+    /// let sample = "\
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -1,2 +1,2 @@
+    /// -a
+    /// +A
+    /// @@ -8 +8 @@
+    /// -h
+    /// +H
+    /// ";
+    ///
+    /// let options = ParseOptions {
+    ///     lenient: true,
+    ///     ..ParseOptions::default()
+    /// };
+    /// let (patches, warnings) = Patch::from_multiple_with_options(sample, &options)?;
+    /// assert_eq!(patches[0].hunks.len(), 1);
+    /// assert_eq!(warnings[0].error.line, 6);
+    /// assert_eq!(warnings[0].skipped, Skipped::Hunk);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_multiple_with_options(
+        s: &'a str,
+        options: &ParseOptions,
+    ) -> Result<(Vec<Self>, Vec<ParseWarning<'a>>), ParseError<'a>> {
+        parse_multiple_patches_with_options(s, options)
+    }
+}
+
+impl<'a> Patch<'a, [u8]> {
+    /// Attempt to parse a patch from the given bytes, without requiring them to be valid UTF-8.
+    ///
+    /// The lines of the resulting patch are byte slices of the input, so they can be applied to
+    /// content in any encoding with [`apply_bytes`](crate::apply_bytes). File paths are decoded
+    /// as UTF-8, replacing invalid sequences.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), patch::ParseError<'static>> {
+    /// # use std::borrow::Cow;
+    /// # use patch::{Line, Patch};
+    /// // "caf\xe9" is "café" in Latin-1
+    /// let sample = b"--- menu.txt\n+++ menu.txt\n@@ -1 +1 @@\n-tea\n+caf\xe9\n";
+    ///
+    /// let patch = Patch::from_bytes(sample)?;
+    /// assert_eq!(patch.hunks[0].lines[1], Line::Add(Cow::Borrowed(&b"caf\xe9"[..])));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_bytes(s: &'a [u8]) -> Result<Self, ParseError<'a>> {
+        parse_single_patch_bytes(s)
+    }
+
+    /// Attempt to parse as many patches as possible from the given bytes, without requiring them
+    /// to be valid UTF-8. See [`Patch::from_bytes`].
+    pub fn from_multiple_bytes(s: &'a [u8]) -> Result<Vec<Self>, ParseError<'a>> {
+        parse_multiple_patches_bytes(s)
+    }
+}
+
+impl<'a, T: ?Sized + ToOwned> Patch<'a, T> {
+    /// What the patch does to its file
+    ///
+    /// This is the [`operation`](Self::operation) of patches with a `diff --git` header. Other
+    /// patches create their file if the old file is `/dev/null`, and delete it if the new file
+    /// is, like GNU patch understands them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::{FileOp, Patch};
+    ///
+    /// let patch = Patch::from_single("--- /dev/null\n+++ new.txt\n@@ -0,0 +1 @@\n+hi\n").unwrap();
+    /// assert_eq!(patch.operation, FileOp::Modify);
+    /// assert_eq!(patch.file_op(), FileOp::Create);
+    /// ```
+    pub fn file_op(&self) -> FileOp<'a> {
+        match &self.operation {
+            FileOp::Modify if self.old.path == DEV_NULL => FileOp::Create,
+            FileOp::Modify if self.new.path == DEV_NULL => FileOp::Delete,
+            operation => operation.clone(),
+        }
+    }
+
+    /// True if the patch changes a binary file, with a `GIT binary patch` or a
+    /// `Binary files a/x and b/x differ` line
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::Patch;
+    ///
+    /// let patch = Patch::from_single("Binary files a/logo.png and b/logo.png differ\n").unwrap();
+    /// assert!(patch.is_binary() && patch.opaque_binary);
+    /// assert_eq!(patch.new.path, "b/logo.png");
+    /// ```
+    pub fn is_binary(&self) -> bool {
+        self.binary.is_some() || self.opaque_binary
+    }
+
+    /// Produce the inverse of this patch, which undoes its changes.
+    ///
+    /// The old and new files trade places, as do the ranges of every hunk, and added lines
+    /// become removed lines and vice versa. This is what `patch -R` and `git apply -R` do.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), patch::ParseError<'static>> {
+    /// # use patch::{Line, Patch};
+    /// let sample = "\
+    /// --- before.py
+    /// +++ after.py
+    /// @@ -1,2 +1,2 @@
+    /// -bacon
+    /// +python
+    ///  guido\n";
+    ///
+    /// let patch = Patch::from_single(sample)?.reverse();
+    /// assert_eq!(&patch.old.path, "after.py");
+    /// assert_eq!(patch.hunks[0].lines[0], Line::Remove("python".into()));
+    /// assert_eq!(patch.hunks[0].lines[1], Line::Add("bacon".into()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reverse(&self) -> Patch<'a, T> {
+        Patch {
+            old: self.new.clone(),
+            new: self.old.clone(),
+            operation: self.operation.reverse(),
+            git: self.git.as_ref().map(GitHeader::reverse),
+            svn: self.svn.as_ref().map(SvnHeader::reverse),
+            hunks: self.hunks.iter().map(Hunk::reverse).collect(),
+            binary: self.binary.as_ref().map(BinaryPatch::reverse),
+            opaque_binary: self.opaque_binary,
+            preamble: self.preamble.clone(),
+            raw: None,
+            old_end_newline: self.new_end_newline,
+            new_end_newline: self.old_end_newline,
+        }
+    }
+
+    /// Recount the lines of every hunk, fixing the counts of their ranges. See [`Hunk::recount`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), patch::ParseError<'static>> {
+    /// # use patch::{Patch, Range};
+    /// let sample = "\
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -1,3 +1,3 @@
+    ///  a
+    /// -b
+    /// +B
+    /// +C
+    /// ";
+    ///
+    /// let mut patch = Patch::from_single(sample)?;
+    /// patch.recount();
+    /// assert_eq!(patch.hunks[0].old_range, Range { start: 1, count: 2 });
+    /// assert_eq!(patch.hunks[0].new_range, Range { start: 1, count: 3 });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn recount(&mut self) {
+        self.hunks.iter_mut().for_each(Hunk::recount);
+    }
+
+    /// Count the lines of all hunks of the patch. See [`Hunk::stats`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use patch::Patch;
+    /// let sample = "\
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -1,2 +1,3 @@
+    ///  a
+    /// -b
+    /// +B
+    /// +C
+    /// ";
+    ///
+    /// let stats = Patch::from_single(sample).unwrap().stats();
+    /// assert_eq!((stats.additions, stats.deletions, stats.context), (2, 1, 1));
+    /// assert_eq!(stats.net(), 1);
+    /// ```
+    pub fn stats(&self) -> LineStats {
+        self.hunks.iter().map(Hunk::stats).sum()
+    }
+
+    /// Keep only the hunks for which `keep` returns true, moving the new ranges of the hunks
+    /// after the removed ones so the patch still applies.
+    ///
+    /// The old file isn't affected by leaving out a hunk, but the new file no longer has its
+    /// changes, so the hunks after it start that many lines earlier or later in the new file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), patch::ParseError<'static>> {
+    /// # use patch::{Patch, Range};
+    /// let sample = "\
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -1 +1,2 @@
+    ///  a
+    /// +b
+    /// @@ -5 +6 @@
+    /// -e
+    /// +E
+    /// ";
+    ///
+    /// let mut patch = Patch::from_single(sample)?;
+    /// patch.retain_hunks(|hunk| hunk.old_range.start > 1);
+    /// assert_eq!(patch.hunks[0].new_range, Range { start: 5, count: 1 });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn retain_hunks(&mut self, mut keep: impl FnMut(&Hunk<'a, T>) -> bool) {
+        let count = self.hunks.len();
+        let mut index = 0;
+        let mut kept_last = true;
+        self.hunks.retain(|hunk| {
+            index += 1;
+            let kept = keep(hunk);
+            if index == count {
+                kept_last = kept;
+            }
+            kept
+        });
+        if self.hunks.len() < count {
+            self.renumber();
+        }
+        // A missing newline at the end belonged to the last hunk
+        if !kept_last {
+            (self.old_end_newline, self.new_end_newline) = (true, true);
+        }
+    }
+
+    /// Remove the hunk at `index` and return it, moving the new ranges of the hunks after it
+    /// like [`Patch::retain_hunks`] does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn drop_hunk(&mut self, index: usize) -> Hunk<'a, T> {
+        let hunk = self.hunks.remove(index);
+        self.renumber();
+        if index == self.hunks.len() {
+            (self.old_end_newline, self.new_end_newline) = (true, true);
+        }
+        hunk
+    }
+
+    /// Change or remove the lines of every hunk with [`Hunk::map_lines`], moving the new ranges
+    /// of the hunks after the ones that changed size.
+    pub fn map_lines(&mut self, mut f: impl FnMut(Line<'a, T>) -> Option<Line<'a, T>>) {
+        for hunk in &mut self.hunks {
+            hunk.map_lines(&mut f);
+        }
+        self.renumber();
+    }
+
+    /// Recompute where the new range of every hunk starts from its old range and the number of
+    /// lines the hunks before it add or remove.
+    ///
+    /// This makes a patch valid again after its hunks were added, removed or changed by hand.
+    pub fn renumber(&mut self) {
+        let mut growth: i64 = 0;
+        for hunk in &mut self.hunks {
+            let lines_before = match hunk.old_range.count {
+                0 => hunk.old_range.start,
+                _ => hunk.old_range.start.saturating_sub(1),
+            };
+            let new_before = (lines_before as i64 + growth).max(0) as u64;
+            hunk.new_range = hunk_range(new_before, hunk.new_range.count);
+            growth += hunk.new_range.count as i64 - hunk.old_range.count as i64;
+        }
+    }
+
+    /// Merge every hunk that starts before the end of the hunk before it in the old file into
+    /// that hunk, so the patch can be applied, and renumber the new ranges like
+    /// [`Patch::renumber`].
+    ///
+    /// Patches put together by hand or by tools that split changes badly can have hunks that
+    /// share lines, which applying them rejects with [`ApplyError::OverlappingHunks`]. The
+    /// merged hunk has every line of the old file once, removed if either hunk removes it, and
+    /// the lines either hunk adds. If the hunks disagree about a line of the old file, or add
+    /// different lines at the same place, no merge is consistent with both: this fails with
+    /// the [`ApplyError::OverlappingHunks`] error of the hunk and leaves the patch unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::{Patch, apply};
+    ///
+    /// let sample = "\
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -1,3 +1,3 @@
+    ///  a
+    /// -b
+    /// +B
+    ///  c
+    /// @@ -2,3 +2,3 @@
+    ///  b
+    ///  c
+    /// -d
+    /// +D
+    /// ";
+    ///
+    /// let mut patch = Patch::from_single(sample).unwrap();
+    /// assert!(apply(&patch, "a\nb\nc\nd\n").is_err());
+    /// patch.merge_overlapping_hunks().unwrap();
+    /// assert_eq!(patch.hunks.len(), 1);
+    /// assert_eq!(apply(&patch, "a\nb\nc\nd\n").unwrap(), "a\nB\nc\nD\n");
+    /// ```
+    pub fn merge_overlapping_hunks(&mut self) -> Result<(), ApplyError>
+    where
+        T: PartialEq,
+    {
+        let mut hunks: Vec<Hunk<'a, T>> = Vec::with_capacity(self.hunks.len());
+        for (i, hunk) in self.hunks.iter().enumerate() {
+            match hunks.last_mut() {
+                Some(previous) if overlap(previous, hunk).is_some() => {
+                    *previous = merge_hunks(previous, hunk).ok_or_else(|| {
+                        ApplyError::OverlappingHunks {
+                            hunk: i,
+                            line: hunk.old_range.first_line(),
+                        }
+                    })?;
+                }
+                _ => hunks.push(hunk.clone()),
+            }
+        }
+        if hunks.len() < self.hunks.len() {
+            self.hunks = hunks;
+            self.renumber();
+        }
+        Ok(())
+    }
+
+    /// Convert into a patch that owns all of its data, so it no longer borrows from the text it
+    /// was parsed from.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::Patch;
+    ///
+    /// fn parse(text: String) -> Patch<'static> {
+    ///     Patch::from_single(&text).unwrap().into_owned()
+    /// }
+    ///
+    /// let patch = parse("--- a\n+++ b\n@@ -1 +1 @@\n-x\n+y\n".to_string());
+    /// assert_eq!(patch.hunks.len(), 1);
+    /// ```
+    pub fn into_owned(self) -> Patch<'static, T>
+    where
+        T: 'static,
+    {
+        Patch {
+            old: self.old.into_owned(),
+            new: self.new.into_owned(),
+            operation: self.operation.into_owned(),
+            git: self.git.map(GitHeader::into_owned),
+            svn: self.svn.map(SvnHeader::into_owned),
+            hunks: self.hunks.into_iter().map(Hunk::into_owned).collect(),
+            binary: self.binary.map(BinaryPatch::into_owned),
+            opaque_binary: self.opaque_binary,
+            preamble: self.preamble.map(owned),
+            raw: self.raw.map(owned),
+            old_end_newline: self.old_end_newline,
+            new_end_newline: self.new_end_newline,
+        }
+    }
+}
+
+// Take ownership of borrowed data
+fn owned<T: ?Sized + ToOwned + 'static>(text: Cow<'_, T>) -> Cow<'static, T> {
+    Cow::Owned(text.into_owned())
+}
+
+/// The kind of change a patch makes to a file
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FileOp<'a> {
+    /// The content of an existing file is changed
+    #[default]
+    Modify,
+    /// The file is created, line prefix: `new file mode`
+    Create,
+    /// The file is deleted, line prefix: `deleted file mode`
+    Delete,
+    /// The file is moved to another path, line prefixes: `rename from`, `rename to`
+    Rename {
+        /// The path of the file before the rename, without the `a/` prefix
+        from: Cow<'a, str>,
+        /// The path of the file after the rename, without the `b/` prefix
+        to: Cow<'a, str>,
+    },
+    /// The file is copied to another path, line prefixes: `copy from`, `copy to`
+    Copy {
+        /// The path of the file that is copied, without the `a/` prefix
+        from: Cow<'a, str>,
+        /// The path of the copy, without the `b/` prefix
+        to: Cow<'a, str>,
+    },
+}
+
+impl<'a> FileOp<'a> {
+    /// The operation that undoes this one
+    ///
+    /// Like `git apply -R`, the paths of renames and copies are swapped.
+    pub fn reverse(&self) -> FileOp<'a> {
+        match self {
+            FileOp::Modify => FileOp::Modify,
+            FileOp::Create => FileOp::Delete,
+            FileOp::Delete => FileOp::Create,
+            FileOp::Rename { from, to } => FileOp::Rename {
+                from: to.clone(),
+                to: from.clone(),
+            },
+            FileOp::Copy { from, to } => FileOp::Copy {
+                from: to.clone(),
+                to: from.clone(),
+            },
+        }
+    }
+
+    /// Convert into an operation that owns all of its data
+    pub fn into_owned(self) -> FileOp<'static> {
+        match self {
+            FileOp::Modify => FileOp::Modify,
+            FileOp::Create => FileOp::Create,
+            FileOp::Delete => FileOp::Delete,
+            FileOp::Rename { from, to } => FileOp::Rename {
+                from: owned(from),
+                to: owned(to),
+            },
+            FileOp::Copy { from, to } => FileOp::Copy {
+                from: owned(from),
+                to: owned(to),
+            },
+        }
+    }
+}
+
+/// The information git adds in front of the file headers of a patch
+///
+/// ```text
+/// diff --git a/old.rs b/new.rs
+/// old mode 100644
+/// new mode 100755
+/// similarity index 90%
+/// rename from old.rs
+/// rename to new.rs
+/// index 3b18e51..a042389
+/// ```
+///
+/// The operation described by these lines is stored in [`Patch::operation`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GitHeader<'a> {
+    /// The old path on the `diff --git` line, usually prefixed with `a/`
+    pub old_path: Cow<'a, str>,
+    /// The new path on the `diff --git` line, usually prefixed with `b/`
+    pub new_path: Cow<'a, str>,
+    /// The mode of the file before the change, line prefix: `old mode` or `deleted file mode`
+    pub old_mode: Option<u32>,
+    /// The mode of the file after the change, line prefix: `new mode` or `new file mode`
+    pub new_mode: Option<u32>,
+    /// How similar the old and new file are in percent, for renames and copies, line prefix:
+    /// `similarity index`
+    pub similarity: Option<u8>,
+    /// How different the old and new file are in percent, for complete rewrites, line prefix:
+    /// `dissimilarity index`
+    pub dissimilarity: Option<u8>,
+    /// The object ids of the file before and after the change, line prefix: `index`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub index: Option<GitIndex<'a>>,
+}
+
+impl<'a> GitHeader<'a> {
+    /// The header of the patch that undoes this one
+    pub fn reverse(&self) -> GitHeader<'a> {
+        GitHeader {
+            old_path: self.new_path.clone(),
+            new_path: self.old_path.clone(),
+            old_mode: self.new_mode,
+            new_mode: self.old_mode,
+            similarity: self.similarity,
+            dissimilarity: self.dissimilarity,
+            index: self.index.as_ref().map(GitIndex::reverse),
+        }
+    }
+
+    /// Convert into a header that owns all of its data
+    pub fn into_owned(self) -> GitHeader<'static> {
+        GitHeader {
+            old_path: owned(self.old_path),
+            new_path: owned(self.new_path),
+            index: self.index.map(GitIndex::into_owned),
+            ..self
+        }
+    }
+
+    // Write the extended header lines, in the order git writes them
+    fn fmt_extended(&self, f: &mut fmt::Formatter, operation: &FileOp) -> fmt::Result {
+        match operation {
+            FileOp::Create => {
+                if let Some(mode) = self.new_mode {
+                    write!(f, "\nnew file mode {:06o}", mode)?;
+                }
+            }
+            FileOp::Delete => {
+                if let Some(mode) = self.old_mode {
+                    write!(f, "\ndeleted file mode {:06o}", mode)?;
+                }
+            }
+            _ => {
+                if let Some(mode) = self.old_mode {
+                    write!(f, "\nold mode {:06o}", mode)?;
+                }
+                if let Some(mode) = self.new_mode {
+                    write!(f, "\nnew mode {:06o}", mode)?;
+                }
+            }
+        }
+        if let Some(similarity) = self.similarity {
+            write!(f, "\nsimilarity index {}%", similarity)?;
+        }
+        if let Some(dissimilarity) = self.dissimilarity {
+            write!(f, "\ndissimilarity index {}%", dissimilarity)?;
+        }
+        match operation {
+            FileOp::Rename { from, to } => {
+                write!(f, "\nrename from ")?;
+                maybe_escape_quote(f, from)?;
+                write!(f, "\nrename to ")?;
+                maybe_escape_quote(f, to)?;
+            }
+            FileOp::Copy { from, to } => {
+                write!(f, "\ncopy from ")?;
+                maybe_escape_quote(f, from)?;
+                write!(f, "\ncopy to ")?;
+                maybe_escape_quote(f, to)?;
+            }
+            _ => {}
+        }
+        if let Some(index) = &self.index {
+            write!(f, "\n{}", index)?;
+        }
+        Ok(())
+    }
+}
+
+/// The `index` line of a git patch, with the abbreviated ids of the blobs of the file before and
+/// after the change
+///
+/// The ids of files that don't exist are all zeros. See `verify_index` for checking that a file
+/// is the one a patch was made for.
+///
+/// # Example
+///
+/// ```
+/// use patch::Patch;
+///
+/// let sample = "\
+/// diff --git a/README.md b/README.md
+/// index 3b18e51..a042389 100644
+/// --- a/README.md
+/// +++ b/README.md
+/// @@ -1 +1 @@
+/// -hello
+/// +hello, world
+/// ";
+/// let patch = Patch::from_single(sample).unwrap();
+/// let index = patch.git.unwrap().index.unwrap();
+/// assert_eq!((&*index.old_id, &*index.new_id, index.mode), ("3b18e51", "a042389", Some(0o100644)));
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GitIndex<'a> {
+    /// The id of the old file, in hexadecimal
+    pub old_id: Cow<'a, str>,
+    /// The id of the new file, in hexadecimal
+    pub new_id: Cow<'a, str>,
+    /// The mode of the file, if the patch doesn't change it
+    pub mode: Option<u32>,
+}
+
+impl<'a> GitIndex<'a> {
+    /// The index line of the patch that undoes this one
+    pub fn reverse(&self) -> GitIndex<'a> {
+        GitIndex {
+            old_id: self.new_id.clone(),
+            new_id: self.old_id.clone(),
+            mode: self.mode,
+        }
+    }
+
+    /// Convert into an index line that owns all of its data
+    pub fn into_owned(self) -> GitIndex<'static> {
+        GitIndex {
+            old_id: owned(self.old_id),
+            new_id: owned(self.new_id),
+            mode: self.mode,
+        }
+    }
+}
+
+impl fmt::Display for GitIndex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "index {}..{}", self.old_id, self.new_id)?;
+        if let Some(mode) = self.mode {
+            write!(f, " {:06o}", mode)?;
+        }
+        Ok(())
+    }
+}
+
+/// The width of the `=` line after the `Index:` line and of the `_` line after the `Property
+/// changes on:` line of Subversion patches
+const SVN_RULE_WIDTH: usize = 67;
+
+/// The information Subversion adds around the file headers and hunks of a patch
+///
+/// ```text
+/// Index: trunk/run.sh
+/// ===================================================================
+/// --- trunk/run.sh	(revision 12)
+/// +++ trunk/run.sh	(working copy)
+/// @@ -1 +1 @@
+/// -echo hello
+/// +echo hello, world
+///
+/// Property changes on: trunk/run.sh
+/// ___________________________________________________________________
+/// Added: svn:executable
+/// ## -0,0 +1 ##
+/// +*
+/// \ No newline at end of property
+/// ```
+///
+/// Patches whose file only had its properties changed have no hunks.
+#[allow(clippy::tabs_in_doc_comments)]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SvnHeader<'a> {
+    /// The path on the `Index:` line, if the patch has one
+    pub index: Option<Cow<'a, str>>,
+    /// The changes to the properties of the file, after the `Property changes on:` line
+    pub properties: Vec<PropertyChange<'a>>,
+}
+
+impl<'a> SvnHeader<'a> {
+    /// The header of the patch that undoes this one
+    pub fn reverse(&self) -> SvnHeader<'a> {
+        SvnHeader {
+            index: self.index.clone(),
+            properties: self
+                .properties
+                .iter()
+                .map(PropertyChange::reverse)
+                .collect(),
+        }
+    }
+
+    /// Convert into a header that owns all of its data
+    pub fn into_owned(self) -> SvnHeader<'static> {
+        SvnHeader {
+            index: self.index.map(owned),
+            properties: self
+                .properties
+                .into_iter()
+                .map(PropertyChange::into_owned)
+                .collect(),
+        }
+    }
+
+    // Write the `Property changes on:` section, the way Subversion 1.7 and later write it
+    fn fmt_properties(&self, f: &mut fmt::Formatter, path: &str) -> fmt::Result {
+        write!(
+            f,
+            "\n\nProperty changes on: {}\n{}",
+            path,
+            "_".repeat(SVN_RULE_WIDTH)
+        )?;
+        for property in &self.properties {
+            let kind = match (&property.old, &property.new) {
+                (None, _) => "Added",
+                (_, None) => "Deleted",
+                _ => "Modified",
+            };
+            let old = property.old.as_deref().map(property_lines);
+            let new = property.new.as_deref().map(property_lines);
+            write!(
+                f,
+                "\n{}: {}\n## -{} +{} ##",
+                kind,
+                property.name,
+                property_range(&old),
+                property_range(&new)
+            )?;
+            for (prefix, value) in [('-', old), ('+', new)] {
+                let Some((lines, end_newline)) = value else {
+                    continue;
+                };
+                for line in lines {
+                    write!(f, "\n{}{}", prefix, line)?;
+                }
+                if !end_newline {
+                    write!(f, "\n\\ No newline at end of property")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The lines of the value of a property, and whether it ends with a newline character
+fn property_lines(value: &str) -> (Vec<&str>, bool) {
+    match value.strip_suffix('\n') {
+        Some(value) => (value.split('\n').collect(), true),
+        None => (value.split('\n').collect(), false),
+    }
+}
+
+// The range of the lines of a property value in the `## -1 +1 ##` line of its change
+fn property_range(value: &Option<(Vec<&str>, bool)>) -> String {
+    match value.as_ref().map_or(0, |(lines, _)| lines.len()) {
+        0 => "0,0".to_string(),
+        1 => "1".to_string(),
+        count => format!("1,{}", count),
+    }
+}
+
+/// A change to a property of a file in a Subversion patch, like `svn:executable` or
+/// `svn:ignore`, line prefixes: `Added:`, `Deleted:`, `Modified:`
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PropertyChange<'a> {
+    /// The name of the property
+    pub name: Cow<'a, str>,
+    /// The value of the property before the change, `None` if it is added
+    pub old: Option<Cow<'a, str>>,
+    /// The value of the property after the change, `None` if it is deleted
+    pub new: Option<Cow<'a, str>>,
+}
+
+impl<'a> PropertyChange<'a> {
+    /// The change that undoes this one
+    pub fn reverse(&self) -> PropertyChange<'a> {
+        PropertyChange {
+            name: self.name.clone(),
+            old: self.new.clone(),
+            new: self.old.clone(),
+        }
+    }
+
+    /// Convert into a change that owns all of its data
+    pub fn into_owned(self) -> PropertyChange<'static> {
+        PropertyChange {
+            name: owned(self.name),
+            old: self.old.map(owned),
+            new: self.new.map(owned),
+        }
+    }
 }
 
 /// Check if a string needs to be quoted, and format it accordingly
-fn maybe_escape_quote(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+///
+/// Strings are quoted like git quotes paths, with the bytes of characters that aren't ASCII
+/// escaped in octal, and also if they contain spaces, so the end of the path is never ambiguous.
+pub(crate) fn maybe_escape_quote(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
     let quote = s
         .chars()
-        .any(|ch| matches!(ch, ' ' | '\t' | '\r' | '\n' | '\"' | '\0' | '\\'));
+        .any(|ch| matches!(ch, ' ' | '\"' | '\\') || ch.is_control() || !ch.is_ascii());
 
     if quote {
         write!(f, "\"")?;
-        for ch in s.chars() {
+        let mut chars = s.chars().peekable();
+        while let Some(ch) = chars.next() {
             match ch {
+                // `\0` followed by octal digits would be read as one octal escape
+                '\0' if chars.peek().is_some_and(|next| ('0'..='7').contains(next)) => {
+                    write!(f, r"\000")?
+                }
                 '\0' => write!(f, r"\0")?,
+                '\x07' => write!(f, r"\a")?,
+                '\x08' => write!(f, r"\b")?,
+                '\t' => write!(f, r"\t")?,
                 '\n' => write!(f, r"\n")?,
+                '\x0b' => write!(f, r"\v")?,
+                '\x0c' => write!(f, r"\f")?,
                 '\r' => write!(f, r"\r")?,
-                '\t' => write!(f, r"\t")?,
                 '"' => write!(f, r#"\""#)?,
                 '\\' => write!(f, r"\\")?,
-                _ => write!(f, "{}", ch)?,
+                ch if ch.is_ascii_control() => write!(f, "\\{:03o}", ch as u32)?,
+                ch if ch.is_ascii() => write!(f, "{}", ch)?,
+                ch => {
+                    for byte in ch.encode_utf8(&mut [0; 4]).bytes() {
+                        write!(f, "\\{:03o}", byte)?;
+                    }
+                }
             }
         }
         write!(f, "\"")
@@ -162,6 +1125,7 @@ fn maybe_escape_quote(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
 
 /// The file path and any additional info of either the old file or the new file
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct File<'a> {
     /// The parsed path or file name of the file
     ///
@@ -179,6 +1143,39 @@ pub struct File<'a> {
     pub meta: Option<FileMetadata<'a>>,
 }
 
+impl File<'_> {
+    /// The time the file was last modified, if the metadata after its path is a timestamp
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::Patch;
+    ///
+    /// let sample = "\
+    /// --- a.txt\t2024-01-01 12:00:00.000000000 +0100
+    /// +++ b.txt\t(working copy)
+    /// @@ -1 +1 @@
+    /// -old
+    /// +new
+    /// ";
+    /// let patch = Patch::from_single(sample).unwrap();
+    /// let timestamp = patch.old.timestamp().unwrap();
+    /// assert_eq!(timestamp.to_rfc3339(), "2024-01-01T12:00:00+01:00");
+    /// assert_eq!(patch.new.timestamp(), None);
+    /// ```
+    pub fn timestamp(&self) -> Option<DateTime<FixedOffset>> {
+        self.meta.as_ref().and_then(FileMetadata::timestamp)
+    }
+
+    /// Convert into a file that owns all of its data
+    pub fn into_owned(self) -> File<'static> {
+        File {
+            path: owned(self.path),
+            meta: self.meta.map(FileMetadata::into_owned),
+        }
+    }
+}
+
 impl fmt::Display for File<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         maybe_escape_quote(f, &self.path)?;
@@ -191,18 +1188,43 @@ impl fmt::Display for File<'_> {
 
 /// Additional metadata provided with the file path
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FileMetadata<'a> {
     /// A complete datetime, e.g. `2002-02-21 23:30:39.942229878 -0800`
+    ///
+    /// Fractional seconds are written with nine digits, the way GNU diff writes them, and left
+    /// out if they are zero.
     DateTime(DateTime<FixedOffset>),
     /// Any other string provided after the file path, e.g. git hash, unrecognized timestamp, etc.
     Other(Cow<'a, str>),
 }
 
+impl FileMetadata<'_> {
+    /// The timestamp, if the metadata is one
+    pub fn timestamp(&self) -> Option<DateTime<FixedOffset>> {
+        match self {
+            FileMetadata::DateTime(datetime) => Some(*datetime),
+            FileMetadata::Other(_) => None,
+        }
+    }
+
+    /// Convert into metadata that owns all of its data
+    pub fn into_owned(self) -> FileMetadata<'static> {
+        match self {
+            FileMetadata::DateTime(datetime) => FileMetadata::DateTime(datetime),
+            FileMetadata::Other(data) => FileMetadata::Other(owned(data)),
+        }
+    }
+}
+
 impl fmt::Display for FileMetadata<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            FileMetadata::DateTime(datetime) if datetime.nanosecond() == 0 => {
+                write!(f, "{}", datetime.format("%F %T %z"))
+            }
             FileMetadata::DateTime(datetime) => {
-                write!(f, "{}", datetime.format("%F %T%.f %z"))
+                write!(f, "{}", datetime.format("%F %T%.9f %z"))
             }
             FileMetadata::Other(data) => maybe_escape_quote(f, data),
         }
@@ -210,16 +1232,48 @@ impl fmt::Display for FileMetadata<'_> {
 }
 
 /// One area where the files differ
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub struct Hunk<'a> {
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "T: serde::Serialize",
+        deserialize = "T::Owned: serde::Deserialize<'de>"
+    ))
+)]
+pub struct Hunk<'a, T: ?Sized + ToOwned = str> {
     /// The range of lines in the old file that this hunk represents
     pub old_range: Range,
     /// The range of lines in the new file that this hunk represents
     pub new_range: Range,
     /// Any trailing text after the hunk's range information
-    pub range_hint: &'a str,
+    pub range_hint: Cow<'a, T>,
     /// Each line of text in the hunk, prefixed with the type of change it represents
-    pub lines: Vec<Line<'a>>,
+    pub lines: Vec<Line<'a, T>>,
+}
+
+// Implemented by hand to only require `T: Debug`, like the `Clone` implementation below only
+// requires `T: ToOwned`
+impl<T: ?Sized + ToOwned + fmt::Debug> fmt::Debug for Hunk<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Hunk")
+            .field("old_range", &self.old_range)
+            .field("new_range", &self.new_range)
+            .field("range_hint", &&*self.range_hint)
+            .field("lines", &self.lines)
+            .finish()
+    }
+}
+
+impl<T: ?Sized + ToOwned> Clone for Hunk<'_, T> {
+    fn clone(&self) -> Self {
+        Hunk {
+            old_range: self.old_range.clone(),
+            new_range: self.new_range.clone(),
+            range_hint: self.range_hint.clone(),
+            lines: self.lines.clone(),
+        }
+    }
 }
 
 impl Hunk<'_> {
@@ -230,24 +1284,454 @@ impl Hunk<'_> {
     }
 }
 
-impl fmt::Display for Hunk<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl<'a, T: ?Sized + ToOwned> Hunk<'a, T> {
+    /// Produce the inverse of this hunk, which undoes its changes.
+    ///
+    /// Removed lines that are directly followed by added lines stay in front of them, so a
+    /// reversed hunk reads the same way a diff program would have written it.
+    pub fn reverse(&self) -> Hunk<'a, T> {
+        let mut lines = Vec::with_capacity(self.lines.len());
+        let mut added = Vec::new();
+        for line in &self.lines {
+            match line {
+                Line::Add(text) => lines.push(Line::Remove(text.clone())),
+                Line::Remove(text) => added.push(Line::Add(text.clone())),
+                Line::Context(text) => {
+                    lines.append(&mut added);
+                    lines.push(Line::Context(text.clone()));
+                }
+            }
+        }
+        lines.append(&mut added);
+
+        Hunk {
+            old_range: self.new_range.clone(),
+            new_range: self.old_range.clone(),
+            range_hint: self.range_hint.clone(),
+            lines,
+        }
+    }
+
+    /// Set the counts of the ranges to the number of lines the hunk has of each file, like
+    /// `git apply --recount`.
+    ///
+    /// This fixes the headers of hunks that were edited by hand. A range that becomes empty
+    /// starts at the line before it, and one that stops being empty at the line after it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use patch::{Hunk, Line, Range};
+    /// let mut hunk = Hunk {
+    ///     old_range: Range { start: 3, count: 2 },
+    ///     new_range: Range { start: 3, count: 2 },
+    ///     range_hint: "".into(),
+    ///     lines: vec![Line::Context("a".into()), Line::Add("b".into())],
+    /// };
+    /// hunk.recount();
+    /// assert_eq!(hunk.old_range, Range { start: 3, count: 1 });
+    /// assert_eq!(hunk.new_range, Range { start: 3, count: 2 });
+    /// ```
+    pub fn recount(&mut self) {
+        let (old_count, new_count) = self.line_counts();
+        recount_range(&mut self.old_range, old_count);
+        recount_range(&mut self.new_range, new_count);
+    }
+
+    /// Count the lines the hunk adds, removes and keeps as context
+    ///
+    /// Unlike the ranges of its header, these are the lines the hunk really has.
+    pub fn stats(&self) -> LineStats {
+        let mut stats = LineStats::default();
+        for line in &self.lines {
+            match line {
+                Line::Add(_) => stats.additions += 1,
+                Line::Remove(_) => stats.deletions += 1,
+                Line::Context(_) => stats.context += 1,
+            }
+        }
+        stats
+    }
+
+    /// Replace every line of the hunk with the one `f` returns for it, leaving it out if `f`
+    /// returns `None`, and recount the ranges like [`Hunk::recount`].
+    ///
+    /// The hunks of a patch that come after this one may need their new ranges moved, which
+    /// [`Patch::map_lines`] and [`Patch::renumber`] do.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use patch::{Hunk, Line, Range};
+    /// let mut hunk = Hunk {
+    ///     old_range: Range { start: 1, count: 2 },
+    ///     new_range: Range { start: 1, count: 2 },
+    ///     range_hint: "".into(),
+    ///     lines: vec![
+    ///         Line::Remove("a".into()),
+    ///         Line::Remove("b".into()),
+    ///         Line::Add("A".into()),
+    ///         Line::Add("B".into()),
+    ///     ],
+    /// };
+    /// // Keep `b`, which the hunk removed
+    /// hunk.map_lines(|line| match line {
+    ///     Line::Remove(text) if text == "b" => Some(Line::Context(text)),
+    ///     Line::Add(text) if text == "B" => None,
+    ///     line => Some(line),
+    /// });
+    /// assert_eq!(hunk.to_string(), "@@ -1,2 +1,2 @@\n-a\n b\n+A");
+    /// ```
+    pub fn map_lines(&mut self, f: impl FnMut(Line<'a, T>) -> Option<Line<'a, T>>) {
+        self.lines = core::mem::take(&mut self.lines)
+            .into_iter()
+            .filter_map(f)
+            .collect();
+        self.recount();
+    }
+
+    // The number of lines of the old and the new file in the hunk
+    pub(crate) fn line_counts(&self) -> (u64, u64) {
+        count_lines(&self.lines)
+    }
+
+    /// Split the hunk into one hunk per cluster of changes, so each of them can be applied on
+    /// its own, like the `s` command of `git add -p`.
+    ///
+    /// The context of the hunk is its leading run of context lines. Changes separated by at
+    /// least twice that many context lines go to separate hunks, which keep that many context
+    /// lines around their changes and get ranges of their own. A hunk without such a gap is
+    /// returned whole, and so is a hunk that starts at the first line of the file, as the start
+    /// of the file may have cut its context short.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), patch::ParseError<'static>> {
+    /// # use patch::{Patch, Range};
+    /// let sample = "\
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -4,6 +4,6 @@
+    ///  a
+    /// -b
+    /// +B
+    ///  c
+    ///  d
+    /// -e
+    /// +E
+    ///  f
+    /// ";
+    ///
+    /// let patch = Patch::from_single(sample)?;
+    /// let hunks = patch.hunks[0].split();
+    /// assert_eq!(hunks.len(), 2);
+    /// assert_eq!(hunks[1].old_range, Range { start: 7, count: 3 });
+    /// assert_eq!(hunks[1].to_string(), "@@ -7,3 +7,3 @@\n d\n-e\n+E\n f");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn split(&self) -> Vec<Hunk<'a, T>> {
+        let is_change = |line: &Line<T>| !matches!(line, Line::Context(_));
+        let (Some(first), Some(last)) = (
+            self.lines.iter().position(is_change),
+            self.lines.iter().rposition(is_change),
+        ) else {
+            return vec![self.clone()];
+        };
+        // Only the leading context is as long as the context of the diff, unless the start of
+        // the file cut it short; the trailing one may always have been cut short by its end
+        if self.old_range.first_line() <= 1 {
+            return vec![self.clone()];
+        }
+        let context = first;
+
+        let mut hunks = Vec::new();
+        let (mut start, mut previous) = (0, first);
+        for i in (first + 1..=last).filter(|&i| is_change(&self.lines[i])) {
+            let gap = i - previous - 1;
+            if gap > 0 && gap >= 2 * context {
+                hunks.push(self.sub_hunk(start..previous + context + 1));
+                start = i - context;
+            }
+            previous = i;
+        }
+        hunks.push(self.sub_hunk(start..self.lines.len()));
+        hunks
+    }
+
+    // The hunk made of some of the lines of this one, with the ranges of those lines
+    pub(crate) fn sub_hunk(&self, lines: core::ops::Range<usize>) -> Hunk<'a, T> {
+        // The lines of each file that precede a range
+        let before = |range: &Range| {
+            if range.count == 0 {
+                range.start
+            } else {
+                range.start.saturating_sub(1)
+            }
+        };
+        let (old_skipped, new_skipped) = count_lines(&self.lines[..lines.start]);
+        let lines = self.lines[lines].to_vec();
+        let (old_count, new_count) = count_lines(&lines);
+        Hunk {
+            old_range: hunk_range(before(&self.old_range) + old_skipped, old_count),
+            new_range: hunk_range(before(&self.new_range) + new_skipped, new_count),
+            range_hint: self.range_hint.clone(),
+            lines,
+        }
+    }
+
+    /// Convert into a hunk that owns all of its data
+    pub fn into_owned(self) -> Hunk<'static, T>
+    where
+        T: 'static,
+    {
+        Hunk {
+            old_range: self.old_range,
+            new_range: self.new_range,
+            range_hint: owned(self.range_hint),
+            lines: self.lines.into_iter().map(Line::into_owned).collect(),
+        }
+    }
+}
+
+// The number of lines of the old and the new file among some lines of a hunk
+fn count_lines<T: ?Sized + ToOwned>(lines: &[Line<'_, T>]) -> (u64, u64) {
+    let old = lines.iter().filter(|line| !matches!(line, Line::Add(_)));
+    let new = lines.iter().filter(|line| !matches!(line, Line::Remove(_)));
+    (old.count() as u64, new.count() as u64)
+}
+
+// Give a range another count, keeping the convention that an empty range starts at the line
+// before where it would be
+fn recount_range(range: &mut Range, count: u64) {
+    match (range.count, count) {
+        (0, 1..) => range.start += 1,
+        (1.., 0) => range.start = range.start.saturating_sub(1),
+        _ => {}
+    }
+    range.count = count;
+}
+
+// The line of the old file `hunk` starts at, if that is before the end of `previous`, so the
+// hunks share lines
+pub(crate) fn overlap<T: ?Sized + ToOwned>(previous: &Hunk<T>, hunk: &Hunk<T>) -> Option<u64> {
+    let first = hunk.old_range.first_line();
+    (previous.old_range.first_line() <= first && first < previous.old_range.end_line())
+        .then_some(first)
+}
+
+// The hunk with the lines of two overlapping hunks, or `None` if they disagree about the lines
+// they share
+fn merge_hunks<'a, T: ?Sized + ToOwned + PartialEq>(
+    first: &Hunk<'a, T>,
+    second: &Hunk<'a, T>,
+) -> Option<Hunk<'a, T>> {
+    let offset = (second.old_range.first_line() - first.old_range.first_line()) as usize;
+    let (first_slots, second_slots) = (slots(first), slots(second));
+    let mut lines = Vec::with_capacity(first.lines.len() + second.lines.len());
+    for i in 0..first_slots.len().max(offset + second_slots.len()) {
+        let empty = (Vec::new(), None);
+        let (first_added, first_old) = first_slots.get(i).unwrap_or(&empty);
+        let (second_added, second_old) = i
+            .checked_sub(offset)
+            .and_then(|j| second_slots.get(j))
+            .unwrap_or(&empty);
+        let added = match (first_added.as_slice(), second_added.as_slice()) {
+            (added, []) | ([], added) => added,
+            (added, other) if added == other => added,
+            _ => return None,
+        };
+        let old = match (first_old, second_old) {
+            (Some(line), Some(other)) => {
+                let (Line::Add(text) | Line::Remove(text) | Line::Context(text)) = line;
+                let (Line::Add(other_text) | Line::Remove(other_text) | Line::Context(other_text)) =
+                    other;
+                if text != other_text {
+                    return None;
+                }
+                // A line one hunk removes is removed, even if the other keeps it as context
+                match line {
+                    Line::Remove(_) => Some(line),
+                    _ => Some(other),
+                }
+            }
+            (Some(line), None) | (None, Some(line)) => Some(line),
+            (None, None) => None,
+        };
+        lines.extend(added.iter().map(|line| (*line).clone()));
+        lines.extend(old.map(|line| (*line).clone()));
+    }
+    let mut hunk = Hunk {
+        old_range: first.old_range.clone(),
+        new_range: first.new_range.clone(),
+        range_hint: first.range_hint.clone(),
+        lines,
+    };
+    let (old_count, new_count) = hunk.line_counts();
+    hunk.old_range = hunk_range(first.old_range.first_line() - 1, old_count);
+    hunk.new_range = hunk_range(first.new_range.first_line() - 1, new_count);
+    Some(hunk)
+}
+
+// The lines of a hunk at every line of the old file, from the one it starts at to the one after
+// its end: the lines it adds before that line, and the line itself, which the last one lacks
+type Slot<'h, 'a, T> = (Vec<&'h Line<'a, T>>, Option<&'h Line<'a, T>>);
+
+fn slots<'h, 'a, T: ?Sized + ToOwned>(hunk: &'h Hunk<'a, T>) -> Vec<Slot<'h, 'a, T>> {
+    let mut slots = vec![(Vec::new(), None)];
+    for line in &hunk.lines {
+        let slot = slots.last_mut().unwrap();
+        match line {
+            Line::Add(_) => slot.0.push(line),
+            Line::Context(_) | Line::Remove(_) => {
+                slot.1 = Some(line);
+                slots.push((Vec::new(), None));
+            }
+        }
+    }
+    slots
+}
+
+impl Hunk<'_> {
+    // Write the hunk with `\ No newline at end of file` after the last line of each side that
+    // doesn't end in a newline character
+    fn fmt_with_markers(
+        &self,
+        f: &mut fmt::Formatter,
+        old_end_newline: bool,
+        new_end_newline: bool,
+        max_width: Option<usize>,
+    ) -> fmt::Result {
         write!(
             f,
             "@@ -{} +{} @@{}",
             self.old_range, self.new_range, self.range_hint
         )?;
 
-        for line in &self.lines {
+        let last_old = self
+            .lines
+            .iter()
+            .rposition(|line| !matches!(line, Line::Add(_)));
+        let last_new = self
+            .lines
+            .iter()
+            .rposition(|line| !matches!(line, Line::Remove(_)));
+        for (i, line) in self.lines.iter().enumerate() {
             write!(f, "\n{}", line)?;
+            let (Line::Add(text) | Line::Remove(text) | Line::Context(text)) = line;
+            if let Some(max) = max_width.filter(|&max| text.chars().count() > max) {
+                write!(f, "\n\\ Line longer than {} characters", max)?;
+            }
+            if (!old_end_newline && last_old == Some(i))
+                || (!new_end_newline && last_new == Some(i))
+            {
+                write!(f, "\n\\ No newline at end of file")?;
+            }
         }
 
         Ok(())
     }
 }
 
+impl fmt::Display for Hunk<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with_markers(f, true, true, None)
+    }
+}
+
+/// The `GIT binary patch` section of a patch
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BinaryPatch<'a> {
+    /// The hunk producing the new file from the old one
+    pub forward: BinaryHunk<'a>,
+    /// The hunk producing the old file from the new one, which git always includes
+    pub reverse: Option<BinaryHunk<'a>>,
+}
+
+impl<'a> BinaryPatch<'a> {
+    /// Produce the inverse of this binary patch, which undoes its changes.
+    ///
+    /// The hunks trade places. Without a reverse hunk, the patch stays the same.
+    pub fn reverse(&self) -> BinaryPatch<'a> {
+        match &self.reverse {
+            Some(reverse) => BinaryPatch {
+                forward: reverse.clone(),
+                reverse: Some(self.forward.clone()),
+            },
+            None => self.clone(),
+        }
+    }
+
+    /// Convert into a binary patch that owns all of its data
+    pub fn into_owned(self) -> BinaryPatch<'static> {
+        BinaryPatch {
+            forward: self.forward.into_owned(),
+            reverse: self.reverse.map(BinaryHunk::into_owned),
+        }
+    }
+}
+
+impl fmt::Display for BinaryPatch<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GIT binary patch\n{}", self.forward)?;
+        if let Some(reverse) = &self.reverse {
+            write!(f, "\n\n{}", reverse)?;
+        }
+        Ok(())
+    }
+}
+
+/// How the data of a [`BinaryHunk`] is used
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BinaryKind {
+    /// The data is the complete content of the file, line prefix: `literal`
+    Literal,
+    /// The data is a delta against the other side of the patch, line prefix: `delta`
+    Delta,
+}
+
+/// One side of a binary patch
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BinaryHunk<'a> {
+    /// Whether the data replaces the file or is a delta against it
+    pub kind: BinaryKind,
+    /// The size of the data once it is decoded
+    pub size: u64,
+    /// The lines of compressed, base85 encoded data, each starting with its length character
+    pub data: Vec<Cow<'a, str>>,
+}
+
+impl BinaryHunk<'_> {
+    /// Convert into a hunk that owns all of its data
+    pub fn into_owned(self) -> BinaryHunk<'static> {
+        BinaryHunk {
+            kind: self.kind,
+            size: self.size,
+            data: self.data.into_iter().map(owned).collect(),
+        }
+    }
+}
+
+impl fmt::Display for BinaryHunk<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            BinaryKind::Literal => write!(f, "literal {}", self.size)?,
+            BinaryKind::Delta => write!(f, "delta {}", self.size)?,
+        }
+        for line in &self.data {
+            write!(f, "\n{}", line)?;
+        }
+        Ok(())
+    }
+}
+
 /// A range of lines in a given file
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Range {
     /// The start line of the chunk in the old or new file
     pub start: u64,
@@ -255,21 +1739,117 @@ pub struct Range {
     pub count: u64,
 }
 
+impl Range {
+    // The first line of the file the range covers, or the line it inserts before if it is empty
+    pub(crate) fn first_line(&self) -> u64 {
+        match self.count {
+            0 => self.start + 1,
+            _ => self.start,
+        }
+    }
+
+    // The line after the last one the range covers
+    pub(crate) fn end_line(&self) -> u64 {
+        self.first_line() + self.count
+    }
+}
+
 impl fmt::Display for Range {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{},{}", self.start, self.count)
     }
 }
 
+/// The numbers of lines of a hunk or patch, see [`Patch::stats`]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineStats {
+    /// The number of lines added
+    pub additions: usize,
+    /// The number of lines removed
+    pub deletions: usize,
+    /// The number of context lines
+    pub context: usize,
+}
+
+impl LineStats {
+    /// How many lines longer the new file is than the old one, which is negative if it is
+    /// shorter
+    pub fn net(&self) -> i64 {
+        self.additions as i64 - self.deletions as i64
+    }
+}
+
+impl core::ops::AddAssign for LineStats {
+    fn add_assign(&mut self, other: LineStats) {
+        self.additions += other.additions;
+        self.deletions += other.deletions;
+        self.context += other.context;
+    }
+}
+
+impl core::iter::Sum for LineStats {
+    fn sum<I: Iterator<Item = LineStats>>(iter: I) -> LineStats {
+        let mut total = LineStats::default();
+        iter.for_each(|stats| total += stats);
+        total
+    }
+}
+
 /// A line of the old file, new file, or both
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub enum Line<'a> {
+///
+/// The text of the line borrows from the parsed patch, except for patches that were
+/// deserialized.
+#[derive(Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "T: serde::Serialize",
+        deserialize = "T::Owned: serde::Deserialize<'de>"
+    ))
+)]
+pub enum Line<'a, T: ?Sized + ToOwned = str> {
     /// A line added to the old file in the new file
-    Add(&'a str),
+    Add(Cow<'a, T>),
     /// A line removed from the old file in the new file
-    Remove(&'a str),
+    Remove(Cow<'a, T>),
     /// A line provided for context in the diff (unchanged); from both the old and the new file
-    Context(&'a str),
+    Context(Cow<'a, T>),
+}
+
+impl<T: ?Sized + ToOwned + fmt::Debug> fmt::Debug for Line<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Add(text) => f.debug_tuple("Add").field(&&**text).finish(),
+            Line::Remove(text) => f.debug_tuple("Remove").field(&&**text).finish(),
+            Line::Context(text) => f.debug_tuple("Context").field(&&**text).finish(),
+        }
+    }
+}
+
+impl<T: ?Sized + ToOwned> Line<'_, T> {
+    /// Convert into a line that owns its text
+    pub fn into_owned(self) -> Line<'static, T>
+    where
+        T: 'static,
+    {
+        match self {
+            Line::Add(text) => Line::Add(owned(text)),
+            Line::Remove(text) => Line::Remove(owned(text)),
+            Line::Context(text) => Line::Context(owned(text)),
+        }
+    }
+}
+
+impl<T: ?Sized + ToOwned> Clone for Line<'_, T> {
+    fn clone(&self) -> Self {
+        match self {
+            Line::Add(text) => Line::Add(text.clone()),
+            Line::Remove(text) => Line::Remove(text.clone()),
+            Line::Context(text) => Line::Context(text.clone()),
+        }
+    }
 }
 
 impl fmt::Display for Line<'_> {
@@ -292,10 +1872,10 @@ mod tests {
         let mut h = Hunk {
             old_range: Range { start: 0, count: 0 },
             new_range: Range { start: 0, count: 0 },
-            range_hint: "",
+            range_hint: "".into(),
             lines: vec![],
         };
-        for (input, expected) in vec![
+        for (input, expected) in [
             ("", None),
             (" ", None),
             ("  ", None),
@@ -305,8 +1885,297 @@ mod tests {
             (" x ", Some("x ")),
             ("  abc def ", Some("abc def ")),
         ] {
-            h.range_hint = input;
+            h.range_hint = input.into();
             assert_eq!(h.hint(), expected);
         }
     }
+
+    #[test]
+    fn test_recount() {
+        let mut hunk = Hunk::<str> {
+            old_range: Range { start: 4, count: 1 },
+            new_range: Range { start: 3, count: 0 },
+            range_hint: "".into(),
+            lines: vec![Line::Add("a".into()), Line::Add("b".into())],
+        };
+        hunk.recount();
+        assert_eq!(hunk.old_range, Range { start: 3, count: 0 });
+        assert_eq!(hunk.new_range, Range { start: 4, count: 2 });
+
+        // Recounting a hunk with the right counts changes nothing
+        let mut recounted = hunk.clone();
+        recounted.recount();
+        assert_eq!(recounted, hunk);
+    }
+
+    #[test]
+    fn test_split() {
+        let patch = Patch::from_single(
+            "\
+--- a.txt
++++ a.txt
+@@ -3,11 +3,11 @@
+ c
+-d
++D
+ e
+ f
+-g
+ h
++i
+ j
+ k
+ l
+-m
++M
+ n
+",
+        )
+        .unwrap();
+        let hunks = patch.hunks[0].split();
+        let headers: Vec<_> = hunks
+            .iter()
+            .map(|hunk| hunk.to_string().lines().next().unwrap().to_string())
+            .collect();
+        // The single line between the removal of `g` and the addition of `i` isn't a gap
+        assert_eq!(
+            headers,
+            ["@@ -3,3 +3,3 @@", "@@ -6,4 +6,4 @@", "@@ -11,3 +11,3 @@"]
+        );
+        assert_eq!(hunks[1].lines[0], Line::Context("f".into()));
+
+        // Without context, every run of changes is a hunk of its own
+        let hunk = Hunk::<str> {
+            old_range: Range { start: 5, count: 3 },
+            new_range: Range { start: 5, count: 3 },
+            range_hint: "".into(),
+            lines: vec![
+                Line::Remove("a".into()),
+                Line::Add("A".into()),
+                Line::Context("b".into()),
+                Line::Add("x".into()),
+                Line::Remove("c".into()),
+            ],
+        };
+        let ranges: Vec<_> = hunk
+            .split()
+            .into_iter()
+            .map(|hunk| (hunk.old_range, hunk.new_range))
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                (Range { start: 5, count: 1 }, Range { start: 5, count: 1 }),
+                (Range { start: 7, count: 1 }, Range { start: 7, count: 1 }),
+            ]
+        );
+        assert_eq!(hunk.split()[1].lines.len(), 2);
+
+        // Changes to the first and the last line of a file leave no context to measure
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new = "one\n2\n3\n4\n5\n6\n7\n8\n9\nten\n";
+        let patch = crate::generator::diff(old, new, 10);
+        assert_eq!(patch.hunks[0].split(), [patch.hunks[0].clone()]);
+    }
+
+    #[test]
+    fn test_edit_hunks() {
+        let sample = "\
+--- a.txt
++++ a.txt
+@@ -1,2 +1,3 @@
+ a
++x
+ b
+@@ -4,2 +5 @@
+-d
+ e
+@@ -8 +8 @@
+-h
++H
+\\ No newline at end of file
+";
+        let content = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let patch = Patch::from_single(sample).unwrap();
+
+        let mut dropped = patch.clone();
+        let hunk = dropped.drop_hunk(0);
+        assert_eq!(hunk, patch.hunks[0]);
+        assert_eq!(dropped.hunks[0].new_range, Range { start: 4, count: 1 });
+        assert_eq!(dropped.hunks[1].new_range, Range { start: 7, count: 1 });
+        assert_eq!(
+            crate::apply(&dropped, content).unwrap(),
+            "a\nb\nc\ne\nf\ng\nH"
+        );
+
+        let mut retained = patch.clone();
+        retained.retain_hunks(|hunk| hunk.old_range.start < 8);
+        assert_eq!(retained.hunks.len(), 2);
+        assert!(retained.new_end_newline);
+        assert_eq!(
+            crate::apply(&retained, content).unwrap(),
+            "a\nx\nb\nc\ne\nf\ng\nh\n"
+        );
+        // Leaving out another hunk keeps the missing newline of the last one
+        let mut retained = patch.clone();
+        retained.retain_hunks(|hunk| hunk.old_range.start != 4);
+        assert!(!retained.new_end_newline);
+
+        // Keeping the removed line moves the last hunk
+        let mut mapped = patch.clone();
+        mapped.map_lines(|line| match line {
+            Line::Remove(text) if text == "d" => Some(Line::Context(text)),
+            line => Some(line),
+        });
+        assert_eq!(mapped.hunks[1].new_range, Range { start: 5, count: 2 });
+        assert_eq!(mapped.hunks[2].new_range, Range { start: 9, count: 1 });
+        assert_eq!(
+            crate::apply(&mapped, content).unwrap(),
+            "a\nx\nb\nc\nd\ne\nf\ng\nH"
+        );
+    }
+
+    #[test]
+    fn test_merge_overlapping_hunks() {
+        // The second hunk repeats the change of the first one, the third is inside the second
+        // and the last only touches it
+        let sample = "\
+--- a.txt
++++ a.txt
+@@ -1,3 +1,4 @@
+ a
+-b
++B
++B2
+ c
+@@ -2,4 +3,4 @@
+-b
++B
++B2
+ c
+ d
+-e
+@@ -4 +4,2 @@
+ d
++D
+@@ -6 +6 @@
+-f
++F
+";
+        let content = "a\nb\nc\nd\ne\nf\n";
+        let mut patch = Patch::from_single(sample).unwrap();
+        assert!(matches!(
+            crate::apply(&patch, content),
+            Err(ApplyError::OverlappingHunks { hunk: 1, line: 2 })
+        ));
+        patch.merge_overlapping_hunks().unwrap();
+        assert_eq!(
+            patch.to_string(),
+            "\
+--- a.txt
++++ a.txt
+@@ -1,5 +1,6 @@
+ a
+-b
++B
++B2
+ c
+ d
++D
+-e
+@@ -6,1 +7,1 @@
+-f
++F"
+        );
+        assert_eq!(
+            crate::apply(&patch, content).unwrap(),
+            "a\nB\nB2\nc\nd\nD\nF\n"
+        );
+
+        // Hunks that disagree about a line of the old file, or add different lines in the same
+        // place, can't be merged
+        for conflicting in [
+            sample.replace(" c\n d\n", " C\n d\n"),
+            sample.replace("+B2\n c\n d", "+B3\n c\n d"),
+        ] {
+            let mut patch = Patch::from_single(&conflicting).unwrap();
+            let unchanged = patch.clone();
+            assert!(matches!(
+                patch.merge_overlapping_hunks(),
+                Err(ApplyError::OverlappingHunks { .. })
+            ));
+            assert_eq!(patch, unchanged);
+        }
+    }
+
+    #[test]
+    fn test_into_owned() {
+        let text = String::from(
+            "diff --git a/old.txt b/new.txt\nsimilarity index 50%\nrename from old.txt\n\
+             rename to new.txt\n--- a/old.txt\t2002-02-21 23:30:39 -0800\n+++ b/new.txt\tnew\n\
+             @@ -1,2 +1,2 @@ hint\n x\n-y\n+z\n",
+        );
+        let patch = Patch::from_single(&text).unwrap();
+        let owned = patch.clone().into_owned();
+        assert_eq!(owned, patch);
+        assert!(matches!(
+            owned.hunks[0].lines[1],
+            Line::Remove(Cow::Owned(_))
+        ));
+
+        // The owned patch outlives the text and can be sent to another thread
+        drop(patch);
+        drop(text);
+        let printed = std::thread::spawn(move || owned.to_string())
+            .join()
+            .unwrap();
+        assert!(printed.ends_with("@@ -1,2 +1,2 @@ hint\n x\n-y\n+z"));
+    }
+
+    #[test]
+    fn test_quoted_paths() {
+        for (path, quoted) in [
+            ("plain/path.rs", "plain/path.rs"),
+            ("with space", "\"with space\""),
+            ("é\t\x7f", "\"\\303\\251\\t\\177\""),
+            ("\0 \u{0}1", "\"\\0 \\0001\""),
+        ] {
+            let file = File {
+                path: path.into(),
+                meta: None,
+            };
+            assert_eq!(file.to_string(), quoted);
+            let text = format!("--- {}\n+++ b\n@@ -1 +1 @@\n-x\n+y\n", quoted);
+            assert_eq!(Patch::from_single(&text).unwrap().old, file);
+        }
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let text = "--- a\t2024-01-01 12:00:00.500000000 +0100\n\
+                    +++ b\t2024-01-01 12:00:00.000000000 +0100\n@@ -1 +1 @@\n-x\n+y\n";
+        let patch = Patch::from_single(text).unwrap();
+        let (old, new) = (
+            patch.old.timestamp().unwrap(),
+            patch.new.timestamp().unwrap(),
+        );
+        assert_eq!(old - new, chrono::TimeDelta::milliseconds(500));
+        assert_eq!(old.offset().local_minus_utc(), 3600);
+
+        // Fractional seconds are written like GNU diff writes them, unless they are zero
+        assert_eq!(
+            patch.old.to_string(),
+            "a\t2024-01-01 12:00:00.500000000 +0100"
+        );
+        assert_eq!(patch.new.to_string(), "b\t2024-01-01 12:00:00 +0100");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_impls() {
+        fn assert_serde<T: serde::Serialize + serde::de::DeserializeOwned>() {}
+        // Deserialized patches own their data instead of borrowing it from the input
+        assert_serde::<Patch<'static>>();
+        assert_serde::<Patch<'static, [u8]>>();
+    }
 }
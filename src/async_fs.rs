@@ -0,0 +1,824 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::fs;
+use tokio::sync::{Semaphore, mpsc};
+
+use crate::applier::{ApplyOptions, apply_partial, apply_with_options, check_index, reject_patch};
+use crate::ast::{FileOp, Patch};
+use crate::fs::{
+    DirReport, FsError, beneath_link, created_links, is_symlink_patch, mode_change, temp_path,
+    with_suffix,
+};
+use crate::patch_set::{
+    FileChange, PatchSet, PatchSetError, current, file_changes, is_dev_null, record, strip_path,
+    target_path,
+};
+use crate::vfs::{DiskFs, SYMLINK_MODE, Vfs};
+
+/// The results of [`apply_to_dir_async`], one for every file, in the order the files are done
+///
+/// This is a [`Stream`], and [`DirApplyStream::next`] gives the next result without needing any
+/// stream utilities. Dropping it stops patching the files that haven't been started yet.
+#[derive(Debug)]
+pub struct DirApplyStream {
+    receiver: mpsc::Receiver<Result<DirReport, FsError>>,
+}
+
+impl DirApplyStream {
+    /// Wait for the next file to be done, returning `None` once all of them are
+    pub async fn next(&mut self) -> Option<Result<DirReport, FsError>> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for DirApplyStream {
+    type Item = Result<DirReport, FsError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+// The patches of a file, or of the old and new files of renames and copies, with the file every
+// patch applies to
+struct Target {
+    files: Vec<TargetFile>,
+    patches: Vec<(usize, Patch<'static>, PathBuf)>,
+}
+
+// What is known about a file before patching it
+struct TargetFile {
+    path: PathBuf,
+    existed: bool,
+    backup: Option<PathBuf>,
+}
+
+/// Apply a set of patches to the files in a directory with `tokio::fs`, patching up to
+/// `max_concurrent` files at a time
+///
+/// Patches are matched up with files like [`apply_to_dir`](crate::apply_to_dir) does it, and the
+/// same [`ApplyOptions`] are supported, but every file is patched on its own instead of all of
+/// them or none: the stream has the [`DirReport`] of every file that was patched, with only that
+/// file in it, and the error of every file that couldn't be. The old and new files of renames
+/// and copies are patched together and share a report. Files and symbolic links are written the
+/// same way, so readers never observe a partially written file, and files beneath a link are
+/// refused the same way.
+///
+/// `max_concurrent` is at least 1. The work is done by tasks spawned on the current tokio
+/// runtime, which keep going when the stream isn't polled, up to `max_concurrent` results ahead.
+///
+/// # Panics
+///
+/// Panics if called outside of a tokio runtime.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use patch::{ApplyOptions, PatchSet, apply_to_dir_async};
+///
+/// # async fn run() {
+/// let diff = tokio::fs::read_to_string("changes.diff").await.unwrap();
+/// let patches = PatchSet::parse(&diff).unwrap();
+/// let mut results = apply_to_dir_async(&patches, Path::new("."), &ApplyOptions::default(), 8);
+/// while let Some(result) = results.next().await {
+///     match result {
+///         Ok(report) => println!("patched {:?}", report.modified),
+///         Err(error) => eprintln!("{}", error),
+///     }
+/// }
+/// # }
+/// ```
+pub fn apply_to_dir_async(
+    patches: &PatchSet,
+    root: &Path,
+    options: &ApplyOptions,
+    max_concurrent: usize,
+) -> DirApplyStream {
+    let max_concurrent = max_concurrent.max(1);
+    let (sender, receiver) = mpsc::channel(max_concurrent);
+    let patches: Vec<Patch<'static>> = patches
+        .patches
+        .iter()
+        .map(|patch| patch.clone().into_owned())
+        .collect();
+    let root = root.to_path_buf();
+    let options = Arc::new(options.clone());
+
+    tokio::spawn(async move {
+        let targets = match plan(patches, &root, &options, &sender).await {
+            Some(targets) => targets,
+            None => return,
+        };
+        let root = Arc::new(root);
+        let permits = Arc::new(Semaphore::new(max_concurrent));
+        for target in targets {
+            let Ok(permit) = permits.clone().acquire_owned().await else {
+                return;
+            };
+            if sender.is_closed() {
+                return;
+            }
+            let (root, options, sender) = (root.clone(), options.clone(), sender.clone());
+            tokio::spawn(async move {
+                let result = patch_file(&root, target, &options).await;
+                let _ = sender.send(result).await;
+                drop(permit);
+            });
+        }
+    });
+
+    DirApplyStream { receiver }
+}
+
+// Find the file every patch applies to, sending the errors of the patches that don't have one.
+// Returns `None` if the stream was dropped.
+async fn plan(
+    patches: Vec<Patch<'static>>,
+    root: &Path,
+    options: &ApplyOptions,
+    sender: &mpsc::Sender<Result<DirReport, FsError>>,
+) -> Option<Vec<Target>> {
+    // Whether every file looked at exists on disk, and after the patches so far
+    let mut on_disk: HashMap<PathBuf, bool> = HashMap::new();
+    let mut exists: HashMap<PathBuf, bool> = HashMap::new();
+    let mut targets: Vec<Target> = Vec::new();
+    let mut target_of: HashMap<PathBuf, usize> = HashMap::new();
+    let created_links = created_links(&patches, options.strip);
+    // The directories known not to be links
+    let mut checked = HashSet::new();
+
+    for (position, patch) in patches.into_iter().enumerate() {
+        let mut result = Ok(());
+        for file in [&patch.old, &patch.new] {
+            if is_dev_null(file) {
+                continue;
+            }
+            let Ok(path) = strip_path(&file.path, options.strip) else {
+                continue;
+            };
+            if let Some(link) = beneath_link(&path, &created_links) {
+                result = Err(FsError::Symlink {
+                    path: root.join(link),
+                });
+                break;
+            }
+            if let Err(error) = check_parents(root, &path, &mut checked).await {
+                result = Err(error);
+                break;
+            }
+            if on_disk.contains_key(&path) {
+                continue;
+            }
+            // Links are there even if their target isn't
+            let full_path = root.join(&path);
+            match fs::symlink_metadata(&full_path).await {
+                Ok(_) => {
+                    on_disk.insert(path, true);
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    on_disk.insert(path, false);
+                }
+                Err(error) => {
+                    result = Err(FsError::Io {
+                        path: full_path,
+                        error,
+                    });
+                }
+            }
+        }
+        let op = patch.file_op();
+        let paths = result.and_then(|()| {
+            let path = target_path(&patch, options.strip, |path| {
+                exists
+                    .get(path)
+                    .or_else(|| on_disk.get(path))
+                    .copied()
+                    .unwrap_or(false)
+            })?;
+            match op {
+                FileOp::Rename { .. } | FileOp::Copy { .. } => {
+                    Ok((strip_path(&patch.new.path, options.strip)?, path))
+                }
+                _ => Ok((path.clone(), path)),
+            }
+        });
+        let (to, path) = match paths {
+            Ok(paths) => paths,
+            Err(error) => {
+                sender.send(Err(error)).await.ok()?;
+                continue;
+            }
+        };
+        if op == FileOp::Delete {
+            exists.insert(path.clone(), false);
+        } else {
+            if matches!(op, FileOp::Rename { .. }) {
+                exists.insert(path.clone(), false);
+            }
+            exists.insert(to.clone(), true);
+        }
+
+        // A rename or a copy joins the targets of its old and new file
+        let index = match (target_of.get(&path).copied(), target_of.get(&to).copied()) {
+            (Some(index), Some(other)) if index != other => {
+                let moved = std::mem::replace(
+                    &mut targets[other],
+                    Target {
+                        files: Vec::new(),
+                        patches: Vec::new(),
+                    },
+                );
+                targets[index].files.extend(moved.files);
+                targets[index].patches.extend(moved.patches);
+                targets[index]
+                    .patches
+                    .sort_by_key(|(position, _, _)| *position);
+                for target in target_of.values_mut().filter(|target| **target == other) {
+                    *target = index;
+                }
+                index
+            }
+            (Some(index), _) | (None, Some(index)) => index,
+            (None, None) => {
+                targets.push(Target {
+                    files: Vec::new(),
+                    patches: Vec::new(),
+                });
+                targets.len() - 1
+            }
+        };
+        for file in [&path, &to] {
+            if target_of.insert(file.clone(), index).is_none() {
+                targets[index].files.push(TargetFile {
+                    path: file.clone(),
+                    existed: on_disk.get(file).copied().unwrap_or(false),
+                    backup: None,
+                });
+            }
+        }
+        targets[index].patches.push((position, patch, path));
+    }
+    targets.retain(|target| !target.patches.is_empty());
+
+    // Backups are named before any file is written, so they don't replace each other or the files
+    // the patches write
+    if let Some(suffix) = &options.backup_suffix {
+        let mut taken: HashSet<PathBuf> = targets
+            .iter()
+            .flat_map(|target| &target.files)
+            .flat_map(|file| [file.path.clone(), with_suffix(&file.path, ".rej")])
+            .collect();
+        let files = targets.iter_mut().flat_map(|target| &mut target.files);
+        for file in files.filter(|file| file.existed) {
+            let backup = backup_path(root, &file.path, suffix, &taken).await;
+            taken.insert(backup.clone());
+            file.backup = Some(backup);
+        }
+    }
+    Some(targets)
+}
+
+// Refuse a path beneath a symbolic link that exists in `root`, like `apply_to_dir` does
+async fn check_parents(
+    root: &Path,
+    path: &Path,
+    checked: &mut HashSet<PathBuf>,
+) -> Result<(), FsError> {
+    for parent in path.ancestors().skip(1) {
+        if parent.as_os_str().is_empty() || checked.contains(parent) {
+            break;
+        }
+        let full_path = root.join(parent);
+        match fs::symlink_metadata(&full_path).await {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(FsError::Symlink { path: full_path });
+            }
+            Ok(_) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => {
+                return Err(FsError::Io {
+                    path: full_path,
+                    error,
+                });
+            }
+        }
+        checked.insert(parent.to_path_buf());
+    }
+    Ok(())
+}
+
+// The first path for the backup of `path` that isn't taken and doesn't exist in `root`, like
+// `apply_to_dir` picks it
+async fn backup_path(root: &Path, path: &Path, suffix: &str, taken: &HashSet<PathBuf>) -> PathBuf {
+    let backup = with_suffix(path, suffix);
+    let mut candidate = backup.clone();
+    for n in 1.. {
+        if !taken.contains(&candidate) && fs::symlink_metadata(root.join(&candidate)).await.is_err()
+        {
+            break;
+        }
+        candidate = with_suffix(&backup, &format!(".{}", n));
+    }
+    candidate
+}
+
+// Apply the patches of a file, or of the files of renames and copies, and write the results
+async fn patch_file(
+    root: &Path,
+    target: Target,
+    options: &ApplyOptions,
+) -> Result<DirReport, FsError> {
+    let Target { files, patches } = target;
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |error| FsError::Io { path, error }
+    };
+
+    // Read every file, with the target of symbolic links as their content
+    let mut originals: HashMap<PathBuf, String> = HashMap::new();
+    let mut links = HashSet::new();
+    for file in &files {
+        let full_path = root.join(&file.path);
+        let link = fs::symlink_metadata(&full_path)
+            .await
+            .is_ok_and(|metadata| metadata.file_type().is_symlink());
+        let symlink_patch = patches.iter().any(|(_, patch, _)| is_symlink_patch(patch));
+        if options.forbid_symlinks && (link || symlink_patch) {
+            return Err(FsError::Symlink { path: full_path });
+        }
+        if !file.existed {
+            continue;
+        }
+        let content = match link {
+            true => {
+                let target = fs::read_link(&full_path)
+                    .await
+                    .map_err(io_error(&full_path))?;
+                links.insert(file.path.clone());
+                target.into_os_string().into_string().map_err(|_| {
+                    let error =
+                        io::Error::new(io::ErrorKind::InvalidData, "link target is not UTF-8");
+                    io_error(&full_path)(error)
+                })?
+            }
+            false => fs::read_to_string(&full_path)
+                .await
+                .map_err(io_error(&full_path))?,
+        };
+        originals.insert(file.path.clone(), content);
+    }
+
+    // The content of every file touched so far, `None` once it has been deleted
+    let mut touched: HashMap<PathBuf, Option<String>> = HashMap::new();
+    let mut rejects: Vec<(PathBuf, String)> = Vec::new();
+    for (_, patch, path) in &patches {
+        let apply_error = |error| {
+            FsError::Patch(PatchSetError::Apply {
+                path: path.clone(),
+                error,
+            })
+        };
+        let text = current(&touched, &originals, path);
+        let patched = match options.reject_files && !patch.is_binary() {
+            true => {
+                check_index(patch, text.as_bytes(), options).map_err(apply_error)?;
+                let (patched, rejected) = apply_partial(patch, text, options);
+                if !rejected.is_empty() {
+                    let text = format!("{}\n", reject_patch(patch, &rejected));
+                    match rejects.iter_mut().find(|(other, _)| other == path) {
+                        Some((_, content)) => content.push_str(&text),
+                        None => rejects.push((path.clone(), text)),
+                    }
+                }
+                patched
+            }
+            false => apply_with_options(patch, text, options).map_err(apply_error)?,
+        };
+        record(
+            &mut touched,
+            &originals,
+            patch,
+            path.clone(),
+            patched,
+            options.strip,
+        )?;
+    }
+
+    // The modes the patches give their files, the last patch of a file winning
+    let mut modes = HashMap::new();
+    for (_, patch, _) in &patches {
+        if let Some(mode) = mode_change(patch) {
+            modes.insert(strip_path(&patch.new.path, options.strip)?, mode);
+        }
+    }
+
+    let mut changes: Vec<(PathBuf, FileChange)> = file_changes(&originals, touched).collect();
+    changes.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut report = DirReport::default();
+    for (path, change) in changes {
+        let full_path = root.join(&path);
+        let backup = files
+            .iter()
+            .find(|file| file.path == path)
+            .and_then(|file| file.backup.clone());
+        if let (Some(backup), Some(original)) = (backup, originals.get(&path)) {
+            let full_backup = root.join(&backup);
+            write_atomic(&full_backup, original)
+                .await
+                .map_err(io_error(&full_backup))?;
+            report.backups.push(backup);
+        }
+        // Links stay links unless a patch gives them another mode
+        let mode = modes.get(&path).copied();
+        let link = mode.map_or(links.contains(&path), |mode| mode == SYMLINK_MODE);
+        match &change {
+            FileChange::Created(target) | FileChange::Modified(target) if link => {
+                let (root, link_path, target) = (root.to_path_buf(), path.clone(), target.clone());
+                tokio::task::spawn_blocking(move || DiskFs::new(root).symlink(&link_path, &target))
+                    .await
+                    .unwrap_or_else(|error| Err(io::Error::other(error)))
+                    .map_err(io_error(&full_path))?;
+            }
+            FileChange::Created(content) | FileChange::Modified(content) => {
+                if let Some(parent) = full_path.parent() {
+                    fs::create_dir_all(parent)
+                        .await
+                        .map_err(io_error(&full_path))?;
+                }
+                write_atomic(&full_path, content)
+                    .await
+                    .map_err(io_error(&full_path))?;
+            }
+            FileChange::Deleted => {
+                fs::remove_file(&full_path)
+                    .await
+                    .map_err(io_error(&full_path))?;
+            }
+        }
+
+        if let (Some(mode), false) = (mode, link || change == FileChange::Deleted) {
+            let mode = mode & 0o777;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+
+                fs::set_permissions(&full_path, std::fs::Permissions::from_mode(mode))
+                    .await
+                    .map_err(io_error(&full_path))?;
+            }
+            report.modes.push((path.clone(), mode));
+        }
+
+        match change {
+            FileChange::Created(_) => report.created.push(path),
+            FileChange::Modified(_) => report.modified.push(path),
+            FileChange::Deleted => report.deleted.push(path),
+        }
+    }
+
+    for (path, content) in rejects {
+        let reject = with_suffix(&path, ".rej");
+        let full_reject = root.join(&reject);
+        write_atomic(&full_reject, &content)
+            .await
+            .map_err(io_error(&full_reject))?;
+        report.rejects.push(reject);
+    }
+    Ok(report)
+}
+
+// Replace the file at `path` without ever leaving it partially written, like `write_atomic` in
+// the `fs` module
+async fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let temp_path = temp_path(path);
+    let result = async {
+        fs::write(&temp_path, content).await?;
+        match fs::metadata(path).await {
+            Ok(metadata) => fs::set_permissions(&temp_path, metadata.permissions()).await?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        fs::rename(&temp_path, path).await
+    }
+    .await;
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    // Gather the results, with the reports in the order of their files
+    async fn collect(mut results: DirApplyStream) -> (Vec<DirReport>, Vec<FsError>) {
+        let (mut reports, mut errors) = (Vec::new(), Vec::new());
+        while let Some(result) = results.next().await {
+            match result {
+                Ok(report) => reports.push(report),
+                Err(error) => errors.push(error),
+            }
+        }
+        reports.sort_by_key(|report| {
+            [&report.created[..], &report.modified, &report.deleted].concat()
+        });
+        (reports, errors)
+    }
+
+    #[tokio::test]
+    async fn test_apply_to_dir_async() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a\n").unwrap();
+        std::fs::write(dir.path().join("gone.txt"), "bye\n").unwrap();
+        std::fs::write(dir.path().join("c.txt"), "c\n").unwrap();
+
+        let patches = PatchSet::parse(
+            "\
+--- a.txt
++++ a.txt
+@@ -1 +1 @@
+-a
++A
+--- /dev/null
++++ docs/new.md
+@@ -0,0 +1 @@
++# Docs
+--- gone.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+--- c.txt
++++ c.txt
+@@ -1 +1 @@
+-x
++X
+--- missing.txt
++++ missing.txt
+@@ -1 +1 @@
+-m
++M
+",
+        )
+        .unwrap();
+        let results = apply_to_dir_async(&patches, dir.path(), &ApplyOptions::default(), 2);
+        let (reports, errors) = collect(results).await;
+        assert_eq!(
+            reports,
+            [
+                DirReport {
+                    modified: vec![PathBuf::from("a.txt")],
+                    ..DirReport::default()
+                },
+                DirReport {
+                    created: vec![PathBuf::from("docs/new.md")],
+                    ..DirReport::default()
+                },
+                DirReport {
+                    deleted: vec![PathBuf::from("gone.txt")],
+                    ..DirReport::default()
+                },
+            ]
+        );
+
+        // Every file is patched on its own, so the failures don't stop the others
+        let mut errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        errors.sort();
+        assert_eq!(errors.len(), 2);
+        assert!(
+            errors[0].starts_with("Failed to patch 'c.txt'"),
+            "{}",
+            errors[0]
+        );
+        assert!(errors[1].contains("missing.txt"), "{}", errors[1]);
+
+        let read = |path: &str| std::fs::read_to_string(dir.path().join(path)).unwrap();
+        assert_eq!(read("a.txt"), "A\n");
+        assert_eq!(read("docs/new.md"), "# Docs\n");
+        assert_eq!(read("c.txt"), "c\n");
+        assert!(!dir.path().join("gone.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_apply_to_dir_async_rejects_and_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "A\nB\n").unwrap();
+
+        let patches = PatchSet::parse(
+            "\
+--- a.txt
++++ a.txt
+@@ -1 +1 @@
+-A
++A2
+--- a.txt
++++ a.txt
+@@ -2 +2 @@
+-Q
++X
+",
+        )
+        .unwrap();
+        let options = ApplyOptions {
+            reject_files: true,
+            backup_suffix: Some(".orig".into()),
+            ..ApplyOptions::default()
+        };
+        let (reports, errors) =
+            collect(apply_to_dir_async(&patches, dir.path(), &options, 1)).await;
+        assert!(errors.is_empty());
+        assert_eq!(
+            reports,
+            [DirReport {
+                modified: vec![PathBuf::from("a.txt")],
+                rejects: vec![PathBuf::from("a.txt.rej")],
+                backups: vec![PathBuf::from("a.txt.orig")],
+                ..DirReport::default()
+            }]
+        );
+
+        let read = |path: &str| std::fs::read_to_string(dir.path().join(path)).unwrap();
+        assert_eq!(read("a.txt"), "A2\nB\n");
+        assert_eq!(read("a.txt.orig"), "A\nB\n");
+        assert!(read("a.txt.rej").contains("-Q\n+X\n"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_apply_to_dir_async_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink("v1.txt", dir.path().join("current")).unwrap();
+        std::os::unix::fs::symlink("v0.txt", dir.path().join("old")).unwrap();
+
+        let patches = PatchSet::parse(
+            "\
+diff --git a/latest b/latest
+new file mode 120000
+--- /dev/null
++++ b/latest
+@@ -0,0 +1 @@
++v2.txt
+\\ No newline at end of file
+diff --git a/current b/current
+--- a/current
++++ b/current
+@@ -1 +1 @@
+-v1.txt
+\\ No newline at end of file
++v2.txt
+\\ No newline at end of file
+diff --git a/old b/old
+deleted file mode 120000
+--- a/old
++++ /dev/null
+@@ -1 +0,0 @@
+-v0.txt
+\\ No newline at end of file
+",
+        )
+        .unwrap();
+        let options = ApplyOptions {
+            strip: 1,
+            forbid_symlinks: true,
+            ..ApplyOptions::default()
+        };
+        let (reports, errors) =
+            collect(apply_to_dir_async(&patches, dir.path(), &options, 2)).await;
+        assert!(reports.is_empty());
+        assert_eq!(errors.len(), 3);
+        assert!(
+            errors
+                .iter()
+                .all(|error| matches!(error, FsError::Symlink { .. }))
+        );
+
+        // Links are created, retargeted and removed like `apply_to_dir` does it
+        let options = ApplyOptions {
+            forbid_symlinks: false,
+            ..options
+        };
+        let (reports, errors) =
+            collect(apply_to_dir_async(&patches, dir.path(), &options, 2)).await;
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(reports.len(), 3);
+        for path in ["latest", "current"] {
+            let target = std::fs::read_link(dir.path().join(path)).unwrap();
+            assert_eq!(target, Path::new("v2.txt"));
+        }
+        assert!(std::fs::symlink_metadata(dir.path().join("old")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_apply_to_dir_async_renames() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("x.txt"), "x\n").unwrap();
+
+        let patches = PatchSet::parse(
+            "\
+diff --git a/x.txt b/y.txt
+similarity index 90%
+rename from x.txt
+rename to y.txt
+--- a/x.txt
++++ b/y.txt
+@@ -1 +1 @@
+-x
++Y
+diff --git a/y.txt b/y.txt
+--- a/y.txt
++++ b/y.txt
+@@ -1 +1 @@
+-Y
++Z
+",
+        )
+        .unwrap();
+        let options = ApplyOptions {
+            strip: 1,
+            ..ApplyOptions::default()
+        };
+        let (reports, errors) =
+            collect(apply_to_dir_async(&patches, dir.path(), &options, 2)).await;
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            reports,
+            [DirReport {
+                created: vec![PathBuf::from("y.txt")],
+                deleted: vec![PathBuf::from("x.txt")],
+                ..DirReport::default()
+            }]
+        );
+        assert!(!dir.path().join("x.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("y.txt")).unwrap(),
+            "Z\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_apply_to_dir_async_beneath_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (root, outside) = (dir.path().join("root"), dir.path().join("outside"));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+
+        // Files beneath a link the patches create and beneath one that exists
+        let diff = format!(
+            "\
+diff --git a/evil b/evil
+new file mode 120000
+--- /dev/null
++++ b/evil
+@@ -0,0 +1 @@
++{}
+\\ No newline at end of file
+diff --git a/evil/pwned b/evil/pwned
+new file mode 100644
+--- /dev/null
++++ b/evil/pwned
+@@ -0,0 +1 @@
++pwned
+--- /dev/null
++++ b/link/file
+@@ -0,0 +1 @@
++pwned
+",
+            outside.display()
+        );
+        let patches = PatchSet::parse(&diff).unwrap();
+        for forbid_symlinks in [true, false] {
+            let options = ApplyOptions {
+                strip: 1,
+                forbid_symlinks,
+                ..ApplyOptions::default()
+            };
+            let (_, errors) = collect(apply_to_dir_async(&patches, &root, &options, 2)).await;
+            let mut paths: Vec<&Path> = errors
+                .iter()
+                .filter_map(|error| match error {
+                    FsError::Symlink { path } => Some(path.as_path()),
+                    _ => None,
+                })
+                .collect();
+            paths.sort();
+            let expected = match forbid_symlinks {
+                true => vec![root.join("evil"), root.join("evil"), root.join("link")],
+                false => vec![root.join("evil"), root.join("link")],
+            };
+            assert_eq!(paths, expected, "{:?}", errors);
+        }
+        assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,377 @@
+//! A command-line interface to the `patch` crate, for applying, checking, producing and
+//! inspecting patches
+//!
+//! Like GNU patch and diff, it exits with status 0 on success, 1 when patches don't apply or
+//! files differ, and 2 on any other trouble.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Arg, ArgAction, ArgMatches, Command, value_parser};
+use patch::{
+    ApplyOptions, DiffAlgorithm, DiffOptions, DiffStat, DirDiffOptions, FsError, PatchSet,
+    apply_to_dir, diff_dirs, diff_with_options, strip_path,
+};
+
+/// What went wrong, with the exit status it leads to
+enum Failure {
+    /// The patches don't apply, or the files differ
+    Mismatch(String),
+    /// Anything else, like files that can't be read or patches that can't be parsed
+    Trouble(String),
+}
+
+fn main() -> ExitCode {
+    let matches = cli().get_matches();
+    let result = match matches.subcommand() {
+        Some(("apply", args)) => apply(args),
+        Some(("check", args)) => check(args),
+        Some(("diff", args)) => diff(args),
+        Some(("stat", args)) => stat(args),
+        Some(("filter", args)) => filter(args),
+        _ => unreachable!("clap requires a subcommand"),
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        // Differences found by `diff`, which aren't an error
+        Ok(false) => ExitCode::from(1),
+        Err(Failure::Mismatch(message)) => {
+            eprintln!("patch-rs: {}", message);
+            ExitCode::from(1)
+        }
+        Err(Failure::Trouble(message)) => {
+            eprintln!("patch-rs: {}", message);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn cli() -> Command {
+    let patch_file = || {
+        Arg::new("patch")
+            .value_name("PATCH")
+            .help("The patch file, or - for the standard input, which is the default")
+    };
+    let apply_args = [
+        Arg::new("strip")
+            .short('p')
+            .long("strip")
+            .value_name("NUM")
+            .value_parser(value_parser!(usize))
+            .default_value("0")
+            .help("Remove NUM leading components from the paths in the patch"),
+        Arg::new("reverse")
+            .short('R')
+            .long("reverse")
+            .action(ArgAction::SetTrue)
+            .help("Undo the patch instead of applying it"),
+        Arg::new("directory")
+            .short('d')
+            .long("directory")
+            .value_name("DIR")
+            .default_value(".")
+            .help("Apply the patch to the files in DIR"),
+        Arg::new("fuzz")
+            .short('F')
+            .long("fuzz")
+            .value_name("NUM")
+            .value_parser(value_parser!(u8))
+            .default_value("0")
+            .help("Ignore up to NUM context lines at the start and end of hunks that don't match"),
+        patch_file(),
+    ];
+
+    Command::new("patch-rs")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Apply, check, produce and inspect patches in the unified format")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("apply")
+                .about("Apply a patch to the files in a directory, like patch(1)")
+                .args(apply_args.clone())
+                .arg(
+                    Arg::new("reject")
+                        .long("reject")
+                        .action(ArgAction::SetTrue)
+                        .help("Write hunks that don't apply to .rej files and apply the rest"),
+                )
+                .arg(
+                    Arg::new("backup")
+                        .short('b')
+                        .long("backup")
+                        .action(ArgAction::SetTrue)
+                        .help("Keep the original of every changed file, with the suffix .orig"),
+                ),
+        )
+        .subcommand(
+            Command::new("check")
+                .about(
+                    "Check that a patch applies to the files in a directory, without changing them",
+                )
+                .args(apply_args),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Compare two files or directories, like diff -u or diff -ruN")
+                .arg(Arg::new("old").value_name("OLD").required(true))
+                .arg(Arg::new("new").value_name("NEW").required(true))
+                .arg(
+                    Arg::new("unified")
+                        .short('U')
+                        .long("unified")
+                        .value_name("NUM")
+                        .value_parser(value_parser!(usize))
+                        .default_value("3")
+                        .help("Show NUM lines of context around every change"),
+                )
+                .arg(
+                    Arg::new("algorithm")
+                        .long("algorithm")
+                        .value_parser(["myers", "patience", "histogram"])
+                        .default_value("myers")
+                        .help("The diff algorithm to use"),
+                )
+                .arg(
+                    Arg::new("find-renames")
+                        .short('M')
+                        .long("find-renames")
+                        .value_name("PERCENT")
+                        .value_parser(value_parser!(u8).range(0..=100))
+                        .num_args(0..=1)
+                        .default_missing_value("50")
+                        .help(
+                            "Detect renamed files that are at least PERCENT similar, 50 by default",
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("stat")
+                .about("Show how many lines a patch changes in every file, like diffstat")
+                .arg(
+                    Arg::new("width")
+                        .short('w')
+                        .long("width")
+                        .value_name("COLUMNS")
+                        .value_parser(value_parser!(usize))
+                        .default_value("80")
+                        .help("Fit the histogram in COLUMNS columns"),
+                )
+                .arg(patch_file()),
+        )
+        .subcommand(
+            Command::new("filter")
+                .about("Keep only the patches of some files, like filterdiff")
+                .arg(
+                    Arg::new("include")
+                        .short('i')
+                        .long("include")
+                        .value_name("GLOB")
+                        .action(ArgAction::Append)
+                        .help("Keep the patches of files matching GLOB, which can be repeated"),
+                )
+                .arg(
+                    Arg::new("exclude")
+                        .short('x')
+                        .long("exclude")
+                        .value_name("GLOB")
+                        .action(ArgAction::Append)
+                        .help(
+                            "Leave out the patches of files matching GLOB, which can be repeated",
+                        ),
+                )
+                .arg(patch_file()),
+        )
+}
+
+// Read the patch file, or the standard input for `-` or no file
+fn read_patch(args: &ArgMatches) -> Result<String, Failure> {
+    match args.get_one::<String>("patch").map(String::as_str) {
+        Some("-") | None => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|error| Failure::Trouble(format!("standard input: {}", error)))?;
+            Ok(text)
+        }
+        Some(path) => fs::read_to_string(path)
+            .map_err(|error| Failure::Trouble(format!("{}: {}", path, error))),
+    }
+}
+
+fn parse(text: &str) -> Result<PatchSet<'_>, Failure> {
+    PatchSet::parse(text).map_err(|error| Failure::Trouble(error.to_string()))
+}
+
+// Parse the patch and the options shared by `apply` and `check`
+fn patches_and_options(
+    text: &str,
+    args: &ArgMatches,
+) -> Result<(PatchSet<'static>, ApplyOptions), Failure> {
+    let reverse = args.get_flag("reverse");
+    let patches = parse(text)?
+        .into_iter()
+        .map(|patch| match reverse {
+            true => patch.reverse().into_owned(),
+            false => patch.into_owned(),
+        })
+        .collect();
+    let options = ApplyOptions {
+        strip: *args.get_one("strip").unwrap(),
+        fuzz: *args.get_one("fuzz").unwrap(),
+        ..ApplyOptions::default()
+    };
+    let patches = PatchSet { patches };
+    Ok((patches, options))
+}
+
+fn apply(args: &ArgMatches) -> Result<bool, Failure> {
+    let text = read_patch(args)?;
+    let (patches, mut options) = patches_and_options(&text, args)?;
+    options.reject_files = args.get_flag("reject");
+    if args.get_flag("backup") {
+        options.backup_suffix = Some(".orig".to_string());
+    }
+    let root = Path::new(args.get_one::<String>("directory").unwrap());
+    let report = apply_to_dir(&patches, root, &options).map_err(|error| match error {
+        FsError::Patch(error) => Failure::Mismatch(error.to_string()),
+        error => Failure::Trouble(error.to_string()),
+    })?;
+
+    let mut changed: Vec<&PathBuf> = [&report.created, &report.modified, &report.deleted]
+        .into_iter()
+        .flatten()
+        .collect();
+    changed.sort();
+    for path in changed {
+        println!("patching file {}", path.display());
+    }
+    for path in &report.rejects {
+        println!("saving rejects to file {}", path.display());
+    }
+    match report.rejects.is_empty() {
+        true => Ok(true),
+        false => Err(Failure::Mismatch("some hunks failed".to_string())),
+    }
+}
+
+fn check(args: &ArgMatches) -> Result<bool, Failure> {
+    let text = read_patch(args)?;
+    let (patches, options) = patches_and_options(&text, args)?;
+    let root = Path::new(args.get_one::<String>("directory").unwrap());
+
+    // Read every file the patches could refer to, like `apply_to_dir` does
+    let mut files = HashMap::new();
+    for patch in &patches {
+        for file in [&patch.old, &patch.new] {
+            if file.path == "/dev/null" {
+                continue;
+            }
+            let path = strip_path(&file.path, options.strip)
+                .map_err(|error| Failure::Trouble(error.to_string()))?;
+            let full_path = root.join(&path);
+            match fs::read_to_string(&full_path) {
+                Ok(content) => {
+                    files.insert(path, content);
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => {
+                    return Err(Failure::Trouble(format!(
+                        "{}: {}",
+                        full_path.display(),
+                        error
+                    )));
+                }
+            }
+        }
+    }
+    patches
+        .apply_to_with_options(&files, &options)
+        .map_err(|error| Failure::Mismatch(error.to_string()))?;
+    Ok(true)
+}
+
+fn diff(args: &ArgMatches) -> Result<bool, Failure> {
+    let old = args.get_one::<String>("old").unwrap();
+    let new = args.get_one::<String>("new").unwrap();
+    let options = DiffOptions {
+        context: *args.get_one("unified").unwrap(),
+        algorithm: match args.get_one::<String>("algorithm").unwrap().as_str() {
+            "patience" => DiffAlgorithm::Patience,
+            "histogram" => DiffAlgorithm::Histogram,
+            _ => DiffAlgorithm::Myers,
+        },
+        ..DiffOptions::default()
+    };
+
+    if Path::new(old).is_dir() && Path::new(new).is_dir() {
+        let options = DirDiffOptions {
+            diff: options,
+            renames: args.get_one("find-renames").copied(),
+            ..DirDiffOptions::default()
+        };
+        let patches = diff_dirs(Path::new(old), Path::new(new), &options)
+            .map_err(|error| Failure::Trouble(error.to_string()))?;
+        if patches.patches.is_empty() {
+            return Ok(true);
+        }
+        println!("{}", patches);
+        return Ok(false);
+    }
+
+    let read = |path: &String| {
+        fs::read_to_string(path).map_err(|error| Failure::Trouble(format!("{}: {}", path, error)))
+    };
+    let (old_text, new_text) = (read(old)?, read(new)?);
+    let mut patch = diff_with_options(&old_text, &new_text, &options);
+    if patch.hunks.is_empty() {
+        return Ok(true);
+    }
+    patch.old.path = old.as_str().into();
+    patch.new.path = new.as_str().into();
+    println!("{}", patch);
+    Ok(false)
+}
+
+fn stat(args: &ArgMatches) -> Result<bool, Failure> {
+    let text = read_patch(args)?;
+    let patches = parse(&text)?;
+    let width = *args.get_one("width").unwrap();
+    println!("{}", DiffStat::from_patch_set(&patches).render(width));
+    Ok(true)
+}
+
+fn filter(args: &ArgMatches) -> Result<bool, Failure> {
+    let text = read_patch(args)?;
+    let mut patches = parse(&text)?;
+    let globs = |name| {
+        args.get_many::<String>(name)
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+    };
+    let includes = globs("include");
+    if !includes.is_empty() {
+        // A patch is kept if any of the patterns matches its path
+        let kept = patches.patches.into_iter().filter(|patch| {
+            let single = PatchSet {
+                patches: vec![patch.clone()],
+            };
+            includes
+                .iter()
+                .any(|pattern| !single.include(pattern).patches.is_empty())
+        });
+        patches = PatchSet {
+            patches: kept.collect(),
+        };
+    }
+    for pattern in globs("exclude") {
+        patches = patches.exclude(pattern);
+    }
+    if !patches.patches.is_empty() {
+        println!("{}", patches);
+    }
+    Ok(true)
+}
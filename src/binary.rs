@@ -0,0 +1,411 @@
+use std::error::Error;
+use std::fmt;
+
+use miniz_oxide::inflate::{TINFLStatus, decompress_to_vec_zlib_with_limit};
+
+use crate::ast::{BinaryHunk, BinaryKind, Patch};
+
+// The largest content a binary hunk is decoded to, so patches can't make it use unbounded memory
+const MAX_SIZE: u64 = 1 << 30;
+
+// The characters git uses to encode binary patches, in the order of the values they stand for
+const BASE85_ALPHABET: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// Error that can occur while applying a binary patch
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BinaryError {
+    /// The patch has no `GIT binary patch` section, like patches that only say that a binary file
+    /// changed
+    NotBinary,
+    /// A line of base85 data is malformed
+    InvalidBase85 {
+        /// The index of the line within the data of the hunk
+        line: usize,
+    },
+    /// The base85 data is not valid zlib compressed data
+    Inflate,
+    /// The data doesn't have the size given in the `literal` or `delta` line
+    SizeMismatch {
+        /// The size given in the patch
+        expected: u64,
+        /// The size of the data
+        actual: u64,
+    },
+    /// A delta was made against content of a different size
+    SourceMismatch {
+        /// The size of the content the delta was made against
+        expected: u64,
+        /// The size of the content the delta was applied to
+        actual: u64,
+    },
+    /// The delta instructions are malformed or refer to data outside of the content
+    InvalidDelta,
+    /// The data is larger than the size given in the `literal` or `delta` line, or that size is
+    /// larger than the 1 GiB that is decoded at most
+    TooLarge {
+        /// The most bytes the data could have
+        limit: u64,
+    },
+}
+
+impl fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryError::NotBinary => write!(f, "Patch doesn't contain a binary patch"),
+            BinaryError::InvalidBase85 { line } => {
+                write!(f, "Invalid base85 data on line {} of binary hunk", line + 1)
+            }
+            BinaryError::Inflate => write!(f, "Binary hunk is not valid zlib data"),
+            BinaryError::SizeMismatch { expected, actual } => write!(
+                f,
+                "Binary hunk has {} bytes of data, expected {}",
+                actual, expected
+            ),
+            BinaryError::SourceMismatch { expected, actual } => write!(
+                f,
+                "Binary delta expects {} bytes of content, got {}",
+                expected, actual
+            ),
+            BinaryError::InvalidDelta => write!(f, "Binary delta is malformed"),
+            BinaryError::TooLarge { limit } => {
+                write!(f, "Binary hunk has more than {} bytes of data", limit)
+            }
+        }
+    }
+}
+
+impl Error for BinaryError {}
+
+/// Apply the `GIT binary patch` section of a patch to the given bytes
+///
+/// Literal hunks replace the content entirely, while delta hunks are applied to it the same way
+/// git applies the deltas in its pack files.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use patch::{Patch, apply_binary};
+///
+/// let patch_text = "\
+/// diff --git a/small.bin b/small.bin
+/// index df93f5f..ffdabad 100644
+/// GIT binary patch
+/// literal 15
+/// WcmZQ&W9Q)H;^yJy;};MV5(WSSmjJZ@
+///
+/// literal 10
+/// RcmZQzWMXDvWn<^y1ONc904@Lk
+/// ";
+///
+/// let patch = Patch::from_single(patch_text)?;
+/// let old: Vec<u8> = (0..10).collect();
+/// let new: Vec<u8> = (5..20).collect();
+/// assert_eq!(apply_binary(&patch, &old)?, new);
+/// # Ok(())
+/// # }
+/// ```
+pub fn apply_binary(patch: &Patch, content: &[u8]) -> Result<Vec<u8>, BinaryError> {
+    let binary = patch.binary.as_ref().ok_or(BinaryError::NotBinary)?;
+    apply_binary_hunk(&binary.forward, content)
+}
+
+/// Apply the `GIT binary patch` section of a patch in reverse, undoing its changes
+///
+/// git includes a hunk that restores the old content in every binary patch. Patches that don't
+/// have one can't be reversed and produce [`BinaryError::NotBinary`].
+pub fn apply_binary_reverse(patch: &Patch, content: &[u8]) -> Result<Vec<u8>, BinaryError> {
+    let hunk = patch
+        .binary
+        .as_ref()
+        .and_then(|binary| binary.reverse.as_ref())
+        .ok_or(BinaryError::NotBinary)?;
+    apply_binary_hunk(hunk, content)
+}
+
+fn apply_binary_hunk(hunk: &BinaryHunk, content: &[u8]) -> Result<Vec<u8>, BinaryError> {
+    let data = hunk.decode()?;
+    match hunk.kind {
+        BinaryKind::Literal => Ok(data),
+        BinaryKind::Delta => apply_delta(&data, content),
+    }
+}
+
+impl BinaryHunk<'_> {
+    /// Decode the base85 data of the hunk and inflate it
+    ///
+    /// For literal hunks, this is the new content. For delta hunks, these are the delta
+    /// instructions.
+    pub fn decode(&self) -> Result<Vec<u8>, BinaryError> {
+        if self.size > MAX_SIZE {
+            return Err(BinaryError::TooLarge { limit: MAX_SIZE });
+        }
+        let mut compressed = Vec::new();
+        for (index, line) in self.data.iter().enumerate() {
+            decode_base85_line(line, &mut compressed)
+                .ok_or(BinaryError::InvalidBase85 { line: index })?;
+        }
+        let data = decompress_to_vec_zlib_with_limit(&compressed, self.size as usize).map_err(
+            |error| match error.status {
+                TINFLStatus::HasMoreOutput => BinaryError::TooLarge { limit: self.size },
+                _ => BinaryError::Inflate,
+            },
+        )?;
+        if data.len() as u64 != self.size {
+            return Err(BinaryError::SizeMismatch {
+                expected: self.size,
+                actual: data.len() as u64,
+            });
+        }
+        Ok(data)
+    }
+}
+
+// Decode a line of base85 data, whose first character gives the number of bytes it encodes
+fn decode_base85_line(line: &str, output: &mut Vec<u8>) -> Option<()> {
+    let line = line.as_bytes();
+    let len = match *line.first()? {
+        ch @ b'A'..=b'Z' => (ch - b'A') as usize + 1,
+        ch @ b'a'..=b'z' => (ch - b'a') as usize + 27,
+        _ => return None,
+    };
+    let encoded = &line[1..];
+    if encoded.len() != len.div_ceil(4) * 5 {
+        return None;
+    }
+
+    let start = output.len();
+    for group in encoded.chunks(5) {
+        let mut value: u32 = 0;
+        for &ch in group {
+            let digit = BASE85_ALPHABET.iter().position(|&c| c == ch)? as u32;
+            value = value.checked_mul(85)?.checked_add(digit)?;
+        }
+        output.extend_from_slice(&value.to_be_bytes());
+    }
+    output.truncate(start + len);
+    Some(())
+}
+
+// Apply a delta in the format of git's pack files: the sizes of the source and the result,
+// followed by instructions that either copy a range of the source or insert new data
+fn apply_delta(delta: &[u8], source: &[u8]) -> Result<Vec<u8>, BinaryError> {
+    let mut delta = delta.iter().copied();
+    let source_size = delta_size(&mut delta).ok_or(BinaryError::InvalidDelta)?;
+    if source_size != source.len() as u64 {
+        return Err(BinaryError::SourceMismatch {
+            expected: source_size,
+            actual: source.len() as u64,
+        });
+    }
+    let target_size = delta_size(&mut delta).ok_or(BinaryError::InvalidDelta)?;
+    if target_size > MAX_SIZE {
+        return Err(BinaryError::TooLarge { limit: MAX_SIZE });
+    }
+    let too_large = BinaryError::TooLarge { limit: target_size };
+
+    let mut output = Vec::with_capacity(target_size.min(source_size.saturating_mul(2)) as usize);
+    while let Some(instruction) = delta.next() {
+        if instruction & 0x80 != 0 {
+            // Copy from the source, with the bits of the instruction telling which bytes of the
+            // offset and size follow
+            let mut offset: usize = 0;
+            for i in 0..4 {
+                if instruction & (1 << i) != 0 {
+                    offset |= (delta.next().ok_or(BinaryError::InvalidDelta)? as usize) << (8 * i);
+                }
+            }
+            let mut size: usize = 0;
+            for i in 0..3 {
+                if instruction & (1 << (4 + i)) != 0 {
+                    size |= (delta.next().ok_or(BinaryError::InvalidDelta)? as usize) << (8 * i);
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            let copied = offset
+                .checked_add(size)
+                .and_then(|end| source.get(offset..end))
+                .ok_or(BinaryError::InvalidDelta)?;
+            if (output.len() + copied.len()) as u64 > target_size {
+                return Err(too_large);
+            }
+            output.extend_from_slice(copied);
+        } else if instruction != 0 {
+            // Insert the following bytes
+            if (output.len() + instruction as usize) as u64 > target_size {
+                return Err(too_large);
+            }
+            for _ in 0..instruction {
+                output.push(delta.next().ok_or(BinaryError::InvalidDelta)?);
+            }
+        } else {
+            return Err(BinaryError::InvalidDelta);
+        }
+    }
+
+    if output.len() as u64 != target_size {
+        return Err(BinaryError::SizeMismatch {
+            expected: target_size,
+            actual: output.len() as u64,
+        });
+    }
+    Ok(output)
+}
+
+// A size in a delta header: little endian groups of 7 bits, with the high bit set on all but the
+// last byte
+fn delta_size(delta: &mut impl Iterator<Item = u8>) -> Option<u64> {
+    let mut size: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = delta.next()?;
+        size |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(size);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    // Generated by `git diff --binary` after replacing 4 bytes and appending 4 more
+    const DELTA_PATCH: &str = "\
+diff --git a/big.bin b/big.bin
+index 17b9c9ac90f32576adf666e462c83142810ab065..ba3f43b001c3fb7d8e8dca7a4b73b9a011de263b 100644
+GIT binary patch
+delta 30
+kcmdlXzDImQ3X7wYv&&>d?)J?Om~XHHiH}SyC5f3i0I?AZrvLx|
+
+delta 16
+YcmdlZzC(OM%H{x;4ED_rnBOo1069Yjk^lez
+
+";
+
+    fn delta_old() -> Vec<u8> {
+        (0..3000u32)
+            .map(|i| ((i * i * 31 + i * 7) % 251) as u8)
+            .collect()
+    }
+
+    fn delta_new() -> Vec<u8> {
+        let mut new = delta_old();
+        new[100..104].copy_from_slice(b"ABCD");
+        new.extend_from_slice(b"tail");
+        new
+    }
+
+    #[test]
+    fn test_apply_binary_delta() {
+        let patch = Patch::from_single(DELTA_PATCH).unwrap();
+        assert_eq!(apply_binary(&patch, &delta_old()).unwrap(), delta_new());
+        assert_eq!(
+            apply_binary_reverse(&patch, &delta_new()).unwrap(),
+            delta_old()
+        );
+    }
+
+    #[test]
+    fn test_binary_patch_is_not_applied_to_text() {
+        let patch = Patch::from_single(DELTA_PATCH).unwrap();
+        assert!(matches!(
+            crate::apply(&patch, "text\n"),
+            Err(crate::ApplyError::Binary)
+        ));
+        assert_eq!(
+            apply_binary(
+                &Patch::from_single("--- a\n+++ b\n@@ -1 +1 @@\n-a\n+b\n").unwrap(),
+                b""
+            ),
+            Err(BinaryError::NotBinary)
+        );
+
+        // Patches that only say that the file changed can't be applied at all
+        let patch = Patch::from_single("Binary files a/x.bin and b/x.bin differ\n").unwrap();
+        assert!(matches!(
+            crate::apply(&patch, "text\n"),
+            Err(crate::ApplyError::Binary)
+        ));
+        assert_eq!(apply_binary(&patch, b""), Err(BinaryError::NotBinary));
+    }
+
+    #[test]
+    fn test_apply_binary_delta_wrong_source() {
+        let patch = Patch::from_single(DELTA_PATCH).unwrap();
+        assert_eq!(
+            apply_binary(&patch, b"short"),
+            Err(BinaryError::SourceMismatch {
+                expected: 3000,
+                actual: 5
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_base85_line() {
+        let mut output = Vec::new();
+        // 4 zero bytes are encoded as 5 zeros, and the length character is 'D' for 4 bytes
+        decode_base85_line("D00000", &mut output).unwrap();
+        assert_eq!(output, [0, 0, 0, 0]);
+
+        // The last group is padded, and the padding is dropped
+        output.clear();
+        decode_base85_line("B|Nj60", &mut output).unwrap();
+        assert_eq!(output, [0xff, 0xff]);
+
+        assert!(decode_base85_line("D0000", &mut output).is_none());
+        assert!(decode_base85_line("1", &mut output).is_none());
+    }
+
+    #[test]
+    fn test_apply_delta_instructions() {
+        // Source size 6, target size 5, copy 3 bytes from offset 2, insert "xy"
+        let delta = [6, 5, 0x80 | 0x01 | 0x10, 2, 3, 2, b'x', b'y'];
+        assert_eq!(apply_delta(&delta, b"abcdef").unwrap(), b"cdexy");
+
+        // Copies can't reach beyond the source
+        let delta = [6, 3, 0x80 | 0x01 | 0x10, 5, 3];
+        assert_eq!(
+            apply_delta(&delta, b"abcdef"),
+            Err(BinaryError::InvalidDelta)
+        );
+
+        // The output can't grow beyond the target size, whether copied or inserted
+        let too_large = Err(BinaryError::TooLarge { limit: 2 });
+        assert_eq!(apply_delta(&[6, 2, 0x80 | 0x10, 3], b"abcdef"), too_large);
+        assert_eq!(
+            apply_delta(&[6, 2, 3, b'x', b'y', b'z'], b"abcdef"),
+            too_large
+        );
+
+        // Nor can the target size be unreasonably large
+        let delta = [6, 0x80, 0x80, 0x80, 0x80, 0x10, 1, b'x'];
+        assert_eq!(
+            apply_delta(&delta, b"abcdef"),
+            Err(BinaryError::TooLarge { limit: MAX_SIZE })
+        );
+    }
+
+    #[test]
+    fn test_decode_limits_size() {
+        let patch = Patch::from_single(DELTA_PATCH).unwrap();
+        let mut hunk = patch.binary.unwrap().forward;
+        assert_eq!(hunk.decode().unwrap().len(), 30);
+
+        // Data that inflates to more than its size is only inflated up to that size
+        hunk.size = 10;
+        assert_eq!(hunk.decode(), Err(BinaryError::TooLarge { limit: 10 }));
+
+        hunk.size = MAX_SIZE + 1;
+        assert_eq!(
+            hunk.decode(),
+            Err(BinaryError::TooLarge { limit: MAX_SIZE })
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Checking that content is the file a git patch was made for, by the ids of git's blobs
+
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
+use core::fmt::Write;
+
+use sha1::{Digest, Sha1};
+
+use crate::applier::ApplyError;
+use crate::ast::Patch;
+
+/// The id git gives to a file with the given content, in hexadecimal
+///
+/// This is the SHA-1 hash of the content after a `blob <size>` header and a NUL byte, like
+/// `git hash-object` computes it.
+///
+/// # Example
+///
+/// ```
+/// use patch::blob_id;
+///
+/// assert_eq!(blob_id(b""), "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+/// assert_eq!(blob_id(b"hello\n"), "ce013625030ba8dba906f756967f9e9ca394464a");
+/// ```
+pub fn blob_id(content: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", content.len()));
+    hasher.update(content);
+    let mut id = String::with_capacity(40);
+    for byte in hasher.finalize() {
+        write!(id, "{:02x}", byte).unwrap();
+    }
+    id
+}
+
+/// Check that the content is the file a git patch was made for, by comparing the id of its blob
+/// to the old id of the patch's `index` line, which may be abbreviated
+///
+/// This catches patches applied to the wrong version of a file before they are applied with
+/// offsets or fuzz. Patches without an `index` line, and patches creating files, whose old id is
+/// all zeros, aren't checked. To check the content whenever a patch is applied, set
+/// [`ApplyOptions::verify_index`](crate::ApplyOptions::verify_index).
+///
+/// # Example
+///
+/// ```
+/// use patch::{ApplyError, Patch, verify_index};
+///
+/// let sample = "\
+/// diff --git a/hello.txt b/hello.txt
+/// index ce01362..a042389 100644
+/// --- a/hello.txt
+/// +++ b/hello.txt
+/// @@ -1 +1 @@
+/// -hello
+/// +hello, world
+/// ";
+/// let patch = Patch::from_single(sample).unwrap();
+/// assert!(verify_index(&patch, b"hello\n").is_ok());
+/// assert!(matches!(
+///     verify_index(&patch, b"hello\r\n"),
+///     Err(ApplyError::IndexMismatch { .. })
+/// ));
+/// ```
+pub fn verify_index<T: ?Sized + ToOwned>(
+    patch: &Patch<'_, T>,
+    content: &[u8],
+) -> Result<(), ApplyError> {
+    let Some(index) = patch.git.as_ref().and_then(|git| git.index.as_ref()) else {
+        return Ok(());
+    };
+    let expected = index.old_id.to_ascii_lowercase();
+    if expected.bytes().all(|digit| digit == b'0') {
+        return Ok(());
+    }
+    let actual = blob_id(content);
+    match actual.starts_with(&expected) {
+        true => Ok(()),
+        false => Err(ApplyError::IndexMismatch { expected, actual }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::applier::{ApplyOptions, apply_with_options};
+
+    const SAMPLE: &str = "\
+diff --git a/hello.txt b/hello.txt
+index ce013625030ba8dba906f756967f9e9ca394464a..a042389 100644
+--- a/hello.txt
++++ b/hello.txt
+@@ -1 +1 @@
+-hello
++hello, world
+";
+
+    #[test]
+    fn test_verify_index() {
+        let mut patch = Patch::from_single(SAMPLE).unwrap();
+        assert!(verify_index(&patch, b"hello\n").is_ok());
+        match verify_index(&patch, b"hello") {
+            Err(ApplyError::IndexMismatch { expected, actual }) => {
+                assert_eq!(expected, "ce013625030ba8dba906f756967f9e9ca394464a");
+                assert_eq!(actual, blob_id(b"hello"));
+            }
+            result => panic!("{:?}", result),
+        }
+
+        // Abbreviated and uppercase ids match too
+        let index = patch.git.as_mut().unwrap().index.as_mut().unwrap();
+        index.old_id = "CE01362".into();
+        assert!(verify_index(&patch, b"hello\n").is_ok());
+
+        // Files that didn't exist aren't checked, and neither are patches without ids
+        let index = patch.git.as_mut().unwrap().index.as_mut().unwrap();
+        index.old_id = "0000000".into();
+        assert!(verify_index(&patch, b"anything\n").is_ok());
+        patch.git = None;
+        assert!(verify_index(&patch, b"anything\n").is_ok());
+    }
+
+    #[test]
+    fn test_apply_with_verify_index() {
+        let patch = Patch::from_single(SAMPLE).unwrap();
+        let options = ApplyOptions {
+            verify_index: true,
+            ..ApplyOptions::default()
+        };
+        assert_eq!(
+            apply_with_options(&patch, "hello\n", &options).unwrap(),
+            "hello, world\n"
+        );
+        // The hunk would apply, but the file isn't the one the patch was made for
+        let content = "hello\nmore\n";
+        assert!(apply_with_options(&patch, content, &ApplyOptions::default()).is_ok());
+        assert!(matches!(
+            apply_with_options(&patch, content, &options),
+            Err(ApplyError::IndexMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,260 @@
+use std::borrow::Cow;
+
+use crate::ast::{DEV_NULL, File, FileMetadata, FileOp, Hunk, Line, Patch};
+use crate::generator::hunk_range;
+
+/// Builds a [`Patch`] from its hunks, computing the ranges of the new file and whether the
+/// files end with a newline
+///
+/// # Example
+///
+/// ```
+/// use patch::{HunkBuilder, PatchBuilder};
+///
+/// let patch = PatchBuilder::new("a/src/main.rs", "b/src/main.rs")
+///     .hunk(
+///         HunkBuilder::new(1)
+///             .context("fn main() {")
+///             .remove("    println!(\"hello\");")
+///             .add("    println!(\"hello, world\");")
+///             .context("}"),
+///     )
+///     .hunk(HunkBuilder::new(10).add("// the end").no_newline())
+///     .build();
+///
+/// assert_eq!(
+///     patch.to_string(),
+///     "\
+/// --- a/src/main.rs
+/// +++ b/src/main.rs
+/// @@ -1,3 +1,3 @@
+///  fn main() {
+/// -    println!(\"hello\");
+/// +    println!(\"hello, world\");
+///  }
+/// @@ -9,0 +10,1 @@
+/// +// the end
+/// \\ No newline at end of file"
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct PatchBuilder<'a> {
+    old: File<'a>,
+    new: File<'a>,
+    hunks: Vec<HunkBuilder<'a>>,
+}
+
+impl<'a> PatchBuilder<'a> {
+    /// Start a patch that changes the file at the `old` path into the one at the `new` path,
+    /// which are usually the same apart from an `a/` or `b/` prefix
+    pub fn new(old: impl Into<Cow<'a, str>>, new: impl Into<Cow<'a, str>>) -> Self {
+        PatchBuilder {
+            old: File {
+                path: old.into(),
+                meta: None,
+            },
+            new: File {
+                path: new.into(),
+                meta: None,
+            },
+            hunks: Vec::new(),
+        }
+    }
+
+    /// Start a patch that creates the file at `path`, whose old file is `/dev/null`
+    pub fn create(path: impl Into<Cow<'a, str>>) -> Self {
+        PatchBuilder::new(DEV_NULL, path)
+    }
+
+    /// Start a patch that deletes the file at `path`, whose new file is `/dev/null`
+    pub fn delete(path: impl Into<Cow<'a, str>>) -> Self {
+        PatchBuilder::new(path, DEV_NULL)
+    }
+
+    /// Write metadata, like a timestamp, after the path of the old file
+    pub fn old_meta(mut self, meta: FileMetadata<'a>) -> Self {
+        self.old.meta = Some(meta);
+        self
+    }
+
+    /// Write metadata, like a timestamp, after the path of the new file
+    pub fn new_meta(mut self, meta: FileMetadata<'a>) -> Self {
+        self.new.meta = Some(meta);
+        self
+    }
+
+    /// Add a hunk after the ones added so far, which have to come before it in the old file
+    pub fn hunk(mut self, hunk: HunkBuilder<'a>) -> Self {
+        self.hunks.push(hunk);
+        self
+    }
+
+    /// Build the patch
+    ///
+    /// The new file of every hunk starts where its old file does, shifted by the number of
+    /// lines the hunks before it add or remove. The files end with a newline unless the last
+    /// hunk says otherwise with [`HunkBuilder::no_newline`].
+    pub fn build(self) -> Patch<'a> {
+        let (old_end_newline, new_end_newline) = self.hunks.last().map_or((true, true), |hunk| {
+            (hunk.old_end_newline, hunk.new_end_newline)
+        });
+        let mut growth: i64 = 0;
+        let mut hunks = Vec::with_capacity(self.hunks.len());
+        for hunk in self.hunks {
+            let hunk = hunk.build_shifted(growth);
+            growth += hunk.new_range.count as i64 - hunk.old_range.count as i64;
+            hunks.push(hunk);
+        }
+        Patch {
+            old: self.old,
+            new: self.new,
+            operation: FileOp::Modify,
+            git: None,
+            svn: None,
+            hunks,
+            binary: None,
+            opaque_binary: false,
+            preamble: None,
+            raw: None,
+            old_end_newline,
+            new_end_newline,
+        }
+    }
+}
+
+/// Builds a [`Hunk`] from its lines, computing its ranges
+///
+/// See [`PatchBuilder`] for an example.
+#[derive(Debug, Clone)]
+pub struct HunkBuilder<'a> {
+    // The number of lines of the old file before the hunk
+    lines_before: u64,
+    hint: Cow<'a, str>,
+    lines: Vec<Line<'a>>,
+    old_end_newline: bool,
+    new_end_newline: bool,
+}
+
+impl<'a> HunkBuilder<'a> {
+    /// Start a hunk at the given line of the old file, counting from 1
+    ///
+    /// If the hunk only adds lines, they are inserted before that line. A hunk at line 1 of a
+    /// file that doesn't exist yet creates its content.
+    pub fn new(line: u64) -> Self {
+        HunkBuilder {
+            lines_before: line.saturating_sub(1),
+            hint: Cow::Borrowed(""),
+            lines: Vec::new(),
+            old_end_newline: true,
+            new_end_newline: true,
+        }
+    }
+
+    /// Write a hint after the ranges of the hunk, usually the name of the function it is in
+    pub fn hint(mut self, hint: impl Into<Cow<'a, str>>) -> Self {
+        self.hint = Cow::Owned(format!(" {}", hint.into()));
+        self
+    }
+
+    /// Add a line that is in both files, without its line ending
+    pub fn context(mut self, text: impl Into<Cow<'a, str>>) -> Self {
+        self.lines.push(Line::Context(text.into()));
+        self
+    }
+
+    /// Add a line that is only in the new file, without its line ending
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, text: impl Into<Cow<'a, str>>) -> Self {
+        self.lines.push(Line::Add(text.into()));
+        self
+    }
+
+    /// Add a line that is only in the old file, without its line ending
+    pub fn remove(mut self, text: impl Into<Cow<'a, str>>) -> Self {
+        self.lines.push(Line::Remove(text.into()));
+        self
+    }
+
+    /// Mark the last line added to the hunk as the end of its files, without a newline character
+    ///
+    /// Like a `\ No newline at end of file` marker, this applies to the old file after a removed
+    /// line, to the new file after an added line and to both after a context line. It only
+    /// matters for the last hunk of a patch, which has to reach the end of the files.
+    pub fn no_newline(mut self) -> Self {
+        match self.lines.last() {
+            Some(Line::Remove(_)) => self.old_end_newline = false,
+            Some(Line::Add(_)) => self.new_end_newline = false,
+            Some(Line::Context(_)) => (self.old_end_newline, self.new_end_newline) = (false, false),
+            None => {}
+        }
+        self
+    }
+
+    /// Build the hunk, with a new file that starts at the same line as the old one
+    pub fn build(self) -> Hunk<'a> {
+        self.build_shifted(0)
+    }
+
+    // Build the hunk with a new file that starts `growth` lines after the old one
+    fn build_shifted(self, growth: i64) -> Hunk<'a> {
+        let (old_count, new_count) =
+            self.lines
+                .iter()
+                .fold((0, 0), |(old, new), line| match line {
+                    Line::Context(_) => (old + 1, new + 1),
+                    Line::Remove(_) => (old + 1, new),
+                    Line::Add(_) => (old, new + 1),
+                });
+        let new_before = (self.lines_before as i64 + growth).max(0) as u64;
+        Hunk {
+            old_range: hunk_range(self.lines_before, old_count),
+            new_range: hunk_range(new_before, new_count),
+            range_hint: self.hint,
+            lines: self.lines,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::applier::apply;
+
+    #[test]
+    fn test_build_patch() {
+        let patch = PatchBuilder::new("a.txt", "a.txt")
+            .hunk(HunkBuilder::new(2).remove("b").remove("c").add("C"))
+            .hunk(HunkBuilder::new(5).hint("section").add("x").context("e"))
+            .hunk(HunkBuilder::new(7).remove("g").add("G").no_newline())
+            .build();
+
+        let headers: Vec<_> = patch
+            .hunks
+            .iter()
+            .map(|hunk| format!("{} {}", hunk.old_range, hunk.new_range))
+            .collect();
+        assert_eq!(headers, ["2,2 2,1", "5,1 4,2", "7,1 7,1"]);
+        assert_eq!(patch.hunks[1].hint(), Some("section"));
+        assert!(patch.old_end_newline);
+        assert!(!patch.new_end_newline);
+
+        let content = "a\nb\nc\nd\ne\nf\ng\n";
+        assert_eq!(apply(&patch, content).unwrap(), "a\nC\nd\nx\ne\nf\nG");
+        assert_eq!(Patch::from_single(&patch.to_string()).unwrap(), patch);
+    }
+
+    #[test]
+    fn test_build_created_file() {
+        let patch = PatchBuilder::create("b/new.txt")
+            .hunk(HunkBuilder::new(1).add("one").add("two"))
+            .build();
+        assert_eq!(
+            patch.to_string(),
+            "--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1,2 @@\n+one\n+two"
+        );
+        assert_eq!(apply(&patch, "").unwrap(), "one\ntwo\n");
+    }
+}
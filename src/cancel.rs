@@ -0,0 +1,61 @@
+//! Stopping long diffs and searches from another thread
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A flag that tells a slow diff or search to stop, like [`DiffOptions::cancel`],
+/// [`ApplyOptions::cancel`] and [`FindReplaceOptions::cancel`]
+///
+/// Clones share the flag, so an editor can keep one and cancel the work done with another on a
+/// different thread, without stopping the thread. The work checks the flag as it goes, and
+/// stops soon after it is set.
+///
+/// Two tokens are equal if they share the same flag.
+///
+/// [`ApplyOptions::cancel`]: crate::ApplyOptions::cancel
+/// [`DiffOptions::cancel`]: crate::DiffOptions::cancel
+/// [`FindReplaceOptions::cancel`]: crate::FindReplaceOptions::cancel
+///
+/// # Example
+///
+/// ```
+/// use patch::CancellationToken;
+///
+/// let token = CancellationToken::new();
+/// let shared = token.clone();
+/// assert!(!shared.is_cancelled());
+/// token.cancel();
+/// assert!(shared.is_cancelled());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token that isn't cancelled yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tell the work done with this token, or with any of its clones, to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether [`cancel`](Self::cancel) was called on this token or on one of its clones
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancellationToken {}
+
+// Whether the work done with the token, if any, should stop
+pub(crate) fn cancelled(cancel: Option<&CancellationToken>) -> bool {
+    cancel.is_some_and(CancellationToken::is_cancelled)
+}
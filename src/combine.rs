@@ -0,0 +1,318 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::applier::{ApplyError, apply};
+use crate::ast::{FileOp, Patch};
+use crate::interdiff::{Side, diff_known, reconstruct};
+
+/// Error that can occur while combining two patches with [`combine`]
+#[derive(Debug)]
+pub enum CombineError {
+    /// The second patch doesn't match the file the first one produces
+    Mismatch {
+        /// The line of the file produced by the first patch, counting from 1
+        line: u64,
+        /// The text of the line according to the first patch
+        first: String,
+        /// The text of the line according to the second patch
+        second: String,
+    },
+    /// One of the patches could not be applied to the reconstructed file
+    Apply(ApplyError),
+}
+
+impl fmt::Display for CombineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CombineError::Mismatch {
+                line,
+                first,
+                second,
+            } => write!(
+                f,
+                "The patches don't match at line {}: the first one produces '{}', the second one expects '{}'",
+                line, first, second
+            ),
+            CombineError::Apply(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for CombineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CombineError::Apply(error) => Some(error),
+            CombineError::Mismatch { .. } => None,
+        }
+    }
+}
+
+impl From<ApplyError> for CombineError {
+    fn from(error: ApplyError) -> Self {
+        CombineError::Apply(error)
+    }
+}
+
+/// Combine two patches applied one after the other into a single patch, like `combinediff`
+///
+/// `second` has to be made against the file `first` produces. The file in between is
+/// reconstructed from the lines both patches show of it, and the result turns the file `first`
+/// is made against straight into the file `second` produces: the ranges of the hunks of
+/// `second` are moved back to where they are before `first` is applied, and hunks of both
+/// patches that overlap or touch are merged into one. Lines neither patch shows are never
+/// part of the result, not even as context.
+///
+/// The result has the old file of `first` and the new file of `second`.
+///
+/// # Example
+///
+/// ```
+/// use patch::{Patch, apply, combine};
+///
+/// let first = Patch::from_single(
+///     "\
+/// --- a/list.txt
+/// +++ b/list.txt
+/// @@ -1,3 +1,4 @@
+///  apples
+/// +bananas
+///  cherries
+///  dates
+/// ",
+/// )
+/// .unwrap();
+/// let second = Patch::from_single(
+///     "\
+/// --- a/list.txt
+/// +++ b/list.txt
+/// @@ -2,3 +2,3 @@
+///  bananas
+/// -cherries
+/// +coconuts
+///  dates
+/// ",
+/// )
+/// .unwrap();
+///
+/// let combined = combine(&first, &second).unwrap();
+/// assert_eq!(
+///     combined.to_string(),
+///     "\
+/// --- a/list.txt
+/// +++ b/list.txt
+/// @@ -1,3 +1,4 @@
+///  apples
+/// -cherries
+/// +bananas
+/// +coconuts
+///  dates"
+/// );
+///
+/// let content = "apples\ncherries\ndates\n";
+/// let step_by_step = apply(&second, &apply(&first, content).unwrap()).unwrap();
+/// assert_eq!(apply(&combined, content).unwrap(), step_by_step);
+/// ```
+pub fn combine(first: &Patch, second: &Patch) -> Result<Patch<'static>, CombineError> {
+    let middle = reconstruct([(first, Side::New), (second, Side::Old)]).map_err(|mismatch| {
+        CombineError::Mismatch {
+            line: mismatch.line,
+            first: mismatch.first,
+            second: mismatch.second,
+        }
+    })?;
+    let old_text = apply(&first.reverse(), &middle)?;
+    let new_text = apply(second, &middle)?;
+    let (hunks, old_end_newline, new_end_newline) = diff_known(&old_text, &new_text);
+
+    let operation = match (&first.operation, &second.operation) {
+        (_, FileOp::Delete) => FileOp::Delete,
+        (FileOp::Create, _) => FileOp::Create,
+        (operation, FileOp::Modify) | (FileOp::Modify, operation) => operation.clone(),
+        (FileOp::Rename { from, .. }, FileOp::Rename { to, .. }) => FileOp::Rename {
+            from: from.clone(),
+            to: to.clone(),
+        },
+        (_, operation) => operation.clone(),
+    };
+
+    Ok(Patch {
+        old: first.old.clone().into_owned(),
+        new: second.new.clone().into_owned(),
+        operation: operation.into_owned(),
+        git: None,
+        svn: None,
+        hunks,
+        binary: None,
+        opaque_binary: false,
+        preamble: None,
+        raw: None,
+        old_end_newline,
+        new_end_newline,
+    })
+}
+
+impl Patch<'_> {
+    /// Combine this patch with the next one applied after it into a single patch. See
+    /// [`combine`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::{CombineError, Patch, apply};
+    ///
+    /// let first = Patch::from_single("--- a/x\n+++ b/x\n@@ -1,2 +1,2 @@\n-a\n+A\n b\n").unwrap();
+    /// let next = Patch::from_single("--- a/x\n+++ b/x\n@@ -1,2 +1,2 @@\n A\n-b\n+B\n").unwrap();
+    /// let both = first.then(&next).unwrap();
+    /// assert_eq!(apply(&both, "a\nb\n").unwrap(), "A\nB\n");
+    ///
+    /// // The next patch has to be made against the file this one produces
+    /// let error = first.then(&first).unwrap_err();
+    /// assert!(matches!(error, CombineError::Mismatch { line: 1, .. }));
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "The patches don't match at line 1: the first one produces 'A', the second one expects 'a'"
+    /// );
+    /// ```
+    pub fn then(&self, next: &Patch) -> Result<Patch<'static>, CombineError> {
+        combine(self, next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::diff;
+
+    #[test]
+    fn test_combine_moves_later_hunks() {
+        let first = Patch::from_single(
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -2,3 +2,5 @@
+ b
++b1
++b2
+ c
+ d
+",
+        )
+        .unwrap();
+        let second = Patch::from_single(
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -21,3 +21,2 @@
+ s
+-t
+ u
+",
+        )
+        .unwrap();
+        assert_eq!(
+            combine(&first, &second).unwrap().to_string(),
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -2,3 +2,5 @@
+ b
++b1
++b2
+ c
+ d
+@@ -19,3 +21,2 @@
+ s
+-t
+ u"
+        );
+    }
+
+    #[test]
+    fn test_combine_undone_change() {
+        let first = Patch::from_single("--- a\n+++ b\n@@ -1,2 +1,2 @@\n-a\n+A\n b\n").unwrap();
+        let combined = combine(&first, &first.reverse()).unwrap();
+        assert_eq!(combined.hunks, []);
+    }
+
+    #[test]
+    fn test_combine_newline_at_end() {
+        // The second patch removes or adds the final newline
+        let cases = [("x\na\n", "y\na\n", "y\na"), ("x\na", "y\na", "y\na\n")];
+        for (old, middle, new) in cases {
+            let combined = combine(&diff(old, middle, 1), &diff(middle, new, 1)).unwrap();
+            assert_eq!(apply(&combined, old).unwrap(), new);
+        }
+    }
+
+    #[test]
+    fn test_combine_zero_context() {
+        // Patches that only add lines, so the second one's old range has no lines
+        let (old, middle, new) = ("a\nb\nc\nd\n", "a\nx\nb\nc\nd\n", "a\nx\nb\nc\nd\ny\n");
+        let (first, second) = (diff(old, middle, 0), diff(middle, new, 0));
+        let combined = combine(&first, &second).unwrap();
+        assert_eq!(apply(&combined, old).unwrap(), new);
+    }
+
+    #[test]
+    fn test_then() {
+        let edits = [
+            "--- a/f\n+++ b/f\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n",
+            "--- a/f\n+++ b/f\n@@ -2,2 +2,3 @@\n B\n+B2\n c\n",
+            "--- a/f\n+++ b/f\n@@ -1,2 +1,1 @@\n-a\n B\n",
+        ];
+        let patches: Vec<Patch> = edits
+            .iter()
+            .map(|edit| Patch::from_single(edit).unwrap())
+            .collect();
+        let squashed = patches[1..]
+            .iter()
+            .try_fold(patches[0].clone().into_owned(), |squashed, next| {
+                squashed.then(next)
+            })
+            .unwrap();
+        assert_eq!(apply(&squashed, "a\nb\nc\n").unwrap(), "B\nB2\nc\n");
+        assert_eq!(
+            squashed,
+            combine(&combine(&patches[0], &patches[1]).unwrap(), &patches[2]).unwrap()
+        );
+
+        // Applying them in another order doesn't work
+        assert!(matches!(
+            patches[1].then(&patches[0]),
+            Err(CombineError::Mismatch { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_then_zero_context() {
+        let versions = [
+            "a\nb\nc\nd\n",
+            "a\nx\nb\nc\nd\n",
+            "a\nx\nb\nc\nd\ny\n",
+            "a\nc\nd\ny\n",
+        ];
+        let patches: Vec<Patch> = versions
+            .windows(2)
+            .map(|pair| diff(pair[0], pair[1], 0))
+            .collect();
+        let squashed = patches[0]
+            .then(&patches[1])
+            .and_then(|squashed| squashed.then(&patches[2]))
+            .unwrap();
+        assert_eq!(apply(&squashed, versions[0]).unwrap(), versions[3]);
+    }
+
+    #[test]
+    fn test_combine_mismatch() {
+        let first = Patch::from_single("--- a\n+++ b\n@@ -1,2 +1,2 @@\n-a\n+A\n b\n").unwrap();
+        let second = Patch::from_single("--- a\n+++ b\n@@ -1 +1 @@\n-a\n+B\n").unwrap();
+        assert!(matches!(
+            combine(&first, &second),
+            Err(CombineError::Mismatch { line: 1, ref first, ref second })
+                if first == "A" && second == "a"
+        ));
+    }
+}
@@ -0,0 +1,412 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use nom::{
+    Parser,
+    branch::alt,
+    bytes::complete::{tag, take_while1},
+    character::complete::{char, line_ending, not_line_ending},
+    combinator::{all_consuming, map, not, opt},
+    error::{ErrorKind, make_error},
+    multi::{count, many0, many1},
+    sequence::{delimited, preceded, terminated},
+};
+
+use crate::ast::{File, FileOp, Hunk, Line, Patch, Range, maybe_escape_quote};
+use crate::parser::{
+    IResult, Input, ParseError, consume_content_line, convert_error, filename, header_line_content,
+    no_newline_indicator, range,
+};
+
+/// The changes a merge commit makes to a file, compared to each of its parents
+///
+/// `git show` and `git diff` write these combined diffs with a `diff --cc` or `diff --combined`
+/// line and hunks starting with `@@@`, one `@` more than there are parents. Each line of a hunk
+/// starts with one column per parent, telling how the line differs from that parent.
+///
+/// # Example
+///
+/// ```
+/// use patch::{CombinedPatch, Origin};
+///
+/// let sample = "\
+/// diff --cc greeting.txt
+/// index 1a2b3c4,5d6e7f8..9a0b1c2
+/// --- a/greeting.txt
+/// +++ b/greeting.txt
+/// @@@ -1,2 -1,2 +1,2 @@@
+///   Hello
+/// - from main
+///  -from topic
+/// ++from the merge
+/// ";
+///
+/// let patch = CombinedPatch::from_single(sample).unwrap();
+/// assert_eq!(patch.parents(), 2);
+/// let lines = &patch.hunks[0].lines;
+/// assert_eq!(lines[3].origins, [Origin::Add, Origin::Add]);
+/// assert_eq!(patch.to_string(), sample.trim_end());
+///
+/// // The changes compared to the first parent, as an ordinary patch
+/// assert_eq!(
+///     patch.parent_patch(0).to_string(),
+///     "--- a/greeting.txt\n+++ b/greeting.txt\n@@ -1,2 +1,2 @@\n Hello\n-from main\n+from the merge"
+/// );
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CombinedPatch<'a> {
+    /// The path on the `diff --cc` or `diff --combined` line
+    pub path: Cow<'a, str>,
+    /// Whether the first line was `diff --combined` rather than `diff --cc`
+    pub combined: bool,
+    /// The extended header lines, like `index`, `mode` or `Binary files differ`, as they are
+    pub header: Vec<Cow<'a, str>>,
+    /// The file information of the parents, line prefix: `---`
+    ///
+    /// This is `None` if the patch has no file header lines, like for binary files.
+    pub old: Option<File<'a>>,
+    /// The file information of the merge result, line prefix: `+++`
+    pub new: Option<File<'a>>,
+    /// hunks of differences; each hunk shows one area where the result differs from a parent
+    pub hunks: Vec<CombinedHunk<'a>>,
+}
+
+/// One area where the result of a merge differs from some of its parents
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CombinedHunk<'a> {
+    /// The range of lines in each parent that this hunk represents
+    pub old_ranges: Vec<Range>,
+    /// The range of lines in the merge result that this hunk represents
+    pub new_range: Range,
+    /// Any trailing text after the hunk's range information
+    pub range_hint: Cow<'a, str>,
+    /// Each line of text in the hunk, prefixed with how it differs from each parent
+    pub lines: Vec<CombinedLine<'a>>,
+}
+
+/// A line of a combined diff
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CombinedLine<'a> {
+    /// How the line differs from each parent, in the order of the parents
+    pub origins: Vec<Origin>,
+    /// The text of the line
+    pub text: Cow<'a, str>,
+    /// true if the line is followed by the text: `\ No newline at end of file`
+    pub no_newline: bool,
+}
+
+/// How a line of a combined diff differs from one parent
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Origin {
+    /// The line is the same as in the parent, or in neither the parent nor the result if it is
+    /// removed compared to another parent, column: ` `
+    Context,
+    /// The line is in the result but not in the parent, column: `+`
+    Add,
+    /// The line is in the parent but not in the result, column: `-`
+    Remove,
+}
+
+impl<'a> CombinedPatch<'a> {
+    /// Attempt to parse a combined diff of a single file
+    ///
+    /// Any lines before the `diff --cc` or `diff --combined` line are skipped, like the commit
+    /// information `git show` writes first.
+    pub fn from_single(s: &'a str) -> Result<Self, ParseError<'a>> {
+        let mut parser = all_consuming(combined_patch);
+        let (_, patch) = parser.parse(Input::new(s)).map_err(convert_error)?;
+        Ok(patch)
+    }
+
+    /// Attempt to parse the combined diffs of several files, like `git show` writes for a merge
+    /// commit
+    pub fn from_multiple(s: &'a str) -> Result<Vec<Self>, ParseError<'a>> {
+        let mut parser = all_consuming(many1(combined_patch));
+        let (_, patches) = parser.parse(Input::new(s)).map_err(convert_error)?;
+        Ok(patches)
+    }
+
+    /// The number of parents of the merge
+    pub fn parents(&self) -> usize {
+        self.hunks.first().map_or(0, |hunk| hunk.old_ranges.len())
+    }
+
+    /// The changes between one parent, counting from 0, and the merge result, as an ordinary
+    /// patch
+    ///
+    /// Lines that are only in other parents are left out. Panics if the merge doesn't have that
+    /// parent.
+    pub fn parent_patch(&self, parent: usize) -> Patch<'a> {
+        let file = |file: &Option<File<'a>>| {
+            file.clone().unwrap_or_else(|| File {
+                path: self.path.clone(),
+                meta: None,
+            })
+        };
+        let (mut old_end_newline, mut new_end_newline) = (true, true);
+        let hunks = self
+            .hunks
+            .iter()
+            .map(|hunk| {
+                let mut lines = Vec::with_capacity(hunk.lines.len());
+                for line in &hunk.lines {
+                    let removed = line.origins.contains(&Origin::Remove);
+                    let line = match line.origins[parent] {
+                        Origin::Add => Line::Add(line.text.clone()),
+                        Origin::Remove => Line::Remove(line.text.clone()),
+                        Origin::Context if removed => continue,
+                        Origin::Context => Line::Context(line.text.clone()),
+                    };
+                    lines.push(line);
+                }
+                Hunk {
+                    old_range: hunk.old_ranges[parent].clone(),
+                    new_range: hunk.new_range.clone(),
+                    range_hint: hunk.range_hint.clone(),
+                    lines,
+                }
+            })
+            .collect();
+
+        // Only the last hunk can reach the end of the files
+        if let Some(hunk) = self.hunks.last() {
+            let last = |shown: &dyn Fn(&CombinedLine) -> bool| {
+                hunk.lines
+                    .iter()
+                    .rfind(|line| shown(line))
+                    .is_some_and(|line| line.no_newline)
+            };
+            let in_result = |line: &CombinedLine| !line.origins.contains(&Origin::Remove);
+            old_end_newline = !last(&|line| match line.origins[parent] {
+                Origin::Remove => true,
+                Origin::Add => false,
+                Origin::Context => in_result(line),
+            });
+            new_end_newline = !last(&in_result);
+        }
+
+        Patch {
+            old: file(&self.old),
+            new: file(&self.new),
+            operation: FileOp::Modify,
+            git: None,
+            svn: None,
+            hunks,
+            binary: None,
+            opaque_binary: false,
+            preamble: None,
+            raw: None,
+            old_end_newline,
+            new_end_newline,
+        }
+    }
+}
+
+impl fmt::Display for CombinedPatch<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "diff --{} ",
+            if self.combined { "combined" } else { "cc" }
+        )?;
+        maybe_escape_quote(f, &self.path)?;
+        for line in &self.header {
+            write!(f, "\n{}", line)?;
+        }
+        if let Some(old) = &self.old {
+            write!(f, "\n--- {}", old)?;
+        }
+        if let Some(new) = &self.new {
+            write!(f, "\n+++ {}", new)?;
+        }
+        for hunk in &self.hunks {
+            write!(f, "\n{}", hunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CombinedHunk<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let marker = "@".repeat(self.old_ranges.len() + 1);
+        write!(f, "{}", marker)?;
+        for range in &self.old_ranges {
+            write!(f, " -{}", range)?;
+        }
+        write!(f, " +{} {}{}", self.new_range, marker, self.range_hint)?;
+        for line in &self.lines {
+            write!(f, "\n{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CombinedLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for origin in &self.origins {
+            let column = match origin {
+                Origin::Context => ' ',
+                Origin::Add => '+',
+                Origin::Remove => '-',
+            };
+            write!(f, "{}", column)?;
+        }
+        write!(f, "{}", self.text)?;
+        if self.no_newline {
+            write!(f, "\n\\ No newline at end of file")?;
+        }
+        Ok(())
+    }
+}
+
+fn combined_patch(input: Input<'_>) -> IResult<Input<'_>, CombinedPatch<'_>> {
+    // Ignore any lines up to the start of the patch, like the commit information
+    let start = alt((tag("diff --cc "), tag("diff --combined ")));
+    let mut parser = many0(preceded(not(start), consume_content_line));
+    let (input, _) = parser.parse(input)?;
+
+    let (input, kind) = alt((tag("diff --cc "), tag("diff --combined "))).parse(input)?;
+    let (input, path) = terminated(filename, line_ending).parse(input)?;
+    let end_of_header = alt((tag("--- "), tag("@@@"), tag("diff --")));
+    let (input, header) = many0(preceded(not(end_of_header), consume_content_line)).parse(input)?;
+    let (input, files) = opt((
+        delimited(tag("--- "), header_line_content, line_ending),
+        delimited(tag("+++ "), header_line_content, line_ending),
+    ))
+    .parse(input)?;
+    let (input, hunks) = many0(combined_hunk).parse(input)?;
+    let (input, _) = many0(line_ending).parse(input)?;
+
+    let (old, new) = files.unzip();
+    Ok((
+        input,
+        CombinedPatch {
+            path,
+            combined: kind.fragment().contains("combined"),
+            header,
+            old,
+            new,
+            hunks,
+        },
+    ))
+}
+
+fn combined_hunk(input: Input<'_>) -> IResult<Input<'_>, CombinedHunk<'_>> {
+    let (input, marker) = take_while1(|c| c == '@')(input)?;
+    let parents = marker.fragment().len() - 1;
+    if parents < 2 {
+        return Err(nom::Err::Error(make_error(input, ErrorKind::Count)));
+    }
+    let (input, old_ranges) = count(preceded(tag(" -"), range), parents).parse(input)?;
+    let (input, new_range) = preceded(tag(" +"), range).parse(input)?;
+    let (input, _) = (char(' '), tag(*marker.fragment())).parse(input)?;
+    let (input, range_hint) = terminated(not_line_ending, line_ending).parse(input)?;
+    let (input, lines) = many1(|input| combined_line(parents, input)).parse(input)?;
+    Ok((
+        input,
+        CombinedHunk {
+            old_ranges,
+            new_range,
+            range_hint: Cow::Borrowed(range_hint.fragment()),
+            lines,
+        },
+    ))
+}
+
+fn combined_line(parents: usize, input: Input<'_>) -> IResult<Input<'_>, CombinedLine<'_>> {
+    let origin = alt((
+        map(char(' '), |_| Origin::Context),
+        map(char('+'), |_| Origin::Add),
+        map(char('-'), |_| Origin::Remove),
+    ));
+    let mut parser = (
+        count(origin, parents),
+        consume_content_line,
+        no_newline_indicator,
+    );
+    let (input, (origins, text, no_newline)) = parser.parse(input)?;
+    Ok((
+        input,
+        CombinedLine {
+            origins,
+            text,
+            no_newline,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_merge_commit() {
+        let sample = "\
+commit 0123456789abcdef0123456789abcdef01234567
+Merge: 1234567 89abcde
+Author: A U Thor <author@example.com>
+
+    Merge branch 'topic'
+
+diff --cc a.txt
+index 1111111,2222222..3333333
+--- a/a.txt
++++ b/a.txt
+@@@ -1,3 -1,2 +1,3 @@@ fn main()
+  one
+ +two
+- three
+ -drei
+++four
+diff --cc image.png
+index 4444444,5555555..6666666
+Binary files differ
+";
+        let patches = CombinedPatch::from_multiple(sample).unwrap();
+        assert_eq!(patches.len(), 2);
+
+        let hunk = &patches[0].hunks[0];
+        assert_eq!(hunk.range_hint, " fn main()");
+        assert_eq!(
+            hunk.old_ranges,
+            [Range { start: 1, count: 3 }, Range { start: 1, count: 2 }]
+        );
+        assert_eq!(
+            patches[0].parent_patch(1).to_string(),
+            "--- a/a.txt\n+++ b/a.txt\n@@ -1,2 +1,3 @@ fn main()\n one\n+two\n-drei\n+four"
+        );
+
+        assert_eq!(patches[1].old, None);
+        assert_eq!(
+            patches[1].header,
+            ["index 4444444,5555555..6666666", "Binary files differ"]
+        );
+        assert_eq!(
+            patches[1].to_string(),
+            "diff --cc image.png\nindex 4444444,5555555..6666666\nBinary files differ"
+        );
+    }
+
+    #[test]
+    fn test_three_parents() {
+        let sample = "\
+diff --combined x
+--- a/x
++++ b/x
+@@@@ -0,0 -0,0 -0,0 +1,1 @@@@
++++x
+\\ No newline at end of file
+";
+        let patch = CombinedPatch::from_single(sample).unwrap();
+        assert_eq!(patch.parents(), 3);
+        assert!(patch.hunks[0].lines[0].no_newline);
+        assert!(!patch.parent_patch(2).new_end_newline);
+        assert_eq!(format!("{}\n", patch), sample);
+    }
+}
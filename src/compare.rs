@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use crate::ast::{Line, Patch};
+
+/// A run of changed lines of a patch, wherever its hunks start and however much context they
+/// have
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Change<'p> {
+    /// The number of lines of the old file before the change
+    offset: u64,
+    removed: Vec<&'p str>,
+    added: Vec<&'p str>,
+    /// Whether the last removed line is the end of the old file, without a newline character
+    old_missing_newline: bool,
+    /// Whether the last added line is the end of the new file, without a newline character
+    new_missing_newline: bool,
+}
+
+impl Patch<'_> {
+    /// Whether two patches make the same changes to the same file, however they are formatted
+    ///
+    /// Unlike `==`, this ignores the timestamps and other metadata of the file headers, the `a/`
+    /// and `b/` prefixes of the paths, how much context the hunks have and how the changes are
+    /// split into hunks, the hints of the hunk headers and the text before the patches. Patches
+    /// creating or deleting a regular file are equal whether they say so with a `diff --git`
+    /// header or with `/dev/null`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::Patch;
+    ///
+    /// let git = Patch::from_single(
+    ///     "\
+    /// diff --git a/greet.py b/greet.py
+    /// --- a/greet.py
+    /// +++ b/greet.py
+    /// @@ -1,3 +1,3 @@ def greet():
+    ///  def greet():
+    /// -    print('hi')
+    /// +    print('hello')
+    ///  greet()
+    /// ",
+    /// )
+    /// .unwrap();
+    /// let plain = Patch::from_single(
+    ///     "\
+    /// --- greet.py\t2024-06-01 10:00:00 +0200
+    /// +++ greet.py\t2024-06-01 10:05:00 +0200
+    /// @@ -2 +2 @@
+    /// -    print('hi')
+    /// +    print('hello')
+    /// ",
+    /// )
+    /// .unwrap();
+    /// assert_ne!(git, plain);
+    /// assert!(git.semantic_eq(&plain));
+    /// ```
+    pub fn semantic_eq(&self, other: &Patch) -> bool {
+        // Patches without modes are about regular files
+        let modes = |patch: &Patch| {
+            let git = patch.git.as_ref();
+            let special = |mode: &u32| *mode != 0o100644;
+            (
+                git.and_then(|git| git.old_mode).filter(special),
+                git.and_then(|git| git.new_mode).filter(special),
+            )
+        };
+        unprefixed(&self.old.path, "a/") == unprefixed(&other.old.path, "a/")
+            && unprefixed(&self.new.path, "b/") == unprefixed(&other.new.path, "b/")
+            && self.file_op() == other.file_op()
+            && modes(self) == modes(other)
+            && self.binary == other.binary
+            && self.opaque_binary == other.opaque_binary
+            && changes(self) == changes(other)
+    }
+
+    /// How similar the changed lines of two patches are, from 0 for patches without any changed
+    /// line in common to 1 for patches adding and removing the same lines
+    ///
+    /// This is the number of added and removed lines the patches have in common, counted twice,
+    /// divided by the number of changed lines of both patches. Where the lines are and which files
+    /// they are in doesn't matter, so this finds patches that were made for different versions
+    /// of a file, or several times by different tools. Two patches without changed lines are
+    /// alike.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::Patch;
+    ///
+    /// let first = Patch::from_single("--- a\n+++ a\n@@ -1,2 +1,2 @@\n-x\n-y\n+X\n+Y\n").unwrap();
+    /// let second = Patch::from_single("--- b\n+++ b\n@@ -8 +8 @@\n-x\n+X\n").unwrap();
+    /// assert_eq!(first.similarity(&second), 4.0 / 6.0);
+    /// assert_eq!(first.similarity(&first), 1.0);
+    /// ```
+    pub fn similarity(&self, other: &Patch) -> f32 {
+        let mut counts: HashMap<(bool, &str), usize> = HashMap::new();
+        for key in changed_lines(self) {
+            *counts.entry(key).or_default() += 1;
+        }
+        let mut common = 0;
+        let mut total = counts.values().sum::<usize>();
+        for key in changed_lines(other) {
+            total += 1;
+            if let Some(count) = counts.get_mut(&key).filter(|count| **count > 0) {
+                *count -= 1;
+                common += 1;
+            }
+        }
+        match total {
+            0 => 1.0,
+            total => (2 * common) as f32 / total as f32,
+        }
+    }
+}
+
+// A path without the given prefix, if it has it
+fn unprefixed<'p>(path: &'p str, prefix: &str) -> &'p str {
+    path.strip_prefix(prefix).unwrap_or(path)
+}
+
+// The added and removed lines of a patch, with whether they are added
+fn changed_lines<'p>(patch: &'p Patch) -> impl Iterator<Item = (bool, &'p str)> {
+    patch
+        .hunks
+        .iter()
+        .flat_map(|hunk| &hunk.lines)
+        .filter_map(|line| match line {
+            Line::Add(text) => Some((true, &**text)),
+            Line::Remove(text) => Some((false, &**text)),
+            Line::Context(_) => None,
+        })
+}
+
+// The runs of changed lines of a patch, in order
+fn changes<'p>(patch: &'p Patch) -> Vec<Change<'p>> {
+    let mut changes: Vec<Change> = Vec::new();
+    for (i, hunk) in patch.hunks.iter().enumerate() {
+        let last_hunk = i + 1 == patch.hunks.len();
+        let last_old = hunk
+            .lines
+            .iter()
+            .rposition(|line| !matches!(line, Line::Add(_)));
+        let last_new = hunk
+            .lines
+            .iter()
+            .rposition(|line| !matches!(line, Line::Remove(_)));
+        // An empty range starts at the line before the hunk
+        let mut offset = match hunk.old_range.count {
+            0 => hunk.old_range.start,
+            _ => hunk.old_range.start.saturating_sub(1),
+        };
+        // Whether the last change is still going on
+        let mut in_change = false;
+        for (j, line) in hunk.lines.iter().enumerate() {
+            if matches!(line, Line::Context(_)) {
+                in_change = false;
+                offset += 1;
+                continue;
+            }
+            // Changes next to each other in different hunks are the same run
+            let continued = changes
+                .last()
+                .is_some_and(|change| change.offset + change.removed.len() as u64 == offset);
+            if !in_change && !continued {
+                changes.push(Change {
+                    offset,
+                    ..Change::default()
+                });
+            }
+            in_change = true;
+            let change = changes.last_mut().unwrap();
+            match line {
+                Line::Remove(text) => {
+                    change.removed.push(text);
+                    change.old_missing_newline =
+                        last_hunk && last_old == Some(j) && !patch.old_end_newline;
+                    offset += 1;
+                }
+                Line::Add(text) => {
+                    change.added.push(text);
+                    change.new_missing_newline =
+                        last_hunk && last_new == Some(j) && !patch.new_end_newline;
+                }
+                Line::Context(_) => unreachable!(),
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::FormatOptions;
+
+    const SAMPLE: &str = "\
+--- a/notes.txt\t2024-01-01 10:00:00 +0000
++++ b/notes.txt\t2024-01-02 10:00:00 +0000
+@@ -1,7 +1,7 @@ Notes
+ one
+-two
++TWO
+ three
+ four
+ five
+-six
++SIX
+ seven
+\\ No newline at end of file
+";
+
+    fn parse(text: &str) -> Patch<'_> {
+        Patch::from_single(text).unwrap()
+    }
+
+    #[test]
+    fn test_semantic_eq() {
+        let patch = parse(SAMPLE);
+        assert!(patch.semantic_eq(&patch));
+        for context in [0, 1, 2] {
+            let options = FormatOptions {
+                context: Some(context),
+                timestamps: false,
+                ..FormatOptions::default()
+            };
+            let text = format!("{}\n", patch.to_string_with(&options));
+            let other = parse(&text);
+            assert!(patch.semantic_eq(&other), "{}", text);
+            assert!(other.semantic_eq(&patch), "{}", text);
+        }
+
+        let unprefixed = SAMPLE
+            .replace("a/notes", "notes")
+            .replace("b/notes", "notes");
+        assert!(patch.semantic_eq(&parse(&unprefixed)));
+
+        let other_change = SAMPLE.replace("+SIX", "+Six");
+        assert!(!patch.semantic_eq(&parse(&other_change)));
+        let other_place = SAMPLE.replace("@@ -1,7 +1,7 @@", "@@ -2,7 +2,7 @@");
+        assert!(!patch.semantic_eq(&parse(&other_place)));
+        let other_file = SAMPLE.replace("b/notes.txt", "b/todo.txt");
+        assert!(!patch.semantic_eq(&parse(&other_file)));
+        let newline = SAMPLE.replace("-six\n+SIX\n seven\n", "-six\n+SIX\n-seven\n+seven\n");
+        assert!(!patch.semantic_eq(&parse(&newline)));
+        assert!(!patch.semantic_eq(&patch.reverse()));
+    }
+
+    #[test]
+    fn test_semantic_eq_file_ops() {
+        let create = |mode: &str| {
+            format!(
+                "\
+diff --git a/new.txt b/new.txt
+new file mode {}
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++new
+",
+                mode
+            )
+        };
+        let git = create("100644");
+        let plain = "--- /dev/null\n+++ new.txt\n@@ -0,0 +1 @@\n+new\n";
+        assert!(parse(&git).semantic_eq(&parse(plain)));
+        let executable = create("100755");
+        assert!(!parse(&git).semantic_eq(&parse(&executable)));
+    }
+
+    #[test]
+    fn test_similarity() {
+        let patch = parse(SAMPLE);
+        let half = parse("--- x\n+++ x\n@@ -2 +2 @@\n-two\n+TWO\n");
+        assert_eq!(patch.similarity(&half), 4.0 / 6.0);
+        assert_eq!(half.similarity(&patch), 4.0 / 6.0);
+        let unrelated = parse("--- x\n+++ x\n@@ -2 +2 @@\n-2\n+II\n");
+        assert_eq!(patch.similarity(&unrelated), 0.0);
+        // The lines are counted as many times as they are changed
+        let twice = parse("--- x\n+++ x\n@@ -1,2 +1,2 @@\n-two\n-two\n+TWO\n+TWO\n");
+        assert_eq!(half.similarity(&twice), 4.0 / 6.0);
+        let empty = Patch {
+            hunks: Vec::new(),
+            ..half.clone()
+        };
+        assert_eq!(empty.similarity(&empty), 1.0);
+        assert_eq!(empty.similarity(&half), 0.0);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::applier::{
+    ApplyError, ApplyOptions, FindReplaceOptions, FindReplaceResult, PreparedHunk, Whitespace,
+    apply_prepared_str, block_hash, find_replace_prepared, prepare_hunks,
+};
+use crate::ast::Patch;
+
+/// A patch prepared to be applied to many contents, like the instances of a template
+///
+/// Applying a patch first gathers the lines every hunk expects and produces, and hashes them to
+/// search for the hunks. A compiled patch does all of that once, so applying it again only has
+/// to go through the content. It gives the same results as applying the patch itself.
+///
+/// # Example
+///
+/// ```
+/// use patch::{CompiledPatch, Patch};
+///
+/// let patch = Patch::from_single(
+///     "\
+/// --- config.toml
+/// +++ config.toml
+/// @@ -1,2 +1,2 @@
+///  [server]
+/// -debug = true
+/// +debug = false
+/// ",
+/// )
+/// .unwrap();
+///
+/// let compiled = CompiledPatch::new(&patch);
+/// for port in [80, 8080] {
+///     let content = format!("[server]\ndebug = true\nport = {}\n", port);
+///     let patched = compiled.apply(&content).unwrap();
+///     assert_eq!(patched, format!("[server]\ndebug = false\nport = {}\n", port));
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CompiledPatch<'p> {
+    patch: &'p Patch<'p>,
+    hunks: Vec<PreparedHunk<'p, str>>,
+    // The hashes of the old lines of every hunk, for every whitespace option they can be found
+    // by hash with
+    block_hashes: Vec<[u64; 3]>,
+}
+
+impl<'p> CompiledPatch<'p> {
+    /// Prepare a patch to be applied many times
+    pub fn new(patch: &'p Patch<'p>) -> Self {
+        let hunks = prepare_hunks(patch);
+        let block_hashes = hunks
+            .iter()
+            .map(|hunk| {
+                HASHED.map(|whitespace| {
+                    block_hash(&hunk.old_lines, whitespace)
+                        .expect("bug: blocks are hashed for these whitespace options")
+                })
+            })
+            .collect();
+        CompiledPatch {
+            patch,
+            hunks,
+            block_hashes,
+        }
+    }
+
+    /// The patch that was compiled
+    pub fn patch(&self) -> &'p Patch<'p> {
+        self.patch
+    }
+
+    /// Apply the patch to the given text content, like [`apply`](crate::apply)
+    pub fn apply(&self, content: &str) -> Result<String, ApplyError> {
+        apply_prepared_str(self.patch, &self.hunks, content, None)
+    }
+
+    /// Apply the patch to the given text content with the given options, like
+    /// [`apply_with_options`](crate::apply_with_options)
+    pub fn apply_with_options(
+        &self,
+        content: &str,
+        options: &ApplyOptions,
+    ) -> Result<String, ApplyError> {
+        apply_prepared_str(self.patch, &self.hunks, content, Some(options))
+    }
+
+    /// Find and replace the hunks of the patch in the given text content, like
+    /// [`find_replace_apply_with_options`](crate::find_replace_apply_with_options)
+    pub fn find_replace_apply(
+        &self,
+        content: &str,
+        options: &FindReplaceOptions,
+    ) -> Result<FindReplaceResult, ApplyError> {
+        let index = HASHED
+            .iter()
+            .position(|&whitespace| whitespace == options.ignore_whitespace);
+        let block_hashes: Vec<_> = self
+            .block_hashes
+            .iter()
+            .map(|hashes| index.map(|index| hashes[index]))
+            .collect();
+        find_replace_prepared(self.patch, &self.hunks, &block_hashes, content, options)
+    }
+}
+
+// The whitespace options blocks of lines can be found by hash with
+const HASHED: [Whitespace; 3] = [Whitespace::Exact, Whitespace::Trailing, Whitespace::All];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::applier::{apply, apply_with_options, find_replace_apply_with_options};
+
+    #[test]
+    fn test_compiled_patch() {
+        let patch = Patch::from_single(
+            "\
+--- a.txt
++++ a.txt
+@@ -2,3 +2,3 @@
+ b
+-c
++C
+ d
+@@ -8,2 +8,3 @@
+ h
++x
+ i
+",
+        )
+        .unwrap();
+        let compiled = CompiledPatch::new(&patch);
+        assert_eq!(compiled.patch(), &patch);
+
+        let contents = [
+            "a\nb\nc\nd\ne\nf\ng\nh\ni\n",
+            "b\nc\nd\ne\nf\ng\nh\ni",
+            "z\nz\nb \nc\nd\ne\nf\ng\nh\ni\n",
+            "a\nb\nx\nd\n",
+        ];
+        let options = ApplyOptions {
+            fuzz: 1,
+            ..ApplyOptions::default()
+        };
+        for content in contents {
+            assert_eq!(
+                compiled.apply(content).ok(),
+                apply(&patch, content).ok(),
+                "{:?}",
+                content
+            );
+            assert_eq!(
+                compiled.apply_with_options(content, &options).ok(),
+                apply_with_options(&patch, content, &options).ok(),
+                "{:?}",
+                content
+            );
+            for whitespace in [
+                Whitespace::Exact,
+                Whitespace::Trailing,
+                Whitespace::BlankLines,
+            ] {
+                let options = FindReplaceOptions {
+                    ignore_whitespace: whitespace,
+                    ..FindReplaceOptions::default()
+                };
+                assert_eq!(
+                    compiled.find_replace_apply(content, &options).ok(),
+                    find_replace_apply_with_options(&patch, content, &options).ok(),
+                    "{:?} {:?}",
+                    content,
+                    whitespace
+                );
+            }
+        }
+        assert_eq!(
+            compiled.apply(contents[0]).unwrap(),
+            "a\nb\nC\nd\ne\nf\ng\nh\nx\ni\n"
+        );
+    }
+}
@@ -0,0 +1,728 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset, Utc};
+#[cfg(feature = "ignore")]
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::ast::{DEV_NULL, FileMetadata, FileOp, GitHeader, Patch};
+use crate::fs::FsError;
+use crate::generator::{DiffAlgorithm, DiffOptions, Edit, diff_with_options, edit_script};
+use crate::patch_set::PatchSet;
+
+/// Options controlling how [`diff_dirs`] compares two directories
+///
+/// # Example
+///
+/// ```
+/// use patch::{DiffOptions, DirDiffOptions};
+///
+/// let options = DirDiffOptions {
+///     diff: DiffOptions {
+///         context: 1,
+///         ..DiffOptions::default()
+///     },
+///     max_file_size: Some(1 << 20),
+///     ..DirDiffOptions::default()
+/// };
+/// assert!(!options.follow_symlinks);
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DirDiffOptions {
+    /// How the patch of every changed file is generated
+    pub diff: DiffOptions,
+    /// Compare the files and directories symbolic links point to, instead of leaving the links
+    /// out
+    ///
+    /// Every directory is only walked once, so links pointing back up the tree don't make the
+    /// walk go on forever.
+    pub follow_symlinks: bool,
+    /// Leave out files larger than this many bytes on either side
+    pub max_file_size: Option<u64>,
+    /// Leave out the files and directories ignored by the `.gitignore` files of each tree, along
+    /// with `.git` directories, like git does
+    ///
+    /// The patterns of a `.gitignore` file apply to the directory it is in and the ones below
+    /// it, with the patterns of deeper files taking precedence. The `.git/info/exclude` file at
+    /// the root of each tree and the file of git's `core.excludesFile` setting apply everywhere,
+    /// below every `.gitignore` file.
+    ///
+    /// Needs the `ignore` feature, without which [`diff_dirs`] fails when this is set.
+    pub gitignore: bool,
+    /// Patterns of more files and directories to leave out, like `target/` or `*.swp`
+    ///
+    /// The patterns have the syntax of `.gitignore` files and are relative to the roots of both
+    /// trees, and the patterns of git's ignore files take precedence over them.
+    ///
+    /// Needs the `ignore` feature, without which [`diff_dirs`] fails when there are any.
+    pub ignore: Vec<String>,
+    /// Detect renamed files, as a removed file and an added file at least this similar in
+    /// percent, like `git diff -M<n>%`
+    ///
+    /// Renames are written with git's `rename from` and `rename to` header lines, instead of a
+    /// patch deleting the file and one creating it. A similarity of 50 is what git uses by
+    /// default.
+    pub renames: Option<u8>,
+    /// Detect copied files, as an added file at least this similar in percent to a file that
+    /// exists on both sides, like `git diff -C<n>%`
+    ///
+    /// Copies are written with git's `copy from` and `copy to` header lines, instead of a patch
+    /// creating the file. An added file is only a copy if it isn't a rename.
+    pub copies: Option<u8>,
+    /// Write the time every file was last modified after its path in the file headers, in UTC,
+    /// like `diff -ru` does
+    pub timestamps: bool,
+}
+
+/// Compare two directory trees, producing a patch for every file that was added, removed or
+/// modified, like `diff -ruN` does
+///
+/// The patches are sorted by the path of their file, which is relative to the roots and
+/// separated by `/`. Like git, the old files are named with an `a/` prefix and the new files
+/// with a `b/` prefix, so the patches apply to the old directory with
+/// [`ApplyOptions::strip`](crate::ApplyOptions::strip) set to 1. Added files are created from
+/// `/dev/null` and removed files deleted to it, unless [`DirDiffOptions::renames`] or
+/// [`DirDiffOptions::copies`] finds the file they were renamed or copied from. Files with the
+/// same content on both sides get no patch.
+///
+/// Files that aren't text, names that aren't valid UTF-8, and files over
+/// [`DirDiffOptions::max_file_size`] are left out of the diff.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use patch::{DirDiffOptions, diff_dirs};
+///
+/// let patches = diff_dirs(Path::new("before"), Path::new("after"), &DirDiffOptions::default())
+///     .unwrap();
+/// for patch in &patches {
+///     println!("{}", patch);
+/// }
+/// ```
+pub fn diff_dirs(
+    old_root: &Path,
+    new_root: &Path,
+    options: &DirDiffOptions,
+) -> Result<PatchSet<'static>, FsError> {
+    let old_files = walk(old_root, options)?;
+    let new_files = walk(new_root, options)?;
+    let paths: BTreeSet<&String> = old_files.keys().chain(new_files.keys()).collect();
+
+    // The text of every file on both sides, `None` where it doesn't exist
+    let mut files = Vec::new();
+    'paths: for path in paths {
+        let mut texts = [None, None];
+        for (text, files) in texts.iter_mut().zip([&old_files, &new_files]) {
+            if let Some(full_path) = files.get(path) {
+                match read_text(full_path, options.max_file_size)? {
+                    Some(content) => *text = Some(content),
+                    None => continue 'paths,
+                }
+            }
+        }
+        let [old, new] = texts;
+        files.push((path.as_str(), old, new));
+    }
+
+    // The paths and texts of the files that were only on one side, or on both
+    let side = |keep: fn(bool, bool) -> bool| -> Vec<(&str, &str)> {
+        files
+            .iter()
+            .filter(|(_, old, new)| keep(old.is_some(), new.is_some()))
+            .map(|(path, old, new)| (*path, old.as_deref().or(new.as_deref()).unwrap()))
+            .collect()
+    };
+    let added = side(|old, new| !old && new);
+    let deleted = side(|old, new| old && !new);
+    let kept = side(|old, new| old && new);
+
+    // The file every renamed or copied file comes from, with its text, whether it was renamed,
+    // and how similar the files are. The most similar files are paired up first, and every
+    // removed file is only renamed once.
+    let mut sources = HashMap::new();
+    let mut renamed = HashSet::new();
+    if let Some(min) = options.renames {
+        for (path, source, similarity) in similar_pairs(&added, &deleted, min) {
+            if !sources.contains_key(path) && renamed.insert(source.0) {
+                sources.insert(path, (source, true, similarity));
+            }
+        }
+    }
+    if let Some(min) = options.copies {
+        for (path, source, similarity) in similar_pairs(&added, &kept, min) {
+            sources.entry(path).or_insert((source, false, similarity));
+        }
+    }
+
+    let mut patches = Vec::new();
+    for (path, old, new) in &files {
+        let (old, new) = (old.as_deref(), new.as_deref());
+        let mut patch = match (old, new) {
+            (Some(old), Some(new)) if old != new => {
+                file_patch(Some((path, old)), Some((path, new)), &options.diff)
+            }
+            (Some(_), Some(_)) => continue,
+            (Some(_), None) if renamed.contains(path) => continue,
+            (Some(old), None) => file_patch(Some((path, old)), None, &options.diff),
+            (None, Some(new)) => match sources.get(path) {
+                Some(&((from, old), rename, similarity)) => {
+                    let mut patch = file_patch(Some((from, old)), Some((path, new)), &options.diff);
+                    let (from, to) = (Cow::Owned(from.to_string()), Cow::Owned(path.to_string()));
+                    patch.operation = match rename {
+                        true => FileOp::Rename { from, to },
+                        false => FileOp::Copy { from, to },
+                    };
+                    patch.git = Some(GitHeader {
+                        old_path: patch.old.path.clone(),
+                        new_path: patch.new.path.clone(),
+                        old_mode: None,
+                        new_mode: None,
+                        similarity: Some(similarity),
+                        dissimilarity: None,
+                        index: None,
+                    });
+                    patch
+                }
+                None => file_patch(None, Some((path, new)), &options.diff),
+            },
+            (None, None) => unreachable!("bug: every file exists on one side"),
+        };
+        if options.timestamps {
+            for (file, prefix, files) in [
+                (&mut patch.old, "a/", &old_files),
+                (&mut patch.new, "b/", &new_files),
+            ] {
+                let full_path = file
+                    .path
+                    .strip_prefix(prefix)
+                    .and_then(|path| files.get(path));
+                if let Some(full_path) = full_path {
+                    file.meta = Some(FileMetadata::DateTime(modified(full_path)?));
+                }
+            }
+        }
+        patches.push(patch);
+    }
+    Ok(PatchSet { patches })
+}
+
+// The patch turning the text of the old file into the text of the new one, given with their
+// paths and `None` where the file doesn't exist
+fn file_patch(
+    old: Option<(&str, &str)>,
+    new: Option<(&str, &str)>,
+    options: &DiffOptions,
+) -> Patch<'static> {
+    let mut patch = diff_with_options(
+        old.map_or("", |(_, text)| text),
+        new.map_or("", |(_, text)| text),
+        options,
+    );
+    let name = |prefix: &str, file: Option<(&str, &str)>| match file {
+        Some((path, _)) => Cow::Owned(format!("{}{}", prefix, path)),
+        None => Cow::Borrowed(DEV_NULL),
+    };
+    patch.old.path = name("a/", old);
+    patch.new.path = name("b/", new);
+    patch.into_owned()
+}
+
+// Every added file paired with every candidate source at least `min` percent similar to it,
+// all given with their paths and texts, from the most similar pair to the least
+fn similar_pairs<'f>(
+    added: &[(&'f str, &'f str)],
+    candidates: &[(&'f str, &'f str)],
+    min: u8,
+) -> Vec<(&'f str, (&'f str, &'f str), u8)> {
+    let mut pairs = Vec::new();
+    for &(path, text) in added {
+        for &candidate in candidates {
+            let similarity = similarity(candidate.1, text);
+            if similarity >= min {
+                pairs.push((path, candidate, similarity));
+            }
+        }
+    }
+    // The sort is stable, so ties are broken by the order of the paths
+    pairs.sort_by_key(|&(_, _, similarity)| Reverse(similarity));
+    pairs
+}
+
+// How similar two texts are in percent, as the share of the larger one made of the lines both
+// have. Empty files are never similar, since there is nothing to compare.
+fn similarity(old: &str, new: &str) -> u8 {
+    if old.is_empty() || new.is_empty() {
+        return 0;
+    }
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let common: usize = edit_script(&old_lines, &new_lines, DiffAlgorithm::Myers)
+        .into_iter()
+        .filter_map(|edit| match edit {
+            Edit::Equal(i, _) => Some(old_lines[i].len()),
+            _ => None,
+        })
+        .sum();
+    (common * 100 / old.len().max(new.len())) as u8
+}
+
+// Every file in a directory tree that isn't left out, by its path relative to the root
+fn walk(root: &Path, options: &DirDiffOptions) -> Result<BTreeMap<String, PathBuf>, FsError> {
+    #[cfg(not(feature = "ignore"))]
+    if options.gitignore || !options.ignore.is_empty() {
+        return Err(FsError::Io {
+            path: root.to_path_buf(),
+            error: io::Error::new(
+                io::ErrorKind::Unsupported,
+                "ignoring files needs the `ignore` feature",
+            ),
+        });
+    }
+    let mut walk = Walk {
+        options,
+        visited: HashSet::new(),
+        #[cfg(feature = "ignore")]
+        ignores: root_ignores(root, options)?,
+        files: BTreeMap::new(),
+    };
+    walk.dir(root, "")?;
+    Ok(walk.files)
+}
+
+/// The state of a walk through a directory tree
+struct Walk<'o> {
+    options: &'o DirDiffOptions,
+    /// The directories walked so far, when following symbolic links
+    visited: HashSet<PathBuf>,
+    /// The ignore files that apply in the current directory, from the lowest precedence to the
+    /// highest
+    #[cfg(feature = "ignore")]
+    ignores: Vec<Gitignore>,
+    /// The files found so far, by their path relative to the root
+    files: BTreeMap<String, PathBuf>,
+}
+
+impl Walk<'_> {
+    // Add the files of a directory, whose path relative to the root is `prefix`
+    fn dir(&mut self, dir: &Path, prefix: &str) -> Result<(), FsError> {
+        let io_error = |path: &Path| {
+            let path = path.to_path_buf();
+            move |error| FsError::Io { path, error }
+        };
+        if self.options.follow_symlinks {
+            let canonical = fs::canonicalize(dir).map_err(io_error(dir))?;
+            if !self.visited.insert(canonical) {
+                return Ok(());
+            }
+        }
+
+        // The patterns of a `.gitignore` file only apply below its directory
+        #[cfg(feature = "ignore")]
+        let outer_ignores = self.ignores.len();
+        #[cfg(feature = "ignore")]
+        if self.options.gitignore {
+            self.ignores
+                .push(ignore_file(dir, &dir.join(".gitignore"))?);
+        }
+
+        for entry in fs::read_dir(dir).map_err(io_error(dir))? {
+            let entry = entry.map_err(io_error(dir))?;
+            let path = entry.path();
+            let file_name = entry.file_name();
+            let Some(name) = file_name.to_str() else {
+                continue;
+            };
+            let mut file_type = entry.file_type().map_err(io_error(&path))?;
+            if file_type.is_symlink() {
+                if !self.options.follow_symlinks {
+                    continue;
+                }
+                file_type = match fs::metadata(&path) {
+                    Ok(metadata) => metadata.file_type(),
+                    // Links pointing nowhere are left out like any other link
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                    Err(error) => return Err(FsError::Io { path, error }),
+                };
+            }
+
+            let relative = format!("{}{}", prefix, name);
+            let is_dir = file_type.is_dir();
+            if self.ignored(&path, is_dir) || (self.options.gitignore && is_dir && name == ".git") {
+                continue;
+            }
+            if is_dir {
+                self.dir(&path, &format!("{}/", relative))?;
+            } else if file_type.is_file() {
+                self.files.insert(relative, path);
+            }
+        }
+
+        #[cfg(feature = "ignore")]
+        self.ignores.truncate(outer_ignores);
+        Ok(())
+    }
+
+    // Whether the ignore files that apply in the current directory leave out a path, going by
+    // the last pattern matching it in the file with the highest precedence that has one
+    #[cfg(feature = "ignore")]
+    fn ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.ignores
+            .iter()
+            .rev()
+            .map(|ignore| ignore.matched(path, is_dir))
+            .find(|matched| !matched.is_none())
+            .is_some_and(|matched| matched.is_ignore())
+    }
+
+    #[cfg(not(feature = "ignore"))]
+    fn ignored(&self, _path: &Path, _is_dir: bool) -> bool {
+        false
+    }
+}
+
+// The ignore files that apply in the whole tree: the patterns of `DirDiffOptions::ignore`, then
+// with `DirDiffOptions::gitignore` git's `core.excludesFile` and `.git/info/exclude`
+#[cfg(feature = "ignore")]
+fn root_ignores(root: &Path, options: &DirDiffOptions) -> Result<Vec<Gitignore>, FsError> {
+    let pattern_error = |error: ignore::Error| FsError::Io {
+        path: root.to_path_buf(),
+        error: io::Error::new(io::ErrorKind::InvalidInput, error),
+    };
+    let mut builder = GitignoreBuilder::new(root);
+    for pattern in &options.ignore {
+        builder.add_line(None, pattern).map_err(pattern_error)?;
+    }
+    let mut ignores = vec![builder.build().map_err(pattern_error)?];
+    if options.gitignore {
+        // The global file belongs to the user rather than the trees, so like git, problems
+        // reading it leave it out instead of failing the diff
+        ignores.push(GitignoreBuilder::new(root).build_global().0);
+        ignores.push(ignore_file(root, &root.join(".git/info/exclude"))?);
+    }
+    Ok(ignores)
+}
+
+// The patterns of an ignore file applying below `dir`, which are none if the file doesn't
+// exist. Lines that aren't valid patterns are left out like git does.
+#[cfg(feature = "ignore")]
+fn ignore_file(dir: &Path, path: &Path) -> Result<Gitignore, FsError> {
+    let io_error = |error| FsError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(error)
+            if matches!(
+                error.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+            ) =>
+        {
+            return Ok(Gitignore::empty());
+        }
+        Err(error) => return Err(io_error(error)),
+    };
+    let mut builder = GitignoreBuilder::new(dir);
+    for line in content.lines() {
+        let _ = builder.add_line(Some(path.to_path_buf()), line);
+    }
+    builder
+        .build()
+        .map_err(|error| io_error(io::Error::new(io::ErrorKind::InvalidData, error)))
+}
+
+// The time a file was last modified, in UTC
+fn modified(path: &Path) -> Result<DateTime<FixedOffset>, FsError> {
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|error| FsError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+    Ok(DateTime::<Utc>::from(modified).fixed_offset())
+}
+
+// The content of a file, or None if it is too large or isn't text
+fn read_text(path: &Path, max_size: Option<u64>) -> Result<Option<String>, FsError> {
+    let io_error = |error| FsError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+    if let Some(max_size) = max_size
+        && fs::metadata(path).map_err(io_error)?.len() > max_size
+    {
+        return Ok(None);
+    }
+    let content = fs::read(path).map_err(io_error)?;
+    Ok(String::from_utf8(content).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, SystemTime};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::applier::ApplyOptions;
+    use crate::fs::apply_to_dir;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_diff_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        write(&old.join("same.txt"), "same\n");
+        write(&new.join("same.txt"), "same\n");
+        write(&old.join("src/lib.rs"), "one\ntwo\n");
+        write(&new.join("src/lib.rs"), "one\n2\n");
+        write(&old.join("removed.txt"), "bye\n");
+        write(&new.join("src/added/new.md"), "# New\n");
+        write(&old.join("binary"), "\u{0}");
+        fs::write(new.join("binary"), [0xff, 0xfe]).unwrap();
+
+        let patches = diff_dirs(&old, &new, &DirDiffOptions::default()).unwrap();
+        let headers: Vec<(&str, &str)> = patches
+            .patches
+            .iter()
+            .map(|patch| (patch.old.path.as_ref(), patch.new.path.as_ref()))
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                ("a/removed.txt", "/dev/null"),
+                ("/dev/null", "b/src/added/new.md"),
+                ("a/src/lib.rs", "b/src/lib.rs"),
+            ]
+        );
+        assert_eq!(
+            patches.patches[0].to_string(),
+            "--- a/removed.txt\n+++ /dev/null\n@@ -1,1 +0,0 @@\n-bye"
+        );
+
+        // The patches turn the old directory into the new one
+        let options = ApplyOptions {
+            strip: 1,
+            ..ApplyOptions::default()
+        };
+        apply_to_dir(&patches, &old, &options).unwrap();
+        fs::remove_file(old.join("binary")).unwrap();
+        fs::remove_file(new.join("binary")).unwrap();
+        let patches = diff_dirs(&old, &new, &DirDiffOptions::default()).unwrap();
+        assert_eq!(patches.patches, []);
+    }
+
+    #[test]
+    fn test_diff_dirs_timestamps() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        write(&old.join("a.txt"), "one\n");
+        write(&new.join("a.txt"), "two\n");
+        write(&new.join("b.txt"), "new\n");
+        let time = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 5);
+        for path in [old.join("a.txt"), new.join("b.txt")] {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        }
+
+        let options = DirDiffOptions {
+            timestamps: true,
+            ..DirDiffOptions::default()
+        };
+        let patches = diff_dirs(&old, &new, &options).unwrap();
+        let text = patches.to_string();
+        assert!(
+            text.starts_with("--- a/a.txt\t2023-11-14 22:13:20.000000005 +0000\n+++ b/a.txt\t"),
+            "{}",
+            text
+        );
+        assert!(text.contains("--- /dev/null\n+++ b/b.txt\t2023-11-14 22:13:20.000000005 +0000\n"));
+        assert!(patches.patches[0].new.timestamp().is_some());
+        let text = format!("{}\n", text);
+        assert_eq!(PatchSet::parse(&text).unwrap(), patches);
+    }
+
+    #[test]
+    fn test_diff_dirs_renames_and_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        let text = "one\ntwo\nthree\nfour\n";
+        write(&old.join("moved.txt"), text);
+        write(&new.join("dir/moved.txt"), text);
+        write(
+            &old.join("edited.txt"),
+            "one\ntwo\nthree\nfour\nfive\nsix\n",
+        );
+        write(&new.join("renamed.txt"), "one\ntwo\nthree\nfour\nfive\n6\n");
+        write(&old.join("kept.txt"), "kept\nlines\n");
+        write(&new.join("kept.txt"), "kept\nlines\n");
+        write(&new.join("copy.txt"), "kept\nlines\n");
+        write(&old.join("gone.txt"), "a\nb\n");
+        write(&new.join("other.txt"), "c\nd\n");
+
+        let options = DirDiffOptions {
+            renames: Some(50),
+            copies: Some(50),
+            ..DirDiffOptions::default()
+        };
+        let patches = diff_dirs(&old, &new, &options).unwrap();
+        let text: Vec<String> = patches.patches.iter().map(Patch::to_string).collect();
+        assert_eq!(
+            text,
+            [
+                "\
+diff --git a/kept.txt b/copy.txt
+similarity index 100%
+copy from kept.txt
+copy to copy.txt",
+                "\
+diff --git a/moved.txt b/dir/moved.txt
+similarity index 100%
+rename from moved.txt
+rename to dir/moved.txt",
+                "--- a/gone.txt\n+++ /dev/null\n@@ -1,2 +0,0 @@\n-a\n-b",
+                "--- /dev/null\n+++ b/other.txt\n@@ -0,0 +1,2 @@\n+c\n+d",
+                "\
+diff --git a/edited.txt b/renamed.txt
+similarity index 85%
+rename from edited.txt
+rename to renamed.txt
+--- a/edited.txt
++++ b/renamed.txt
+@@ -3,4 +3,4 @@
+ three
+ four
+ five
+-six
++6",
+            ]
+        );
+
+        // Without detection, renames are a deleted and an added file
+        let patches = diff_dirs(&old, &new, &DirDiffOptions::default()).unwrap();
+        assert_eq!(patches.patches.len(), 7);
+    }
+
+    #[cfg(feature = "ignore")]
+    #[test]
+    fn test_diff_dirs_ignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        for root in [&old, &new] {
+            write(&root.join(".gitignore"), "target/\n*.swp\n");
+            write(&root.join("src/.gitignore"), "!keep.swp\n/generated.rs\n");
+            write(&root.join(".git/info/exclude"), "*.tmp\nsrc/keep.swp\n");
+        }
+        for path in [
+            "target/debug/out.txt",
+            ".git/HEAD",
+            "src/.lib.rs.swp",
+            "src/keep.swp",
+            "src/generated.rs",
+            "src/nested/generated.rs",
+            "build.log",
+            "notes.tmp",
+        ] {
+            write(&new.join(path), "new\n");
+        }
+
+        let options = DirDiffOptions {
+            gitignore: true,
+            ignore: vec!["*.log".to_string()],
+            ..DirDiffOptions::default()
+        };
+        let patches = diff_dirs(&old, &new, &options).unwrap();
+        let paths: Vec<&str> = patches
+            .patches
+            .iter()
+            .map(|p| p.new.path.as_ref())
+            .collect();
+        assert_eq!(paths, ["b/src/keep.swp", "b/src/nested/generated.rs"]);
+
+        let patches = diff_dirs(&old, &new, &DirDiffOptions::default()).unwrap();
+        assert_eq!(patches.patches.len(), 8);
+    }
+
+    #[cfg(not(feature = "ignore"))]
+    #[test]
+    fn test_diff_dirs_ignore_unsupported() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = DirDiffOptions {
+            ignore: vec!["*.log".to_string()],
+            ..DirDiffOptions::default()
+        };
+        let error = diff_dirs(dir.path(), dir.path(), &options).unwrap_err();
+        assert!(matches!(
+            error,
+            FsError::Io { error, .. } if error.kind() == io::ErrorKind::Unsupported
+        ));
+    }
+
+    #[test]
+    fn test_diff_dirs_max_file_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        write(&old.join("small.txt"), "a\n");
+        write(&new.join("small.txt"), "b\n");
+        write(&old.join("large.txt"), "a\n");
+        write(&new.join("large.txt"), "a very long line\n");
+
+        let options = DirDiffOptions {
+            max_file_size: Some(10),
+            ..DirDiffOptions::default()
+        };
+        let patches = diff_dirs(&old, &new, &options).unwrap();
+        assert_eq!(patches.patches.len(), 1);
+        assert_eq!(patches.patches[0].new.path, "b/small.txt");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_diff_dirs_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        fs::create_dir(&old).unwrap();
+        write(&dir.path().join("target/file.txt"), "linked\n");
+        write(&new.join("real.txt"), "real\n");
+        symlink(dir.path().join("target"), new.join("link")).unwrap();
+        // A link back up the tree
+        symlink(&new, new.join("loop")).unwrap();
+
+        let patches = diff_dirs(&old, &new, &DirDiffOptions::default()).unwrap();
+        let paths: Vec<&str> = patches
+            .patches
+            .iter()
+            .map(|p| p.new.path.as_ref())
+            .collect();
+        assert_eq!(paths, ["b/real.txt"]);
+
+        let options = DirDiffOptions {
+            follow_symlinks: true,
+            ..DirDiffOptions::default()
+        };
+        let patches = diff_dirs(&old, &new, &options).unwrap();
+        let paths: Vec<&str> = patches
+            .patches
+            .iter()
+            .map(|p| p.new.path.as_ref())
+            .collect();
+        assert_eq!(paths, ["b/link/file.txt", "b/real.txt"]);
+    }
+}
@@ -0,0 +1,240 @@
+use std::iter;
+use std::ops::Range;
+
+use crate::applier::{ApplyError, apply, range_index};
+use crate::ast::{Line, Patch};
+
+/// A replacement of a range of bytes of some content, see [`text_edits`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct TextEdit {
+    /// The byte offset in the original content of the start of the replaced text
+    pub start: usize,
+    /// The byte offset in the original content of the end of the replaced text, which is
+    /// `start` for an insertion
+    pub end: usize,
+    /// The text that replaces the range, which is empty for a deletion
+    pub replacement: String,
+}
+
+/// Turn a patch into the edits it makes to the given content, as replacements of byte ranges
+///
+/// The edits are sorted, don't overlap and all refer to offsets in the original content, like
+/// the `TextEdit`s of the Language Server Protocol, so an editor or a rope can apply them in
+/// place instead of replacing the whole document. Applying them from the last to the first
+/// gives the same text as [`apply`], with whole lines and their line endings replaced.
+///
+/// # Example
+///
+/// ```
+/// use patch::{Patch, TextEdit, text_edits};
+///
+/// let content = "a\nb\nc\nd\n";
+/// let patch = Patch::from_single(
+///     "\
+/// --- a.txt
+/// +++ a.txt
+/// @@ -1,4 +1,4 @@
+///  a
+/// -b
+/// +B
+///  c
+/// +x
+///  d
+/// ",
+/// )
+/// .unwrap();
+///
+/// let edits = text_edits(&patch, content).unwrap();
+/// assert_eq!(edits[0], TextEdit { start: 2, end: 4, replacement: "B\n".to_string() });
+/// assert_eq!(edits[1], TextEdit { start: 6, end: 6, replacement: "x\n".to_string() });
+///
+/// let mut result = content.to_string();
+/// for edit in edits.iter().rev() {
+///     result.replace_range(edit.start..edit.end, &edit.replacement);
+/// }
+/// assert_eq!(result, "a\nB\nc\nx\nd\n");
+/// ```
+pub fn text_edits(patch: &Patch, content: &str) -> Result<Vec<TextEdit>, ApplyError> {
+    let output = apply(patch, content)?;
+    let old_starts = line_starts(content);
+    let new_starts = line_starts(&output);
+    let bytes = |(old, new): (Range<usize>, Range<usize>)| {
+        (
+            old_starts[old.start]..old_starts[old.end],
+            new_starts[new.start]..new_starts[new.end],
+        )
+    };
+
+    let mut edits: Vec<TextEdit> = Vec::new();
+    // The end of the last change in the old and the new content
+    let (mut old_end, mut new_end) = (0, 0);
+    let end = (content.len()..content.len(), output.len()..output.len());
+    for (mut old, mut new) in changed_lines(patch)
+        .into_iter()
+        .map(bytes)
+        .chain(iter::once(end))
+    {
+        // The text between two changes is the same in both, apart from the line ending of the
+        // last line in it, which may come from an added line or from the end of the file
+        let common = common_prefix(&content[old_end..old.start], &output[new_end..new.start]);
+        old.start = old_end + common;
+        new.start = new_end + common;
+        (old_end, new_end) = (old.end, new.end);
+        if content[old.clone()] == output[new.clone()] {
+            continue;
+        }
+        match edits.last_mut() {
+            Some(last) if last.end == old.start => {
+                last.end = old.end;
+                last.replacement.push_str(&output[new]);
+            }
+            _ => edits.push(TextEdit {
+                start: old.start,
+                end: old.end,
+                replacement: output[new].to_string(),
+            }),
+        }
+    }
+    Ok(edits)
+}
+
+// The runs of removed and added lines of every hunk, as ranges of line indices of the old and
+// the new content, placed where `apply` places them
+fn changed_lines(patch: &Patch) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut changes = Vec::new();
+    let mut current_line = 0;
+    let mut growth: isize = 0;
+    for hunk in &patch.hunks {
+        let mut old = range_index(&hunk.old_range).max(current_line);
+        let mut new = (old as isize + growth) as usize;
+        let mut run = None;
+        for line in &hunk.lines {
+            match line {
+                Line::Context(_) => {
+                    if let Some((old_start, new_start)) = run.take() {
+                        changes.push((old_start..old, new_start..new));
+                    }
+                    old += 1;
+                    new += 1;
+                }
+                Line::Remove(_) => {
+                    run.get_or_insert((old, new));
+                    old += 1;
+                }
+                Line::Add(_) => {
+                    run.get_or_insert((old, new));
+                    new += 1;
+                }
+            }
+        }
+        if let Some((old_start, new_start)) = run {
+            changes.push((old_start..old, new_start..new));
+        }
+        growth = new as isize - old as isize;
+        current_line = old;
+    }
+    changes
+}
+
+// The byte offset of the start of every line, and of the end of the text
+fn line_starts(text: &str) -> Vec<usize> {
+    iter::once(0)
+        .chain(text.split_inclusive('\n').scan(0, |offset, line| {
+            *offset += line.len();
+            Some(*offset)
+        }))
+        .collect()
+}
+
+// The length in bytes of the longest common prefix of two strings
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|&((_, x), y)| x != y)
+        .map_or(a.len().min(b.len()), |((i, _), _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn edited(content: &str, edits: &[TextEdit]) -> String {
+        let mut result = content.to_string();
+        for edit in edits.iter().rev() {
+            result.replace_range(edit.start..edit.end, &edit.replacement);
+        }
+        result
+    }
+
+    fn check_edits(patch: &str, content: &str) -> Vec<TextEdit> {
+        let patch = Patch::from_single(patch).unwrap();
+        let edits = text_edits(&patch, content).unwrap();
+        assert_eq!(edited(content, &edits), apply(&patch, content).unwrap());
+        edits
+    }
+
+    fn edit(start: usize, end: usize, replacement: &str) -> TextEdit {
+        TextEdit {
+            start,
+            end,
+            replacement: replacement.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_text_edits() {
+        let patch = "\
+--- a.txt
++++ a.txt
+@@ -1,3 +1,2 @@
+ a
+-b
+-c
+@@ -4,0 +4,1 @@
++x
+@@ -6 +6 @@
+-f
++F
+";
+        assert_eq!(
+            check_edits(patch, "a\nb\nc\nd\ne\nf\n"),
+            [edit(2, 6, ""), edit(8, 8, "x\n"), edit(10, 12, "F\n")]
+        );
+        // Line endings are kept
+        assert_eq!(
+            check_edits(patch, "a\r\nb\r\nc\r\nd\r\ne\r\nf\r\n"),
+            [edit(3, 9, ""), edit(12, 12, "x\r\n"), edit(15, 18, "F\r\n")]
+        );
+    }
+
+    #[test]
+    fn test_text_edits_end_of_file() {
+        // Adding a line after the last one gives it a newline
+        let add = "--- a.txt\n+++ a.txt\n@@ -1 +1,2 @@\n a\n+b\n";
+        assert_eq!(check_edits(add, "a"), [edit(1, 1, "\nb\n")]);
+
+        // Only the newline at the end changes
+        let newline = "\
+--- a.txt
++++ a.txt
+@@ -1,2 +1,2 @@
+ a
+-b
++b
+\\ No newline at end of file
+";
+        assert_eq!(check_edits(newline, "a\nb\n"), [edit(2, 4, "b")]);
+
+        // A patch that changes nothing has no edits
+        let empty = crate::builder::PatchBuilder::new("a.txt", "a.txt").build();
+        assert_eq!(text_edits(&empty, "a\n").unwrap(), []);
+    }
+
+    #[test]
+    fn test_text_edits_failure() {
+        let patch = Patch::from_single("--- a.txt\n+++ a.txt\n@@ -1 +1 @@\n-x\n+y\n").unwrap();
+        assert!(text_edits(&patch, "a\n").is_err());
+    }
+}
@@ -0,0 +1,301 @@
+//! A C interface, for using the crate as a shared library from C, C++ and other languages
+//!
+//! The declarations are in `include/patch.h`. Strings returned by these functions are allocated
+//! by the crate and have to be freed with [`patch_string_free`], and patch sets with
+//! [`patch_set_free`]. They end with a NUL character, so text containing one can't be returned.
+//!
+//! The shared library is built with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+
+use std::ffi::{CString, c_char, c_int};
+use std::ptr;
+use std::slice;
+
+use crate::applier::apply;
+use crate::patch_set::PatchSet;
+
+/// The function succeeded
+pub const PATCH_OK: c_int = 0;
+/// A pointer was null, a string wasn't valid UTF-8, an index was out of bounds or the result
+/// contains a NUL character
+pub const PATCH_INVALID_ARGUMENT: c_int = -1;
+/// The patch couldn't be applied to the text
+pub const PATCH_APPLY_ERROR: c_int = 1;
+
+/// Parse the patches of a file, given as `len` bytes of UTF-8 text, which don't need to end with
+/// a NUL character
+///
+/// The text is copied, so it can be freed once this returns. Returns null if the text can't be
+/// parsed, and then stores the message of the error in `*error` unless `error` is null.
+///
+/// # Safety
+///
+/// `text` must point to `len` readable bytes, and `error` must be null or point to writable
+/// memory for a pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn patch_set_parse(
+    text: *const c_char,
+    len: usize,
+    error: *mut *mut c_char,
+) -> *mut PatchSet<'static> {
+    let Some(text) = (unsafe { text_arg(text, len) }) else {
+        unsafe { set_string(error, "the text is null or not valid UTF-8") };
+        return ptr::null_mut();
+    };
+    match PatchSet::parse(text) {
+        Ok(set) => {
+            let patches = set.into_iter().map(|patch| patch.into_owned()).collect();
+            Box::into_raw(Box::new(PatchSet { patches }))
+        }
+        Err(parse_error) => {
+            unsafe { set_string(error, &parse_error.to_string()) };
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free a patch set returned by [`patch_set_parse`], which does nothing for null
+///
+/// # Safety
+///
+/// `set` must be null or a pointer returned by [`patch_set_parse`] that hasn't been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn patch_set_free(set: *mut PatchSet<'static>) {
+    if !set.is_null() {
+        drop(unsafe { Box::from_raw(set) });
+    }
+}
+
+/// The number of patches in a set, or 0 for null
+///
+/// # Safety
+///
+/// `set` must be null or a valid pointer returned by [`patch_set_parse`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn patch_set_len(set: *const PatchSet<'static>) -> usize {
+    unsafe { set.as_ref() }.map_or(0, |set| set.patches.len())
+}
+
+/// Write the patches in the Unified Format, or return null for null or if the patches contain a
+/// NUL character
+///
+/// # Safety
+///
+/// `set` must be null or a valid pointer returned by [`patch_set_parse`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn patch_set_to_string(set: *const PatchSet<'static>) -> *mut c_char {
+    unsafe { set.as_ref() }
+        .and_then(|set| into_c_string(&set.to_string()))
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Write the patches as JSON in the schema of [`PatchSet::to_json`], or return null for null
+///
+/// # Safety
+///
+/// `set` must be null or a valid pointer returned by [`patch_set_parse`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn patch_set_to_json(set: *const PatchSet<'static>) -> *mut c_char {
+    unsafe { set.as_ref() }
+        .and_then(|set| into_c_string(&set.to_json()))
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Apply the patch at `index` in a set to a file's content, given as `len` bytes of UTF-8 text
+///
+/// On success, returns [`PATCH_OK`] and stores the patched text in `*output`. Otherwise returns
+/// [`PATCH_INVALID_ARGUMENT`] or [`PATCH_APPLY_ERROR`], and stores the message of the error in
+/// `*error` unless `error` is null. Patched text containing a NUL character is an invalid
+/// argument, as it can't be returned.
+///
+/// # Safety
+///
+/// `set` must be a valid pointer returned by [`patch_set_parse`], `text` must point to `len`
+/// readable bytes, `output` must point to writable memory for a pointer, and `error` must be
+/// null or point to writable memory for a pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn patch_set_apply(
+    set: *const PatchSet<'static>,
+    index: usize,
+    text: *const c_char,
+    len: usize,
+    output: *mut *mut c_char,
+    error: *mut *mut c_char,
+) -> c_int {
+    let patch = unsafe { set.as_ref() }.and_then(|set| set.patches.get(index));
+    let text = unsafe { text_arg(text, len) };
+    let (Some(patch), Some(text), false) = (patch, text, output.is_null()) else {
+        unsafe {
+            set_string(
+                error,
+                "a pointer is null, the text is not valid UTF-8 or the index is out of bounds",
+            )
+        };
+        return PATCH_INVALID_ARGUMENT;
+    };
+    match apply(patch, text) {
+        Ok(patched) => match into_c_string(&patched) {
+            Some(patched) => {
+                unsafe { *output = patched };
+                PATCH_OK
+            }
+            None => {
+                unsafe { set_string(error, "the patched text contains a NUL character") };
+                PATCH_INVALID_ARGUMENT
+            }
+        },
+        Err(apply_error) => {
+            unsafe { set_string(error, &apply_error.to_string()) };
+            PATCH_APPLY_ERROR
+        }
+    }
+}
+
+/// Free a string returned by one of these functions, which does nothing for null
+///
+/// # Safety
+///
+/// `string` must be null or a string returned by one of these functions that hasn't been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn patch_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
+
+// The text of a pointer and length, if it is valid UTF-8
+unsafe fn text_arg<'t>(text: *const c_char, len: usize) -> Option<&'t str> {
+    if text.is_null() {
+        return None;
+    }
+    let bytes = unsafe { slice::from_raw_parts(text.cast::<u8>(), len) };
+    std::str::from_utf8(bytes).ok()
+}
+
+// A string as a NUL-terminated C string, unless it contains a NUL character, which C can't tell
+// from the end of the string
+fn into_c_string(s: &str) -> Option<*mut c_char> {
+    CString::new(s).ok().map(CString::into_raw)
+}
+
+// Store an error message for the caller, unless they passed null, leaving out any NUL characters
+// in it
+unsafe fn set_string(target: *mut *mut c_char, s: &str) {
+    if !target.is_null() {
+        let message = into_c_string(&s.replace('\0', ""));
+        unsafe { *target = message.unwrap_or(ptr::null_mut()) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ffi::CStr;
+
+    use pretty_assertions::assert_eq;
+
+    const SAMPLE: &str = "--- a\n+++ b\n@@ -1 +1 @@\n-old\n+new\n";
+
+    // Take a string returned by the functions, freeing it
+    fn take(string: *mut c_char) -> String {
+        assert!(!string.is_null());
+        let s = unsafe { CStr::from_ptr(string) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        unsafe { patch_string_free(string) };
+        s
+    }
+
+    #[test]
+    fn test_parse_and_apply() {
+        let mut error = ptr::null_mut();
+        let set = unsafe { patch_set_parse(SAMPLE.as_ptr().cast(), SAMPLE.len(), &mut error) };
+        assert!(!set.is_null() && error.is_null());
+        assert_eq!(unsafe { patch_set_len(set) }, 1);
+        assert_eq!(
+            take(unsafe { patch_set_to_string(set) }),
+            "--- a\n+++ b\n@@ -1,1 +1,1 @@\n-old\n+new"
+        );
+        assert!(take(unsafe { patch_set_to_json(set) }).starts_with("{\"version\":1,"));
+
+        let mut output = ptr::null_mut();
+        let text = "old\n";
+        let status = unsafe {
+            patch_set_apply(
+                set,
+                0,
+                text.as_ptr().cast(),
+                text.len(),
+                &mut output,
+                &mut error,
+            )
+        };
+        assert_eq!(status, PATCH_OK);
+        assert_eq!(take(output), "new\n");
+
+        let text = "other\n";
+        let status = unsafe {
+            patch_set_apply(
+                set,
+                0,
+                text.as_ptr().cast(),
+                text.len(),
+                &mut output,
+                &mut error,
+            )
+        };
+        assert_eq!(status, PATCH_APPLY_ERROR);
+        assert!(!take(error).is_empty());
+
+        let status = unsafe {
+            patch_set_apply(
+                set,
+                1,
+                text.as_ptr().cast(),
+                text.len(),
+                &mut output,
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(status, PATCH_INVALID_ARGUMENT);
+        unsafe { patch_set_free(set) };
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let mut error = ptr::null_mut();
+        let text = "--- a\n+++ b\n@@ -1 +1 @@\n?\n";
+        let set = unsafe { patch_set_parse(text.as_ptr().cast(), text.len(), &mut error) };
+        assert!(set.is_null());
+        assert!(take(error).starts_with("Line 4"));
+
+        let set = unsafe { patch_set_parse(ptr::null(), 0, ptr::null_mut()) };
+        assert!(set.is_null());
+        assert_eq!(unsafe { patch_set_len(set) }, 0);
+        assert!(unsafe { patch_set_to_string(set) }.is_null());
+        unsafe { patch_set_free(set) };
+    }
+
+    #[test]
+    fn test_apply_output_with_nul() {
+        let set = unsafe { patch_set_parse(SAMPLE.as_ptr().cast(), SAMPLE.len(), ptr::null_mut()) };
+        let (mut output, mut error) = (ptr::null_mut(), ptr::null_mut());
+        let text = "old\na\0b\n";
+        let status = unsafe {
+            patch_set_apply(
+                set,
+                0,
+                text.as_ptr().cast(),
+                text.len(),
+                &mut output,
+                &mut error,
+            )
+        };
+        assert_eq!(status, PATCH_INVALID_ARGUMENT);
+        assert!(output.is_null());
+        assert_eq!(take(error), "the patched text contains a NUL character");
+        unsafe { patch_set_free(set) };
+    }
+}
@@ -0,0 +1,486 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use nom::{
+    Parser,
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, line_ending, not_line_ending},
+    combinator::{all_consuming, map, not, opt},
+    multi::{many0, many1},
+    sequence::{delimited, preceded},
+};
+
+use crate::ast::{FileOp, Hunk, Line, Patch, Range};
+use crate::parser::{
+    Expected, IResult, Input, ParseError, SpanError, consume_content_line, convert_error,
+    header_line_content, no_newline_indicator, u64_digit,
+};
+
+/// What the marker of a line in a section of a context diff hunk says about it
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Mark {
+    /// `  `, the line is in both files
+    Context,
+    /// `! `, the line is replaced by the lines marked the same way in the other section
+    Change,
+    /// `- ` in the old section or `+ ` in the new section, the line is only in this file
+    Only,
+}
+
+// A line of a section, with whether it is followed by `\ No newline at end of file`
+type SectionLine<'a> = (Mark, Cow<'a, str>, bool);
+
+impl<'a> Patch<'a> {
+    #[allow(clippy::tabs_in_doc_comments)]
+    /// Attempt to parse a patch in the context format, like `diff -c` produces
+    ///
+    /// Context diffs show the old and new lines of each hunk in two separate sections, marking
+    /// changed lines with `!`. They are converted to the same hunks as unified diffs, so the
+    /// patch can be applied or written in the Unified Format like any other. Any lines before
+    /// the `***` file header are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::Patch;
+    ///
+    /// let sample = "\
+    /// *** lao	2002-02-21 23:30:39.942229878 -0800
+    /// --- tzu	2002-02-21 23:30:50.442260588 -0800
+    /// ***************
+    /// *** 1,3 ****
+    /// - The Way that can be told of is not the eternal Way;
+    ///   The name that can be named is not the eternal name.
+    /// ! The Nameless is the origin of Heaven and Earth;
+    /// --- 1,2 ----
+    ///   The name that can be named is not the eternal name.
+    /// ! The named is the mother of all things.
+    /// ";
+    ///
+    /// let patch = Patch::from_context(sample).unwrap();
+    /// assert_eq!(
+    ///     patch.to_string().lines().skip(2).collect::<Vec<_>>(),
+    ///     [
+    ///         "@@ -1,3 +1,2 @@",
+    ///         "-The Way that can be told of is not the eternal Way;",
+    ///         " The name that can be named is not the eternal name.",
+    ///         "-The Nameless is the origin of Heaven and Earth;",
+    ///         "+The named is the mother of all things.",
+    ///     ]
+    /// );
+    /// ```
+    pub fn from_context(s: &'a str) -> Result<Self, ParseError<'a>> {
+        let mut parser = all_consuming(patch);
+        let (_, patch) = parser.parse(Input::new(s)).map_err(convert_error)?;
+        Ok(patch)
+    }
+
+    /// Attempt to parse several patches in the context format, like `diff -rc` produces
+    ///
+    /// See [`Patch::from_context`] for details. The `diff` command lines and any other lines
+    /// between the patches are skipped.
+    pub fn from_multiple_context(s: &'a str) -> Result<Vec<Self>, ParseError<'a>> {
+        let mut parser = all_consuming(many1(patch));
+        let (_, patches) = parser.parse(Input::new(s)).map_err(convert_error)?;
+        Ok(patches)
+    }
+}
+
+impl Patch<'_> {
+    /// Write the patch in the context format, like `diff -c` does
+    ///
+    /// Like the `Display` implementation, the text doesn't end with a newline character. The
+    /// `diff --git` header lines and the changes to binary files can't be written in the
+    /// context format and are left out.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::diff;
+    ///
+    /// let patch = diff("a\nb\nc\n", "a\nB\nc\nd\n", 1);
+    /// assert_eq!(
+    ///     patch.to_context_string(),
+    ///     "\
+    /// *** old
+    /// --- new
+    /// ***************
+    /// *** 1,3 ****
+    ///   a
+    /// ! b
+    ///   c
+    /// --- 1,4 ----
+    ///   a
+    /// ! B
+    ///   c
+    /// + d"
+    /// );
+    /// ```
+    pub fn to_context_string(&self) -> String {
+        let mut output = format!("*** {}\n--- {}", self.old, self.new);
+        for (i, hunk) in self.hunks.iter().enumerate() {
+            let (old_end_newline, new_end_newline) = if i + 1 == self.hunks.len() {
+                (self.old_end_newline, self.new_end_newline)
+            } else {
+                (true, true)
+            };
+            write!(
+                output,
+                "\n***************{}\n*** {} ****",
+                hunk.range_hint,
+                context_range(&hunk.old_range)
+            )
+            .unwrap();
+            write_section(&mut output, hunk, Side::Old, old_end_newline);
+            write!(output, "\n--- {} ----", context_range(&hunk.new_range)).unwrap();
+            write_section(&mut output, hunk, Side::New, new_end_newline);
+        }
+        output
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Side {
+    Old,
+    New,
+}
+
+impl Side {
+    fn other(self) -> Side {
+        match self {
+            Side::Old => Side::New,
+            Side::New => Side::Old,
+        }
+    }
+
+    // Whether a line is only in the file of this side
+    fn shows_only(self, line: &Line) -> bool {
+        matches!(
+            (self, line),
+            (Side::Old, Line::Remove(_)) | (Side::New, Line::Add(_))
+        )
+    }
+}
+
+// Write the lines of one side of a hunk, unless that side has no changes
+fn write_section(output: &mut String, hunk: &Hunk, side: Side, end_newline: bool) {
+    let own = |line: &Line| side.shows_only(line);
+    let other = |line: &Line| side.other().shows_only(line);
+    if !hunk.lines.iter().any(own) {
+        return;
+    }
+    let last = hunk.lines.iter().rposition(|line| !other(line));
+
+    // Every run of changes between context lines, as the range of its lines
+    let mut start = 0;
+    while start < hunk.lines.len() {
+        let end = hunk.lines[start..]
+            .iter()
+            .position(|line| matches!(line, Line::Context(_)))
+            .map_or(hunk.lines.len(), |i| start + i)
+            .max(start + 1);
+        let run = &hunk.lines[start..end];
+        let replaced = run.iter().any(own) && run.iter().any(other);
+        for (i, line) in run.iter().enumerate() {
+            let (mark, text) = match line {
+                Line::Context(text) => ("  ", text),
+                Line::Add(text) | Line::Remove(text) if own(line) => {
+                    (if replaced { "! " } else { only_mark(side) }, text)
+                }
+                _ => continue,
+            };
+            write!(output, "\n{}{}", mark, text).unwrap();
+            if !end_newline && last == Some(start + i) {
+                output.push_str("\n\\ No newline at end of file");
+            }
+        }
+        start = end;
+    }
+}
+
+// The mark of lines only in one file
+fn only_mark(side: Side) -> &'static str {
+    match side {
+        Side::Old => "- ",
+        Side::New => "+ ",
+    }
+}
+
+// A range as the first and last line, or as a single line if it has at most one
+fn context_range(range: &Range) -> String {
+    match range.count {
+        0 | 1 => range.start.to_string(),
+        count => format!("{},{}", range.start, range.start + count - 1),
+    }
+}
+
+fn patch(input: Input<'_>) -> IResult<Input<'_>, Patch<'_>> {
+    // Ignore any lines up to the file header, like the `diff -c` command line
+    let mut parser = many0(preceded(not(tag("*** ")), consume_content_line));
+    let (input, _) = parser.parse(input)?;
+    let (input, old) = delimited(tag("*** "), header_line_content, line_ending).parse(input)?;
+    let (input, new) = delimited(tag("--- "), header_line_content, line_ending).parse(input)?;
+    let (input, chunks) = many1(hunk).parse(input)?;
+    let (input, _) = many0(line_ending).parse(input)?;
+
+    // Only the last hunk can reach the end of the files
+    let (old_end_newline, new_end_newline) = chunks.last().map_or((true, true), |chunk| chunk.1);
+    Ok((
+        input,
+        Patch {
+            old,
+            new,
+            operation: FileOp::Modify,
+            git: None,
+            svn: None,
+            hunks: chunks.into_iter().map(|chunk| chunk.0).collect(),
+            binary: None,
+            opaque_binary: false,
+            preamble: None,
+            raw: None,
+            old_end_newline,
+            new_end_newline,
+        },
+    ))
+}
+
+// A hunk, with whether the old and the new file end in a newline character according to it
+fn hunk(input: Input<'_>) -> IResult<Input<'_>, (Hunk<'_>, (bool, bool))> {
+    let start = input;
+    let (input, _) = tag("***************")(input)?;
+    // `diff -p` writes the hint after the separator, like after the ranges of unified diffs
+    let (input, range_hint) = not_line_ending(input)?;
+    let (input, _) = line_ending(input)?;
+    let (input, old_range) =
+        delimited(tag("*** "), range, (tag(" ****"), line_ending)).parse(input)?;
+    let (input, old_lines) = many0(|input| section_line('-', input)).parse(input)?;
+    let (input, new_range) =
+        delimited(tag("--- "), range, (tag(" ----"), line_ending)).parse(input)?;
+    let (input, new_lines) = many0(|input| section_line('+', input)).parse(input)?;
+
+    let invalid = || SpanError::failure(start, Expected::LineCount);
+    let (lines, end_newlines) = merge(old_lines, new_lines).ok_or_else(invalid)?;
+    let count = |removed: bool| {
+        lines
+            .iter()
+            .filter(|line| {
+                !matches!(
+                    (line, removed),
+                    (Line::Add(_), true) | (Line::Remove(_), false)
+                )
+            })
+            .count() as u64
+    };
+    let old_range = to_range(old_range, count(true)).ok_or_else(invalid)?;
+    let new_range = to_range(new_range, count(false)).ok_or_else(invalid)?;
+    Ok((
+        input,
+        (
+            Hunk {
+                old_range,
+                new_range,
+                range_hint: Cow::Borrowed(range_hint.fragment()),
+                lines,
+            },
+            end_newlines,
+        ),
+    ))
+}
+
+// The first line of a range and its last line, if it has more than one
+fn range(input: Input<'_>) -> IResult<Input<'_>, (u64, Option<u64>)> {
+    (u64_digit, opt(preceded(char(','), u64_digit))).parse(input)
+}
+
+// Check that a range has as many lines as its section, and convert it to a unified one
+fn to_range((first, last): (u64, Option<u64>), count: u64) -> Option<Range> {
+    let valid = match (count, last) {
+        // An empty range is written as the line before it
+        (0, _) => true,
+        (_, Some(last)) => last >= first && last - first + 1 == count,
+        (_, None) => count == 1,
+    };
+    valid.then_some(Range {
+        start: first,
+        count,
+    })
+}
+
+fn section_line(only: char, input: Input<'_>) -> IResult<Input<'_>, SectionLine<'_>> {
+    let mut parser = (
+        alt((
+            map(tag("  "), |_| Mark::Context),
+            map(tag("! "), |_| Mark::Change),
+            map((char(only), char(' ')), |_| Mark::Only),
+        )),
+        consume_content_line,
+        no_newline_indicator,
+    );
+    parser.parse(input)
+}
+
+// Interleave the lines of both sections into the lines of a unified hunk, or return `None` if
+// they don't fit together
+fn merge<'a>(
+    old: Vec<SectionLine<'a>>,
+    new: Vec<SectionLine<'a>>,
+) -> Option<(Vec<Line<'a>>, (bool, bool))> {
+    let (mut old_end_newline, mut new_end_newline) = (true, true);
+    let mut lines = Vec::with_capacity(old.len() + new.len());
+
+    // A section is left out if its side has no changes, and the other one shows its context
+    if old.is_empty() || new.is_empty() {
+        let only_old = new.is_empty();
+        for (mark, text, no_newline) in old.into_iter().chain(new) {
+            lines.push(match (mark, only_old) {
+                (Mark::Context, _) => Line::Context(text),
+                (Mark::Only, true) => Line::Remove(text),
+                (Mark::Only, false) => Line::Add(text),
+                (Mark::Change, _) => return None,
+            });
+            if no_newline {
+                match (mark, only_old) {
+                    (Mark::Context, _) => (old_end_newline, new_end_newline) = (false, false),
+                    (_, true) => old_end_newline = false,
+                    (_, false) => new_end_newline = false,
+                }
+            }
+        }
+        return (!lines.is_empty()).then_some((lines, (old_end_newline, new_end_newline)));
+    }
+
+    let (mut i, mut j) = (0, 0);
+    loop {
+        match (old.get(i), new.get(j)) {
+            (None, None) => break,
+            (Some((Mark::Only, text, _)), _) => {
+                lines.push(Line::Remove(text.clone()));
+                i += 1;
+            }
+            (_, Some((Mark::Only, text, _))) => {
+                lines.push(Line::Add(text.clone()));
+                j += 1;
+            }
+            (Some((Mark::Change, ..)), Some((Mark::Change, ..))) => {
+                while let Some((Mark::Change, text, _)) = old.get(i) {
+                    lines.push(Line::Remove(text.clone()));
+                    i += 1;
+                }
+                while let Some((Mark::Change, text, _)) = new.get(j) {
+                    lines.push(Line::Add(text.clone()));
+                    j += 1;
+                }
+            }
+            (Some((Mark::Context, old_text, _)), Some((Mark::Context, new_text, _)))
+                if old_text == new_text =>
+            {
+                lines.push(Line::Context(old_text.clone()));
+                (i, j) = (i + 1, j + 1);
+            }
+            _ => return None,
+        }
+    }
+    let old_end_newline = !old.last().is_some_and(|line| line.2);
+    let new_end_newline = !new.last().is_some_and(|line| line.2);
+    Some((lines, (old_end_newline, new_end_newline)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nom::error::ErrorKind;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_context_round_trip() {
+        let unified = "\
+--- a/file.txt
++++ b/file.txt
+@@ -1,4 +1,3 @@ fn main()
+ a
+-b
++B
+ c
+-d
+@@ -10,0 +10,2 @@
++x
++y
+\\ No newline at end of file";
+        let patch = Patch::from_single(unified).unwrap();
+        let context = patch.to_context_string();
+        assert_eq!(
+            context,
+            "\
+*** a/file.txt
+--- b/file.txt
+*************** fn main()
+*** 1,4 ****
+  a
+! b
+  c
+- d
+--- 1,3 ----
+  a
+! B
+  c
+***************
+*** 10 ****
+--- 10,11 ----
++ x
++ y
+\\ No newline at end of file"
+        );
+        assert_eq!(
+            Patch::from_context(&format!("{}\n", context)).unwrap(),
+            patch
+        );
+    }
+
+    #[test]
+    fn test_multiple_context() {
+        let sample = "\
+diff -c old/a new/a
+*** old/a
+--- new/a
+***************
+*** 1 ****
+! a
+--- 1 ----
+! A
+Only in new: c
+diff -c old/b new/b
+*** old/b
+--- new/b
+***************
+*** 2,3 ****
+  b
+- c
+--- 2 ----
+";
+        let patches = Patch::from_multiple_context(sample).unwrap();
+        assert_eq!(patches.len(), 2);
+        assert_eq!(
+            patches[1].to_string(),
+            "--- old/b\n+++ new/b\n@@ -2,2 +2,1 @@\n b\n-c"
+        );
+    }
+
+    #[test]
+    fn test_context_wrong_line_count() {
+        let sample = "\
+*** a
+--- b
+***************
+*** 1,3 ****
+! a
+--- 1 ----
+! A
+";
+        let error = Patch::from_context(sample).unwrap_err();
+        assert_eq!(error.line, 3);
+        assert_eq!(error.kind, ErrorKind::Verify);
+        assert_eq!(error.expected, Some(Expected::LineCount));
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt::Write;
+
+use crate::ast::{Line, Patch};
+use crate::formats::normal::{normal_range, runs};
+
+impl Patch<'_> {
+    /// Write the patch as an `ed` script, like `diff -e` does
+    ///
+    /// Every run of changes between context lines becomes an `a`, `c` or `d` command. The
+    /// commands are written from the end of the file to its start, so that each one refers to
+    /// lines the previous ones haven't moved. A line consisting of a single `.` would end the
+    /// text of a command, so it is written as `..` and fixed with `s/.//`, the way `diff -e`
+    /// does it.
+    ///
+    /// `ed` scripts can't say that a file doesn't end with a newline character, and the lines
+    /// they add always end with one. Like the `Display` implementation, the text doesn't end with
+    /// a newline character, but `ed` expects one after the last command.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::diff;
+    ///
+    /// let patch = diff("a\nb\nc\nd\n", "a\nB\nc\n", 1);
+    /// assert_eq!(patch.to_ed_script(), "4d\n2c\nB\n.");
+    /// ```
+    pub fn to_ed_script(&self) -> String {
+        let mut output = String::new();
+        for hunk in self.hunks.iter().rev() {
+            for run in runs(hunk).into_iter().rev() {
+                let range = normal_range(run.old_before, run.removed);
+                match (run.removed, run.added) {
+                    (_, 0) => writeln!(output, "{}d", range).unwrap(),
+                    (0, _) => writeln!(output, "{}a", run.old_before).unwrap(),
+                    _ => writeln!(output, "{}c", range).unwrap(),
+                }
+                if run.added == 0 {
+                    continue;
+                }
+
+                let mut insert_mode = true;
+                for line in &hunk.lines[run.lines] {
+                    let Line::Add(text) = line else { continue };
+                    if !insert_mode {
+                        output.push_str("a\n");
+                        insert_mode = true;
+                    }
+                    if text == "." {
+                        // Leave insert mode after a `..` line and remove the extra dot
+                        output.push_str("..\n.\ns/.//\n");
+                        insert_mode = false;
+                    } else {
+                        writeln!(output, "{}", text).unwrap();
+                    }
+                }
+                if insert_mode {
+                    output.push_str(".\n");
+                }
+            }
+        }
+        output.pop();
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_ed_script() {
+        let patch = Patch::from_single(
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -1,4 +1,6 @@
++first
+ a
+-b
+-c
++.
++C
+ d
+@@ -10,2 +12,3 @@
+ j
++.
+ k
+",
+        )
+        .unwrap();
+        assert_eq!(
+            patch.to_ed_script(),
+            "\
+10a
+..
+.
+s/.//
+2,3c
+..
+.
+s/.//
+a
+C
+.
+0a
+first
+."
+        );
+    }
+}
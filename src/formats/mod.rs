@@ -0,0 +1,9 @@
+//! Serializers and parsers for the formats patches are written in
+
+mod context;
+mod ed;
+mod normal;
+mod unified;
+mod word_diff;
+
+pub use unified::{FormatOptions, Prefix};
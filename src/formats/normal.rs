@@ -0,0 +1,388 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::ops::Range as IndexRange;
+
+use nom::{
+    Parser,
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, line_ending, one_of},
+    combinator::{all_consuming, opt},
+    multi::{many0, many1},
+    sequence::{preceded, terminated},
+};
+
+use crate::ast::{File, FileOp, Hunk, Line, Patch, Range};
+use crate::parser::{
+    Expected, IResult, Input, ParseError, SpanError, consume_content_line, convert_error,
+    no_newline_indicator, u64_digit,
+};
+
+impl<'a> Patch<'a> {
+    /// Attempt to parse a patch in the normal format, like `diff` produces without options
+    ///
+    /// Every change command becomes a hunk without context lines. Normal diffs don't name the
+    /// files they compare, so the file headers of the returned patch are named `old` and `new`
+    /// like the ones of [`diff`](crate::diff).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::{Patch, apply};
+    ///
+    /// let sample = "\
+    /// 2c2
+    /// < b
+    /// ---
+    /// > B
+    /// 4d3
+    /// < d
+    /// 5c4,5
+    /// < e
+    /// ---
+    /// > f
+    /// > g
+    /// ";
+    ///
+    /// let patch = Patch::from_normal(sample).unwrap();
+    /// assert_eq!(patch.hunks.len(), 3);
+    /// assert_eq!(apply(&patch, "a\nb\nc\nd\ne\n").unwrap(), "a\nB\nc\nf\ng\n");
+    /// ```
+    pub fn from_normal(s: &'a str) -> Result<Self, ParseError<'a>> {
+        let mut parser = all_consuming(patch);
+        let (_, patch) = parser.parse(Input::new(s)).map_err(convert_error)?;
+        Ok(patch)
+    }
+}
+
+impl Patch<'_> {
+    /// Write the patch in the normal format, like `diff` does without options
+    ///
+    /// Every run of changes between context lines becomes a change command. Like the `Display`
+    /// implementation, the text doesn't end with a newline character. The file headers, the
+    /// context lines and the changes to binary files can't be written in the normal format and
+    /// are left out.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::diff;
+    ///
+    /// let patch = diff("a\nb\nc\n", "a\nB\nc\nd\n", 1);
+    /// assert_eq!(patch.to_normal_string(), "2c2\n< b\n---\n> B\n3a4\n> d");
+    /// ```
+    pub fn to_normal_string(&self) -> String {
+        let mut output = String::new();
+        for (i, hunk) in self.hunks.iter().enumerate() {
+            let (old_end_newline, new_end_newline) = if i + 1 == self.hunks.len() {
+                (self.old_end_newline, self.new_end_newline)
+            } else {
+                (true, true)
+            };
+            let last_old = hunk
+                .lines
+                .iter()
+                .rposition(|line| !matches!(line, Line::Add(_)));
+            let last_new = hunk
+                .lines
+                .iter()
+                .rposition(|line| !matches!(line, Line::Remove(_)));
+            let marker = |i: usize| {
+                (!old_end_newline && last_old == Some(i))
+                    || (!new_end_newline && last_new == Some(i))
+            };
+
+            for run in runs(hunk) {
+                let old_range = normal_range(run.old_before, run.removed);
+                let new_range = normal_range(run.new_before, run.added);
+                if !output.is_empty() {
+                    output.push('\n');
+                }
+                match (run.removed, run.added) {
+                    (_, 0) => write!(output, "{}d{}", old_range, run.new_before).unwrap(),
+                    (0, _) => write!(output, "{}a{}", run.old_before, new_range).unwrap(),
+                    _ => write!(output, "{}c{}", old_range, new_range).unwrap(),
+                }
+                // Removed lines are written before the added ones, whatever their order in
+                // the hunk
+                for (prefix, removal) in [("< ", true), ("> ", false)] {
+                    if !removal && run.removed > 0 && run.added > 0 {
+                        output.push_str("\n---");
+                    }
+                    for i in run.lines.clone() {
+                        let text = match &hunk.lines[i] {
+                            Line::Remove(text) if removal => text,
+                            Line::Add(text) if !removal => text,
+                            _ => continue,
+                        };
+                        write!(output, "\n{}{}", prefix, text).unwrap();
+                        if marker(i) {
+                            output.push_str("\n\\ No newline at end of file");
+                        }
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+/// A run of changed lines between the context lines of a hunk
+pub(super) struct Run {
+    /// The lines of the old file before the run
+    pub(super) old_before: u64,
+    /// The lines of the new file before the run
+    pub(super) new_before: u64,
+    /// The indices of the lines of the run in the hunk
+    pub(super) lines: IndexRange<usize>,
+    /// The number of removed lines
+    pub(super) removed: u64,
+    /// The number of added lines
+    pub(super) added: u64,
+}
+
+// Every run of changed lines of a hunk, in order
+pub(super) fn runs(hunk: &Hunk) -> Vec<Run> {
+    let mut runs = Vec::new();
+    let mut old_before = lines_before(&hunk.old_range);
+    let mut new_before = lines_before(&hunk.new_range);
+    let mut start = 0;
+    while start < hunk.lines.len() {
+        if let Line::Context(_) = hunk.lines[start] {
+            (old_before, new_before) = (old_before + 1, new_before + 1);
+            start += 1;
+            continue;
+        }
+        let end = hunk.lines[start..]
+            .iter()
+            .position(|line| matches!(line, Line::Context(_)))
+            .map_or(hunk.lines.len(), |i| start + i);
+        let removed = hunk.lines[start..end]
+            .iter()
+            .filter(|line| matches!(line, Line::Remove(_)))
+            .count() as u64;
+        let added = (end - start) as u64 - removed;
+        runs.push(Run {
+            old_before,
+            new_before,
+            lines: start..end,
+            removed,
+            added,
+        });
+        (old_before, new_before) = (old_before + removed, new_before + added);
+        start = end;
+    }
+    runs
+}
+
+// The lines of a file before a range
+fn lines_before(range: &Range) -> u64 {
+    if range.count == 0 {
+        range.start
+    } else {
+        range.start.saturating_sub(1)
+    }
+}
+
+// The lines of a change command after `lines_before` lines, as the first and last line or as a
+// single line
+pub(super) fn normal_range(lines_before: u64, count: u64) -> String {
+    match count {
+        0 | 1 => (lines_before + 1).to_string(),
+        _ => format!("{},{}", lines_before + 1, lines_before + count),
+    }
+}
+
+fn patch(input: Input<'_>) -> IResult<Input<'_>, Patch<'_>> {
+    let (input, chunks) = many1(command).parse(input)?;
+    let (input, _) = many0(line_ending).parse(input)?;
+
+    // Only the last command can reach the end of the files
+    let (old_end_newline, new_end_newline) = chunks.last().map_or((true, true), |chunk| chunk.1);
+    let file = |path| File {
+        path: Cow::Borrowed(path),
+        meta: None,
+    };
+    Ok((
+        input,
+        Patch {
+            old: file("old"),
+            new: file("new"),
+            operation: FileOp::Modify,
+            git: None,
+            svn: None,
+            hunks: chunks.into_iter().map(|chunk| chunk.0).collect(),
+            binary: None,
+            opaque_binary: false,
+            preamble: None,
+            raw: None,
+            old_end_newline,
+            new_end_newline,
+        },
+    ))
+}
+
+// A change command as a hunk, with whether the old and the new file end in a newline character
+// according to it
+fn command(input: Input<'_>) -> IResult<Input<'_>, (Hunk<'_>, (bool, bool))> {
+    let start = input;
+    let (input, (old, kind, new)) =
+        terminated((range, one_of("acd"), range), line_ending).parse(input)?;
+    let (input, removed) = many0(|input| content_line("< ", input)).parse(input)?;
+    let (input, added) = if kind == 'c' {
+        preceded(
+            terminated(tag("---"), line_ending),
+            many1(|input| content_line("> ", input)),
+        )
+        .parse(input)?
+    } else {
+        many0(|input| content_line("> ", input)).parse(input)?
+    };
+
+    let invalid = || SpanError::failure(start, Expected::LineCount);
+    // Lines are only added after a line of the old file, and only removed after a line of the
+    // new one
+    let empty =
+        |(start, last): (u64, Option<u64>)| last.is_none().then_some(Range { start, count: 0 });
+    let (old_range, new_range) = match kind {
+        'a' if removed.is_empty() => (empty(old), to_range(new, added.len())),
+        'd' if added.is_empty() => (to_range(old, removed.len()), empty(new)),
+        'c' => (to_range(old, removed.len()), to_range(new, added.len())),
+        _ => return Err(invalid()),
+    };
+    let (old_range, new_range) = old_range.zip(new_range).ok_or_else(invalid)?;
+    let end_newlines = (
+        !removed.last().is_some_and(|line| line.1),
+        !added.last().is_some_and(|line| line.1),
+    );
+    let lines = removed
+        .into_iter()
+        .map(|line| Line::Remove(line.0))
+        .chain(added.into_iter().map(|line| Line::Add(line.0)))
+        .collect();
+    Ok((
+        input,
+        (
+            Hunk {
+                old_range,
+                new_range,
+                range_hint: Cow::Borrowed(""),
+                lines,
+            },
+            end_newlines,
+        ),
+    ))
+}
+
+// The first line of a range and its last line, if it has more than one
+fn range(input: Input<'_>) -> IResult<Input<'_>, (u64, Option<u64>)> {
+    (u64_digit, opt(preceded(char(','), u64_digit))).parse(input)
+}
+
+// Check that a range has as many lines as its command, and convert it to a unified one
+fn to_range((first, last): (u64, Option<u64>), count: usize) -> Option<Range> {
+    let count = count as u64;
+    let valid = match last {
+        Some(last) => last >= first && last - first + 1 == count,
+        None => count == 1,
+    };
+    valid.then_some(Range {
+        start: first,
+        count,
+    })
+}
+
+// A line of a file, with whether it is followed by `\ No newline at end of file`
+fn content_line<'a>(
+    prefix: &'static str,
+    input: Input<'a>,
+) -> IResult<Input<'a>, (Cow<'a, str>, bool)> {
+    let mut parser = (
+        preceded(
+            alt((tag(prefix), tag(prefix.trim_end()))),
+            consume_content_line,
+        ),
+        no_newline_indicator,
+    );
+    parser.parse(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nom::error::ErrorKind;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_normal_round_trip() {
+        let normal = "\
+0a1
+> first
+3,4c4
+< c
+< d
+---
+> C
+7,8d6
+< g
+< h
+\\ No newline at end of file";
+        let patch = Patch::from_normal(normal).unwrap();
+        assert_eq!(
+            patch.to_string(),
+            "\
+--- old
++++ new
+@@ -0,0 +1,1 @@
++first
+@@ -3,2 +4,1 @@
+-c
+-d
++C
+@@ -7,2 +6,0 @@
+-g
+-h
+\\ No newline at end of file"
+        );
+        assert_eq!(patch.to_normal_string(), normal);
+    }
+
+    #[test]
+    fn test_normal_from_unified() {
+        let patch = Patch::from_single(
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -1,5 +1,5 @@
+-a
+ b
++b2
+ c
+-d
++D
+ e
+",
+        )
+        .unwrap();
+        assert_eq!(
+            patch.to_normal_string(),
+            "1d0\n< a\n2a2\n> b2\n4c4\n< d\n---\n> D"
+        );
+    }
+
+    #[test]
+    fn test_normal_wrong_line_count() {
+        let error = Patch::from_normal("1,3d0\n< a\n").unwrap_err();
+        assert_eq!(error.line, 1);
+        assert_eq!(error.kind, ErrorKind::Verify);
+        assert_eq!(error.expected, Some(Expected::LineCount));
+    }
+
+    #[test]
+    fn test_normal_number_too_large() {
+        let error = Patch::from_normal("99999999999999999999999c2\n< a\n---\n> b\n").unwrap_err();
+        assert_eq!(error.line, 1);
+        assert_eq!(error.kind, ErrorKind::MapRes);
+    }
+}
@@ -0,0 +1,631 @@
+use std::borrow::Cow;
+use std::ops::Range as IndexRange;
+
+use crate::applier::{ApplyError, ApplyOptions, HunkStatus, check};
+use crate::ast::{File, Hunk, Line, MarkWide, Patch, Range};
+use crate::generator::hunk_range;
+use crate::patch_set::{PatchSet, is_dev_null, join_patches};
+
+/// The prefixes written in front of the paths of a patch, see [`FormatOptions::prefix`]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub enum Prefix {
+    /// Write the paths as they are
+    #[default]
+    Keep,
+    /// Prefix the old paths with `a/` and the new paths with `b/`, like git does by default
+    Git,
+    /// Write the paths without a prefix, like `git diff --no-prefix`
+    None,
+    /// Prefix the old and new paths with the given strings, like `git diff --src-prefix=<old>
+    /// --dst-prefix=<new>`
+    Custom {
+        /// The prefix of the old paths, usually ending with a `/`
+        old: String,
+        /// The prefix of the new paths, usually ending with a `/`
+        new: String,
+    },
+}
+
+/// Options controlling how a patch is written by [`Patch::to_string_with`]
+///
+/// The default options write a patch exactly like its `Display` implementation does.
+///
+/// # Example
+///
+/// ```
+/// use patch::{FormatOptions, Patch, Prefix};
+///
+/// let patch = Patch::from_single(
+///     "\
+/// --- a/notes.txt\t2024-01-01 10:00:00.000000000 +0000
+/// +++ b/notes.txt\t2024-01-02 10:00:00.000000000 +0000
+/// @@ -1,5 +1,5 @@
+///  one
+///  two
+/// -three
+/// +THREE
+///  four
+///  five
+/// ",
+/// )
+/// .unwrap();
+///
+/// let options = FormatOptions {
+///     context: Some(1),
+///     prefix: Prefix::None,
+///     timestamps: false,
+///     ..FormatOptions::default()
+/// };
+/// assert_eq!(
+///     patch.to_string_with(&options),
+///     "\
+/// --- notes.txt
+/// +++ notes.txt
+/// @@ -2,3 +2,3 @@
+///  two
+/// -three
+/// +THREE
+///  four"
+/// );
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FormatOptions {
+    /// The largest number of context lines to keep around each change, like `diff -U <n>`,
+    /// or `None` to keep all of them
+    ///
+    /// A patch only knows the context lines it already has, so this can only reduce the
+    /// context. Hunks are split where the context between two changes becomes too long, and
+    /// hunks without changes are left out.
+    pub context: Option<usize>,
+    /// The prefixes of the paths on the file header lines and the `diff --git` line
+    ///
+    /// An existing `a/` or `b/` prefix is replaced, and `/dev/null` is always written as it is.
+    pub prefix: Prefix,
+    /// Whether to write the timestamps and any other metadata after the paths on the file
+    /// header lines
+    pub timestamps: bool,
+    /// Whether to write the text after the ranges of the hunk headers, which is usually the line
+    /// of the function a hunk is in
+    pub hints: bool,
+    /// Expand the tabs of the lines of the hunks to spaces, with a tab stop every `n` columns of
+    /// the files, like `diff --expand-tabs`
+    ///
+    /// This is meant for showing patches. Patches written with it don't apply to files with tabs.
+    pub tab_width: Option<usize>,
+    /// Mark the lines of the hunks that are longer than this many characters after any tabs are
+    /// expanded, with a `\ Line longer than <n> characters` line after each of them
+    ///
+    /// Like [`tab_width`](FormatOptions::tab_width), this is meant for showing patches, for
+    /// example in reviews. Most tools can't read the marks.
+    pub max_width: Option<usize>,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            context: None,
+            prefix: Prefix::Keep,
+            timestamps: true,
+            hints: true,
+            tab_width: None,
+            max_width: None,
+        }
+    }
+}
+
+impl Patch<'_> {
+    /// Write the patch in the Unified Format, with the amount of context, the path prefixes and
+    /// the timestamps chosen by `options`
+    ///
+    /// Like the `Display` implementation, the text doesn't end with a newline character. See
+    /// [`FormatOptions`] for an example.
+    pub fn to_string_with(&self, options: &FormatOptions) -> String {
+        let mut patch = self.clone();
+
+        if let Some(context) = options.context {
+            let ends = |hunks: &[Hunk]| {
+                hunks
+                    .last()
+                    .map(|hunk| (last_line(&hunk.old_range), last_line(&hunk.new_range)))
+            };
+            let (old_end, new_end) = ends(&patch.hunks).unzip();
+            patch.hunks = patch
+                .hunks
+                .iter()
+                .flat_map(|hunk| trim_context(hunk, context))
+                .collect();
+            // A missing newline character can only be marked if the last hunk still reaches the
+            // end of the file
+            let (trimmed_old_end, trimmed_new_end) = ends(&patch.hunks).unzip();
+            patch.old_end_newline |= trimmed_old_end != old_end;
+            patch.new_end_newline |= trimmed_new_end != new_end;
+        }
+
+        if let Some((old_prefix, new_prefix)) = match &options.prefix {
+            Prefix::Keep => None,
+            Prefix::Git => Some(("a/", "b/")),
+            Prefix::None => Some(("", "")),
+            Prefix::Custom { old, new } => Some((old.as_str(), new.as_str())),
+        } {
+            if !is_dev_null(&patch.old) {
+                patch.old.path = with_prefix(&patch.old.path, old_prefix);
+            }
+            if !is_dev_null(&patch.new) {
+                patch.new.path = with_prefix(&patch.new.path, new_prefix);
+            }
+            if let Some(git) = &mut patch.git {
+                git.old_path = with_prefix(&git.old_path, old_prefix);
+                git.new_path = with_prefix(&git.new_path, new_prefix);
+            }
+        }
+
+        if !options.timestamps {
+            patch.old = File {
+                meta: None,
+                ..patch.old
+            };
+            patch.new = File {
+                meta: None,
+                ..patch.new
+            };
+        }
+
+        if !options.hints {
+            for hunk in &mut patch.hunks {
+                hunk.range_hint = Cow::Borrowed("");
+            }
+        }
+
+        if let Some(width) = options.tab_width.filter(|&width| width > 0) {
+            for line in patch.hunks.iter_mut().flat_map(|hunk| &mut hunk.lines) {
+                let (Line::Add(text) | Line::Remove(text) | Line::Context(text)) = line;
+                if text.contains('\t') {
+                    *text = Cow::Owned(expand_tabs(text, width));
+                }
+            }
+        }
+
+        match options.max_width {
+            Some(max) => MarkWide(&patch, max).to_string(),
+            None => patch.to_string(),
+        }
+    }
+}
+
+impl<'a> Patch<'a> {
+    /// Produce a patch with `context` lines of context around every change, taken from the
+    /// `original` content the patch applies to, like `rediff`.
+    ///
+    /// Unlike [`FormatOptions::context`], this can add context as well as remove it, so a patch
+    /// made with `diff -U0` can be turned into one with three lines of context. Every hunk is
+    /// located in `original` the way [`check`](crate::check) does it with
+    /// [`ApplyOptions::unidiff_zero`], changes that end up close enough share a hunk, and the
+    /// ranges are recomputed. Hunks without changes are left out.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use patch::Patch;
+    ///
+    /// let original = "a\nb\nc\nd\ne\n";
+    /// let patch = Patch::from_single("--- x\n+++ x\n@@ -3 +3 @@\n-c\n+C\n")?;
+    ///
+    /// let patch = patch.recontext(original, 1)?;
+    /// assert_eq!(patch.hunks[0].to_string(), "@@ -2,3 +2,3 @@\n b\n-c\n+C\n d");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn recontext<'b>(&self, original: &'b str, context: usize) -> Result<Patch<'b>, ApplyError>
+    where
+        'a: 'b,
+    {
+        let lines: Vec<&str> = original.lines().collect();
+        let options = ApplyOptions {
+            unidiff_zero: true,
+            ..ApplyOptions::default()
+        };
+        let report = check(self, original, &options);
+
+        // Every run of changes, with the index in `lines` of the first line of the old file it
+        // replaces
+        let mut runs: Vec<(usize, &[Line<'a>])> = Vec::new();
+        let mut offset = 0;
+        for (hunk, status) in self.hunks.iter().zip(report.hunks) {
+            let stated = hunk.old_range.start.saturating_sub(1) as i64;
+            let mut position = match status {
+                HunkStatus::Failed(error) => return Err(error),
+                // Hunks without old lines are found anywhere, so they go where the ranges say
+                HunkStatus::Applied { .. } if hunk.old_range.count == 0 => {
+                    let position = hunk.old_range.start as i64 + offset;
+                    if position < 0 || position as usize > lines.len() {
+                        return Err(ApplyError::LineOutOfBounds {
+                            line: hunk.old_range.start,
+                            total_lines: lines.len(),
+                        });
+                    }
+                    position as usize
+                }
+                HunkStatus::Applied { line, .. } => {
+                    offset = line as i64 - 1 - stated;
+                    line as usize - 1
+                }
+            };
+            let mut i = 0;
+            while i < hunk.lines.len() {
+                let start = i;
+                while i < hunk.lines.len() && !matches!(hunk.lines[i], Line::Context(_)) {
+                    i += 1;
+                }
+                if i > start {
+                    runs.push((position, &hunk.lines[start..i]));
+                    position += hunk.lines[start..i]
+                        .iter()
+                        .filter(|line| matches!(line, Line::Remove(_)))
+                        .count();
+                }
+                if i < hunk.lines.len() {
+                    position += 1;
+                    i += 1;
+                }
+            }
+        }
+
+        // Group the runs that are at most twice the context apart, like `build_hunks`
+        let old_end = |&(position, run): &(usize, &[Line])| {
+            position
+                + run
+                    .iter()
+                    .filter(|line| matches!(line, Line::Remove(_)))
+                    .count()
+        };
+        let mut groups: Vec<Vec<(usize, &[Line<'a>])>> = Vec::new();
+        for run in runs {
+            match groups.last_mut() {
+                Some(group) if run.0 <= old_end(group.last().unwrap()) + 2 * context => {
+                    group.push(run)
+                }
+                _ => groups.push(vec![run]),
+            }
+        }
+
+        let context_lines = |range: IndexRange<usize>| {
+            lines[range]
+                .iter()
+                .map(|line| Line::Context(Cow::Borrowed(*line)))
+        };
+        // How many more lines the new file has than the old one before the current hunk
+        let mut growth: i64 = 0;
+        let mut hunks = Vec::with_capacity(groups.len());
+        for group in groups {
+            let first = group[0].0.saturating_sub(context);
+            let mut hunk_lines: Vec<Line<'b>> = context_lines(first..group[0].0).collect();
+            let mut end = group[0].0;
+            for run in &group {
+                hunk_lines.extend(context_lines(end..run.0));
+                hunk_lines.extend(run.1.iter().cloned());
+                end = old_end(run);
+            }
+            let last = (end + context).min(lines.len());
+            hunk_lines.extend(context_lines(end..last));
+
+            let old_count = (last - first) as u64;
+            let new_count = hunk_lines
+                .iter()
+                .filter(|line| !matches!(line, Line::Remove(_)))
+                .count() as u64;
+            hunks.push(Hunk {
+                old_range: hunk_range(first as u64, old_count),
+                new_range: hunk_range((first as i64 + growth) as u64, new_count),
+                range_hint: Cow::Borrowed(""),
+                lines: hunk_lines,
+            });
+            growth += new_count as i64 - old_count as i64;
+        }
+
+        // A missing newline character can only be marked if the last hunk reaches the end of
+        // the file. The content tells whether it ends with one, unless the patch changes that
+        let reached_end = hunks
+            .last()
+            .is_some_and(|hunk| last_line(&hunk.old_range) >= lines.len() as u64);
+        let original_end_newline = original.is_empty() || original.ends_with('\n');
+        let (old_end_newline, new_end_newline) = if !reached_end {
+            (true, true)
+        } else if self.old_end_newline != self.new_end_newline {
+            (original_end_newline, self.new_end_newline)
+        } else {
+            (original_end_newline, original_end_newline)
+        };
+
+        Ok(Patch {
+            old: self.old.clone(),
+            new: self.new.clone(),
+            operation: self.operation.clone(),
+            git: self.git.clone(),
+            svn: self.svn.clone(),
+            hunks,
+            binary: self.binary.clone(),
+            opaque_binary: self.opaque_binary,
+            preamble: self.preamble.clone(),
+            raw: None,
+            old_end_newline,
+            new_end_newline,
+        })
+    }
+}
+
+impl PatchSet<'_> {
+    /// Write every patch of the set with [`Patch::to_string_with`], one after the other
+    pub fn to_string_with(&self, options: &FormatOptions) -> String {
+        join_patches(
+            self.patches
+                .iter()
+                .map(|patch| patch.to_string_with(options)),
+        )
+    }
+}
+
+// The last line of a range, or the line before it if it is empty
+fn last_line(range: &Range) -> u64 {
+    if range.count == 0 {
+        range.start
+    } else {
+        range.start + range.count - 1
+    }
+}
+
+// Split a hunk into the hunks that keep at most `context` context lines around its changes
+fn trim_context<'a>(hunk: &Hunk<'a>, context: usize) -> Vec<Hunk<'a>> {
+    let changes: Vec<usize> = (0..hunk.lines.len())
+        .filter(|&i| !matches!(hunk.lines[i], Line::Context(_)))
+        .collect();
+
+    // Each group is a range of indices into the lines of the hunk, like in `build_hunks`
+    let mut groups: Vec<IndexRange<usize>> = Vec::new();
+    for &i in &changes {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(hunk.lines.len());
+        match groups.last_mut() {
+            Some(group) if start <= group.end => group.end = end,
+            _ => groups.push(start..end),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|group| hunk.sub_hunk(group))
+        .collect()
+}
+
+// Replace the tabs of a line with spaces up to the next tab stop
+fn expand_tabs(text: &str, width: usize) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut column = 0;
+    for ch in text.chars() {
+        if ch == '\t' {
+            let spaces = width - column % width;
+            expanded.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            expanded.push(ch);
+            column += 1;
+        }
+    }
+    expanded
+}
+
+// Replace the `a/` or `b/` prefix of a path, if it has one, with another prefix
+fn with_prefix<'a>(path: &str, prefix: &str) -> Cow<'a, str> {
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+    Cow::Owned(format!("{}{}", prefix, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::applier::apply;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_context_splits_hunks() {
+        let patch = Patch::from_single(
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -1,8 +1,8 @@
+-a
++A
+ b
+ c
+ d
+ e
+ f
+-g
++G
+ h
+\\ No newline at end of file
+",
+        )
+        .unwrap();
+        let options = FormatOptions {
+            context: Some(1),
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            patch.to_string_with(&options),
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -1,2 +1,2 @@
+-a
++A
+ b
+@@ -6,3 +6,3 @@
+ f
+-g
++G
+ h
+\\ No newline at end of file"
+        );
+
+        // Without context, the end of the file isn't shown anymore
+        let options = FormatOptions {
+            context: Some(0),
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            patch.to_string_with(&options),
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -1,1 +1,1 @@
+-a
++A
+@@ -7,1 +7,1 @@
+-g
++G"
+        );
+    }
+
+    #[test]
+    fn test_display_options() {
+        let patch = Patch::from_single(
+            "\
+--- a/main.c
++++ b/main.c
+@@ -1,2 +1,2 @@ int main()
+ \tint a;\t// the first
+-ab\tc
++abc\tdefgh
+",
+        )
+        .unwrap();
+        let options = FormatOptions {
+            hints: false,
+            tab_width: Some(4),
+            max_width: Some(10),
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            patch.to_string_with(&options),
+            "\
+--- a/main.c
++++ b/main.c
+@@ -1,2 +1,2 @@
+     int a;  // the first
+\\ Line longer than 10 characters
+-ab  c
++abc defgh"
+        );
+        // The patch itself is unchanged
+        assert_eq!(patch.hunks[0].range_hint, " int main()");
+        assert_eq!(patch.hunks[0].lines[2], Line::Add("abc\tdefgh".into()));
+    }
+
+    #[test]
+    fn test_prefixes() {
+        let patch = Patch::from_single(
+            "\
+diff --git a/new.txt b/new.txt
+new file mode 100644
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++new
+",
+        )
+        .unwrap();
+        let options = FormatOptions {
+            prefix: Prefix::Custom {
+                old: "old/".into(),
+                new: "new/".into(),
+            },
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            patch.to_string_with(&options),
+            "\
+diff --git old/new.txt new/new.txt
+new file mode 100644
+--- /dev/null
++++ new/new.txt
+@@ -0,0 +1,1 @@
++new"
+        );
+        assert_eq!(
+            patch.to_string_with(&FormatOptions::default()),
+            patch.to_string()
+        );
+    }
+
+    #[test]
+    fn test_recontext() {
+        let original = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj";
+        // Made with `diff -U0`
+        let patch = Patch::from_single(
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -2 +2 @@
+-b
++B
+@@ -3,0 +4,2 @@
++x
++y
+@@ -9 +11 @@
+-i
++I
+",
+        )
+        .unwrap();
+
+        let recontexted = patch.recontext(original, 2).unwrap();
+        assert_eq!(
+            recontexted.to_string(),
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -1,5 +1,7 @@
+ a
+-b
++B
+ c
++x
++y
+ d
+ e
+@@ -7,4 +9,4 @@
+ g
+ h
+-i
++I
+ j
+\\ No newline at end of file"
+        );
+        assert_eq!(
+            apply(&recontexted, original).unwrap(),
+            "a\nB\nc\nx\ny\nd\ne\nf\ng\nh\nI\nj"
+        );
+
+        // With less context, the changes get hunks of their own again
+        let hunks = recontexted.recontext(original, 0).unwrap().hunks;
+        let headers: Vec<_> = hunks
+            .iter()
+            .map(|hunk| format!("-{} +{}", hunk.old_range, hunk.new_range))
+            .collect();
+        assert_eq!(headers, ["-2,1 +2,1", "-3,0 +4,2", "-9,1 +11,1"]);
+    }
+
+    #[test]
+    fn test_recontext_mismatch() {
+        let patch = Patch::from_single("--- x\n+++ x\n@@ -1 +1 @@\n-z\n+Z\n").unwrap();
+        assert!(matches!(
+            patch.recontext("a\nb\n", 3),
+            Err(ApplyError::ContextMismatch { line: 1, .. })
+        ));
+    }
+}
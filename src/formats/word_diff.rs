@@ -0,0 +1,386 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use nom::{
+    Parser,
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{line_ending, not_line_ending},
+    combinator::{all_consuming, eof, not},
+    multi::{many0, many1},
+    sequence::{delimited, preceded, terminated},
+};
+
+use crate::ast::{FileOp, Hunk, Line, Patch};
+use crate::parser::{
+    IResult, Input, ParseError, Span, chunk_header, consume_content_line, convert_error,
+    header_line_content,
+};
+use crate::words::{Granularity, WordChange};
+
+impl<'a> Patch<'a> {
+    /// Attempt to parse a patch written like `git diff --word-diff` does
+    ///
+    /// The lines of the hunks of a word diff have no prefix. The words removed from a line are
+    /// written as `[-removed-]` and the words added to it as `{+added+}`, so a line with either
+    /// stands for a removed line and an added line. Every run of such lines becomes the removed
+    /// lines followed by the added lines, which [`Hunk::word_changes`] pairs up again.
+    ///
+    /// Any lines before the file headers, like the `diff --git` header lines, are ignored. Word
+    /// diffs don't mark the lines without a newline character at the end of the files, so the
+    /// returned patch says both files end with one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::{Patch, apply};
+    ///
+    /// let sample = "\
+    /// --- a/notes.txt
+    /// +++ b/notes.txt
+    /// @@ -1,3 +1,3 @@
+    /// first line
+    /// the [-old-]{+new+} words
+    /// last line
+    /// ";
+    ///
+    /// let patch = Patch::from_word_diff(sample).unwrap();
+    /// assert_eq!(
+    ///     apply(&patch, "first line\nthe old words\nlast line\n").unwrap(),
+    ///     "first line\nthe new words\nlast line\n"
+    /// );
+    /// ```
+    pub fn from_word_diff(s: &'a str) -> Result<Self, ParseError<'a>> {
+        let mut parser = all_consuming(patch);
+        let (_, patch) = parser.parse(Input::new(s)).map_err(convert_error)?;
+        Ok(patch)
+    }
+}
+
+impl Patch<'_> {
+    /// Write the patch like `git diff --word-diff` does, comparing the changed lines with the
+    /// given granularity
+    ///
+    /// The lines paired by [`Hunk::word_changes`] are written as one line marking the words
+    /// removed from it and added to it, and the other removed and added lines are written
+    /// whole, inside the markers. Like the `Display` implementation, the text doesn't end with
+    /// a newline character. The `diff --git` header lines, the changes to binary files and the
+    /// missing newline characters at the end of the files can't be written in a word diff and
+    /// are left out.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::{Granularity, diff};
+    ///
+    /// let patch = diff("one\nthe old words\n", "one\nthe new words\nadded\n", 1);
+    /// assert_eq!(
+    ///     patch.to_word_diff_string(Granularity::Word),
+    ///     "\
+    /// --- old
+    /// +++ new
+    /// @@ -1,2 +1,3 @@
+    /// one
+    /// the [-old-]{+new+} words
+    /// {+added+}"
+    /// );
+    /// ```
+    pub fn to_word_diff_string(&self, granularity: Granularity) -> String {
+        let mut output = format!("--- {}\n+++ {}", self.old, self.new);
+        for hunk in &self.hunks {
+            write!(
+                output,
+                "\n@@ -{} +{} @@{}",
+                hunk.old_range, hunk.new_range, hunk.range_hint
+            )
+            .unwrap();
+            let changes: HashMap<usize, WordChange> = hunk
+                .word_changes(granularity)
+                .into_iter()
+                .map(|change| (change.old_line, change))
+                .collect();
+            let paired: Vec<usize> = changes.values().map(|change| change.new_line).collect();
+            for (i, line) in hunk.lines.iter().enumerate() {
+                // Added lines paired with a removed line are written along with it
+                if matches!(line, Line::Add(_)) && paired.contains(&i) {
+                    continue;
+                }
+                output.push('\n');
+                match line {
+                    Line::Context(text) => output.push_str(text),
+                    Line::Remove(old) => match changes.get(&i) {
+                        Some(change) => {
+                            let Line::Add(new) = &hunk.lines[change.new_line] else {
+                                unreachable!("bug: removed lines are paired with added lines");
+                            };
+                            write_change(&mut output, old, new, change);
+                        }
+                        None => write!(output, "[-{}-]", old).unwrap(),
+                    },
+                    Line::Add(new) => write!(output, "{{+{}+}}", new).unwrap(),
+                }
+            }
+        }
+        output
+    }
+}
+
+// Write a removed line and the added line paired with it as one line, going through the parts
+// both have in the same order on both sides
+fn write_change(output: &mut String, old: &str, new: &str, change: &WordChange) {
+    // A side that is an empty line still needs a marker to be read back
+    if old.is_empty() {
+        output.push_str("[--]");
+    }
+    let (mut i, mut j) = (0, 0);
+    // How much of the current common part of each side was already written
+    let (mut old_written, mut new_written) = (0, 0);
+    loop {
+        if let Some(span) = change.old_spans.get(i).filter(|span| span.changed) {
+            write!(output, "[-{}-]", &old[span.range.clone()]).unwrap();
+            i += 1;
+        }
+        if let Some(span) = change.new_spans.get(j).filter(|span| span.changed) {
+            write!(output, "{{+{}+}}", &new[span.range.clone()]).unwrap();
+            j += 1;
+        }
+        let (Some(old_span), Some(new_span)) = (change.old_spans.get(i), change.new_spans.get(j))
+        else {
+            break;
+        };
+        // The common parts are the same text on both sides, but split where the other side
+        // changed
+        let old_rest = &old[old_span.range.start + old_written..old_span.range.end];
+        let new_rest = &new[new_span.range.start + new_written..new_span.range.end];
+        let len = old_rest.len().min(new_rest.len());
+        output.push_str(&old_rest[..len]);
+        (old_written, new_written) = (old_written + len, new_written + len);
+        if old_written == old_span.range.len() {
+            (i, old_written) = (i + 1, 0);
+        }
+        if new_written == new_span.range.len() {
+            (j, new_written) = (j + 1, 0);
+        }
+    }
+    if new.is_empty() {
+        output.push_str("{++}");
+    }
+}
+
+// The lines of the old and the new file a line of a word diff stands for
+fn split_line(line: &str) -> Vec<Line<'_>> {
+    // The text of each side, if the line has any
+    let (mut old, mut new): (Option<String>, Option<String>) = (None, None);
+    let common = |old: &mut Option<String>, new: &mut Option<String>, text: &str| {
+        if !text.is_empty() {
+            old.get_or_insert_default().push_str(text);
+            new.get_or_insert_default().push_str(text);
+        }
+    };
+    let mut changed = false;
+    let mut rest = line;
+    while let Some((start, open, close)) = [("[-", "-]"), ("{+", "+}")]
+        .into_iter()
+        .filter_map(|(open, close)| Some((rest.find(open)?, open, close)))
+        .min()
+    {
+        let marked = &rest[start + open.len()..];
+        // Markers that aren't closed are text
+        let Some(end) = marked.find(close) else {
+            common(&mut old, &mut new, &rest[..start + open.len()]);
+            rest = marked;
+            continue;
+        };
+        common(&mut old, &mut new, &rest[..start]);
+        let side = if open == "[-" { &mut old } else { &mut new };
+        side.get_or_insert_default().push_str(&marked[..end]);
+        changed = true;
+        rest = &marked[end + close.len()..];
+    }
+    if !changed {
+        return vec![Line::Context(Cow::Borrowed(line))];
+    }
+    common(&mut old, &mut new, rest);
+    let old = old.map(|old| Line::Remove(Cow::Owned(old)));
+    let new = new.map(|new| Line::Add(Cow::Owned(new)));
+    old.into_iter().chain(new).collect()
+}
+
+fn patch(input: Input<'_>) -> IResult<Input<'_>, Patch<'_>> {
+    // Ignore any lines up to the file header, like the `diff --git` header lines
+    let mut parser = many0(preceded(not(tag("--- ")), consume_content_line));
+    let (input, _) = parser.parse(input)?;
+    let (input, old) = delimited(tag("--- "), header_line_content, line_ending).parse(input)?;
+    let (input, new) = delimited(tag("+++ "), header_line_content, line_ending).parse(input)?;
+    let (input, hunks) = many1(hunk).parse(input)?;
+    Ok((
+        input,
+        Patch {
+            old,
+            new,
+            operation: FileOp::Modify,
+            git: None,
+            svn: None,
+            hunks,
+            binary: None,
+            opaque_binary: false,
+            preamble: None,
+            raw: None,
+            old_end_newline: true,
+            new_end_newline: true,
+        },
+    ))
+}
+
+fn hunk(input: Input<'_>) -> IResult<Input<'_>, Hunk<'_>> {
+    let (input, (old_range, new_range, range_hint)) = chunk_header(input)?;
+    // The last line of the input may not end with a newline character
+    let line = preceded(
+        (not(eof), not(tag("@@ -"))),
+        terminated(not_line_ending, alt((line_ending, eof))),
+    );
+    let (input, texts) = many0(line).parse(input)?;
+
+    let mut lines = Vec::with_capacity(texts.len());
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    for text in texts {
+        for line in split_line(text.text()) {
+            match line {
+                Line::Remove(_) => removed.push(line),
+                Line::Add(_) => added.push(line),
+                Line::Context(_) => {
+                    lines.append(&mut removed);
+                    lines.append(&mut added);
+                    lines.push(line);
+                }
+            }
+        }
+    }
+    lines.append(&mut removed);
+    lines.append(&mut added);
+
+    // Ignore the empty lines after the hunk, which aren't context lines if the hunk has as
+    // many lines without them as its header says
+    let mut old_lines = lines
+        .iter()
+        .filter(|line| !matches!(line, Line::Add(_)))
+        .count() as u64;
+    while old_lines > old_range.count
+        && matches!(lines.last(), Some(Line::Context(text)) if text.is_empty())
+    {
+        lines.pop();
+        old_lines -= 1;
+    }
+
+    Ok((
+        input,
+        Hunk {
+            old_range,
+            new_range,
+            range_hint,
+            lines,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::applier::apply;
+    use crate::generator::diff;
+
+    #[test]
+    fn test_split_line() {
+        assert_eq!(split_line("plain"), [Line::Context(Cow::Borrowed("plain"))]);
+        assert_eq!(
+            split_line("a [-b-]{+c+} d"),
+            [
+                Line::Remove(Cow::Borrowed("a b d")),
+                Line::Add(Cow::Borrowed("a c d"))
+            ]
+        );
+        assert_eq!(
+            split_line("[-gone-]"),
+            [Line::Remove(Cow::Borrowed("gone"))]
+        );
+        assert_eq!(split_line("{+new+}"), [Line::Add(Cow::Borrowed("new"))]);
+        assert_eq!(
+            split_line("[--]{+x+}"),
+            [
+                Line::Remove(Cow::Borrowed("")),
+                Line::Add(Cow::Borrowed("x"))
+            ]
+        );
+        // Markers that aren't closed are text
+        assert_eq!(
+            split_line("a[-b {+c+}"),
+            [
+                Line::Remove(Cow::Borrowed("a[-b ")),
+                Line::Add(Cow::Borrowed("a[-b c"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_word_diff_round_trip() {
+        let old = "fn main() {\n    let x = 1;\n    print(x);\n\n    done();\n}\n";
+        let new = "fn main() {\n    let y = 2;\n    print(y);\n    more();\n\n}\n";
+        let patch = diff(old, new, 1);
+        for granularity in [Granularity::Word, Granularity::Char] {
+            let text = patch.to_word_diff_string(granularity);
+            let parsed = Patch::from_word_diff(&text).unwrap();
+            assert_eq!(apply(&parsed, old).unwrap(), new, "{}", text);
+            // A trailing newline character doesn't add an empty context line
+            let padded = format!("{}\n\n", text);
+            let parsed = Patch::from_word_diff(&padded).unwrap();
+            assert_eq!(parsed.hunks, Patch::from_word_diff(&text).unwrap().hunks);
+        }
+        assert_eq!(
+            patch.to_word_diff_string(Granularity::Word),
+            "\
+--- old
++++ new
+@@ -1,6 +1,6 @@
+fn main() {
+    let [-x-]{+y+} = [-1-]{+2+};
+    print([-x-]{+y+});
+{+    more();+}
+
+[-    done();-]
+}"
+        );
+
+        let patch = diff("a\nb\n", "a\n\n", 0);
+        let text = patch.to_word_diff_string(Granularity::Word);
+        assert_eq!(text, "--- old\n+++ new\n@@ -2,1 +2,1 @@\n[-b-]{++}");
+        assert_eq!(
+            apply(&Patch::from_word_diff(&text).unwrap(), "a\nb\n").unwrap(),
+            "a\n\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_git_word_diff() {
+        let sample = "\
+diff --git a/README.md b/README.md
+index 1a2b3c4..5d6e7f8 100644
+--- a/README.md
++++ b/README.md
+@@ -1,3 +1,3 @@ # Title
+Some [-old-]{+new+} text.
+Unchanged line.
+[-Removed line.-]{+Added line.+}
+";
+        let patch = Patch::from_word_diff(sample).unwrap();
+        assert_eq!(patch.old.path, "a/README.md");
+        assert_eq!(patch.hunks[0].hint(), Some("# Title"));
+        assert_eq!(
+            apply(&patch, "Some old text.\nUnchanged line.\nRemoved line.\n").unwrap(),
+            "Some new text.\nUnchanged line.\nAdded line.\n"
+        );
+    }
+}
@@ -0,0 +1,842 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::applier::ApplyOptions;
+use crate::ast::{FileOp, Patch};
+use crate::patch_set::{FileChange, PatchSet, PatchSetError, is_dev_null, strip_path};
+use crate::vfs::{DiskFs, SYMLINK_MODE, Vfs};
+
+/// Error that can occur while applying patches to a directory
+#[derive(Debug)]
+pub enum FsError {
+    /// A file could not be read or written
+    Io {
+        /// The path of the file
+        path: PathBuf,
+        /// The underlying error
+        error: io::Error,
+    },
+    /// The patches could not be applied to the files
+    Patch(PatchSetError),
+    /// A patch creates, changes or patches through a symbolic link, which
+    /// [`ApplyOptions::forbid_symlinks`] refuses, or patches a file beneath one, which is always
+    /// refused
+    Symlink {
+        /// The path of the link
+        path: PathBuf,
+    },
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            FsError::Patch(error) => write!(f, "{}", error),
+            FsError::Symlink { path } => {
+                write!(f, "{}: refusing to patch a symbolic link", path.display())
+            }
+        }
+    }
+}
+
+impl Error for FsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FsError::Io { error, .. } => Some(error),
+            FsError::Patch(error) => Some(error),
+            FsError::Symlink { .. } => None,
+        }
+    }
+}
+
+impl From<PatchSetError> for FsError {
+    fn from(error: PatchSetError) -> Self {
+        FsError::Patch(error)
+    }
+}
+
+/// The files changed by [`apply_to_dir`], relative to the directory the patches were applied to
+///
+/// Every list is sorted.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DirReport {
+    /// Files that didn't exist before
+    pub created: Vec<PathBuf>,
+    /// Files that existed before and had their content changed
+    pub modified: Vec<PathBuf>,
+    /// Files that were removed
+    pub deleted: Vec<PathBuf>,
+    /// The `.rej` files written for hunks that couldn't be applied, with
+    /// [`ApplyOptions::reject_files`]
+    pub rejects: Vec<PathBuf>,
+    /// The backups made of files before they were changed, with [`ApplyOptions::backup_suffix`]
+    pub backups: Vec<PathBuf>,
+    /// Files whose permission bits were set by the `old mode`/`new mode` or `new file mode` lines
+    /// of git patches, with the bits they were set to, like `0o755`
+    ///
+    /// [`DiskFs`](crate::DiskFs) only sets them on Unix, but they are listed on every platform.
+    pub modes: Vec<(PathBuf, u32)>,
+}
+
+/// Apply a set of patches to the files in a directory
+///
+/// Paths in the patches are interpreted relative to `root` after removing
+/// [`ApplyOptions::strip`] leading components, and patches are matched up with
+/// files the same way [`PatchSet::apply_to`] does it. Patches referring to absolute paths or
+/// paths containing `..` are refused, so nothing outside of `root` can be touched. All patches
+/// are applied in memory first, so if any of them fails nothing on disk is changed.
+///
+/// Changed files are written to a temporary file next to the original, which then replaces the
+/// original with a rename, so readers never observe a partially written file. Files are created
+/// (along with their parent directories) or removed when the patches create or delete them,
+/// see [`Patch::file_op`](crate::Patch::file_op). The permission bits given by the mode lines of
+/// git patches, like `new mode 100755`, are set on Unix and listed in [`DirReport::modes`] on
+/// every platform.
+///
+/// Symbolic links are patched like git does it, as files whose content is their target: patches
+/// with the mode `120000` create and delete links, and patches of existing links change their
+/// target. [`ApplyOptions::forbid_symlinks`] refuses them instead. Files beneath a link, one that
+/// exists or one that a patch creates, are always refused, as the link could lead out of `root`.
+///
+/// With [`ApplyOptions::reject_files`], hunks that can't be applied no longer make the whole
+/// operation fail. They are left out, and written to `<file>.rej` next to the file they were
+/// meant for, which replaces any existing file of that name. Other errors, like a missing file,
+/// still stop everything before anything is written.
+///
+/// With [`ApplyOptions::backup_suffix`], the original content of every file that is modified or
+/// deleted is first saved to a file named like it with the suffix added. If that name is already
+/// taken, by an existing file or by a file the patches write, `.1`, `.2` and so on are added to
+/// it until it isn't, so no backup ever replaces another file.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use patch::{ApplyOptions, PatchSet, apply_to_dir};
+///
+/// let diff = std::fs::read_to_string("changes.diff").unwrap();
+/// let patches = PatchSet::parse(&diff).unwrap();
+/// let report = apply_to_dir(&patches, Path::new("."), &ApplyOptions::default()).unwrap();
+/// for path in &report.modified {
+///     println!("patching file {}", path.display());
+/// }
+/// ```
+pub fn apply_to_dir(
+    patches: &PatchSet,
+    root: &Path,
+    options: &ApplyOptions,
+) -> Result<DirReport, FsError> {
+    apply_to_vfs(patches, &mut DiskFs::new(root), options).map_err(|error| match error {
+        FsError::Io { path, error } => FsError::Io {
+            path: root.join(path),
+            error,
+        },
+        FsError::Symlink { path } => FsError::Symlink {
+            path: root.join(path),
+        },
+        error => error,
+    })
+}
+
+/// Apply a set of patches to the files of a [`Vfs`], like [`apply_to_dir`] applies them to a
+/// directory
+///
+/// The paths of [`FsError::Io`] errors are relative to the root of the filesystem.
+///
+/// # Example
+///
+/// ```
+/// use patch::{ApplyOptions, MemoryFs, PatchSet, apply_to_vfs};
+///
+/// let sample = "\
+/// --- /dev/null
+/// +++ docs/new.md
+/// @@ -0,0 +1 @@
+/// +# Docs
+/// ";
+///
+/// let mut files = MemoryFs::new();
+/// let patches = PatchSet::parse(sample).unwrap();
+/// let report = apply_to_vfs(&patches, &mut files, &ApplyOptions::default()).unwrap();
+/// assert_eq!(report.created.len(), 1);
+/// assert_eq!(files.get("docs/new.md"), Some("# Docs\n"));
+/// ```
+pub fn apply_to_vfs(
+    patches: &PatchSet,
+    vfs: &mut impl Vfs,
+    options: &ApplyOptions,
+) -> Result<DirReport, FsError> {
+    // Read every file a patch could refer to, with the target of symbolic links as their content
+    let mut files = HashMap::new();
+    let mut links = HashSet::new();
+    let created_links = created_links(patches, options.strip);
+    let mut checked = HashSet::new();
+    for patch in patches {
+        for file in [&patch.old, &patch.new] {
+            if is_dev_null(file) {
+                continue;
+            }
+            let path = strip_path(&file.path, options.strip)?;
+            if files.contains_key(&path) {
+                continue;
+            }
+            if let Some(link) = beneath_link(&path, &created_links) {
+                return Err(FsError::Symlink { path: link.clone() });
+            }
+            check_parents(vfs, &path, &mut checked)?;
+            let content = match vfs.read_link(&path) {
+                Ok(Some(_)) if options.forbid_symlinks => return Err(FsError::Symlink { path }),
+                Ok(Some(target)) => {
+                    links.insert(path.clone());
+                    Ok(target)
+                }
+                Ok(None) => vfs.read(&path),
+                Err(error) => Err(error),
+            };
+            match content {
+                Ok(content) => {
+                    files.insert(path, content);
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => return Err(FsError::Io { path, error }),
+            }
+        }
+
+        if options.forbid_symlinks && is_symlink_patch(patch) {
+            let file = if is_dev_null(&patch.new) {
+                &patch.old
+            } else {
+                &patch.new
+            };
+            let path = strip_path(&file.path, options.strip)?;
+            return Err(FsError::Symlink { path });
+        }
+    }
+
+    let (changes, rejected) = if options.reject_files {
+        patches.apply_partially_to(&files, options)?
+    } else {
+        (patches.apply_to_with_options(&files, options)?, Vec::new())
+    };
+
+    // Patches touching the same file share its `.rej` file
+    let mut rejects: Vec<(PathBuf, String)> = Vec::new();
+    for (path, patch) in rejected {
+        let path = with_suffix(&path, ".rej");
+        let text = format!("{}\n", patch);
+        match rejects.iter_mut().find(|(other, _)| *other == path) {
+            Some((_, content)) => content.push_str(&text),
+            None => rejects.push((path, text)),
+        }
+    }
+    rejects.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut changes: Vec<(PathBuf, FileChange)> = changes.into_iter().collect();
+    changes.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    // The modes the patches give their files, the last patch of a file winning
+    let mut modes = HashMap::new();
+    for patch in patches {
+        if let Some(mode) = mode_change(patch) {
+            modes.insert(strip_path(&patch.new.path, options.strip)?, mode);
+        }
+    }
+
+    // The files the patches write, which backups must not replace
+    let mut taken: HashSet<PathBuf> = changes
+        .iter()
+        .map(|(path, _)| path.clone())
+        .chain(rejects.iter().map(|(path, _)| path.clone()))
+        .collect();
+
+    let mut report = DirReport::default();
+    for (path, change) in changes {
+        if let (Some(suffix), Some(original)) = (&options.backup_suffix, files.get(&path)) {
+            let backup = backup_path(vfs, &path, suffix, &taken);
+            vfs.write(&backup, original).map_err(|error| FsError::Io {
+                path: backup.clone(),
+                error,
+            })?;
+            taken.insert(backup.clone());
+            report.backups.push(backup);
+        }
+
+        // Links stay links unless a patch gives them another mode
+        let mode = modes.get(&path).copied();
+        let link = mode.map_or(links.contains(&path), |mode| mode == SYMLINK_MODE);
+        let result = match &change {
+            FileChange::Created(content) | FileChange::Modified(content) if link => {
+                vfs.symlink(&path, content)
+            }
+            FileChange::Created(content) | FileChange::Modified(content) => {
+                vfs.write(&path, content)
+            }
+            FileChange::Deleted => vfs.delete(&path),
+        };
+        result.map_err(|error| FsError::Io {
+            path: path.clone(),
+            error,
+        })?;
+
+        if let (Some(mode), false) = (mode, link || change == FileChange::Deleted) {
+            let mode = mode & 0o777;
+            vfs.set_mode(&path, mode).map_err(|error| FsError::Io {
+                path: path.clone(),
+                error,
+            })?;
+            report.modes.push((path.clone(), mode));
+        }
+
+        match change {
+            FileChange::Created(_) => report.created.push(path),
+            FileChange::Modified(_) => report.modified.push(path),
+            FileChange::Deleted => report.deleted.push(path),
+        }
+    }
+
+    for (path, content) in rejects {
+        vfs.write(&path, &content).map_err(|error| FsError::Io {
+            path: path.clone(),
+            error,
+        })?;
+        report.rejects.push(path);
+    }
+
+    Ok(report)
+}
+
+// The paths of the symbolic links the patches create, or turn files into
+pub(crate) fn created_links<'p, 'a: 'p>(
+    patches: impl IntoIterator<Item = &'p Patch<'a>>,
+    strip: usize,
+) -> HashSet<PathBuf> {
+    patches
+        .into_iter()
+        .filter(|patch| mode_change(patch) == Some(SYMLINK_MODE))
+        .filter_map(|patch| strip_path(&patch.new.path, strip).ok())
+        .collect()
+}
+
+// The link of `links` that `path` is beneath, if any
+pub(crate) fn beneath_link<'l>(path: &Path, links: &'l HashSet<PathBuf>) -> Option<&'l PathBuf> {
+    path.ancestors()
+        .skip(1)
+        .find_map(|parent| links.get(parent))
+}
+
+// Refuse a path beneath a symbolic link that exists, through which a patch could reach outside of
+// the root. `checked` has the directories already known not to be links.
+fn check_parents(
+    vfs: &impl Vfs,
+    path: &Path,
+    checked: &mut HashSet<PathBuf>,
+) -> Result<(), FsError> {
+    for parent in path.ancestors().skip(1) {
+        if parent.as_os_str().is_empty() || checked.contains(parent) {
+            break;
+        }
+        let path = parent.to_path_buf();
+        match vfs.read_link(parent) {
+            Ok(Some(_)) => return Err(FsError::Symlink { path }),
+            Ok(None) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(FsError::Io { path, error }),
+        }
+        checked.insert(path);
+    }
+    Ok(())
+}
+
+// Whether a git patch creates, deletes or changes a symbolic link
+pub(crate) fn is_symlink_patch(patch: &Patch) -> bool {
+    let modes = patch
+        .git
+        .iter()
+        .flat_map(|git| [git.old_mode, git.new_mode]);
+    modes.flatten().any(|mode| mode == SYMLINK_MODE)
+}
+
+// The mode a git patch gives its file, if it creates it with a mode or changes its mode
+pub(crate) fn mode_change(patch: &Patch) -> Option<u32> {
+    let git = patch.git.as_ref()?;
+    let mode = git.new_mode.filter(|&mode| git.old_mode != Some(mode))?;
+    (patch.file_op() != FileOp::Delete).then_some(mode)
+}
+
+// The path with the given suffix added to its file name
+pub(crate) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+// The first path for the backup of `path` that isn't taken and doesn't exist in the filesystem
+fn backup_path(vfs: &impl Vfs, path: &Path, suffix: &str, taken: &HashSet<PathBuf>) -> PathBuf {
+    let backup = with_suffix(path, suffix);
+    let is_free = |candidate: &PathBuf| !taken.contains(candidate) && !vfs.exists(candidate);
+    if is_free(&backup) {
+        return backup;
+    }
+    (1..)
+        .map(|n| with_suffix(&backup, &format!(".{}", n)))
+        .find(is_free)
+        .expect("bug: ran out of backup names")
+}
+
+// Replace the file at `path` without ever leaving it partially written
+pub(crate) fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let temp_path = temp_path(path);
+    let result = fs::write(&temp_path, content).and_then(|()| {
+        // Keep the permissions of the file being replaced
+        match fs::metadata(path) {
+            Ok(metadata) => fs::set_permissions(&temp_path, metadata.permissions())?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        fs::rename(&temp_path, path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// A path next to `path` for writing its new content to, which no other write uses
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        file_name,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::vfs::MemoryFs;
+
+    const SAMPLE: &str = "\
+--- src/lib.rs
++++ src/lib.rs
+@@ -1,2 +1,2 @@
+-old
++new
+ unchanged
+--- /dev/null
++++ docs/new.md
+@@ -0,0 +1 @@
++# Docs
+--- obsolete.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+";
+
+    #[test]
+    fn test_apply_to_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "old\nunchanged\n").unwrap();
+        fs::write(dir.path().join("obsolete.txt"), "bye\n").unwrap();
+
+        let patches = PatchSet::parse(SAMPLE).unwrap();
+        let report = apply_to_dir(&patches, dir.path(), &ApplyOptions::default()).unwrap();
+        assert_eq!(
+            report,
+            DirReport {
+                created: vec![PathBuf::from("docs/new.md")],
+                modified: vec![PathBuf::from("src/lib.rs")],
+                deleted: vec![PathBuf::from("obsolete.txt")],
+                rejects: vec![],
+                backups: vec![],
+                modes: vec![],
+            }
+        );
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("src/lib.rs")).unwrap(),
+            "new\nunchanged\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("docs/new.md")).unwrap(),
+            "# Docs\n"
+        );
+        assert!(!dir.path().join("obsolete.txt").exists());
+
+        // No temporary files are left behind
+        let entries: Vec<_> = fs::read_dir(dir.path().join("src")).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn test_apply_to_dir_is_all_or_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "old\nunchanged\n").unwrap();
+
+        // obsolete.txt doesn't exist, so the last patch fails
+        let patches = PatchSet::parse(SAMPLE).unwrap();
+        let err = apply_to_dir(&patches, dir.path(), &ApplyOptions::default()).unwrap_err();
+        assert!(matches!(
+            err,
+            FsError::Patch(PatchSetError::FileNotFound { .. })
+        ));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("src/lib.rs")).unwrap(),
+            "old\nunchanged\n"
+        );
+        assert!(!dir.path().join("docs").exists());
+    }
+
+    #[test]
+    fn test_apply_to_dir_writes_rejects() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "A\nB\nC\nD\nE\nF\n").unwrap();
+
+        let patches = PatchSet::parse(
+            "\
+--- a.txt
++++ a.txt
+@@ -1,2 +1,3 @@
+ A
++A2
+ B
+@@ -5,2 +6,2 @@
+ E
+-Q
++X
+--- a.txt
++++ a.txt
+@@ -1 +1 @@
+-Z
++Y
+",
+        )
+        .unwrap();
+        let options = ApplyOptions {
+            reject_files: true,
+            ..ApplyOptions::default()
+        };
+        let report = apply_to_dir(&patches, dir.path(), &options).unwrap();
+        assert_eq!(report.modified, [PathBuf::from("a.txt")]);
+        assert_eq!(report.rejects, [PathBuf::from("a.txt.rej")]);
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+            "A\nA2\nB\nC\nD\nE\nF\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("a.txt.rej")).unwrap(),
+            "\
+--- a.txt
++++ a.txt
+@@ -5,2 +5,2 @@
+ E
+-Q
++X
+--- a.txt
++++ a.txt
+@@ -1,1 +1,1 @@
+-Z
++Y
+"
+        );
+    }
+
+    #[test]
+    fn test_apply_to_dir_makes_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::create_dir(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "old\nunchanged\n").unwrap();
+        fs::write(dir.path().join("obsolete.txt"), "bye\n").unwrap();
+        // An earlier backup is kept
+        fs::write(dir.path().join("obsolete.txt.orig"), "earlier\n").unwrap();
+
+        let options = ApplyOptions {
+            backup_suffix: Some(".orig".into()),
+            ..ApplyOptions::default()
+        };
+        let patches = PatchSet::parse(SAMPLE).unwrap();
+        let report = apply_to_dir(&patches, dir.path(), &options).unwrap();
+        assert_eq!(
+            report.backups,
+            [
+                PathBuf::from("obsolete.txt.orig.1"),
+                PathBuf::from("src/lib.rs.orig")
+            ]
+        );
+
+        let read = |path: &str| fs::read_to_string(dir.path().join(path)).unwrap();
+        assert_eq!(read("src/lib.rs.orig"), "old\nunchanged\n");
+        assert_eq!(read("obsolete.txt.orig.1"), "bye\n");
+        assert_eq!(read("obsolete.txt.orig"), "earlier\n");
+        assert!(!dir.path().join("docs/new.md.orig").exists());
+    }
+
+    #[test]
+    fn test_apply_to_vfs() {
+        let mut files: MemoryFs = [
+            ("src/lib.rs", "old\nunchanged\n"),
+            ("obsolete.txt", "bye\n"),
+        ]
+        .into_iter()
+        .collect();
+        let options = ApplyOptions {
+            backup_suffix: Some(".orig".into()),
+            ..ApplyOptions::default()
+        };
+        let patches = PatchSet::parse(SAMPLE).unwrap();
+        let report = apply_to_vfs(&patches, &mut files, &options).unwrap();
+        assert_eq!(report.created, [PathBuf::from("docs/new.md")]);
+        assert_eq!(
+            files.paths().collect::<Vec<_>>(),
+            [
+                Path::new("docs/new.md"),
+                Path::new("obsolete.txt.orig"),
+                Path::new("src/lib.rs"),
+                Path::new("src/lib.rs.orig"),
+            ]
+        );
+        assert_eq!(files.get("src/lib.rs"), Some("new\nunchanged\n"));
+
+        // Nothing is written when a patch fails
+        let mut files: MemoryFs = [("src/lib.rs", "old\nunchanged\n")].into_iter().collect();
+        let before = files.clone();
+        assert!(apply_to_vfs(&patches, &mut files, &options).is_err());
+        assert_eq!(files, before);
+    }
+
+    const MODE_CHANGES: &str = "\
+diff --git a/run.sh b/run.sh
+old mode 100644
+new mode 100755
+diff --git a/tool.py b/tool.py
+new file mode 100755
+--- /dev/null
++++ b/tool.py
+@@ -0,0 +1 @@
++print()
+";
+
+    #[test]
+    fn test_apply_to_vfs_binary_files_differ() {
+        let mut files: MemoryFs = [("logo.png", "")].into_iter().collect();
+        let options = ApplyOptions {
+            strip: 1,
+            ..ApplyOptions::default()
+        };
+        let diff =
+            "diff --git a/logo.png b/logo.png\nBinary files a/logo.png and b/logo.png differ\n";
+        let patches = PatchSet::parse(diff).unwrap();
+        let error = apply_to_vfs(&patches, &mut files, &options).unwrap_err();
+        assert!(
+            matches!(
+                &error,
+                FsError::Patch(PatchSetError::Apply { path, error: crate::ApplyError::Binary })
+                    if path == Path::new("logo.png")
+            ),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_apply_to_vfs_sets_modes() {
+        let mut files: MemoryFs = [("run.sh", "echo\n")].into_iter().collect();
+        let options = ApplyOptions {
+            strip: 1,
+            ..ApplyOptions::default()
+        };
+        let patches = PatchSet::parse(MODE_CHANGES).unwrap();
+        let report = apply_to_vfs(&patches, &mut files, &options).unwrap();
+        assert_eq!(
+            report.modes,
+            [
+                (PathBuf::from("run.sh"), 0o755),
+                (PathBuf::from("tool.py"), 0o755)
+            ]
+        );
+        assert_eq!(files.mode("run.sh"), Some(0o755));
+        assert_eq!(files.mode("tool.py"), Some(0o755));
+        assert_eq!(files.get("run.sh"), Some("echo\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_to_dir_sets_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("run.sh"), "echo\n").unwrap();
+        fs::set_permissions(dir.path().join("run.sh"), fs::Permissions::from_mode(0o644)).unwrap();
+        let options = ApplyOptions {
+            strip: 1,
+            ..ApplyOptions::default()
+        };
+        let patches = PatchSet::parse(MODE_CHANGES).unwrap();
+        apply_to_dir(&patches, dir.path(), &options).unwrap();
+        for path in ["run.sh", "tool.py"] {
+            let metadata = fs::metadata(dir.path().join(path)).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o755, "{}", path);
+        }
+    }
+
+    const SYMLINKS: &str = "\
+diff --git a/latest b/latest
+new file mode 120000
+--- /dev/null
++++ b/latest
+@@ -0,0 +1 @@
++v2.txt
+\\ No newline at end of file
+diff --git a/current b/current
+--- a/current
++++ b/current
+@@ -1 +1 @@
+-v1.txt
+\\ No newline at end of file
++v2.txt
+\\ No newline at end of file
+";
+
+    #[test]
+    fn test_apply_to_vfs_symlinks() {
+        let mut files = MemoryFs::new();
+        files.insert_symlink("current", "v1.txt");
+        let options = ApplyOptions {
+            strip: 1,
+            ..ApplyOptions::default()
+        };
+        let patches = PatchSet::parse(SYMLINKS).unwrap();
+        let report = apply_to_vfs(&patches, &mut files, &options).unwrap();
+        assert!(report.modes.is_empty());
+        for path in ["latest", "current"] {
+            assert_eq!(
+                files.read_link(Path::new(path)).unwrap().as_deref(),
+                Some("v2.txt")
+            );
+        }
+
+        let mut files = MemoryFs::new();
+        files.insert_symlink("current", "v1.txt");
+        let options = ApplyOptions {
+            forbid_symlinks: true,
+            ..options
+        };
+        let error = apply_to_vfs(&patches, &mut files, &options).unwrap_err();
+        assert!(matches!(error, FsError::Symlink { .. }), "{:?}", error);
+        assert_eq!(
+            files.read_link(Path::new("current")).unwrap().as_deref(),
+            Some("v1.txt")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_to_dir_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink("v1.txt", dir.path().join("current")).unwrap();
+        let options = ApplyOptions {
+            strip: 1,
+            ..ApplyOptions::default()
+        };
+        let patches = PatchSet::parse(SYMLINKS).unwrap();
+        apply_to_dir(&patches, dir.path(), &options).unwrap();
+        for path in ["latest", "current"] {
+            let target = fs::read_link(dir.path().join(path)).unwrap();
+            assert_eq!(target, Path::new("v2.txt"));
+        }
+
+        let options = ApplyOptions {
+            forbid_symlinks: true,
+            ..options
+        };
+        let error = apply_to_dir(&patches, dir.path(), &options).unwrap_err();
+        assert!(matches!(error, FsError::Symlink { .. }), "{:?}", error);
+    }
+
+    // A patch creating a link to `target`, and one creating a file beneath it
+    fn escape(target: &Path) -> String {
+        format!(
+            "\
+diff --git a/evil b/evil
+new file mode 120000
+--- /dev/null
++++ b/evil
+@@ -0,0 +1 @@
++{}
+\\ No newline at end of file
+diff --git a/evil/pwned b/evil/pwned
+new file mode 100644
+--- /dev/null
++++ b/evil/pwned
+@@ -0,0 +1 @@
++pwned
+",
+            target.display()
+        )
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_to_dir_beneath_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (root, outside) = (dir.path().join("root"), dir.path().join("outside"));
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        let options = ApplyOptions {
+            strip: 1,
+            ..ApplyOptions::default()
+        };
+
+        // A file beneath a link the patches create
+        let diff = escape(&outside);
+        let patches = PatchSet::parse(&diff).unwrap();
+        let error = apply_to_dir(&patches, &root, &options).unwrap_err();
+        assert!(
+            matches!(&error, FsError::Symlink { path } if *path == root.join("evil")),
+            "{:?}",
+            error
+        );
+        assert!(fs::symlink_metadata(root.join("evil")).is_err());
+
+        // A file beneath a link that exists, with or without `forbid_symlinks`
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        let patches = PatchSet::parse(
+            "\
+--- /dev/null
++++ b/link/file
+@@ -0,0 +1 @@
++pwned
+",
+        )
+        .unwrap();
+        for forbid_symlinks in [false, true] {
+            let options = ApplyOptions {
+                forbid_symlinks,
+                ..options.clone()
+            };
+            let error = apply_to_dir(&patches, &root, &options).unwrap_err();
+            assert!(
+                matches!(&error, FsError::Symlink { path } if *path == root.join("link")),
+                "{:?}",
+                error
+            );
+        }
+        assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,1013 @@
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::ops::Range as IndexRange;
+
+use crate::applier::range_index;
+use crate::ast::{File, FileOp, Hunk, Line, Patch, Range};
+use crate::cancel::{CancellationToken, cancelled};
+
+mod histogram;
+mod indent;
+mod patience;
+
+/// A single step of an edit script turning the old lines into the new lines
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Edit {
+    /// The old line at the first index is identical to the new line at the second index
+    Equal(usize, usize),
+    /// The old line at this index was removed
+    Delete(usize),
+    /// The new line at this index was added
+    Insert(usize),
+}
+
+/// The algorithm used to compute the differences between two files
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum DiffAlgorithm {
+    /// Myers' O(ND) algorithm, which always produces a minimal diff
+    #[default]
+    Myers,
+    /// Anchors the diff on lines that occur exactly once in both files before falling back to
+    /// Myers' algorithm between those anchors, like `git diff --patience`
+    ///
+    /// The result is not always minimal but tends to be easier to read for source code, since
+    /// common lines like `}` or blank lines don't get matched up across unrelated changes.
+    Patience,
+    /// Anchors the diff on the longest common region containing the least frequent lines, like
+    /// `git diff --histogram`
+    ///
+    /// This produces results similar to [`DiffAlgorithm::Patience`], but also finds anchors when
+    /// no line is unique, and is usually the fastest choice for large files with many repeated
+    /// lines.
+    Histogram,
+}
+
+/// Options controlling how a diff is generated
+///
+/// # Example
+///
+/// ```
+/// use patch::{DiffAlgorithm, DiffOptions, diff_with_options};
+///
+/// let options = DiffOptions {
+///     algorithm: DiffAlgorithm::Histogram,
+///     ..DiffOptions::default()
+/// };
+/// let patch = diff_with_options("a\nb\n", "a\nc\n", &options);
+/// assert_eq!(patch.hunks.len(), 1);
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DiffOptions {
+    /// The algorithm used to compute the differences
+    pub algorithm: DiffAlgorithm,
+    /// The number of unchanged lines to include around each change
+    pub context: usize,
+    /// How to find the function or section every hunk is in, which is written after the ranges
+    /// of its header like `git diff` does, as in `@@ -10,7 +10,8 @@ fn main() {`
+    ///
+    /// The hunks of the returned patch have no [`range_hint`](crate::Hunk::range_hint) by
+    /// default.
+    pub function_context: Option<FunctionContext>,
+    /// Slide the runs of added or removed lines to where they read best, like git's indent
+    /// heuristic
+    ///
+    /// A run that adds or removes a block of code after a repeated line like `}` could just as
+    /// well start one line earlier, so the algorithm may show it changing the end of the block
+    /// before it instead of the whole new block. This is on by default like in git; use
+    /// [`Patch::normalize`] to do the same for parsed patches.
+    pub indent_heuristic: bool,
+    /// A token that stops a slow diff, for example of large files with few lines in common,
+    /// when it is cancelled from another thread
+    ///
+    /// The differences that are left to find when the token is cancelled are given up on: the
+    /// lines between the ones already matched are all removed and added, so the patch is still
+    /// correct, only larger than it would have been. Check
+    /// [`is_cancelled`](CancellationToken::is_cancelled) to tell whether it is complete.
+    pub cancel: Option<CancellationToken>,
+}
+
+/// How the function or section a hunk is in is found, see [`DiffOptions::function_context`]
+///
+/// The old file is searched backwards from the line before the first line of the hunk for a
+/// line naming a function or section, which is written after the ranges of the hunk header,
+/// trimmed and cut to 80 bytes like git does.
+///
+/// # Example
+///
+/// ```
+/// use patch::{DiffOptions, FunctionContext, diff_with_options};
+///
+/// let old = "fn main() {\n    one();\n    two();\n    three();\n}\n";
+/// let new = "fn main() {\n    one();\n    two();\n    four();\n}\n";
+/// let options = DiffOptions {
+///     context: 1,
+///     function_context: Some(FunctionContext::Default),
+///     ..DiffOptions::default()
+/// };
+/// let patch = diff_with_options(old, new, &options);
+/// assert_eq!(patch.hunks[0].hint(), Some("fn main() {"));
+/// ```
+#[derive(Debug, Clone)]
+pub enum FunctionContext {
+    /// The last line that starts with a letter, `_` or `$`, like git does for files without a
+    /// diff driver
+    Default,
+    /// The last line matching one of the patterns, like the `xfuncname` patterns of a git diff
+    /// driver, which are usually chosen for the language of the file
+    ///
+    /// The first pattern that matches a line finds it. The text written for the line is the
+    /// first group the pattern captures, or the whole match if it captures none.
+    #[cfg(feature = "regex")]
+    Patterns(Vec<regex::Regex>),
+}
+
+impl FunctionContext {
+    /// Compile the given patterns into [`FunctionContext::Patterns`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::FunctionContext;
+    ///
+    /// let python = FunctionContext::patterns(&[r"^\s*((class|(async\s+)?def)\s.*)$"]).unwrap();
+    /// ```
+    #[cfg(feature = "regex")]
+    pub fn patterns(patterns: &[&str]) -> Result<Self, regex::Error> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| regex::Regex::new(pattern))
+            .collect::<Result<_, _>>()?;
+        Ok(FunctionContext::Patterns(patterns))
+    }
+
+    // The text naming the function or section of the line, if it starts one
+    fn heading<'a>(&self, line: &'a str) -> Option<&'a str> {
+        match self {
+            FunctionContext::Default => line
+                .starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '$')
+                .then_some(line),
+            #[cfg(feature = "regex")]
+            FunctionContext::Patterns(patterns) => patterns.iter().find_map(|pattern| {
+                let captures = pattern.captures(line)?;
+                Some(captures.get(1).or(captures.get(0))?.as_str())
+            }),
+        }
+    }
+}
+
+// Patterns are compared by their text, since compiled regular expressions can't be compared
+impl PartialEq for FunctionContext {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (FunctionContext::Default, FunctionContext::Default) => true,
+            #[cfg(feature = "regex")]
+            (FunctionContext::Patterns(patterns), FunctionContext::Patterns(others)) => patterns
+                .iter()
+                .map(regex::Regex::as_str)
+                .eq(others.iter().map(regex::Regex::as_str)),
+            #[cfg(feature = "regex")]
+            _ => false,
+        }
+    }
+}
+
+impl Eq for FunctionContext {}
+
+// The heading git writes for a hunk starting after the given number of lines of the old file
+fn hunk_heading<'a>(
+    lines: &[&'a str],
+    lines_before: usize,
+    function_context: &FunctionContext,
+) -> Option<&'a str> {
+    let heading = lines[..lines_before.min(lines.len())]
+        .iter()
+        .rev()
+        .find_map(|line| function_context.heading(line))?
+        .trim_end();
+    let end = (0..=heading.len().min(80))
+        .rev()
+        .find(|&end| heading.is_char_boundary(end))
+        .unwrap_or(0);
+    Some(&heading[..end])
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: DiffAlgorithm::default(),
+            context: 3,
+            function_context: None,
+            indent_heuristic: true,
+            cancel: None,
+        }
+    }
+}
+
+/// Produce a patch describing the changes needed to turn `old` into `new`
+///
+/// The differences are computed line by line with [Myers' algorithm][myers] and grouped into
+/// hunks with up to `context` unchanged lines surrounding each change, the same way
+/// `diff -U <context>` does. Hunks whose context would overlap are merged into one.
+///
+/// The file headers of the returned patch are named `old` and `new`. Overwrite `patch.old.path`
+/// and `patch.new.path` if the patch should refer to real files.
+///
+/// # Example
+///
+/// ```
+/// use patch::{apply, diff};
+///
+/// let old = "line 1\nline 2\nline 3\n";
+/// let new = "line 1\nnew line 2\nline 3\n";
+///
+/// let patch = diff(old, new, 3);
+/// assert_eq!(
+///     patch.to_string(),
+///     "\
+/// --- old
+/// +++ new
+/// @@ -1,3 +1,3 @@
+///  line 1
+/// -line 2
+/// +new line 2
+///  line 3"
+/// );
+/// assert_eq!(apply(&patch, old).unwrap(), new);
+/// ```
+///
+/// [myers]: http://www.xmailserver.org/diff2.pdf
+pub fn diff<'a>(old: &'a str, new: &'a str, context: usize) -> Patch<'a> {
+    diff_with_options(
+        old,
+        new,
+        &DiffOptions {
+            context,
+            ..DiffOptions::default()
+        },
+    )
+}
+
+/// Produce a patch describing the changes needed to turn `old` into `new`, choosing the diff
+/// algorithm and the amount of context with `options`
+///
+/// See [`diff`] for details about the returned patch.
+///
+/// # Example
+///
+/// ```
+/// use patch::{DiffAlgorithm, DiffOptions, diff_with_options};
+///
+/// let options = DiffOptions {
+///     algorithm: DiffAlgorithm::Patience,
+///     context: 1,
+///     ..DiffOptions::default()
+/// };
+/// let patch = diff_with_options("a\nb\nc\n", "a\nc\n", &options);
+/// assert_eq!(patch.to_string(), "--- old\n+++ new\n@@ -1,3 +1,2 @@\n a\n-b\n c");
+/// ```
+pub fn diff_with_options<'a>(old: &'a str, new: &'a str, options: &DiffOptions) -> Patch<'a> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let old_end_newline = old.is_empty() || old.ends_with('\n');
+    let new_end_newline = new.is_empty() || new.ends_with('\n');
+
+    // A last line without a newline character is different from the same line with one
+    let old_keys = missing_newlines(&old_lines, old_end_newline);
+    let new_keys = missing_newlines(&new_lines, new_end_newline);
+    let mut edits = cancellable_edit_script(
+        &old_keys,
+        &new_keys,
+        options.algorithm,
+        options.cancel.as_ref(),
+    );
+    if options.indent_heuristic {
+        indent::slide_runs(&mut edits, &old_keys, &new_keys, &old_lines, &new_lines);
+    }
+    let mut hunks = build_hunks(&edits, &old_lines, &new_lines, options.context);
+    if let Some(function_context) = &options.function_context {
+        for hunk in &mut hunks {
+            let lines_before = range_index(&hunk.old_range);
+            if let Some(heading) = hunk_heading(&old_lines, lines_before, function_context) {
+                hunk.range_hint = Cow::Owned(format!(" {}", heading));
+            }
+        }
+    }
+
+    // A missing newline character is only marked if the last hunk reaches the end of the file
+    let reaches_end =
+        |range: &Range, len: usize| range.count > 0 && range.start + range.count - 1 == len as u64;
+    let last = hunks.last();
+    let old_end_newline =
+        old_end_newline || !last.is_some_and(|hunk| reaches_end(&hunk.old_range, old_lines.len()));
+    let new_end_newline =
+        new_end_newline || !last.is_some_and(|hunk| reaches_end(&hunk.new_range, new_lines.len()));
+
+    Patch {
+        old: File {
+            path: Cow::Borrowed("old"),
+            meta: None,
+        },
+        new: File {
+            path: Cow::Borrowed("new"),
+            meta: None,
+        },
+        operation: FileOp::Modify,
+        git: None,
+        svn: None,
+        hunks,
+        binary: None,
+        opaque_binary: false,
+        preamble: None,
+        raw: None,
+        old_end_newline,
+        new_end_newline,
+    }
+}
+
+// Pair every line with whether it is the last one and lacks a newline character
+pub(crate) fn missing_newlines<'a>(lines: &[&'a str], end_newline: bool) -> Vec<(&'a str, bool)> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| (*line, !end_newline && i + 1 == lines.len()))
+        .collect()
+}
+
+/// Compute an edit script between two sequences of lines with the given algorithm
+pub(crate) fn edit_script<T: Hash + Eq>(
+    old: &[T],
+    new: &[T],
+    algorithm: DiffAlgorithm,
+) -> Vec<Edit> {
+    cancellable_edit_script(old, new, algorithm, None)
+}
+
+/// Compute an edit script like [`edit_script`], removing and adding all of the lines that are
+/// left once `cancel` is cancelled
+pub(crate) fn cancellable_edit_script<T: Hash + Eq>(
+    old: &[T],
+    new: &[T],
+    algorithm: DiffAlgorithm,
+    cancel: Option<&CancellationToken>,
+) -> Vec<Edit> {
+    let mut edits = Vec::with_capacity(old.len().max(new.len()));
+    let (old_range, new_range) = (0..old.len(), 0..new.len());
+    match algorithm {
+        DiffAlgorithm::Myers => myers(old, old_range, new, new_range, &mut edits, cancel),
+        DiffAlgorithm::Patience => {
+            patience::patience(old, old_range, new, new_range, &mut edits, cancel)
+        }
+        DiffAlgorithm::Histogram => {
+            histogram::histogram(old, old_range, new, new_range, &mut edits, cancel)
+        }
+    }
+    removals_first(&mut edits);
+    edits
+}
+
+/// Compute the shortest edit script between the given ranges of two sequences of lines
+pub(crate) fn myers<T: PartialEq>(
+    old: &[T],
+    old_range: IndexRange<usize>,
+    new: &[T],
+    new_range: IndexRange<usize>,
+    edits: &mut Vec<Edit>,
+    cancel: Option<&CancellationToken>,
+) {
+    let max_d = (old_range.len() + new_range.len()).div_ceil(2) + 1;
+    let mut search = Search {
+        vf: V::new(max_d),
+        vb: V::new(max_d),
+        cancel,
+    };
+    conquer(old, old_range, new, new_range, &mut search, edits);
+}
+
+// The furthest points of the forward and backward searches for the middle snake, which are
+// reused by every part of the problem, and the token that stops them
+struct Search<'c> {
+    vf: V,
+    vb: V,
+    cancel: Option<&'c CancellationToken>,
+}
+
+// Within a run of changes the order of removals and additions is arbitrary. Diff programs
+// conventionally list the removed lines first, so reorder each run to match.
+fn removals_first(edits: &mut [Edit]) {
+    for run in edits.split_mut(|edit| matches!(edit, Edit::Equal(..))) {
+        run.sort_by_key(|edit| match *edit {
+            Edit::Delete(o) => (0, o),
+            Edit::Insert(n) => (1, n),
+            Edit::Equal(..) => unreachable!(),
+        });
+    }
+}
+
+/// The furthest reaching x coordinate of each diagonal k, indexable by negative k
+struct V {
+    offset: isize,
+    v: Vec<usize>,
+}
+
+impl V {
+    fn new(max_d: usize) -> Self {
+        Self {
+            offset: max_d as isize,
+            v: vec![0; 2 * max_d + 2],
+        }
+    }
+}
+
+impl core::ops::Index<isize> for V {
+    type Output = usize;
+
+    fn index(&self, k: isize) -> &usize {
+        &self.v[(k + self.offset) as usize]
+    }
+}
+
+impl core::ops::IndexMut<isize> for V {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.v[(k + self.offset) as usize]
+    }
+}
+
+fn common_prefix_len<T: PartialEq>(
+    old: &[T],
+    old_range: IndexRange<usize>,
+    new: &[T],
+    new_range: IndexRange<usize>,
+) -> usize {
+    old[old_range]
+        .iter()
+        .zip(&new[new_range])
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+fn common_suffix_len<T: PartialEq>(
+    old: &[T],
+    old_range: IndexRange<usize>,
+    new: &[T],
+    new_range: IndexRange<usize>,
+) -> usize {
+    old[old_range]
+        .iter()
+        .rev()
+        .zip(new[new_range].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+// Finds a point on an optimal edit path that splits the problem into two smaller ones, searching
+// forward from the start and backward from the end at the same time until the paths overlap.
+// This is the linear space refinement described in section 4b of Myers' paper.
+fn find_middle_snake<T: PartialEq>(
+    old: &[T],
+    old_range: IndexRange<usize>,
+    new: &[T],
+    new_range: IndexRange<usize>,
+    search: &mut Search,
+) -> Option<(usize, usize)> {
+    let Search { vf, vb, cancel } = search;
+    let n = old_range.len();
+    let m = new_range.len();
+
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+
+    vf[1] = 0;
+    vb[1] = 0;
+
+    let d_max = (n + m).div_ceil(2) + 1;
+    for d in 0..d_max as isize {
+        // Giving up replaces the whole range
+        if cancelled(*cancel) {
+            return None;
+        }
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && vf[k - 1] < vf[k + 1]) {
+                vf[k + 1]
+            } else {
+                vf[k - 1] + 1
+            };
+            let y = (x as isize - k) as usize;
+            let (x0, y0) = (x, y);
+            if x < n && y < m {
+                x += common_prefix_len(
+                    old,
+                    old_range.start + x..old_range.end,
+                    new,
+                    new_range.start + y..new_range.end,
+                );
+            }
+            vf[k] = x;
+            if odd && (k - delta).abs() < d && vf[k] + vb[-(k - delta)] >= n {
+                return Some((x0 + old_range.start, y0 + new_range.start));
+            }
+        }
+
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && vb[k - 1] < vb[k + 1]) {
+                vb[k + 1]
+            } else {
+                vb[k - 1] + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            if x < n && y < m {
+                let advance = common_suffix_len(
+                    old,
+                    old_range.start..old_range.start + n - x,
+                    new,
+                    new_range.start..new_range.start + m - y,
+                );
+                x += advance;
+                y += advance;
+            }
+            vb[k] = x;
+            if !odd && (k - delta).abs() <= d && vb[k] + vf[-(k - delta)] >= n {
+                return Some((n - x + old_range.start, m - y + new_range.start));
+            }
+        }
+    }
+
+    None
+}
+
+fn conquer<T: PartialEq>(
+    old: &[T],
+    mut old_range: IndexRange<usize>,
+    new: &[T],
+    mut new_range: IndexRange<usize>,
+    search: &mut Search,
+    edits: &mut Vec<Edit>,
+) {
+    let prefix = common_prefix_len(old, old_range.clone(), new, new_range.clone());
+    for i in 0..prefix {
+        edits.push(Edit::Equal(old_range.start + i, new_range.start + i));
+    }
+    old_range.start += prefix;
+    new_range.start += prefix;
+
+    let suffix = common_suffix_len(old, old_range.clone(), new, new_range.clone());
+    let suffix_old = old_range.end - suffix;
+    let suffix_new = new_range.end - suffix;
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    if old_range.is_empty() {
+        edits.extend(new_range.map(Edit::Insert));
+    } else if new_range.is_empty() {
+        edits.extend(old_range.map(Edit::Delete));
+    } else if let Some((x, y)) =
+        find_middle_snake(old, old_range.clone(), new, new_range.clone(), search)
+    {
+        conquer(
+            old,
+            old_range.start..x,
+            new,
+            new_range.start..y,
+            search,
+            edits,
+        );
+        conquer(old, x..old_range.end, new, y..new_range.end, search, edits);
+    } else {
+        edits.extend(old_range.map(Edit::Delete));
+        edits.extend(new_range.map(Edit::Insert));
+    }
+
+    for i in 0..suffix {
+        edits.push(Edit::Equal(suffix_old + i, suffix_new + i));
+    }
+}
+
+/// Group an edit script into hunks with the given amount of surrounding context
+pub(crate) fn build_hunks<'a>(
+    edits: &[Edit],
+    old: &[&'a str],
+    new: &[&'a str],
+    context: usize,
+) -> Vec<Hunk<'a>> {
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal(..)))
+        .map(|(i, _)| i)
+        .collect();
+
+    // Each group is a range of indices into `edits`, including the surrounding context
+    let mut groups: Vec<IndexRange<usize>> = Vec::new();
+    for &i in &changes {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(edits.len());
+        match groups.last_mut() {
+            Some(group) if start <= group.end => group.end = end,
+            _ => groups.push(start..end),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|group| {
+            // Count the lines of each file that precede the hunk
+            let (old_before, new_before) = match edits[group.start] {
+                Edit::Equal(o, n) => (o, n),
+                Edit::Delete(o) => (o, new_position(edits, group.start)),
+                Edit::Insert(n) => (old_position(edits, group.start), n),
+            };
+
+            let lines: Vec<Line<'a>> = edits[group]
+                .iter()
+                .map(|edit| match *edit {
+                    Edit::Equal(o, _) => Line::Context(Cow::Borrowed(old[o])),
+                    Edit::Delete(o) => Line::Remove(Cow::Borrowed(old[o])),
+                    Edit::Insert(n) => Line::Add(Cow::Borrowed(new[n])),
+                })
+                .collect();
+
+            let old_count = lines
+                .iter()
+                .filter(|line| !matches!(line, Line::Add(_)))
+                .count() as u64;
+            let new_count = lines
+                .iter()
+                .filter(|line| !matches!(line, Line::Remove(_)))
+                .count() as u64;
+
+            Hunk {
+                old_range: hunk_range(old_before as u64, old_count),
+                new_range: hunk_range(new_before as u64, new_count),
+                range_hint: Cow::Borrowed(""),
+                lines,
+            }
+        })
+        .collect()
+}
+
+// An empty range refers to the line before the change, a non-empty one to its first line
+pub(crate) fn hunk_range(lines_before: u64, count: u64) -> Range {
+    Range {
+        start: if count == 0 {
+            lines_before
+        } else {
+            lines_before + 1
+        },
+        count,
+    }
+}
+
+// Recover the position in the old file at an insertion, which an `Edit::Insert` doesn't record
+fn old_position(edits: &[Edit], index: usize) -> usize {
+    edits[..index]
+        .iter()
+        .rev()
+        .find_map(|edit| match *edit {
+            Edit::Equal(o, _) | Edit::Delete(o) => Some(o + 1),
+            Edit::Insert(_) => None,
+        })
+        .unwrap_or(0)
+}
+
+// Recover the position in the new file at a deletion, which an `Edit::Delete` doesn't record
+fn new_position(edits: &[Edit], index: usize) -> usize {
+    edits[..index]
+        .iter()
+        .rev()
+        .find_map(|edit| match *edit {
+            Edit::Equal(_, n) | Edit::Insert(n) => Some(n + 1),
+            Edit::Delete(_) => None,
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apply;
+
+    use pretty_assertions::assert_eq;
+
+    fn lines(s: &str) -> Vec<&str> {
+        s.lines().collect()
+    }
+
+    // Rebuild both sides from the edit script to make sure it is a valid transformation
+    fn check_edits(old: &str, new: &str, algorithm: DiffAlgorithm) -> Vec<Edit> {
+        let (old, new) = (lines(old), lines(new));
+        let edits = edit_script(&old, &new, algorithm);
+        let mut rebuilt_old = Vec::new();
+        let mut rebuilt_new = Vec::new();
+        for edit in &edits {
+            match *edit {
+                Edit::Equal(o, n) => {
+                    assert_eq!(old[o], new[n]);
+                    rebuilt_old.push(old[o]);
+                    rebuilt_new.push(new[n]);
+                }
+                Edit::Delete(o) => rebuilt_old.push(old[o]),
+                Edit::Insert(n) => rebuilt_new.push(new[n]),
+            }
+        }
+        assert_eq!(rebuilt_old, old);
+        assert_eq!(rebuilt_new, new);
+        edits
+    }
+
+    #[test]
+    fn test_edit_scripts() {
+        for algorithm in [
+            DiffAlgorithm::Myers,
+            DiffAlgorithm::Patience,
+            DiffAlgorithm::Histogram,
+        ] {
+            check_edits("", "", algorithm);
+            check_edits("a\nb\nc", "", algorithm);
+            check_edits("", "a\nb\nc", algorithm);
+            check_edits("a\nb\nc", "a\nb\nc", algorithm);
+            check_edits("a\nb\nc\na\nb\nb\na", "c\nb\na\nb\na\nc", algorithm);
+            check_edits("x\ny\nz", "a\nb\nc\nd", algorithm);
+            check_edits("a\nx\nb\ny\nc", "c\ny\nb\nx\na", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_myers_is_minimal() {
+        // The classic example from the paper has an edit distance of 5
+        let edits = check_edits(
+            "a\nb\nc\na\nb\nb\na",
+            "c\nb\na\nb\na\nc",
+            DiffAlgorithm::Myers,
+        );
+        let changes = edits
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Equal(..)))
+            .count();
+        assert_eq!(changes, 5);
+    }
+
+    #[test]
+    fn test_patience_anchors_on_unique_lines() {
+        // Moving a function in front of another one. Myers matches up the braces and blank lines
+        // of the two functions, while patience keeps the untouched function intact.
+        let old = "\
+fn one() {
+    1
+}
+
+fn two() {
+    2
+}
+";
+        let new = "\
+fn two() {
+    2
+}
+
+fn one() {
+    1
+}
+";
+        let options = DiffOptions {
+            algorithm: DiffAlgorithm::Patience,
+            context: 1,
+            ..DiffOptions::default()
+        };
+        let patch = diff_with_options(old, new, &options);
+        let changed: Vec<&Line> = patch.hunks.iter().flat_map(|hunk| &hunk.lines).collect();
+        assert!(
+            changed
+                .iter()
+                .all(|line| !matches!(line, Line::Add(s) | Line::Remove(s) if s.contains("two")))
+        );
+        assert_eq!(apply(&patch, old).unwrap(), new);
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let patch = diff("a\nb\n", "a\nb\n", 3);
+        assert!(patch.hunks.is_empty());
+    }
+
+    #[test]
+    fn test_diff_separate_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new = "1\nzwei\n3\n4\n5\n6\n7\n8\nneun\n10\n";
+        let patch = diff(old, new, 1);
+        assert_eq!(
+            format!("{}\n", patch),
+            "\
+--- old
++++ new
+@@ -1,3 +1,3 @@
+ 1
+-2
++zwei
+ 3
+@@ -8,3 +8,3 @@
+ 8
+-9
++neun
+ 10
+"
+        );
+        assert_eq!(apply(&patch, old).unwrap(), new);
+    }
+
+    #[test]
+    fn test_diff_merges_close_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n";
+        let new = "1\ntwo\n3\n4\nfive\n6\n";
+        let patch = diff(old, new, 1);
+        assert_eq!(patch.hunks.len(), 1);
+        assert_eq!(patch.hunks[0].old_range, Range { start: 1, count: 6 });
+        assert_eq!(apply(&patch, old).unwrap(), new);
+    }
+
+    #[test]
+    fn test_diff_empty_sides() {
+        let patch = diff("", "a\nb\n", 3);
+        assert_eq!(patch.hunks[0].old_range, Range { start: 0, count: 0 });
+        assert_eq!(patch.hunks[0].new_range, Range { start: 1, count: 2 });
+        assert_eq!(apply(&patch, "").unwrap(), "a\nb\n");
+
+        let patch = diff("a\nb\n", "", 3);
+        assert_eq!(patch.hunks[0].old_range, Range { start: 1, count: 2 });
+        assert_eq!(patch.hunks[0].new_range, Range { start: 0, count: 0 });
+        assert_eq!(apply(&patch, "a\nb\n").unwrap(), "");
+    }
+
+    #[test]
+    fn test_diff_round_trip() {
+        let old = "fn main() {\n    println!(\"hi\");\n}\n\nfn other() {}\n";
+        let new = "use std::io;\n\nfn main() {\n    println!(\"hello\");\n}\n";
+        let patch = diff(old, new, 3);
+        let text = format!("{}\n", patch);
+        let parsed = Patch::from_single(&text).unwrap();
+        assert_eq!(parsed, patch);
+        assert_eq!(apply(&parsed, old).unwrap(), new);
+    }
+
+    #[test]
+    fn test_diff_missing_newlines() {
+        // Adding a newline character to the last line changes it
+        let patch = diff("a\nb", "a\nb\n", 3);
+        assert_eq!(
+            patch.to_string(),
+            "--- old\n+++ new\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+b"
+        );
+        assert_eq!(apply(&patch, "a\nb").unwrap(), "a\nb\n");
+        assert_eq!(apply(&patch.reverse(), "a\nb\n").unwrap(), "a\nb");
+
+        let patch = diff("a\nb", "a\nc", 3);
+        let text = format!("{}\n", patch);
+        assert_eq!(Patch::from_single(&text).unwrap(), patch);
+        assert_eq!(apply(&patch, "a\nb").unwrap(), "a\nc");
+
+        // Missing newlines outside of the hunks aren't marked
+        let patch = diff("a\nb\nc\nd\ne", "A\nb\nc\nd\ne", 1);
+        assert!(patch.old_end_newline && patch.new_end_newline);
+        assert_eq!(apply(&patch, "a\nb\nc\nd\ne").unwrap(), "A\nb\nc\nd\ne");
+    }
+
+    #[test]
+    fn test_histogram_repetitive_lines() {
+        let old = "}\n}\nx\n}\n}\ny\n}\n";
+        let new = "}\nx\n}\nz\n}\n}\ny\n}\n}\n";
+        let options = DiffOptions {
+            algorithm: DiffAlgorithm::Histogram,
+            context: 1,
+            ..DiffOptions::default()
+        };
+        let patch = diff_with_options(old, new, &options);
+        assert_eq!(apply(&patch, old).unwrap(), new);
+    }
+
+    #[test]
+    fn test_diff_cancelled() {
+        let (old, new) = ("a\nb\nc\nd\n", "a\nx\nc\ny\n");
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        for algorithm in [
+            DiffAlgorithm::Myers,
+            DiffAlgorithm::Patience,
+            DiffAlgorithm::Histogram,
+        ] {
+            let options = DiffOptions {
+                algorithm,
+                ..DiffOptions::default()
+            };
+            assert_eq!(diff_with_options(old, new, &options).stats().deletions, 2);
+            // The lines left to compare are all replaced, which still gives a valid patch
+            let options = DiffOptions {
+                cancel: Some(cancel.clone()),
+                ..options
+            };
+            let patch = diff_with_options(old, new, &options);
+            let stats = patch.stats();
+            assert_eq!(
+                (stats.deletions, stats.additions),
+                (3, 3),
+                "{:?}",
+                algorithm
+            );
+            assert_eq!(apply(&patch, old).unwrap(), new);
+        }
+    }
+
+    #[test]
+    fn test_indent_heuristic() {
+        let block = "    if b {\n        x();\n    }\n";
+        let old = format!("fn f() {{\n{block}    if a {{\n        x();\n    }}\n}}\n");
+        let new = format!("fn f() {{\n{block}{block}    if a {{\n        x();\n    }}\n}}\n");
+        let options = DiffOptions {
+            context: 1,
+            ..DiffOptions::default()
+        };
+        let patch = diff_with_options(&old, &new, &options);
+        assert_eq!(
+            patch.hunks[0].to_string(),
+            "@@ -1,2 +1,5 @@\n fn f() {\n+    if b {\n+        x();\n+    }\n     if b {"
+        );
+        assert_eq!(apply(&patch, &old).unwrap(), new);
+
+        // Parsed patches only slide within the context of their hunks
+        let options = DiffOptions {
+            context: 4,
+            indent_heuristic: false,
+            ..DiffOptions::default()
+        };
+        let mut unslid = diff_with_options(&old, &new, &options);
+        let added = |patch: &Patch| {
+            patch.hunks[0]
+                .lines
+                .iter()
+                .position(|line| matches!(line, Line::Add(_)))
+        };
+        assert_eq!(added(&unslid), Some(4));
+        unslid.normalize();
+        assert_eq!(added(&unslid), Some(1));
+        assert_eq!(apply(&unslid, &old).unwrap(), new);
+    }
+
+    #[test]
+    fn test_function_context() {
+        let old = "\
+struct Point {
+    x: i32,
+}
+
+impl Point {
+    fn new() -> Self {
+        Point { x: 0 }
+    }
+}
+";
+        let new = old.replace("x: 0", "x: 1").replace("x: i32", "x: i64");
+        let options = DiffOptions {
+            context: 1,
+            function_context: Some(FunctionContext::Default),
+            ..DiffOptions::default()
+        };
+        let patch = diff_with_options(old, &new, &options);
+        let hints: Vec<_> = patch.hunks.iter().map(|hunk| hunk.hint()).collect();
+        // Nothing comes before the first hunk, and the lines of the methods are indented
+        assert_eq!(hints, [None, Some("impl Point {")]);
+        assert_eq!(patch.hunks[1].range_hint, " impl Point {");
+        assert_eq!(apply(&patch, old).unwrap(), new);
+
+        let long = format!("{}\n\na\n", "é".repeat(50));
+        let changed = long.replace("a", "b");
+        let patch = diff_with_options(&long, &changed, &options);
+        assert_eq!(patch.hunks[0].hint(), Some("é".repeat(40).as_str()));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_function_context_patterns() {
+        let old = "class Shape:\n    def area(self):\n        return 0\n\n        pass\n";
+        let new = old.replace("return 0", "return 1");
+        let options = DiffOptions {
+            context: 0,
+            function_context: Some(
+                FunctionContext::patterns(&[r"^\s*(def \w+)", r"^class \w+"]).unwrap(),
+            ),
+            ..DiffOptions::default()
+        };
+        let patch = diff_with_options(old, &new, &options);
+        assert_eq!(patch.hunks[0].hint(), Some("def area"));
+
+        let options = DiffOptions {
+            function_context: Some(FunctionContext::patterns(&[r"^class \w+"]).unwrap()),
+            ..options
+        };
+        let patch = diff_with_options(old, &new, &options);
+        assert_eq!(patch.hunks[0].hint(), Some("class Shape"));
+        assert_eq!(
+            options.function_context,
+            Some(FunctionContext::patterns(&[r"^class \w+"]).unwrap())
+        );
+    }
+}
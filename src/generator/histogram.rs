@@ -0,0 +1,220 @@
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::ops::Range as IndexRange;
+
+use crate::hash::HashMap;
+
+use super::{Edit, myers};
+use crate::cancel::{CancellationToken, cancelled};
+
+// Lines occurring more often than this in the old range are never used as anchors. This is the
+// same cutoff git uses to keep the search from going quadratic on highly repetitive input.
+const MAX_CHAIN_LEN: usize = 64;
+
+// How many times a range is split around a region before what is left of it is handed to Myers'
+// algorithm. Every split scans the whole range, so this bounds the work on input that can only be
+// split unevenly.
+const MAX_DEPTH: usize = 64;
+
+/// Compute an edit script with the histogram diff algorithm
+///
+/// This is an extension of patience diff: instead of only anchoring on lines that are unique to
+/// both sides, it looks for the longest common region that contains the least frequently
+/// occurring lines of the old range and splits the problem around it. Regions without any
+/// usable anchor are handed to Myers' algorithm, and so are ranges split too many times.
+pub(crate) fn histogram<T: Hash + Eq>(
+    old: &[T],
+    old_range: IndexRange<usize>,
+    new: &[T],
+    new_range: IndexRange<usize>,
+    edits: &mut Vec<Edit>,
+    cancel: Option<&CancellationToken>,
+) {
+    split(old, old_range, new, new_range, edits, cancel, 0);
+}
+
+// Diff a range that has been split `depth` times already. The part before the best region is
+// diffed recursively and the part after it in a loop, so the stack only grows with the splits on
+// the left.
+fn split<T: Hash + Eq>(
+    old: &[T],
+    mut old_range: IndexRange<usize>,
+    new: &[T],
+    mut new_range: IndexRange<usize>,
+    edits: &mut Vec<Edit>,
+    cancel: Option<&CancellationToken>,
+    mut depth: usize,
+) {
+    while !old_range.is_empty()
+        && !new_range.is_empty()
+        && old[old_range.start] == new[new_range.start]
+    {
+        edits.push(Edit::Equal(old_range.start, new_range.start));
+        old_range.start += 1;
+        new_range.start += 1;
+    }
+    let mut suffix = 0;
+    while suffix < old_range.len()
+        && suffix < new_range.len()
+        && old[old_range.end - suffix - 1] == new[new_range.end - suffix - 1]
+    {
+        suffix += 1;
+    }
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    // The regions are extended as far as they go, so the parts around them never start or end
+    // with common lines
+    loop {
+        // Once cancelled, what is left goes to Myers' algorithm, which gives up right away
+        let region = match old_range.is_empty()
+            || new_range.is_empty()
+            || depth >= MAX_DEPTH
+            || cancelled(cancel)
+        {
+            true => None,
+            false => best_region(old, old_range.clone(), new, new_range.clone()),
+        };
+        let Some(region) = region else {
+            myers(
+                old,
+                old_range.clone(),
+                new,
+                new_range.clone(),
+                edits,
+                cancel,
+            );
+            break;
+        };
+        depth += 1;
+        split(
+            old,
+            old_range.start..region.old.start,
+            new,
+            new_range.start..region.new.start,
+            edits,
+            cancel,
+            depth,
+        );
+        for (o, n) in region.old.clone().zip(region.new.clone()) {
+            edits.push(Edit::Equal(o, n));
+        }
+        old_range.start = region.old.end;
+        new_range.start = region.new.end;
+    }
+
+    for i in 0..suffix {
+        edits.push(Edit::Equal(old_range.end + i, new_range.end + i));
+    }
+}
+
+/// A run of identical lines in both files
+struct Region {
+    old: IndexRange<usize>,
+    new: IndexRange<usize>,
+}
+
+// Find the common region whose rarest line has the fewest occurrences in the old range,
+// preferring longer regions when there is a tie, and then the ones closest to the middle of the
+// new range, so splitting around them leaves parts of similar sizes
+fn best_region<T: Hash + Eq>(
+    old: &[T],
+    old_range: IndexRange<usize>,
+    new: &[T],
+    new_range: IndexRange<usize>,
+) -> Option<Region> {
+    let mut positions: HashMap<&T, Vec<usize>> = HashMap::new();
+    for i in old_range.clone() {
+        positions.entry(&old[i]).or_default().push(i);
+    }
+    let occurrences = |line: &T| positions.get(line).map_or(0, Vec::len);
+
+    let mut best: Option<Region> = None;
+    let mut best_count = MAX_CHAIN_LEN + 1;
+
+    for j in new_range.clone() {
+        let Some(candidates) = positions.get(&new[j]) else {
+            continue;
+        };
+        if candidates.len() > MAX_CHAIN_LEN || candidates.len() > best_count {
+            continue;
+        }
+
+        for &i in candidates {
+            let (mut old_start, mut new_start) = (i, j);
+            while old_start > old_range.start
+                && new_start > new_range.start
+                && old[old_start - 1] == new[new_start - 1]
+            {
+                old_start -= 1;
+                new_start -= 1;
+            }
+            let (mut old_end, mut new_end) = (i + 1, j + 1);
+            while old_end < old_range.end && new_end < new_range.end && old[old_end] == new[new_end]
+            {
+                old_end += 1;
+                new_end += 1;
+            }
+
+            let count = (old_start..old_end)
+                .map(|k| occurrences(&old[k]))
+                .min()
+                .unwrap_or(0);
+            let middle = (new_range.start + new_range.end) / 2;
+            let better = best.as_ref().is_none_or(|region| {
+                let len = old_end - old_start;
+                len > region.old.len()
+                    || (len == region.old.len()
+                        && new_start.abs_diff(middle) < region.new.start.abs_diff(middle))
+            });
+            if count < best_count || (count == best_count && better) {
+                best_count = count;
+                best = Some(Region {
+                    old: old_start..old_end,
+                    new: new_start..new_end,
+                });
+            }
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_best_region_prefers_rare_lines() {
+        // "x" is the rarest line shared by both sides, so it wins over the longer run of "a"s
+        let old = ["a", "a", "a", "x", "a"];
+        let new = ["x", "a", "a", "a"];
+        let region = best_region(&old, 0..5, &new, 0..4).unwrap();
+        assert_eq!((region.old, region.new), (3..5, 0..2));
+    }
+
+    #[test]
+    fn test_histogram_large_input() {
+        // Splitting around the first of the equally good regions would scan the rest of the
+        // input again for every unchanged line, and recurse as deep
+        let n = 20_000;
+        let old: Vec<usize> = (0..n).collect();
+        let new: Vec<usize> = (0..n).map(|i| if i % 2 == 0 { i } else { n + i }).collect();
+        let mut edits = Vec::new();
+        histogram(&old, 0..n, &new, 0..n, &mut edits, None);
+        let equal = edits
+            .iter()
+            .filter(|edit| matches!(edit, Edit::Equal(..)))
+            .count();
+        assert_eq!((equal, edits.len()), (n / 2, n / 2 * 3));
+    }
+
+    #[test]
+    fn test_best_region_skips_common_lines() {
+        let old = ["a"; MAX_CHAIN_LEN + 1];
+        let new = ["a"];
+        assert!(best_region(&old, 0..old.len(), &new, 0..1).is_none());
+    }
+}
@@ -0,0 +1,381 @@
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+
+use super::{Edit, missing_newlines, removals_first};
+use crate::ast::{Line, Patch};
+
+// The weights git gives to the properties of the places a run of lines could start or end at,
+// where lower scores are better. See `xdiff/xdiffi.c` in git for how they were tuned.
+const START_OF_FILE_PENALTY: i32 = 1;
+const END_OF_FILE_PENALTY: i32 = 21;
+const TOTAL_BLANK_WEIGHT: i32 = -30;
+const POST_BLANK_WEIGHT: i32 = 6;
+const RELATIVE_INDENT_PENALTY: i32 = -4;
+const RELATIVE_INDENT_WITH_BLANK_PENALTY: i32 = 10;
+const RELATIVE_OUTDENT_PENALTY: i32 = 24;
+const RELATIVE_OUTDENT_WITH_BLANK_PENALTY: i32 = 17;
+const RELATIVE_DEDENT_PENALTY: i32 = 23;
+const RELATIVE_DEDENT_WITH_BLANK_PENALTY: i32 = 17;
+const INDENT_WEIGHT: i32 = 60;
+
+// Runs are only tried at this many positions, and lines only looked at this far
+const MAX_SLIDING: usize = 100;
+const MAX_INDENT: i32 = 200;
+const MAX_BLANKS: i32 = 20;
+
+// The indentation of a line, counting tabs up to the next multiple of 8, or -1 for blank lines
+fn line_indent(line: &str) -> i32 {
+    let mut indent = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => indent += 1,
+            '\t' => indent += 8 - indent % 8,
+            c if c.is_whitespace() => {}
+            _ => return indent,
+        }
+        if indent >= MAX_INDENT {
+            return MAX_INDENT;
+        }
+    }
+    -1
+}
+
+/// What the lines around a place between two lines look like
+struct Split {
+    end_of_file: bool,
+    /// The indentation of the line after the split, or -1 if it is blank
+    indent: i32,
+    /// The number of blank lines before the split
+    pre_blank: i32,
+    /// The indentation of the first line before the split that isn't blank, or -1
+    pre_indent: i32,
+    /// The number of blank lines after the line after the split
+    post_blank: i32,
+    /// The indentation of the first line after those blank lines, or -1
+    post_indent: i32,
+}
+
+// Look at the lines around the split before the line at the given index
+fn measure(lines: &[&str], split: usize) -> Split {
+    let (end_of_file, indent) = match lines.get(split) {
+        Some(line) => (false, line_indent(line)),
+        None => (true, -1),
+    };
+    // The number of blank lines and the indentation of the next line that isn't blank
+    let scan = |lines: &mut dyn Iterator<Item = &&str>| {
+        let mut blank = 0;
+        for line in lines {
+            let indent = line_indent(line);
+            if indent != -1 {
+                return (blank, indent);
+            }
+            blank += 1;
+            if blank == MAX_BLANKS {
+                return (blank, 0);
+            }
+        }
+        (blank, -1)
+    };
+    let (pre_blank, pre_indent) = scan(&mut lines[..split.min(lines.len())].iter().rev());
+    let (post_blank, post_indent) = scan(&mut lines.iter().skip(split + 1));
+    Split {
+        end_of_file,
+        indent,
+        pre_blank,
+        pre_indent,
+        post_blank,
+        post_indent,
+    }
+}
+
+/// The score of a position of a run, from both of its ends
+#[derive(Debug, Clone, Copy, Default)]
+struct Score {
+    effective_indent: i32,
+    penalty: i32,
+}
+
+impl Score {
+    fn add(&mut self, split: &Split) {
+        if split.pre_indent == -1 && split.pre_blank == 0 {
+            self.penalty += START_OF_FILE_PENALTY;
+        }
+        if split.end_of_file {
+            self.penalty += END_OF_FILE_PENALTY;
+        }
+        let post_blank = if split.indent == -1 {
+            1 + split.post_blank
+        } else {
+            0
+        };
+        let total_blank = split.pre_blank + post_blank;
+        self.penalty += TOTAL_BLANK_WEIGHT * total_blank;
+        self.penalty += POST_BLANK_WEIGHT * post_blank;
+
+        let indent = if split.indent != -1 {
+            split.indent
+        } else {
+            split.post_indent
+        };
+        let any_blanks = total_blank != 0;
+        self.effective_indent += indent;
+        if indent == -1 || split.pre_indent == -1 || indent == split.pre_indent {
+            // No adjustment
+        } else if indent > split.pre_indent {
+            self.penalty += match any_blanks {
+                true => RELATIVE_INDENT_WITH_BLANK_PENALTY,
+                false => RELATIVE_INDENT_PENALTY,
+            };
+        } else if split.post_indent != -1 && split.post_indent > indent {
+            self.penalty += match any_blanks {
+                true => RELATIVE_OUTDENT_WITH_BLANK_PENALTY,
+                false => RELATIVE_OUTDENT_PENALTY,
+            };
+        } else {
+            self.penalty += match any_blanks {
+                true => RELATIVE_DEDENT_WITH_BLANK_PENALTY,
+                false => RELATIVE_DEDENT_PENALTY,
+            };
+        }
+    }
+
+    // Whether this score is at least as good as the other one
+    fn not_worse_than(&self, other: &Score) -> bool {
+        let indents = (self.effective_indent - other.effective_indent).signum();
+        INDENT_WEIGHT * indents + (self.penalty - other.penalty) <= 0
+    }
+}
+
+/// Slide the runs of only added or only removed lines of an edit script to the positions git's
+/// indent heuristic finds easiest to read, such as around whole blocks of code
+///
+/// A run can slide up when the line before it is the same as its last line, and down when the
+/// line after it is the same as its first line, since either way it adds or removes the same
+/// lines. `old` and `new` are compared to know which lines are the same, and `old_text` and
+/// `new_text` are the texts whose indentation is looked at.
+pub(crate) fn slide_runs<T: PartialEq>(
+    edits: &mut [Edit],
+    old: &[T],
+    new: &[T],
+    old_text: &[&str],
+    new_text: &[&str],
+) {
+    let is_equal = |edit: &Edit| matches!(edit, Edit::Equal(..));
+    let mut start = 0;
+    while start < edits.len() {
+        if is_equal(&edits[start]) {
+            start += 1;
+            continue;
+        }
+        let end = edits[start..]
+            .iter()
+            .position(is_equal)
+            .map_or(edits.len(), |len| start + len);
+        let (keys, text, first) = match edits[start] {
+            Edit::Insert(j)
+                if edits[start..end]
+                    .iter()
+                    .all(|e| matches!(e, Edit::Insert(_))) =>
+            {
+                (new, new_text, j)
+            }
+            Edit::Delete(i)
+                if edits[start..end]
+                    .iter()
+                    .all(|e| matches!(e, Edit::Delete(_))) =>
+            {
+                (old, old_text, i)
+            }
+            _ => {
+                start = end;
+                continue;
+            }
+        };
+        let len = end - start;
+
+        // How far the run can slide up and down over the unchanged lines around it
+        let mut up = 0;
+        while up < start
+            && is_equal(&edits[start - 1 - up])
+            && keys[first - up - 1] == keys[first + len - 1 - up]
+        {
+            up += 1;
+        }
+        let mut down = 0;
+        while end + down < edits.len()
+            && is_equal(&edits[end + down])
+            && keys[first + down] == keys[first + len + down]
+        {
+            down += 1;
+        }
+        if up + down == 0 {
+            start = end;
+            continue;
+        }
+
+        // Like git, only try the positions near the lowest one, preferring lower ones on ties
+        let lowest = first + down;
+        let highest = (first - up)
+            .max(lowest.saturating_sub(len + 1))
+            .max(lowest.saturating_sub(MAX_SLIDING));
+        let mut best: Option<(usize, Score)> = None;
+        for position in highest..=lowest {
+            let mut score = Score::default();
+            score.add(&measure(text, position + len));
+            score.add(&measure(text, position));
+            if best.is_none_or(|(_, best)| score.not_worse_than(&best)) {
+                best = Some((position, score));
+            }
+        }
+        let (best, _) = best.expect("bug: a run can always stay where it is");
+
+        // Rewrite the run with the unchanged lines around it, whose indices on the other side
+        // follow each other
+        let is_insert = matches!(edits[start], Edit::Insert(_));
+        let window = start - up..end + down;
+        let other_first = match edits[if up > 0 { window.start } else { end }] {
+            Edit::Equal(i, _) if is_insert => i,
+            Edit::Equal(_, j) => j,
+            _ => unreachable!("bug: runs only slide over unchanged lines"),
+        };
+        let before = best - (first - up);
+        let equal = |side: usize, other: usize| match is_insert {
+            true => Edit::Equal(other, side),
+            false => Edit::Equal(side, other),
+        };
+        let mut rewritten = Vec::with_capacity(window.len());
+        for k in 0..before {
+            rewritten.push(equal(first - up + k, other_first + k));
+        }
+        for k in 0..len {
+            rewritten.push(match is_insert {
+                true => Edit::Insert(best + k),
+                false => Edit::Delete(best + k),
+            });
+        }
+        for k in 0..up + down - before {
+            rewritten.push(equal(best + len + k, other_first + before + k));
+        }
+        edits[window.clone()].copy_from_slice(&rewritten);
+        start = window.end;
+    }
+    // A run slid next to another one is merged with it
+    removals_first(edits);
+}
+
+impl<'a> Patch<'a> {
+    /// Slide the runs of added or removed lines of every hunk to where git's indent heuristic
+    /// places them, like [`DiffOptions::indent_heuristic`](crate::DiffOptions::indent_heuristic)
+    /// does for generated diffs
+    ///
+    /// Diffs made by other programs often start or end a run in the middle of a block, when
+    /// moving it over a repeated line like `}` would add or remove the same lines. This changes
+    /// only which lines are shown as changed, so the patch still applies with the same result.
+    /// Only the lines of each hunk are looked at to decide where a run reads best.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::Patch;
+    ///
+    /// let mut patch = Patch::from_single(
+    ///     "\
+    /// --- a.rs
+    /// +++ a.rs
+    /// @@ -1,3 +1,7 @@
+    ///  fn one() {
+    ///      one();
+    /// +}
+    /// +
+    /// +fn two() {
+    /// +    two();
+    ///  }
+    /// ",
+    /// )
+    /// .unwrap();
+    ///
+    /// patch.normalize();
+    /// assert_eq!(
+    ///     patch.hunks[0].to_string(),
+    ///     "\
+    /// @@ -1,3 +1,7 @@
+    ///  fn one() {
+    ///      one();
+    ///  }
+    /// +
+    /// +fn two() {
+    /// +    two();
+    /// +}"
+    /// );
+    /// ```
+    pub fn normalize(&mut self) {
+        let hunks = self.hunks.len();
+        for (h, hunk) in self.hunks.iter_mut().enumerate() {
+            // The indices in the hunk of the lines of either side, and their texts
+            let (mut old, mut new) = (Vec::new(), Vec::new());
+            let (mut old_text, mut new_text) = (Vec::new(), Vec::new());
+            let mut edits = Vec::with_capacity(hunk.lines.len());
+            for (index, line) in hunk.lines.iter().enumerate() {
+                match line {
+                    Line::Context(text) => {
+                        edits.push(Edit::Equal(old.len(), new.len()));
+                        old.push(index);
+                        old_text.push(text.as_ref());
+                        new.push(index);
+                        new_text.push(text.as_ref());
+                    }
+                    Line::Remove(text) => {
+                        edits.push(Edit::Delete(old.len()));
+                        old.push(index);
+                        old_text.push(text.as_ref());
+                    }
+                    Line::Add(text) => {
+                        edits.push(Edit::Insert(new.len()));
+                        new.push(index);
+                        new_text.push(text.as_ref());
+                    }
+                }
+            }
+
+            // Only the last line of the last hunk can lack a newline character
+            let last = h + 1 == hunks;
+            slide_runs(
+                &mut edits,
+                &missing_newlines(&old_text, !last || self.old_end_newline),
+                &missing_newlines(&new_text, !last || self.new_end_newline),
+                &old_text,
+                &new_text,
+            );
+            let text = |index: usize| -> Cow<'a, str> {
+                match &hunk.lines[index] {
+                    Line::Add(text) | Line::Remove(text) | Line::Context(text) => text.clone(),
+                }
+            };
+            hunk.lines = edits
+                .iter()
+                .map(|edit| match *edit {
+                    Edit::Equal(i, _) => Line::Context(text(old[i])),
+                    Edit::Delete(i) => Line::Remove(text(old[i])),
+                    Edit::Insert(j) => Line::Add(text(new[j])),
+                })
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_line_indent() {
+        assert_eq!(line_indent("abc"), 0);
+        assert_eq!(line_indent("    abc"), 4);
+        assert_eq!(line_indent("  \tabc"), 8);
+        assert_eq!(line_indent("\t \tabc"), 16);
+        assert_eq!(line_indent(" \t "), -1);
+        assert_eq!(line_indent(""), -1);
+    }
+}
@@ -0,0 +1,162 @@
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::ops::Range as IndexRange;
+
+use crate::hash::HashMap;
+
+use super::{Edit, myers};
+use crate::cancel::{CancellationToken, cancelled};
+
+/// Compute an edit script with the patience diff algorithm
+///
+/// Lines that occur exactly once in both ranges are matched up, and the longest sequence of
+/// those matches that appears in the same order on both sides becomes a set of anchors. The
+/// regions between the anchors are diffed recursively, falling back to Myers' algorithm once no
+/// unique lines are left.
+pub(crate) fn patience<T: Hash + Eq>(
+    old: &[T],
+    mut old_range: IndexRange<usize>,
+    new: &[T],
+    mut new_range: IndexRange<usize>,
+    edits: &mut Vec<Edit>,
+    cancel: Option<&CancellationToken>,
+) {
+    // Matching lines at the edges are always part of the result
+    while !old_range.is_empty()
+        && !new_range.is_empty()
+        && old[old_range.start] == new[new_range.start]
+    {
+        edits.push(Edit::Equal(old_range.start, new_range.start));
+        old_range.start += 1;
+        new_range.start += 1;
+    }
+    let mut suffix = 0;
+    while suffix < old_range.len()
+        && suffix < new_range.len()
+        && old[old_range.end - suffix - 1] == new[new_range.end - suffix - 1]
+    {
+        suffix += 1;
+    }
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    // Once cancelled, what is left goes to Myers' algorithm, which gives up right away
+    let anchors = match cancelled(cancel) {
+        true => Vec::new(),
+        false => unique_anchors(old, old_range.clone(), new, new_range.clone()),
+    };
+    if anchors.is_empty() {
+        myers(
+            old,
+            old_range.clone(),
+            new,
+            new_range.clone(),
+            edits,
+            cancel,
+        );
+    } else {
+        let (mut old_pos, mut new_pos) = (old_range.start, new_range.start);
+        for (o, n) in anchors {
+            patience(old, old_pos..o, new, new_pos..n, edits, cancel);
+            edits.push(Edit::Equal(o, n));
+            old_pos = o + 1;
+            new_pos = n + 1;
+        }
+        patience(
+            old,
+            old_pos..old_range.end,
+            new,
+            new_pos..new_range.end,
+            edits,
+            cancel,
+        );
+    }
+
+    for i in 0..suffix {
+        edits.push(Edit::Equal(old_range.end + i, new_range.end + i));
+    }
+}
+
+// Find the longest increasing sequence of (old, new) positions of lines unique to both ranges
+fn unique_anchors<T: Hash + Eq>(
+    old: &[T],
+    old_range: IndexRange<usize>,
+    new: &[T],
+    new_range: IndexRange<usize>,
+) -> Vec<(usize, usize)> {
+    // For every line, count its occurrences on each side and remember where it was seen
+    let mut counts: HashMap<&T, (usize, usize, usize, usize)> = HashMap::new();
+    for i in old_range {
+        let entry = counts.entry(&old[i]).or_insert((0, 0, i, 0));
+        entry.0 += 1;
+    }
+    for i in new_range {
+        if let Some(entry) = counts.get_mut(&new[i]) {
+            entry.1 += 1;
+            entry.3 = i;
+        }
+    }
+
+    let mut matches: Vec<(usize, usize)> = counts
+        .into_values()
+        .filter(|&(old_count, new_count, _, _)| old_count == 1 && new_count == 1)
+        .map(|(_, _, o, n)| (o, n))
+        .collect();
+    matches.sort_unstable();
+
+    longest_increasing(&matches)
+}
+
+// Patience sorting: deal the matches onto piles, each card going on the leftmost pile whose top
+// card is greater. The number of piles is the length of the longest increasing subsequence, which
+// is recovered by following the back references from the top of the last pile.
+fn longest_increasing(matches: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut pile_tops: Vec<usize> = Vec::new();
+    let mut back_refs: Vec<Option<usize>> = Vec::with_capacity(matches.len());
+
+    for (i, &(_, n)) in matches.iter().enumerate() {
+        let pile = pile_tops.partition_point(|&top| matches[top].1 < n);
+        back_refs.push(if pile > 0 {
+            Some(pile_tops[pile - 1])
+        } else {
+            None
+        });
+        if pile == pile_tops.len() {
+            pile_tops.push(i);
+        } else {
+            pile_tops[pile] = i;
+        }
+    }
+
+    let mut result = Vec::with_capacity(pile_tops.len());
+    let mut current = pile_tops.last().copied();
+    while let Some(i) = current {
+        result.push(matches[i]);
+        current = back_refs[i];
+    }
+    result.reverse();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_longest_increasing() {
+        assert_eq!(longest_increasing(&[]), []);
+        assert_eq!(
+            longest_increasing(&[(0, 9), (1, 4), (2, 6), (3, 1), (4, 7), (5, 8)]),
+            [(1, 4), (2, 6), (4, 7), (5, 8)]
+        );
+    }
+
+    #[test]
+    fn test_unique_anchors_ignore_repeated_lines() {
+        let old = ["}", "a", "}", "b"];
+        let new = ["}", "x", "a", "}"];
+        assert_eq!(unique_anchors(&old, 0..4, &new, 0..4), [(1, 2)]);
+    }
+}
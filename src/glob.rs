@@ -0,0 +1,132 @@
+/// Match a path against a shell-style pattern
+///
+/// `*` matches any number of characters except `/`, `**` matches any number of characters
+/// including `/`, so `src/**/*.rs` matches `src/lib.rs` and `src/a/b/lib.rs`, and `?` matches
+/// any character except `/`. `[abc]` matches one of the characters between the brackets, which
+/// may include ranges like `a-z`, and `[!abc]` or `[^abc]` any other character. Every other
+/// character matches itself.
+pub(crate) fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    let mut matcher = Matcher {
+        seen: vec![None; (pattern.len() + 1) * (path.len() + 1)],
+        pattern: &pattern,
+        path: &path,
+    };
+    matcher.matches(0, 0)
+}
+
+// Matches the rest of a pattern against the rest of a path, remembering every answer so that
+// patterns with many stars take polynomial time instead of backtracking exponentially
+struct Matcher<'a> {
+    pattern: &'a [char],
+    path: &'a [char],
+    /// Whether the pattern from `p` on matches the path from `i` on, at `p * (path.len() + 1) + i`
+    seen: Vec<Option<bool>>,
+}
+
+impl Matcher<'_> {
+    fn matches(&mut self, p: usize, i: usize) -> bool {
+        let key = p * (self.path.len() + 1) + i;
+        if let Some(matched) = self.seen[key] {
+            return matched;
+        }
+        let matched = self.match_uncached(p, i);
+        self.seen[key] = Some(matched);
+        matched
+    }
+
+    fn match_uncached(&mut self, p: usize, i: usize) -> bool {
+        let (pattern, path) = (self.pattern, self.path);
+        match &pattern[p..] {
+            [] => i == path.len(),
+            ['*', '*', '/', ..] => {
+                // Any number of whole directories, including none
+                self.matches(p + 3, i)
+                    || (i..path.len())
+                        .filter(|&j| path[j] == '/')
+                        .any(|j| self.matches(p + 3, j + 1))
+            }
+            ['*', '*', ..] => (i..=path.len()).any(|j| self.matches(p + 2, j)),
+            ['*', ..] => {
+                let end = path[i..]
+                    .iter()
+                    .position(|&c| c == '/')
+                    .map_or(path.len(), |end| i + end);
+                (i..=end).any(|j| self.matches(p + 1, j))
+            }
+            ['?', ..] => path.get(i).is_some_and(|&c| c != '/') && self.matches(p + 1, i + 1),
+            ['[', class @ ..] => match (class_end(class), path.get(i)) {
+                (Some(end), Some(&c)) => {
+                    c != '/' && class_matches(&class[..end], c) && self.matches(p + end + 2, i + 1)
+                }
+                (Some(_), None) => false,
+                // An unclosed bracket is an ordinary character
+                (None, _) => path.get(i) == Some(&'[') && self.matches(p + 1, i + 1),
+            },
+            [c, ..] => path.get(i) == Some(c) && self.matches(p + 1, i + 1),
+        }
+    }
+}
+
+// The position of the `]` closing a character class, which can't be its first character
+fn class_end(class: &[char]) -> Option<usize> {
+    let start = match class.first() {
+        Some('!' | '^') => 2,
+        _ => 1,
+    };
+    class
+        .iter()
+        .skip(start)
+        .position(|&c| c == ']')
+        .map(|i| i + start)
+}
+
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negated, class) = match class {
+        ['!' | '^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            found |= (class[i]..=class[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("src/*.rs", "src/lib.rs"));
+        assert!(!glob_match("src/*.rs", "src/a/lib.rs"));
+        assert!(glob_match("src/**/*.rs", "src/lib.rs"));
+        assert!(glob_match("src/**/*.rs", "src/a/b/lib.rs"));
+        assert!(!glob_match("src/**/*.rs", "tests/lib.rs"));
+        assert!(glob_match("**", "any/thing"));
+        assert!(glob_match("**/Cargo.toml", "Cargo.toml"));
+        assert!(glob_match("**/Cargo.toml", "a/b/Cargo.toml"));
+        assert!(glob_match("docs/**", "docs/a/b.md"));
+        assert!(glob_match("file?.txt", "file1.txt"));
+        assert!(!glob_match("file?.txt", "file/.txt"));
+        assert!(glob_match("[a-c]x[!0-9]", "bxy"));
+        assert!(!glob_match("[a-c]x[!0-9]", "bx1"));
+        assert!(glob_match("[]]", "]"));
+        assert!(glob_match("a[b", "a[b"));
+        assert!(!glob_match("*.rs", "lib.rs.orig"));
+        assert!(!glob_match(&"**a".repeat(20), &"a".repeat(19)));
+        assert!(!glob_match(
+            &format!("{}b", "*a".repeat(20)),
+            &"a".repeat(100)
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+//! Hashing for the diff algorithms and the applier, which works without the standard library
+
+use core::hash::Hasher;
+
+#[cfg(feature = "std")]
+pub(crate) use std::collections::HashMap;
+
+#[cfg(not(feature = "std"))]
+pub(crate) use map::HashMap;
+
+/// A 64-bit FNV-1a hasher, which is fast for the short keys lines are and doesn't need a source
+/// of randomness
+pub(crate) struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// The parts of `std::collections::HashMap` the diff algorithms use, for when it isn't available.
+// Keys are kept in buckets of a `BTreeMap` by their hash, so they only need `Hash` and `Eq`.
+#[cfg(not(feature = "std"))]
+mod map {
+    use alloc::collections::BTreeMap;
+    use alloc::vec::Vec;
+    use core::borrow::Borrow;
+    use core::hash::{Hash, Hasher};
+
+    use super::FnvHasher;
+
+    pub(crate) struct HashMap<K, V> {
+        buckets: BTreeMap<u64, Vec<(K, V)>>,
+    }
+
+    fn hash_of<T: Hash + ?Sized>(value: &T) -> u64 {
+        let mut hasher = FnvHasher::default();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub(crate) struct Entry<'m, K, V> {
+        bucket: &'m mut Vec<(K, V)>,
+        key: K,
+    }
+
+    impl<K: Hash + Eq, V> HashMap<K, V> {
+        pub(crate) fn new() -> Self {
+            HashMap {
+                buckets: BTreeMap::new(),
+            }
+        }
+
+        pub(crate) fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+            let bucket = self.buckets.entry(hash_of(&key)).or_default();
+            Entry { bucket, key }
+        }
+
+        pub(crate) fn get<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&V>
+        where
+            K: Borrow<Q>,
+        {
+            let bucket = self.buckets.get(&hash_of(key))?;
+            bucket
+                .iter()
+                .find(|(k, _)| k.borrow() == key)
+                .map(|(_, v)| v)
+        }
+
+        pub(crate) fn get_mut<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+        where
+            K: Borrow<Q>,
+        {
+            let bucket = self.buckets.get_mut(&hash_of(key))?;
+            bucket
+                .iter_mut()
+                .find(|(k, _)| k.borrow() == key)
+                .map(|(_, v)| v)
+        }
+
+        pub(crate) fn into_values(self) -> impl Iterator<Item = V> {
+            self.buckets.into_values().flatten().map(|(_, v)| v)
+        }
+    }
+
+    impl<'m, K: Eq, V> Entry<'m, K, V> {
+        pub(crate) fn or_insert(self, default: V) -> &'m mut V {
+            let index = match self.bucket.iter().position(|(k, _)| *k == self.key) {
+                Some(index) => index,
+                None => {
+                    self.bucket.push((self.key, default));
+                    self.bucket.len() - 1
+                }
+            };
+            &mut self.bucket[index].1
+        }
+
+        pub(crate) fn or_default(self) -> &'m mut V
+        where
+            V: Default,
+        {
+            self.or_insert(V::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_fnv_hasher() {
+        // The reference values of 64-bit FNV-1a
+        let mut hasher = FnvHasher::default();
+        assert_eq!(hasher.finish(), 0xcbf2_9ce4_8422_2325);
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[test]
+    fn test_hash_map() {
+        let mut map = HashMap::new();
+        *map.entry("a").or_default() += 1;
+        *map.entry("b").or_insert(5) += 1;
+        *map.entry("a").or_default() += 1;
+        assert_eq!(map.get("a"), Some(&2));
+        assert_eq!(map.get_mut("b").map(|count| *count), Some(6));
+        assert_eq!(map.get("c"), None);
+        let mut values: Vec<i32> = map.into_values().collect();
+        values.sort();
+        assert_eq!(values, [2, 6]);
+    }
+}
@@ -0,0 +1,374 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use crate::applier::{ApplyError, apply};
+use crate::ast::{FileOp, Hunk, Line, Patch, Range};
+use crate::generator::{DiffAlgorithm, build_hunks, edit_script, missing_newlines};
+
+/// The number of context lines around the changes of an interdiff, like `interdiff` uses
+const CONTEXT: usize = 3;
+
+/// Stands in for the lines of the original file that neither patch shows
+const UNKNOWN: &str = "\0interdiff: unknown line ";
+
+/// Compute the differences between two patches made against the same file, like `interdiff`
+///
+/// The original file is reconstructed from the lines both patches show of it, both patches are
+/// applied to it and the results are compared. The returned patch turns the file produced by
+/// `old` into the file produced by `new`, so it shows what changed between two versions of a
+/// patch. Lines of the original file that neither patch shows are never part of it, not even
+/// as context.
+///
+/// The patches have to show the same text for the lines of the original file they both show,
+/// otherwise an [`ApplyError::ContextMismatch`] tells the first line where they disagree, with
+/// the text shown by `old` as the expected line and the text shown by `new` as the actual one.
+///
+/// # Example
+///
+/// ```
+/// use patch::{Patch, interdiff};
+///
+/// let v1 = Patch::from_single(
+///     "\
+/// --- a/greet.py
+/// +++ b/greet.py
+/// @@ -10,3 +10,3 @@
+///  def greet():
+/// -    print('hi')
+/// +    print('hello')
+///  greet()
+/// ",
+/// )
+/// .unwrap();
+/// let v2 = Patch::from_single(
+///     "\
+/// --- a/greet.py
+/// +++ b/greet.py
+/// @@ -10,3 +10,3 @@
+///  def greet():
+/// -    print('hi')
+/// +    print('hello, world')
+///  greet()
+/// ",
+/// )
+/// .unwrap();
+///
+/// let changes = interdiff(&v1, &v2).unwrap();
+/// assert_eq!(
+///     changes.to_string(),
+///     "\
+/// --- b/greet.py
+/// +++ b/greet.py
+/// @@ -10,3 +10,3 @@
+///  def greet():
+/// -    print('hello')
+/// +    print('hello, world')
+///  greet()"
+/// );
+/// ```
+pub fn interdiff(old: &Patch, new: &Patch) -> Result<Patch<'static>, ApplyError> {
+    let base = reconstruct([(old, Side::Old), (new, Side::Old)]).map_err(|mismatch| {
+        ApplyError::ContextMismatch {
+            line: mismatch.line,
+            expected: mismatch.first,
+            actual: mismatch.second,
+        }
+    })?;
+    let old_text = apply(old, &base)?;
+    let new_text = apply(new, &base)?;
+    let (hunks, old_end_newline, new_end_newline) = diff_known(&old_text, &new_text);
+
+    Ok(Patch {
+        old: old.new.clone().into_owned(),
+        new: new.new.clone().into_owned(),
+        operation: FileOp::Modify,
+        git: None,
+        svn: None,
+        hunks,
+        binary: None,
+        opaque_binary: false,
+        preamble: None,
+        raw: None,
+        old_end_newline,
+        new_end_newline,
+    })
+}
+
+/// The side of a patch a file is reconstructed from
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Side {
+    /// The context and removed lines, at the positions of the old ranges
+    Old,
+    /// The context and added lines, at the positions of the new ranges
+    New,
+}
+
+/// Two patches show different text for the same line of a file
+#[derive(Debug)]
+pub(crate) struct Mismatch {
+    /// The line, counting from 1
+    pub(crate) line: u64,
+    /// The text shown by the first patch
+    pub(crate) first: String,
+    /// The text shown by the second patch
+    pub(crate) second: String,
+}
+
+// Rebuild as much of a file as the given sides of two patches show, with a placeholder for
+// every other line
+pub(crate) fn reconstruct(sides: [(&Patch, Side); 2]) -> Result<String, Mismatch> {
+    let mut known: BTreeMap<u64, &str> = BTreeMap::new();
+    // Whether the file is known to end without a newline character after its last known line
+    let mut ends_without_newline = false;
+    // Whether the last known line is known to be the last line of the file, which it is if a
+    // hunk marks a missing newline character on either side, as that hunk reaches the end
+    let mut end_known = false;
+    // The number of lines the file has at least, which ranges without lines need too, as they
+    // start after the line they name
+    let mut min_lines = 0;
+
+    for (i, (patch, side)) in sides.into_iter().enumerate() {
+        for hunk in &patch.hunks {
+            let lines = hunk.lines.iter().filter_map(|line| match (line, side) {
+                (Line::Context(text), _)
+                | (Line::Remove(text), Side::Old)
+                | (Line::Add(text), Side::New) => Some(&**text),
+                _ => None,
+            });
+            let start = match side {
+                Side::Old => hunk.old_range.start,
+                Side::New => hunk.new_range.start,
+            };
+            min_lines = min_lines.max(start);
+            for (line, text) in (start..).zip(lines) {
+                match known.insert(line, text) {
+                    Some(other) if other != text => {
+                        let (first, second) = if i == 0 { (text, other) } else { (other, text) };
+                        return Err(Mismatch {
+                            line,
+                            first: first.to_owned(),
+                            second: second.to_owned(),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let (end_newline, other_end_newline) = match side {
+            Side::Old => (patch.old_end_newline, patch.new_end_newline),
+            Side::New => (patch.new_end_newline, patch.old_end_newline),
+        };
+        if !patch.hunks.is_empty() {
+            ends_without_newline |= !end_newline;
+            end_known |= !end_newline || !other_end_newline;
+        }
+    }
+
+    let last = known
+        .keys()
+        .next_back()
+        .copied()
+        .unwrap_or(0)
+        .max(min_lines);
+    let mut file = String::new();
+    for line in 1..=last {
+        match known.get(&line) {
+            Some(text) => file.push_str(text),
+            None => {
+                file.push_str(UNKNOWN);
+                file.push_str(&line.to_string());
+            }
+        }
+        if line < last || !ends_without_newline {
+            file.push('\n');
+        }
+    }
+    // Whatever follows the last known line is unknown too
+    if !end_known {
+        file.push_str(UNKNOWN);
+        file.push_str("end\n");
+    }
+    Ok(file)
+}
+
+// Diff two versions of a file made from the same reconstructed one, leaving out its unknown
+// lines. Returns the hunks and whether each side ends with a newline character.
+pub(crate) fn diff_known(old_text: &str, new_text: &str) -> (Vec<Hunk<'static>>, bool, bool) {
+    let old_lines: Vec<&str> = old_text.lines().collect();
+    let new_lines: Vec<&str> = new_text.lines().collect();
+    let old_end_newline = old_text.is_empty() || old_text.ends_with('\n');
+    let new_end_newline = new_text.is_empty() || new_text.ends_with('\n');
+    let old_keys = missing_newlines(&old_lines, old_end_newline);
+    let new_keys = missing_newlines(&new_lines, new_end_newline);
+
+    // The unknown lines are never changed, so both versions have all of them in the same order,
+    // and the known lines between them can be compared separately
+    let unknown = |lines: &[&str]| -> Vec<usize> {
+        (0..lines.len())
+            .filter(|&i| lines[i].starts_with(UNKNOWN))
+            .collect()
+    };
+    let old_unknown = unknown(&old_lines);
+    let new_unknown = unknown(&new_lines);
+    debug_assert_eq!(old_unknown.len(), new_unknown.len());
+
+    let mut hunks = Vec::new();
+    let (mut old_start, mut new_start) = (0, 0);
+    let ends = old_unknown
+        .into_iter()
+        .zip(new_unknown)
+        .chain([(old_lines.len(), new_lines.len())]);
+    for (old_end, new_end) in ends {
+        let edits = edit_script(
+            &old_keys[old_start..old_end],
+            &new_keys[new_start..new_end],
+            DiffAlgorithm::Myers,
+        );
+        let segment = build_hunks(
+            &edits,
+            &old_lines[old_start..old_end],
+            &new_lines[new_start..new_end],
+            CONTEXT,
+        );
+        hunks.extend(segment.into_iter().map(|hunk| Hunk {
+            old_range: shift(hunk.old_range, old_start),
+            new_range: shift(hunk.new_range, new_start),
+            range_hint: Cow::Borrowed(""),
+            lines: hunk.lines.into_iter().map(Line::into_owned).collect(),
+        }));
+        (old_start, new_start) = (old_end + 1, new_end + 1);
+    }
+
+    // A missing newline character is only marked if the last hunk reaches the end of the file
+    let reaches_end =
+        |range: &Range, len: usize| range.count > 0 && range.start + range.count - 1 == len as u64;
+    let last = hunks.last();
+    let old_end_newline =
+        old_end_newline || !last.is_some_and(|hunk| reaches_end(&hunk.old_range, old_lines.len()));
+    let new_end_newline =
+        new_end_newline || !last.is_some_and(|hunk| reaches_end(&hunk.new_range, new_lines.len()));
+    (hunks, old_end_newline, new_end_newline)
+}
+
+// Move a range computed for a part of a file that starts after `lines_before` lines
+fn shift(range: Range, lines_before: usize) -> Range {
+    Range {
+        start: range.start + lines_before as u64,
+        count: range.count,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::diff;
+
+    #[test]
+    fn test_interdiff_separate_hunks() {
+        let v1 = Patch::from_single(
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -2,3 +2,3 @@
+ b
+-c
++C
+ d
+@@ -20,3 +20,3 @@
+ t
+-u
++U
+ v
+",
+        )
+        .unwrap();
+        let v2 = Patch::from_single(
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -2,3 +2,4 @@
+ b
+-c
++C
++C2
+ d
+@@ -40,2 +41,2 @@
+-x
++X
+ y
+",
+        )
+        .unwrap();
+
+        // The hunk only in v1 is undone, the one only in v2 is done and the unknown lines in
+        // between never show up
+        assert_eq!(
+            interdiff(&v1, &v2).unwrap().to_string(),
+            "\
+--- b/file.txt
++++ b/file.txt
+@@ -2,3 +2,4 @@
+ b
+ C
++C2
+ d
+@@ -20,3 +21,3 @@
+ t
+-U
++u
+ v
+@@ -40,2 +41,2 @@
+-x
++X
+ y"
+        );
+    }
+
+    #[test]
+    fn test_interdiff_same_patch() {
+        let patch = Patch::from_single("--- a\n+++ b\n@@ -1,2 +1,2 @@\n-a\n+b\n c\n").unwrap();
+        assert_eq!(interdiff(&patch, &patch).unwrap().hunks, []);
+    }
+
+    #[test]
+    fn test_interdiff_newline_at_end() {
+        // Newlines added or removed at the end by either patch
+        let cases = [
+            (("a\nb\n", "a\nB\n"), ("a\nb\n", "a\nb")),
+            (("a\nb\n", "a\nb"), ("a\nb\n", "a\nB\n")),
+            (("a\nb", "a\nB"), ("a\nb", "a\nb\n")),
+            (("a\nb", "a\nb\n"), ("a\nb", "a\nB")),
+        ];
+        for ((old_base, old_text), (new_base, new_text)) in cases {
+            let changes =
+                interdiff(&diff(old_base, old_text, 1), &diff(new_base, new_text, 1)).unwrap();
+            assert_eq!(apply(&changes, old_text).unwrap(), new_text);
+        }
+    }
+
+    #[test]
+    fn test_interdiff_zero_context() {
+        // Patches that only add lines, so the ranges on the side of the base have no lines
+        let base = "c\n}\n";
+        let (old_text, new_text) = ("c\nx\ny\nz\n}\n", "c\n}\nw\nv\n");
+        let (old, new) = (diff(base, old_text, 0), diff(base, new_text, 0));
+        assert_eq!(old.hunks[0].old_range, Range { start: 1, count: 0 });
+        assert_eq!(new.hunks[0].old_range, Range { start: 2, count: 0 });
+        let changes = interdiff(&old, &new).unwrap();
+        assert_eq!(apply(&changes, old_text).unwrap(), new_text);
+    }
+
+    #[test]
+    fn test_interdiff_mismatched_bases() {
+        let v1 = Patch::from_single("--- a\n+++ b\n@@ -1,2 +1,2 @@\n-a\n+b\n c\n").unwrap();
+        let v2 = Patch::from_single("--- a\n+++ b\n@@ -2 +2 @@\n-x\n+y\n").unwrap();
+        assert!(matches!(
+            interdiff(&v1, &v2),
+            Err(ApplyError::ContextMismatch { line: 2, ref expected, ref actual })
+                if expected == "c" && actual == "x"
+        ));
+    }
+}
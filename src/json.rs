@@ -0,0 +1,1095 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt::{self, Write};
+
+use chrono::DateTime;
+
+use crate::ast::{
+    BinaryHunk, BinaryKind, BinaryPatch, File, FileMetadata, FileOp, GitHeader, GitIndex, Hunk,
+    Line, Patch, PropertyChange, Range, SvnHeader,
+};
+use crate::patch_set::PatchSet;
+
+/// The version of the JSON schema written by [`Patch::to_json`], which is the only version
+/// [`Patch::from_json`] accepts
+const VERSION: u64 = 1;
+
+// How deeply arrays and objects may be nested, which is far more than the schema needs, so
+// malicious input can't overflow the stack
+const MAX_DEPTH: usize = 32;
+
+/// Error that can occur while reading a patch from JSON with [`Patch::from_json`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum JsonError {
+    /// The text isn't valid JSON
+    Syntax {
+        /// The byte offset in the text where the error was found
+        offset: usize,
+    },
+    /// The JSON is nested too deeply to be a patch
+    TooDeep {
+        /// The byte offset in the text where the limit was exceeded
+        offset: usize,
+    },
+    /// A field the schema requires is missing
+    MissingField {
+        /// The path of the field, like `hunks[0].old_range`
+        field: String,
+    },
+    /// A field has a value the schema doesn't allow
+    InvalidValue {
+        /// The path of the field, like `hunks[0].lines[2].kind`
+        field: String,
+        /// What the schema allows
+        expected: &'static str,
+    },
+    /// The JSON was written for a version of the schema this version of the crate doesn't know
+    UnsupportedVersion(u64),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::Syntax { offset } => write!(f, "Invalid JSON at byte {}", offset),
+            JsonError::TooDeep { offset } => {
+                write!(f, "The JSON is nested too deeply at byte {}", offset)
+            }
+            JsonError::MissingField { field } => write!(f, "Missing field '{}'", field),
+            JsonError::InvalidValue { field, expected } => {
+                write!(
+                    f,
+                    "Invalid value of field '{}': expected {}",
+                    field, expected
+                )
+            }
+            JsonError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported version {} of the patch schema", version)
+            }
+        }
+    }
+}
+
+impl Error for JsonError {}
+
+impl Patch<'_> {
+    /// Write the patch as JSON, in a stable schema that doesn't depend on how the types of this
+    /// crate are laid out
+    ///
+    /// The JSON is written without whitespace between its tokens. A patch is an object with
+    /// these fields, where the fields marked optional may be left out or `null` when reading it
+    /// back with [`Patch::from_json`], and unknown fields are ignored:
+    ///
+    /// - `version`: the number `1`, the version of the schema
+    /// - `old`, `new`: the files, objects with the fields
+    ///   - `path`: a string
+    ///   - `meta` (optional): `{"kind": "datetime", "value": "<RFC 3339 timestamp>"}` or
+    ///     `{"kind": "other", "value": "<text after the path>"}`
+    /// - `operation` (optional, default modify): an object with the field `kind`, one of
+    ///   `"modify"`, `"create"`, `"delete"`, `"rename"` and `"copy"`, and with renames and copies
+    ///   the strings `from` and `to`
+    /// - `git` (optional): the `diff --git` header, an object with the strings `old_path` and
+    ///   `new_path`, the optional octal strings `old_mode` and `new_mode`, like `"100644"`, and
+    ///   the optional percentages `similarity` and `dissimilarity`, and the optional `index`
+    ///   line, an object with the strings `old_id` and `new_id` and the optional octal string
+    ///   `mode`
+    /// - `svn` (optional): the Subversion header, an object with the optional string `index` and
+    ///   the array `properties` of objects with the string `name` and the optional strings `old`
+    ///   and `new`
+    /// - `hunks`: an array of objects with the fields
+    ///   - `old_range`, `new_range`: objects with the numbers `start` and `count`
+    ///   - `range_hint` (optional): a string, the text after the ranges of the hunk header
+    ///   - `lines`: an array of objects with a `kind`, one of `"context"`, `"add"` and
+    ///     `"remove"`, and the string `text`, without the line's prefix and newline character
+    /// - `binary` (optional): a git binary patch, an object with the hunk `forward` and the
+    ///   optional hunk `reverse`, each an object with a `kind` of `"literal"` or `"delta"`, the
+    ///   number `size` and the array `data` of the encoded lines
+    /// - `opaque_binary` (optional, default `false`): whether the patch only says that a binary
+    ///   file changed
+    /// - `preamble` (optional): a string, the text before the start of the patch
+    /// - `old_end_newline`, `new_end_newline` (optional, default `true`): whether the last line
+    ///   of the files ends in a newline character
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::Patch;
+    ///
+    /// let patch = Patch::from_single("--- a\n+++ b\n@@ -1 +1 @@\n-old\n+new\n").unwrap();
+    /// let json = patch.to_json();
+    /// assert!(json.contains(r#""lines":[{"kind":"remove","text":"old"},{"kind":"add","text":"new"}]"#));
+    /// assert_eq!(Patch::from_json(&json).unwrap(), patch);
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write!(json, "{{\"version\":{},", VERSION).unwrap();
+        write_patch_fields(&mut json, self);
+        json.push('}');
+        json
+    }
+}
+
+impl Patch<'static> {
+    /// Read a patch from JSON in the schema [`Patch::to_json`] writes
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::{Line, Patch};
+    ///
+    /// let patch = Patch::from_json(
+    ///     r#"{
+    ///         "version": 1,
+    ///         "old": {"path": "a/main.rs"},
+    ///         "new": {"path": "b/main.rs"},
+    ///         "hunks": [{
+    ///             "old_range": {"start": 1, "count": 1},
+    ///             "new_range": {"start": 1, "count": 2},
+    ///             "lines": [
+    ///                 {"kind": "context", "text": "fn main() {}"},
+    ///                 {"kind": "add", "text": "fn test() {}"}
+    ///             ]
+    ///         }]
+    ///     }"#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(patch.hunks[0].lines[1], Line::Add("fn test() {}".into()));
+    /// ```
+    pub fn from_json(json: &str) -> Result<Patch<'static>, JsonError> {
+        let value = parse(json)?;
+        let object = Object::new(&value, String::new())?;
+        check_version(&object)?;
+        read_patch(&object)
+    }
+}
+
+impl PatchSet<'_> {
+    /// Write the patches as JSON, as an object with the `version` of the schema and the array
+    /// `patches` of the patches in the schema of [`Patch::to_json`], without their versions
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write!(json, "{{\"version\":{},\"patches\":[", VERSION).unwrap();
+        for (i, patch) in self.patches.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push('{');
+            write_patch_fields(&mut json, patch);
+            json.push('}');
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+impl PatchSet<'static> {
+    /// Read patches from JSON in the schema [`PatchSet::to_json`] writes
+    pub fn from_json(json: &str) -> Result<PatchSet<'static>, JsonError> {
+        let value = parse(json)?;
+        let object = Object::new(&value, String::new())?;
+        check_version(&object)?;
+        let patches = object
+            .array("patches")?
+            .map(|(value, path)| read_patch(&Object::new(value, path)?))
+            .collect::<Result<_, _>>()?;
+        Ok(PatchSet { patches })
+    }
+}
+
+fn write_patch_fields(json: &mut String, patch: &Patch) {
+    json.push_str("\"old\":");
+    write_file(json, &patch.old);
+    json.push_str(",\"new\":");
+    write_file(json, &patch.new);
+
+    json.push_str(",\"operation\":");
+    match &patch.operation {
+        FileOp::Modify => json.push_str("{\"kind\":\"modify\"}"),
+        FileOp::Create => json.push_str("{\"kind\":\"create\"}"),
+        FileOp::Delete => json.push_str("{\"kind\":\"delete\"}"),
+        FileOp::Rename { from, to } | FileOp::Copy { from, to } => {
+            let kind = match patch.operation {
+                FileOp::Rename { .. } => "rename",
+                _ => "copy",
+            };
+            write!(json, "{{\"kind\":\"{}\",\"from\":", kind).unwrap();
+            write_string(json, from);
+            json.push_str(",\"to\":");
+            write_string(json, to);
+            json.push('}');
+        }
+    }
+
+    json.push_str(",\"git\":");
+    match &patch.git {
+        Some(git) => {
+            json.push_str("{\"old_path\":");
+            write_string(json, &git.old_path);
+            json.push_str(",\"new_path\":");
+            write_string(json, &git.new_path);
+            for (name, mode) in [("old_mode", git.old_mode), ("new_mode", git.new_mode)] {
+                write!(json, ",\"{}\":", name).unwrap();
+                match mode {
+                    Some(mode) => write!(json, "\"{:o}\"", mode).unwrap(),
+                    None => json.push_str("null"),
+                }
+            }
+            for (name, percent) in [
+                ("similarity", git.similarity),
+                ("dissimilarity", git.dissimilarity),
+            ] {
+                write!(json, ",\"{}\":", name).unwrap();
+                match percent {
+                    Some(percent) => write!(json, "{}", percent).unwrap(),
+                    None => json.push_str("null"),
+                }
+            }
+            json.push_str(",\"index\":");
+            match &git.index {
+                Some(index) => {
+                    json.push_str("{\"old_id\":");
+                    write_string(json, &index.old_id);
+                    json.push_str(",\"new_id\":");
+                    write_string(json, &index.new_id);
+                    match index.mode {
+                        Some(mode) => write!(json, ",\"mode\":\"{:o}\"}}", mode).unwrap(),
+                        None => json.push_str(",\"mode\":null}"),
+                    }
+                }
+                None => json.push_str("null"),
+            }
+            json.push('}');
+        }
+        None => json.push_str("null"),
+    }
+
+    json.push_str(",\"svn\":");
+    match &patch.svn {
+        Some(svn) => {
+            json.push_str("{\"index\":");
+            write_optional_string(json, svn.index.as_deref());
+            json.push_str(",\"properties\":[");
+            for (i, property) in svn.properties.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                json.push_str("{\"name\":");
+                write_string(json, &property.name);
+                json.push_str(",\"old\":");
+                write_optional_string(json, property.old.as_deref());
+                json.push_str(",\"new\":");
+                write_optional_string(json, property.new.as_deref());
+                json.push('}');
+            }
+            json.push_str("]}");
+        }
+        None => json.push_str("null"),
+    }
+
+    json.push_str(",\"hunks\":[");
+    for (i, hunk) in patch.hunks.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write_hunk(json, hunk);
+    }
+    json.push(']');
+
+    json.push_str(",\"binary\":");
+    match &patch.binary {
+        Some(binary) => {
+            json.push_str("{\"forward\":");
+            write_binary_hunk(json, &binary.forward);
+            json.push_str(",\"reverse\":");
+            match &binary.reverse {
+                Some(reverse) => write_binary_hunk(json, reverse),
+                None => json.push_str("null"),
+            }
+            json.push('}');
+        }
+        None => json.push_str("null"),
+    }
+
+    json.push_str(",\"preamble\":");
+    write_optional_string(json, patch.preamble.as_deref());
+
+    write!(
+        json,
+        ",\"opaque_binary\":{},\"old_end_newline\":{},\"new_end_newline\":{}",
+        patch.opaque_binary, patch.old_end_newline, patch.new_end_newline
+    )
+    .unwrap();
+}
+
+fn write_file(json: &mut String, file: &File) {
+    json.push_str("{\"path\":");
+    write_string(json, &file.path);
+    json.push_str(",\"meta\":");
+    match &file.meta {
+        Some(FileMetadata::DateTime(datetime)) => {
+            json.push_str("{\"kind\":\"datetime\",\"value\":");
+            write_string(json, &datetime.to_rfc3339());
+            json.push('}');
+        }
+        Some(FileMetadata::Other(other)) => {
+            json.push_str("{\"kind\":\"other\",\"value\":");
+            write_string(json, other);
+            json.push('}');
+        }
+        None => json.push_str("null"),
+    }
+    json.push('}');
+}
+
+fn write_hunk(json: &mut String, hunk: &Hunk) {
+    let range = |json: &mut String, range: &Range| {
+        write!(
+            json,
+            "{{\"start\":{},\"count\":{}}}",
+            range.start, range.count
+        )
+        .unwrap();
+    };
+    json.push_str("{\"old_range\":");
+    range(json, &hunk.old_range);
+    json.push_str(",\"new_range\":");
+    range(json, &hunk.new_range);
+    json.push_str(",\"range_hint\":");
+    write_string(json, &hunk.range_hint);
+    json.push_str(",\"lines\":[");
+    for (i, line) in hunk.lines.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        let (kind, text) = match line {
+            Line::Context(text) => ("context", text),
+            Line::Add(text) => ("add", text),
+            Line::Remove(text) => ("remove", text),
+        };
+        write!(json, "{{\"kind\":\"{}\",\"text\":", kind).unwrap();
+        write_string(json, text);
+        json.push('}');
+    }
+    json.push_str("]}");
+}
+
+fn write_binary_hunk(json: &mut String, hunk: &BinaryHunk) {
+    let kind = match hunk.kind {
+        BinaryKind::Literal => "literal",
+        BinaryKind::Delta => "delta",
+    };
+    write!(
+        json,
+        "{{\"kind\":\"{}\",\"size\":{},\"data\":[",
+        kind, hunk.size
+    )
+    .unwrap();
+    for (i, line) in hunk.data.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write_string(json, line);
+    }
+    json.push_str("]}");
+}
+
+fn write_optional_string(json: &mut String, s: Option<&str>) {
+    match s {
+        Some(s) => write_string(json, s),
+        None => json.push_str("null"),
+    }
+}
+
+// Write a JSON string literal, escaping quotes, backslashes and control characters
+fn write_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// A parsed JSON value
+#[derive(Debug, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    /// A number, `None` if it isn't an integer from 0 to `u64::MAX`, which is all the schema uses
+    Number(Option<u64>),
+    String(String),
+    Array(Vec<Value>),
+    /// The fields of an object, in their order in the text
+    Object(Vec<(String, Value)>),
+}
+
+// Parse a complete JSON text
+fn parse(json: &str) -> Result<Value, JsonError> {
+    let mut parser = Parser {
+        bytes: json.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value(0)?;
+    parser.whitespace();
+    if parser.pos < parser.bytes.len() {
+        return Err(parser.error());
+    }
+    Ok(value)
+}
+
+/// A recursive descent parser of JSON as described by RFC 8259
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self) -> JsonError {
+        JsonError::Syntax { offset: self.pos }
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    // Consume the given literal text, or fail without consuming anything
+    fn expect(&mut self, literal: &str) -> Result<(), JsonError> {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, JsonError> {
+        self.whitespace();
+        match self.bytes.get(self.pos) {
+            Some(b'{' | b'[') if depth == MAX_DEPTH => Err(JsonError::TooDeep { offset: self.pos }),
+            Some(b'{') => self.object(depth + 1),
+            Some(b'[') => self.array(depth + 1),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'n') => self.expect("null").map(|_| Value::Null),
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, JsonError> {
+        self.pos += 1;
+        let mut fields = Vec::new();
+        self.whitespace();
+        if self.expect("}").is_ok() {
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.whitespace();
+            if self.bytes.get(self.pos) != Some(&b'"') {
+                return Err(self.error());
+            }
+            let name = self.string()?;
+            self.whitespace();
+            self.expect(":")?;
+            fields.push((name, self.value(depth)?));
+            self.whitespace();
+            if self.expect("}").is_ok() {
+                return Ok(Value::Object(fields));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, JsonError> {
+        self.pos += 1;
+        let mut values = Vec::new();
+        self.whitespace();
+        if self.expect("]").is_ok() {
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value(depth)?);
+            self.whitespace();
+            if self.expect("]").is_ok() {
+                return Ok(Value::Array(values));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError> {
+        let start = self.pos;
+        let digits = |parser: &mut Self| {
+            let first = parser.pos;
+            while parser.bytes.get(parser.pos).is_some_and(u8::is_ascii_digit) {
+                parser.pos += 1;
+            }
+            match parser.pos > first {
+                true => Ok(()),
+                false => Err(parser.error()),
+            }
+        };
+        let _ = self.expect("-");
+        if self.expect("0").is_err() {
+            digits(self)?;
+        }
+        let integer_end = self.pos;
+        if self.expect(".").is_ok() {
+            digits(self)?;
+        }
+        if let Some(b'e' | b'E') = self.bytes.get(self.pos) {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.bytes.get(self.pos) {
+                self.pos += 1;
+            }
+            digits(self)?;
+        }
+        let integer = match integer_end == self.pos {
+            // The number has no sign, fraction or exponent, so it is made of ASCII digits
+            true => std::str::from_utf8(&self.bytes[start..integer_end])
+                .ok()
+                .and_then(|digits| digits.parse().ok()),
+            false => None,
+        };
+        Ok(Value::Number(integer))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(&b) = self.bytes.get(self.pos) {
+                if b == b'"' || b == b'\\' || b < b' ' {
+                    break;
+                }
+                self.pos += 1;
+            }
+            // The input is a `str` and the run stops at ASCII bytes, so it is valid UTF-8
+            s.push_str(std::str::from_utf8(&self.bytes[start..self.pos]).unwrap());
+            match self.bytes.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escaped = match self.bytes.get(self.pos) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            self.pos += 1;
+                            s.push(self.unicode_escape()?);
+                            continue;
+                        }
+                        _ => return Err(self.error()),
+                    };
+                    self.pos += 1;
+                    s.push(escaped);
+                }
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    // Parse the hex digits of a `\u` escape, and of a second one for surrogate pairs
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let hex = |parser: &mut Self| {
+            let digits = parser.bytes.get(parser.pos..parser.pos + 4);
+            let code = digits
+                .and_then(|digits| std::str::from_utf8(digits).ok())
+                .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                .ok_or(parser.error())?;
+            parser.pos += 4;
+            Ok(code)
+        };
+        let code = hex(self)?;
+        let code = match code {
+            0xD800..=0xDBFF => {
+                self.expect("\\u")?;
+                let low = hex(self)?;
+                if !(0xDC00..=0xDFFF).contains(&low) {
+                    return Err(self.error());
+                }
+                0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00)
+            }
+            code => code,
+        };
+        char::from_u32(code).ok_or(self.error())
+    }
+}
+
+/// The fields of a JSON object, with the path of the object for errors
+struct Object<'v> {
+    fields: &'v [(String, Value)],
+    path: String,
+}
+
+impl<'v> Object<'v> {
+    fn new(value: &'v Value, path: String) -> Result<Self, JsonError> {
+        match value {
+            Value::Object(fields) => Ok(Object { fields, path }),
+            _ => Err(invalid(path, "an object")),
+        }
+    }
+
+    fn path(&self, name: &str) -> String {
+        match self.path.is_empty() {
+            true => name.to_string(),
+            false => format!("{}.{}", self.path, name),
+        }
+    }
+
+    // The value of a field, where `null` counts as missing like the schema says
+    fn optional(&self, name: &str) -> Option<&'v Value> {
+        // Like JavaScript, the last of duplicate fields wins
+        let (_, value) = self.fields.iter().rev().find(|(field, _)| field == name)?;
+        match value {
+            Value::Null => None,
+            value => Some(value),
+        }
+    }
+
+    fn required(&self, name: &str) -> Result<&'v Value, JsonError> {
+        self.optional(name).ok_or_else(|| JsonError::MissingField {
+            field: self.path(name),
+        })
+    }
+
+    fn object(&self, name: &str) -> Result<Object<'v>, JsonError> {
+        Object::new(self.required(name)?, self.path(name))
+    }
+
+    fn optional_object(&self, name: &str) -> Result<Option<Object<'v>>, JsonError> {
+        self.optional(name)
+            .map(|value| Object::new(value, self.path(name)))
+            .transpose()
+    }
+
+    fn string(&self, name: &str) -> Result<String, JsonError> {
+        self.optional_string(name)?
+            .ok_or_else(|| JsonError::MissingField {
+                field: self.path(name),
+            })
+    }
+
+    fn optional_string(&self, name: &str) -> Result<Option<String>, JsonError> {
+        match self.optional(name) {
+            Some(Value::String(s)) => Ok(Some(s.clone())),
+            Some(_) => Err(invalid(self.path(name), "a string")),
+            None => Ok(None),
+        }
+    }
+
+    fn number(&self, name: &str) -> Result<u64, JsonError> {
+        match self.required(name)? {
+            Value::Number(Some(n)) => Ok(*n),
+            _ => Err(invalid(self.path(name), "a non-negative integer")),
+        }
+    }
+
+    fn optional_bool(&self, name: &str) -> Result<Option<bool>, JsonError> {
+        match self.optional(name) {
+            Some(Value::Bool(b)) => Ok(Some(*b)),
+            Some(_) => Err(invalid(self.path(name), "a boolean")),
+            None => Ok(None),
+        }
+    }
+
+    // The values of an array field, with their paths
+    fn array(
+        &self,
+        name: &str,
+    ) -> Result<impl Iterator<Item = (&'v Value, String)> + use<'v>, JsonError> {
+        let path = self.path(name);
+        match self.required(name)? {
+            Value::Array(values) => Ok(values
+                .iter()
+                .enumerate()
+                .map(move |(i, value)| (value, format!("{}[{}]", path, i)))),
+            _ => Err(invalid(path, "an array")),
+        }
+    }
+
+    // The `kind` field of the objects of enums, with its path
+    fn kind(&self) -> Result<(String, String), JsonError> {
+        Ok((self.string("kind")?, self.path("kind")))
+    }
+}
+
+fn invalid(field: String, expected: &'static str) -> JsonError {
+    JsonError::InvalidValue { field, expected }
+}
+
+fn check_version(object: &Object) -> Result<(), JsonError> {
+    match object.number("version")? {
+        VERSION => Ok(()),
+        version => Err(JsonError::UnsupportedVersion(version)),
+    }
+}
+
+fn read_patch(object: &Object) -> Result<Patch<'static>, JsonError> {
+    let old = read_file(&object.object("old")?)?;
+    let new = read_file(&object.object("new")?)?;
+    let operation = match object.optional_object("operation")? {
+        Some(operation) => {
+            let (kind, path) = operation.kind()?;
+            let paths = || -> Result<_, JsonError> {
+                Ok((
+                    Cow::Owned(operation.string("from")?),
+                    Cow::Owned(operation.string("to")?),
+                ))
+            };
+            match kind.as_str() {
+                "modify" => FileOp::Modify,
+                "create" => FileOp::Create,
+                "delete" => FileOp::Delete,
+                "rename" => {
+                    let (from, to) = paths()?;
+                    FileOp::Rename { from, to }
+                }
+                "copy" => {
+                    let (from, to) = paths()?;
+                    FileOp::Copy { from, to }
+                }
+                _ => {
+                    return Err(invalid(
+                        path,
+                        "\"modify\", \"create\", \"delete\", \"rename\" or \"copy\"",
+                    ));
+                }
+            }
+        }
+        None => FileOp::Modify,
+    };
+
+    let hunks = object
+        .array("hunks")?
+        .map(|(value, path)| read_hunk(&Object::new(value, path)?))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Patch {
+        old,
+        new,
+        operation,
+        git: object
+            .optional_object("git")?
+            .map(|git| read_git(&git))
+            .transpose()?,
+        svn: object
+            .optional_object("svn")?
+            .map(|svn| read_svn(&svn))
+            .transpose()?,
+        hunks,
+        binary: object
+            .optional_object("binary")?
+            .map(|binary| {
+                Ok::<_, JsonError>(BinaryPatch {
+                    forward: read_binary_hunk(&binary.object("forward")?)?,
+                    reverse: binary
+                        .optional_object("reverse")?
+                        .map(|reverse| read_binary_hunk(&reverse))
+                        .transpose()?,
+                })
+            })
+            .transpose()?,
+        opaque_binary: object.optional_bool("opaque_binary")?.unwrap_or(false),
+        preamble: object.optional_string("preamble")?.map(Cow::Owned),
+        raw: None,
+        old_end_newline: object.optional_bool("old_end_newline")?.unwrap_or(true),
+        new_end_newline: object.optional_bool("new_end_newline")?.unwrap_or(true),
+    })
+}
+
+fn read_file(object: &Object) -> Result<File<'static>, JsonError> {
+    let path = Cow::Owned(object.string("path")?);
+    let meta = match object.optional_object("meta")? {
+        Some(meta) => {
+            let (kind, path) = meta.kind()?;
+            let value = meta.string("value")?;
+            match kind.as_str() {
+                "datetime" => match DateTime::parse_from_rfc3339(&value) {
+                    Ok(datetime) => Some(FileMetadata::DateTime(datetime)),
+                    Err(_) => return Err(invalid(meta.path("value"), "an RFC 3339 timestamp")),
+                },
+                "other" => Some(FileMetadata::Other(Cow::Owned(value))),
+                _ => return Err(invalid(path, "\"datetime\" or \"other\"")),
+            }
+        }
+        None => None,
+    };
+    Ok(File { path, meta })
+}
+
+fn read_git(object: &Object) -> Result<GitHeader<'static>, JsonError> {
+    let mode = |name| read_mode(object, name);
+    let percent = |name| match object.optional(name) {
+        Some(Value::Number(Some(n))) if *n <= 100 => Ok(Some(*n as u8)),
+        Some(_) => Err(invalid(object.path(name), "an integer from 0 to 100")),
+        None => Ok(None),
+    };
+    Ok(GitHeader {
+        old_path: Cow::Owned(object.string("old_path")?),
+        new_path: Cow::Owned(object.string("new_path")?),
+        old_mode: mode("old_mode")?,
+        new_mode: mode("new_mode")?,
+        similarity: percent("similarity")?,
+        dissimilarity: percent("dissimilarity")?,
+        index: object
+            .optional_object("index")?
+            .map(|index| {
+                Ok::<_, JsonError>(GitIndex {
+                    old_id: Cow::Owned(index.string("old_id")?),
+                    new_id: Cow::Owned(index.string("new_id")?),
+                    mode: read_mode(&index, "mode")?,
+                })
+            })
+            .transpose()?,
+    })
+}
+
+fn read_mode(object: &Object, name: &str) -> Result<Option<u32>, JsonError> {
+    match object.optional_string(name)? {
+        Some(mode) => u32::from_str_radix(&mode, 8)
+            .map(Some)
+            .map_err(|_| invalid(object.path(name), "an octal string")),
+        None => Ok(None),
+    }
+}
+
+fn read_svn(object: &Object) -> Result<SvnHeader<'static>, JsonError> {
+    let properties = object
+        .array("properties")?
+        .map(|(value, path)| {
+            let property = Object::new(value, path)?;
+            Ok(PropertyChange {
+                name: Cow::Owned(property.string("name")?),
+                old: property.optional_string("old")?.map(Cow::Owned),
+                new: property.optional_string("new")?.map(Cow::Owned),
+            })
+        })
+        .collect::<Result<_, JsonError>>()?;
+    Ok(SvnHeader {
+        index: object.optional_string("index")?.map(Cow::Owned),
+        properties,
+    })
+}
+
+fn read_hunk(object: &Object) -> Result<Hunk<'static>, JsonError> {
+    let range = |name| -> Result<Range, JsonError> {
+        let range = object.object(name)?;
+        Ok(Range {
+            start: range.number("start")?,
+            count: range.number("count")?,
+        })
+    };
+    let old_range = range("old_range")?;
+    let new_range = range("new_range")?;
+    let lines = object
+        .array("lines")?
+        .map(|(value, path)| {
+            let line = Object::new(value, path)?;
+            let (kind, path) = line.kind()?;
+            let text = Cow::Owned(line.string("text")?);
+            match kind.as_str() {
+                "context" => Ok(Line::Context(text)),
+                "add" => Ok(Line::Add(text)),
+                "remove" => Ok(Line::Remove(text)),
+                _ => Err(invalid(path, "\"context\", \"add\" or \"remove\"")),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Hunk {
+        old_range,
+        new_range,
+        range_hint: Cow::Owned(object.optional_string("range_hint")?.unwrap_or_default()),
+        lines,
+    })
+}
+
+fn read_binary_hunk(object: &Object) -> Result<BinaryHunk<'static>, JsonError> {
+    let (kind, path) = object.kind()?;
+    let kind = match kind.as_str() {
+        "literal" => BinaryKind::Literal,
+        "delta" => BinaryKind::Delta,
+        _ => return Err(invalid(path, "\"literal\" or \"delta\"")),
+    };
+    let data = object
+        .array("data")?
+        .map(|(value, path)| match value {
+            Value::String(line) => Ok(Cow::Owned(line.clone())),
+            _ => Err(invalid(path, "a string")),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(BinaryHunk {
+        kind,
+        size: object.number("size")?,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_round_trip() {
+        let sample = "\
+Rename \"tab\\there.txt\"
+diff --git \"a/tab\\there.txt\" b/new.txt
+old mode 100644
+new mode 100755
+similarity index 90%
+rename from \"tab\\there.txt\"
+rename to new.txt
+--- \"a/tab\\there.txt\"\t2002-02-21 23:30:39.942229878 -0800
++++ b/new.txt\tsome \"meta\"
+@@ -1,2 +1,2 @@ fn main() {
+ \u{e9}t\u{e9} \\ \u{1}
+-old
++new
+\\ No newline at end of file
+";
+        let patch = Patch::from_single(sample).unwrap();
+        let json = patch.to_json();
+        assert_eq!(Patch::from_json(&json).unwrap(), patch);
+        assert!(json.contains("\"old_mode\":\"100644\""));
+        assert!(json.contains("\"preamble\":\"Rename \\\"tab\\\\there.txt\\\"\\n\""));
+        assert!(json.contains(
+            "\"meta\":{\"kind\":\"datetime\",\"value\":\"2002-02-21T23:30:39.942229878-08:00\"}"
+        ));
+        assert!(json.contains("\"text\":\"\u{e9}t\u{e9} \\\\ \\u0001\""));
+
+        let text = format!("{}{}", sample, sample);
+        let set = PatchSet::parse(&text).unwrap();
+        let set_json = set.to_json();
+        assert!(set_json.starts_with("{\"version\":1,\"patches\":[{\"old\":"));
+        assert_eq!(PatchSet::from_json(&set_json).unwrap(), set);
+    }
+
+    #[test]
+    fn test_from_json_defaults() {
+        let patch = Patch::from_json(
+            r#"{"version": 1, "old": {"path": "a"}, "new": {"path": "b", "meta": null},
+                "extra": [1.5e3, -2, {"ignored": true}], "hunks": []}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            patch,
+            Patch {
+                old: File {
+                    path: "a".into(),
+                    meta: None,
+                },
+                new: File {
+                    path: "b".into(),
+                    meta: None,
+                },
+                operation: FileOp::Modify,
+                git: None,
+                svn: None,
+                hunks: Vec::new(),
+                binary: None,
+                opaque_binary: false,
+                preamble: None,
+                raw: None,
+                old_end_newline: true,
+                new_end_newline: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        let patch = |hunks: &str| {
+            Patch::from_json(&format!(
+                r#"{{"version": 1, "old": {{"path": "a"}}, "new": {{"path": "b"}}, "hunks": {}}}"#,
+                hunks
+            ))
+        };
+        assert_eq!(patch("[").unwrap_err(), JsonError::Syntax { offset: 69 });
+        assert_eq!(
+            patch(r#"[{"old_range": {"start": 1, "count": 0}}]"#).unwrap_err(),
+            JsonError::MissingField {
+                field: "hunks[0].new_range".to_string()
+            }
+        );
+        let range =
+            r#""old_range": {"start": 1, "count": 1}, "new_range": {"start": 1, "count": 1}"#;
+        assert_eq!(
+            patch(&format!(
+                r#"[{{{}, "lines": [{{"kind": "same", "text": ""}}]}}]"#,
+                range
+            ))
+            .unwrap_err(),
+            JsonError::InvalidValue {
+                field: "hunks[0].lines[0].kind".to_string(),
+                expected: "\"context\", \"add\" or \"remove\""
+            }
+        );
+        assert_eq!(
+            patch(&format!(r#"[{{{}, "lines": [], "range_hint": 3}}]"#, range)).unwrap_err(),
+            JsonError::InvalidValue {
+                field: "hunks[0].range_hint".to_string(),
+                expected: "a string"
+            }
+        );
+        assert_eq!(
+            Patch::from_json(r#"{"version": 2}"#).unwrap_err(),
+            JsonError::UnsupportedVersion(2)
+        );
+        assert_eq!(
+            Patch::from_json(&"[".repeat(100)).unwrap_err(),
+            JsonError::TooDeep { offset: 32 }
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(r#" {"a": [null, true, false, 0, 12, -1, 1.0, "\u00e9\ud83d\ude00\n\/"]} "#),
+            Ok(Value::Object(vec![(
+                "a".to_string(),
+                Value::Array(vec![
+                    Value::Null,
+                    Value::Bool(true),
+                    Value::Bool(false),
+                    Value::Number(Some(0)),
+                    Value::Number(Some(12)),
+                    Value::Number(None),
+                    Value::Number(None),
+                    Value::String("\u{e9}\u{1f600}\n/".to_string()),
+                ])
+            )]))
+        );
+        for invalid in [
+            "",
+            "01",
+            "[1,]",
+            "{\"a\" 1}",
+            "\"\\ud800\"",
+            "\"\n\"",
+            "nul",
+            "1 2",
+        ] {
+            assert!(parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -40,15 +40,197 @@
 //! # }
 //! ```
 //!
+//! ## Features
+//!
+//! - `cli`: builds the `patch-rs` binary, with `apply`, `check`, `diff`, `stat` and `filter`
+//!   subcommands for using the crate from the command line.
+//! - `ffi`: adds the `ffi` module of `extern "C"` functions, declared in `include/patch.h`,
+//!   for using the crate as a shared library from other languages.
+//! - `ignore`: makes `diff_dirs` honor `DirDiffOptions::gitignore` and `DirDiffOptions::ignore`,
+//!   matching the patterns with the `ignore` crate like git does.
+//! - `miette`: implements `miette::Diagnostic` for [`ParseError`], so parse errors are shown
+//!   with the line they occurred on and what was expected there.
+//! - `mmap`: adds `apply_mmap`, which applies a patch to a memory-mapped file, for files too
+//!   large to read into memory.
+//! - `rayon`: adds `PatchSet::par_apply`, which applies the patches of different files in
+//!   parallel.
+//! - `regex`: adds `FunctionContext::Patterns`, which finds the function or section of the
+//!   hunks of generated diffs with regular expressions.
+//! - `ropey`: adds `apply_to_rope`, which applies a patch to a `ropey::Rope` in place, and
+//!   `RopeLines`, which reads the lines of one for `apply_source`.
+//! - `serde`: implements `Serialize` and `Deserialize` for [`Patch`] and the types it is made of.
+//!   Deserialized patches own their text, so they don't borrow from the serialized data.
+//! - `sha1`: adds `verify_index`, which checks that a file is the one a git patch was made for
+//!   by the id of its blob, the same check for [`ApplyOptions::verify_index`], and `blob_id`.
+//!   Unlike the other features, this works without the standard library.
+//! - `std`, enabled by default: adds everything that needs the standard library, like reading
+//!   files, writing to `std::io::Write` and `PatchSet`. Without it, the crate is `no_std` and
+//!   only needs `alloc`, keeping the parser, [`apply`] and the other string-based appliers,
+//!   [`diff`] and [`merge`].
+//! - `tokio`: adds `apply_to_dir_async`, which applies patches to the files in a directory with
+//!   `tokio::fs`, several files at a time, and streams the result of every file.
+//! - `wasm`: adds `parse_patch`, `apply_patch` and `generate_diff`, which are exported to
+//!   JavaScript as `parsePatch`, `applyPatch` and `generateDiff` with `wasm-bindgen`.
+//!
 //! [Unified Format]: https://www.gnu.org/software/diffutils/manual/html_node/Unified-Format.html
 //! [spec]: http://www.artima.com/weblogs/viewpost.jsp?thread=164293
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![deny(unused_must_use)]
 
+extern crate alloc;
+
 mod applier;
 mod ast;
+#[cfg(feature = "tokio")]
+mod async_fs;
+#[cfg(feature = "std")]
+mod binary;
+#[cfg(feature = "sha1")]
+mod blob;
+#[cfg(feature = "std")]
+mod builder;
+mod cancel;
+#[cfg(feature = "std")]
+mod combine;
+#[cfg(feature = "std")]
+mod combined;
+#[cfg(feature = "std")]
+mod compare;
+#[cfg(feature = "std")]
+mod compiled;
+#[cfg(feature = "std")]
+mod dir_diff;
+#[cfg(feature = "std")]
+mod edits;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+mod formats;
+#[cfg(feature = "std")]
+mod fs;
+mod generator;
+#[cfg(feature = "std")]
+mod glob;
+mod hash;
+#[cfg(feature = "std")]
+mod interdiff;
+#[cfg(feature = "std")]
+mod json;
+#[cfg(feature = "std")]
+mod lint;
+#[cfg(feature = "std")]
+mod mailbox;
+#[cfg(feature = "std")]
+mod mapping;
+mod merge;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "std")]
+mod moves;
 mod parser;
+#[cfg(feature = "std")]
+mod patch_set;
+#[cfg(feature = "std")]
+mod range_diff;
+#[cfg(feature = "std")]
+mod reader;
+#[cfg(feature = "std")]
+mod rebase;
+#[cfg(feature = "std")]
+mod render;
+#[cfg(feature = "ropey")]
+mod rope;
+#[cfg(feature = "std")]
+mod series;
+#[cfg(feature = "std")]
+mod source;
+#[cfg(feature = "std")]
+mod stats;
+mod text;
+#[cfg(feature = "std")]
+mod vfs;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "std")]
+mod words;
 
-pub use applier::{ApplyError, apply, find_replace_apply};
+#[cfg(feature = "std")]
+pub use applier::apply_to_writer;
+pub use applier::{
+    AppliedState, ApplyError, ApplyObserver, ApplyOptions, ApplyReport, FindReplaceOptions,
+    FindReplaceResult, HunkStatus, LineEnding, RejectedHunk, Whitespace, apply, apply_bytes,
+    apply_cow, apply_partial, apply_reverse, apply_selected, apply_with_conflicts,
+    apply_with_observer, apply_with_options, apply_with_report, check, find_replace_apply,
+    find_replace_apply_with_options, reject_patch,
+};
 pub use ast::*;
-pub use parser::ParseError;
+#[cfg(feature = "tokio")]
+pub use async_fs::{DirApplyStream, apply_to_dir_async};
+#[cfg(feature = "std")]
+pub use binary::{BinaryError, apply_binary, apply_binary_reverse};
+#[cfg(feature = "sha1")]
+pub use blob::{blob_id, verify_index};
+#[cfg(feature = "std")]
+pub use builder::{HunkBuilder, PatchBuilder};
+pub use cancel::CancellationToken;
+#[cfg(feature = "std")]
+pub use combine::{CombineError, combine};
+#[cfg(feature = "std")]
+pub use combined::{CombinedHunk, CombinedLine, CombinedPatch, Origin};
+#[cfg(feature = "std")]
+pub use compiled::CompiledPatch;
+#[cfg(feature = "std")]
+pub use dir_diff::{DirDiffOptions, diff_dirs};
+#[cfg(feature = "std")]
+pub use edits::{TextEdit, text_edits};
+#[cfg(feature = "std")]
+pub use formats::{FormatOptions, Prefix};
+#[cfg(feature = "std")]
+pub use fs::{DirReport, FsError, apply_to_dir, apply_to_vfs};
+pub use generator::{DiffAlgorithm, DiffOptions, FunctionContext, diff, diff_with_options};
+#[cfg(feature = "std")]
+pub use interdiff::interdiff;
+#[cfg(feature = "std")]
+pub use json::JsonError;
+#[cfg(feature = "std")]
+pub use lint::{Lint, LintCode, Severity, lint};
+#[cfg(feature = "std")]
+pub use mailbox::{EmailPatch, MailboxError, Trailer, parse_mailbox};
+#[cfg(feature = "std")]
+pub use mapping::LineMapping;
+pub use merge::{Conflict, ConflictStyle, MergeOptions, MergeResult, merge, merge_with_options};
+#[cfg(feature = "mmap")]
+pub use mmap::apply_mmap;
+#[cfg(feature = "std")]
+pub use moves::MovedBlock;
+pub use parser::{Expected, ParseError, ParseLimit, ParseOptions, ParseWarning, Skipped};
+#[cfg(feature = "rayon")]
+pub use patch_set::PatchSetReport;
+#[cfg(feature = "std")]
+pub use patch_set::{FileChange, PatchSet, PatchSetError, strip_path};
+#[cfg(feature = "std")]
+pub use range_diff::{RangeDiffEntry, range_diff};
+#[cfg(feature = "std")]
+pub use reader::ReadError;
+#[cfg(feature = "std")]
+pub use rebase::{RebaseError, rebase};
+#[cfg(feature = "std")]
+pub use render::{
+    Color, ColorChoice, HtmlLayout, HtmlOptions, Overflow, SideBySideOptions, Style,
+    TerminalOptions,
+};
+#[cfg(feature = "ropey")]
+pub use rope::{RopeLines, apply_to_rope};
+#[cfg(feature = "std")]
+pub use series::{PatchStack, Series, SeriesEntry, SeriesError, StackError};
+#[cfg(feature = "std")]
+pub use source::{LineSink, LineSource, apply_source};
+#[cfg(feature = "std")]
+pub use stats::{DiffStat, FileStat};
+#[cfg(feature = "std")]
+pub use vfs::{DiskFs, MemoryFs, Vfs};
+#[cfg(feature = "wasm")]
+pub use wasm::{apply_patch, generate_diff, parse_patch};
+#[cfg(feature = "std")]
+pub use words::{Granularity, LineSpan, WordChange};
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{Hunk, Line};
+use crate::patch_set::{PatchSet, file_name};
+
+/// A structural problem found in a patch by [`lint`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Lint {
+    /// What kind of problem this is
+    pub code: LintCode,
+    /// How serious the problem is, which is the [`LintCode::severity`] of its code
+    pub severity: Severity,
+    /// The index of the patch with the problem in the set
+    pub patch: usize,
+    /// The index of the hunk with the problem in its patch, if the problem is about a hunk
+    pub hunk: Option<usize>,
+    /// The path of the file of the patch, without the `a/` or `b/` prefix of git patches
+    pub path: String,
+    /// A description of the problem
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.path)?;
+        if let Some(hunk) = self.hunk {
+            write!(f, ", hunk {}", hunk + 1)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// The kinds of problems [`lint`] finds
+///
+/// Every code has a stable name for machine-readable output, which is what `Display` writes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum LintCode {
+    /// A hunk changes some of the same lines of the old file as the hunk before it
+    OverlappingHunks,
+    /// A hunk starts before the hunk before it in the old file
+    HunksOutOfOrder,
+    /// A hunk has fewer or more lines than the ranges of its header say
+    LineCountMismatch,
+    /// Two overlapping hunks disagree about the content of a line of the old file
+    ContextMismatch,
+    /// A patch removes the newline at the end of its file, so the new file ends with a
+    /// `\ No newline at end of file` marker
+    MissingNewline,
+    /// A file is changed by more than one patch of the set
+    DuplicateFile,
+}
+
+impl LintCode {
+    /// The stable name of the code, like `overlapping-hunks`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintCode::OverlappingHunks => "overlapping-hunks",
+            LintCode::HunksOutOfOrder => "hunks-out-of-order",
+            LintCode::LineCountMismatch => "line-count-mismatch",
+            LintCode::ContextMismatch => "context-mismatch",
+            LintCode::MissingNewline => "missing-newline",
+            LintCode::DuplicateFile => "duplicate-file",
+        }
+    }
+
+    /// How serious problems of this kind are
+    ///
+    /// Errors make a patch fail to apply or apply wrongly, warnings are usually mistakes and
+    /// infos are merely unusual.
+    pub fn severity(&self) -> Severity {
+        match self {
+            LintCode::OverlappingHunks
+            | LintCode::HunksOutOfOrder
+            | LintCode::LineCountMismatch
+            | LintCode::ContextMismatch => Severity::Error,
+            LintCode::DuplicateFile => Severity::Warning,
+            LintCode::MissingNewline => Severity::Info,
+        }
+    }
+}
+
+impl fmt::Display for LintCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// How serious a [`Lint`] is, from the least to the most serious
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Severity {
+    /// Unusual, but not a problem in itself
+    Info,
+    /// Probably a mistake
+    Warning,
+    /// The patch can't be applied as intended
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}", severity)
+    }
+}
+
+/// Look for structural problems in the patches of a set, like overlapping hunks or hunk headers
+/// that don't match their lines
+///
+/// The problems are returned in the order of the patches and hunks they are found in. A set
+/// without problems returns no lints.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), patch::ParseError<'static>> {
+/// # use patch::{LintCode, PatchSet, Severity, lint};
+/// let sample = "\
+/// --- a.txt
+/// +++ a.txt
+/// @@ -1,3 +1,3 @@
+///  a
+/// -b
+/// +B
+/// @@ -10 +10 @@
+/// -j
+/// +J
+/// ";
+///
+/// let lints = lint(&PatchSet::parse(sample)?);
+/// assert_eq!(lints.len(), 1);
+/// assert_eq!(lints[0].code, LintCode::LineCountMismatch);
+/// assert_eq!(lints[0].severity, Severity::Error);
+/// assert_eq!(
+///     lints[0].to_string(),
+///     "error[line-count-mismatch]: a.txt, hunk 1: the header says the hunk has 3 old and 3 new \
+///      lines, but it has 2 and 2"
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub fn lint(patches: &PatchSet) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    // The first patch of every file
+    let mut files = HashMap::new();
+    for (index, patch) in patches.patches.iter().enumerate() {
+        let mut report = |code: LintCode, hunk: Option<usize>, message: String| {
+            lints.push(Lint {
+                code,
+                severity: code.severity(),
+                patch: index,
+                hunk,
+                path: file_name(patch).to_string(),
+                message,
+            })
+        };
+
+        let first = *files.entry(file_name(patch)).or_insert(index);
+        if first != index {
+            report(
+                LintCode::DuplicateFile,
+                None,
+                format!("the file is also changed by patch {}", first + 1),
+            );
+        }
+
+        for (i, hunk) in patch.hunks.iter().enumerate() {
+            let counts = hunk.line_counts();
+            if counts != (hunk.old_range.count, hunk.new_range.count) {
+                report(
+                    LintCode::LineCountMismatch,
+                    Some(i),
+                    format!(
+                        "the header says the hunk has {} old and {} new lines, but it has {} and {}",
+                        hunk.old_range.count, hunk.new_range.count, counts.0, counts.1
+                    ),
+                );
+            }
+            let Some(previous) = i.checked_sub(1).map(|previous| &patch.hunks[previous]) else {
+                continue;
+            };
+            if hunk.old_range.start < previous.old_range.start {
+                report(
+                    LintCode::HunksOutOfOrder,
+                    Some(i),
+                    format!(
+                        "the hunk starts at line {}, before hunk {} at line {}",
+                        hunk.old_range.start, i, previous.old_range.start
+                    ),
+                );
+            } else if hunk.old_range.first_line() < previous.old_range.end_line() {
+                report(
+                    LintCode::OverlappingHunks,
+                    Some(i),
+                    format!(
+                        "the hunk starts at line {}, before the end of hunk {} at line {}",
+                        hunk.old_range.first_line(),
+                        i,
+                        previous.old_range.end_line() - 1
+                    ),
+                );
+                if let Some(line) = context_mismatch(previous, hunk) {
+                    report(
+                        LintCode::ContextMismatch,
+                        Some(i),
+                        format!("line {} of the old file differs from hunk {}", line, i),
+                    );
+                }
+            }
+        }
+
+        if patch.old_end_newline && !patch.new_end_newline {
+            report(
+                LintCode::MissingNewline,
+                patch.hunks.len().checked_sub(1),
+                "the new file doesn't end with a newline".to_string(),
+            );
+        }
+    }
+    lints
+}
+
+// The first line of the old file two hunks both have, but with different content
+fn context_mismatch(first: &Hunk, second: &Hunk) -> Option<u64> {
+    let first_lines = old_lines(first);
+    old_lines(second)
+        .into_iter()
+        .filter(|(line, text)| first_lines.get(line).is_some_and(|other| other != text))
+        .map(|(line, _)| line)
+        .min()
+}
+
+// The lines of the old file in a hunk, by their line number
+fn old_lines<'h>(hunk: &'h Hunk) -> HashMap<u64, &'h str> {
+    let old = hunk.lines.iter().filter_map(|line| match line {
+        Line::Context(text) | Line::Remove(text) => Some(text.as_ref()),
+        Line::Add(_) => None,
+    });
+    (hunk.old_range.first_line()..).zip(old).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn codes(sample: &str) -> Vec<(LintCode, usize, Option<usize>)> {
+        let set = PatchSet::parse(sample).unwrap();
+        lint(&set)
+            .into_iter()
+            .map(|lint| (lint.code, lint.patch, lint.hunk))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_clean_patch() {
+        let sample = "\
+--- a.txt
++++ a.txt
+@@ -1,3 +1,3 @@
+ a
+-b
++B
+ c
+@@ -10 +10,2 @@
+ j
++k
+";
+        assert_eq!(codes(sample), []);
+    }
+
+    #[test]
+    fn test_lint_hunks() {
+        let sample = "\
+--- a.txt
++++ a.txt
+@@ -1,3 +1,3 @@
+ a
+-b
++B
+ c
+@@ -3,2 +3,2 @@
+ x
+-d
++D
+@@ -2 +2 @@
+-b
++B
+";
+        assert_eq!(
+            codes(sample),
+            [
+                (LintCode::OverlappingHunks, 0, Some(1)),
+                (LintCode::ContextMismatch, 0, Some(1)),
+                (LintCode::HunksOutOfOrder, 0, Some(2)),
+            ]
+        );
+        let lints = lint(&PatchSet::parse(sample).unwrap());
+        assert_eq!(
+            lints[1].to_string(),
+            "error[context-mismatch]: a.txt, hunk 2: line 3 of the old file differs from hunk 1"
+        );
+    }
+
+    #[test]
+    fn test_lint_files() {
+        let sample = "\
+--- a/a.txt
++++ b/a.txt
+@@ -1 +1 @@
+-a
++A
+\\ No newline at end of file
+--- a/b.txt
++++ b/b.txt
+@@ -1 +1 @@
+-b
++B
+--- a/a.txt
++++ b/a.txt
+@@ -5 +5 @@
+-e
++E
+";
+        assert_eq!(
+            codes(sample),
+            [
+                (LintCode::MissingNewline, 0, Some(0)),
+                (LintCode::DuplicateFile, 2, None),
+            ]
+        );
+    }
+}
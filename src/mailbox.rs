@@ -0,0 +1,693 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::parser::ParseError;
+use crate::patch_set::PatchSet;
+use crate::stats::DiffStat;
+
+/// The commit id written on the `From` line of emails without one
+const ZERO_COMMIT: &str = "0000000000000000000000000000000000000000";
+
+/// A patch sent by email, like `git format-patch` writes them, with the commit it comes from
+///
+/// # Example
+///
+/// ```
+/// use patch::EmailPatch;
+///
+/// let email = "\
+/// From 2a7c5e1f0b3d4c6e8f9a0b1c2d3e4f5a6b7c8d9e Mon Sep 17 00:00:00 2001
+/// From: A U Thor <author@example.com>
+/// Date: Thu, 7 Apr 2005 15:13:13 -0700
+/// Subject: [PATCH] Greet the world
+///
+/// Saying hello to everyone is friendlier.
+///
+/// Signed-off-by: A U Thor <author@example.com>
+/// ---
+///  hello.txt | 2 +-
+///  1 file changed, 1 insertion(+), 1 deletion(-)
+///
+/// diff --git a/hello.txt b/hello.txt
+/// index 1234567..89abcde 100644
+/// --- a/hello.txt
+/// +++ b/hello.txt
+/// @@ -1 +1 @@
+/// -Hello
+/// +Hello, world
+/// --\x20
+/// 2.43.0
+///
+/// ";
+///
+/// let patch = EmailPatch::parse(email).unwrap();
+/// assert_eq!(patch.author, "A U Thor <author@example.com>");
+/// assert_eq!(patch.subject, "Greet the world");
+/// assert_eq!(patch.message, "Saying hello to everyone is friendlier.");
+/// assert_eq!(patch.trailers[0].key, "Signed-off-by");
+/// assert_eq!(patch.patches.patches[0].hunks.len(), 1);
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EmailPatch<'a> {
+    /// The commit id on the `From <commit> <date>` line that starts the email, if it has one
+    pub commit: Option<Cow<'a, str>>,
+    /// The author of the change, usually as `Name <email>`, header: `From`
+    pub author: Cow<'a, str>,
+    /// The date of the change as it is written in the email, header: `Date`
+    ///
+    /// See [`EmailPatch::date_time`] to parse it.
+    pub date: Option<Cow<'a, str>>,
+    /// The subject of the email without the `[PATCH ...]` prefix, which is the first line of
+    /// the commit message, header: `Subject`
+    pub subject: Cow<'a, str>,
+    /// The rest of the commit message, without the trailers at its end
+    pub message: Cow<'a, str>,
+    /// The trailers at the end of the commit message, like `Signed-off-by: Name <email>`
+    pub trailers: Vec<Trailer<'a>>,
+    /// The patches of the change, which is empty if the commit doesn't change any file
+    pub patches: PatchSet<'a>,
+}
+
+/// A `Key: value` line at the end of a commit message, like `Signed-off-by` or `Reviewed-by`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Trailer<'a> {
+    /// The part before the `:`
+    pub key: Cow<'a, str>,
+    /// The part after the `:`
+    pub value: Cow<'a, str>,
+}
+
+/// Error that can occur while parsing an email with [`EmailPatch::parse`] or
+/// [`parse_mailbox`]
+#[derive(Debug, Clone)]
+pub enum MailboxError<'a> {
+    /// The email lacks a header that every patch email has
+    MissingHeader(&'static str),
+    /// The patches in the email could not be parsed
+    Parse(ParseError<'a>),
+}
+
+impl fmt::Display for MailboxError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailboxError::MissingHeader(name) => write!(f, "The email has no '{}' header", name),
+            MailboxError::Parse(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for MailboxError<'_> {}
+
+impl<'a> From<ParseError<'a>> for MailboxError<'a> {
+    fn from(error: ParseError<'a>) -> Self {
+        MailboxError::Parse(error)
+    }
+}
+
+impl<'a> EmailPatch<'a> {
+    /// Parse a single email, like one of the files `git format-patch` writes
+    ///
+    /// The headers are decoded if they are encoded as in RFC 2047, and `From`, `Date` and
+    /// `Subject` lines at the start of the body replace the headers like `git am` does it. The
+    /// message ends at the first `---` line, and the signature at the end of the email, after
+    /// a `-- ` line, is ignored.
+    pub fn parse(s: &'a str) -> Result<Self, MailboxError<'a>> {
+        let mut lines = Lines::new(s);
+
+        let mut commit = None;
+        if let Some(line) = lines.peek().filter(|line| line.starts_with("From ")) {
+            commit = line.split_whitespace().nth(1).map(Cow::Borrowed);
+            lines.next();
+        }
+
+        let mut author = None;
+        let mut date = None;
+        let mut subject = None;
+        let mut set_header = |name: &str, value: Cow<'a, str>| {
+            if name.eq_ignore_ascii_case("from") {
+                author = Some(value);
+            } else if name.eq_ignore_ascii_case("date") {
+                date = Some(value);
+            } else if name.eq_ignore_ascii_case("subject") {
+                subject = Some(value);
+            }
+        };
+        for (name, value) in headers(&mut lines).0 {
+            set_header(name, decode_header(value));
+        }
+
+        // Headers at the start of the body describe the commit better than the ones of the
+        // email, which may have been sent by someone else
+        while lines.peek() == Some("") {
+            lines.next();
+        }
+        let mut in_body = lines.clone();
+        let (in_body_headers, complete) = headers(&mut in_body);
+        let known = ["from", "date", "subject"];
+        let only_known = in_body_headers
+            .iter()
+            .all(|(name, _)| known.iter().any(|known| name.eq_ignore_ascii_case(known)));
+        if complete && !in_body_headers.is_empty() && only_known {
+            for (name, value) in in_body_headers {
+                set_header(name, decode_header(value));
+            }
+            lines = in_body;
+        }
+
+        // The message ends at the `---` line before the diffstat, or at the first patch
+        let start = lines.offset;
+        let mut end = s.len();
+        let mut diff_start = s.len();
+        while let Some(line) = lines.peek() {
+            if line == "---" {
+                end = lines.offset;
+                lines.next();
+                diff_start = lines.offset;
+                break;
+            }
+            if line.starts_with("diff --git ") || line.starts_with("--- ") {
+                end = lines.offset;
+                diff_start = lines.offset;
+                break;
+            }
+            lines.next();
+        }
+        let (message, trailers) = split_trailers(s[start..end].trim_end());
+
+        let mut diff = &s[diff_start..];
+        if let Some(signature) = diff.rfind("\n-- \n").or_else(|| diff.rfind("\n-- \r\n")) {
+            diff = &diff[..signature + 1];
+        }
+        let has_patches = Lines::new(diff)
+            .any(|line| line.starts_with("diff --git ") || line.starts_with("--- "));
+        let patches = if has_patches {
+            let mut patches = PatchSet::parse(diff)?;
+            // The diffstat before the first patch is written again from the patches
+            patches.patches[0].preamble = None;
+            patches
+        } else {
+            PatchSet::default()
+        };
+
+        Ok(EmailPatch {
+            commit,
+            author: author.ok_or(MailboxError::MissingHeader("From"))?,
+            date,
+            subject: strip_subject_prefix(subject.ok_or(MailboxError::MissingHeader("Subject"))?),
+            message: Cow::Borrowed(message),
+            trailers,
+            patches,
+        })
+    }
+
+    /// The date of the change, if it has one in the format of email headers
+    pub fn date_time(&self) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc2822(self.date.as_deref()?).ok()
+    }
+}
+
+/// Writes the email like `git format-patch` does, so that `git am` can apply it
+///
+/// The email starts with the `From <commit>` line of the mbox format, with zeros for a missing
+/// commit id, and the subject gets a `[PATCH]` prefix. The message and the trailers are
+/// followed by a `---` line, the [`DiffStat`] of the patches, the patches themselves and a
+/// `-- ` line with the version of this crate. Headers that aren't ASCII are encoded as in
+/// RFC 2047. Like other `Display` implementations, the text doesn't end with a newline
+/// character.
+///
+/// # Example
+///
+/// ```
+/// use patch::{EmailPatch, PatchSet, diff};
+///
+/// let mut patch = diff("Hello\n", "Hello, world\n", 3);
+/// patch.old.path = "a/hello.txt".into();
+/// patch.new.path = "b/hello.txt".into();
+///
+/// let email = EmailPatch {
+///     commit: None,
+///     author: "A U Thor <author@example.com>".into(),
+///     date: Some("Thu, 7 Apr 2005 15:13:13 -0700".into()),
+///     subject: "Greet the world".into(),
+///     message: "Saying hello to everyone is friendlier.".into(),
+///     trailers: Vec::new(),
+///     patches: PatchSet::from(vec![patch]),
+/// };
+/// let text = email.to_string();
+/// assert!(text.starts_with("From 0000000000000000000000000000000000000000 Mon Sep 17 00:00:00 2001\n"));
+/// assert!(text.contains("\nSubject: [PATCH] Greet the world\n"));
+/// assert!(text.contains("\n---\n hello.txt | 2 +-\n 1 file changed, 1 insertion(+), 1 deletion(-)\n\n"));
+///
+/// // Reading the email back gives the same commit
+/// let parsed = EmailPatch::parse(&text).unwrap();
+/// assert_eq!(parsed.patches, email.patches);
+/// assert_eq!(parsed.message, email.message);
+/// ```
+impl fmt::Display for EmailPatch<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let commit = self.commit.as_deref().unwrap_or(ZERO_COMMIT);
+        writeln!(f, "From {} Mon Sep 17 00:00:00 2001", commit)?;
+        writeln!(f, "From: {}", encode_address(&self.author))?;
+        if let Some(date) = &self.date {
+            writeln!(f, "Date: {}", date)?;
+        }
+        writeln!(f, "Subject: [PATCH] {}", encode_header(&self.subject))?;
+        let patches = self.patches.to_string();
+        let unicode = !self.message.is_ascii()
+            || self
+                .trailers
+                .iter()
+                .any(|trailer| !trailer.value.is_ascii())
+            || !patches.is_ascii();
+        if unicode {
+            writeln!(f, "MIME-Version: 1.0")?;
+            writeln!(f, "Content-Type: text/plain; charset=UTF-8")?;
+            writeln!(f, "Content-Transfer-Encoding: 8bit")?;
+        }
+        writeln!(f)?;
+
+        if !self.message.is_empty() {
+            write!(f, "{}\n\n", self.message)?;
+        }
+        for trailer in &self.trailers {
+            writeln!(f, "{}: {}", trailer.key, trailer.value)?;
+        }
+        writeln!(f, "---")?;
+        write!(
+            f,
+            "{}\n\n",
+            DiffStat::from_patch_set(&self.patches).render(72)
+        )?;
+        if !patches.is_empty() {
+            writeln!(f, "{}", patches)?;
+        }
+        write!(f, "-- \npatch {}", env!("CARGO_PKG_VERSION"))
+    }
+}
+
+/// Parse every email of a mailbox in the mbox format, like `git format-patch --stdout` writes
+///
+/// Every email starts with a `From ` line at the start of the mailbox or after an empty line.
+/// See [`EmailPatch::parse`] for the details of each email.
+pub fn parse_mailbox(s: &str) -> Result<Vec<EmailPatch<'_>>, MailboxError<'_>> {
+    let mut starts = vec![0];
+    let mut lines = Lines::new(s);
+    let mut previous_empty = false;
+    while let Some(line) = lines.peek() {
+        if previous_empty && line.starts_with("From ") {
+            starts.push(lines.offset);
+        }
+        previous_empty = line.is_empty();
+        lines.next();
+    }
+    starts.push(s.len());
+    starts
+        .windows(2)
+        .filter(|window| !s[window[0]..window[1]].trim().is_empty())
+        .map(|window| EmailPatch::parse(&s[window[0]..window[1]]))
+        .collect()
+}
+
+/// The lines of a text without their line endings, remembering where the next line starts
+#[derive(Debug, Clone)]
+struct Lines<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> Lines<'a> {
+    fn new(text: &'a str) -> Self {
+        Lines { text, offset: 0 }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        let rest = &self.text[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        let line = rest.split('\n').next().unwrap_or(rest);
+        Some(line.strip_suffix('\r').unwrap_or(line))
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let line = self.peek()?;
+        let rest = &self.text[self.offset..];
+        self.offset += rest.find('\n').map_or(rest.len(), |end| end + 1);
+        Some(line)
+    }
+}
+
+// Read the header lines up to the next empty line, joining folded lines. Returns the headers
+// and whether they end with an empty line, rather than a line that isn't a header.
+fn headers<'a>(lines: &mut Lines<'a>) -> (Vec<(&'a str, Cow<'a, str>)>, bool) {
+    let mut headers: Vec<(&'a str, Cow<'a, str>)> = Vec::new();
+    while let Some(line) = lines.peek() {
+        if line.is_empty() {
+            lines.next();
+            return (headers, true);
+        }
+        if line.starts_with([' ', '\t']) {
+            match headers.last_mut() {
+                Some((_, value)) => value.to_mut().push_str(line),
+                None => return (headers, false),
+            }
+        } else {
+            match line.split_once(':') {
+                Some((name, value)) if !name.is_empty() && !name.contains(' ') => {
+                    headers.push((name, Cow::Borrowed(value.trim_start())));
+                }
+                _ => return (headers, false),
+            }
+        }
+        lines.next();
+    }
+    (headers, false)
+}
+
+// Remove the `[PATCH ...]` and `Re:` prefixes of a subject, like `git am` does
+fn strip_subject_prefix(subject: Cow<'_, str>) -> Cow<'_, str> {
+    let end = subject.trim_end().len();
+    let mut start = subject.len() - subject.trim_start().len();
+    loop {
+        let rest = &subject[start..end];
+        let after = if let Some((_, after)) =
+            rest.strip_prefix('[').and_then(|rest| rest.split_once(']'))
+        {
+            after
+        } else if rest
+            .get(..3)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("re:"))
+        {
+            &rest[3..]
+        } else {
+            break;
+        };
+        start = end - after.trim_start().len();
+    }
+    match subject {
+        Cow::Borrowed(subject) => Cow::Borrowed(&subject[start..end]),
+        Cow::Owned(subject) => Cow::Owned(subject[start..end].to_owned()),
+    }
+}
+
+// Split the trailers off the last paragraph of a commit message, if every line of it is one
+fn split_trailers(message: &str) -> (&str, Vec<Trailer<'_>>) {
+    let (body, last) = match message.rfind("\n\n") {
+        Some(i) => (message[..i].trim_end(), &message[i + 2..]),
+        None => ("", message),
+    };
+    let trailers: Option<Vec<Trailer>> = last
+        .lines()
+        .map(|line| {
+            let (key, value) = line.split_once(": ")?;
+            let is_token =
+                !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+            is_token.then(|| Trailer {
+                key: Cow::Borrowed(key),
+                value: Cow::Borrowed(value.trim()),
+            })
+        })
+        .collect();
+    match trailers {
+        Some(trailers) if !last.is_empty() => (body, trailers),
+        _ => (message, Vec::new()),
+    }
+}
+
+// Encode a header that isn't ASCII as an RFC 2047 encoded word
+fn encode_header(value: &str) -> Cow<'_, str> {
+    if value.is_ascii() {
+        return Cow::Borrowed(value);
+    }
+    let mut output = String::from("=?UTF-8?q?");
+    for byte in value.bytes() {
+        match byte {
+            b' ' => output.push('_'),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b',' | b'-' => {
+                output.push(char::from(byte))
+            }
+            _ => output.push_str(&format!("={:02X}", byte)),
+        }
+    }
+    output.push_str("?=");
+    Cow::Owned(output)
+}
+
+// Encode the name of an address like `Name <email>`, leaving the email as it is
+fn encode_address(address: &str) -> Cow<'_, str> {
+    match address.split_once(" <") {
+        Some((name, email)) if !name.is_ascii() => {
+            Cow::Owned(format!("{} <{}", encode_header(name), email))
+        }
+        _ => encode_header(address),
+    }
+}
+
+// Decode the RFC 2047 encoded words of a header, like `=?UTF-8?q?Ren=C3=A9?=`
+fn decode_header(value: Cow<'_, str>) -> Cow<'_, str> {
+    if !value.contains("=?") {
+        return value;
+    }
+    let mut output = String::with_capacity(value.len());
+    let mut rest = &*value;
+    // Whitespace between two encoded words is left out
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let (before, word) = rest.split_at(start);
+        match decode_word(word) {
+            Some((decoded, len)) => {
+                if !(after_word && before.trim().is_empty()) {
+                    output.push_str(before);
+                }
+                output.push_str(&decoded);
+                rest = &word[len..];
+                after_word = true;
+            }
+            None => {
+                output.push_str(before);
+                output.push_str("=?");
+                rest = &word[2..];
+                after_word = false;
+            }
+        }
+    }
+    output.push_str(rest);
+    Cow::Owned(output)
+}
+
+// Decode an encoded word at the start of the text, returning it with its encoded length
+fn decode_word(word: &str) -> Option<(String, usize)> {
+    let inner = word.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let text = &inner[..inner.find("?=")?];
+    let bytes = if encoding.eq_ignore_ascii_case("q") {
+        decode_q(text)?
+    } else if encoding.eq_ignore_ascii_case("b") {
+        decode_base64(text)?
+    } else {
+        return None;
+    };
+    let decoded =
+        if charset.eq_ignore_ascii_case("utf-8") || charset.eq_ignore_ascii_case("us-ascii") {
+            String::from_utf8_lossy(&bytes).into_owned()
+        } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+            bytes.iter().map(|&byte| char::from(byte)).collect()
+        } else {
+            return None;
+        };
+    let len = "=?".len() + charset.len() + 1 + encoding.len() + 1 + text.len() + "?=".len();
+    Some((decoded, len))
+}
+
+fn decode_q(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.bytes();
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'_' => bytes.push(b' '),
+            b'=' => {
+                let hex = [chars.next()?, chars.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    Some(bytes)
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let value = |byte: u8| match byte {
+        b'A'..=b'Z' => Some(byte - b'A'),
+        b'a'..=b'z' => Some(byte - b'a' + 26),
+        b'0'..=b'9' => Some(byte - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in text.bytes().filter(|&byte| byte != b'=') {
+        buffer = (buffer << 6) | u32::from(value(byte)?);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_mailbox() {
+        let mailbox = "\
+From 1111111111111111111111111111111111111111 Mon Sep 17 00:00:00 2001
+From: =?UTF-8?q?Ren=C3=A9=20Descartes?= <rene@example.com>
+Date: Sat, 1 Jun 2024 10:00:00 +0200
+Subject: [PATCH 1/2] Think
+ therefore be
+
+---
+ a.txt | 1 +
+ 1 file changed, 1 insertion(+)
+
+diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -1 +1,2 @@
+ I think
++therefore I am
+--\x20
+2.43.0
+
+From 2222222222222222222222222222222222222222 Mon Sep 17 00:00:00 2001
+From: Sender <sender@example.com>
+Subject: Re: [PATCH 2/2] =?utf-8?b?w4lkaXQ=?= the docs
+
+From: Author <author@example.com>
+Date: Sun, 2 Jun 2024 10:00:00 +0200
+
+First paragraph.
+
+Second paragraph: not a trailer.
+Reviewed-by: Someone <someone@example.com>
+Acked-by: Another <another@example.com>
+";
+        let patches = parse_mailbox(mailbox).unwrap();
+        assert_eq!(patches.len(), 2);
+
+        assert_eq!(
+            patches[0].commit.as_deref(),
+            Some("1111111111111111111111111111111111111111")
+        );
+        assert_eq!(patches[0].author, "René Descartes <rene@example.com>");
+        assert_eq!(patches[0].subject, "Think therefore be");
+        assert_eq!(patches[0].message, "");
+        assert_eq!(patches[0].patches.patches.len(), 1);
+        assert_eq!(
+            patches[0].patches.patches[0].hunks[0].lines.len(),
+            2,
+            "the signature isn't part of the hunk"
+        );
+        assert_eq!(
+            patches[0].date_time(),
+            DateTime::parse_from_rfc3339("2024-06-01T10:00:00+02:00").ok()
+        );
+
+        assert_eq!(patches[1].author, "Author <author@example.com>");
+        assert_eq!(patches[1].subject, "Édit the docs");
+        assert_eq!(
+            patches[1].message,
+            "First paragraph.\n\nSecond paragraph: not a trailer.\nReviewed-by: Someone <someone@example.com>\nAcked-by: Another <another@example.com>"
+        );
+        assert_eq!(patches[1].trailers, []);
+        assert_eq!(patches[1].patches, PatchSet::default());
+    }
+
+    #[test]
+    fn test_trailers() {
+        let (message, trailers) =
+            split_trailers("Fix it\n\nSigned-off-by: A <a@example.com>\nCc: B <b@example.com>");
+        assert_eq!(message, "Fix it");
+        assert_eq!(
+            trailers,
+            [
+                Trailer {
+                    key: "Signed-off-by".into(),
+                    value: "A <a@example.com>".into()
+                },
+                Trailer {
+                    key: "Cc".into(),
+                    value: "B <b@example.com>".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_email() {
+        let patches = PatchSet::parse(
+            "\
+diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -1,1 +1,1 @@
+-a
++à
+",
+        )
+        .unwrap();
+        let email = EmailPatch {
+            commit: Some("1111111111111111111111111111111111111111".into()),
+            author: "René Descartes <rene@example.com>".into(),
+            date: Some("Sat, 1 Jun 2024 10:00:00 +0200".into()),
+            subject: "Café au lait".into(),
+            message: "Accents are fine.".into(),
+            trailers: vec![Trailer {
+                key: "Signed-off-by".into(),
+                value: "René Descartes <rene@example.com>".into(),
+            }],
+            patches,
+        };
+        let text = email.to_string();
+        assert_eq!(
+            text.lines().take(9).collect::<Vec<_>>(),
+            [
+                "From 1111111111111111111111111111111111111111 Mon Sep 17 00:00:00 2001",
+                "From: =?UTF-8?q?Ren=C3=A9_Descartes?= <rene@example.com>",
+                "Date: Sat, 1 Jun 2024 10:00:00 +0200",
+                "Subject: [PATCH] =?UTF-8?q?Caf=C3=A9_au_lait?=",
+                "MIME-Version: 1.0",
+                "Content-Type: text/plain; charset=UTF-8",
+                "Content-Transfer-Encoding: 8bit",
+                "",
+                "Accents are fine.",
+            ]
+        );
+        let version = format!("\n+à\n-- \npatch {}", env!("CARGO_PKG_VERSION"));
+        assert!(text.ends_with(&version));
+        assert_eq!(EmailPatch::parse(&text).unwrap(), email);
+    }
+
+    #[test]
+    fn test_missing_subject() {
+        assert!(matches!(
+            EmailPatch::parse("From: A <a@example.com>\n\nbody\n"),
+            Err(MailboxError::MissingHeader("Subject"))
+        ));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::ast::{Hunk, Line, Patch, Range};
+
+/// Where a line of one side of a patch ends up on the other side, see
+/// [`Patch::map_old_to_new`] and [`Patch::map_new_to_old`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum LineMapping {
+    /// The line is at the given line number on the other side, counting from 1
+    Mapped(u64),
+    /// The line of the old file was removed by the patch, so it isn't in the new file
+    Deleted,
+    /// The line of the new file was added by the patch, so it isn't in the old file
+    Added,
+}
+
+impl<T: ?Sized + ToOwned> Patch<'_, T> {
+    /// Find where a line of the old file, counting from 1, is in the new file
+    ///
+    /// Lines outside of the hunks move by the number of lines the hunks before them add or
+    /// remove. This keeps annotations like breakpoints, comments or blame information on the
+    /// right lines after the patch is applied.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), patch::ParseError<'static>> {
+    /// # use patch::{LineMapping, Patch};
+    /// let sample = "\
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -2,3 +2,4 @@
+    ///  b
+    /// -c
+    /// +C
+    /// +D
+    ///  e
+    /// ";
+    ///
+    /// let patch = Patch::from_single(sample)?;
+    /// assert_eq!(patch.map_old_to_new(1), LineMapping::Mapped(1));
+    /// assert_eq!(patch.map_old_to_new(3), LineMapping::Deleted);
+    /// assert_eq!(patch.map_old_to_new(4), LineMapping::Mapped(5));
+    /// assert_eq!(patch.map_old_to_new(10), LineMapping::Mapped(11));
+    /// assert_eq!(patch.map_new_to_old(4), LineMapping::Added);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn map_old_to_new(&self, line: u64) -> LineMapping {
+        map_line(&self.hunks, line, Side::Old)
+    }
+
+    /// Find where a line of the new file, counting from 1, is in the old file, the inverse of
+    /// [`Patch::map_old_to_new`]
+    pub fn map_new_to_old(&self, line: u64) -> LineMapping {
+        map_line(&self.hunks, line, Side::New)
+    }
+}
+
+/// The side of a patch a line number is on
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Side {
+    Old,
+    New,
+}
+
+fn map_line<T: ?Sized + ToOwned>(hunks: &[Hunk<'_, T>], line: u64, from: Side) -> LineMapping {
+    let ranges = |hunk: &Hunk<'_, T>| match from {
+        Side::Old => (first_line(&hunk.old_range), first_line(&hunk.new_range)),
+        Side::New => (first_line(&hunk.new_range), first_line(&hunk.old_range)),
+    };
+    // How far lines after the last hunk seen so far move
+    let mut shift: i64 = 0;
+    for hunk in hunks {
+        let (mut position, mut other) = ranges(hunk);
+        if line < position {
+            // The line is between two hunks, which both move it the same way
+            return mapped(line, other as i64 - position as i64);
+        }
+        for hunk_line in &hunk.lines {
+            match (hunk_line, from) {
+                (Line::Context(_), _) => {
+                    if position == line {
+                        return LineMapping::Mapped(other);
+                    }
+                    position += 1;
+                    other += 1;
+                }
+                (Line::Remove(_), Side::Old) | (Line::Add(_), Side::New) => {
+                    if position == line {
+                        return match from {
+                            Side::Old => LineMapping::Deleted,
+                            Side::New => LineMapping::Added,
+                        };
+                    }
+                    position += 1;
+                }
+                (Line::Add(_), Side::Old) | (Line::Remove(_), Side::New) => other += 1,
+            }
+        }
+        shift = other as i64 - position as i64;
+    }
+    mapped(line, shift)
+}
+
+fn mapped(line: u64, shift: i64) -> LineMapping {
+    LineMapping::Mapped((line as i64 + shift).max(1) as u64)
+}
+
+// The first line of a range, or the line after it if it is empty
+fn first_line(range: &Range) -> u64 {
+    if range.count == 0 {
+        range.start + 1
+    } else {
+        range.start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_map_lines() {
+        let patch = Patch::from_single(
+            "\
+--- a.txt
++++ a.txt
+@@ -0,0 +1,2 @@
++x
++y
+@@ -3,2 +4,0 @@
+-c
+-d
+@@ -6,0 +7 @@
++z
+",
+        )
+        .unwrap();
+
+        let old_to_new: Vec<_> = (1..=7).map(|line| patch.map_old_to_new(line)).collect();
+        assert_eq!(
+            old_to_new,
+            [
+                LineMapping::Mapped(3),
+                LineMapping::Mapped(4),
+                LineMapping::Deleted,
+                LineMapping::Deleted,
+                LineMapping::Mapped(5),
+                LineMapping::Mapped(6),
+                LineMapping::Mapped(8),
+            ]
+        );
+
+        let new_to_old: Vec<_> = (1..=8).map(|line| patch.map_new_to_old(line)).collect();
+        assert_eq!(
+            new_to_old,
+            [
+                LineMapping::Added,
+                LineMapping::Added,
+                LineMapping::Mapped(1),
+                LineMapping::Mapped(2),
+                LineMapping::Mapped(5),
+                LineMapping::Mapped(6),
+                LineMapping::Added,
+                LineMapping::Mapped(7),
+            ]
+        );
+
+        // Mapping a line there and back leads to the same line
+        for line in [1, 2, 5, 6, 7, 20] {
+            let LineMapping::Mapped(new) = patch.map_old_to_new(line) else {
+                panic!("line {} should be mapped", line);
+            };
+            assert_eq!(patch.map_new_to_old(new), LineMapping::Mapped(line));
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::ops::Range as IndexRange;
+
+use crate::generator::{DiffAlgorithm, Edit, edit_script};
+
+/// How conflicts are presented in the merged text
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ConflictStyle {
+    /// Show both sides of the conflict, like git's default `merge` conflict style
+    #[default]
+    Merge,
+    /// Also show what the conflicting region looked like in the base, like git's `diff3`
+    /// conflict style
+    Diff3,
+}
+
+/// Options for [`merge_with_options`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MergeOptions {
+    /// The name written after the `<<<<<<<` marker
+    pub ours_label: String,
+    /// The name written after the `|||||||` marker
+    pub base_label: String,
+    /// The name written after the `>>>>>>>` marker
+    pub theirs_label: String,
+    /// How conflicts are presented in the merged text
+    pub style: ConflictStyle,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            ours_label: "ours".into(),
+            base_label: "base".into(),
+            theirs_label: "theirs".into(),
+            style: ConflictStyle::default(),
+        }
+    }
+}
+
+/// A region that was changed differently by both sides of a merge
+///
+/// All ranges are 0-based line indices.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Conflict {
+    /// The lines of the merged text occupied by this conflict, including the conflict markers
+    pub merged: IndexRange<usize>,
+    /// The conflicting lines of the base text
+    pub base: IndexRange<usize>,
+    /// The conflicting lines of our text
+    pub ours: IndexRange<usize>,
+    /// The conflicting lines of their text
+    pub theirs: IndexRange<usize>,
+}
+
+/// The outcome of a three-way merge
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MergeResult {
+    /// The merged text, with conflict markers around every conflicting region
+    pub text: String,
+    /// Every conflict, in the order they appear in the text
+    pub conflicts: Vec<Conflict>,
+}
+
+impl MergeResult {
+    /// True if both sides could be merged without any conflicts
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merge the changes made by `ours` and `theirs` to a common `base` text, like `diff3 -m`
+///
+/// The differences of both sides to the base are computed line by line. Regions changed by only
+/// one side take that side's version, and regions changed the same way by both sides are taken
+/// once. Regions changed differently by both sides are conflicts, which are surrounded by
+/// conflict markers in the merged text and listed in [`MergeResult::conflicts`]. The newline at
+/// the end of the text is merged the same way, so a side that adds or removes it wins.
+///
+/// # Example
+///
+/// ```
+/// use patch::merge;
+///
+/// let base = "a\nb\nc\n";
+/// let ours = "A\nb\nc\n";
+/// let theirs = "a\nb\nC\n";
+///
+/// let result = merge(base, ours, theirs);
+/// assert!(result.is_clean());
+/// assert_eq!(result.text, "A\nb\nC\n");
+///
+/// let result = merge(base, "a\nours\nc\n", "a\ntheirs\nc\n");
+/// assert_eq!(result.conflicts.len(), 1);
+/// assert_eq!(
+///     result.text,
+///     "a\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\nc\n",
+/// );
+/// ```
+pub fn merge(base: &str, ours: &str, theirs: &str) -> MergeResult {
+    merge_with_options(base, ours, theirs, &MergeOptions::default())
+}
+
+/// Merge the changes made by `ours` and `theirs` to a common `base` text, with the given labels
+/// and conflict style
+///
+/// See [`merge`].
+pub fn merge_with_options(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    options: &MergeOptions,
+) -> MergeResult {
+    let base_lines: Vec<&str> = base.lines().collect();
+    let our_lines: Vec<&str> = ours.lines().collect();
+    let their_lines: Vec<&str> = theirs.lines().collect();
+
+    let mut text = String::with_capacity(ours.len().max(theirs.len()));
+    let mut line_count = 0;
+    let mut push_line = |text: &mut String, line: &str| {
+        text.push_str(line);
+        text.push('\n');
+        line_count += 1;
+        line_count
+    };
+
+    let mut conflicts = Vec::new();
+    let mut ends_in_conflict = false;
+    for region in merge_regions(&base_lines, &our_lines, &their_lines) {
+        ends_in_conflict = matches!(region, MergeRegion::Conflict { .. });
+        match region {
+            MergeRegion::Resolved { lines } => {
+                for line in lines {
+                    push_line(&mut text, line);
+                }
+            }
+            MergeRegion::Conflict {
+                base: base_range,
+                ours: our_range,
+                theirs: their_range,
+            } => {
+                let start = push_line(&mut text, &format!("<<<<<<< {}", options.ours_label)) - 1;
+                for line in &our_lines[our_range.clone()] {
+                    push_line(&mut text, line);
+                }
+                if options.style == ConflictStyle::Diff3 {
+                    push_line(&mut text, &format!("||||||| {}", options.base_label));
+                    for line in &base_lines[base_range.clone()] {
+                        push_line(&mut text, line);
+                    }
+                }
+                push_line(&mut text, "=======");
+                for line in &their_lines[their_range.clone()] {
+                    push_line(&mut text, line);
+                }
+                let end = push_line(&mut text, &format!(">>>>>>> {}", options.theirs_label));
+
+                conflicts.push(Conflict {
+                    merged: start..end,
+                    base: base_range,
+                    ours: our_range,
+                    theirs: their_range,
+                });
+            }
+        }
+    }
+
+    // The final newline is merged like a line of its own: a side that adds or removes it wins.
+    // Conflict markers always end with one.
+    let end_newline = |text: &str| text.is_empty() || text.ends_with('\n');
+    let end_newline = match end_newline(ours) == end_newline(base) {
+        true => end_newline(theirs),
+        false => end_newline(ours),
+    };
+    if !end_newline && !ends_in_conflict && text.ends_with('\n') {
+        text.pop();
+    }
+
+    MergeResult { text, conflicts }
+}
+
+/// A region of the merged text
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum MergeRegion<'a, T: ?Sized = str> {
+    /// Lines that could be merged without a conflict
+    Resolved { lines: Vec<&'a T> },
+    /// Lines that both sides changed differently, as line ranges of each of the inputs
+    Conflict {
+        base: IndexRange<usize>,
+        ours: IndexRange<usize>,
+        theirs: IndexRange<usize>,
+    },
+}
+
+// Split the three inputs into alternating stable regions, where all of them agree, and unstable
+// regions, where at least one of the sides differs from the base. This is the diff3 algorithm as
+// described in "A Formal Investigation of Diff3" by Khanna, Kunal and Pierce.
+pub(crate) fn merge_regions<'a, T: ?Sized + Hash + Eq>(
+    base: &[&'a T],
+    ours: &[&'a T],
+    theirs: &[&'a T],
+) -> Vec<MergeRegion<'a, T>> {
+    let our_matches = matches(base, ours);
+    let their_matches = matches(base, theirs);
+
+    let mut regions = Vec::new();
+    let mut stable = Vec::new();
+    let (mut i, mut j, mut k) = (0, 0, 0);
+
+    loop {
+        if i < base.len() && our_matches[i] == Some(j) && their_matches[i] == Some(k) {
+            stable.push(base[i]);
+            i += 1;
+            j += 1;
+            k += 1;
+            continue;
+        }
+
+        // Find the next base line that both sides kept
+        let next = (i..base.len()).find_map(|n| match (our_matches[n], their_matches[n]) {
+            (Some(o), Some(t)) => Some((n, o, t)),
+            _ => None,
+        });
+        let (next_i, next_j, next_k) = next.unwrap_or((base.len(), ours.len(), theirs.len()));
+
+        if next_i == i && next_j == j && next_k == k {
+            // Reached the end of all three inputs
+            break;
+        }
+
+        let base_chunk = &base[i..next_i];
+        let our_chunk = &ours[j..next_j];
+        let their_chunk = &theirs[k..next_k];
+
+        let resolved = if our_chunk == base_chunk || our_chunk == their_chunk {
+            Some(their_chunk)
+        } else if their_chunk == base_chunk {
+            Some(our_chunk)
+        } else {
+            None
+        };
+        match resolved {
+            Some(lines) => stable.extend_from_slice(lines),
+            None => {
+                if !stable.is_empty() {
+                    regions.push(MergeRegion::Resolved {
+                        lines: core::mem::take(&mut stable),
+                    });
+                }
+                regions.push(MergeRegion::Conflict {
+                    base: i..next_i,
+                    ours: j..next_j,
+                    theirs: k..next_k,
+                });
+            }
+        }
+
+        (i, j, k) = (next_i, next_j, next_k);
+    }
+
+    if !stable.is_empty() {
+        regions.push(MergeRegion::Resolved { lines: stable });
+    }
+    regions
+}
+
+// For every base line, the index of the line it is matched with in `other`, if it was kept
+fn matches<T: ?Sized + Hash + Eq>(base: &[&T], other: &[&T]) -> Vec<Option<usize>> {
+    let mut matched = vec![None; base.len()];
+    for edit in edit_script(base, other, DiffAlgorithm::Myers) {
+        if let Edit::Equal(b, o) = edit {
+            matched[b] = Some(o);
+        }
+    }
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_merge_clean() {
+        let base = "1\n2\n3\n4\n5\n";
+        let ours = "0\n1\n2\n3\n4\n5\n";
+        let theirs = "1\n2\n3\n4\nfive\n";
+        let result = merge(base, ours, theirs);
+        assert!(result.is_clean());
+        assert_eq!(result.text, "0\n1\n2\n3\n4\nfive\n");
+    }
+
+    #[test]
+    fn test_merge_same_change_on_both_sides() {
+        let result = merge("a\nb\nc\n", "a\nB\nc\n", "a\nB\nc\n");
+        assert!(result.is_clean());
+        assert_eq!(result.text, "a\nB\nc\n");
+    }
+
+    #[test]
+    fn test_merge_conflict_regions() {
+        let base = "a\nb\nc\nd\n";
+        let ours = "a\nx\nc\nd\n";
+        let theirs = "a\ny\ny\nc\nd\n";
+        let options = MergeOptions {
+            style: ConflictStyle::Diff3,
+            ..MergeOptions::default()
+        };
+        let result = merge_with_options(base, ours, theirs, &options);
+        assert_eq!(
+            result.text,
+            "a\n<<<<<<< ours\nx\n||||||| base\nb\n=======\ny\ny\n>>>>>>> theirs\nc\nd\n"
+        );
+        assert_eq!(
+            result.conflicts,
+            [Conflict {
+                merged: 1..9,
+                base: 1..2,
+                ours: 1..2,
+                theirs: 1..3,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_merge_deletion_conflict() {
+        let result = merge("a\nb\nc\n", "a\nc\n", "a\nB\nc\n");
+        assert_eq!(
+            result.text,
+            "a\n<<<<<<< ours\n=======\nB\n>>>>>>> theirs\nc\n"
+        );
+        assert_eq!(result.conflicts[0].ours, 1..1);
+    }
+
+    #[test]
+    fn test_merge_newline_at_end() {
+        assert_eq!(merge("a", "b", "a").text, "b");
+        assert_eq!(merge("a\n", "b\n", "a\n").text, "b\n");
+
+        // A side that adds or removes the final newline wins, even if the other side doesn't
+        assert_eq!(merge("a\nb\n", "a\nb\n", "a\nb").text, "a\nb");
+        assert_eq!(merge("a\nb\n", "a\nb", "A\nb\n").text, "A\nb");
+        assert_eq!(merge("a\nb", "a\nb\n", "A\nb").text, "A\nb\n");
+        assert_eq!(merge("a\nb", "A\nb", "a\nb\n").text, "A\nb\n");
+
+        // The conflict markers at the end of the text keep their newline
+        let result = merge("a\nb\n", "a\nB", "a\nC\n");
+        assert_eq!(
+            result.text,
+            "a\n<<<<<<< ours\nB\n=======\nC\n>>>>>>> theirs\n"
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use memchr::{memchr, memchr_iter};
+use memmap2::Mmap;
+
+use crate::applier::ApplyError;
+use crate::ast::Patch;
+use crate::source::{ExactTarget, NextLine, apply_exact};
+
+/// Apply a patch to a file without reading it into memory, writing the output to `writer`
+///
+/// The file is memory-mapped and its line boundaries are found with `memchr` as the hunks need
+/// them, so even files of several gigabytes are applied to in a single pass. Lines outside of
+/// the hunks are copied to the writer as they are, without being checked to be UTF-8. Otherwise
+/// this works like [`apply_to_writer`](crate::apply_to_writer): the hunks have to match at the
+/// line numbers of the patch, and the output up to a failing hunk may already have been written.
+///
+/// The file must not be changed while the patch is applied, which would change the mapped
+/// content under the applier.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use patch::{Patch, apply_mmap};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("log.txt");
+/// std::fs::write(&path, "line 1\nline 2\nline 3\n")?;
+///
+/// let patch = Patch::from_single(
+///     "\
+/// --- log.txt
+/// +++ log.txt
+/// @@ -2 +2 @@
+/// -line 2
+/// +new line 2
+/// ",
+/// )?;
+///
+/// let mut output = Vec::new();
+/// apply_mmap(&patch, &path, &mut output)?;
+/// assert_eq!(output, b"line 1\nnew line 2\nline 3\n");
+/// # Ok(())
+/// # }
+/// ```
+pub fn apply_mmap(
+    patch: &Patch,
+    path: impl AsRef<Path>,
+    writer: &mut impl Write,
+) -> Result<(), ApplyError> {
+    let file = File::open(path).map_err(ApplyError::Io)?;
+    // Empty files can't be mapped on every platform
+    if file.metadata().map_err(ApplyError::Io)?.len() == 0 {
+        return apply_mapped(patch, &[], writer);
+    }
+    // SAFETY: the mapping is only read, and the caller is told not to change the file meanwhile
+    let map = unsafe { Mmap::map(&file) }.map_err(ApplyError::Io)?;
+    apply_mapped(patch, &map, writer)
+}
+
+// Apply a patch to mapped content, copying the lines between the hunks in one piece
+fn apply_mapped(patch: &Patch, content: &[u8], writer: &mut impl Write) -> Result<(), ApplyError> {
+    let mut target = MappedTarget {
+        lines: Lines {
+            content,
+            offset: 0,
+            index: 0,
+        },
+        output: Output {
+            writer,
+            pending: None,
+            previous: match memchr(b'\n', content) {
+                Some(end) => split_ending(&content[..=end]).1,
+                None => b"\n",
+            },
+        },
+    };
+    let reached_end = apply_exact(patch, &mut target)?;
+    let MappedTarget { lines, mut output } = target;
+    output.lines(&content[lines.offset..])?;
+
+    // The patch decides whether the output ends in a newline character if its last hunk reached
+    // the end of the content, otherwise the content keeps its own ending
+    let end_newline = if reached_end {
+        patch.new_end_newline
+    } else {
+        content.ends_with(b"\n")
+    };
+    if end_newline {
+        output.flush()?;
+    }
+    Ok(())
+}
+
+// Applies a patch to mapped content, writing the output to a writer
+struct MappedTarget<'c, 'w, W> {
+    lines: Lines<'c>,
+    output: Output<'w, 'c, W>,
+}
+
+impl<'p, W: Write> ExactTarget<'p> for MappedTarget<'_, '_, W> {
+    fn position(&self) -> usize {
+        self.lines.index
+    }
+
+    fn keep_to(&mut self, end: usize) -> Result<(), ApplyError> {
+        let skipped = self.lines.skip_to(end);
+        Ok(self.output.lines(skipped)?)
+    }
+
+    fn next_line(&mut self, expected: &str, keep: bool) -> Result<NextLine, ApplyError> {
+        let Some((actual, ending)) = self.lines.next() else {
+            return Ok(NextLine::End);
+        };
+        if actual != expected.as_bytes() {
+            let actual = String::from_utf8_lossy(actual).into_owned();
+            return Ok(NextLine::Mismatch(actual));
+        }
+        if keep {
+            self.output.line(actual, ending)?;
+        }
+        Ok(NextLine::Matched)
+    }
+
+    fn add(&mut self, text: &'p str) -> Result<(), ApplyError> {
+        Ok(self.output.line(text.as_bytes(), b"")?)
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.lines.offset == self.lines.content.len()
+    }
+}
+
+/// Reads the lines of the content in order
+struct Lines<'c> {
+    content: &'c [u8],
+    /// The byte offset of the next line
+    offset: usize,
+    /// The index of the next line, which is the number of lines read so far
+    index: usize,
+}
+
+impl<'c> Lines<'c> {
+    // The next line and its line ending, which is empty for a last line without a newline
+    fn next(&mut self) -> Option<(&'c [u8], &'c [u8])> {
+        let rest = &self.content[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        let len = memchr(b'\n', rest).map_or(rest.len(), |end| end + 1);
+        self.offset += len;
+        self.index += 1;
+        Some(split_ending(&rest[..len]))
+    }
+
+    // Move to the line at the given index, or to the end of the content if it has fewer lines,
+    // returning the bytes of the lines skipped over
+    fn skip_to(&mut self, index: usize) -> &'c [u8] {
+        let start = self.offset;
+        let Some(count) = index.checked_sub(self.index).filter(|&count| count > 0) else {
+            return &[];
+        };
+        let rest = &self.content[start..];
+        match memchr_iter(b'\n', rest).nth(count - 1) {
+            Some(end) => {
+                self.offset += end + 1;
+                self.index = index;
+            }
+            None => {
+                let newlines = memchr_iter(b'\n', rest).count();
+                self.index += newlines + usize::from(!rest.is_empty() && !rest.ends_with(b"\n"));
+                self.offset = self.content.len();
+            }
+        }
+        &self.content[start..self.offset]
+    }
+}
+
+/// The output, whose last line only gets its line ending once it is known to be needed
+struct Output<'w, 'x, W> {
+    writer: &'w mut W,
+    /// The line ending owed to the last line written, which is empty if it takes `previous`
+    pending: Option<&'x [u8]>,
+    /// The line ending given to lines that don't have their own
+    previous: &'x [u8],
+}
+
+impl<'x, W: Write> Output<'_, 'x, W> {
+    fn line(&mut self, text: &[u8], ending: &'x [u8]) -> io::Result<()> {
+        self.flush()?;
+        self.writer.write_all(text)?;
+        self.pending = Some(ending);
+        Ok(())
+    }
+
+    // Several lines of the content at once, keeping the line ending of the last one pending
+    fn lines(&mut self, lines: &'x [u8]) -> io::Result<()> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let (text, ending) = split_ending(lines);
+        self.line(text, ending)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(ending) = self.pending.take() {
+            if !ending.is_empty() {
+                self.previous = ending;
+            }
+            self.writer.write_all(self.previous)?;
+        }
+        Ok(())
+    }
+}
+
+// Split the line ending off the end of some text
+fn split_ending(text: &[u8]) -> (&[u8], &[u8]) {
+    let len = if text.ends_with(b"\r\n") {
+        2
+    } else if text.ends_with(b"\n") {
+        1
+    } else {
+        0
+    };
+    text.split_at(text.len() - len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::applier::apply;
+
+    // Applying to mapped content gives the same text as applying to a string
+    fn check_mapped(patch: &str, content: &str) -> String {
+        let patch = Patch::from_single(patch).unwrap();
+        let mut output = Vec::new();
+        apply_mapped(&patch, content.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, apply(&patch, content).unwrap());
+        output
+    }
+
+    #[test]
+    fn test_apply_mapped() {
+        let patch = "\
+--- a.txt
++++ a.txt
+@@ -1,3 +1,2 @@
+ a
+-b
+-c
+@@ -4,0 +4,1 @@
++x
+@@ -6 +6 @@
+-f
++F
+";
+        assert_eq!(check_mapped(patch, "a\nb\nc\nd\ne\nf\n"), "a\nd\nx\ne\nF\n");
+        assert_eq!(
+            check_mapped(patch, "a\r\nb\r\nc\r\nd\r\ne\r\nf\r\n"),
+            "a\r\nd\r\nx\r\ne\r\nF\r\n"
+        );
+
+        let add = "--- a.txt\n+++ a.txt\n@@ -1 +1,2 @@\n a\n+b\n";
+        assert_eq!(check_mapped(add, "a"), "a\nb\n");
+        let newline = "--- a.txt\n+++ a.txt\n@@ -1 +1 @@\n-a\n+a\n\\ No newline at end of file\n";
+        assert_eq!(check_mapped(newline, "a\n"), "a");
+        let create = "--- /dev/null\n+++ a.txt\n@@ -0,0 +1,2 @@\n+a\n+b\n";
+        assert_eq!(check_mapped(create, ""), "a\nb\n");
+    }
+
+    #[test]
+    fn test_apply_mmap_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        // Lines outside of the hunks don't have to be UTF-8
+        std::fs::write(&path, b"\xff\xfe\nold\n\x80\n").unwrap();
+
+        let patch = Patch::from_single("--- a\n+++ a\n@@ -2 +2 @@\n-old\n+new\n").unwrap();
+        let mut output = Vec::new();
+        apply_mmap(&patch, &path, &mut output).unwrap();
+        assert_eq!(output, b"\xff\xfe\nnew\n\x80\n");
+
+        let patch = Patch::from_single("--- a\n+++ a\n@@ -5 +5 @@\n-old\n+new\n").unwrap();
+        assert!(matches!(
+            apply_mmap(&patch, &path, &mut Vec::new()),
+            Err(ApplyError::LineOutOfBounds {
+                line: 4,
+                total_lines: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_apply_mapped_overlapping_hunks() {
+        let patch = Patch::from_single(
+            "\
+--- a.txt
++++ a.txt
+@@ -1,2 +1,2 @@
+ a
+-b
++B
+@@ -1,2 +1,2 @@
+ a
+-b
++B
+",
+        )
+        .unwrap();
+        assert!(matches!(
+            apply_mapped(&patch, b"a\nb\na\nb\nc\n", &mut Vec::new()),
+            Err(ApplyError::OverlappingHunks { hunk: 1, line: 1 })
+        ));
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use crate::ast::{Line, Patch};
+
+// The number of letters and digits a block needs to count as moved, like git requires
+const MIN_ALNUM: usize = 20;
+
+/// A block of lines a patch removes in one place and adds unchanged in another, see
+/// [`Patch::moved_blocks`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MovedBlock {
+    /// The index in [`Patch::hunks`] of the hunk the block is removed from
+    pub old_hunk: usize,
+    /// The indices of the removed lines in the [`Hunk::lines`](crate::Hunk::lines) of that hunk
+    pub old_lines: Range<usize>,
+    /// The index in [`Patch::hunks`] of the hunk the block is added to
+    pub new_hunk: usize,
+    /// The indices of the added lines in the [`Hunk::lines`](crate::Hunk::lines) of that hunk
+    pub new_lines: Range<usize>,
+}
+
+impl Patch<'_> {
+    /// Find the blocks of lines the patch removes in one place and adds unchanged in another,
+    /// like `git diff --color-moved` does, so that they can be shown differently from the
+    /// lines that really changed
+    ///
+    /// Every run of added lines is matched with the longest run of removed lines it starts the
+    /// same as, taking every removed line at most once. Like git, blocks with fewer than 20
+    /// letters and digits are not considered moved, since short lines like `}` are often
+    /// removed and added in unrelated places. This works the same for generated and parsed
+    /// patches, and nothing is stored on the patch, so the blocks always match its hunks as
+    /// they are.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::{MovedBlock, Patch};
+    ///
+    /// let patch = Patch::from_single(
+    ///     "\
+    /// --- a.rs
+    /// +++ a.rs
+    /// @@ -1,4 +1,4 @@
+    /// -use std::collections::HashMap;
+    ///  use std::fs;
+    ///  use std::io;
+    /// -}
+    /// +use std::collections::HashMap;
+    /// +{
+    /// ",
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(
+    ///     patch.moved_blocks(),
+    ///     [MovedBlock {
+    ///         old_hunk: 0,
+    ///         old_lines: 0..1,
+    ///         new_hunk: 0,
+    ///         new_lines: 4..5,
+    ///     }]
+    /// );
+    /// ```
+    pub fn moved_blocks(&self) -> Vec<MovedBlock> {
+        // Where every removed line is, by its text
+        let mut removed: HashMap<&str, Vec<(usize, usize)>> = HashMap::new();
+        for (h, hunk) in self.hunks.iter().enumerate() {
+            for (i, line) in hunk.lines.iter().enumerate() {
+                if let Line::Remove(text) = line {
+                    removed.entry(text).or_default().push((h, i));
+                }
+            }
+        }
+
+        let mut used = HashSet::new();
+        let mut blocks = Vec::new();
+        for (h, hunk) in self.hunks.iter().enumerate() {
+            let mut i = 0;
+            while i < hunk.lines.len() {
+                let Line::Add(added) = &hunk.lines[i] else {
+                    i += 1;
+                    continue;
+                };
+                // The longest block of removed lines this block of added lines starts with,
+                // preferring the first one on ties
+                let mut best: Option<(usize, usize, usize)> = None;
+                for &(old_hunk, old_start) in removed.get(added.as_ref()).into_iter().flatten() {
+                    let len = self.block_len((old_hunk, old_start), (h, i), &used);
+                    if len > 0 && best.is_none_or(|(_, _, best_len)| len > best_len) {
+                        best = Some((old_hunk, old_start, len));
+                    }
+                }
+                let Some((old_hunk, old_start, len)) = best else {
+                    i += 1;
+                    continue;
+                };
+                let alnum: usize = hunk.lines[i..i + len]
+                    .iter()
+                    .map(|line| text(line).chars().filter(|c| c.is_alphanumeric()).count())
+                    .sum();
+                if alnum < MIN_ALNUM {
+                    i += 1;
+                    continue;
+                }
+                used.extend((old_start..old_start + len).map(|line| (old_hunk, line)));
+                blocks.push(MovedBlock {
+                    old_hunk,
+                    old_lines: old_start..old_start + len,
+                    new_hunk: h,
+                    new_lines: i..i + len,
+                });
+                i += len;
+            }
+        }
+        blocks
+    }
+
+    // The number of lines that are removed from the first position and added at the second one
+    // in the same order, without the removed lines already moved elsewhere
+    fn block_len(
+        &self,
+        (old_hunk, old_start): (usize, usize),
+        (new_hunk, new_start): (usize, usize),
+        used: &HashSet<(usize, usize)>,
+    ) -> usize {
+        let old_lines = &self.hunks[old_hunk].lines[old_start..];
+        let new_lines = &self.hunks[new_hunk].lines[new_start..];
+        old_lines
+            .iter()
+            .zip(new_lines)
+            .enumerate()
+            .take_while(|(k, pair)| match pair {
+                (Line::Remove(old), Line::Add(new)) => {
+                    old == new && !used.contains(&(old_hunk, old_start + k))
+                }
+                _ => false,
+            })
+            .count()
+    }
+}
+
+// The text of a line, whatever its kind
+fn text<'l>(line: &'l Line) -> &'l str {
+    match line {
+        Line::Add(text) | Line::Remove(text) | Line::Context(text) => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::generator::diff;
+
+    #[test]
+    fn test_moved_blocks() {
+        let old = "\
+fn first() {
+    first_step();
+    second_step();
+}
+
+fn second() {
+    other_work_done();
+}
+";
+        let new = "\
+fn second() {
+    other_work_done();
+}
+
+fn first() {
+    first_step();
+    second_step();
+}
+";
+        let patch = diff(old, new, 3);
+        let blocks = patch.moved_blocks();
+        assert_eq!(blocks.len(), 1);
+        let block = &blocks[0];
+        let lines = |hunk: usize, range: &Range<usize>| -> Vec<&str> {
+            patch.hunks[hunk].lines[range.clone()]
+                .iter()
+                .map(text)
+                .collect()
+        };
+        assert_eq!(
+            lines(block.old_hunk, &block.old_lines),
+            lines(block.new_hunk, &block.new_lines)
+        );
+        // The whole second function moved, with the blank line between the functions removed
+        // after it and added before it
+        assert_eq!(block.old_lines.len(), 3);
+    }
+
+    #[test]
+    fn test_moved_blocks_across_hunks() {
+        let patch = Patch::from_single(
+            "\
+--- a.txt
++++ a.txt
+@@ -1,3 +1,1 @@
+ start
+-the moved line of the file
+-another moved line
+@@ -10,1 +8,4 @@
+ end
++the moved line of the file
++a changed line
++}
+",
+        )
+        .unwrap();
+        assert_eq!(
+            patch.moved_blocks(),
+            [MovedBlock {
+                old_hunk: 0,
+                old_lines: 1..2,
+                new_hunk: 1,
+                new_lines: 1..2,
+            }]
+        );
+
+        // Short blocks are not moves
+        let patch = Patch::from_single("--- a\n+++ a\n@@ -1,2 +1,2 @@\n-}\n x\n+}\n").unwrap();
+        assert_eq!(patch.moved_blocks(), []);
+    }
+}
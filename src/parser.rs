@@ -1,57 +1,457 @@
-use std::borrow::Cow;
-use std::error::Error;
+use alloc::borrow::{Cow, ToOwned};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error;
 
 use chrono::DateTime;
+use nom::error::ErrorKind;
 use nom::*;
 use nom::{
     branch::alt,
-    bytes::complete::{is_not, tag, take_until},
-    character::complete::{char, digit1, line_ending, none_of, not_line_ending, one_of},
-    combinator::{map, not, opt},
-    multi::{many0, many1},
+    bytes::complete::{tag, take_till1, take_while1},
+    character::complete::{char, digit1, hex_digit1, line_ending, not_line_ending, satisfy},
+    combinator::{map, map_opt, map_res, not, opt, peek, recognize},
+    multi::{count, fold_many1, many0, many1},
     sequence::{delimited, preceded, terminated},
 };
 
 use crate::ast::*;
 
-type Input<'a> = nom_locate::LocatedSpan<&'a str>;
+pub(crate) type Input<'a> = nom_locate::LocatedSpan<&'a str>;
+type ByteInput<'a> = nom_locate::LocatedSpan<&'a [u8]>;
+
+/// The input of the parser: text for patches made of `str`s, bytes for patches made of `[u8]`s
+///
+/// Everything but the lines of the hunks has to be text, so it is decoded as UTF-8 if necessary.
+pub(crate) trait Span<'a>:
+    nom::Input<Item: AsChar> + Compare<&'static str> + Offset + Copy + 'a
+{
+    /// The type of the lines of the parsed patch
+    type Text: ?Sized + ToOwned;
+
+    /// The remaining input
+    fn text(&self) -> &'a Self::Text;
+
+    /// The remaining input as a `str`, replacing invalid UTF-8 sequences
+    fn to_str(&self) -> Cow<'a, str>;
+
+    /// The longest prefix of the remaining input that is valid UTF-8
+    fn valid_str(&self) -> &'a str;
+
+    /// The remaining input as bytes
+    fn as_bytes(&self) -> &'a [u8];
+
+    fn starts_with(&self, prefix: &str) -> bool;
+
+    /// The line number of the start of the remaining input
+    fn line_number(&self) -> u32;
+
+    /// The offset of the start of the remaining input
+    fn byte_offset(&self) -> usize;
+
+    /// The column of the start of the remaining input, counting characters from 1
+    fn column(&self) -> usize;
+
+    /// The whole line containing the start of the remaining input, without its line ending
+    fn line_text(&self) -> String;
+}
+
+impl<'a> Span<'a> for Input<'a> {
+    type Text = str;
+
+    fn text(&self) -> &'a str {
+        self.fragment()
+    }
+
+    fn to_str(&self) -> Cow<'a, str> {
+        Cow::Borrowed(self.fragment())
+    }
+
+    fn valid_str(&self) -> &'a str {
+        self.fragment()
+    }
+
+    fn as_bytes(&self) -> &'a [u8] {
+        let text: &'a str = self.fragment();
+        text.as_bytes()
+    }
+
+    fn starts_with(&self, prefix: &str) -> bool {
+        self.fragment().starts_with(prefix)
+    }
+
+    fn line_number(&self) -> u32 {
+        self.location_line()
+    }
+
+    fn byte_offset(&self) -> usize {
+        self.location_offset()
+    }
+
+    fn column(&self) -> usize {
+        self.get_utf8_column()
+    }
+
+    fn line_text(&self) -> String {
+        let line = String::from_utf8_lossy(self.get_line_beginning());
+        line.strip_suffix('\r').unwrap_or(&line).to_string()
+    }
+}
+
+impl<'a> Span<'a> for ByteInput<'a> {
+    type Text = [u8];
+
+    fn text(&self) -> &'a [u8] {
+        self.fragment()
+    }
+
+    fn to_str(&self) -> Cow<'a, str> {
+        String::from_utf8_lossy(self.fragment())
+    }
+
+    fn valid_str(&self) -> &'a str {
+        let bytes: &'a [u8] = self.fragment();
+        match core::str::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(error) => core::str::from_utf8(&bytes[..error.valid_up_to()])
+                .expect("bug: bytes up to the first invalid sequence should be valid UTF-8"),
+        }
+    }
+
+    fn as_bytes(&self) -> &'a [u8] {
+        self.fragment()
+    }
+
+    fn starts_with(&self, prefix: &str) -> bool {
+        self.fragment().starts_with(prefix.as_bytes())
+    }
+
+    fn line_number(&self) -> u32 {
+        self.location_line()
+    }
+
+    fn byte_offset(&self) -> usize {
+        self.location_offset()
+    }
+
+    fn column(&self) -> usize {
+        self.get_utf8_column()
+    }
+
+    fn line_text(&self) -> String {
+        let line = String::from_utf8_lossy(self.get_line_beginning());
+        line.strip_suffix('\r').unwrap_or(&line).to_string()
+    }
+}
 
 /// Type returned when an error occurs while parsing a patch
 #[derive(Debug, Clone)]
 pub struct ParseError<'a> {
-    /// The line where the parsing error occurred
+    /// The line where the parsing error occurred, counting from 1
     pub line: u32,
+    /// The column where the parsing error occurred, counting characters from 1
+    pub column: usize,
     /// The offset within the input where the parsing error occurred
     pub offset: usize,
+    /// The text of the line where the parsing error occurred, without its line ending
+    ///
+    /// This is empty if the error occurred at the end of the input.
+    pub line_text: String,
     /// The failed input
-    pub fragment: &'a str,
+    ///
+    /// For patches parsed from bytes, this stops before the first invalid UTF-8 sequence.
+    pub fragment: Cow<'a, str>,
+    /// What the parser expected to find where it failed, if it knows
+    pub expected: Option<Expected>,
+    /// The limit of the [`ParseOptions`] that the input exceeds, if that's why parsing failed
+    pub limit: Option<ParseLimit>,
     /// The actual parsing error
     pub kind: nom::error::ErrorKind,
 }
 
-#[doc(hidden)]
-impl<'a> From<nom::Err<nom::error::Error<Input<'a>>>> for ParseError<'a> {
-    fn from(err: nom::Err<nom::error::Error<Input<'a>>>) -> Self {
-        match err {
-            nom::Err::Incomplete(_) => unreachable!("bug: parser should not return incomplete"),
-            // Unify both error types because at this point the error is not recoverable
-            nom::Err::Error(error) | nom::Err::Failure(error) => Self {
-                line: error.input.location_line(),
-                offset: error.input.location_offset(),
-                fragment: error.input.fragment(),
-                kind: error.code,
-            },
+impl ParseError<'_> {
+    /// Convert into an error that owns the failed input, so it no longer borrows from the text
+    /// that was parsed
+    pub fn into_owned(self) -> ParseError<'static> {
+        ParseError {
+            fragment: Cow::Owned(self.fragment.into_owned()),
+            ..self
+        }
+    }
+
+    // The rest of the line where the error occurred
+    fn found(&self) -> &str {
+        let start = self
+            .line_text
+            .char_indices()
+            .nth(self.column.saturating_sub(1))
+            .map_or(self.line_text.len(), |(i, _)| i);
+        &self.line_text[start..]
+    }
+}
+
+/// What the parser expected to find where it failed, see [`ParseError::expected`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Expected {
+    /// The `---` and `+++` lines naming the files of a patch
+    FileHeader,
+    /// A hunk header, like `@@ -1,3 +1,4 @@`
+    HunkHeader,
+    /// A line of a hunk, starting with ` `, `-` or `+`
+    HunkLine,
+    /// As many lines as the ranges of a hunk say it has
+    LineCount,
+    /// The data of a `GIT binary patch`
+    BinaryData,
+    /// A change to a property in a Subversion patch, like `Added: svn:executable`
+    PropertyChange,
+    /// The end of the input, after the only patch it should contain
+    EndOfInput,
+}
+
+impl core::fmt::Display for Expected {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let expected = match self {
+            Expected::FileHeader => "file headers",
+            Expected::HunkHeader => "a hunk header",
+            Expected::HunkLine => "a hunk line",
+            Expected::LineCount => "as many lines as the hunk header says",
+            Expected::BinaryData => "binary data",
+            Expected::PropertyChange => "a property change",
+            Expected::EndOfInput => "the end of the input",
+        };
+        write!(f, "{}", expected)
+    }
+}
+
+/// A limit on the input set by [`ParseOptions`], see [`ParseError::limit`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ParseLimit {
+    /// [`ParseOptions::max_size`]
+    Size,
+    /// [`ParseOptions::max_hunks`]
+    Hunks,
+    /// [`ParseOptions::max_line_length`]
+    LineLength,
+    /// [`ParseOptions::max_files`]
+    Files,
+}
+
+impl core::fmt::Display for ParseLimit {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let limit = match self {
+            ParseLimit::Size => "the input is larger than the maximum size",
+            ParseLimit::Hunks => "the patch has more hunks than the maximum",
+            ParseLimit::LineLength => "the line is longer than the maximum length",
+            ParseLimit::Files => "the input has more patches than the maximum",
+        };
+        write!(f, "{}", limit)
+    }
+}
+
+/// Options for parsing patches, see [`Patch::from_multiple_with_options`]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ParseOptions {
+    /// Skip the parts of the input that can't be parsed instead of failing, and report each of
+    /// them as a [`ParseWarning`]
+    ///
+    /// A malformed hunk, or one with fewer or more lines than its header says, is left out of its
+    /// patch along with any text up to the next hunk. A patch whose file headers can't be parsed
+    /// is left out entirely. Parsing only fails if no patch is left.
+    pub lenient: bool,
+    /// Fix the counts of the ranges of every hunk to the number of lines it has, like
+    /// `git apply --recount`, see [`Hunk::recount`]
+    ///
+    /// With this, lenient parsing no longer skips the hunks whose counts are wrong.
+    pub recount: bool,
+    /// The maximum length of the input in bytes
+    ///
+    /// This and the other limits let servers parse patches they are sent without spending
+    /// unbounded time and memory on them. Exceeding any of them fails parsing with a
+    /// [`ParseError::limit`], even when parsing is lenient.
+    pub max_size: Option<usize>,
+    /// The maximum number of hunks of a patch
+    pub max_hunks: Option<usize>,
+    /// The maximum length of a line of the input in bytes, without its line ending
+    pub max_line_length: Option<usize>,
+    /// The maximum number of patches of the input, which is the number of files it changes
+    pub max_files: Option<usize>,
+    /// Keep the exact text of every patch in [`Patch::raw`], so that writing the patches
+    /// reproduces the input byte for byte
+    ///
+    /// This keeps what parsing otherwise normalizes, like line endings, the counts of ranges that
+    /// are left out, empty lines and any text after the last patch. A patch that is changed after
+    /// parsing is written from its fields again.
+    pub lossless: bool,
+}
+
+/// A problem in the input that was skipped by [`ParseOptions::lenient`] parsing
+#[derive(Debug, Clone)]
+pub struct ParseWarning<'a> {
+    /// Where the problem is and what was expected there
+    pub error: ParseError<'a>,
+    /// What was left out of the parsed patches because of it
+    pub skipped: Skipped,
+}
+
+impl ParseWarning<'_> {
+    /// Convert into a warning that no longer borrows from the text that was parsed
+    pub fn into_owned(self) -> ParseWarning<'static> {
+        ParseWarning {
+            error: self.error.into_owned(),
+            skipped: self.skipped,
+        }
+    }
+}
+
+impl core::fmt::Display for ParseWarning<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let skipped = match self.skipped {
+            Skipped::Hunk => "the hunk",
+            Skipped::Text => "the text up to the next hunk",
+            Skipped::Patch => "the patch",
+        };
+        write!(f, "{}, skipped {}", self.error, skipped)
+    }
+}
+
+/// What was skipped because of a [`ParseWarning`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Skipped {
+    /// A hunk, from its header up to the next hunk
+    Hunk,
+    /// Text between two hunks of a patch
+    Text,
+    /// A whole patch, up to the start of the next one
+    Patch,
+}
+
+/// The error of the parsers, which knows what they expected to find
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SpanError<I> {
+    input: I,
+    code: ErrorKind,
+    expected: Option<Expected>,
+}
+
+impl<I> SpanError<I> {
+    /// A failure that stops parsing because the input isn't what `expected` says
+    pub(crate) fn failure(input: I, expected: Expected) -> nom::Err<Self> {
+        nom::Err::Failure(SpanError {
+            input,
+            code: ErrorKind::Verify,
+            expected: Some(expected),
+        })
+    }
+}
+
+impl<I> error::ParseError<I> for SpanError<I> {
+    fn from_error_kind(input: I, code: ErrorKind) -> Self {
+        SpanError {
+            input,
+            code,
+            expected: None,
+        }
+    }
+
+    fn append(_: I, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I, E> error::FromExternalError<I, E> for SpanError<I> {
+    fn from_external_error(input: I, code: ErrorKind, _: E) -> Self {
+        SpanError {
+            input,
+            code,
+            expected: None,
+        }
+    }
+}
+
+pub(crate) type IResult<I, O> = nom::IResult<I, O, SpanError<I>>;
+
+// Record what `parser` expected if it fails, unless a parser it called already did
+pub(crate) fn expect<I, O>(
+    expected: Expected,
+    mut parser: impl Parser<I, Output = O, Error = SpanError<I>>,
+) -> impl FnMut(I) -> IResult<I, O> {
+    move |input| {
+        parser.parse(input).map_err(|err| {
+            err.map(|mut error| {
+                error.expected.get_or_insert(expected);
+                error
+            })
+        })
+    }
+}
+
+pub(crate) fn convert_error<'a, I: Span<'a>>(err: nom::Err<SpanError<I>>) -> ParseError<'a> {
+    match err {
+        nom::Err::Incomplete(_) => unreachable!("bug: parser should not return incomplete"),
+        // Unify both error types because at this point the error is not recoverable
+        nom::Err::Error(error) | nom::Err::Failure(error) => ParseError {
+            line: error.input.line_number(),
+            column: error.input.column(),
+            offset: error.input.byte_offset(),
+            line_text: error.input.line_text(),
+            fragment: Cow::Borrowed(error.input.valid_str()),
+            expected: error.expected,
+            limit: None,
+            kind: error.code,
+        },
+    }
+}
+
+// The error for input at the given position that exceeds a limit of the options
+fn limit_error<'a, I: Span<'a>>(input: I, limit: ParseLimit) -> ParseError<'a> {
+    let error = SpanError {
+        input,
+        code: ErrorKind::TooLarge,
+        expected: None,
+    };
+    ParseError {
+        limit: Some(limit),
+        ..convert_error(nom::Err::Failure(error))
+    }
+}
+
+// Check the limits of the options that can be checked before parsing
+fn check_input<'a, I: Span<'a>>(input: I, options: &ParseOptions) -> Result<(), ParseError<'a>> {
+    if options
+        .max_size
+        .is_some_and(|max_size| input.input_len() > max_size)
+    {
+        return Err(limit_error(input, ParseLimit::Size));
+    }
+    if let Some(max_line_length) = options.max_line_length {
+        let mut offset = 0;
+        for line in input.as_bytes().split(|&byte| byte == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.len() > max_line_length {
+                return Err(limit_error(input.take_from(offset), ParseLimit::LineLength));
+            }
+            offset += line.len() + 1;
         }
     }
+    Ok(())
 }
 
-impl std::fmt::Display for ParseError<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(
-            f,
-            "Line {}: Error while parsing: {}",
-            self.line, self.fragment
-        )
+impl core::fmt::Display for ParseError<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "Line {}, column {}: ", self.line, self.column)?;
+        if let Some(limit) = self.limit {
+            return write!(f, "{}", limit);
+        }
+        match self.expected {
+            Some(expected) => write!(f, "expected {}", expected)?,
+            None => write!(f, "unexpected input")?,
+        }
+        match self.found() {
+            "" if self.fragment.is_empty() => write!(f, ", found the end of the input"),
+            "" => write!(f, ", found the end of the line"),
+            found => write!(f, ", found '{}'", found),
+        }
     }
 }
 
@@ -61,65 +461,421 @@ impl Error for ParseError<'_> {
     }
 }
 
-fn consume_content_line(input: Input<'_>) -> IResult<Input<'_>, &str> {
+#[cfg(feature = "miette")]
+impl miette::Diagnostic for ParseError<'_> {
+    fn code<'b>(&'b self) -> Option<Box<dyn core::fmt::Display + 'b>> {
+        Some(Box::new(format!("patch::parse::{:?}", self.kind)))
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        Some(&self.line_text)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+        let start = self.line_text.len() - self.found().len();
+        let label = match self.limit {
+            Some(limit) => Some(limit.to_string()),
+            None => self
+                .expected
+                .map(|expected| format!("expected {}", expected)),
+        };
+        let span = miette::LabeledSpan::new_with_span(label, (start, self.found().len()));
+        Some(Box::new(core::iter::once(span)))
+    }
+}
+
+pub(crate) fn consume_content_line<'a, I: Span<'a>>(input: I) -> IResult<I, Cow<'a, I::Text>> {
     let mut parser = terminated(not_line_ending, line_ending);
     let (input, raw) = parser.parse(input)?;
-    Ok((input, raw.fragment()))
+    Ok((input, Cow::Borrowed(raw.text())))
 }
 
-pub(crate) fn parse_single_patch(s: &str) -> Result<Patch, ParseError<'_>> {
-    let (remaining_input, patch) = patch(Input::new(s))?;
-    // Parser should return an error instead of producing remaining input
-    assert!(
-        remaining_input.fragment().is_empty(),
-        "bug: failed to parse entire input. \
-        Remaining: '{}'",
-        remaining_input.fragment()
-    );
-    Ok(patch)
+pub(crate) fn parse_single_patch(s: &str) -> Result<Patch<'_>, ParseError<'_>> {
+    parse_single(Input::new(s))
 }
 
-pub(crate) fn parse_multiple_patches(s: &str) -> Result<Vec<Patch>, ParseError<'_>> {
-    let (remaining_input, patches) = multiple_patches(Input::new(s))?;
-    // Parser should return an error instead of producing remaining input
-    assert!(
-        remaining_input.fragment().is_empty(),
-        "bug: failed to parse entire input. \
-        Remaining: '{}'",
-        remaining_input.fragment()
-    );
-    Ok(patches)
+// Parse the first patch of the input, returning it along with the length of the input it was
+// parsed from
+#[cfg(feature = "std")]
+pub(crate) fn parse_next_patch(s: &str) -> Result<(Patch<'_>, usize), ParseError<'_>> {
+    let (remaining_input, patch) =
+        patch(Input::new(s), &ParseOptions::default(), &mut Vec::new()).map_err(convert_error)?;
+    Ok((patch, remaining_input.location_offset()))
 }
 
-fn multiple_patches(input: Input<'_>) -> IResult<Input<'_>, Vec<Patch>> {
-    let mut parser = many1(patch);
-    parser.parse(input)
+// Parse the text a patch was parsed from without loss again, to tell whether it has changed since
+pub(crate) fn reparse(raw: &str, lenient: bool) -> Option<Patch<'_>> {
+    let options = ParseOptions {
+        lenient,
+        lossless: true,
+        ..ParseOptions::default()
+    };
+    let (_, patch) = patch(Input::new(raw), &options, &mut Vec::new()).ok()?;
+    Some(Patch {
+        raw: Some(Cow::Borrowed(raw)),
+        ..patch
+    })
+}
+
+pub(crate) fn parse_single_patch_bytes(s: &[u8]) -> Result<Patch<'_, [u8]>, ParseError<'_>> {
+    parse_single(ByteInput::new(s))
+}
+
+pub(crate) fn parse_multiple_patches(s: &str) -> Result<Vec<Patch<'_>>, ParseError<'_>> {
+    parse_multiple(Input::new(s), &ParseOptions::default()).map(|(patches, _)| patches)
 }
 
-fn patch(input: Input<'_>) -> IResult<Input<'_>, Patch> {
-    let (input, files) = headers(input)?;
-    let (input, hunks) = chunks(input)?;
-    let (input, no_newline_indicator) = no_newline_indicator(input)?;
+pub(crate) fn parse_multiple_patches_with_options<'a>(
+    s: &'a str,
+    options: &ParseOptions,
+) -> Result<(Vec<Patch<'a>>, Vec<ParseWarning<'a>>), ParseError<'a>> {
+    parse_multiple(Input::new(s), options)
+}
+
+pub(crate) fn parse_multiple_patches_bytes(
+    s: &[u8],
+) -> Result<Vec<Patch<'_, [u8]>>, ParseError<'_>> {
+    parse_multiple(ByteInput::new(s), &ParseOptions::default()).map(|(patches, _)| patches)
+}
+
+fn parse_single<'a, I: Span<'a>>(input: I) -> Result<Patch<'a, I::Text>, ParseError<'a>> {
+    let (input, patch) =
+        patch(input, &ParseOptions::default(), &mut Vec::new()).map_err(convert_error)?;
+    // Anything after the patch that doesn't start another one is ignored
+    let (input, _) = preamble(input).map_err(convert_error)?;
+    if input.input_len() > 0 {
+        return Err(convert_error(SpanError::failure(
+            input,
+            Expected::EndOfInput,
+        )));
+    }
+    Ok(patch)
+}
+
+// The problems lenient parsing skipped, with what it skipped because of them
+type Warnings<I> = Vec<(SpanError<I>, Skipped)>;
+
+type Parsed<'a, T> = (Vec<Patch<'a, T>>, Vec<ParseWarning<'a>>);
+
+fn parse_multiple<'a, I: Span<'a>>(
+    input: I,
+    options: &ParseOptions,
+) -> Result<Parsed<'a, I::Text>, ParseError<'a>> {
+    check_input(input, options)?;
+    let mut patches = Vec::new();
+    let mut warnings = Vec::new();
+    let mut input = input;
+    loop {
+        match patch(input, options, &mut warnings) {
+            Ok((rest, patch)) => {
+                if options.max_hunks.is_some_and(|max| patch.hunks.len() > max) {
+                    return Err(limit_error(input, ParseLimit::Hunks));
+                }
+                if options.max_files.is_some_and(|max| patches.len() == max) {
+                    return Err(limit_error(input, ParseLimit::Files));
+                }
+                patches.push(patch);
+                input = rest;
+            }
+            Err(error) => {
+                // Anything after the last patch that doesn't start another one is ignored
+                let (rest, _) = preamble(input).map_err(convert_error)?;
+                if rest.input_len() == 0 && !patches.is_empty() {
+                    if let Some(raw) = patches.last_mut().and_then(|patch| patch.raw.as_mut()) {
+                        raw.to_mut().push_str(&input.to_str());
+                    }
+                    break;
+                }
+                if !options.lenient {
+                    return Err(convert_error(error));
+                }
+                warnings.push((unwrap_error(error), Skipped::Patch));
+                // Go on from the line after the start of the patch that failed
+                match consume_content_line(rest) {
+                    Ok((rest, _)) => input = rest,
+                    Err(_) => break,
+                }
+            }
+        }
+        if input.input_len() == 0 {
+            break;
+        }
+    }
+    let mut warnings: Vec<_> = warnings
+        .into_iter()
+        .map(|(error, skipped)| ParseWarning {
+            error: convert_error(nom::Err::Error(error)),
+            skipped,
+        })
+        .collect();
+    if patches.is_empty() {
+        // Nothing could be salvaged, so the first problem is the error
+        return Err(match warnings.is_empty() {
+            true => convert_error(SpanError::failure(input, Expected::FileHeader)),
+            false => warnings.remove(0).error,
+        });
+    }
+    Ok((patches, warnings))
+}
+
+fn patch<'a, I: Span<'a>>(
+    input: I,
+    options: &ParseOptions,
+    warnings: &mut Warnings<I>,
+) -> IResult<I, Patch<'a, I::Text>> {
+    let start = input;
+    let (input, skipped) = recognize(preamble).parse(input)?;
+    let leading_text = (skipped.input_len() > 0).then(|| skipped.to_str());
+    // CVS writes more lines between the `Index:` line and the file headers
+    let (input, index) = opt(terminated(svn_index, preamble)).parse(input)?;
+    let (input, git) = opt(git_header).parse(input)?;
+    let mut properties = Vec::new();
+    // Without `--binary`, git only says that binary files differ, and so does diff without a
+    // `diff --git` header
+    let (input, binary_files) = opt(binary_files).parse(input)?;
+    let opaque_binary = binary_files.is_some();
+    let (input, (old, new, hunks, binary, (old_end_newline, new_end_newline))) =
+        match (&git, binary_files) {
+            (Some((header, operation)), None) if input.starts_with("GIT binary patch") => {
+                let (input, binary) = binary_patch(input)?;
+                let (old, new) = git_files(header, operation);
+                (input, (old, new, Vec::new(), Some(binary), (true, true)))
+            }
+            (git, Some((old_path, new_path))) => {
+                let (old, new) = match git {
+                    Some((header, operation)) => git_files(header, operation),
+                    None => (
+                        File {
+                            path: old_path,
+                            meta: None,
+                        },
+                        File {
+                            path: new_path,
+                            meta: None,
+                        },
+                    ),
+                };
+                (input, (old, new, Vec::new(), None, (true, true)))
+            }
+            // Patches that only rename a file or change its mode have no file headers or hunks
+            (Some((header, operation)), None) if !input.starts_with("--- ") => {
+                let (old, new) = git_files(header, operation);
+                (input, (old, new, Vec::new(), None, (true, true)))
+            }
+            // Subversion leaves out the file headers of files that only had their properties changed,
+            // and of binary files
+            (None, None) if !input.starts_with("--- ") => {
+                let (input, section) = opt(property_changes).parse(input)?;
+                let path = match (&index, section) {
+                    (Some(path), section) => {
+                        properties = section.map_or_else(Vec::new, |section| section.1);
+                        path.clone()
+                    }
+                    (None, Some((path, section))) => {
+                        properties = section;
+                        path
+                    }
+                    (None, None) => {
+                        return Err(nom::Err::Error(SpanError {
+                            input,
+                            code: ErrorKind::Tag,
+                            expected: Some(Expected::FileHeader),
+                        }));
+                    }
+                };
+                let file = File { path, meta: None };
+                (input, (file.clone(), file, Vec::new(), None, (true, true)))
+            }
+            _ => {
+                let (input, (old, new)) = expect(Expected::FileHeader, headers).parse(input)?;
+                // Subversion writes the file headers of files that only had their properties
+                // changed, without hunks
+                let (input, chunks) = if options.lenient {
+                    lenient_chunks(input, options, warnings)?
+                } else if index.is_some() {
+                    many0(chunk).parse(input)?
+                } else {
+                    chunks(input)?
+                };
+                // Only the last hunk can reach the end of the files
+                let end_newlines = chunks.last().map_or((true, true), |chunk| chunk.1);
+                let mut hunks: Vec<_> = chunks.into_iter().map(|chunk| chunk.0).collect();
+                if options.recount {
+                    hunks.iter_mut().for_each(Hunk::recount);
+                }
+                (input, (old, new, hunks, None, end_newlines))
+            }
+        };
     // Ignore trailing empty lines produced by some diff programs
     let mut parser = many0(line_ending);
     let (input, _) = parser.parse(input)?;
+    let (input, section) = opt(terminated(property_changes, many0(line_ending))).parse(input)?;
+    if let Some((_, section)) = section {
+        properties = section;
+    }
 
-    let (old, new) = files;
+    let (operation, git) = match git {
+        Some((header, operation)) => (operation, Some(header)),
+        None => (FileOp::Modify, None),
+    };
+    let svn =
+        (index.is_some() || !properties.is_empty()).then_some(SvnHeader { index, properties });
     Ok((
         input,
         Patch {
             old,
             new,
+            operation,
+            git,
+            svn,
             hunks,
-            end_newline: !no_newline_indicator,
+            binary,
+            opaque_binary,
+            preamble: leading_text,
+            raw: options
+                .lossless
+                .then(|| start.take(start.input_len() - input.input_len()).to_str()),
+            old_end_newline,
+            new_end_newline,
         },
     ))
 }
 
+// Skip the lines before a patch, like a commit message, up to the start of the next one
+fn preamble<'a, I: Span<'a>>(input: I) -> IResult<I, ()> {
+    let mut parser = many0(preceded(not(patch_start), consume_content_line));
+    let (input, _) = parser.parse(input)?;
+    Ok((input, ()))
+}
+
+// The first line of a patch
+fn patch_start<'a, I: Span<'a>>(input: I) -> IResult<I, ()> {
+    let mut parser = alt((
+        map(tag("diff --git "), |_| ()),
+        map(tag("--- "), |_| ()),
+        map(binary_files, |_| ()),
+        map(svn_index, |_| ()),
+        property_changes_header,
+    ));
+    parser.parse(input)
+}
+
+// The `Index:` line of Subversion and CVS, followed by a line of `=`
+fn svn_index<'a, I: Span<'a>>(input: I) -> IResult<I, Cow<'a, str>> {
+    let mut parser = terminated(
+        delimited(tag("Index: "), bare, line_ending),
+        (take_while1(|ch: I::Item| ch.as_char() == '='), line_ending),
+    );
+    parser.parse(input)
+}
+
+// The start of the section of a Subversion patch listing the changes to the properties of a file
+fn property_changes_header<'a, I: Span<'a>>(input: I) -> IResult<I, ()> {
+    let mut parser = (
+        tag("Property changes on: "),
+        not_line_ending,
+        line_ending,
+        take_while1(|ch: I::Item| ch.as_char() == '_'),
+        line_ending,
+    );
+    let (input, _) = parser.parse(input)?;
+    Ok((input, ()))
+}
+
+// The section of a Subversion patch listing the changes to the properties of a file, with the
+// path of the file
+fn property_changes<'a, I: Span<'a>>(
+    input: I,
+) -> IResult<I, (Cow<'a, str>, Vec<PropertyChange<'a>>)> {
+    let (input, path) = delimited(tag("Property changes on: "), bare, line_ending).parse(input)?;
+    let (input, _) =
+        terminated(take_while1(|ch: I::Item| ch.as_char() == '_'), line_ending).parse(input)?;
+    let (input, properties) =
+        expect(Expected::PropertyChange, many1(property_change)).parse(input)?;
+    Ok((input, (path, properties)))
+}
+
+// A property change, like Subversion 1.7 and later write it with the values in a hunk, or like
+// earlier versions write it with one line for each value
+fn property_change<'a, I: Span<'a>>(input: I) -> IResult<I, PropertyChange<'a>> {
+    let mut parser = alt((
+        tag("Added: "),
+        tag("Deleted: "),
+        tag("Modified: "),
+        tag("Name: "),
+    ));
+    let (input, _) = parser.parse(input)?;
+    let (input, name) = terminated(not_line_ending, line_ending).parse(input)?;
+    let name = name.to_str();
+
+    let old_value = |input| preceded(tag("   - "), property_line).parse(input);
+    let new_value = |input| preceded(tag("   + "), property_line).parse(input);
+    let (input, (old, new)) = alt((
+        property_hunk,
+        (
+            map(opt(old_value), |old| old.map(Cow::Borrowed)),
+            map(opt(new_value), |new| new.map(Cow::Borrowed)),
+        ),
+    ))
+    .parse(input)?;
+    Ok((input, PropertyChange { name, old, new }))
+}
+
+// The old and new values of a property, as a hunk whose lines are the lines of the values
+type PropertyValues<'a> = (Option<Cow<'a, str>>, Option<Cow<'a, str>>);
+
+fn property_hunk<'a, I: Span<'a>>(input: I) -> IResult<I, PropertyValues<'a>> {
+    let (input, _) = tag("## -")(input)?;
+    let (input, old_range) = range(input)?;
+    let (input, _) = tag(" +")(input)?;
+    let (input, new_range) = range(input)?;
+    let (input, _) = terminated(tag(" ##"), line_ending).parse(input)?;
+    let (input, old) = property_value('-', old_range.count)(input)?;
+    let (input, new) = property_value('+', new_range.count)(input)?;
+    Ok((input, (old, new)))
+}
+
+// The given number of lines of a property value, each starting with `prefix`
+fn property_value<'a, I: Span<'a>>(
+    prefix: char,
+    lines: u64,
+) -> impl FnMut(I) -> IResult<I, Option<Cow<'a, str>>> {
+    move |input| {
+        if lines == 0 {
+            return Ok((input, None));
+        }
+        let mut parser = (
+            count(preceded(char(prefix), property_line), lines as usize),
+            map(
+                opt(terminated(
+                    tag("\\ No newline at end of property"),
+                    opt(line_ending),
+                )),
+                |matched| matched.is_some(),
+            ),
+        );
+        let (input, (values, no_newline)) = parser.parse(input)?;
+        let value = match values.as_slice() {
+            [value] if no_newline => Cow::Borrowed(*value),
+            _ => {
+                let mut value = values.join("\n");
+                if !no_newline {
+                    value.push('\n');
+                }
+                Cow::Owned(value)
+            }
+        };
+        Ok((input, Some(value)))
+    }
+}
+
+fn property_line<'a, I: Span<'a>>(input: I) -> IResult<I, &'a str> {
+    let (input, line) = terminated(not_line_ending, line_ending).parse(input)?;
+    Ok((input, line.valid_str()))
+}
+
 // Header lines
-fn headers(input: Input<'_>) -> IResult<Input<'_>, (File, File)> {
-    // Ignore any preamble lines in produced diffs
-    let (input, _) = take_until("---")(input)?;
+fn headers<'a, I: Span<'a>>(input: I) -> IResult<I, (File<'a>, File<'a>)> {
     let (input, _) = tag("--- ")(input)?;
     let (input, oldfile) = header_line_content(input)?;
     let (input, _) = line_ending(input)?;
@@ -129,7 +885,291 @@ fn headers(input: Input<'_>) -> IResult<Input<'_>, (File, File)> {
     Ok((input, (oldfile, newfile)))
 }
 
-fn header_line_content(input: Input<'_>) -> IResult<Input<'_>, File> {
+// The `diff --git` line and the extended header lines following it
+fn git_header<'a, I: Span<'a>>(input: I) -> IResult<I, (GitHeader<'a>, FileOp<'a>)> {
+    let (input, _) = tag("diff --git ")(input)?;
+    let (input, (old_path, new_path)) = git_paths(input)?;
+    let (input, _) = line_ending(input)?;
+    let mut parser = many0(terminated(extended_header_line, line_ending));
+    let (input, lines) = parser.parse(input)?;
+
+    let mut header = GitHeader {
+        old_path,
+        new_path,
+        old_mode: None,
+        new_mode: None,
+        similarity: None,
+        dissimilarity: None,
+        index: None,
+    };
+    let mut operation = FileOp::Modify;
+    let (mut rename_from, mut rename_to, mut copy_from, mut copy_to) = (None, None, None, None);
+    for line in lines {
+        match line {
+            ExtendedHeaderLine::OldMode(mode) => header.old_mode = Some(mode),
+            ExtendedHeaderLine::NewMode(mode) => header.new_mode = Some(mode),
+            ExtendedHeaderLine::NewFileMode(mode) => {
+                header.new_mode = Some(mode);
+                operation = FileOp::Create;
+            }
+            ExtendedHeaderLine::DeletedFileMode(mode) => {
+                header.old_mode = Some(mode);
+                operation = FileOp::Delete;
+            }
+            ExtendedHeaderLine::Similarity(score) => header.similarity = Some(score),
+            ExtendedHeaderLine::Dissimilarity(score) => header.dissimilarity = Some(score),
+            ExtendedHeaderLine::RenameFrom(path) => rename_from = Some(path),
+            ExtendedHeaderLine::RenameTo(path) => rename_to = Some(path),
+            ExtendedHeaderLine::CopyFrom(path) => copy_from = Some(path),
+            ExtendedHeaderLine::CopyTo(path) => copy_to = Some(path),
+            ExtendedHeaderLine::Index(index) => header.index = Some(index),
+            ExtendedHeaderLine::Other => {}
+        }
+    }
+    if let (Some(from), Some(to)) = (rename_from, rename_to) {
+        operation = FileOp::Rename { from, to };
+    } else if let (Some(from), Some(to)) = (copy_from, copy_to) {
+        operation = FileOp::Copy { from, to };
+    }
+
+    Ok((input, (header, operation)))
+}
+
+// The two paths of a `diff --git` line, which are only quoted if they contain special
+// characters, so unquoted paths containing spaces are ambiguous
+fn git_paths<'a, I: Span<'a>>(input: I) -> IResult<I, (Cow<'a, str>, Cow<'a, str>)> {
+    let mut parser = alt((
+        (quoted, preceded(char(' '), filename)),
+        // An unquoted path can't contain a quote, so a quote starts the new path
+        (
+            map_opt(
+                take_till1(|ch: I::Item| matches!(ch.as_char(), '"' | '\r' | '\n')),
+                |old: I| {
+                    let old = old.to_str();
+                    let len = old.strip_suffix(' ')?.len();
+                    Some(sub_str(&old, 0..len))
+                },
+            ),
+            quoted,
+        ),
+        map(not_line_ending, |line: I| split_paths(line.to_str(), " ")),
+    ));
+    parser.parse(input)
+}
+
+// Split two unquoted paths separated by `separator`, like the paths of a `diff --git` line. Both
+// paths are the same except for their prefix unless the file was renamed or copied, so prefer
+// splitting the line in the middle.
+fn split_paths<'a>(line: Cow<'a, str>, separator: &str) -> (Cow<'a, str>, Cow<'a, str>) {
+    fn without_prefix(path: &str) -> &str {
+        path.split_once('/').map_or(path, |(_, rest)| rest)
+    }
+
+    let paths_len = line.len().saturating_sub(separator.len());
+    let middle = paths_len / 2;
+    if paths_len.is_multiple_of(2)
+        && line.is_char_boundary(middle)
+        && line[middle..].starts_with(separator)
+        && without_prefix(&line[..middle]) == without_prefix(&line[middle + separator.len()..])
+    {
+        return (
+            sub_str(&line, 0..middle),
+            sub_str(&line, middle + separator.len()..line.len()),
+        );
+    }
+
+    let split = line
+        .match_indices(separator)
+        .map(|(split, _)| split)
+        .find(|&split| line[split + separator.len()..].starts_with("b/"))
+        .or_else(|| line.find(separator))
+        .unwrap_or(line.len());
+    (
+        sub_str(&line, 0..split),
+        sub_str(&line, (split + separator.len()).min(line.len())..line.len()),
+    )
+}
+
+// The line diff and git write instead of the changes of a binary file, with its old and new
+// path, which are only quoted if they contain special characters
+fn binary_files<'a, I: Span<'a>>(input: I) -> IResult<I, (Cow<'a, str>, Cow<'a, str>)> {
+    let (input, _) = tag("Binary files ")(input)?;
+    let mut parser = alt((
+        (quoted, delimited(tag(" and "), quoted, tag(" differ"))),
+        map_opt(not_line_ending, |line: I| {
+            let line = line.to_str();
+            let len = line.strip_suffix(" differ")?.len();
+            Some(split_paths(sub_str(&line, 0..len), " and "))
+        }),
+    ));
+    let (input, paths) = parser.parse(input)?;
+    let (input, _) = opt(line_ending).parse(input)?;
+    Ok((input, paths))
+}
+
+// Take a part of a string, borrowing it if the string is borrowed
+fn sub_str<'a>(s: &Cow<'a, str>, range: core::ops::Range<usize>) -> Cow<'a, str> {
+    match s {
+        Cow::Borrowed(s) => Cow::Borrowed(&s[range]),
+        Cow::Owned(s) => Cow::Owned(s[range].to_owned()),
+    }
+}
+
+/// A line between the `diff --git` line and the file headers
+enum ExtendedHeaderLine<'a> {
+    OldMode(u32),
+    NewMode(u32),
+    NewFileMode(u32),
+    DeletedFileMode(u32),
+    Similarity(u8),
+    Dissimilarity(u8),
+    RenameFrom(Cow<'a, str>),
+    RenameTo(Cow<'a, str>),
+    CopyFrom(Cow<'a, str>),
+    CopyTo(Cow<'a, str>),
+    Index(GitIndex<'a>),
+    /// A recognized line that isn't kept, like the `index` line of combined diffs
+    Other,
+}
+
+fn extended_header_line<'a, I: Span<'a>>(input: I) -> IResult<I, ExtendedHeaderLine<'a>> {
+    let mut parser = alt((
+        map(
+            preceded(tag("old mode "), mode),
+            ExtendedHeaderLine::OldMode,
+        ),
+        map(
+            preceded(tag("new mode "), mode),
+            ExtendedHeaderLine::NewMode,
+        ),
+        map(
+            preceded(tag("new file mode "), mode),
+            ExtendedHeaderLine::NewFileMode,
+        ),
+        map(
+            preceded(tag("deleted file mode "), mode),
+            ExtendedHeaderLine::DeletedFileMode,
+        ),
+        map(
+            delimited(tag("similarity index "), percentage, char('%')),
+            ExtendedHeaderLine::Similarity,
+        ),
+        map(
+            delimited(tag("dissimilarity index "), percentage, char('%')),
+            ExtendedHeaderLine::Dissimilarity,
+        ),
+        map(
+            preceded(tag("rename from "), filename),
+            ExtendedHeaderLine::RenameFrom,
+        ),
+        map(
+            preceded(tag("rename to "), filename),
+            ExtendedHeaderLine::RenameTo,
+        ),
+        map(
+            preceded(tag("copy from "), filename),
+            ExtendedHeaderLine::CopyFrom,
+        ),
+        map(
+            preceded(tag("copy to "), filename),
+            ExtendedHeaderLine::CopyTo,
+        ),
+        map(
+            preceded(tag("index "), git_index),
+            ExtendedHeaderLine::Index,
+        ),
+        map(preceded(tag("index "), not_line_ending), |_| {
+            ExtendedHeaderLine::Other
+        }),
+    ));
+    parser.parse(input)
+}
+
+// The ids and mode of an `index` line, which combined diffs write with more than one old id
+fn git_index<'a, I: Span<'a>>(input: I) -> IResult<I, GitIndex<'a>> {
+    let mut parser = map(
+        (
+            map(hex_digit1, |id: I| id.to_str()),
+            preceded(tag(".."), map(hex_digit1, |id: I| id.to_str())),
+            opt(preceded(char(' '), mode)),
+            peek(line_ending),
+        ),
+        |(old_id, new_id, mode, _)| GitIndex {
+            old_id,
+            new_id,
+            mode,
+        },
+    );
+    parser.parse(input)
+}
+
+fn mode<'a, I: Span<'a>>(input: I) -> IResult<I, u32> {
+    let mut parser = map_res(digit1, |digits: I| u32::from_str_radix(&digits.to_str(), 8));
+    parser.parse(input)
+}
+
+fn percentage<'a, I: Span<'a>>(input: I) -> IResult<I, u8> {
+    let mut parser = map_res(digit1, |digits: I| digits.to_str().parse::<u8>());
+    parser.parse(input)
+}
+
+// The `GIT binary patch` line, followed by the forward hunk and usually the reverse hunk
+fn binary_patch<'a, I: Span<'a>>(input: I) -> IResult<I, BinaryPatch<'a>> {
+    let (input, _) = terminated(tag("GIT binary patch"), line_ending).parse(input)?;
+    let (input, forward) = expect(Expected::BinaryData, binary_hunk).parse(input)?;
+    let (input, reverse) = opt(binary_hunk).parse(input)?;
+    Ok((input, BinaryPatch { forward, reverse }))
+}
+
+fn binary_hunk<'a, I: Span<'a>>(input: I) -> IResult<I, BinaryHunk<'a>> {
+    let mut parser = alt((
+        map(tag("literal "), |_| BinaryKind::Literal),
+        map(tag("delta "), |_| BinaryKind::Delta),
+    ));
+    let (input, kind) = parser.parse(input)?;
+    let (input, size) = u64_digit(input)?;
+    let (input, _) = line_ending(input)?;
+
+    // Every line of data starts with a letter giving its length, and the data ends with an empty
+    // line
+    let mut parser = many1(terminated(base85_line, line_ending));
+    let (input, data) = parser.parse(input)?;
+    let (input, _) = opt(line_ending).parse(input)?;
+    Ok((input, BinaryHunk { kind, size, data }))
+}
+
+fn base85_line<'a, I: Span<'a>>(input: I) -> IResult<I, Cow<'a, str>> {
+    let mut parser = recognize((
+        satisfy(|ch| ch.is_ascii_alphabetic()),
+        take_while1(|ch: I::Item| {
+            let ch = ch.as_char();
+            ch.is_ascii_alphanumeric() || "!#$%&()*+-;<=>?@^_`{|}~".contains(ch)
+        }),
+    ));
+    let (input, line) = parser.parse(input)?;
+    // The line only contains ASCII characters
+    Ok((input, Cow::Borrowed(line.valid_str())))
+}
+
+// The files of a patch without file headers, which git leaves out when a file is only renamed or
+// has its mode changed
+fn git_files<'a>(header: &GitHeader<'a>, operation: &FileOp) -> (File<'a>, File<'a>) {
+    let file = |path: &Cow<'a, str>| File {
+        path: path.clone(),
+        meta: None,
+    };
+    let dev_null = File {
+        path: Cow::Borrowed("/dev/null"),
+        meta: None,
+    };
+    match operation {
+        FileOp::Create => (dev_null, file(&header.new_path)),
+        FileOp::Delete => (file(&header.old_path), dev_null),
+        _ => (file(&header.old_path), file(&header.new_path)),
+    }
+}
+
+pub(crate) fn header_line_content<'a, I: Span<'a>>(input: I) -> IResult<I, File<'a>> {
     let (input, filename) = filename(input)?;
     let mut parser = opt(preceded(char('\t'), file_metadata));
     let (input, after) = parser.parse(input)?;
@@ -152,30 +1192,116 @@ fn header_line_content(input: Input<'_>) -> IResult<Input<'_>, File> {
     ))
 }
 
-// Hunks of the file differences
-fn chunks(input: Input<'_>) -> IResult<Input<'_>, Vec<Hunk>> {
+// Hunks of the file differences, each with whether the old and the new file end in a newline
+// character according to it
+type Chunk<'a, T> = (Hunk<'a, T>, (bool, bool));
+
+fn chunks<'a, I: Span<'a>>(input: I) -> IResult<I, Vec<Chunk<'a, I::Text>>> {
     let mut parser = many1(chunk);
     parser.parse(input)
 }
 
-fn chunk(input: Input<'_>) -> IResult<Input<'_>, Hunk> {
-    let (input, ranges) = chunk_header(input)?;
-    let mut parser = many1(chunk_line);
-    let (input, lines) = parser.parse(input)?;
+// Parse the hunks of a file, skipping the malformed ones and any text between the hunks
+fn lenient_chunks<'a, I: Span<'a>>(
+    mut input: I,
+    options: &ParseOptions,
+    warnings: &mut Warnings<I>,
+) -> IResult<I, Vec<Chunk<'a, I::Text>>> {
+    let mut chunks = Vec::new();
+    loop {
+        let skipping = match chunk(input) {
+            Ok((rest, chunk)) if options.recount || has_line_counts(&chunk.0) => {
+                chunks.push(chunk);
+                input = rest;
+                false
+            }
+            Ok((rest, _)) => {
+                let error = SpanError::failure(rest, Expected::LineCount);
+                warnings.push((unwrap_error(error), Skipped::Hunk));
+                input = rest;
+                true
+            }
+            Err(error) if input.starts_with("@@") => {
+                warnings.push((unwrap_error(error), Skipped::Hunk));
+                (input, _) = consume_content_line(input)?;
+                true
+            }
+            Err(error) if chunks.is_empty() => return Err(error),
+            Err(_) => false,
+        };
+        // Text up to the next hunk belongs to this file, anything else is before the next file
+        let mut parser = many0(preceded(
+            not(alt((map(tag("@@ "), |_| ()), patch_start))),
+            consume_content_line,
+        ));
+        let (rest, _) = parser.parse(input)?;
+        if !rest.starts_with("@@ ") {
+            break;
+        }
+        if !skipping && rest.input_len() < input.input_len() {
+            warnings.push((
+                unwrap_error(SpanError::failure(input, Expected::HunkHeader)),
+                Skipped::Text,
+            ));
+        }
+        input = rest;
+    }
+    Ok((input, chunks))
+}
+
+// Whether a hunk has as many lines as its ranges say
+fn has_line_counts<T: ?Sized + ToOwned>(hunk: &Hunk<'_, T>) -> bool {
+    hunk.line_counts() == (hunk.old_range.count, hunk.new_range.count)
+}
+
+fn unwrap_error<I>(error: nom::Err<SpanError<I>>) -> SpanError<I> {
+    match error {
+        nom::Err::Error(error) | nom::Err::Failure(error) => error,
+        nom::Err::Incomplete(_) => unreachable!("bug: parser should not return incomplete"),
+    }
+}
+
+fn chunk<'a, I: Span<'a>>(input: I) -> IResult<I, Chunk<'a, I::Text>> {
+    let (input, ranges) = expect(Expected::HunkHeader, chunk_header).parse(input)?;
+    let mut parser = expect(
+        Expected::HunkLine,
+        many1((chunk_line, no_newline_indicator)),
+    );
+    let (input, marked_lines) = parser.parse(input)?;
+
+    // A missing newline is marked after the last line of the side it belongs to, or after the
+    // last context line if both sides lack it
+    let (mut old_end_newline, mut new_end_newline) = (true, true);
+    let mut lines = Vec::with_capacity(marked_lines.len());
+    for (line, no_newline) in marked_lines {
+        if no_newline {
+            match line {
+                Line::Remove(_) => old_end_newline = false,
+                Line::Add(_) => new_end_newline = false,
+                Line::Context(_) => (old_end_newline, new_end_newline) = (false, false),
+            }
+        }
+        lines.push(line);
+    }
 
     let (old_range, new_range, range_hint) = ranges;
     Ok((
         input,
-        Hunk {
-            old_range,
-            new_range,
-            range_hint,
-            lines,
-        },
+        (
+            Hunk {
+                old_range,
+                new_range,
+                range_hint,
+                lines,
+            },
+            (old_end_newline, new_end_newline),
+        ),
     ))
 }
 
-fn chunk_header(input: Input<'_>) -> IResult<Input<'_>, (Range, Range, &'_ str)> {
+pub(crate) fn chunk_header<'a, I: Span<'a>>(
+    input: I,
+) -> IResult<I, (Range, Range, Cow<'a, I::Text>)> {
     let (input, _) = tag("@@ -")(input)?;
     let (input, old_range) = range(input)?;
     let (input, _) = tag(" +")(input)?;
@@ -185,10 +1311,13 @@ fn chunk_header(input: Input<'_>) -> IResult<Input<'_>, (Range, Range, &'_ str)>
     // Save hint provided after @@ (git sometimes adds this)
     let (input, range_hint) = not_line_ending(input)?;
     let (input, _) = line_ending(input)?;
-    Ok((input, (old_range, new_range, &range_hint)))
+    Ok((
+        input,
+        (old_range, new_range, Cow::Borrowed(range_hint.text())),
+    ))
 }
 
-fn range(input: Input<'_>) -> IResult<Input<'_>, Range> {
+pub(crate) fn range<'a, I: Span<'a>>(input: I) -> IResult<I, Range> {
     let (input, start) = u64_digit(input)?;
     let mut parser = opt(preceded(char(','), u64_digit));
     let (input, count) = parser.parse(input)?;
@@ -196,10 +1325,10 @@ fn range(input: Input<'_>) -> IResult<Input<'_>, Range> {
     Ok((input, Range { start, count }))
 }
 
-fn u64_digit(input: Input<'_>) -> IResult<Input<'_>, u64> {
-    let (input, digits) = digit1(input)?;
-    let num = digits.fragment().parse::<u64>().unwrap();
-    Ok((input, num))
+pub(crate) fn u64_digit<'a, I: Span<'a>>(input: I) -> IResult<I, u64> {
+    // Numbers too large for a `u64` are an error rather than a panic
+    let mut parser = map_res(digit1, |digits: I| digits.to_str().parse::<u64>());
+    parser.parse(input)
 }
 
 // Looks for lines starting with + or - or space, but not +++ or ---. Not a foolproof check.
@@ -230,7 +1359,7 @@ fn u64_digit(input: Input<'_>) -> IResult<Input<'_>, u64> {
 //FIXME: Use the ranges in the chunk header to figure out how many chunk lines to parse. Will need
 // to figure out how to count in nom more robustly than many1!(). Maybe using switch!()?
 //FIXME: The test_parse_triple_plus_minus_hack test will no longer panic when this is fixed.
-fn chunk_line(input: Input<'_>) -> IResult<Input<'_>, Line> {
+fn chunk_line<'a, I: Span<'a>>(input: I) -> IResult<I, Line<'a, I::Text>> {
     let mut parser = alt((
         map(
             preceded((char('+'), not(tag("++ "))), consume_content_line),
@@ -246,7 +1375,7 @@ fn chunk_line(input: Input<'_>) -> IResult<Input<'_>, Line> {
 }
 
 // Trailing newline indicator
-fn no_newline_indicator(input: Input<'_>) -> IResult<Input<'_>, bool> {
+pub(crate) fn no_newline_indicator<'a, I: Span<'a>>(input: I) -> IResult<I, bool> {
     let mut parser = map(
         opt(terminated(
             tag("\\ No newline at end of file"),
@@ -257,51 +1386,79 @@ fn no_newline_indicator(input: Input<'_>) -> IResult<Input<'_>, bool> {
     parser.parse(input)
 }
 
-fn filename(input: Input<'_>) -> IResult<Input<'_>, Cow<str>> {
+pub(crate) fn filename<'a, I: Span<'a>>(input: I) -> IResult<I, Cow<'a, str>> {
     let mut parser = alt((quoted, bare));
     parser.parse(input)
 }
 
-fn file_metadata(input: Input<'_>) -> IResult<Input<'_>, Cow<str>> {
-    let mut parser = alt((
-        quoted,
-        map(not_line_ending, |data: Input<'_>| {
-            Cow::Borrowed(*data.fragment())
-        }),
-    ));
+fn file_metadata<'a, I: Span<'a>>(input: I) -> IResult<I, Cow<'a, str>> {
+    let mut parser = alt((quoted, map(not_line_ending, |data: I| data.to_str())));
     parser.parse(input)
 }
 
-fn quoted(input: Input<'_>) -> IResult<Input<'_>, Cow<str>> {
+fn quoted<'a, I: Span<'a>>(input: I) -> IResult<I, Cow<'a, str>> {
     let mut parser = delimited(char('\"'), unescaped_str, char('\"'));
     parser.parse(input)
 }
 
-fn bare(input: Input<'_>) -> IResult<Input<'_>, Cow<str>> {
-    let mut parser = map(is_not("\t\r\n"), |data: Input<'_>| {
-        Cow::Borrowed(*data.fragment())
-    });
+fn bare<'a, I: Span<'a>>(input: I) -> IResult<I, Cow<'a, str>> {
+    let mut parser = map(
+        take_till1(|ch: I::Item| matches!(ch.as_char(), '\t' | '\r' | '\n')),
+        |data: I| data.to_str(),
+    );
     parser.parse(input)
 }
 
-fn unescaped_str(input: Input<'_>) -> IResult<Input<'_>, Cow<str>> {
-    let mut parser = many1(alt((unescaped_char, escaped_char)));
-    let (input, raw) = parser.parse(input)?;
-    Ok((input, raw.into_iter().collect::<Cow<str>>()))
+// The text of a quoted string, whose octal escapes are the bytes of its UTF-8 encoding, like
+// git writes the bytes of paths that aren't ASCII. Bytes that aren't valid UTF-8 are replaced.
+fn unescaped_str<'a, I: Span<'a>>(input: I) -> IResult<I, Cow<'a, str>> {
+    let mut parser = fold_many1(
+        alt((
+            map(unescaped_char, Unescaped::Char),
+            map(octal_escape, Unescaped::Byte),
+            map(escaped_char, Unescaped::Char),
+        )),
+        Vec::new,
+        |mut bytes, unescaped| {
+            match unescaped {
+                Unescaped::Char(ch) => {
+                    bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes())
+                }
+                Unescaped::Byte(byte) => bytes.push(byte),
+            }
+            bytes
+        },
+    );
+    let (input, bytes) = parser.parse(input)?;
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(error) => String::from_utf8_lossy(error.as_bytes()).into_owned(),
+    };
+    Ok((input, Cow::Owned(text)))
+}
+
+enum Unescaped {
+    Char(char),
+    Byte(u8),
 }
 
 // Parses an unescaped character
-fn unescaped_char(input: Input<'_>) -> IResult<Input<'_>, char> {
-    none_of("\0\n\r\t\\\"")(input)
+fn unescaped_char<'a, I: Span<'a>>(input: I) -> IResult<I, char> {
+    satisfy(|ch| !"\0\n\r\t\\\"".contains(ch)).parse(input)
 }
 
 // Parses an escaped character and returns its unescaped equivalent
-fn escaped_char(input: Input<'_>) -> IResult<Input<'_>, char> {
-    let mut parser = map(preceded(char('\\'), one_of(r#"0nrt"\"#)), |ch| match ch {
+fn escaped_char<'a, I: Span<'a>>(input: I) -> IResult<I, char> {
+    let escapable = satisfy(|ch| r#"0abtnvfr"\"#.contains(ch));
+    let mut parser = map(preceded(char('\\'), escapable), |ch| match ch {
         '0' => '\0',
+        'a' => '\x07',
+        'b' => '\x08',
+        't' => '\t',
         'n' => '\n',
+        'v' => '\x0b',
+        'f' => '\x0c',
         'r' => '\r',
-        't' => '\t',
         '"' => '"',
         '\\' => '\\',
         _ => unreachable!(),
@@ -309,13 +1466,25 @@ fn escaped_char(input: Input<'_>) -> IResult<Input<'_>, char> {
     parser.parse(input)
 }
 
+// Parses a byte escaped as three octal digits, like `\303`
+fn octal_escape<'a, I: Span<'a>>(input: I) -> IResult<I, u8> {
+    let digits = recognize((
+        satisfy(|ch| ('0'..='3').contains(&ch)),
+        count(satisfy(|ch| ('0'..='7').contains(&ch)), 2),
+    ));
+    let mut parser = map_res(preceded(char('\\'), digits), |digits: I| {
+        u8::from_str_radix(&digits.to_str(), 8)
+    });
+    parser.parse(input)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use pretty_assertions::assert_eq;
 
-    type ParseResult<'a, T> = Result<T, nom::Err<nom::error::Error<Input<'a>>>>;
+    type ParseResult<'a, T> = Result<T, nom::Err<SpanError<Input<'a>>>>;
 
     // Using a macro instead of a function so that error messages cite the most helpful line number
     macro_rules! test_parser {
@@ -339,6 +1508,10 @@ mod tests {
     #[test]
     fn test_quoted() -> ParseResult<'static, ()> {
         test_parser!(quoted("\"file name\"") -> "file name".to_string());
+        test_parser!(quoted("\"a/file with \\303\\251.txt\"") -> "a/file with é.txt".to_string());
+        test_parser!(quoted("\"\\a\\b\\v\\f\\0\\012\"") -> "\x07\x08\x0b\x0c\0\n".to_string());
+        // Bytes that aren't valid UTF-8 are replaced
+        test_parser!(quoted("\"caf\\351\"") -> "caf\u{fffd}".to_string());
         Ok(())
     }
 
@@ -473,11 +1646,540 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_git_header() -> ParseResult<'static, ()> {
+        let sample = "\
+diff --git a/old name.rs b/new name.rs
+old mode 100644
+new mode 100755
+similarity index 90%
+rename from old name.rs
+rename to new name.rs
+index 4e1ba4d..09c3e9e
+";
+        test_parser!(git_header(sample) -> (
+            GitHeader {
+                old_path: "a/old name.rs".into(),
+                new_path: "b/new name.rs".into(),
+                old_mode: Some(0o100644),
+                new_mode: Some(0o100755),
+                similarity: Some(90),
+                dissimilarity: None,
+                index: Some(GitIndex {
+                    old_id: "4e1ba4d".into(),
+                    new_id: "09c3e9e".into(),
+                    mode: None,
+                }),
+            },
+            FileOp::Rename {
+                from: "old name.rs".into(),
+                to: "new name.rs".into(),
+            },
+        ));
+
+        let sample2 = "\
+diff --git a/gone b/gone
+deleted file mode 100644
+--- a/gone\n";
+        test_parser!(git_header(sample2) -> @(
+            "--- a/gone\n",
+            (
+                GitHeader {
+                    old_path: "a/gone".into(),
+                    new_path: "b/gone".into(),
+                    old_mode: Some(0o100644),
+                    new_mode: None,
+                    similarity: None,
+                    dissimilarity: None,
+                    index: None,
+                },
+                FileOp::Delete,
+            ),
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_property_changes() -> ParseResult<'static, ()> {
+        let sample = "\
+Property changes on: trunk/run.sh
+___________________________________________________________________
+Added: svn:executable
+## -0,0 +1 ##
++*
+\\ No newline at end of property
+Modified: svn:ignore
+## -1 +1,2 ##
+-*.o
++*.o
++target
+Deleted: svn:eol-style
+   - native
+";
+        test_parser!(property_changes(sample) -> (
+            "trunk/run.sh".into(),
+            vec![
+                PropertyChange {
+                    name: "svn:executable".into(),
+                    old: None,
+                    new: Some("*".into()),
+                },
+                PropertyChange {
+                    name: "svn:ignore".into(),
+                    old: Some("*.o\n".into()),
+                    new: Some("*.o\ntarget\n".into()),
+                },
+                PropertyChange {
+                    name: "svn:eol-style".into(),
+                    old: Some("native".into()),
+                    new: None,
+                },
+            ],
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let sample = "\
+--- a.txt
++++ a.txt
+@@ -1 +1 @@
+-a
++A
+--- b.txt
++++ b.txt
+@@ -1 +1 @x
+";
+        let error = parse_multiple_patches(sample).unwrap_err();
+        assert_eq!(error.line, 8);
+        assert_eq!(error.column, 9);
+        assert_eq!(error.offset, 66);
+        assert_eq!(error.line_text, "@@ -1 +1 @x");
+        assert_eq!(error.expected, Some(Expected::HunkHeader));
+        assert_eq!(
+            error.to_string(),
+            "Line 8, column 9: expected a hunk header, found ' @x'"
+        );
+
+        let error = parse_single_patch("--- a.txt\nb.txt\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+        assert_eq!(error.expected, Some(Expected::FileHeader));
+
+        let error = parse_single_patch(&sample[..58]).unwrap_err();
+        assert_eq!(error.line, 6);
+        assert_eq!(error.expected, Some(Expected::EndOfInput));
+
+        // Text after the last patch is ignored
+        let patches = parse_multiple_patches("--- a\n+++ a\n@@ -1 +1 @@\n-a\n+A\n~~~\n").unwrap();
+        assert_eq!(patches.len(), 1);
+
+        // Numbers too large for a `u64` are errors, in hunk headers and in binary data
+        let sample = "--- a\n+++ a\n@@ -1,99999999999999999999 +1 @@\n-a\n+A\n";
+        let error = parse_single_patch(sample).unwrap_err();
+        assert_eq!(
+            (error.line, error.expected),
+            (3, Some(Expected::HunkHeader))
+        );
+        let sample = "\
+diff --git a/x b/x
+GIT binary patch
+literal 99999999999999999999
+HcmV?d00001
+
+literal 0
+HcmV?d00001
+
+";
+        let error = parse_single_patch(sample).unwrap_err();
+        assert_eq!(
+            (error.line, error.expected),
+            (3, Some(Expected::BinaryData))
+        );
+    }
+
+    #[test]
+    fn test_lenient_parsing() {
+        let sample = "\
+--- a.txt
++++ a.txt
+@@ -1,2 +1,2 @@
+-a
++A
+ b
+@@ -5 +5 @x
+-e
++E
+@@ -7,2 +7,2 @@
+ g
+~h
+@@ -9 +9 @@
+-i
++I
+garbage
+@@ -20 +20 @@
+-t
++T
+--- b.txt
+~~~
+--- c.txt
++++ c.txt
+@@ -1 +1 @@
+-c
++C
+";
+        let options = ParseOptions {
+            lenient: true,
+            ..ParseOptions::default()
+        };
+        let (patches, warnings) = parse_multiple_patches_with_options(sample, &options).unwrap();
+        assert_eq!(patches.len(), 2);
+        let ranges: Vec<_> = patches[0]
+            .hunks
+            .iter()
+            .map(|hunk| hunk.old_range.start)
+            .collect();
+        assert_eq!(ranges, [1, 9, 20]);
+        assert_eq!(patches[1].old.path, "c.txt");
+
+        let found: Vec<_> = warnings
+            .iter()
+            .map(|warning| (warning.error.line, warning.error.expected, warning.skipped))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (7, Some(Expected::HunkHeader), Skipped::Hunk),
+                (12, Some(Expected::LineCount), Skipped::Hunk),
+                (16, Some(Expected::HunkHeader), Skipped::Text),
+                (21, Some(Expected::FileHeader), Skipped::Patch),
+            ]
+        );
+        assert_eq!(
+            warnings[2].to_string(),
+            "Line 16, column 1: expected a hunk header, found 'garbage', skipped the text up to the \
+             next hunk"
+        );
+
+        // Without the option, the patch without a `+++` line is an error
+        let error = parse_multiple_patches(sample).unwrap_err();
+        assert_eq!(error.line, 21);
+
+        let error = parse_multiple_patches_with_options("~~~\n", &options).unwrap_err();
+        assert_eq!(error.expected, Some(Expected::FileHeader));
+    }
+
+    #[test]
+    fn test_recount_option() {
+        let sample = "\
+--- a.txt
++++ a.txt
+@@ -1,3 +1,3 @@
+-a
++A
+ b
+@@ -7,2 +7,2 @@
+ g
+~h
+";
+        let options = ParseOptions {
+            recount: true,
+            ..ParseOptions::default()
+        };
+        let (patches, warnings) = parse_multiple_patches_with_options(sample, &options).unwrap();
+        let ranges: Vec<_> = patches[0]
+            .hunks
+            .iter()
+            .map(|hunk| (hunk.old_range.clone(), hunk.new_range.clone()))
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                (Range { start: 1, count: 2 }, Range { start: 1, count: 2 }),
+                (Range { start: 7, count: 1 }, Range { start: 7, count: 1 }),
+            ]
+        );
+        assert!(warnings.is_empty());
+
+        // Lenient parsing no longer skips the hunks with wrong counts
+        let options = ParseOptions {
+            lenient: true,
+            recount: true,
+            ..ParseOptions::default()
+        };
+        let (patches, warnings) = parse_multiple_patches_with_options(sample, &options).unwrap();
+        assert_eq!(patches[0].hunks.len(), 2);
+        assert_eq!(warnings.len(), 0);
+    }
+
+    #[test]
+    fn test_lossless_option() {
+        let sample = "\
+Commit message\r
+--- a.txt\t2024-06-01 10:00:00.000000000 +0200\r
++++ a.txt\t2024-06-01 10:00:01 +0200\r
+@@ -1 +1 @@ fn main\r
+-a\r
++A\r
+\r
+\r
+--- b.txt
++++ b.txt
+@@ -1,2 +1,2 @@
+-b
++B
+ c
+\\ No newline at end of file
+-- \n2.45.0
+";
+        let options = ParseOptions {
+            lossless: true,
+            ..ParseOptions::default()
+        };
+        let (patches, _) = parse_multiple_patches_with_options(sample, &options).unwrap();
+        assert_eq!(patches.len(), 2);
+        let written: String = patches.iter().map(|patch| patch.to_string()).collect();
+        assert_eq!(written, sample);
+
+        // Patches that are changed are written from their fields, and the others stay the same
+        let mut patches = patches;
+        patches[0].hunks[0].lines[1] = Line::Add("Ä".into());
+        assert_eq!(
+            patches[0].to_string(),
+            "Commit message\r\n\
+             --- a.txt\t2024-06-01 10:00:00 +0200\n\
+             +++ a.txt\t2024-06-01 10:00:01 +0200\n\
+             @@ -1,1 +1,1 @@ fn main\n\
+             -a\n\
+             +Ä"
+        );
+        assert!(sample.ends_with(&patches[1].to_string()));
+
+        // Without the option, nothing is kept
+        let patches = parse_multiple_patches(sample).unwrap();
+        assert!(patches.iter().all(|patch| patch.raw.is_none()));
+        assert!(
+            patches[0]
+                .to_string()
+                .starts_with("Commit message\r\n--- a.txt\t")
+        );
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let sample = "\
+--- a.txt
++++ a.txt
+@@ -1 +1 @@
+-a
++A
+@@ -3 +3 @@
+-c
++C
+--- b.txt
++++ b.txt
+@@ -1 +1 @@
+-a long line
++b
+";
+        let parse = |options: ParseOptions| {
+            parse_multiple_patches_with_options(sample, &options).map(|(patches, _)| patches)
+        };
+        let limit = |options| {
+            let error = parse(options).unwrap_err();
+            (error.limit, error.line)
+        };
+        let options = ParseOptions {
+            max_size: Some(sample.len()),
+            max_hunks: Some(2),
+            max_line_length: Some(12),
+            max_files: Some(2),
+            ..ParseOptions::default()
+        };
+        assert_eq!(parse(options.clone()).unwrap().len(), 2);
+
+        let size = ParseOptions {
+            max_size: Some(sample.len() - 1),
+            ..options.clone()
+        };
+        assert_eq!(limit(size), (Some(ParseLimit::Size), 1));
+        let hunks = ParseOptions {
+            max_hunks: Some(1),
+            ..options.clone()
+        };
+        assert_eq!(limit(hunks), (Some(ParseLimit::Hunks), 1));
+        let line_length = ParseOptions {
+            max_line_length: Some(11),
+            ..options.clone()
+        };
+        assert_eq!(limit(line_length), (Some(ParseLimit::LineLength), 12));
+        // The limits are kept even by lenient parsing
+        let files = ParseOptions {
+            max_files: Some(1),
+            lenient: true,
+            ..options
+        };
+        let error = parse(files).unwrap_err();
+        assert_eq!((error.limit, error.line), (Some(ParseLimit::Files), 9));
+        assert_eq!(
+            error.to_string(),
+            "Line 9, column 1: the input has more patches than the maximum"
+        );
+    }
+
+    #[cfg(feature = "miette")]
+    #[test]
+    fn test_parse_error_diagnostic() {
+        use miette::Diagnostic;
+
+        let error = parse_single_patch("--- a\n+++ a\n  @@ -1 +1 @@\n").unwrap_err();
+        let labels: Vec<_> = error.labels().unwrap().collect();
+        assert_eq!(labels[0].offset(), 0);
+        assert_eq!(labels[0].len(), "  @@ -1 +1 @@".len());
+        assert_eq!(labels[0].label(), Some("expected a hunk header"));
+    }
+
+    #[test]
+    fn test_binary_files_differ() {
+        let sample = "\
+diff --git a/logo.png b/logo.png
+index 5e8d3f1..9a0c2b7 100644
+Binary files a/logo.png and b/logo.png differ
+diff --git a/icon.ico b/icon.ico
+new file mode 100644
+index 0000000..c5b4e2a
+Binary files /dev/null and b/icon.ico differ
+diff -r old/data.bin new/data.bin
+Binary files old/data.bin and new/data.bin differ
+--- a/notes.txt
++++ b/notes.txt
+@@ -1 +1 @@
+-draft
++final
+";
+        let patches = parse_multiple_patches(sample).unwrap();
+        assert_eq!(patches.len(), 4);
+        assert!(
+            patches[..3]
+                .iter()
+                .all(|patch| patch.opaque_binary && patch.hunks.is_empty())
+        );
+        assert!(!patches[3].opaque_binary);
+        assert_eq!(patches[1].operation, FileOp::Create);
+        assert_eq!(patches[1].old.path, "/dev/null");
+        assert!(patches[2].git.is_none());
+        assert_eq!(patches[2].old.path, "old/data.bin");
+        assert_eq!(patches[2].new.path, "new/data.bin");
+
+        assert_eq!(
+            patches[2].to_string(),
+            "diff -r old/data.bin new/data.bin\nBinary files old/data.bin and new/data.bin differ"
+        );
+        assert_eq!(
+            patches[1].to_string(),
+            "diff --git a/icon.ico b/icon.ico\nnew file mode 100644\nindex 0000000..c5b4e2a\n\
+             Binary files /dev/null and b/icon.ico differ"
+        );
+    }
+
+    #[test]
+    fn test_svn_patch() {
+        let sample = "\
+Index: trunk/run.sh
+===================================================================
+--- trunk/run.sh\t(revision 12)
++++ trunk/run.sh\t(working copy)
+@@ -1 +1 @@
+-echo hello
++echo hello, world
+
+Property changes on: trunk/run.sh
+___________________________________________________________________
+Added: svn:executable
+## -0,0 +1 ##
++*
+\\ No newline at end of property
+Index: trunk/logo.png
+===================================================================
+Cannot display: file marked as a binary type.
+svn:mime-type = application/octet-stream
+";
+        let patches = parse_multiple_patches(sample).unwrap();
+        assert_eq!(patches.len(), 2);
+        let svn = patches[0].svn.as_ref().unwrap();
+        assert_eq!(svn.index.as_deref(), Some("trunk/run.sh"));
+        assert_eq!(svn.properties[0].name, "svn:executable");
+        assert_eq!(patches[0].hunks.len(), 1);
+        assert_eq!(patches[1].new.path, "trunk/logo.png");
+        assert!(patches[1].hunks.is_empty());
+
+        // The property changes are written back the way Subversion writes them
+        let text = format!("{}\n", patches[0]);
+        let properties = sample.find("\nProperty changes").unwrap();
+        let end = sample.find("Index: trunk/logo").unwrap();
+        assert!(text.starts_with("Index: trunk/run.sh\n====="));
+        assert!(text.ends_with(&sample[properties..end]));
+        assert_eq!(parse_multiple_patches(&text).unwrap()[0], patches[0]);
+    }
+
+    #[test]
+    fn test_split_git_paths() {
+        for (line, old, new) in [
+            ("a/x b/x", "a/x", "b/x"),
+            (
+                "a/with b/space b/with b/space",
+                "a/with b/space",
+                "b/with b/space",
+            ),
+            ("a/old b/new", "a/old", "b/new"),
+            ("a/one two b/three", "a/one two", "b/three"),
+        ] {
+            assert_eq!(split_paths(line.into(), " "), (old.into(), new.into()));
+        }
+    }
+
+    #[test]
+    fn test_git_index() -> ParseResult<'static, ()> {
+        test_parser!(git_index("3b18e51..a042389 100644\n") -> @("\n", GitIndex {
+            old_id: "3b18e51".into(),
+            new_id: "a042389".into(),
+            mode: Some(0o100644),
+        }));
+        test_parser!(git_index("0000000..c5b4e2a\n") -> @("\n", GitIndex {
+            old_id: "0000000".into(),
+            new_id: "c5b4e2a".into(),
+            mode: None,
+        }));
+        // Combined diffs have an old id for every parent
+        assert!(git_index(Input::new("fabadb8,cc95eb0..4866510\n")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_files() -> ParseResult<'static, ()> {
+        test_parser!(binary_files("Binary files a/x and b/x differ\n") -> ("a/x".into(), "b/x".into()));
+        test_parser!(binary_files("Binary files a/x and y and b/x and y differ") -> (
+            "a/x and y".into(),
+            "b/x and y".into(),
+        ));
+        test_parser!(binary_files("Binary files /dev/null and b/new differ\n") -> (
+            "/dev/null".into(),
+            "b/new".into(),
+        ));
+        test_parser!(binary_files("Binary files \"a/x y\" and \"b/x y\" differ\n") -> (
+            "a/x y".into(),
+            "b/x y".into(),
+        ));
+        Ok(())
+    }
+
     #[test]
     fn test_range() -> ParseResult<'static, ()> {
         test_parser!(range("1,7") -> Range { start: 1, count: 7 });
 
         test_parser!(range("2") -> Range { start: 2, count: 1 });
+
+        assert!(range(Input::new("99999999999999999999,1")).is_err());
         Ok(())
     }
 
@@ -486,7 +2188,7 @@ mod tests {
         test_parser!(chunk_header("@@ -1,7 +1,6 @@ foo bar\n") -> (
             Range { start: 1, count: 7 },
             Range { start: 1, count: 6 },
-            " foo bar",
+            " foo bar".into(),
         ));
         Ok(())
     }
@@ -507,20 +2209,20 @@ mod tests {
         let expected = Hunk {
             old_range: Range { start: 1, count: 7 },
             new_range: Range { start: 1, count: 6 },
-            range_hint: "",
+            range_hint: "".into(),
             lines: vec![
-                Line::Remove("The Way that can be told of is not the eternal Way;"),
-                Line::Remove("The name that can be named is not the eternal name."),
-                Line::Context("The Nameless is the origin of Heaven and Earth;"),
-                Line::Remove("The Named is the mother of all things."),
-                Line::Add("The named is the mother of all things."),
-                Line::Add(""),
-                Line::Context("Therefore let there always be non-being,"),
-                Line::Context("  so we may see their subtlety,"),
-                Line::Context("And let there always be being,"),
+                Line::Remove("The Way that can be told of is not the eternal Way;".into()),
+                Line::Remove("The name that can be named is not the eternal name.".into()),
+                Line::Context("The Nameless is the origin of Heaven and Earth;".into()),
+                Line::Remove("The Named is the mother of all things.".into()),
+                Line::Add("The named is the mother of all things.".into()),
+                Line::Add("".into()),
+                Line::Context("Therefore let there always be non-being,".into()),
+                Line::Context("  so we may see their subtlety,".into()),
+                Line::Context("And let there always be being,".into()),
             ],
         };
-        test_parser!(chunk(sample) -> expected);
+        test_parser!(chunk(sample) -> (expected, (true, true)));
         Ok(())
     }
 
@@ -561,40 +2263,49 @@ mod tests {
                     DateTime::parse_from_rfc3339("2002-02-21T23:30:50.442260588-08:00").unwrap(),
                 )),
             },
+            operation: FileOp::Modify,
+            git: None,
+            svn: None,
+            binary: None,
+            opaque_binary: false,
+            preamble: None,
+            raw: None,
             hunks: vec![
                 Hunk {
                     old_range: Range { start: 1, count: 7 },
                     new_range: Range { start: 1, count: 6 },
-                    range_hint: "",
+                    range_hint: "".into(),
                     lines: vec![
-                        Line::Remove("The Way that can be told of is not the eternal Way;"),
-                        Line::Remove("The name that can be named is not the eternal name."),
-                        Line::Context("The Nameless is the origin of Heaven and Earth;"),
-                        Line::Remove("The Named is the mother of all things."),
-                        Line::Add("The named is the mother of all things."),
-                        Line::Add(""),
-                        Line::Context("Therefore let there always be non-being,"),
-                        Line::Context("  so we may see their subtlety,"),
-                        Line::Context("And let there always be being,"),
+                        Line::Remove("The Way that can be told of is not the eternal Way;".into()),
+                        Line::Remove("The name that can be named is not the eternal name.".into()),
+                        Line::Context("The Nameless is the origin of Heaven and Earth;".into()),
+                        Line::Remove("The Named is the mother of all things.".into()),
+                        Line::Add("The named is the mother of all things.".into()),
+                        Line::Add("".into()),
+                        Line::Context("Therefore let there always be non-being,".into()),
+                        Line::Context("  so we may see their subtlety,".into()),
+                        Line::Context("And let there always be being,".into()),
                     ],
                 },
                 Hunk {
                     old_range: Range { start: 9, count: 3 },
                     new_range: Range { start: 8, count: 6 },
-                    range_hint: "",
+                    range_hint: "".into(),
                     lines: vec![
-                        Line::Context("The two are the same,"),
-                        Line::Context("But after they are produced,"),
-                        Line::Context("  they have different names."),
-                        Line::Add("They both may be called deep and profound."),
-                        Line::Add("Deeper and more profound,"),
-                        Line::Add("The door of all subtleties!"),
+                        Line::Context("The two are the same,".into()),
+                        Line::Context("But after they are produced,".into()),
+                        Line::Context("  they have different names.".into()),
+                        Line::Add("They both may be called deep and profound.".into()),
+                        Line::Add("Deeper and more profound,".into()),
+                        Line::Add("The door of all subtleties!".into()),
                     ],
                 },
             ],
-            end_newline: true,
+            old_end_newline: true,
+            new_end_newline: true,
         };
 
+        let patch = |input| patch(input, &ParseOptions::default(), &mut Vec::new());
         test_parser!(patch(sample) -> expected);
 
         assert_eq!(format!("{}\n", expected), sample);
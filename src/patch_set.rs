@@ -0,0 +1,1240 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::applier::{
+    ApplyError, ApplyOptions, apply_partial, apply_with_options, check_index, reject_patch,
+};
+use crate::ast::{DEV_NULL, File, FileOp, Patch};
+use crate::glob::glob_match;
+use crate::parser::{
+    ParseError, ParseOptions, ParseWarning, parse_multiple_patches,
+    parse_multiple_patches_with_options,
+};
+
+/// The patches made of the hunks that were rejected, with the path of the file they were meant for
+type Rejects<'a> = Vec<(PathBuf, Patch<'a>)>;
+
+/// A collection of patches, typically one for every file touched by a change
+///
+/// Patches are kept in the order they were parsed. Several patches may refer to the same file,
+/// in which case they are applied one after the other.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PatchSet<'a> {
+    /// The patches in this set, in order
+    pub patches: Vec<Patch<'a>>,
+}
+
+/// How a file was changed by applying a [`PatchSet`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum FileChange {
+    /// The file didn't exist before and now has the given content
+    Created(String),
+    /// The file existed before and now has the given content
+    Modified(String),
+    /// The file existed before and was removed
+    Deleted,
+}
+
+/// Error that can occur while applying a [`PatchSet`]
+#[derive(Debug)]
+pub enum PatchSetError {
+    /// A patch refers to a path that is absolute or leads outside of the current directory
+    InvalidPath {
+        /// The offending path, as given by the patch
+        path: String,
+    },
+    /// None of the files named by a patch could be found
+    FileNotFound {
+        /// The path of the old file given by the patch
+        old: PathBuf,
+        /// The path of the new file given by the patch
+        new: PathBuf,
+    },
+    /// A patch creates a file that already exists
+    FileExists {
+        /// The path of the file
+        path: PathBuf,
+    },
+    /// A patch could not be applied to its file
+    Apply {
+        /// The path of the file the patch was applied to
+        path: PathBuf,
+        /// The reason the patch could not be applied
+        error: ApplyError,
+    },
+}
+
+impl fmt::Display for PatchSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchSetError::InvalidPath { path } => write!(f, "Refusing to patch '{}'", path),
+            PatchSetError::FileNotFound { old, new } => write!(
+                f,
+                "Neither '{}' nor '{}' could be found",
+                old.display(),
+                new.display()
+            ),
+            PatchSetError::FileExists { path } => {
+                write!(f, "File '{}' already exists", path.display())
+            }
+            PatchSetError::Apply { path, error } => {
+                write!(f, "Failed to patch '{}': {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl Error for PatchSetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PatchSetError::Apply { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> PatchSet<'a> {
+    /// Parse all of the patches in the given string. See [`Patch::from_multiple`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), patch::ParseError<'static>> {
+    /// # use patch::PatchSet;
+    /// let sample = "\
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -1 +1 @@
+    /// -a
+    /// +A
+    /// --- b.txt
+    /// +++ b.txt
+    /// @@ -1 +1 @@
+    /// -b
+    /// +B
+    /// ";
+    ///
+    /// let set = PatchSet::parse(sample)?;
+    /// assert_eq!(set.patches.len(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn parse(s: &'a str) -> Result<Self, ParseError<'a>> {
+        parse_multiple_patches(s).map(|patches| PatchSet { patches })
+    }
+
+    /// Parse all of the patches in the given string as chosen by [`ParseOptions`], returning the
+    /// warnings of lenient parsing along with them. See [`Patch::from_multiple_with_options`].
+    pub fn parse_with_options(
+        s: &'a str,
+        options: &ParseOptions,
+    ) -> Result<(Self, Vec<ParseWarning<'a>>), ParseError<'a>> {
+        let (patches, warnings) = parse_multiple_patches_with_options(s, options)?;
+        Ok((PatchSet { patches }, warnings))
+    }
+
+    /// The text before the first patch of the set, like the message of the commit it was made
+    /// from. See [`Patch::preamble`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::PatchSet;
+    ///
+    /// let sample = "\
+    /// Capitalize the letters
+    ///
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -1,1 +1,1 @@
+    /// -a
+    /// +A
+    /// ";
+    ///
+    /// let set = PatchSet::parse(sample).unwrap();
+    /// assert_eq!(set.preamble(), Some("Capitalize the letters\n\n"));
+    /// assert_eq!(format!("{}\n", set), sample);
+    /// ```
+    pub fn preamble(&self) -> Option<&str> {
+        self.patches.first()?.preamble.as_deref()
+    }
+
+    /// Keep only the patches whose path satisfies the given predicate, like `filterdiff`
+    ///
+    /// The path of a patch is the path of its new file as written in the patch, or the path of
+    /// its old file if it deletes the file. The patches that are kept are unchanged and stay in
+    /// the same order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::PatchSet;
+    ///
+    /// let sample = "\
+    /// --- a/src/lib.rs
+    /// +++ b/src/lib.rs
+    /// @@ -1 +1 @@
+    /// -a
+    /// +A
+    /// --- a/README.md
+    /// +++ b/README.md
+    /// @@ -1 +1 @@
+    /// -b
+    /// +B
+    /// ";
+    ///
+    /// let set = PatchSet::parse(sample).unwrap();
+    /// let docs = set.filter(|path| path.ends_with(".md"));
+    /// assert_eq!(docs.patches.len(), 1);
+    /// assert_eq!(docs.patches[0].new.path, "b/README.md");
+    /// ```
+    pub fn filter(&self, mut keep: impl FnMut(&str) -> bool) -> PatchSet<'a> {
+        let patches = self.patches.iter().filter(|patch| keep(patch_path(patch)));
+        PatchSet {
+            patches: patches.cloned().collect(),
+        }
+    }
+
+    /// Keep only the patches whose path matches the given glob pattern
+    ///
+    /// In the pattern, `*` matches anything but `/`, `**` matches anything, `?` matches a single
+    /// character but `/` and `[...]` matches one of the characters between the brackets. The
+    /// pattern is matched against the path of every patch as described in [`PatchSet::filter`],
+    /// and also against that path without its first component, so `src/**/*.rs` matches both
+    /// `src/lib.rs` and the `b/src/lib.rs` of patches made by git.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::PatchSet;
+    ///
+    /// let sample = "\
+    /// --- a/src/lib.rs
+    /// +++ b/src/lib.rs
+    /// @@ -1 +1 @@
+    /// -a
+    /// +A
+    /// --- a/src/bin/main.rs
+    /// +++ b/src/bin/main.rs
+    /// @@ -1 +1 @@
+    /// -b
+    /// +B
+    /// --- a/README.md
+    /// +++ b/README.md
+    /// @@ -1 +1 @@
+    /// -c
+    /// +C
+    /// ";
+    ///
+    /// let set = PatchSet::parse(sample).unwrap();
+    /// assert_eq!(set.include("src/**/*.rs").patches.len(), 2);
+    /// assert_eq!(set.exclude("src/bin/*").patches.len(), 2);
+    /// ```
+    pub fn include(&self, pattern: &str) -> PatchSet<'a> {
+        self.filter(|path| path_matches(pattern, path))
+    }
+
+    /// Split the set into one set per file, like `splitdiff`
+    ///
+    /// Every set holds the patches of one file, in the order they appear in this one, and the
+    /// sets are in the order their files first appear. Patches are grouped by their path as
+    /// described in [`PatchSet::filter`], ignoring `a/` and `b/` prefixes. The patches are
+    /// unchanged, git headers included, so every set can be printed and applied on its own.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::PatchSet;
+    ///
+    /// let sample = "\
+    /// diff --git a/src/lib.rs b/src/lib.rs
+    /// index 1111111..2222222 100644
+    /// --- a/src/lib.rs
+    /// +++ b/src/lib.rs
+    /// @@ -1 +1 @@
+    /// -a
+    /// +A
+    /// diff --git a/README.md b/README.md
+    /// index 3333333..4444444 100644
+    /// --- a/README.md
+    /// +++ b/README.md
+    /// @@ -1 +1 @@
+    /// -b
+    /// +B
+    /// ";
+    ///
+    /// let set = PatchSet::parse(sample).unwrap();
+    /// let files = set.split();
+    /// assert_eq!(files.len(), 2);
+    /// assert!(files[1].to_string().starts_with("diff --git a/README.md b/README.md\n"));
+    /// ```
+    pub fn split(&self) -> Vec<PatchSet<'a>> {
+        let mut sets: Vec<(&str, PatchSet<'a>)> = Vec::new();
+        for patch in &self.patches {
+            let path = file_name(patch);
+            match sets.iter_mut().find(|(other, _)| *other == path) {
+                Some((_, set)) => set.patches.push(patch.clone()),
+                None => sets.push((
+                    path,
+                    PatchSet {
+                        patches: vec![patch.clone()],
+                    },
+                )),
+            }
+        }
+        sets.into_iter().map(|(_, set)| set).collect()
+    }
+
+    /// Keep only the patches whose path doesn't match the given glob pattern
+    ///
+    /// See [`PatchSet::include`] for the syntax of the pattern.
+    pub fn exclude(&self, pattern: &str) -> PatchSet<'a> {
+        self.filter(|path| !path_matches(pattern, path))
+    }
+
+    /// Apply every patch in the set to the given files, with the default [`ApplyOptions`]
+    ///
+    /// See [`PatchSet::apply_to_with_options`].
+    pub fn apply_to(
+        &self,
+        files: &HashMap<PathBuf, String>,
+    ) -> Result<HashMap<PathBuf, FileChange>, PatchSetError> {
+        self.apply_to_with_options(files, &ApplyOptions::default())
+    }
+
+    /// Apply every patch in the set to the given files, which map paths to their contents
+    ///
+    /// [`ApplyOptions::strip`] leading components are removed from the paths in the patches, and
+    /// the rest is normalized before looking it up, so `./src/lib.rs` refers to the same file as
+    /// `src/lib.rs`. Absolute paths and paths containing `..` are refused. See [`strip_path`].
+    ///
+    /// The file a patch applies to is found the same way GNU patch does it: a patch whose old
+    /// file is `/dev/null` creates its new file, a patch whose new file is `/dev/null` deletes its
+    /// old file, and any other patch modifies its old file or, if there is no such file, its new
+    /// file. See [`Patch::file_op`]. Patches that rename or copy a file write the patched content
+    /// to the new path, and renames remove the old path. Patches touching the same file are
+    /// applied in order, each one seeing the result of the previous one.
+    ///
+    /// The files themselves are not modified. Instead, every file that changed is returned with
+    /// the kind of change. The first patch that fails aborts the whole operation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use std::path::PathBuf;
+    ///
+    /// use patch::{FileChange, PatchSet};
+    ///
+    /// let sample = "\
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -1 +1 @@
+    /// -a
+    /// +A
+    /// --- /dev/null
+    /// +++ b.txt
+    /// @@ -0,0 +1 @@
+    /// +B
+    /// ";
+    ///
+    /// let files = HashMap::from([(PathBuf::from("a.txt"), "a\n".to_string())]);
+    /// let changes = PatchSet::parse(sample).unwrap().apply_to(&files).unwrap();
+    /// assert_eq!(changes[&PathBuf::from("a.txt")], FileChange::Modified("A\n".into()));
+    /// assert_eq!(changes[&PathBuf::from("b.txt")], FileChange::Created("B\n".into()));
+    /// ```
+    pub fn apply_to_with_options(
+        &self,
+        files: &HashMap<PathBuf, String>,
+        options: &ApplyOptions,
+    ) -> Result<HashMap<PathBuf, FileChange>, PatchSetError> {
+        self.apply_files(files, options, None)
+    }
+
+    /// Apply every patch in the set like [`PatchSet::apply_to_with_options`], leaving out the
+    /// hunks that can't be applied instead of failing
+    ///
+    /// Along with the changes, returns the patches made of the rejected hunks of every patch,
+    /// with the path of the file they were applied to. See [`reject_patch`].
+    pub(crate) fn apply_partially_to(
+        &self,
+        files: &HashMap<PathBuf, String>,
+        options: &ApplyOptions,
+    ) -> Result<(HashMap<PathBuf, FileChange>, Rejects<'a>), PatchSetError> {
+        let mut rejects = Vec::new();
+        let changes = self.apply_files(files, options, Some(&mut rejects))?;
+        Ok((changes, rejects))
+    }
+
+    // Rejected hunks are collected into `rejects` if given, and are errors otherwise
+    fn apply_files(
+        &self,
+        files: &HashMap<PathBuf, String>,
+        options: &ApplyOptions,
+        mut rejects: Option<&mut Rejects<'a>>,
+    ) -> Result<HashMap<PathBuf, FileChange>, PatchSetError> {
+        // The current content of every file touched so far, `None` once it has been deleted
+        let mut touched: HashMap<PathBuf, Option<String>> = HashMap::new();
+
+        for patch in &self.patches {
+            let exists = |path: &Path| match touched.get(path) {
+                Some(content) => content.is_some(),
+                None => files.contains_key(path),
+            };
+
+            let path = target_path(patch, options.strip, exists)?;
+            let content = current(&touched, files, &path);
+
+            let patched = match rejects.as_deref_mut() {
+                // Binary patches have no hunks to reject
+                Some(rejects) if !patch.is_binary() => {
+                    check_index(patch, content.as_bytes(), options).map_err(|error| {
+                        PatchSetError::Apply {
+                            path: path.clone(),
+                            error,
+                        }
+                    })?;
+                    let (patched, rejected) = apply_partial(patch, content, options);
+                    if !rejected.is_empty() {
+                        rejects.push((path.clone(), reject_patch(patch, &rejected)));
+                    }
+                    patched
+                }
+                _ => apply_with_options(patch, content, options).map_err(|error| {
+                    PatchSetError::Apply {
+                        path: path.clone(),
+                        error,
+                    }
+                })?,
+            };
+
+            record(&mut touched, files, patch, path, patched, options.strip)?;
+        }
+
+        Ok(file_changes(files, touched).collect())
+    }
+}
+
+// The current content of a file, from the files touched so far or the original files
+pub(crate) fn current<'f>(
+    touched: &'f HashMap<PathBuf, Option<String>>,
+    files: &'f HashMap<PathBuf, String>,
+    path: &Path,
+) -> &'f str {
+    match touched.get(path) {
+        Some(content) => content.as_deref().unwrap_or_default(),
+        None => files.get(path).map(String::as_str).unwrap_or_default(),
+    }
+}
+
+// Record the content `patch` produced from the file at `path` among the files touched so far
+pub(crate) fn record(
+    touched: &mut HashMap<PathBuf, Option<String>>,
+    files: &HashMap<PathBuf, String>,
+    patch: &Patch,
+    path: PathBuf,
+    patched: String,
+    strip: usize,
+) -> Result<(), PatchSetError> {
+    match patch.file_op() {
+        FileOp::Delete => {
+            touched.insert(path, None);
+        }
+        // The patched content goes to the new path, and a rename removes the old one
+        op @ (FileOp::Rename { .. } | FileOp::Copy { .. }) => {
+            let to = strip_path(&patch.new.path, strip)?;
+            if to != path {
+                let taken = match touched.get(&to) {
+                    Some(content) => content.is_some(),
+                    None => files.contains_key(&to),
+                };
+                if taken {
+                    return Err(PatchSetError::FileExists { path: to });
+                }
+                if matches!(op, FileOp::Rename { .. }) {
+                    touched.insert(path, None);
+                }
+            }
+            touched.insert(to, Some(patched));
+        }
+        _ => {
+            touched.insert(path, Some(patched));
+        }
+    }
+    Ok(())
+}
+
+// How the touched files were changed from the original ones
+pub(crate) fn file_changes(
+    files: &HashMap<PathBuf, String>,
+    touched: HashMap<PathBuf, Option<String>>,
+) -> impl Iterator<Item = (PathBuf, FileChange)> {
+    touched.into_iter().filter_map(|(path, content)| {
+        let change = match (files.contains_key(&path), content) {
+            (true, Some(content)) => FileChange::Modified(content),
+            (false, Some(content)) => FileChange::Created(content),
+            (true, None) => FileChange::Deleted,
+            (false, None) => return None,
+        };
+        Some((path, change))
+    })
+}
+
+/// The outcome of applying a [`PatchSet`] with [`PatchSet::par_apply`]
+#[cfg(feature = "rayon")]
+#[derive(Debug, Default)]
+pub struct PatchSetReport {
+    /// How every file whose patches all applied was changed
+    pub changes: HashMap<PathBuf, FileChange>,
+    /// Why patches couldn't be applied, in the order of the patches in the set
+    ///
+    /// A file is left out of `changes` once one of its patches fails, and the patches after it
+    /// in the set aren't applied.
+    pub errors: Vec<PatchSetError>,
+}
+
+#[cfg(feature = "rayon")]
+impl PatchSetReport {
+    /// True if every patch of the set was applied
+    pub fn succeeded(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl PatchSet<'_> {
+    /// Apply every patch in the set like [`PatchSet::apply_to_with_options`], applying the
+    /// patches of different files in parallel
+    ///
+    /// The patches of each file are applied one after the other, on a thread of the global rayon
+    /// pool. Instead of stopping at the first patch that fails, every file is patched on its own
+    /// and the report gathers the changes of the files that were patched and the errors of the
+    /// others. Which file a patch applies to is decided before applying any of them, as if they
+    /// all applied. The patches of the old and the new file of a rename or a copy are applied
+    /// together, and fail together.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use std::path::PathBuf;
+    ///
+    /// use patch::{ApplyOptions, FileChange, PatchSet};
+    ///
+    /// let sample = "\
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -1 +1 @@
+    /// -a
+    /// +A
+    /// --- b.txt
+    /// +++ b.txt
+    /// @@ -1 +1 @@
+    /// -x
+    /// +X
+    /// ";
+    ///
+    /// let files = HashMap::from([
+    ///     (PathBuf::from("a.txt"), "a\n".to_string()),
+    ///     (PathBuf::from("b.txt"), "b\n".to_string()),
+    /// ]);
+    /// let report = PatchSet::parse(sample)
+    ///     .unwrap()
+    ///     .par_apply(&files, &ApplyOptions::default());
+    /// assert_eq!(report.changes[&PathBuf::from("a.txt")], FileChange::Modified("A\n".into()));
+    /// assert_eq!(report.errors.len(), 1);
+    /// assert!(report.errors[0].to_string().starts_with("Failed to patch 'b.txt'"));
+    /// ```
+    pub fn par_apply(
+        &self,
+        files: &HashMap<PathBuf, String>,
+        options: &ApplyOptions,
+    ) -> PatchSetReport {
+        use rayon::prelude::*;
+
+        // Whether every file touched so far exists after the patches before
+        let mut exists: HashMap<PathBuf, bool> = HashMap::new();
+        // The patches applied together, with their index in the set and the file they apply to.
+        // A rename or a copy puts its old and new file in the same group.
+        let mut groups: Vec<Vec<(usize, &Patch, PathBuf)>> = Vec::new();
+        let mut group_of: HashMap<PathBuf, usize> = HashMap::new();
+        let mut errors = Vec::new();
+        for (index, patch) in self.patches.iter().enumerate() {
+            let op = patch.file_op();
+            let paths = target_path(patch, options.strip, |path| {
+                exists
+                    .get(path)
+                    .copied()
+                    .unwrap_or_else(|| files.contains_key(path))
+            })
+            .and_then(|path| match op {
+                FileOp::Rename { .. } | FileOp::Copy { .. } => {
+                    Ok((strip_path(&patch.new.path, options.strip)?, path))
+                }
+                _ => Ok((path.clone(), path)),
+            });
+            let (to, path) = match paths {
+                Ok(paths) => paths,
+                Err(error) => {
+                    errors.push((index, error));
+                    continue;
+                }
+            };
+            if op == FileOp::Delete {
+                exists.insert(path.clone(), false);
+            } else {
+                if matches!(op, FileOp::Rename { .. }) {
+                    exists.insert(path.clone(), false);
+                }
+                exists.insert(to.clone(), true);
+            }
+
+            let group = match (group_of.get(&path).copied(), group_of.get(&to).copied()) {
+                (Some(group), Some(other)) if group != other => {
+                    let moved = std::mem::take(&mut groups[other]);
+                    groups[group].extend(moved);
+                    groups[group].sort_by_key(|(index, _, _)| *index);
+                    for member in group_of.values_mut().filter(|member| **member == other) {
+                        *member = group;
+                    }
+                    group
+                }
+                (Some(group), _) | (None, Some(group)) => group,
+                (None, None) => {
+                    groups.push(Vec::new());
+                    groups.len() - 1
+                }
+            };
+            group_of.insert(to, group);
+            group_of.insert(path.clone(), group);
+            groups[group].push((index, patch, path));
+        }
+
+        let results: Vec<_> = groups
+            .into_par_iter()
+            .map(|patches| {
+                let mut touched = HashMap::new();
+                for (index, patch, path) in patches {
+                    let content = current(&touched, files, &path);
+                    let patched = apply_with_options(patch, content, options).map_err(|error| {
+                        (
+                            index,
+                            PatchSetError::Apply {
+                                path: path.clone(),
+                                error,
+                            },
+                        )
+                    })?;
+                    record(&mut touched, files, patch, path, patched, options.strip)
+                        .map_err(|error| (index, error))?;
+                }
+                Ok(touched)
+            })
+            .collect();
+
+        let mut report = PatchSetReport::default();
+        for result in results {
+            match result {
+                Ok(touched) => report.changes.extend(file_changes(files, touched)),
+                Err(error) => errors.push(error),
+            }
+        }
+        errors.sort_by_key(|(index, _)| *index);
+        report.errors = errors.into_iter().map(|(_, error)| error).collect();
+        report
+    }
+}
+
+impl<'a> From<Vec<Patch<'a>>> for PatchSet<'a> {
+    fn from(patches: Vec<Patch<'a>>) -> Self {
+        PatchSet { patches }
+    }
+}
+
+impl<'a> IntoIterator for PatchSet<'a> {
+    type Item = Patch<'a>;
+    type IntoIter = std::vec::IntoIter<Patch<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.patches.into_iter()
+    }
+}
+
+impl<'p, 'a> IntoIterator for &'p PatchSet<'a> {
+    type Item = &'p Patch<'a>;
+    type IntoIter = std::slice::Iter<'p, Patch<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.patches.iter()
+    }
+}
+
+impl fmt::Display for PatchSet<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            join_patches(self.patches.iter().map(Patch::to_string))
+        )
+    }
+}
+
+// Join the text of patches with newline characters, except after the patches that were written
+// without loss, whose text ends with the line ending they had
+pub(crate) fn join_patches(texts: impl IntoIterator<Item = String>) -> String {
+    let mut joined = String::new();
+    for text in texts {
+        if !joined.is_empty() && !joined.ends_with('\n') {
+            joined.push('\n');
+        }
+        joined.push_str(&text);
+    }
+    joined
+}
+
+pub(crate) fn is_dev_null(file: &File) -> bool {
+    file.path == DEV_NULL
+}
+
+// The path a patch is about: its new file, unless it deletes its old one
+fn patch_path<'p>(patch: &'p Patch) -> &'p str {
+    if is_dev_null(&patch.new) {
+        &patch.old.path
+    } else {
+        &patch.new.path
+    }
+}
+
+// The path of a patch without its `a/` or `b/` prefix, if it has one
+pub(crate) fn file_name<'p>(patch: &'p Patch) -> &'p str {
+    let path = patch_path(patch);
+    path.strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path)
+}
+
+// Match a path with or without its first component, which is often `a/` or `b/`
+fn path_matches(pattern: &str, path: &str) -> bool {
+    glob_match(pattern, path)
+        || path
+            .split_once('/')
+            .is_some_and(|(_, rest)| glob_match(pattern, rest))
+}
+
+/// Remove `strip` leading components from a path found in a patch and validate the result, like
+/// the `-p` option of GNU patch
+///
+/// Consecutive slashes count as a single separator. The resulting path is normalized, and
+/// refused with [`PatchSetError::InvalidPath`] if it is empty, absolute or contains `..`, so it
+/// can't refer to anything outside of the current directory.
+///
+/// # Example
+///
+/// ```
+/// use std::path::PathBuf;
+///
+/// use patch::strip_path;
+///
+/// assert_eq!(strip_path("a/src/lib.rs", 1).unwrap(), PathBuf::from("src/lib.rs"));
+/// assert_eq!(strip_path("/usr/src/lib.rs", 2).unwrap(), PathBuf::from("src/lib.rs"));
+/// assert!(strip_path("a/../../etc/passwd", 1).is_err());
+/// assert!(strip_path("lib.rs", 1).is_err());
+/// ```
+pub fn strip_path(path: &str, strip: usize) -> Result<PathBuf, PatchSetError> {
+    let invalid = || PatchSetError::InvalidPath {
+        path: path.to_string(),
+    };
+
+    let mut rest = path;
+    for _ in 0..strip {
+        let separator = rest.find('/').ok_or_else(invalid)?;
+        rest = rest[separator..].trim_start_matches('/');
+    }
+
+    let mut resolved = PathBuf::new();
+    for component in Path::new(rest).components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(invalid());
+            }
+        }
+    }
+    if resolved.as_os_str().is_empty() {
+        return Err(invalid());
+    }
+    Ok(resolved)
+}
+
+// Pick the file a patch should be applied to
+pub(crate) fn target_path(
+    patch: &Patch,
+    strip: usize,
+    exists: impl Fn(&Path) -> bool,
+) -> Result<PathBuf, PatchSetError> {
+    let op = patch.file_op();
+    if op == FileOp::Create {
+        let new = strip_path(&patch.new.path, strip)?;
+        return if exists(&new) {
+            Err(PatchSetError::FileExists { path: new })
+        } else {
+            Ok(new)
+        };
+    }
+
+    let old = strip_path(&patch.old.path, strip)?;
+    if exists(&old) {
+        return Ok(old);
+    }
+    if op == FileOp::Delete {
+        return Err(PatchSetError::FileNotFound {
+            old,
+            new: PathBuf::from(patch.new.path.as_ref()),
+        });
+    }
+    let new = strip_path(&patch.new.path, strip)?;
+    if exists(&new) {
+        Ok(new)
+    } else {
+        Err(PatchSetError::FileNotFound { old, new })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn files(entries: &[(&str, &str)]) -> HashMap<PathBuf, String> {
+        entries
+            .iter()
+            .map(|(path, content)| (PathBuf::from(path), content.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_apply_to_groups_patches_per_file() {
+        let sample = "\
+--- a.txt
++++ a.txt
+@@ -1,2 +1,2 @@
+-one
++ONE
+ two
+--- a.txt
++++ a.txt
+@@ -1,2 +1,2 @@
+ ONE
+-two
++TWO
+";
+        let set = PatchSet::parse(sample).unwrap();
+        let changes = set.apply_to(&files(&[("a.txt", "one\ntwo\n")])).unwrap();
+        assert_eq!(
+            changes,
+            HashMap::from([(
+                PathBuf::from("a.txt"),
+                FileChange::Modified("ONE\nTWO\n".into())
+            )])
+        );
+    }
+
+    #[test]
+    fn test_apply_to_resolves_new_name() {
+        let sample = "\
+--- a.txt.orig
++++ a.txt
+@@ -1 +1 @@
+-one
++ONE
+";
+        let set = PatchSet::parse(sample).unwrap();
+        let changes = set.apply_to(&files(&[("a.txt", "one\n")])).unwrap();
+        assert_eq!(
+            changes[&PathBuf::from("a.txt")],
+            FileChange::Modified("ONE\n".into())
+        );
+    }
+
+    #[test]
+    fn test_apply_to_deletes() {
+        let sample = "\
+--- a.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-one
+";
+        let set = PatchSet::parse(sample).unwrap();
+        let changes = set.apply_to(&files(&[("a.txt", "one\n")])).unwrap();
+        assert_eq!(changes[&PathBuf::from("a.txt")], FileChange::Deleted);
+    }
+
+    #[test]
+    fn test_apply_to_git_file_ops() {
+        let sample = "\
+diff --git a/empty.txt b/empty.txt
+new file mode 100644
+index 0000000..e69de29
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+index e69de29..0000000
+diff --git a/old.txt b/new.txt
+similarity index 90%
+rename from old.txt
+rename to new.txt
+--- a/old.txt
++++ b/new.txt
+@@ -1 +1 @@
+-one
++ONE
+diff --git a/src.txt b/copy.txt
+similarity index 100%
+copy from src.txt
+copy to copy.txt
+";
+        let set = PatchSet::parse(sample).unwrap();
+        let options = ApplyOptions {
+            strip: 1,
+            ..ApplyOptions::default()
+        };
+        let existing = files(&[("gone.txt", ""), ("old.txt", "one\n"), ("src.txt", "src\n")]);
+        let mut changes: Vec<_> = set
+            .apply_to_with_options(&existing, &options)
+            .unwrap()
+            .into_iter()
+            .collect();
+        changes.sort_by(|(a, _), (b, _)| a.cmp(b));
+        assert_eq!(
+            changes,
+            [
+                (
+                    PathBuf::from("copy.txt"),
+                    FileChange::Created("src\n".into())
+                ),
+                (PathBuf::from("empty.txt"), FileChange::Created("".into())),
+                (PathBuf::from("gone.txt"), FileChange::Deleted),
+                (
+                    PathBuf::from("new.txt"),
+                    FileChange::Created("ONE\n".into())
+                ),
+                (PathBuf::from("old.txt"), FileChange::Deleted),
+            ]
+        );
+
+        // A rename doesn't replace an existing file
+        let rename = PatchSet::from(vec![set.patches[2].clone()]);
+        let existing = files(&[("old.txt", "one\n"), ("new.txt", "other\n")]);
+        assert!(matches!(
+            rename.apply_to_with_options(&existing, &options),
+            Err(PatchSetError::FileExists { .. })
+        ));
+    }
+
+    #[test]
+    fn test_preamble() {
+        let sample = "\
+From 1111111111111111111111111111111111111111 Mon Sep 17 00:00:00 2001
+Subject: [PATCH] Change the letters
+
+Explain why.
+---
+diff --git a/a.txt b/a.txt
+index 7898192..6f8dbc1 100644
+--- a/a.txt
++++ b/a.txt
+@@ -1,1 +1,1 @@
+-a
++A
+Only in new: c.txt
+diff -u old/b.txt new/b.txt
+--- old/b.txt
++++ new/b.txt
+@@ -1,1 +1,1 @@
+-b
++B
+";
+        let set = PatchSet::parse(sample).unwrap();
+        assert_eq!(set.patches.len(), 2);
+        assert_eq!(
+            set.preamble(),
+            Some(
+                "From 1111111111111111111111111111111111111111 Mon Sep 17 00:00:00 2001\n\
+                 Subject: [PATCH] Change the letters\n\nExplain why.\n---\n"
+            )
+        );
+        assert_eq!(
+            set.patches[1].preamble.as_deref(),
+            Some("Only in new: c.txt\ndiff -u old/b.txt new/b.txt\n")
+        );
+        assert_eq!(format!("{}\n", set), sample);
+
+        // Patches that are edited keep their preamble
+        let reversed = PatchSet::from(set.patches.iter().map(Patch::reverse).collect::<Vec<_>>());
+        assert_eq!(reversed.preamble(), set.preamble());
+        assert!(PatchSet::default().preamble().is_none());
+        let plain = PatchSet::parse("--- a\n+++ b\n@@ -1 +1 @@\n-x\n+y\n").unwrap();
+        assert!(plain.preamble().is_none());
+    }
+
+    #[test]
+    fn test_apply_to_errors() {
+        let create = PatchSet::parse("--- /dev/null\n+++ a.txt\n@@ -0,0 +1 @@\n+one\n").unwrap();
+        assert!(matches!(
+            create.apply_to(&files(&[("a.txt", "one\n")])),
+            Err(PatchSetError::FileExists { .. })
+        ));
+
+        let modify = PatchSet::parse("--- a.txt\n+++ b.txt\n@@ -1 +1 @@\n-one\n+two\n").unwrap();
+        assert!(matches!(
+            modify.apply_to(&files(&[])),
+            Err(PatchSetError::FileNotFound { .. })
+        ));
+        assert!(matches!(
+            modify.apply_to(&files(&[("b.txt", "three\n")])),
+            Err(PatchSetError::Apply { .. })
+        ));
+
+        let escape = PatchSet::parse("--- ../a.txt\n+++ ../a.txt\n@@ -1 +1 @@\n-a\n+b\n").unwrap();
+        assert!(matches!(
+            escape.apply_to(&files(&[("../a.txt", "a\n")])),
+            Err(PatchSetError::InvalidPath { .. })
+        ));
+    }
+
+    #[test]
+    fn test_apply_to_strips_prefixes() {
+        let sample = "\
+diff --git a/src/a.txt b/src/a.txt
+--- a/src/a.txt
++++ b/src/a.txt
+@@ -1 +1 @@
+-one
++ONE
+";
+        let set = PatchSet::parse(sample).unwrap();
+        let files = files(&[("src/a.txt", "one\n")]);
+        assert!(matches!(
+            set.apply_to(&files),
+            Err(PatchSetError::FileNotFound { .. })
+        ));
+
+        let options = ApplyOptions {
+            strip: 1,
+            ..ApplyOptions::default()
+        };
+        let changes = set.apply_to_with_options(&files, &options).unwrap();
+        assert_eq!(
+            changes[&PathBuf::from("src/a.txt")],
+            FileChange::Modified("ONE\n".into())
+        );
+    }
+
+    #[test]
+    fn test_strip_path() {
+        assert_eq!(strip_path("a/./b", 0).unwrap(), PathBuf::from("a/b"));
+        assert_eq!(strip_path("a//b/c", 1).unwrap(), PathBuf::from("b/c"));
+        assert_eq!(strip_path("a/b/c", 2).unwrap(), PathBuf::from("c"));
+        assert!(strip_path("a/b/c", 3).is_err());
+        assert!(strip_path("a/b/", 2).is_err());
+        assert!(strip_path("../etc/passwd", 0).is_err());
+        assert!(strip_path("a/../../b", 0).is_err());
+        assert!(strip_path("/etc/passwd", 0).is_err());
+        assert!(strip_path(".", 0).is_err());
+    }
+
+    #[test]
+    fn test_filter_by_path() {
+        let sample = "\
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1 +1 @@
+-a
++A
+--- a/old.rs
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+--- /dev/null
++++ b/docs/guide.md
+@@ -0,0 +1 @@
++new
+";
+        let set = PatchSet::parse(sample).unwrap();
+
+        let mut paths = Vec::new();
+        set.filter(|path| {
+            paths.push(path.to_string());
+            true
+        });
+        assert_eq!(paths, ["b/src/lib.rs", "a/old.rs", "b/docs/guide.md"]);
+
+        assert_eq!(set.include("**/*.rs").patches, &set.patches[..2]);
+        assert_eq!(set.exclude("**/*.rs").patches, &set.patches[2..]);
+        // `*` doesn't match `/`
+        assert_eq!(set.include("*.rs").patches, &set.patches[1..2]);
+        assert_eq!(
+            set.include("docs/*").to_string(),
+            set.exclude("**.rs").to_string()
+        );
+        assert_eq!(set.include("*.txt").patches, []);
+    }
+
+    #[test]
+    fn test_split() {
+        let sample = "\
+--- a/a.txt
++++ b/a.txt
+@@ -1 +1 @@
+-a
++A
+--- b.txt
++++ b.txt
+@@ -1 +1 @@
+-b
++B
+--- a/a.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-A
+";
+        let set = PatchSet::parse(sample).unwrap();
+        let files = set.split();
+        assert_eq!(
+            files,
+            [
+                PatchSet {
+                    patches: vec![set.patches[0].clone(), set.patches[2].clone()]
+                },
+                PatchSet {
+                    patches: vec![set.patches[1].clone()]
+                },
+            ]
+        );
+        assert_eq!(PatchSet::default().split(), []);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_apply() {
+        let mut sample = String::new();
+        let mut contents = Vec::new();
+        for i in 0..50 {
+            sample.push_str(&format!(
+                "--- f{i}.txt\n+++ f{i}.txt\n@@ -1 +1 @@\n-{i}\n+{}\n",
+                i * 2
+            ));
+            contents.push((format!("f{i}.txt"), format!("{i}\n")));
+        }
+        // Two patches of the same file apply in order, and a created file can be patched again
+        sample.push_str("--- f0.txt\n+++ f0.txt\n@@ -1 +1 @@\n-0\n+zero\n");
+        sample.push_str("--- /dev/null\n+++ new.txt\n@@ -0,0 +1 @@\n+a\n");
+        sample.push_str("--- new.txt\n+++ new.txt\n@@ -1 +1 @@\n-a\n+b\n");
+        // A file whose patch fails is left out, along with its later patches
+        sample.push_str("--- f1.txt\n+++ f1.txt\n@@ -1 +1 @@\n-x\n+y\n");
+        sample.push_str("--- f1.txt\n+++ f1.txt\n@@ -1 +1 @@\n-2\n+3\n");
+        sample.push_str("--- missing.txt\n+++ missing.txt\n@@ -1 +1 @@\n-a\n+b\n");
+
+        let entries: Vec<_> = contents
+            .iter()
+            .map(|(path, content)| (path.as_str(), content.as_str()))
+            .collect();
+        let files = files(&entries);
+        let set = PatchSet::parse(&sample).unwrap();
+        let report = set.par_apply(&files, &ApplyOptions::default());
+
+        assert_eq!(report.changes.len(), 50);
+        assert_eq!(
+            report.changes[&PathBuf::from("f0.txt")],
+            FileChange::Modified("zero\n".into())
+        );
+        assert_eq!(
+            report.changes[&PathBuf::from("f7.txt")],
+            FileChange::Modified("14\n".into())
+        );
+        assert_eq!(
+            report.changes[&PathBuf::from("new.txt")],
+            FileChange::Created("b\n".into())
+        );
+        assert!(!report.changes.contains_key(&PathBuf::from("f1.txt")));
+        let errors: Vec<_> = report
+            .errors
+            .iter()
+            .map(|error| error.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "Failed to patch 'f1.txt': Context mismatch at line 1: expected 'x', got '2'",
+                "Neither 'missing.txt' nor 'missing.txt' could be found",
+            ]
+        );
+        assert!(!report.succeeded());
+
+        // Without failures, the changes are the same as applying the patches in order
+        let set = PatchSet {
+            patches: set.patches[..53].to_vec(),
+        };
+        let report = set.par_apply(&files, &ApplyOptions::default());
+        assert!(report.succeeded());
+        assert_eq!(report.changes, set.apply_to(&files).unwrap());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_apply_renames() {
+        let sample = "\
+diff --git a/x.txt b/x.txt
+--- a/x.txt
++++ b/x.txt
+@@ -1 +1 @@
+-x
++X
+diff --git a/x.txt b/y.txt
+similarity index 90%
+rename from x.txt
+rename to y.txt
+--- a/x.txt
++++ b/y.txt
+@@ -1 +1 @@
+-X
++Y
+diff --git a/y.txt b/y.txt
+--- a/y.txt
++++ b/y.txt
+@@ -1 +1 @@
+-Y
++Z
+diff --git a/src.txt b/copy.txt
+similarity index 100%
+copy from src.txt
+copy to copy.txt
+";
+        let set = PatchSet::parse(sample).unwrap();
+        let options = ApplyOptions {
+            strip: 1,
+            ..ApplyOptions::default()
+        };
+        let existing = files(&[("x.txt", "x\n"), ("src.txt", "src\n")]);
+        let report = set.par_apply(&existing, &options);
+        assert!(report.succeeded(), "{:?}", report.errors);
+        assert_eq!(
+            report.changes,
+            HashMap::from([
+                (PathBuf::from("x.txt"), FileChange::Deleted),
+                (PathBuf::from("y.txt"), FileChange::Created("Z\n".into())),
+                (
+                    PathBuf::from("copy.txt"),
+                    FileChange::Created("src\n".into())
+                ),
+            ])
+        );
+        assert_eq!(
+            report.changes,
+            set.apply_to_with_options(&existing, &options).unwrap()
+        );
+    }
+}
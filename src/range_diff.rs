@@ -0,0 +1,237 @@
+use std::cmp::Reverse;
+
+use crate::ast::Patch;
+
+/// How similar two patches have to be for [`range_diff`] to take one for a new version of the
+/// other, like the creation factor of `git range-diff`
+const MIN_SIMILARITY: f32 = 0.5;
+
+/// What happened to a patch of a series in another version of it, see [`range_diff`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RangeDiffEntry {
+    /// The patch at this index of the new series has no counterpart in the old one
+    Added(usize),
+    /// The patch at this index of the old series has no counterpart in the new one
+    Dropped(usize),
+    /// The patches at these indices of the series are versions of the same patch
+    Matched {
+        /// The index of the patch in the old series
+        old: usize,
+        /// The index of the patch in the new series
+        new: usize,
+        /// Whether the changes of the patch are different, see [`Patch::semantic_eq`]
+        modified: bool,
+        /// Whether the patch moved relative to the other patches both series have
+        reordered: bool,
+    },
+}
+
+/// Compare two versions of a series of patches, like `git range-diff` compares two ranges of
+/// commits
+///
+/// Every patch of the new series is matched with the patch of the old series it is a version
+/// of: first the ones making the same changes, then the most similar ones, whose changed lines
+/// have to be at least half the same, see [`Patch::similarity`]. The patches that were moved are
+/// the fewest that have to be taken out for the others to be in the same order in both series.
+///
+/// The entries are in the order of the new series, with every dropped patch before the first
+/// entry matched with a later patch of the old series.
+///
+/// # Example
+///
+/// ```
+/// use patch::{Patch, RangeDiffEntry, range_diff};
+///
+/// let parse = |text| Patch::from_single(text).unwrap();
+/// let old = [
+///     parse("--- a\n+++ a\n@@ -1 +1 @@\n-a\n+A\n"),
+///     parse("--- b\n+++ b\n@@ -1 +1 @@\n-b\n+B\n"),
+/// ];
+/// let new = [
+///     parse("--- b\n+++ b\n@@ -1 +1 @@\n-b\n+B\n"),
+///     parse("--- c\n+++ c\n@@ -1 +1 @@\n-c\n+C\n"),
+/// ];
+/// assert_eq!(
+///     range_diff(&old, &new),
+///     [
+///         RangeDiffEntry::Dropped(0),
+///         RangeDiffEntry::Matched {
+///             old: 1,
+///             new: 0,
+///             modified: false,
+///             reordered: false,
+///         },
+///         RangeDiffEntry::Added(1),
+///     ]
+/// );
+/// ```
+pub fn range_diff(old_series: &[Patch], new_series: &[Patch]) -> Vec<RangeDiffEntry> {
+    // The candidate pairs, best first: equal patches, then the most similar ones, then the ones
+    // of the same file and the ones closest to each other in the series
+    let mut candidates = Vec::new();
+    for (old, old_patch) in old_series.iter().enumerate() {
+        for (new, new_patch) in new_series.iter().enumerate() {
+            let equal = old_patch.semantic_eq(new_patch);
+            let similarity = old_patch.similarity(new_patch);
+            if equal || similarity >= MIN_SIMILARITY {
+                let same_file = old_patch.new.path == new_patch.new.path;
+                candidates.push((equal, similarity, same_file, old.abs_diff(new), old, new));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then(b.1.total_cmp(&a.1))
+            .then(b.2.cmp(&a.2))
+            .then((a.3, a.4, a.5).cmp(&(b.3, b.4, b.5)))
+    });
+    // Which patch of the old series every patch of the new series is matched with
+    let mut matches: Vec<Option<(usize, bool)>> = vec![None; new_series.len()];
+    let mut matched_old = vec![false; old_series.len()];
+    for (equal, _, _, _, old, new) in candidates {
+        if matches[new].is_none() && !matched_old[old] {
+            matches[new] = Some((old, !equal));
+            matched_old[old] = true;
+        }
+    }
+
+    let in_order = longest_increasing(
+        &matches
+            .iter()
+            .flatten()
+            .map(|&(old, _)| old)
+            .collect::<Vec<_>>(),
+    );
+    let mut entries = Vec::with_capacity(old_series.len() + new_series.len());
+    let mut dropped = (0..old_series.len())
+        .filter(|&old| !matched_old[old])
+        .peekable();
+    for (new, matched) in matches.iter().enumerate() {
+        match *matched {
+            Some((old, modified)) => {
+                while let Some(index) = dropped.next_if(|&index| index < old) {
+                    entries.push(RangeDiffEntry::Dropped(index));
+                }
+                entries.push(RangeDiffEntry::Matched {
+                    old,
+                    new,
+                    modified,
+                    reordered: !in_order.contains(&old),
+                });
+            }
+            None => entries.push(RangeDiffEntry::Added(new)),
+        }
+    }
+    entries.extend(dropped.map(RangeDiffEntry::Dropped));
+    entries
+}
+
+// The values of the longest subsequence of `values` that is increasing, with the smallest values
+// of the ones that are as long
+fn longest_increasing(values: &[usize]) -> Vec<usize> {
+    // The length of the longest increasing subsequence ending at every value, and the value
+    // before it in that subsequence
+    let mut lengths: Vec<(usize, Option<usize>)> = Vec::with_capacity(values.len());
+    for (i, value) in values.iter().enumerate() {
+        let best = (0..i)
+            .filter(|&j| values[j] < *value)
+            .max_by_key(|&j| (lengths[j].0, Reverse(values[j])));
+        lengths.push(match best {
+            Some(j) => (lengths[j].0 + 1, Some(j)),
+            None => (1, None),
+        });
+    }
+    let mut end = (0..values.len()).max_by_key(|&i| (lengths[i].0, Reverse(values[i])));
+    let mut subsequence = Vec::new();
+    while let Some(i) = end {
+        subsequence.push(values[i]);
+        end = lengths[i].1;
+    }
+    subsequence.reverse();
+    subsequence
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::ast::Line;
+
+    fn patch(path: &str, old: &str, new: &str) -> Patch<'static> {
+        let text = format!(
+            "--- a/{0}\n+++ b/{0}\n@@ -1,2 +1,2 @@\n-{1}\n-{1}2\n+{2}\n+{2}2\n",
+            path, old, new
+        );
+        Patch::from_single(&text).unwrap().into_owned()
+    }
+
+    #[test]
+    fn test_range_diff() {
+        let old = [
+            patch("a.txt", "a", "A"),
+            patch("b.txt", "b", "B"),
+            patch("c.txt", "c", "C"),
+            patch("d.txt", "d", "D"),
+        ];
+        // `c.txt` moved to the front, `b.txt` changes one more line, `a.txt` was dropped and
+        // `e.txt` added
+        let mut changed = patch("b.txt", "b", "B");
+        changed.hunks[0].lines[3] = Line::Add("Bee".into());
+        let new = [
+            patch("c.txt", "c", "C"),
+            changed,
+            patch("d.txt", "d", "D"),
+            patch("e.txt", "e", "E"),
+        ];
+        let matched = |old, new, modified, reordered| RangeDiffEntry::Matched {
+            old,
+            new,
+            modified,
+            reordered,
+        };
+        assert_eq!(
+            range_diff(&old, &new),
+            [
+                RangeDiffEntry::Dropped(0),
+                matched(2, 0, false, true),
+                matched(1, 1, true, false),
+                matched(3, 2, false, false),
+                RangeDiffEntry::Added(3),
+            ]
+        );
+
+        assert_eq!(range_diff(&[], &[]), []);
+        assert_eq!(range_diff(&old[..1], &[]), [RangeDiffEntry::Dropped(0)]);
+    }
+
+    #[test]
+    fn test_range_diff_prefers_equal_patches() {
+        // Both old patches are similar enough to the new one, but only one makes its changes
+        let mut similar = patch("a.txt", "a", "A");
+        similar.hunks[0].lines[3] = Line::Add("other".into());
+        let old = [similar, patch("a.txt", "a", "A")];
+        let new = [patch("a.txt", "a", "A")];
+        assert_eq!(
+            range_diff(&old, &new),
+            [
+                RangeDiffEntry::Dropped(0),
+                RangeDiffEntry::Matched {
+                    old: 1,
+                    new: 0,
+                    modified: false,
+                    reordered: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_longest_increasing() {
+        assert_eq!(longest_increasing(&[]), []);
+        assert_eq!(longest_increasing(&[2, 0, 1, 3]), [0, 1, 3]);
+        assert_eq!(longest_increasing(&[3, 2, 1]), [1]);
+        assert_eq!(longest_increasing(&[2, 1, 3]), [1, 3]);
+    }
+}
@@ -0,0 +1,257 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use crate::ast::Patch;
+use crate::parser::{ParseError, parse_next_patch};
+
+/// Error that can occur while parsing patches from a reader
+#[derive(Debug)]
+pub enum ReadError {
+    /// The input could not be read, or isn't valid UTF-8
+    Io(io::Error),
+    /// A patch could not be parsed
+    ///
+    /// The line and offset of the error count from the start of the input.
+    Parse(ParseError<'static>),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(error) => write!(f, "{}", error),
+            ReadError::Parse(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReadError::Io(error) => Some(error),
+            ReadError::Parse(error) => Some(error),
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(error: io::Error) -> Self {
+        ReadError::Io(error)
+    }
+}
+
+impl Patch<'static> {
+    /// Parse the patches of a diff as they are read, without holding all of it in memory
+    ///
+    /// Only the text of one patch is kept at a time: as soon as the start of the next patch is
+    /// read, the previous one is parsed and yielded. A patch that fails to parse is yielded as
+    /// an error, and parsing continues with the next one. Reading stops at the first I/O error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::Patch;
+    ///
+    /// let diff = "\
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -1 +1 @@
+    /// -a
+    /// +A
+    /// --- b.txt
+    /// +++ b.txt
+    /// @@ -1 +1 @@
+    /// -b
+    /// +B
+    /// ";
+    ///
+    /// let paths: Vec<String> = Patch::parse_reader(diff.as_bytes())
+    ///     .map(|patch| patch.unwrap().new.path.into_owned())
+    ///     .collect();
+    /// assert_eq!(paths, ["a.txt", "b.txt"]);
+    /// ```
+    pub fn parse_reader<R: BufRead>(
+        reader: R,
+    ) -> impl Iterator<Item = Result<Patch<'static>, ReadError>> {
+        PatchReader {
+            reader,
+            buffer: String::new(),
+            started: false,
+            in_hunks: false,
+            svn: false,
+            lines_before: 0,
+            bytes_before: 0,
+            done: false,
+        }
+    }
+}
+
+struct PatchReader<R> {
+    reader: R,
+    /// The text read since the end of the last patch
+    buffer: String,
+    /// Whether the buffer contains the start of a patch
+    started: bool,
+    /// Whether the buffer contains the start of the hunks of a patch
+    in_hunks: bool,
+    /// Whether the patch in the buffer starts with the `Index:` line of Subversion, so that the
+    /// next such line starts the next patch
+    svn: bool,
+    /// The number of lines before the start of the buffer
+    lines_before: u32,
+    /// The number of bytes before the start of the buffer
+    bytes_before: usize,
+    done: bool,
+}
+
+impl<R: BufRead> PatchReader<R> {
+    // Parse a patch from the first `end` bytes of the buffer, and keep the rest of them along
+    // with the bytes after `end`, which are the start of the next patch
+    fn parse(&mut self, end: usize) -> Result<Patch<'static>, ReadError> {
+        let text = &self.buffer[..end];
+        let (result, consumed) = match parse_next_patch(text) {
+            Ok((patch, consumed)) => (Ok(patch.into_owned()), consumed),
+            Err(error) => {
+                let error = ParseError {
+                    line: self.lines_before + error.line,
+                    offset: self.bytes_before + error.offset,
+                    ..error.into_owned()
+                };
+                (Err(ReadError::Parse(error)), end)
+            }
+        };
+
+        self.lines_before += self.buffer[..consumed].matches('\n').count() as u32;
+        self.bytes_before += consumed;
+        self.buffer.drain(..consumed);
+        result
+    }
+}
+
+impl<R: BufRead> Iterator for PatchReader<R> {
+    type Item = Result<Patch<'static>, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error.into()));
+                }
+                Ok(0) => {
+                    self.done = true;
+                    // Anything after the last patch is ignored, like trailing lines are when
+                    // parsing a whole diff at once
+                    if !self.started {
+                        return None;
+                    }
+                    return Some(self.parse(self.buffer.len()));
+                }
+                Ok(_) => {}
+            }
+
+            // Subversion leaves out the file headers and hunks of some patches, so only its
+            // `Index:` line starts them
+            let index = line.starts_with("Index: ");
+            // The line saying that binary files differ is all of the patch without a `diff --git`
+            // header, and ends the patch with one
+            let binary_files =
+                line.starts_with("Binary files ") && line.trim_end().ends_with(" differ");
+            let starts_patch = (line.starts_with("diff --git ") && !self.svn)
+                || (index && (!self.started || self.in_hunks || self.svn))
+                || ((line.starts_with("--- ") || binary_files) && (!self.started || self.in_hunks));
+            if starts_patch && self.started {
+                let end = self.buffer.len();
+                self.buffer.push_str(&line);
+                self.in_hunks = binary_files;
+                self.svn = index;
+                return Some(self.parse(end));
+            }
+
+            if starts_patch {
+                self.started = true;
+                self.svn = index;
+                self.in_hunks = binary_files;
+            } else if self.started
+                && (line.starts_with("@@ ") || line.starts_with("GIT binary") || binary_files)
+            {
+                self.in_hunks = true;
+            }
+            self.buffer.push_str(&line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_reader_matches_parse_multiple() {
+        for sample in [
+            "git.diff",
+            "hg.diff",
+            "bzr.diff",
+            "svn.diff",
+            "svn-properties.diff",
+            "sample3.diff",
+            "git-binary.diff",
+            "binary-differs.diff",
+        ] {
+            let path = format!("{}/tests/samples/{}", env!("CARGO_MANIFEST_DIR"), sample);
+            let text = std::fs::read_to_string(path).unwrap();
+            let expected = Patch::from_multiple(&text).unwrap();
+            let patches: Vec<Patch> = Patch::parse_reader(text.as_bytes())
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(patches, expected, "{}", sample);
+        }
+    }
+
+    #[test]
+    fn test_parse_reader_continues_after_errors() {
+        let text = "\
+--- a.txt
++++ a.txt
+@@ -1 +1 @@
+-a
++A
+--- broken.txt
++++ broken.txt
+@@ -x +1 @@
+--- b.txt
++++ b.txt
+@@ -1 +1 @@
+-b
++B
+";
+        let results: Vec<_> = Patch::parse_reader(text.as_bytes()).collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().new.path, "a.txt");
+        match &results[1] {
+            Err(ReadError::Parse(error)) => assert_eq!(error.line, 8),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(results[2].as_ref().unwrap().new.path, "b.txt");
+    }
+
+    #[test]
+    fn test_parse_reader_empty() {
+        assert_eq!(Patch::parse_reader(&b""[..]).count(), 0);
+        assert_eq!(Patch::parse_reader(&b"just some text\n"[..]).count(), 0);
+    }
+
+    #[test]
+    fn test_parse_reader_invalid_utf8() {
+        let mut patches = Patch::parse_reader(&b"--- a\n+++ b\n@@ -1 +1 @@\n-\xff\n+x\n"[..]);
+        assert!(matches!(patches.next(), Some(Err(ReadError::Io(_)))));
+        assert!(patches.next().is_none());
+    }
+}
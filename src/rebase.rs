@@ -0,0 +1,237 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::applier::{ApplyError, apply};
+use crate::ast::{File, GitHeader, Line, Patch};
+use crate::generator::{DiffOptions, diff_with_options};
+use crate::merge::{MergeResult, merge};
+
+/// The number of context lines of a rebased patch whose hunks have no context to go by
+const CONTEXT: usize = 3;
+
+/// Error that can occur while rebasing a patch with [`rebase`]
+#[derive(Debug)]
+pub enum RebaseError {
+    /// The patch could not be applied to the old base
+    Apply(ApplyError),
+    /// The changes of the patch conflict with the ones made to the base
+    ///
+    /// The merge has the new base with the changes of the patch, and conflict markers around
+    /// the changes that conflict.
+    Conflict(MergeResult),
+}
+
+impl fmt::Display for RebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RebaseError::Apply(error) => write!(f, "{}", error),
+            RebaseError::Conflict(merge) => write!(
+                f,
+                "The patch conflicts with the changes to the base in {} places",
+                merge.conflicts.len()
+            ),
+        }
+    }
+}
+
+impl Error for RebaseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RebaseError::Apply(error) => Some(error),
+            RebaseError::Conflict(_) => None,
+        }
+    }
+}
+
+impl From<ApplyError> for RebaseError {
+    fn from(error: ApplyError) -> Self {
+        RebaseError::Apply(error)
+    }
+}
+
+/// Move a patch made against `old_base` onto `new_base`, a changed version of the same file
+///
+/// The patch is applied to `old_base`, and its changes are merged with the ones that turned
+/// `old_base` into `new_base`, like `git rebase` does. The result is the patch from `new_base`
+/// to the merge: its hunks are where the changes are in `new_base`, with context lines taken
+/// from it, as many as the patch had. It keeps the paths, the operation and the preamble of the
+/// patch, but not the timestamps or the `index` line, which were only true of the old base.
+///
+/// # Example
+///
+/// ```
+/// use patch::{Patch, apply, rebase};
+///
+/// let patch = Patch::from_single(
+///     "\
+/// --- a/list.txt
+/// +++ b/list.txt
+/// @@ -1,3 +1,3 @@
+///  apples
+/// -bananas
+/// +blueberries
+///  cherries
+/// ",
+/// )
+/// .unwrap();
+/// let old_base = "apples\nbananas\ncherries\n";
+/// let new_base = "avocados\napples\nbananas\ncherries\n";
+///
+/// let rebased = rebase(&patch, old_base, new_base).unwrap();
+/// assert_eq!(
+///     rebased.to_string(),
+///     "\
+/// --- a/list.txt
+/// +++ b/list.txt
+/// @@ -2,3 +2,3 @@
+///  apples
+/// -bananas
+/// +blueberries
+///  cherries"
+/// );
+/// assert_eq!(
+///     apply(&rebased, new_base).unwrap(),
+///     "avocados\napples\nblueberries\ncherries\n"
+/// );
+/// ```
+pub fn rebase<'a>(
+    patch: &Patch<'a>,
+    old_base: &str,
+    new_base: &str,
+) -> Result<Patch<'a>, RebaseError> {
+    let patched = apply(patch, old_base)?;
+    let merged = merge(old_base, &patched, new_base);
+    if !merged.is_clean() {
+        return Err(RebaseError::Conflict(merged));
+    }
+
+    let options = DiffOptions {
+        context: context(patch),
+        ..DiffOptions::default()
+    };
+    let rebased = diff_with_options(new_base, &merged.text, &options).into_owned();
+    Ok(Patch {
+        old: File {
+            meta: None,
+            ..patch.old.clone()
+        },
+        new: File {
+            meta: None,
+            ..patch.new.clone()
+        },
+        operation: patch.operation.clone(),
+        git: patch
+            .git
+            .clone()
+            .map(|git| GitHeader { index: None, ..git }),
+        svn: patch.svn.clone(),
+        preamble: patch.preamble.clone(),
+        ..rebased
+    })
+}
+
+// The largest number of context lines before or after the changes of a hunk of the patch
+fn context(patch: &Patch) -> usize {
+    let changes = |line: &Line| !matches!(line, Line::Context(_));
+    patch
+        .hunks
+        .iter()
+        .filter_map(|hunk| {
+            let first = hunk.lines.iter().position(changes)?;
+            let last = hunk.lines.iter().rposition(changes)?;
+            Some(first.max(hunk.lines.len() - 1 - last))
+        })
+        .max()
+        .unwrap_or(CONTEXT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    const PATCH: &str = "\
+diff --git a/list.txt b/list.txt
+index 1234567..89abcde 100644
+--- a/list.txt
++++ b/list.txt
+@@ -2,3 +2,3 @@
+ b
+-c
++C
+ d
+@@ -7,2 +7,2 @@
+ g
+-h
++H
+\\ No newline at end of file
+";
+
+    const OLD_BASE: &str = "a\nb\nc\nd\ne\nf\ng\nh";
+
+    #[test]
+    fn test_rebase() {
+        let patch = Patch::from_single(PATCH).unwrap();
+        let new_base = "0\na\nb\nc\nd\ne\nf2\ng\nh";
+        let rebased = rebase(&patch, OLD_BASE, new_base).unwrap();
+        assert_eq!(
+            rebased.to_string(),
+            "\
+diff --git a/list.txt b/list.txt
+--- a/list.txt
++++ b/list.txt
+@@ -3,3 +3,3 @@
+ b
+-c
++C
+ d
+@@ -8,2 +8,2 @@
+ g
+-h
+\\ No newline at end of file
++H
+\\ No newline at end of file"
+        );
+        assert_eq!(
+            apply(&rebased, new_base).unwrap(),
+            "0\na\nb\nC\nd\ne\nf2\ng\nH"
+        );
+    }
+
+    #[test]
+    fn test_rebase_end_of_file() {
+        // The new base ends with a newline character, which the patch doesn't change
+        let new_base = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let text = PATCH.replace("\\ No newline at end of file\n", "");
+        let patch = Patch::from_single(&text).unwrap();
+        let rebased = rebase(&patch, new_base, new_base).unwrap();
+        assert!(rebased.new_end_newline);
+        assert_eq!(
+            apply(&rebased, new_base).unwrap(),
+            "a\nb\nC\nd\ne\nf\ng\nH\n"
+        );
+
+        // A patch adding the newline character adds it to the new base too
+        let patch = Patch::from_single(
+            "--- a/list.txt\n+++ b/list.txt\n@@ -8 +8 @@\n-h\n\\ No newline at end of file\n+h\n",
+        )
+        .unwrap();
+        let rebased = rebase(&patch, OLD_BASE, "x\nh").unwrap();
+        assert_eq!(apply(&rebased, "x\nh").unwrap(), "x\nh\n");
+    }
+
+    #[test]
+    fn test_rebase_conflict() {
+        let patch = Patch::from_single(PATCH).unwrap();
+        let new_base = "a\nb\nc2\nd\ne\nf\ng\nh";
+        match rebase(&patch, OLD_BASE, new_base) {
+            Err(RebaseError::Conflict(merge)) => assert_eq!(merge.conflicts.len(), 1),
+            result => panic!("{:?}", result),
+        }
+        assert!(matches!(
+            rebase(&patch, "unrelated\n", new_base),
+            Err(RebaseError::Apply(_))
+        ));
+    }
+}
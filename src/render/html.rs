@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::ast::{Hunk, Line, Patch};
+use crate::render::side_by_side::{Cell, hunk_rows};
+use crate::words::{Granularity, LineSpan};
+
+/// How [`Patch::to_html`] lays out the lines of a patch
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum HtmlLayout {
+    /// One line per row, with the old and new line number in front of it, like a unified diff
+    #[default]
+    Inline,
+    /// The old lines on the left and the new lines on the right next to each other, see
+    /// [`Patch::to_side_by_side_string`]
+    SideBySide,
+}
+
+/// Options controlling how [`Patch::to_html`] writes a patch
+///
+/// # Example
+///
+/// ```
+/// use patch::{Granularity, HtmlLayout, HtmlOptions};
+///
+/// let options = HtmlOptions {
+///     layout: HtmlLayout::SideBySide,
+///     highlight: Some(Granularity::Char),
+/// };
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct HtmlOptions {
+    /// How the lines are laid out
+    pub layout: HtmlLayout,
+    /// Mark the parts of changed lines that changed, with the given granularity, see
+    /// [`Hunk::word_changes`]
+    pub highlight: Option<Granularity>,
+}
+
+impl Patch<'_> {
+    /// Write the patch as an HTML table, with CSS classes for styling it in a web page
+    ///
+    /// The table has the class `diff`, along with `diff-inline` or `diff-side-by-side` for its
+    /// [`HtmlLayout`]. Its head holds the paths of the files, in `diff-old-path` and
+    /// `diff-new-path` cells, on rows of their own for the inline layout. Every hunk starts with
+    /// a `diff-hunk-header` row, and the cells of the lines have the class `diff-line-number`, or
+    /// `diff-text` with `diff-add`, `diff-remove`, `diff-context` or `diff-empty` for the side of
+    /// a row without a line. Changed parts of lines are wrapped in `<del>` and `<ins>` elements.
+    /// All text is escaped, and the markup doesn't end with a newline character.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::{HtmlOptions, Patch};
+    ///
+    /// let patch = Patch::from_single("--- a\n+++ b\n@@ -1 +1 @@\n-x < y\n+x > y\n").unwrap();
+    /// let html = patch.to_html(&HtmlOptions::default());
+    /// assert!(html.starts_with("<table class=\"diff diff-inline\">"));
+    /// assert!(html.contains(
+    ///     "<td class=\"diff-line-number\">1</td><td class=\"diff-line-number\"></td>\
+    ///      <td class=\"diff-text diff-remove\">x &lt; y</td>"
+    /// ));
+    /// ```
+    pub fn to_html(&self, options: &HtmlOptions) -> String {
+        let (class, columns) = match options.layout {
+            HtmlLayout::Inline => ("diff-inline", 3),
+            HtmlLayout::SideBySide => ("diff-side-by-side", 4),
+        };
+        let mut html = String::new();
+        writeln!(html, "<table class=\"diff {}\">", class).unwrap();
+        // The paths sit above the columns of their side, or above all the columns on rows of
+        // their own for the inline layout, whose columns aren't split by side
+        let (path_columns, between_paths) = match options.layout {
+            HtmlLayout::Inline => (columns, "</tr><tr>"),
+            HtmlLayout::SideBySide => (columns / 2, ""),
+        };
+        let path_cell = |html: &mut String, class: &str, path: &str| {
+            write!(
+                html,
+                "<th colspan=\"{}\" class=\"{}\">",
+                path_columns, class
+            )
+            .unwrap();
+            escape(html, path);
+            html.push_str("</th>");
+        };
+        html.push_str("<thead><tr>");
+        path_cell(&mut html, "diff-old-path", &self.old.path);
+        html.push_str(between_paths);
+        path_cell(&mut html, "diff-new-path", &self.new.path);
+        html.push_str("</tr></thead>\n<tbody>");
+
+        for hunk in &self.hunks {
+            write!(
+                html,
+                "\n<tr class=\"diff-hunk-header\"><td colspan=\"{}\">",
+                columns
+            )
+            .unwrap();
+            escape(
+                &mut html,
+                &format!(
+                    "@@ -{} +{} @@{}",
+                    hunk.old_range, hunk.new_range, hunk.range_hint
+                ),
+            );
+            html.push_str("</td></tr>");
+
+            let spans = changed_spans(hunk, options.highlight);
+            match options.layout {
+                HtmlLayout::Inline => inline_rows(&mut html, hunk, &spans),
+                HtmlLayout::SideBySide => {
+                    for (_, old, new) in hunk_rows(hunk) {
+                        html.push_str("\n<tr>");
+                        side_cells(&mut html, old, "diff-remove", hunk, &spans);
+                        side_cells(&mut html, new, "diff-add", hunk, &spans);
+                        html.push_str("</tr>");
+                    }
+                }
+            }
+        }
+        html.push_str("\n</tbody>\n</table>");
+        html
+    }
+}
+
+// The parts of the changed lines of a hunk, by the index of their line
+fn changed_spans(hunk: &Hunk, highlight: Option<Granularity>) -> HashMap<usize, Vec<LineSpan>> {
+    let mut spans = HashMap::new();
+    if let Some(granularity) = highlight {
+        for change in hunk.word_changes(granularity) {
+            spans.insert(change.old_line, change.old_spans);
+            spans.insert(change.new_line, change.new_spans);
+        }
+    }
+    spans
+}
+
+// Write a row for every line of a hunk, with both line numbers in front of it
+fn inline_rows(html: &mut String, hunk: &Hunk, spans: &HashMap<usize, Vec<LineSpan>>) {
+    let mut old_number = hunk.old_range.start.max(1);
+    let mut new_number = hunk.new_range.start.max(1);
+    for (index, line) in hunk.lines.iter().enumerate() {
+        let (old, new, class) = match line {
+            Line::Context(_) => (Some(old_number), Some(new_number), "diff-context"),
+            Line::Remove(_) => (Some(old_number), None, "diff-remove"),
+            Line::Add(_) => (None, Some(new_number), "diff-add"),
+        };
+        old_number += old.is_some() as u64;
+        new_number += new.is_some() as u64;
+
+        html.push_str("\n<tr>");
+        number_cell(html, old);
+        number_cell(html, new);
+        let (Line::Add(text) | Line::Remove(text) | Line::Context(text)) = line;
+        text_cell(html, class, text, spans.get(&index), is_added(line));
+        html.push_str("</tr>");
+    }
+}
+
+// Write the cells of one side of a side-by-side row
+fn side_cells(
+    html: &mut String,
+    cell: Option<Cell>,
+    changed_class: &str,
+    hunk: &Hunk,
+    spans: &HashMap<usize, Vec<LineSpan>>,
+) {
+    let Some(cell) = cell else {
+        html.push_str(
+            "<td class=\"diff-line-number\"></td><td class=\"diff-text diff-empty\"></td>",
+        );
+        return;
+    };
+    number_cell(html, Some(cell.number));
+    let line = &hunk.lines[cell.index];
+    let class = match line {
+        Line::Context(_) => "diff-context",
+        _ => changed_class,
+    };
+    text_cell(
+        html,
+        class,
+        cell.text,
+        spans.get(&cell.index),
+        is_added(line),
+    );
+}
+
+fn is_added(line: &Line) -> bool {
+    matches!(line, Line::Add(_))
+}
+
+fn number_cell(html: &mut String, number: Option<u64>) {
+    html.push_str("<td class=\"diff-line-number\">");
+    if let Some(number) = number {
+        write!(html, "{}", number).unwrap();
+    }
+    html.push_str("</td>");
+}
+
+// Write the cell of the text of a line, with its changed parts in `<ins>` or `<del>` elements
+fn text_cell(
+    html: &mut String,
+    class: &str,
+    text: &str,
+    spans: Option<&Vec<LineSpan>>,
+    added: bool,
+) {
+    write!(html, "<td class=\"diff-text {}\">", class).unwrap();
+    match spans {
+        Some(spans) => {
+            let element = if added { "ins" } else { "del" };
+            for span in spans {
+                let part = &text[span.range.clone()];
+                if span.changed {
+                    write!(html, "<{}>", element).unwrap();
+                    escape(html, part);
+                    write!(html, "</{}>", element).unwrap();
+                } else {
+                    escape(html, part);
+                }
+            }
+        }
+        None => escape(html, text),
+    }
+    html.push_str("</td>");
+}
+
+// Write text with the characters that have a meaning in HTML escaped
+fn escape(html: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    const SAMPLE: &str = "\
+--- a/<lib>.rs
++++ b/<lib>.rs
+@@ -1,2 +1,3 @@
+ use std::io;
+-let a = \"x\";
++let b = \"x\";
++added();
+";
+
+    #[test]
+    fn test_to_html_inline() {
+        let patch = Patch::from_single(SAMPLE).unwrap();
+        let options = HtmlOptions {
+            highlight: Some(Granularity::Word),
+            ..HtmlOptions::default()
+        };
+        assert_eq!(
+            patch.to_html(&options),
+            "\
+<table class=\"diff diff-inline\">
+<thead><tr><th colspan=\"3\" class=\"diff-old-path\">a/&lt;lib&gt;.rs</th></tr>\
+<tr><th colspan=\"3\" class=\"diff-new-path\">b/&lt;lib&gt;.rs</th></tr></thead>
+<tbody>
+<tr class=\"diff-hunk-header\"><td colspan=\"3\">@@ -1,2 +1,3 @@</td></tr>
+<tr><td class=\"diff-line-number\">1</td><td class=\"diff-line-number\">1</td>\
+<td class=\"diff-text diff-context\">use std::io;</td></tr>
+<tr><td class=\"diff-line-number\">2</td><td class=\"diff-line-number\"></td>\
+<td class=\"diff-text diff-remove\">let <del>a</del> = &quot;x&quot;;</td></tr>
+<tr><td class=\"diff-line-number\"></td><td class=\"diff-line-number\">2</td>\
+<td class=\"diff-text diff-add\">let <ins>b</ins> = &quot;x&quot;;</td></tr>
+<tr><td class=\"diff-line-number\"></td><td class=\"diff-line-number\">3</td>\
+<td class=\"diff-text diff-add\">added();</td></tr>
+</tbody>
+</table>"
+        );
+    }
+
+    #[test]
+    fn test_to_html_side_by_side() {
+        let patch = Patch::from_single(SAMPLE).unwrap();
+        let options = HtmlOptions {
+            layout: HtmlLayout::SideBySide,
+            highlight: None,
+        };
+        let html = patch.to_html(&options);
+        assert_eq!(
+            html.lines().nth(1),
+            Some(
+                "<thead><tr><th colspan=\"2\" class=\"diff-old-path\">a/&lt;lib&gt;.rs</th>\
+                 <th colspan=\"2\" class=\"diff-new-path\">b/&lt;lib&gt;.rs</th></tr></thead>"
+            )
+        );
+        let rows: Vec<&str> = html.lines().skip(4).take(3).collect();
+        assert_eq!(
+            rows,
+            [
+                "<tr><td class=\"diff-line-number\">1</td><td class=\"diff-text diff-context\">\
+                 use std::io;</td><td class=\"diff-line-number\">1</td>\
+                 <td class=\"diff-text diff-context\">use std::io;</td></tr>",
+                "<tr><td class=\"diff-line-number\">2</td><td class=\"diff-text diff-remove\">\
+                 let a = &quot;x&quot;;</td><td class=\"diff-line-number\">2</td>\
+                 <td class=\"diff-text diff-add\">let b = &quot;x&quot;;</td></tr>",
+                "<tr><td class=\"diff-line-number\"></td><td class=\"diff-text diff-empty\"></td>\
+                 <td class=\"diff-line-number\">3</td><td class=\"diff-text diff-add\">added();</td>\
+                 </tr>",
+            ]
+        );
+        assert!(html.contains("<th colspan=\"2\" class=\"diff-old-path\">"));
+    }
+}
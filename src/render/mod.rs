@@ -0,0 +1,9 @@
+//! Ways of showing patches to people
+
+mod html;
+mod side_by_side;
+mod terminal;
+
+pub use html::{HtmlLayout, HtmlOptions};
+pub use side_by_side::{Overflow, SideBySideOptions};
+pub use terminal::{Color, ColorChoice, Style, TerminalOptions};
@@ -0,0 +1,395 @@
+use std::fmt::Write;
+use std::mem;
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::ast::{Hunk, Line, Patch, Range};
+
+/// What [`Patch::to_side_by_side_string`] does with lines too long for their column
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Overflow {
+    /// Cut the lines at the end of their column, like `diff -y` does
+    #[default]
+    Truncate,
+    /// Continue the lines on the following rows
+    Wrap,
+}
+
+/// Options controlling how [`Patch::to_side_by_side_string`] writes a patch
+///
+/// # Example
+///
+/// ```
+/// use patch::{Overflow, SideBySideOptions};
+///
+/// let options = SideBySideOptions {
+///     width: 120,
+///     overflow: Overflow::Wrap,
+///     ..SideBySideOptions::default()
+/// };
+/// assert!(options.line_numbers);
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SideBySideOptions {
+    /// The width of the output in columns of a terminal, usually the width of the terminal,
+    /// where wide characters, like most CJK ones, take up two columns
+    pub width: usize,
+    /// Whether to write the number of every line in front of it
+    pub line_numbers: bool,
+    /// What to do with lines too long for their column
+    pub overflow: Overflow,
+    /// The number of columns between tab stops, which tabs are expanded to
+    pub tab_width: usize,
+}
+
+impl Default for SideBySideOptions {
+    fn default() -> Self {
+        SideBySideOptions {
+            width: 130,
+            line_numbers: true,
+            overflow: Overflow::Truncate,
+            tab_width: 8,
+        }
+    }
+}
+
+/// A line of one side of a row
+#[derive(Clone, Copy)]
+pub(super) struct Cell<'l> {
+    /// The index of the line in [`Hunk::lines`]
+    pub(super) index: usize,
+    /// The number of the line in its file
+    pub(super) number: u64,
+    pub(super) text: &'l str,
+}
+
+impl Patch<'_> {
+    /// Write the patch in two columns, with the old lines on the left and the new lines on the
+    /// right next to each other, like `diff -y` does
+    ///
+    /// The file paths come first, and every hunk starts with its header. Between the columns, a
+    /// `|` marks a changed line, a `<` a removed line and a `>` an added line. Removed lines are
+    /// paired with the lines added in their place, the first with the first and so on. Every
+    /// character is counted as one column, and the text doesn't end with a newline character.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::{Patch, SideBySideOptions};
+    ///
+    /// let patch = Patch::from_single(
+    ///     "\
+    /// --- a.txt
+    /// +++ b.txt
+    /// @@ -1,3 +1,4 @@
+    ///  one
+    /// -two
+    /// +2
+    ///  three
+    /// +four
+    /// ",
+    /// )
+    /// .unwrap();
+    /// let options = SideBySideOptions {
+    ///     width: 25,
+    ///     ..SideBySideOptions::default()
+    /// };
+    /// assert_eq!(
+    ///     patch.to_side_by_side_string(&options),
+    ///     "\
+    /// a.txt         b.txt
+    /// @@ -1,3 +1,4 @@
+    /// 1 one         1 one
+    /// 2 two       | 2 2
+    /// 3 three       3 three
+    ///             > 4 four"
+    /// );
+    /// ```
+    pub fn to_side_by_side_string(&self, options: &SideBySideOptions) -> String {
+        let number_width = match options.line_numbers {
+            true => {
+                let largest = self
+                    .hunks
+                    .iter()
+                    .map(|hunk| {
+                        let end = |range: &Range| range.start + range.count;
+                        end(&hunk.old_range).max(end(&hunk.new_range))
+                    })
+                    .max()
+                    .unwrap_or(0);
+                largest.to_string().len() + 1
+            }
+            false => 0,
+        };
+        let layout = Layout {
+            options,
+            number_width,
+            // Both columns share the width left by the marker between them
+            column_width: options.width.saturating_sub(3) / 2,
+        };
+
+        let mut rows = Vec::new();
+        // The paths start where the lines do, without room for line numbers
+        let paths = Layout {
+            number_width: 0,
+            ..layout
+        };
+        paths.write_row(
+            &mut rows,
+            ' ',
+            [&*self.old.path, &*self.new.path],
+            [None, None],
+        );
+        for hunk in &self.hunks {
+            rows.push(format!(
+                "@@ -{} +{} @@{}",
+                hunk.old_range, hunk.new_range, hunk.range_hint
+            ));
+            for (marker, old, new) in hunk_rows(hunk) {
+                let texts = [old, new].map(|cell| cell.map_or("", |cell| cell.text));
+                let numbers = [old, new].map(|cell| cell.map(|cell| cell.number));
+                layout.write_row(&mut rows, marker, texts, numbers);
+            }
+        }
+        rows.join("\n")
+    }
+}
+
+// The rows of a hunk, as the marker between the columns and the old and new line
+pub(super) fn hunk_rows<'h>(hunk: &'h Hunk) -> Vec<(char, Option<Cell<'h>>, Option<Cell<'h>>)> {
+    let mut rows = Vec::new();
+    let mut old_number = hunk.old_range.start.max(1);
+    let mut new_number = hunk.new_range.start.max(1);
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    // Pair up the removed lines with the added lines after them
+    let flush = |rows: &mut Vec<_>, removed: &mut Vec<Cell<'h>>, added: &mut Vec<Cell<'h>>| {
+        let len = removed.len().max(added.len());
+        for k in 0..len {
+            let (old, new) = (removed.get(k).copied(), added.get(k).copied());
+            let marker = match (old, new) {
+                (Some(_), Some(_)) => '|',
+                (Some(_), None) => '<',
+                _ => '>',
+            };
+            rows.push((marker, old, new));
+        }
+        removed.clear();
+        added.clear();
+    };
+    for (index, line) in hunk.lines.iter().enumerate() {
+        let cell = |number, text: &'h str| Cell {
+            index,
+            number,
+            text,
+        };
+        match line {
+            Line::Context(text) => {
+                flush(&mut rows, &mut removed, &mut added);
+                rows.push((
+                    ' ',
+                    Some(cell(old_number, text)),
+                    Some(cell(new_number, text)),
+                ));
+                old_number += 1;
+                new_number += 1;
+            }
+            Line::Remove(text) => {
+                if !added.is_empty() {
+                    flush(&mut rows, &mut removed, &mut added);
+                }
+                removed.push(cell(old_number, text));
+                old_number += 1;
+            }
+            Line::Add(text) => {
+                added.push(cell(new_number, text));
+                new_number += 1;
+            }
+        }
+    }
+    flush(&mut rows, &mut removed, &mut added);
+    rows
+}
+
+/// The widths of the parts of every row
+#[derive(Clone, Copy)]
+struct Layout<'o> {
+    options: &'o SideBySideOptions,
+    /// The width of the line numbers with the space after them, or 0 without line numbers
+    number_width: usize,
+    /// The width of each column, including the line numbers
+    column_width: usize,
+}
+
+impl Layout<'_> {
+    // Write the rows showing an old and a new line, more than one if they wrap
+    fn write_row(
+        &self,
+        rows: &mut Vec<String>,
+        marker: char,
+        texts: [&str; 2],
+        numbers: [Option<u64>; 2],
+    ) {
+        let text_width = self.column_width.saturating_sub(self.number_width);
+        let [old, new] = texts.map(|text| {
+            let text = expand_tabs(text, self.options.tab_width);
+            let mut chunks = wrap(&text, text_width);
+            if self.options.overflow == Overflow::Truncate {
+                chunks.truncate(1);
+                chunks.retain(|chunk| chunk.width() <= text_width);
+            }
+            if chunks.is_empty() {
+                chunks.push(String::new());
+            }
+            chunks
+        });
+
+        for k in 0..old.len().max(new.len()) {
+            let mut row = String::new();
+            for (side, chunks) in [&old, &new].into_iter().enumerate() {
+                if side == 1 {
+                    write!(row, " {} ", if k == 0 { marker } else { ' ' }).unwrap();
+                }
+                let number = numbers[side].filter(|_| k == 0 && self.number_width > 0);
+                match number {
+                    Some(number) => write!(row, "{:>1$} ", number, self.number_width - 1),
+                    None => write!(row, "{:1$}", "", self.number_width),
+                }
+                .unwrap();
+                let chunk = chunks.get(k).map_or("", String::as_str);
+                row.push_str(chunk);
+                let padding = text_width.saturating_sub(chunk.width());
+                row.extend(std::iter::repeat_n(' ', padding));
+            }
+            rows.push(row.trim_end().to_string());
+        }
+    }
+}
+
+// Split a line into pieces that fit in `width` columns of a terminal, counting the columns
+// every character takes up, with a character wider than that alone in its piece
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let (mut chunk, mut chunk_width) = (String::new(), 0);
+    for c in text.chars() {
+        let char_width = c.width().unwrap_or(0);
+        if chunk_width + char_width > width && !chunk.is_empty() {
+            chunks.push(mem::take(&mut chunk));
+            chunk_width = 0;
+        }
+        chunk.push(c);
+        chunk_width += char_width;
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+// Replace the tabs of a line with spaces up to the next tab stop
+fn expand_tabs(text: &str, tab_width: usize) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut column = 0;
+    for c in text.chars() {
+        if c == '\t' && tab_width > 0 {
+            let spaces = tab_width - column % tab_width;
+            expanded.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            expanded.push(c);
+            column += c.width().unwrap_or(0);
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_side_by_side() {
+        let patch = Patch::from_single(
+            "\
+--- old.rs
++++ new.rs
+@@ -9,4 +9,3 @@ fn main() {
+ \tlet x = 1;
+-\tlet some_long_name = x;
++\tlet y = x;
+-\tprintln!(\"{}\", y);
+ }
+",
+        )
+        .unwrap();
+        let options = SideBySideOptions {
+            width: 51,
+            tab_width: 4,
+            ..SideBySideOptions::default()
+        };
+        assert_eq!(
+            patch.to_side_by_side_string(&options),
+            "\
+old.rs                     new.rs
+@@ -9,4 +9,3 @@ fn main() {
+ 9     let x = 1;           9     let x = 1;
+10     let some_long_nam | 10     let y = x;
+11     println!(\"{}\", y) <
+12 }                       11 }"
+        );
+
+        let options = SideBySideOptions {
+            width: 31,
+            line_numbers: false,
+            overflow: Overflow::Wrap,
+            tab_width: 1,
+        };
+        assert_eq!(
+            patch.to_side_by_side_string(&options),
+            "\
+old.rs           new.rs
+@@ -9,4 +9,3 @@ fn main() {
+ let x = 1;       let x = 1;
+ let some_long |  let y = x;
+_name = x;
+ println!(\"{}\" <
+, y);
+}                }"
+        );
+    }
+
+    #[test]
+    fn test_side_by_side_wide_characters() {
+        // Wide characters take up two columns, so the columns after them still line up
+        let patch =
+            Patch::from_single("--- a\n+++ b\n@@ -1 +1 @@\n-日本語のテキスト\n+text\n").unwrap();
+        let options = SideBySideOptions {
+            width: 25,
+            line_numbers: false,
+            ..SideBySideOptions::default()
+        };
+        assert_eq!(
+            patch.to_side_by_side_string(&options),
+            "a             b\n@@ -1,1 +1,1 @@\n日本語のテ  | text"
+        );
+
+        let options = SideBySideOptions {
+            overflow: Overflow::Wrap,
+            ..options
+        };
+        assert_eq!(
+            patch.to_side_by_side_string(&options),
+            "a             b\n@@ -1,1 +1,1 @@\n日本語のテ  | text\nキスト"
+        );
+        assert_eq!(wrap("a日本", 1), ["a", "日", "本"]);
+    }
+
+    #[test]
+    fn test_expand_tabs() {
+        assert_eq!(expand_tabs("a\tbc\td", 4), "a   bc  d");
+        assert_eq!(expand_tabs("日\tb", 4), "日  b");
+        assert_eq!(expand_tabs("\t", 8), "        ");
+        assert_eq!(expand_tabs("a\tb", 0), "a\tb");
+    }
+}
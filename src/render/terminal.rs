@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::io::{self, IsTerminal};
+
+use crate::ast::{Line, Patch};
+use crate::words::{Granularity, LineSpan};
+
+/// A color of the 8 every terminal supports, or of the larger palettes most support
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    /// A color of the 256 color palette
+    Fixed(u8),
+    /// A 24-bit color, given by its red, green and blue components
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    // The SGR parameters selecting the color, with `base` 30 for the foreground and 40 for the
+    // background
+    fn write_sgr(&self, output: &mut String, base: u8) {
+        let basic = |n: u8| base + n;
+        match *self {
+            Color::Black => write!(output, "{}", basic(0)),
+            Color::Red => write!(output, "{}", basic(1)),
+            Color::Green => write!(output, "{}", basic(2)),
+            Color::Yellow => write!(output, "{}", basic(3)),
+            Color::Blue => write!(output, "{}", basic(4)),
+            Color::Magenta => write!(output, "{}", basic(5)),
+            Color::Cyan => write!(output, "{}", basic(6)),
+            Color::White => write!(output, "{}", basic(7)),
+            Color::Fixed(n) => write!(output, "{};5;{}", base + 8, n),
+            Color::Rgb(r, g, b) => write!(output, "{};2;{};{};{}", base + 8, r, g, b),
+        }
+        .unwrap();
+    }
+}
+
+/// How a part of a colored patch looks, see [`TerminalOptions`]
+///
+/// The default style leaves the text as it is.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Style {
+    /// The color of the text
+    pub foreground: Option<Color>,
+    /// The color behind the text
+    pub background: Option<Color>,
+    /// Whether the text is bold
+    pub bold: bool,
+    /// Whether the foreground and background colors are swapped, like git highlights changed
+    /// words
+    pub reverse: bool,
+}
+
+impl Style {
+    /// The style with the given text color
+    pub fn foreground(color: Color) -> Style {
+        Style {
+            foreground: Some(color),
+            ..Style::default()
+        }
+    }
+
+    // Write the text in this style, resetting the style after it
+    fn paint(&self, output: &mut String, text: &str) {
+        if text.is_empty() || *self == Style::default() {
+            output.push_str(text);
+            return;
+        }
+        let mut parameters = Vec::new();
+        if self.bold {
+            parameters.push("1".to_string());
+        }
+        if self.reverse {
+            parameters.push("7".to_string());
+        }
+        for (color, base) in [(self.foreground, 30), (self.background, 40)] {
+            if let Some(color) = color {
+                let mut parameter = String::new();
+                color.write_sgr(&mut parameter, base);
+                parameters.push(parameter);
+            }
+        }
+        let sgr = parameters.join(";");
+        write!(output, "\x1b[{}m{}\x1b[m", sgr, text).unwrap();
+    }
+}
+
+/// Whether [`Patch::to_terminal_string`] colors the patch
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ColorChoice {
+    /// Color the patch if the standard output is a terminal and the `NO_COLOR` environment
+    /// variable isn't set
+    #[default]
+    Auto,
+    /// Always color the patch
+    Always,
+    /// Never color the patch
+    Never,
+}
+
+impl ColorChoice {
+    // Whether the patch is colored
+    fn enabled(&self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Options controlling how [`Patch::to_terminal_string`] colors a patch
+///
+/// The default colors are the ones git uses.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TerminalOptions {
+    /// Whether to color the patch at all
+    pub color: ColorChoice,
+    /// The style of the `diff --git` line, the extended header lines and the file headers
+    pub file_header: Style,
+    /// The style of the ranges of hunk headers, which is not used for the function or section
+    /// after them
+    pub hunk_header: Style,
+    /// The style of context lines
+    pub context: Style,
+    /// The style of added lines
+    pub added: Style,
+    /// The style of removed lines
+    pub removed: Style,
+    /// Highlight the parts of changed lines that changed, with the given granularity, see
+    /// [`Hunk::word_changes`](crate::Hunk::word_changes)
+    pub highlight: Option<Granularity>,
+    /// The style of the highlighted parts of added lines
+    pub added_highlight: Style,
+    /// The style of the highlighted parts of removed lines
+    pub removed_highlight: Style,
+}
+
+impl Default for TerminalOptions {
+    fn default() -> Self {
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        let reverse = |color| Style {
+            reverse: true,
+            ..Style::foreground(color)
+        };
+        TerminalOptions {
+            color: ColorChoice::Auto,
+            file_header: bold,
+            hunk_header: Style::foreground(Color::Cyan),
+            context: Style::default(),
+            added: Style::foreground(Color::Green),
+            removed: Style::foreground(Color::Red),
+            highlight: None,
+            added_highlight: reverse(Color::Green),
+            removed_highlight: reverse(Color::Red),
+        }
+    }
+}
+
+impl Patch<'_> {
+    /// Write the patch in the Unified Format, colored with ANSI escape codes for a terminal like
+    /// `git diff` does
+    ///
+    /// With [`ColorChoice::Auto`], the patch is only colored if the standard output is a
+    /// terminal, and written like its `Display` implementation does otherwise. Like that
+    /// implementation, the text doesn't end with a newline character, so it can be printed with
+    /// `println!`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::{ColorChoice, Patch, TerminalOptions};
+    ///
+    /// let patch = Patch::from_single("--- a\n+++ b\n@@ -1 +1 @@\n-old\n+new\n").unwrap();
+    /// let options = TerminalOptions {
+    ///     color: ColorChoice::Always,
+    ///     ..TerminalOptions::default()
+    /// };
+    /// assert_eq!(
+    ///     patch.to_terminal_string(&options),
+    ///     "\x1b[1m--- a\x1b[m\n\x1b[1m+++ b\x1b[m\n\x1b[36m@@ -1,1 +1,1 @@\x1b[m\n\
+    ///      \x1b[31m-old\x1b[m\n\x1b[32m+new\x1b[m"
+    /// );
+    /// ```
+    pub fn to_terminal_string(&self, options: &TerminalOptions) -> String {
+        let text = self.to_string();
+        if !options.color.enabled() {
+            return text;
+        }
+
+        let mut lines = text.split('\n').peekable();
+        let mut output = Vec::new();
+        let painted = |style: &Style, text: &str| {
+            let mut line = String::new();
+            style.paint(&mut line, text);
+            line
+        };
+        // The file headers are all lines before the first hunk header
+        while let Some(line) =
+            lines.next_if(|line| self.hunks.is_empty() || !line.starts_with("@@ -"))
+        {
+            output.push(painted(&options.file_header, line));
+        }
+
+        for hunk in &self.hunks {
+            let Some(header) = lines.next() else {
+                break;
+            };
+            let ranges_end = header[2..].find("@@").map_or(header.len(), |end| end + 4);
+            let mut painted_header = painted(&options.hunk_header, &header[..ranges_end]);
+            painted_header.push_str(&header[ranges_end..]);
+            output.push(painted_header);
+
+            let changes = match options.highlight {
+                Some(granularity) => hunk.word_changes(granularity),
+                None => Vec::new(),
+            };
+            let mut spans: HashMap<usize, &[LineSpan]> = HashMap::new();
+            for change in &changes {
+                spans.insert(change.old_line, &change.old_spans);
+                spans.insert(change.new_line, &change.new_spans);
+            }
+
+            for (index, line) in hunk.lines.iter().enumerate() {
+                let Some(text) = lines.next() else {
+                    break;
+                };
+                let (style, highlight) = match line {
+                    Line::Add(_) => (&options.added, &options.added_highlight),
+                    Line::Remove(_) => (&options.removed, &options.removed_highlight),
+                    Line::Context(_) => (&options.context, &options.context),
+                };
+                match spans.get(&index) {
+                    Some(spans) => {
+                        let (prefix, content) = text.split_at(1);
+                        let mut painted_line = painted(style, prefix);
+                        for span in spans.iter() {
+                            let style = if span.changed { highlight } else { style };
+                            style.paint(&mut painted_line, &content[span.range.clone()]);
+                        }
+                        output.push(painted_line);
+                    }
+                    None => output.push(painted(style, text)),
+                }
+                // The markers of missing newline characters
+                while let Some(marker) = lines.next_if(|line| line.starts_with('\\')) {
+                    output.push(marker.to_string());
+                }
+            }
+        }
+
+        // The property changes of Subversion patches
+        output.extend(lines.map(|line| painted(&options.file_header, line)));
+        output.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    const SAMPLE: &str = "\
+diff --git a/a.rs b/a.rs
+--- a/a.rs
++++ b/a.rs
+@@ -1,3 +1,3 @@ fn main() {
+ let x = 1;
+-let y = x + 1;
++let y = x + 2;
+ let z = y;
+\\ No newline at end of file
+";
+
+    #[test]
+    fn test_to_terminal_string() {
+        let patch = Patch::from_single(SAMPLE).unwrap();
+        let options = TerminalOptions {
+            color: ColorChoice::Always,
+            ..TerminalOptions::default()
+        };
+        assert_eq!(
+            patch.to_terminal_string(&options),
+            "\
+\x1b[1mdiff --git a/a.rs b/a.rs\x1b[m
+\x1b[1m--- a/a.rs\x1b[m
+\x1b[1m+++ b/a.rs\x1b[m
+\x1b[36m@@ -1,3 +1,3 @@\x1b[m fn main() {
+ let x = 1;
+\x1b[31m-let y = x + 1;\x1b[m
+\x1b[32m+let y = x + 2;\x1b[m
+ let z = y;
+\\ No newline at end of file"
+        );
+
+        let options = TerminalOptions {
+            color: ColorChoice::Never,
+            ..TerminalOptions::default()
+        };
+        assert_eq!(patch.to_terminal_string(&options), patch.to_string());
+    }
+
+    #[test]
+    fn test_to_terminal_string_highlight() {
+        let patch = Patch::from_single(SAMPLE).unwrap();
+        let options = TerminalOptions {
+            color: ColorChoice::Always,
+            file_header: Style::default(),
+            hunk_header: Style::default(),
+            highlight: Some(Granularity::Word),
+            added: Style::foreground(Color::Fixed(10)),
+            added_highlight: Style {
+                background: Some(Color::Rgb(0, 80, 0)),
+                ..Style::foreground(Color::Fixed(10))
+            },
+            ..TerminalOptions::default()
+        };
+        let output = patch.to_terminal_string(&options);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[5],
+            "\x1b[31m-\x1b[m\x1b[31mlet y = x + \x1b[m\x1b[7;31m1\x1b[m\x1b[31m;\x1b[m"
+        );
+        assert_eq!(
+            lines[6],
+            "\x1b[38;5;10m+\x1b[m\x1b[38;5;10mlet y = x + \x1b[m\x1b[38;5;10;48;2;0;80;0m2\x1b[m\
+             \x1b[38;5;10m;\x1b[m"
+        );
+    }
+}
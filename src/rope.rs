@@ -0,0 +1,471 @@
+use std::borrow::Cow;
+
+use ropey::{Rope, RopeSlice};
+
+use crate::applier::ApplyError;
+use crate::ast::Patch;
+use crate::source::{ExactTarget, LineSource, NextLine, apply_exact};
+
+/// Apply a patch to a [`Rope`] in place, like [`apply`](crate::apply) does to a string
+///
+/// Only the lines the hunks change are spliced out of the rope and into it, so applying a small
+/// patch to a large editor buffer doesn't rebuild it. Lines end at `\n` like for `apply`,
+/// whichever other characters the features of ropey make [`Rope::line`] end lines at, so the
+/// rope is searched for them up to the last hunk. The hunks have to match at the line numbers of
+/// the patch; if one doesn't, the rope is left as it was.
+///
+/// Added lines take the line ending of the line before them, and the rope keeps its own ending
+/// unless the last hunk reaches the end of it.
+///
+/// # Example
+///
+/// ```
+/// use patch::{Patch, apply_to_rope};
+/// use ropey::Rope;
+///
+/// let mut rope = Rope::from_str("line 1\nline 2\nline 3\n");
+/// let patch = Patch::from_single(
+///     "\
+/// --- old.txt
+/// +++ new.txt
+/// @@ -1,3 +1,3 @@
+///  line 1
+/// -line 2
+/// +new line 2
+///  line 3
+/// ",
+/// )
+/// .unwrap();
+///
+/// apply_to_rope(&patch, &mut rope).unwrap();
+/// assert_eq!(rope, "line 1\nnew line 2\nline 3\n");
+/// ```
+pub fn apply_to_rope(patch: &Patch, rope: &mut Rope) -> Result<(), ApplyError> {
+    // The changes are all checked before the rope is touched, and spliced in from the last
+    // one so the offsets of the ones before stay valid
+    let mut target = RopeTarget {
+        rope,
+        lines: LineStarts::new(),
+        ends_with_newline: rope.len_chars() > 0 && rope.char(rope.len_chars() - 1) == '\n',
+        line: 0,
+        splice: None,
+        splices: Vec::new(),
+    };
+    let reached_end = apply_exact(patch, &mut target)?;
+    target.end_splice();
+    let splices = target.splices;
+
+    for (start, end, added) in splices.into_iter().rev() {
+        let start = rope.byte_to_char(start);
+        rope.remove(start..rope.byte_to_char(end));
+        rope.insert(start, &added);
+    }
+
+    // The patch decides whether the rope ends in a newline character if its last hunk reached
+    // the end of it
+    if reached_end && rope.len_chars() > 0 {
+        let last = rope.len_chars() - 1;
+        if patch.new_end_newline && rope.char(last) != '\n' {
+            let ending = last_ending(rope);
+            rope.insert(rope.len_chars(), ending);
+        } else if !patch.new_end_newline && rope.char(last) == '\n' {
+            let crlf = last > 0 && rope.char(last - 1) == '\r';
+            rope.remove(last - usize::from(crlf)..);
+        }
+    }
+    Ok(())
+}
+
+/// The lines of a [`Rope`], for applying a patch to it with [`apply_source`](crate::apply_source)
+///
+/// Lines end at `\n` only, like [`apply`](crate::apply) ends them, whichever other characters
+/// the features of ropey make [`Rope::line`] end lines at. Finding them reads the whole rope once.
+///
+/// # Example
+///
+/// ```
+/// use patch::{Patch, RopeLines, apply_source};
+/// use ropey::Rope;
+///
+/// let rope = Rope::from_str("line 1\nline 2\n");
+/// let patch = Patch::from_single("--- a\n+++ b\n@@ -2 +2 @@\n-line 2\n+new line 2\n").unwrap();
+///
+/// let mut output = String::new();
+/// apply_source(&patch, &RopeLines::new(&rope), &mut output).unwrap();
+/// assert_eq!(output, "line 1\nnew line 2\n");
+/// ```
+#[derive(Debug, Clone)]
+pub struct RopeLines<'r> {
+    rope: &'r Rope,
+    lines: LineStarts,
+}
+
+impl<'r> RopeLines<'r> {
+    /// Find the lines of a rope
+    pub fn new(rope: &'r Rope) -> Self {
+        let mut lines = LineStarts::new();
+        lines.search_to(rope, usize::MAX);
+        RopeLines { rope, lines }
+    }
+}
+
+/// The lines are borrowed from the rope unless they span several of its chunks
+impl LineSource for RopeLines<'_> {
+    fn len(&self) -> usize {
+        self.lines.count(self.rope)
+    }
+
+    fn line(&self, index: usize) -> Cow<'_, str> {
+        let (line, _) = self.lines.line(self.rope, index);
+        match line.as_str() {
+            Some(text) => Cow::Borrowed(text),
+            None => Cow::Owned(line.to_string()),
+        }
+    }
+
+    fn line_ending(&self, index: usize) -> Option<&str> {
+        match self.lines.line(self.rope, index) {
+            (_, "") => None,
+            (_, ending) => Some(ending),
+        }
+    }
+}
+
+// Finds the lines of a rope a patch changes, without changing it yet
+struct RopeTarget<'r> {
+    rope: &'r Rope,
+    lines: LineStarts,
+    ends_with_newline: bool,
+    /// The index of the next line of the rope
+    line: usize,
+    /// The start of the lines being replaced, and the lines replacing them so far
+    splice: Option<(usize, String)>,
+    /// The byte offsets of the lines to replace, from the start of the first to the start of
+    /// the one after the last, and their replacement
+    splices: Vec<(usize, usize, String)>,
+}
+
+impl RopeTarget<'_> {
+    // Finish the lines being replaced before the next line
+    fn end_splice(&mut self) {
+        if let Some((start, added)) = self.splice.take() {
+            self.lines.search_to(self.rope, self.line);
+            let start = self.lines.start(self.rope, start);
+            let end = self.lines.start(self.rope, self.line);
+            self.splices.push((start, end, added));
+        }
+    }
+
+    fn has_line(&mut self, line: usize) -> bool {
+        self.lines.search_to(self.rope, line);
+        self.lines.start(self.rope, line) < self.rope.len_bytes()
+    }
+
+    // The line ending of the line before the given one, which added lines there take, or of the
+    // first line if there is none before
+    fn ending_before(&mut self, line: usize) -> &'static str {
+        let before = line.saturating_sub(1);
+        if !self.has_line(before) {
+            return "\n";
+        }
+        self.lines.search_to(self.rope, before + 1);
+        match self.lines.line(self.rope, before) {
+            (_, "") => "\n",
+            (_, ending) => ending,
+        }
+    }
+}
+
+impl<'p> ExactTarget<'p> for RopeTarget<'_> {
+    fn position(&self) -> usize {
+        self.line
+    }
+
+    fn keep_to(&mut self, end: usize) -> Result<(), ApplyError> {
+        self.end_splice();
+        self.lines.search_to(self.rope, end);
+        self.line = self.line.max(end.min(self.lines.count(self.rope)));
+        Ok(())
+    }
+
+    fn next_line(&mut self, expected: &str, keep: bool) -> Result<NextLine, ApplyError> {
+        if !self.has_line(self.line) {
+            return Ok(NextLine::End);
+        }
+        self.lines.search_to(self.rope, self.line + 1);
+        let (actual, _) = self.lines.line(self.rope, self.line);
+        if actual != expected {
+            return Ok(NextLine::Mismatch(actual.to_string()));
+        }
+        if keep {
+            self.end_splice();
+        } else {
+            self.splice
+                .get_or_insert_with(|| (self.line, String::new()));
+        }
+        self.line += 1;
+        Ok(NextLine::Matched)
+    }
+
+    fn add(&mut self, text: &'p str) -> Result<(), ApplyError> {
+        let start = self.splice.as_ref().map_or(self.line, |(start, _)| *start);
+        let ending = self.ending_before(start);
+        // The last line of the rope gets an ending once a line follows it
+        let ends_last = start > 0 && !self.has_line(start) && !self.ends_with_newline;
+        let (_, added) = self.splice.get_or_insert_with(|| (start, String::new()));
+        if ends_last && added.is_empty() {
+            added.push_str(ending);
+        }
+        added.push_str(text);
+        added.push_str(ending);
+        Ok(())
+    }
+
+    fn at_end(&mut self) -> bool {
+        !self.has_line(self.line)
+    }
+}
+
+// The byte offsets where the lines of a rope start, found by searching it for `\n` only as far
+// as the lines are needed. The line indices of ropey can't be used, as ropey also ends lines at
+// `\r`, U+2028 and other characters when its `cr_lines` or `unicode_lines` features are on, which
+// any crate depending on ropey turns on for every other one.
+#[derive(Debug, Clone)]
+struct LineStarts {
+    /// The starts of the lines found so far, followed by the end of the rope once it has been
+    /// searched if it ends with a newline
+    starts: Vec<usize>,
+    /// The byte offset the rope has been searched up to
+    searched: usize,
+}
+
+impl LineStarts {
+    fn new() -> Self {
+        LineStarts {
+            starts: vec![0],
+            searched: 0,
+        }
+    }
+
+    // Search the rope until the start of the given line is found or the rope ends
+    fn search_to(&mut self, rope: &Rope, line: usize) {
+        if self.starts.len() > line {
+            return;
+        }
+        let (chunks, mut chunk_start, _, _) = rope.chunks_at_byte(self.searched);
+        for chunk in chunks {
+            let from = self.searched - chunk_start;
+            let newlines = chunk.bytes().enumerate().skip(from);
+            self.starts.extend(
+                newlines
+                    .filter(|&(_, byte)| byte == b'\n')
+                    .map(|(i, _)| chunk_start + i + 1),
+            );
+            chunk_start += chunk.len();
+            self.searched = chunk_start;
+            if self.starts.len() > line {
+                break;
+            }
+        }
+    }
+
+    // The start of the given line, or the end of the rope if it has no such line, of the lines
+    // found so far
+    fn start(&self, rope: &Rope, line: usize) -> usize {
+        self.starts.get(line).copied().unwrap_or(rope.len_bytes())
+    }
+
+    // The number of lines found so far
+    fn count(&self, rope: &Rope) -> usize {
+        self.starts
+            .partition_point(|&start| start < rope.len_bytes())
+    }
+
+    // The text of a line found so far and its line ending
+    fn line<'r>(&self, rope: &'r Rope, line: usize) -> (RopeSlice<'r>, &'static str) {
+        let line = rope.byte_slice(self.start(rope, line)..self.start(rope, line + 1));
+        let len = line.len_bytes();
+        let ending = if len >= 2 && line.byte(len - 2) == b'\r' && line.byte(len - 1) == b'\n' {
+            "\r\n"
+        } else if len >= 1 && line.byte(len - 1) == b'\n' {
+            "\n"
+        } else {
+            ""
+        };
+        (line.byte_slice(..len - ending.len()), ending)
+    }
+}
+
+// The line ending of the last line of a rope that has one, which a last line without one takes
+fn last_ending(rope: &Rope) -> &'static str {
+    let mut chars = rope.chars_at(rope.len_chars());
+    while let Some(char) = chars.prev() {
+        if char == '\n' {
+            return if chars.prev() == Some('\r') {
+                "\r\n"
+            } else {
+                "\n"
+            };
+        }
+    }
+    "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::applier::apply;
+
+    // Applying to a rope gives the same text as applying to a string
+    fn check_rope(patch: &str, content: &str) -> String {
+        let patch = Patch::from_single(patch).unwrap();
+        let mut rope = Rope::from_str(content);
+        apply_to_rope(&patch, &mut rope).unwrap();
+        assert_eq!(rope.to_string(), apply(&patch, content).unwrap());
+        rope.to_string()
+    }
+
+    #[test]
+    fn test_apply_to_rope() {
+        let patch = "\
+--- a.txt
++++ a.txt
+@@ -1,3 +1,2 @@
+ a
+-b
+-c
+@@ -4,0 +4,1 @@
++x
+@@ -6 +6 @@
+-f
++F
+";
+        assert_eq!(check_rope(patch, "a\nb\nc\nd\ne\nf\n"), "a\nd\nx\ne\nF\n");
+        assert_eq!(
+            check_rope(patch, "a\r\nb\r\nc\r\nd\r\ne\r\nf\r\n"),
+            "a\r\nd\r\nx\r\ne\r\nF\r\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_to_rope_end_of_file() {
+        let add = "--- a.txt\n+++ a.txt\n@@ -1 +1,2 @@\n a\n+b\n";
+        assert_eq!(check_rope(add, "a"), "a\nb\n");
+
+        let newline = "\
+--- a.txt
++++ a.txt
+@@ -1,2 +1,2 @@
+ a
+-b
++b
+\\ No newline at end of file
+";
+        assert_eq!(check_rope(newline, "a\nb\n"), "a\nb");
+
+        let create = "--- /dev/null\n+++ a.txt\n@@ -0,0 +1,2 @@\n+a\n+b\n";
+        assert_eq!(check_rope(create, ""), "a\nb\n");
+    }
+
+    #[test]
+    fn test_apply_to_rope_other_line_breaks() {
+        // Lines end at `\n` only, even with the features of ropey that end them at other
+        // characters
+        let patch = "\
+--- a.txt
++++ a.txt
+@@ -1,3 +1,3 @@
+ a\u{2028}b
+-c\u{85}d
++C\u{b}D
+ e\u{c}f\u{2029}
+";
+        let content = "a\u{2028}b\nc\u{85}d\ne\u{c}f\u{2029}\ng\rh\n";
+        assert!(Rope::from_str(content).len_lines() > 5);
+        assert_eq!(
+            check_rope(patch, content),
+            "a\u{2028}b\nC\u{b}D\ne\u{c}f\u{2029}\ng\rh\n"
+        );
+
+        let add = "--- a.txt\n+++ a.txt\n@@ -1 +1,2 @@\n a\n+x\n";
+        assert_eq!(check_rope(add, "a\nb\rc\r"), "a\nx\nb\rc\r");
+
+        let newline = "--- a.txt\n+++ a.txt\n@@ -2 +2 @@\n-b\n\\ No newline at end of file\n+b\n";
+        assert_eq!(check_rope(newline, "a\r\nb"), "a\r\nb\r\n");
+    }
+
+    #[test]
+    fn test_apply_to_rope_chunks() {
+        // Lines are found across the many chunks of a large rope
+        let content: String = (0..2000).map(|i| format!("line {i}\u{2028}\n")).collect();
+        let patch = "\
+--- a.txt
++++ a.txt
+@@ -1500,2 +1500,2 @@
+ line 1499\u{2028}
+-line 1500\u{2028}
++new
+";
+        let patched = check_rope(patch, &content);
+        assert!(patched.contains("line 1499\u{2028}\nnew\nline 1501"));
+        assert!(Rope::from_str(&content).chunks().count() > 10);
+    }
+
+    #[test]
+    fn test_rope_line_source() {
+        let patch = Patch::from_single("--- a.txt\n+++ a.txt\n@@ -2 +2 @@\n-b\n+B\n").unwrap();
+        let rope = Rope::from_str("a\r\nb\r\nc");
+        let mut output = String::new();
+        crate::source::apply_source(&patch, &RopeLines::new(&rope), &mut output).unwrap();
+        assert_eq!(output, "a\r\nB\r\nc");
+
+        let rope = Rope::from_str("a\u{2028}b\nc\r");
+        let lines = RopeLines::new(&rope);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines.line(0), "a\u{2028}b");
+        assert_eq!(lines.line_ending(0), Some("\n"));
+        assert_eq!(lines.line(1), "c\r");
+        assert_eq!(lines.line_ending(1), None);
+        assert!(RopeLines::new(&Rope::new()).is_empty());
+    }
+
+    #[test]
+    fn test_apply_to_rope_mismatch() {
+        let patch =
+            Patch::from_single("--- a.txt\n+++ a.txt\n@@ -1,2 +1,2 @@\n-a\n+A\n-x\n+X\n").unwrap();
+        let mut rope = Rope::from_str("a\nb\n");
+        assert!(matches!(
+            apply_to_rope(&patch, &mut rope),
+            Err(ApplyError::ContextMismatch { line: 2, .. })
+        ));
+        assert_eq!(rope, "a\nb\n");
+    }
+
+    #[test]
+    fn test_apply_to_rope_overlapping_hunks() {
+        let patch = Patch::from_single(
+            "\
+--- a.txt
++++ a.txt
+@@ -1,2 +1,2 @@
+ a
+-b
++B
+@@ -1,2 +1,2 @@
+ a
+-b
++B
+",
+        )
+        .unwrap();
+        let mut rope = Rope::from_str("a\nb\na\nb\nc\n");
+        assert!(matches!(
+            apply_to_rope(&patch, &mut rope),
+            Err(ApplyError::OverlappingHunks { hunk: 1, line: 1 })
+        ));
+        assert_eq!(rope, "a\nb\na\nb\nc\n");
+    }
+}
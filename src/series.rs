@@ -0,0 +1,495 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::applier::ApplyOptions;
+use crate::fs::{DirReport, FsError, apply_to_dir, write_atomic};
+use crate::parser::ParseError;
+use crate::patch_set::{PatchSet, PatchSetError, strip_path};
+
+/// The strip level of the patches of a series that don't have a `-p` option
+const DEFAULT_STRIP: usize = 1;
+
+/// The list of patches of a quilt stack, in the order they are applied, as found in a `series`
+/// file
+///
+/// Every line of a `series` file names a patch, optionally followed by `-pN` to apply it with
+/// a strip level of `N` instead of 1, and `-R` to apply it in reverse. Empty lines and comments
+/// starting with `#` are ignored.
+///
+/// # Example
+///
+/// ```
+/// use patch::Series;
+///
+/// let series = Series::parse("\
+/// ## Fixes from upstream
+/// fix-build.patch
+/// vendor/update-docs.diff -p0
+/// revert-feature.patch -R # until it works
+/// ").unwrap();
+///
+/// assert_eq!(series.entries.len(), 3);
+/// assert_eq!(series.entries[0].strip, 1);
+/// assert_eq!(series.entries[1].strip, 0);
+/// assert!(series.entries[2].reverse);
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Series {
+    /// The patches of the series, in order
+    pub entries: Vec<SeriesEntry>,
+}
+
+/// A patch of a [`Series`], with the options it is applied with
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SeriesEntry {
+    /// The name of the patch file, relative to the directory of the `series` file
+    pub name: String,
+    /// The number of leading components to remove from the paths in the patch, see
+    /// [`ApplyOptions::strip`]
+    pub strip: usize,
+    /// Whether the patch is applied in reverse
+    pub reverse: bool,
+}
+
+impl SeriesEntry {
+    /// An entry for the patch of the given name, applied with a strip level of 1
+    pub fn new(name: impl Into<String>) -> Self {
+        SeriesEntry {
+            name: name.into(),
+            strip: DEFAULT_STRIP,
+            reverse: false,
+        }
+    }
+}
+
+/// Error that can occur while parsing a `series` file with [`Series::parse`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SeriesError {
+    /// A patch is followed by an option quilt doesn't know about
+    InvalidOption {
+        /// The line of the option, counting from 1
+        line: usize,
+        /// The option
+        option: String,
+    },
+}
+
+impl fmt::Display for SeriesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeriesError::InvalidOption { line, option } => {
+                write!(f, "Line {}: invalid option '{}'", line, option)
+            }
+        }
+    }
+}
+
+impl Error for SeriesError {}
+
+impl Series {
+    /// Parse the content of a `series` file
+    pub fn parse(s: &str) -> Result<Self, SeriesError> {
+        let mut entries = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split_once('#').map_or(line, |(line, _)| line);
+            let mut words = line.split_whitespace();
+            let Some(name) = words.next() else { continue };
+
+            let mut entry = SeriesEntry::new(name);
+            for option in words {
+                let strip = option.strip_prefix("-p").and_then(|n| n.parse().ok());
+                match (option, strip) {
+                    (_, Some(strip)) => entry.strip = strip,
+                    ("-R", _) => entry.reverse = true,
+                    _ => {
+                        return Err(SeriesError::InvalidOption {
+                            line: i + 1,
+                            option: option.to_string(),
+                        });
+                    }
+                }
+            }
+            entries.push(entry);
+        }
+        Ok(Series { entries })
+    }
+
+    /// The entry of the patch of the given name, if it is in the series
+    pub fn get(&self, name: &str) -> Option<&SeriesEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+}
+
+/// Writes the series like quilt does, one patch per line, with its options if they aren't the
+/// default ones
+impl fmt::Display for Series {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            write!(f, "{}", entry.name)?;
+            if entry.strip != DEFAULT_STRIP {
+                write!(f, " -p{}", entry.strip)?;
+            }
+            if entry.reverse {
+                write!(f, " -R")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error that can occur while pushing or popping the patches of a [`PatchStack`]
+#[derive(Debug)]
+pub enum StackError {
+    /// A file of the stack could not be read or written
+    Io {
+        /// The path of the file
+        path: PathBuf,
+        /// The underlying error
+        error: io::Error,
+    },
+    /// The `series` file could not be parsed
+    Series(SeriesError),
+    /// A patch that is applied according to the stack isn't in the series, or isn't in the same
+    /// order
+    NotInSeries {
+        /// The name of the patch
+        name: String,
+    },
+    /// A patch file could not be parsed
+    Parse {
+        /// The name of the patch
+        name: String,
+        /// The underlying error
+        error: ParseError<'static>,
+    },
+    /// The name of a patch refers to a file outside of the patches directory, or a patch could
+    /// not be applied to the files
+    Apply {
+        /// The name of the patch
+        name: String,
+        /// The underlying error
+        error: Box<FsError>,
+    },
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            StackError::Series(error) => write!(f, "{}", error),
+            StackError::NotInSeries { name } => {
+                write!(f, "Patch '{}' is applied but not in the series", name)
+            }
+            StackError::Parse { name, error } => write!(f, "{}: {}", name, error),
+            StackError::Apply { name, error } => write!(f, "{}: {}", name, error),
+        }
+    }
+}
+
+impl Error for StackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StackError::Io { error, .. } => Some(error),
+            StackError::Series(error) => Some(error),
+            StackError::NotInSeries { .. } => None,
+            StackError::Parse { error, .. } => Some(error),
+            StackError::Apply { error, .. } => Some(error.as_ref()),
+        }
+    }
+}
+
+impl From<SeriesError> for StackError {
+    fn from(error: SeriesError) -> Self {
+        StackError::Series(error)
+    }
+}
+
+/// A stack of patches applied to a working directory, laid out like quilt does it
+///
+/// The series is read from `patches/series`, and the patches from the `patches` directory. The
+/// names of the applied patches are kept in `.pc/applied-patches`, so a stack can be opened again
+/// later, or by quilt itself. Patches are applied with [`apply_to_dir`], so pushing or popping a
+/// patch that doesn't apply changes nothing.
+///
+/// Unlike quilt, no copies of the files are kept in `.pc`: popping a patch applies it in
+/// reverse, which gives back the original files as long as they weren't changed since.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::Path;
+///
+/// use patch::{ApplyOptions, PatchStack};
+///
+/// let mut stack = PatchStack::open(Path::new(".")).unwrap();
+/// while let Some(report) = stack.push(&ApplyOptions::default()).unwrap() {
+///     println!("Applied {} ({} files changed)", stack.top().unwrap().name, report.modified.len());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PatchStack {
+    root: PathBuf,
+    series: Series,
+    applied: usize,
+}
+
+impl PatchStack {
+    /// Open the stack of patches of a working directory
+    ///
+    /// A missing `series` file is an empty series, and a missing `.pc/applied-patches` file means
+    /// that no patch is applied.
+    pub fn open(root: &Path) -> Result<Self, StackError> {
+        let series = match read_optional(&root.join("patches").join("series"))? {
+            Some(content) => Series::parse(&content)?,
+            None => Series::default(),
+        };
+
+        // The applied patches are always the first ones of the series
+        let applied_path = root.join(".pc").join("applied-patches");
+        let mut applied = 0;
+        for name in read_optional(&applied_path)?.unwrap_or_default().lines() {
+            let name = name.trim();
+            if name.is_empty() {
+                continue;
+            }
+            if series
+                .entries
+                .get(applied)
+                .is_none_or(|entry| entry.name != name)
+            {
+                return Err(StackError::NotInSeries {
+                    name: name.to_string(),
+                });
+            }
+            applied += 1;
+        }
+
+        Ok(PatchStack {
+            root: root.to_path_buf(),
+            series,
+            applied,
+        })
+    }
+
+    /// The series of the stack
+    pub fn series(&self) -> &Series {
+        &self.series
+    }
+
+    /// The patches that are applied, from the bottom of the stack to its top
+    pub fn applied(&self) -> &[SeriesEntry] {
+        &self.series.entries[..self.applied]
+    }
+
+    /// The patches that aren't applied yet, in the order they will be pushed
+    pub fn unapplied(&self) -> &[SeriesEntry] {
+        &self.series.entries[self.applied..]
+    }
+
+    /// The last patch that was applied, if any
+    pub fn top(&self) -> Option<&SeriesEntry> {
+        self.applied().last()
+    }
+
+    /// Apply the next patch of the series, like `quilt push`
+    ///
+    /// The strip level of the patch and whether it is reversed come from the series, and replace
+    /// [`ApplyOptions::strip`]. Returns the files that were changed, or `None` if every patch of
+    /// the series is already applied.
+    pub fn push(&mut self, options: &ApplyOptions) -> Result<Option<DirReport>, StackError> {
+        let Some(entry) = self.unapplied().first() else {
+            return Ok(None);
+        };
+        let report = self.apply(entry, false, options)?;
+        self.applied += 1;
+        self.save()?;
+        Ok(Some(report))
+    }
+
+    /// Remove the last applied patch, like `quilt pop`
+    ///
+    /// The patch is applied in reverse with the options of its series entry, see
+    /// [`PatchStack::push`]. Returns the files that were changed, or `None` if no patch is
+    /// applied.
+    pub fn pop(&mut self, options: &ApplyOptions) -> Result<Option<DirReport>, StackError> {
+        let Some(entry) = self.top() else {
+            return Ok(None);
+        };
+        let report = self.apply(entry, true, options)?;
+        self.applied -= 1;
+        self.save()?;
+        Ok(Some(report))
+    }
+
+    // Apply the patch of an entry to the working directory, in reverse if `pop` is true
+    fn apply(
+        &self,
+        entry: &SeriesEntry,
+        pop: bool,
+        options: &ApplyOptions,
+    ) -> Result<DirReport, StackError> {
+        let apply_error = |error: FsError| StackError::Apply {
+            name: entry.name.clone(),
+            error: Box::new(error),
+        };
+
+        let path =
+            strip_path(&entry.name, 0).map_err(|error: PatchSetError| apply_error(error.into()))?;
+        let path = self.root.join("patches").join(path);
+        let text = fs::read_to_string(&path).map_err(|error| StackError::Io { path, error })?;
+        let mut patches = PatchSet::parse(&text).map_err(|error| StackError::Parse {
+            name: entry.name.clone(),
+            error: error.into_owned(),
+        })?;
+        if entry.reverse != pop {
+            patches = patches
+                .patches
+                .iter()
+                .map(|patch| patch.reverse())
+                .collect::<Vec<_>>()
+                .into();
+        }
+
+        let options = ApplyOptions {
+            strip: entry.strip,
+            ..options.clone()
+        };
+        apply_to_dir(&patches, &self.root, &options).map_err(apply_error)
+    }
+
+    // Write the names of the applied patches to `.pc/applied-patches`
+    fn save(&self) -> Result<(), StackError> {
+        let dir = self.root.join(".pc");
+        let path = dir.join("applied-patches");
+        let content: String = self
+            .applied()
+            .iter()
+            .map(|entry| format!("{}\n", entry.name))
+            .collect();
+        fs::create_dir_all(&dir)
+            .and_then(|()| write_atomic(&path, &content))
+            .map_err(|error| StackError::Io { path, error })
+    }
+}
+
+// The content of a file, or `None` if it doesn't exist
+fn read_optional(path: &Path) -> Result<Option<String>, StackError> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(StackError::Io {
+            path: path.to_path_buf(),
+            error,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_series_round_trip() {
+        let series = Series::parse("a.patch\n\n# comment\nb.patch -p0 -R\nc.patch -p1\n").unwrap();
+        assert_eq!(
+            series.entries,
+            [
+                SeriesEntry::new("a.patch"),
+                SeriesEntry {
+                    name: "b.patch".into(),
+                    strip: 0,
+                    reverse: true,
+                },
+                SeriesEntry::new("c.patch"),
+            ]
+        );
+        assert_eq!(series.to_string(), "a.patch\nb.patch -p0 -R\nc.patch\n");
+    }
+
+    #[test]
+    fn test_series_invalid_option() {
+        assert_eq!(
+            Series::parse("a.patch\nb.patch -x\n").unwrap_err(),
+            SeriesError::InvalidOption {
+                line: 2,
+                option: "-x".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("patches")).unwrap();
+        fs::write(
+            root.join("patches/series"),
+            "first.patch\nsecond.patch -p0\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("patches/first.patch"),
+            "--- a/hello.txt\n+++ b/hello.txt\n@@ -1 +1 @@\n-hello\n+hello, world\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("patches/second.patch"),
+            "--- /dev/null\n+++ bye.txt\n@@ -0,0 +1 @@\n+bye\n",
+        )
+        .unwrap();
+        fs::write(root.join("hello.txt"), "hello\n").unwrap();
+
+        let options = ApplyOptions::default();
+        let mut stack = PatchStack::open(root).unwrap();
+        assert_eq!(stack.unapplied().len(), 2);
+
+        let report = stack.push(&options).unwrap().unwrap();
+        assert_eq!(report.modified, [PathBuf::from("hello.txt")]);
+        let report = stack.push(&options).unwrap().unwrap();
+        assert_eq!(report.created, [PathBuf::from("bye.txt")]);
+        assert!(stack.push(&options).unwrap().is_none());
+        assert_eq!(
+            fs::read_to_string(root.join(".pc/applied-patches")).unwrap(),
+            "first.patch\nsecond.patch\n"
+        );
+
+        // The applied state is kept on disk
+        let mut stack = PatchStack::open(root).unwrap();
+        assert_eq!(stack.top().unwrap().name, "second.patch");
+        stack.pop(&options).unwrap().unwrap();
+        assert!(!root.join("bye.txt").exists());
+        stack.pop(&options).unwrap().unwrap();
+        assert!(stack.pop(&options).unwrap().is_none());
+        assert_eq!(
+            fs::read_to_string(root.join("hello.txt")).unwrap(),
+            "hello\n"
+        );
+        assert!(stack.applied().is_empty());
+    }
+
+    #[test]
+    fn test_failed_push_changes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("patches")).unwrap();
+        fs::write(root.join("patches/series"), "broken.patch\n").unwrap();
+        fs::write(
+            root.join("patches/broken.patch"),
+            "--- a/hello.txt\n+++ b/hello.txt\n@@ -1 +1 @@\n-goodbye\n+hello, world\n",
+        )
+        .unwrap();
+        fs::write(root.join("hello.txt"), "hello\n").unwrap();
+
+        let mut stack = PatchStack::open(root).unwrap();
+        let error = stack.push(&ApplyOptions::default()).unwrap_err();
+        assert!(matches!(error, StackError::Apply { .. }));
+        assert!(stack.applied().is_empty());
+        assert!(!root.join(".pc").exists());
+    }
+}
@@ -0,0 +1,429 @@
+use std::borrow::Cow;
+use std::io;
+
+use crate::applier::{ApplyError, check_overlap, range_index};
+use crate::ast::{Line, Patch};
+
+/// Content made of lines that can be read one at a time, which [`apply_source`] applies a patch to
+///
+/// Implementing it for a memory-mapped file, a rope or a database of lines lets a patch be
+/// applied without copying the whole content into a string first.
+pub trait LineSource {
+    /// The number of lines of the content
+    fn len(&self) -> usize;
+
+    /// True if the content has no lines
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The text of the line at the given index, counting from 0, without its line ending
+    fn line(&self, index: usize) -> Cow<'_, str>;
+
+    /// The line ending of the line at the given index, like `"\n"` or `"\r\n"`, or `None` for a
+    /// last line that doesn't end with a newline
+    fn line_ending(&self, index: usize) -> Option<&str>;
+}
+
+/// Every line of a slice of strings ends with `\n`
+impl LineSource for [&str] {
+    fn len(&self) -> usize {
+        <[&str]>::len(self)
+    }
+
+    fn line(&self, index: usize) -> Cow<'_, str> {
+        Cow::Borrowed(self[index])
+    }
+
+    fn line_ending(&self, _: usize) -> Option<&str> {
+        Some("\n")
+    }
+}
+
+/// Every line of a slice of strings ends with `\n`
+impl LineSource for [String] {
+    fn len(&self) -> usize {
+        <[String]>::len(self)
+    }
+
+    fn line(&self, index: usize) -> Cow<'_, str> {
+        Cow::Borrowed(&self[index])
+    }
+
+    fn line_ending(&self, _: usize) -> Option<&str> {
+        Some("\n")
+    }
+}
+
+/// Where [`apply_source`] writes the patched content, one piece of text at a time
+pub trait LineSink {
+    /// Write the next piece of the output, which is a line or a line ending
+    fn write(&mut self, text: &str) -> io::Result<()>;
+}
+
+impl LineSink for String {
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.push_str(text);
+        Ok(())
+    }
+}
+
+impl LineSink for Vec<u8> {
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.extend_from_slice(text.as_bytes());
+        Ok(())
+    }
+}
+
+/// Apply a patch to content read line by line from a [`LineSource`], writing the output to a
+/// [`LineSink`]
+///
+/// This works like [`apply`](crate::apply): the hunks have to match at the line numbers of the
+/// patch, added lines take the line ending of the line before them and the content keeps its
+/// own ending unless the last hunk reaches the end of it. Only the lines the hunks touch are
+/// compared, and unchanged lines are copied to the sink as the source gives them. If the patch
+/// can't be applied, the output up to the failing hunk may already have been written.
+///
+/// # Example
+///
+/// ```
+/// use patch::{Patch, apply_source};
+///
+/// let lines = vec!["line 1".to_string(), "line 2".to_string(), "line 3".to_string()];
+/// let patch = Patch::from_single(
+///     "\
+/// --- old.txt
+/// +++ new.txt
+/// @@ -1,3 +1,3 @@
+///  line 1
+/// -line 2
+/// +new line 2
+///  line 3
+/// ",
+/// )
+/// .unwrap();
+///
+/// let mut output = String::new();
+/// apply_source(&patch, lines.as_slice(), &mut output).unwrap();
+/// assert_eq!(output, "line 1\nnew line 2\nline 3\n");
+/// ```
+pub fn apply_source<S: LineSource + ?Sized>(
+    patch: &Patch,
+    source: &S,
+    sink: &mut impl LineSink,
+) -> Result<(), ApplyError> {
+    let len = source.len();
+    let mut target = SourceTarget {
+        source,
+        output: Output {
+            sink,
+            pending: None,
+            previous: match len {
+                0 => "\n",
+                _ => source.line_ending(0).unwrap_or("\n"),
+            },
+        },
+        index: 0,
+    };
+    let reached_end = apply_exact(patch, &mut target)?;
+    target.keep_to(len)?;
+
+    // The patch decides whether the output ends in a newline character if its last hunk reached
+    // the end of the content, otherwise the content keeps its own ending
+    let end_newline = if reached_end {
+        patch.new_end_newline
+    } else {
+        len > 0 && source.line_ending(len - 1).is_some()
+    };
+    target.output.finish(end_newline)?;
+    Ok(())
+}
+
+/// Content a patch is applied to exactly at the line numbers it gives, read one line at a time
+/// and in order, along with where the output goes, see [`apply_exact`]
+pub(crate) trait ExactTarget<'p> {
+    /// The index of the next line of the content
+    fn position(&self) -> usize;
+
+    /// Keep the lines from the next one up to the one at `end`, or up to the end of the content
+    /// if it has fewer lines
+    fn keep_to(&mut self, end: usize) -> Result<(), ApplyError>;
+
+    /// Read the next line if its text is `expected`, keeping it in the output if `keep`
+    fn next_line(&mut self, expected: &str, keep: bool) -> Result<NextLine, ApplyError>;
+
+    /// Add a line to the output, which takes the line ending of the line before it
+    fn add(&mut self, text: &'p str) -> Result<(), ApplyError>;
+
+    /// Whether every line of the content has been read
+    fn at_end(&mut self) -> bool;
+}
+
+/// What [`ExactTarget::next_line`] found
+pub(crate) enum NextLine {
+    /// The line that was expected
+    Matched,
+    /// Another line, with its text
+    Mismatch(String),
+    /// The end of the content
+    End,
+}
+
+// Apply the hunks of a patch exactly at the line numbers it gives, checking that they don't
+// overlap, which is the loop shared by `apply_source`, `apply_to_rope` and `apply_mmap`. The lines
+// after the last hunk are left to the caller. Returns whether the last hunk reached the end of
+// the content.
+pub(crate) fn apply_exact<'p>(
+    patch: &'p Patch,
+    target: &mut impl ExactTarget<'p>,
+) -> Result<bool, ApplyError> {
+    if patch.is_binary() {
+        return Err(ApplyError::Binary);
+    }
+
+    // Whether the last hunk was applied up to the end of the content
+    let mut reached_end = false;
+    for (i, hunk) in patch.hunks.iter().enumerate() {
+        check_overlap(patch, i)?;
+        let start = range_index(&hunk.old_range);
+        target.keep_to(start)?;
+        if target.position() < start {
+            return Err(ApplyError::LineOutOfBounds {
+                line: target.position() as u64 + 1,
+                total_lines: target.position(),
+            });
+        }
+
+        for line in &hunk.lines {
+            match line {
+                Line::Context(text) | Line::Remove(text) => {
+                    let position = target.position();
+                    match target.next_line(text, matches!(line, Line::Context(_)))? {
+                        NextLine::Matched => {}
+                        NextLine::Mismatch(actual) => {
+                            return Err(ApplyError::ContextMismatch {
+                                line: position as u64 + 1,
+                                expected: text.to_string(),
+                                actual,
+                            });
+                        }
+                        NextLine::End => {
+                            return Err(ApplyError::LineOutOfBounds {
+                                line: position as u64 + 1,
+                                total_lines: position,
+                            });
+                        }
+                    }
+                }
+                Line::Add(text) => target.add(text)?,
+            }
+        }
+        reached_end = target.at_end();
+    }
+    Ok(reached_end)
+}
+
+// Applies a patch to the lines of a source, writing them to a sink
+struct SourceTarget<'s, 'x, S: ?Sized, K> {
+    source: &'x S,
+    output: Output<'s, 'x, K>,
+    /// The index of the next line of the source
+    index: usize,
+}
+
+impl<'x, S: LineSource + ?Sized, K: LineSink> ExactTarget<'x> for SourceTarget<'_, 'x, S, K> {
+    fn position(&self) -> usize {
+        self.index
+    }
+
+    fn keep_to(&mut self, end: usize) -> Result<(), ApplyError> {
+        let end = end.min(self.source.len());
+        for index in self.index..end {
+            let source = self.source;
+            self.output
+                .push(source.line(index), source.line_ending(index))?;
+        }
+        self.index = self.index.max(end);
+        Ok(())
+    }
+
+    fn next_line(&mut self, expected: &str, keep: bool) -> Result<NextLine, ApplyError> {
+        let source = self.source;
+        if self.index >= source.len() {
+            return Ok(NextLine::End);
+        }
+        let actual = source.line(self.index);
+        if actual != expected {
+            return Ok(NextLine::Mismatch(actual.into_owned()));
+        }
+        if keep {
+            self.output.push(actual, source.line_ending(self.index))?;
+        }
+        self.index += 1;
+        Ok(NextLine::Matched)
+    }
+
+    fn add(&mut self, text: &'x str) -> Result<(), ApplyError> {
+        Ok(self.output.push(Cow::Borrowed(text), None)?)
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.index == self.source.len()
+    }
+}
+
+/// The lines of the output, which are written to the sink as soon as their line ending is known
+struct Output<'s, 'x, S> {
+    sink: &'s mut S,
+    /// The last line and the line ending it had in the content, if any. It only gets a line
+    /// ending once another line follows it, or if the output ends in a newline character.
+    pending: Option<(Cow<'x, str>, Option<&'x str>)>,
+    /// The line ending given to lines that don't have their own
+    previous: &'x str,
+}
+
+impl<'x, S: LineSink> Output<'_, 'x, S> {
+    fn push(&mut self, line: Cow<'x, str>, ending: Option<&'x str>) -> io::Result<()> {
+        match self.pending.replace((line, ending)) {
+            Some(pending) => self.write(pending, true),
+            None => Ok(()),
+        }
+    }
+
+    // Lines without an ending of their own take the one of the line before them
+    fn write(
+        &mut self,
+        (line, ending): (Cow<'x, str>, Option<&'x str>),
+        with_ending: bool,
+    ) -> io::Result<()> {
+        let ending = ending.unwrap_or(self.previous);
+        self.previous = ending;
+        self.sink.write(&line)?;
+        if with_ending {
+            self.sink.write(ending)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, end_newline: bool) -> io::Result<()> {
+        match self.pending.take() {
+            Some(pending) => self.write(pending, end_newline),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::applier::apply;
+
+    // A source with the lines and line endings of a string
+    struct Text<'a>(Vec<(&'a str, Option<&'a str>)>);
+
+    impl<'a> Text<'a> {
+        fn new(content: &'a str) -> Self {
+            Text(
+                content
+                    .split_inclusive('\n')
+                    .map(|line| match line.strip_suffix("\r\n") {
+                        Some(line) => (line, Some("\r\n")),
+                        None => match line.strip_suffix('\n') {
+                            Some(line) => (line, Some("\n")),
+                            None => (line, None),
+                        },
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    impl LineSource for Text<'_> {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn line(&self, index: usize) -> Cow<'_, str> {
+            Cow::Borrowed(self.0[index].0)
+        }
+
+        fn line_ending(&self, index: usize) -> Option<&str> {
+            self.0[index].1
+        }
+    }
+
+    // Applying to a source gives the same text as applying to a string
+    fn check_source(patch: &str, content: &str) -> String {
+        let patch = Patch::from_single(patch).unwrap();
+        let mut output = String::new();
+        apply_source(&patch, &Text::new(content), &mut output).unwrap();
+        assert_eq!(output, apply(&patch, content).unwrap());
+        output
+    }
+
+    #[test]
+    fn test_apply_source() {
+        let patch = "\
+--- a.txt
++++ a.txt
+@@ -1,3 +1,2 @@
+ a
+-b
+-c
+@@ -4,0 +4,1 @@
++x
+@@ -6 +6 @@
+-f
++F
+";
+        assert_eq!(check_source(patch, "a\nb\nc\nd\ne\nf\n"), "a\nd\nx\ne\nF\n");
+        assert_eq!(
+            check_source(patch, "a\r\nb\r\nc\r\nd\r\ne\r\nf\r\n"),
+            "a\r\nd\r\nx\r\ne\r\nF\r\n"
+        );
+
+        let add = "--- a.txt\n+++ a.txt\n@@ -1 +1,2 @@\n a\n+b\n";
+        assert_eq!(check_source(add, "a"), "a\nb\n");
+        let newline = "--- a.txt\n+++ a.txt\n@@ -1 +1 @@\n-a\n+a\n\\ No newline at end of file\n";
+        assert_eq!(check_source(newline, "a\n"), "a");
+    }
+
+    #[test]
+    fn test_apply_source_mismatch() {
+        let patch =
+            Patch::from_single("--- a.txt\n+++ a.txt\n@@ -2,2 +2,2 @@\n b\n-x\n+X\n").unwrap();
+        let lines = ["a", "b", "c"];
+        let result = apply_source(&patch, &lines[..], &mut String::new());
+        assert!(matches!(
+            result,
+            Err(ApplyError::ContextMismatch { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_apply_source_overlapping_hunks() {
+        let patch = Patch::from_single(
+            "\
+--- a.txt
++++ a.txt
+@@ -1,2 +1,2 @@
+ a
+-b
++B
+@@ -1,2 +1,2 @@
+ a
+-b
++B
+",
+        )
+        .unwrap();
+        let result = apply_source(&patch, &Text::new("a\nb\na\nb\nc\n"), &mut String::new());
+        assert!(matches!(
+            result,
+            Err(ApplyError::OverlappingHunks { hunk: 1, line: 1 })
+        ));
+    }
+}
@@ -0,0 +1,392 @@
+use std::fmt;
+
+use crate::ast::{Line, LineStats, Patch};
+use crate::patch_set::{PatchSet, file_name};
+
+/// How many lines a patch changes in a file, see [`DiffStat`]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct FileStat {
+    /// The path of the file, without the `a/` or `b/` prefix of git patches
+    pub path: String,
+    /// The number of lines added
+    pub insertions: usize,
+    /// The number of lines removed
+    pub deletions: usize,
+    /// The number of lines replaced by another one, which are counted both as an insertion and
+    /// as a deletion
+    ///
+    /// Within every run of changed lines, as many lines as the smaller of the number of removed
+    /// and added lines count as replaced, like `diffstat -m` does it.
+    pub modifications: usize,
+    /// Whether the patch changes a binary file, whose lines aren't counted
+    pub binary: bool,
+}
+
+impl FileStat {
+    /// Count the lines changed by a patch
+    pub fn from_patch(patch: &Patch) -> FileStat {
+        let mut stat = FileStat {
+            path: file_name(patch).to_string(),
+            binary: patch.is_binary(),
+            ..FileStat::default()
+        };
+        for hunk in &patch.hunks {
+            // The lines removed and added by the current run of changes
+            let (mut removed, mut added) = (0, 0);
+            for line in hunk.lines.iter().chain([&Line::Context("".into())]) {
+                match line {
+                    Line::Add(_) => added += 1,
+                    Line::Remove(_) => removed += 1,
+                    Line::Context(_) => {
+                        stat.insertions += added;
+                        stat.deletions += removed;
+                        stat.modifications += removed.min(added);
+                        (removed, added) = (0, 0);
+                    }
+                }
+            }
+        }
+        stat
+    }
+}
+
+/// The number of lines changed in every file of a patch, like `diffstat` or `git diff --stat`
+///
+/// Formatting a `DiffStat` with `Display` draws the familiar histogram in 80 columns, and
+/// [`DiffStat::render`] draws it in any number of columns.
+///
+/// # Example
+///
+/// ```
+/// use patch::{DiffStat, PatchSet};
+///
+/// let sample = "\
+/// --- a/src/lib.rs
+/// +++ b/src/lib.rs
+/// @@ -1,3 +1,4 @@
+///  fn a() {}
+/// -fn b() {}
+/// +fn b() -> u8 { 1 }
+/// +fn c() {}
+///  fn d() {}
+/// --- a/README.md
+/// +++ b/README.md
+/// @@ -1 +1 @@
+/// -# Old
+/// +# New
+/// ";
+///
+/// let stat = DiffStat::from_patch_set(&PatchSet::parse(sample).unwrap());
+/// assert_eq!(stat.insertions(), 3);
+/// assert_eq!(
+///     stat.to_string(),
+///     " src/lib.rs | 3 ++-
+///  README.md  | 2 +-
+///  2 files changed, 3 insertions(+), 2 deletions(-)"
+/// );
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DiffStat {
+    /// The statistics of every file, in the order of the patches
+    pub files: Vec<FileStat>,
+}
+
+impl DiffStat {
+    /// Count the lines changed by a patch
+    pub fn from_patch(patch: &Patch) -> DiffStat {
+        DiffStat {
+            files: vec![FileStat::from_patch(patch)],
+        }
+    }
+
+    /// Count the lines changed by every patch of a set, with one entry per file
+    ///
+    /// The counts of several patches changing the same file are added up.
+    pub fn from_patch_set(patches: &PatchSet) -> DiffStat {
+        let mut files: Vec<FileStat> = Vec::new();
+        for patch in patches {
+            let stat = FileStat::from_patch(patch);
+            match files.iter_mut().find(|file| file.path == stat.path) {
+                Some(file) => {
+                    file.insertions += stat.insertions;
+                    file.deletions += stat.deletions;
+                    file.modifications += stat.modifications;
+                    file.binary |= stat.binary;
+                }
+                None => files.push(stat),
+            }
+        }
+        DiffStat { files }
+    }
+
+    /// The number of lines added to all files
+    pub fn insertions(&self) -> usize {
+        self.files.iter().map(|file| file.insertions).sum()
+    }
+
+    /// The number of lines removed from all files
+    pub fn deletions(&self) -> usize {
+        self.files.iter().map(|file| file.deletions).sum()
+    }
+
+    /// Draw the histogram of the changes so that its lines fit in `width` columns
+    ///
+    /// Every file gets a line with its path, the number of lines changed and a bar of `+` and
+    /// `-` signs, scaled down if the largest change doesn't fit, like `git diff --stat=<width>`.
+    /// A last line sums the changes up. Paths aren't shortened, so lines with long paths may
+    /// still be wider than `width`.
+    pub fn render(&self, width: usize) -> String {
+        let mut output = String::new();
+        let changes = |file: &FileStat| file.insertions + file.deletions;
+        let name_width = self
+            .files
+            .iter()
+            .map(|file| file.path.chars().count())
+            .max()
+            .unwrap_or(0);
+        let max_change = self.files.iter().map(changes).max().unwrap_or(0);
+        let count_width = if self.files.iter().any(|file| file.binary) {
+            max_change.to_string().len().max("Bin".len())
+        } else {
+            max_change.to_string().len()
+        };
+        // " name | count " comes before the bar
+        let graph_width = width.saturating_sub(name_width + count_width + 4).max(1);
+
+        for file in &self.files {
+            let padding = name_width - file.path.chars().count();
+            output.push_str(&format!(" {}{} | ", file.path, " ".repeat(padding)));
+            if file.binary {
+                output.push_str(&format!("{:>count_width$}\n", "Bin"));
+                continue;
+            }
+            let (added, removed) = scale(file.insertions, file.deletions, graph_width, max_change);
+            output.push_str(&format!(
+                "{:>count_width$} {}{}",
+                changes(file),
+                "+".repeat(added),
+                "-".repeat(removed)
+            ));
+            output.truncate(output.trim_end_matches(' ').len());
+            output.push('\n');
+        }
+
+        let plural = |count: usize, word: &str| {
+            format!("{} {}{}", count, word, if count == 1 { "" } else { "s" })
+        };
+        output.push_str(&format!(" {} changed", plural(self.files.len(), "file")));
+        let (insertions, deletions) = (self.insertions(), self.deletions());
+        if insertions > 0 || deletions == 0 {
+            output.push_str(&format!(", {}(+)", plural(insertions, "insertion")));
+        }
+        if deletions > 0 || insertions == 0 {
+            output.push_str(&format!(", {}(-)", plural(deletions, "deletion")));
+        }
+        output
+    }
+}
+
+impl PatchSet<'_> {
+    /// Count the lines of all hunks of every patch of the set. See [`Patch::stats`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use patch::PatchSet;
+    ///
+    /// let sample = "\
+    /// --- a.txt
+    /// +++ a.txt
+    /// @@ -1 +1,2 @@
+    ///  a
+    /// +b
+    /// --- c.txt
+    /// +++ c.txt
+    /// @@ -1,2 +0,0 @@
+    /// -c
+    /// -d
+    /// ";
+    ///
+    /// let totals = PatchSet::parse(sample).unwrap().totals();
+    /// assert_eq!((totals.additions, totals.deletions, totals.context), (1, 2, 1));
+    /// assert_eq!(totals.net(), -1);
+    /// ```
+    pub fn totals(&self) -> LineStats {
+        self.patches.iter().map(Patch::stats).sum()
+    }
+}
+
+impl fmt::Display for DiffStat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(80))
+    }
+}
+
+// The number of `+` and `-` signs in the bar of a file, scaled down like git does it if the
+// largest change doesn't fit in `width`
+fn scale(added: usize, removed: usize, width: usize, max_change: usize) -> (usize, usize) {
+    if max_change <= width {
+        return (added, removed);
+    }
+    let linear = |count: usize| {
+        if count == 0 {
+            0
+        } else {
+            1 + count * (width - 1) / max_change
+        }
+    };
+    let mut total = linear(added + removed);
+    if total < 2 && added > 0 && removed > 0 {
+        total = 2;
+    }
+    if added < removed {
+        let added = linear(added);
+        (added, total - added)
+    } else {
+        let removed = linear(removed);
+        (total - removed, removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::ast::Hunk;
+
+    #[test]
+    fn test_file_stat() {
+        let patch = Patch::from_single(
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -1,6 +1,6 @@
+ a
+-b
+-c
++B
+ d
++e
++f
+-g
++G
+",
+        )
+        .unwrap();
+        assert_eq!(
+            FileStat::from_patch(&patch),
+            FileStat {
+                path: "file.txt".into(),
+                insertions: 4,
+                deletions: 3,
+                modifications: 2,
+                binary: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_totals() {
+        let set = PatchSet::parse(
+            "\
+--- a/file.txt
++++ b/file.txt
+@@ -1,3 +1,2 @@
+ a
+-b
+-c
+@@ -9,2 +8,4 @@
+ i
++j
++k
+ l
+diff --git a/image.png b/image.png
+Binary files a/image.png and b/image.png differ
+",
+        )
+        .unwrap();
+        let stats: Vec<_> = set.patches[0].hunks.iter().map(Hunk::stats).collect();
+        assert_eq!(
+            stats,
+            [
+                LineStats {
+                    additions: 0,
+                    deletions: 2,
+                    context: 1,
+                },
+                LineStats {
+                    additions: 2,
+                    deletions: 0,
+                    context: 2,
+                },
+            ]
+        );
+        assert_eq!(set.patches[1].stats(), LineStats::default());
+        assert_eq!(
+            set.totals(),
+            LineStats {
+                additions: 2,
+                deletions: 2,
+                context: 3,
+            }
+        );
+        assert_eq!(set.totals().net(), 0);
+        assert_eq!(PatchSet::default().totals(), LineStats::default());
+    }
+
+    #[test]
+    fn test_render_scales_down() {
+        let stat = DiffStat {
+            files: vec![
+                FileStat {
+                    path: "big.txt".into(),
+                    insertions: 300,
+                    deletions: 100,
+                    ..FileStat::default()
+                },
+                FileStat {
+                    path: "small.txt".into(),
+                    insertions: 1,
+                    ..FileStat::default()
+                },
+                FileStat {
+                    path: "image.png".into(),
+                    binary: true,
+                    ..FileStat::default()
+                },
+            ],
+        };
+        assert_eq!(
+            stat.render(40),
+            format!(
+                " big.txt   | 400 {}{}
+ small.txt |   1 +
+ image.png | Bin
+ 3 files changed, 301 insertions(+), 100 deletions(-)",
+                "+".repeat(18),
+                "-".repeat(6)
+            )
+        );
+    }
+
+    #[test]
+    fn test_render_summary() {
+        let stat = DiffStat {
+            files: vec![FileStat {
+                path: "gone.txt".into(),
+                deletions: 1,
+                ..FileStat::default()
+            }],
+        };
+        assert_eq!(
+            stat.to_string(),
+            " gone.txt | 1 -\n 1 file changed, 1 deletion(-)"
+        );
+        assert_eq!(
+            DiffStat::default().to_string(),
+            " 0 files changed, 0 insertions(+), 0 deletions(-)"
+        );
+    }
+}
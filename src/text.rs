@@ -0,0 +1,190 @@
+use alloc::borrow::{Cow, ToOwned};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::hash::Hash;
+use core::ops::Range;
+
+/// The kinds of content patches can be made of: `str` for UTF-8 text and `[u8]` for anything else
+pub(crate) trait Text: Debug + Eq + Hash + ToOwned {
+    /// Split into lines, separating each one from its line ending
+    ///
+    /// Lines end in `\n` or `\r\n`. The last line has no line ending if the text doesn't end in
+    /// a newline character.
+    fn lines_with_endings(&self) -> Vec<(&Self, Option<&Self>)>;
+
+    /// True if the last line ends in a newline character
+    fn ends_with_newline(&self) -> bool;
+
+    /// The same text as a `str`, replacing invalid UTF-8 sequences
+    fn to_str_lossy(&self) -> Cow<'_, str>;
+
+    /// The content equivalent to the given text
+    fn literal(text: &str) -> &Self;
+
+    /// The bytes of the content
+    fn as_bytes(&self) -> &[u8];
+
+    /// The part of the content between two byte offsets
+    fn slice(&self, range: Range<usize>) -> &Self;
+
+    /// The same text without the whitespace at its end
+    fn trim_end_whitespace(&self) -> &Self;
+
+    /// True if both texts are the same after removing all of their whitespace
+    fn eq_ignoring_whitespace(&self, other: &Self) -> bool;
+
+    /// True if the text is empty or only made of whitespace
+    fn is_blank(&self) -> bool;
+}
+
+impl Text for str {
+    fn lines_with_endings(&self) -> Vec<(&str, Option<&str>)> {
+        // The line endings are borrowed from the text too, so the output of an applied patch
+        // can borrow whole runs of unchanged lines
+        self.split_inclusive('\n')
+            .map(|line| {
+                let len = if line.ends_with("\r\n") {
+                    2
+                } else {
+                    usize::from(line.ends_with('\n'))
+                };
+                let (text, ending) = line.split_at(line.len() - len);
+                (text, Some(ending).filter(|ending| !ending.is_empty()))
+            })
+            .collect()
+    }
+
+    fn ends_with_newline(&self) -> bool {
+        self.ends_with('\n')
+    }
+
+    fn to_str_lossy(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self)
+    }
+
+    fn literal(text: &str) -> &str {
+        text
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        str::as_bytes(self)
+    }
+
+    fn slice(&self, range: Range<usize>) -> &str {
+        &self[range]
+    }
+
+    fn trim_end_whitespace(&self) -> &str {
+        self.trim_end()
+    }
+
+    fn eq_ignoring_whitespace(&self, other: &str) -> bool {
+        let not_whitespace = |c: &char| !c.is_whitespace();
+        self.chars()
+            .filter(not_whitespace)
+            .eq(other.chars().filter(not_whitespace))
+    }
+
+    fn is_blank(&self) -> bool {
+        self.trim_start().is_empty()
+    }
+}
+
+impl Text for [u8] {
+    fn lines_with_endings(&self) -> Vec<(&[u8], Option<&[u8]>)> {
+        self.split_inclusive(|&byte| byte == b'\n')
+            .map(|line| {
+                let len = if line.ends_with(b"\r\n") {
+                    2
+                } else {
+                    usize::from(line.ends_with(b"\n"))
+                };
+                let (text, ending) = line.split_at(line.len() - len);
+                (text, Some(ending).filter(|ending| !ending.is_empty()))
+            })
+            .collect()
+    }
+
+    fn ends_with_newline(&self) -> bool {
+        self.ends_with(b"\n")
+    }
+
+    fn to_str_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self)
+    }
+
+    fn literal(text: &str) -> &[u8] {
+        text.as_bytes()
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+
+    fn slice(&self, range: Range<usize>) -> &[u8] {
+        &self[range]
+    }
+
+    fn trim_end_whitespace(&self) -> &[u8] {
+        self.trim_ascii_end()
+    }
+
+    fn eq_ignoring_whitespace(&self, other: &[u8]) -> bool {
+        let not_whitespace = |byte: &&u8| !byte.is_ascii_whitespace();
+        self.iter()
+            .filter(not_whitespace)
+            .eq(other.iter().filter(not_whitespace))
+    }
+
+    fn is_blank(&self) -> bool {
+        self.trim_ascii_start().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_lines_with_endings() {
+        assert_eq!(
+            "a\r\nb\nc".lines_with_endings(),
+            [("a", Some("\r\n")), ("b", Some("\n")), ("c", None)]
+        );
+        assert_eq!("".lines_with_endings(), []);
+
+        for sample in ["", "\n", "a", "a\n", "a\r\nb", "a\r\r\n\nb\n", "\r\n\r\n"] {
+            // The lines and their endings make up the whole text
+            let lines = sample.as_bytes().lines_with_endings();
+            let parts: Vec<&[u8]> = lines
+                .iter()
+                .flat_map(|(line, ending)| [*line, ending.unwrap_or_default()])
+                .collect();
+            assert_eq!(parts.concat(), sample.as_bytes());
+
+            // The lines are the same for text and bytes, and the same as `str::lines`
+            let text_lines: Vec<&str> = sample.lines_with_endings().iter().map(|l| l.0).collect();
+            let byte_lines: Vec<&[u8]> = lines.iter().map(|l| l.0).collect();
+            let expected: Vec<&str> = sample.lines().collect();
+            assert_eq!(text_lines, expected, "{:?}", sample);
+            assert_eq!(
+                byte_lines,
+                expected.iter().map(|l| l.as_bytes()).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_whitespace() {
+        assert_eq!(" a \t".trim_end_whitespace(), " a");
+        assert_eq!(b" a \t".trim_end_whitespace(), b" a");
+        assert!("a b\tc".eq_ignoring_whitespace(" abc "));
+        assert!(b"a b\tc"[..].eq_ignoring_whitespace(b" abc "));
+        assert!(!"a b".eq_ignoring_whitespace("ab c d"));
+        assert!(" \t".is_blank() && "".is_blank() && !" a".is_blank());
+        assert!(b" \t"[..].is_blank() && !b" a"[..].is_blank());
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::fs::{temp_path, write_atomic};
+
+/// The mode [`MemoryFs`] gives to the files it creates
+const DEFAULT_MODE: u32 = 0o644;
+
+/// The mode git gives to symbolic links
+pub(crate) const SYMLINK_MODE: u32 = 0o120000;
+
+/// A filesystem that patches can be applied to with [`apply_to_vfs`](crate::apply_to_vfs)
+///
+/// Paths are relative to the root of the filesystem, and have been checked not to be absolute or
+/// contain `..`. [`DiskFs`] is a directory on disk, and [`MemoryFs`] keeps its files in memory,
+/// for testing code that applies patches without touching the disk.
+pub trait Vfs {
+    /// The content of a file, failing with [`io::ErrorKind::NotFound`] if it doesn't exist
+    fn read(&self, path: &Path) -> io::Result<String>;
+
+    /// True if there is a file, or anything else, at the path
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Replace the content of a file, creating it and its parent directories if needed
+    ///
+    /// Readers should never observe a partially written file.
+    fn write(&mut self, path: &Path, content: &str) -> io::Result<()>;
+
+    /// Move a file to another path, replacing any file there
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Remove a file
+    fn delete(&mut self, path: &Path) -> io::Result<()>;
+
+    /// Set the Unix permission bits of a file, like `chmod`
+    fn set_mode(&mut self, path: &Path, mode: u32) -> io::Result<()>;
+
+    /// The target of the symbolic link at the path, or `None` if there is no link there
+    fn read_link(&self, path: &Path) -> io::Result<Option<String>>;
+
+    /// Make the path a symbolic link to `target`, replacing any file there and creating its
+    /// parent directories if needed
+    fn symlink(&mut self, path: &Path, target: &str) -> io::Result<()>;
+}
+
+/// The files of a directory on disk
+///
+/// Files are written to a temporary file next to them, which then replaces them with a rename,
+/// keeping their permissions. Setting the mode of files does nothing on platforms other than
+/// Unix.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DiskFs {
+    root: PathBuf,
+}
+
+impl DiskFs {
+    /// The files in the given directory
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        DiskFs { root: root.into() }
+    }
+
+    /// The directory the paths are relative to
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Vfs for DiskFs {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(self.root.join(path))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        fs::symlink_metadata(self.root.join(path)).is_ok()
+    }
+
+    fn write(&mut self, path: &Path, content: &str) -> io::Result<()> {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(&path, content)
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        let to = self.root.join(to);
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(self.root.join(from), to)
+    }
+
+    fn delete(&mut self, path: &Path) -> io::Result<()> {
+        fs::remove_file(self.root.join(path))
+    }
+
+    #[cfg(unix)]
+    fn set_mode(&mut self, path: &Path, mode: u32) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(self.root.join(path), fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(not(unix))]
+    fn set_mode(&mut self, _: &Path, _: u32) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<Option<String>> {
+        let path = self.root.join(path);
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let target = fs::read_link(&path)?;
+                let target = target.into_os_string().into_string().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "link target is not UTF-8")
+                })?;
+                Ok(Some(target))
+            }
+            Ok(_) => Ok(None),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn symlink(&mut self, path: &Path, target: &str) -> io::Result<()> {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Replace the file with a rename, like `write` does
+        let temp_path = temp_path(&path);
+        make_symlink(target, &temp_path)?;
+        fs::rename(&temp_path, &path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp_path);
+        })
+    }
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &str, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(windows)]
+fn make_symlink(target: &str, path: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, path)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn make_symlink(_: &str, _: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Files kept in memory, with their mode
+///
+/// Directories aren't represented, so writing a file never fails. Symbolic links are files with
+/// the mode `0o120000`, like git stores them, whose content is their target.
+///
+/// # Example
+///
+/// ```
+/// use patch::{ApplyOptions, MemoryFs, PatchSet, apply_to_vfs};
+///
+/// let mut files = MemoryFs::new();
+/// files.insert("src/lib.rs", "old\n");
+///
+/// let diff = "--- src/lib.rs\n+++ src/lib.rs\n@@ -1 +1 @@\n-old\n+new\n";
+/// let patches = PatchSet::parse(diff).unwrap();
+/// apply_to_vfs(&patches, &mut files, &ApplyOptions::default()).unwrap();
+/// assert_eq!(files.get("src/lib.rs"), Some("new\n"));
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MemoryFs {
+    files: BTreeMap<PathBuf, (String, u32)>,
+}
+
+impl MemoryFs {
+    /// An empty filesystem
+    pub fn new() -> Self {
+        MemoryFs::default()
+    }
+
+    /// Add a file, or replace the content of an existing one, keeping its mode. New files, and
+    /// files replacing symbolic links, have the mode `0o644`.
+    pub fn insert(&mut self, path: impl Into<PathBuf>, content: impl Into<String>) {
+        let content = content.into();
+        let file = self
+            .files
+            .entry(path.into())
+            .or_insert((String::new(), DEFAULT_MODE));
+        file.0 = content;
+        if file.1 == SYMLINK_MODE {
+            file.1 = DEFAULT_MODE;
+        }
+    }
+
+    /// Add a symbolic link to `target`, replacing any file at the path
+    pub fn insert_symlink(&mut self, path: impl Into<PathBuf>, target: impl Into<String>) {
+        self.files
+            .insert(path.into(), (target.into(), SYMLINK_MODE));
+    }
+
+    /// The content of a file
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<&str> {
+        self.files
+            .get(path.as_ref())
+            .map(|(content, _)| content.as_str())
+    }
+
+    /// The mode of a file
+    pub fn mode(&self, path: impl AsRef<Path>) -> Option<u32> {
+        self.files.get(path.as_ref()).map(|&(_, mode)| mode)
+    }
+
+    /// The paths of the files, in order
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(PathBuf::as_path)
+    }
+}
+
+impl Vfs for MemoryFs {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        self.get(path).map(str::to_string).ok_or_else(not_found)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn write(&mut self, path: &Path, content: &str) -> io::Result<()> {
+        self.insert(path, content);
+        Ok(())
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        let file = self.files.remove(from).ok_or_else(not_found)?;
+        self.files.insert(to.to_path_buf(), file);
+        Ok(())
+    }
+
+    fn delete(&mut self, path: &Path) -> io::Result<()> {
+        self.files.remove(path).map(|_| ()).ok_or_else(not_found)
+    }
+
+    fn set_mode(&mut self, path: &Path, mode: u32) -> io::Result<()> {
+        let (_, file_mode) = self.files.get_mut(path).ok_or_else(not_found)?;
+        *file_mode = mode;
+        Ok(())
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<Option<String>> {
+        Ok(self
+            .files
+            .get(path)
+            .filter(|(_, mode)| *mode == SYMLINK_MODE)
+            .map(|(target, _)| target.clone()))
+    }
+
+    fn symlink(&mut self, path: &Path, target: &str) -> io::Result<()> {
+        self.insert_symlink(path, target);
+        Ok(())
+    }
+}
+
+impl<P: Into<PathBuf>, S: Into<String>> FromIterator<(P, S)> for MemoryFs {
+    fn from_iter<I: IntoIterator<Item = (P, S)>>(files: I) -> Self {
+        let mut fs = MemoryFs::new();
+        for (path, content) in files {
+            fs.insert(path, content);
+        }
+        fs
+    }
+}
+
+fn not_found() -> io::Error {
+    io::ErrorKind::NotFound.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_memory_fs() {
+        let mut fs: MemoryFs = [("a.txt", "a\n"), ("b/c.txt", "c\n")].into_iter().collect();
+        assert_eq!(fs.read(Path::new("a.txt")).unwrap(), "a\n");
+        assert_eq!(
+            fs.read(Path::new("missing")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        fs.set_mode(Path::new("a.txt"), 0o755).unwrap();
+        fs.write(Path::new("a.txt"), "A\n").unwrap();
+        assert_eq!(
+            (fs.get("a.txt"), fs.mode("a.txt")),
+            (Some("A\n"), Some(0o755))
+        );
+
+        fs.rename(Path::new("a.txt"), Path::new("d.txt")).unwrap();
+        fs.delete(Path::new("b/c.txt")).unwrap();
+        assert!(!fs.exists(Path::new("a.txt")));
+        assert_eq!(fs.paths().collect::<Vec<_>>(), [Path::new("d.txt")]);
+        assert_eq!(fs.mode("d.txt"), Some(0o755));
+        assert!(fs.delete(Path::new("b/c.txt")).is_err());
+    }
+
+    #[test]
+    fn test_memory_fs_symlinks() {
+        let mut fs = MemoryFs::new();
+        fs.symlink(Path::new("link"), "target").unwrap();
+        assert_eq!(
+            fs.read_link(Path::new("link")).unwrap().as_deref(),
+            Some("target")
+        );
+        assert_eq!(fs.mode("link"), Some(SYMLINK_MODE));
+
+        fs.write(Path::new("link"), "content\n").unwrap();
+        assert_eq!(fs.read_link(Path::new("link")).unwrap(), None);
+        assert_eq!(fs.mode("link"), Some(DEFAULT_MODE));
+        assert_eq!(fs.read_link(Path::new("missing")).unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_disk_fs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut disk = DiskFs::new(dir.path());
+        disk.write(Path::new("a/b.txt"), "b\n").unwrap();
+        assert_eq!(disk.read(Path::new("a/b.txt")).unwrap(), "b\n");
+
+        disk.set_mode(Path::new("a/b.txt"), 0o600).unwrap();
+        disk.rename(Path::new("a/b.txt"), Path::new("c/d.txt"))
+            .unwrap();
+        let metadata = fs::metadata(dir.path().join("c/d.txt")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+        disk.delete(Path::new("c/d.txt")).unwrap();
+        assert!(!disk.exists(Path::new("c/d.txt")));
+        assert!(disk.exists(Path::new("a")));
+
+        disk.symlink(Path::new("e/link"), "../a").unwrap();
+        assert_eq!(
+            disk.read_link(Path::new("e/link")).unwrap().as_deref(),
+            Some("../a")
+        );
+        disk.symlink(Path::new("e/link"), "../c").unwrap();
+        assert_eq!(
+            disk.read_link(Path::new("e/link")).unwrap().as_deref(),
+            Some("../c")
+        );
+        assert_eq!(disk.read_link(Path::new("a")).unwrap(), None);
+    }
+}
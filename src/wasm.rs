@@ -0,0 +1,123 @@
+//! JavaScript bindings, for running the crate in a browser or Node.js after building it with
+//! `cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib`
+//! and generating the JavaScript glue with `wasm-bindgen`
+//!
+//! The crate type is given on the command line rather than in the manifest, since a `cdylib`
+//! can't be built without the standard library.
+
+use js_sys::{Error, JSON, Reflect};
+use wasm_bindgen::prelude::*;
+
+use crate::applier::{ApplyError, apply};
+use crate::ast::Patch;
+use crate::generator::{DiffOptions, diff_with_options};
+use crate::parser::ParseError;
+use crate::patch_set::PatchSet;
+
+/// Parse the patches of a file, like [`PatchSet::parse`]
+///
+/// The patches are returned as an object in the schema of [`PatchSet::to_json`]. Errors are
+/// thrown as `Error` objects named `ParseError`, with the `line` and `column` where parsing
+/// failed, counting from 1, and the `offset` of that place in the text.
+#[wasm_bindgen(js_name = parsePatch)]
+pub fn parse_patch(text: &str) -> Result<JsValue, JsValue> {
+    let patches = PatchSet::parse(text).map_err(|error| parse_error(&error))?;
+    JSON::parse(&patches.to_json())
+}
+
+/// Apply the patch of a single file to its text, like [`apply`]
+///
+/// Patches that can't be parsed are thrown like [`parse_patch`] throws them. Patches that can't
+/// be applied are thrown as `Error` objects named `ApplyError`, with the `kind` of error, like
+/// `"contextMismatch"`, and the `line` of the text it occurred at, if it is known.
+#[wasm_bindgen(js_name = applyPatch)]
+pub fn apply_patch(text: &str, patch: &str) -> Result<String, JsValue> {
+    let patch = Patch::from_single(patch).map_err(|error| parse_error(&error))?;
+    apply(&patch, text).map_err(|error| {
+        let (kind, line) = apply_error_details(&error);
+        let line = line.map_or(JsValue::UNDEFINED, |line| JsValue::from_f64(line as f64));
+        error_object(
+            "ApplyError",
+            &error.to_string(),
+            &[("kind", JsValue::from_str(kind)), ("line", line)],
+        )
+    })
+}
+
+/// Produce a patch in the Unified Format turning `old` into `new`, like [`diff`](crate::diff),
+/// with `context` lines of context around the changes, 3 by default
+#[wasm_bindgen(js_name = generateDiff)]
+pub fn generate_diff(old: &str, new: &str, context: Option<usize>) -> String {
+    let options = DiffOptions {
+        context: context.unwrap_or(3),
+        ..DiffOptions::default()
+    };
+    diff_with_options(old, new, &options).to_string()
+}
+
+fn parse_error(error: &ParseError) -> JsValue {
+    error_object(
+        "ParseError",
+        &error.to_string(),
+        &[
+            ("line", JsValue::from_f64(error.line.into())),
+            ("column", JsValue::from_f64(error.column as f64)),
+            ("offset", JsValue::from_f64(error.offset as f64)),
+        ],
+    )
+}
+
+// The kind of an apply error as JavaScript names it, and the line it occurred at
+fn apply_error_details(error: &ApplyError) -> (&'static str, Option<u64>) {
+    match error {
+        ApplyError::LineOutOfBounds { line, .. } => ("lineOutOfBounds", Some(*line)),
+        ApplyError::ContextMismatch { line, .. } => ("contextMismatch", Some(*line)),
+        ApplyError::HunkNotFound { .. } => ("hunkNotFound", None),
+        ApplyError::Binary => ("binary", None),
+        ApplyError::Io(_) => ("io", None),
+        ApplyError::NoContext { line } => ("noContext", Some(*line)),
+        ApplyError::SearchLimit { line } => ("searchLimit", Some(*line)),
+        ApplyError::OverlappingHunks { line, .. } => ("overlappingHunks", Some(*line)),
+        ApplyError::Cancelled => ("cancelled", None),
+        ApplyError::IndexMismatch { .. } => ("indexMismatch", None),
+    }
+}
+
+// A JavaScript `Error` with the given name, message and additional properties
+fn error_object(name: &str, message: &str, properties: &[(&str, JsValue)]) -> JsValue {
+    let error = Error::new(message);
+    error.set_name(name);
+    for (key, value) in properties {
+        // Setting a property of a plain `Error` can't fail
+        let _ = Reflect::set(&error, &JsValue::from_str(key), value);
+    }
+    error.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    // The functions creating JavaScript values only work on wasm32 targets, so only the rest is
+    // tested here
+    #[test]
+    fn test_generate_diff() {
+        assert_eq!(
+            generate_diff("a\nb\n", "a\nc\n", Some(0)),
+            "--- old\n+++ new\n@@ -2,1 +2,1 @@\n-b\n+c"
+        );
+    }
+
+    #[test]
+    fn test_apply_error_details() {
+        let patch = Patch::from_single("--- a\n+++ b\n@@ -1,2 +1,2 @@\n x\n-y\n+z\n").unwrap();
+        let error = apply(&patch, "w\ny\n").unwrap_err();
+        assert_eq!(apply_error_details(&error), ("contextMismatch", Some(1)));
+        assert_eq!(
+            apply_error_details(&ApplyError::NoContext { line: 4 }),
+            ("noContext", Some(4))
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use std::ops::Range;
+
+use crate::ast::{Hunk, Line};
+use crate::generator::{DiffAlgorithm, Edit, edit_script};
+
+/// How finely [`Hunk::word_changes`] compares a removed line with the line added in its place
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Granularity {
+    /// Compare words, runs of whitespace and punctuation characters, like `git diff --word-diff`
+    #[default]
+    Word,
+    /// Compare characters
+    Char,
+}
+
+/// A part of a changed line, see [`WordChange`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LineSpan {
+    /// The byte offsets of the part in its line
+    pub range: Range<usize>,
+    /// Whether the part was removed from the old line or added to the new one, rather than being
+    /// in both
+    pub changed: bool,
+}
+
+/// A line removed by a hunk paired with the line added in its place, split into the parts that
+/// changed and the parts both lines have
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WordChange {
+    /// The index of the removed line in [`Hunk::lines`]
+    pub old_line: usize,
+    /// The index of the added line in [`Hunk::lines`]
+    pub new_line: usize,
+    /// The parts of the removed line, which cover all of it in order
+    pub old_spans: Vec<LineSpan>,
+    /// The parts of the added line, which cover all of it in order
+    pub new_spans: Vec<LineSpan>,
+}
+
+impl Hunk<'_> {
+    /// Find the words that changed between the removed lines of the hunk and the lines added in
+    /// their place, to highlight them like `git diff --word-diff` or a review tool would
+    ///
+    /// In every run of removed lines followed by added lines, the first removed line is paired
+    /// with the first added line, the second with the second, and so on. Lines left without a
+    /// pair changed as a whole, and have no word changes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use patch::{Granularity, LineSpan, Patch};
+    /// let patch = Patch::from_single(
+    ///     "\
+    /// --- a.rs
+    /// +++ a.rs
+    /// @@ -1 +1 @@
+    /// -let x = 1;
+    /// +let y = 1;
+    /// ",
+    /// )
+    /// .unwrap();
+    ///
+    /// let changes = patch.hunks[0].word_changes(Granularity::Word);
+    /// assert_eq!((changes[0].old_line, changes[0].new_line), (0, 1));
+    /// let changed: Vec<_> = changes[0]
+    ///     .new_spans
+    ///     .iter()
+    ///     .filter(|span| span.changed)
+    ///     .map(|span| &"let y = 1;"[span.range.clone()])
+    ///     .collect();
+    /// assert_eq!(changed, ["y"]);
+    /// ```
+    pub fn word_changes(&self, granularity: Granularity) -> Vec<WordChange> {
+        let mut changes = Vec::new();
+        let mut removed = Vec::new();
+        let mut added = 0;
+        for (index, line) in self.lines.iter().enumerate() {
+            match line {
+                Line::Remove(_) if added > 0 => {
+                    removed.clear();
+                    added = 0;
+                    removed.push(index);
+                }
+                Line::Remove(_) => removed.push(index),
+                Line::Add(new) => {
+                    if let Some(&old_line) = removed.get(added) {
+                        let Line::Remove(old) = &self.lines[old_line] else {
+                            unreachable!("bug: only removed lines are paired");
+                        };
+                        let (old_spans, new_spans) = refine(old, new, granularity);
+                        changes.push(WordChange {
+                            old_line,
+                            new_line: index,
+                            old_spans,
+                            new_spans,
+                        });
+                    }
+                    added += 1;
+                }
+                Line::Context(_) => {
+                    removed.clear();
+                    added = 0;
+                }
+            }
+        }
+        changes
+    }
+}
+
+/// Split two lines into the parts they have in common and the parts that changed between them
+pub(crate) fn refine(
+    old: &str,
+    new: &str,
+    granularity: Granularity,
+) -> (Vec<LineSpan>, Vec<LineSpan>) {
+    let old_tokens = tokens(old, granularity);
+    let new_tokens = tokens(new, granularity);
+    let mut old_spans = Spans::default();
+    let mut new_spans = Spans::default();
+    for edit in edit_script(&old_tokens, &new_tokens, DiffAlgorithm::Myers) {
+        match edit {
+            Edit::Equal(i, j) => {
+                old_spans.push(old_tokens[i].len(), false);
+                new_spans.push(new_tokens[j].len(), false);
+            }
+            Edit::Delete(i) => old_spans.push(old_tokens[i].len(), true),
+            Edit::Insert(j) => new_spans.push(new_tokens[j].len(), true),
+        }
+    }
+    (old_spans.spans, new_spans.spans)
+}
+
+// Split a line into the tokens compared with the given granularity, which make up all of it
+fn tokens(line: &str, granularity: Granularity) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let kind = |c: char| (c.is_alphanumeric() || c == '_', c.is_whitespace());
+        let len = match granularity {
+            Granularity::Word if kind(c) != (false, false) => rest
+                .find(|next| kind(next) != kind(c))
+                .unwrap_or(rest.len()),
+            _ => c.len_utf8(),
+        };
+        let (token, remaining) = rest.split_at(len);
+        tokens.push(token);
+        rest = remaining;
+    }
+    tokens
+}
+
+/// The spans of a line, built a token at a time
+#[derive(Default)]
+struct Spans {
+    spans: Vec<LineSpan>,
+    offset: usize,
+}
+
+impl Spans {
+    // Add the next token of the line, joining it with the last span if both changed or not
+    fn push(&mut self, len: usize, changed: bool) {
+        let end = self.offset + len;
+        match self.spans.last_mut() {
+            Some(last) if last.changed == changed => last.range.end = end,
+            _ => self.spans.push(LineSpan {
+                range: self.offset..end,
+                changed,
+            }),
+        }
+        self.offset = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::ast::Patch;
+
+    // The parts of a line, with the changed ones in brackets
+    fn show(line: &str, spans: &[LineSpan]) -> String {
+        spans
+            .iter()
+            .map(|span| match span.changed {
+                true => format!("[{}]", &line[span.range.clone()]),
+                false => line[span.range.clone()].to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(
+            tokens("foo_bar(x, 42)  é", Granularity::Word),
+            ["foo_bar", "(", "x", ",", " ", "42", ")", "  ", "é"]
+        );
+        assert_eq!(tokens("ab c", Granularity::Char), ["a", "b", " ", "c"]);
+        assert_eq!(tokens("", Granularity::Word), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_refine() {
+        let (old, new) = ("call(a, b);", "call(a, c, d);");
+        let (old_spans, new_spans) = refine(old, new, Granularity::Word);
+        assert_eq!(show(old, &old_spans), "call(a, [b]);");
+        assert_eq!(show(new, &new_spans), "call(a, [c, d]);");
+
+        let (old, new) = ("color", "colour");
+        let (old_spans, new_spans) = refine(old, new, Granularity::Char);
+        assert_eq!(show(old, &old_spans), "color");
+        assert_eq!(show(new, &new_spans), "colo[u]r");
+        let (_, new_spans) = refine(old, new, Granularity::Word);
+        assert_eq!(show(new, &new_spans), "[colour]");
+    }
+
+    #[test]
+    fn test_word_changes() {
+        let patch = Patch::from_single(
+            "\
+--- a.txt
++++ a.txt
+@@ -1,5 +1,5 @@
+-one two
+-three
++one 2
++three!
++added
+ context
+-removed
+-four
++4
+",
+        )
+        .unwrap();
+        let hunk = &patch.hunks[0];
+        let changes = hunk.word_changes(Granularity::Word);
+        let pairs: Vec<_> = changes
+            .iter()
+            .map(|change| (change.old_line, change.new_line))
+            .collect();
+        assert_eq!(pairs, [(0, 2), (1, 3), (6, 8)]);
+        assert_eq!(show("one 2", &changes[0].new_spans), "one [2]");
+        assert_eq!(show("three!", &changes[1].new_spans), "three[!]");
+        assert_eq!(show("removed", &changes[2].old_spans), "[removed]");
+    }
+}
@@ -0,0 +1,117 @@
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use pretty_assertions::assert_eq;
+
+const PATCH: &str = "\
+--- a/hello.txt
++++ b/hello.txt
+@@ -1,2 +1,2 @@
+ hello
+-world
++there
+--- a/docs/notes.md
++++ b/docs/notes.md
+@@ -1,1 +1,2 @@
+ notes
++more
+";
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_patch-rs"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+fn setup() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("docs")).unwrap();
+    fs::write(dir.path().join("hello.txt"), "hello\nworld\n").unwrap();
+    fs::write(dir.path().join("docs/notes.md"), "notes\n").unwrap();
+    fs::write(dir.path().join("changes.diff"), PATCH).unwrap();
+    dir
+}
+
+#[test]
+fn test_check_and_apply() {
+    let dir = setup();
+    let output = run(dir.path(), &["check", "-p1", "changes.diff"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("hello.txt")).unwrap(),
+        "hello\nworld\n"
+    );
+
+    let output = run(dir.path(), &["apply", "-p1", "changes.diff"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "patching file docs/notes.md\npatching file hello.txt\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("hello.txt")).unwrap(),
+        "hello\nthere\n"
+    );
+
+    // The patch no longer applies, but its reverse does
+    let output = run(dir.path(), &["check", "-p1", "changes.diff"]);
+    assert_eq!(output.status.code(), Some(1));
+    let output = run(dir.path(), &["apply", "-p1", "-R", "changes.diff"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("docs/notes.md")).unwrap(),
+        "notes\n"
+    );
+
+    let output = run(dir.path(), &["apply", "missing.diff"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_diff() {
+    let dir = setup();
+    fs::write(dir.path().join("new.txt"), "hello\nthere\n").unwrap();
+    let output = run(dir.path(), &["diff", "-U0", "hello.txt", "new.txt"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        "--- hello.txt\n+++ new.txt\n@@ -2,1 +2,1 @@\n-world\n+there\n"
+    );
+
+    let output = run(dir.path(), &["diff", "hello.txt", "hello.txt"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn test_stat_and_filter() {
+    let dir = setup();
+    let output = run(dir.path(), &["stat", "changes.diff"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        " hello.txt     | 2 +-
+ docs/notes.md | 1 +
+ 2 files changed, 2 insertions(+), 1 deletion(-)
+"
+    );
+
+    let output = run(dir.path(), &["filter", "-i", "docs/*.md", "changes.diff"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), &PATCH[PATCH.find("--- a/docs").unwrap()..]);
+
+    let output = run(
+        dir.path(),
+        &["filter", "-x", "**.md", "-x", "*.txt", "changes.diff"],
+    );
+    assert_eq!(stdout(&output), "");
+}
@@ -1,5 +1,7 @@
+use std::borrow::Cow;
+
 use chrono::DateTime;
-use patch::{File, FileMetadata, ParseError, Patch};
+use patch::{BinaryKind, File, FileMetadata, FileOp, Line, ParseError, Patch};
 
 use pretty_assertions::assert_eq;
 
@@ -31,7 +33,7 @@ fn test_parse() -> Result<(), ParseError<'static>> {
             meta: None
         }
     );
-    assert!(patch.end_newline);
+    assert!(patch.new_end_newline);
 
     assert_eq!(format!("{}\n", patch), sample);
 
@@ -67,13 +69,52 @@ fn test_parse_no_newline_indicator() -> Result<(), ParseError<'static>> {
             meta: None
         }
     );
-    assert!(!patch.end_newline);
+    assert!(!patch.old_end_newline && !patch.new_end_newline);
 
     assert_eq!(format!("{}\n", patch), sample);
 
     Ok(())
 }
 
+#[test]
+fn test_parse_no_newline_indicator_per_side() -> Result<(), ParseError<'static>> {
+    // Only the old file lacks a newline at the end
+    let sample = "\
+--- before.py
++++ after.py
+@@ -1,2 +1,2 @@
+ bacon
+-guido
+\\ No newline at end of file
++guido\n";
+    let patch = Patch::from_single(sample)?;
+    assert!(!patch.old_end_newline);
+    assert!(patch.new_end_newline);
+    assert_eq!(patch.hunks[0].lines.len(), 3);
+    assert_eq!(format!("{}\n", patch), sample);
+
+    let reversed = patch.reverse();
+    assert!(reversed.old_end_newline);
+    assert!(!reversed.new_end_newline);
+
+    // Both files lack it, after different lines
+    let sample = "\
+--- before.py
++++ after.py
+@@ -1,2 +1,2 @@
+ bacon
+-guido
+\\ No newline at end of file
++python
+\\ No newline at end of file\n";
+    let patch = Patch::from_single(sample)?;
+    assert!(!patch.old_end_newline);
+    assert!(!patch.new_end_newline);
+    assert_eq!(format!("{}\n", patch), sample);
+
+    Ok(())
+}
+
 #[test]
 fn test_parse_timestamps() -> Result<(), ParseError<'static>> {
     let sample = "\
@@ -107,7 +148,7 @@ fn test_parse_timestamps() -> Result<(), ParseError<'static>> {
             )),
         }
     );
-    assert!(!patch.end_newline);
+    assert!(!patch.old_end_newline && !patch.new_end_newline);
 
     // to_string() uses Display but adds no trailing newline
     assert_eq!(patch.to_string(), sample);
@@ -147,7 +188,7 @@ fn test_parse_other() -> Result<(), ParseError<'static>> {
             )),
         }
     );
-    assert!(patch.end_newline);
+    assert!(patch.new_end_newline);
 
     assert_eq!(format!("{}\n", patch), sample);
 
@@ -184,13 +225,53 @@ fn test_parse_escaped() -> Result<(), ParseError<'static>> {
             )),
         }
     );
-    assert!(patch.end_newline);
+    assert!(patch.new_end_newline);
 
     assert_eq!(format!("{}\n", patch), sample);
 
     Ok(())
 }
 
+#[test]
+fn test_parse_quoted_paths() -> Result<(), ParseError<'static>> {
+    // git quotes paths that aren't ASCII, but not paths with spaces, which it ends with a tab in
+    // the file headers instead
+    let sample = "\
+diff --git \"a/caf\\303\\251 menu.txt\" \"b/caf\\303\\251 menu.txt\"
+index 3b18e51..a042389 100644
+--- \"a/caf\\303\\251 menu.txt\"
++++ \"b/caf\\303\\251 menu.txt\"
+@@ -1 +1 @@
+-old
++new
+diff --git a/my notes.txt b/my notes.txt
+--- a/my notes.txt\t
++++ b/my notes.txt\t2024-01-01 12:00:00.000000000 +0100
+@@ -1 +1 @@
+-old
++new
+";
+    let patches = Patch::from_multiple(sample)?;
+    assert_eq!(patches[0].old.path, "a/café menu.txt");
+    assert_eq!(patches[0].git.as_ref().unwrap().new_path, "b/café menu.txt");
+    assert_eq!(
+        patches[1].old,
+        File {
+            path: "a/my notes.txt".into(),
+            meta: None,
+        }
+    );
+    assert_eq!(patches[1].new.path, "b/my notes.txt");
+    assert!(patches[1].new.timestamp().is_some());
+
+    // Paths are quoted again the way git quotes them, and paths with spaces are quoted too
+    let text: String = patches.iter().map(|patch| format!("{}\n", patch)).collect();
+    assert!(text.starts_with(&sample[..sample.find("@@").unwrap()]));
+    assert!(text.contains("\n--- \"a/my notes.txt\"\n+++ \"b/my notes.txt\"\t2024-01-01"));
+    assert_eq!(Patch::from_multiple(&text).unwrap(), patches);
+    Ok(())
+}
+
 #[test]
 fn test_parse_triple_plus_minus() -> Result<(), ParseError<'static>> {
     // Our parser has some hacky rules to make sure that lines starting with +++ or --- aren't
@@ -226,7 +307,7 @@ fn test_parse_triple_plus_minus() -> Result<(), ParseError<'static>> {
             meta: None
         }
     );
-    assert!(patch.end_newline);
+    assert!(patch.new_end_newline);
 
     assert_eq!(patch.hunks.len(), 1);
     assert_eq!(patch.hunks[0].lines.len(), 8);
@@ -278,10 +359,152 @@ fn test_parse_triple_plus_minus_hack() {
             meta: None
         }
     );
-    assert!(patch.end_newline);
+    assert!(patch.new_end_newline);
 
     assert_eq!(patch.hunks.len(), 1);
     assert_eq!(patch.hunks[0].lines.len(), 8);
 
     assert_eq!(format!("{}\n", patch), sample);
 }
+
+#[test]
+fn test_parse_git_rename_without_hunks() -> Result<(), ParseError<'static>> {
+    let sample = "\
+diff --git a/src/old.rs b/src/new.rs
+similarity index 100%
+rename from src/old.rs
+rename to src/new.rs
+diff --git a/run.sh b/run.sh
+old mode 100644
+new mode 100755
+";
+    let patches = Patch::from_multiple(sample)?;
+    assert_eq!(patches.len(), 2);
+
+    assert_eq!(
+        patches[0].operation,
+        FileOp::Rename {
+            from: "src/old.rs".into(),
+            to: "src/new.rs".into(),
+        }
+    );
+    assert_eq!(&patches[0].old.path, "a/src/old.rs");
+    assert_eq!(&patches[0].new.path, "b/src/new.rs");
+    assert!(patches[0].hunks.is_empty());
+
+    let git = patches[1].git.as_ref().unwrap();
+    assert_eq!(patches[1].operation, FileOp::Modify);
+    assert_eq!(
+        (git.old_mode, git.new_mode),
+        (Some(0o100644), Some(0o100755))
+    );
+
+    let formatted: String = patches.iter().map(|patch| format!("{}\n", patch)).collect();
+    assert_eq!(formatted, sample);
+    Ok(())
+}
+
+#[test]
+fn test_parse_git_new_file() -> Result<(), ParseError<'static>> {
+    let sample = "\
+diff --git a/hello.txt b/hello.txt
+new file mode 100644
+--- /dev/null
++++ b/hello.txt
+@@ -0,0 +1 @@
++hello
+";
+    let patch = Patch::from_single(sample)?;
+    assert_eq!(patch.operation, FileOp::Create);
+    assert_eq!(patch.git.as_ref().unwrap().new_mode, Some(0o100644));
+    assert_eq!(patch.hunks.len(), 1);
+
+    let reversed = patch.reverse();
+    assert_eq!(reversed.operation, FileOp::Delete);
+    assert_eq!(reversed.git.unwrap().old_mode, Some(0o100644));
+    Ok(())
+}
+
+#[test]
+fn test_parse_git_binary_patch() -> Result<(), ParseError<'static>> {
+    let sample = "\
+diff --git a/small.bin b/small.bin
+index df93f5f..ffdabad 100644
+GIT binary patch
+literal 15
+WcmZQ&W9Q)H;^yJy;};MV5(WSSmjJZ@
+
+literal 10
+RcmZQzWMXDvWn<^y1ONc904@Lk
+
+diff --git a/text.txt b/text.txt
+--- a/text.txt
++++ b/text.txt
+@@ -1 +1 @@
+-old
++new
+";
+    let patches = Patch::from_multiple(sample)?;
+    assert_eq!(patches.len(), 2);
+
+    let binary = patches[0].binary.as_ref().unwrap();
+    assert!(patches[0].hunks.is_empty());
+    assert_eq!(binary.forward.kind, BinaryKind::Literal);
+    assert_eq!(binary.forward.size, 15);
+    assert_eq!(binary.forward.data, ["WcmZQ&W9Q)H;^yJy;};MV5(WSSmjJZ@"]);
+    assert_eq!(binary.reverse.as_ref().unwrap().size, 10);
+    assert!(patches[1].binary.is_none());
+
+    // Everything is written back out, including the index line
+    let index = patches[0].git.as_ref().unwrap().index.as_ref().unwrap();
+    assert_eq!((&*index.old_id, &*index.new_id), ("df93f5f", "ffdabad"));
+    assert_eq!(
+        format!("{}\n", patches[0]),
+        "\
+diff --git a/small.bin b/small.bin
+index df93f5f..ffdabad 100644
+GIT binary patch
+literal 15
+WcmZQ&W9Q)H;^yJy;};MV5(WSSmjJZ@
+
+literal 10
+RcmZQzWMXDvWn<^y1ONc904@Lk
+"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parse_bytes() -> Result<(), ParseError<'static>> {
+    // Latin-1 content that isn't valid UTF-8, including in the hunk hint
+    let sample = b"\
+--- a/r\xe9sum\xe9.txt
++++ b/r\xe9sum\xe9.txt
+@@ -1,2 +1,2 @@ fa\xe7ade
+ na\xefve
+-caf\xe9
++th\xe9
+";
+    let patch = Patch::from_bytes(sample)?;
+    assert_eq!(&patch.old.path, "a/r\u{fffd}sum\u{fffd}.txt");
+    assert_eq!(*patch.hunks[0].range_hint, b" fa\xe7ade"[..]);
+    assert_eq!(
+        patch.hunks[0].lines,
+        [
+            Line::Context(Cow::Borrowed(&b"na\xefve"[..])),
+            Line::Remove(Cow::Borrowed(&b"caf\xe9"[..])),
+            Line::Add(Cow::Borrowed(&b"th\xe9"[..])),
+        ]
+    );
+
+    // Parsing valid UTF-8 as bytes finds the same patches as parsing it as text
+    let text = std::fs::read_to_string("tests/samples/git.diff").unwrap();
+    let from_text = Patch::from_multiple(&text).unwrap();
+    let from_bytes = Patch::from_multiple_bytes(text.as_bytes()).unwrap();
+    assert_eq!(from_text.len(), from_bytes.len());
+    for (text_patch, bytes_patch) in from_text.iter().zip(&from_bytes) {
+        assert_eq!(text_patch.old, bytes_patch.old);
+        assert_eq!(text_patch.hunks.len(), bytes_patch.hunks.len());
+    }
+    Ok(())
+}
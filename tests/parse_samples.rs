@@ -31,3 +31,24 @@ fn parse_samples() {
         assert_eq!(patches, patches2);
     }
 }
+
+#[cfg(feature = "std")]
+#[test]
+fn parse_samples_lossless() {
+    let options = patch::ParseOptions {
+        lossless: true,
+        ..patch::ParseOptions::default()
+    };
+    let samples_path = PathBuf::from(file!()).parent().unwrap().join("samples");
+    for file in fs::read_dir(samples_path).unwrap() {
+        let path = file.unwrap().path();
+        if path.extension().unwrap_or_default() != "diff" {
+            continue;
+        }
+
+        // Writing the patches reproduces the sample exactly, with everything parsing normalizes
+        let data = fs::read_to_string(&path).unwrap();
+        let (set, _) = patch::PatchSet::parse_with_options(&data, &options).unwrap();
+        assert_eq!(set.to_string(), data, "{:?}", path);
+    }
+}
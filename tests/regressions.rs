@@ -1,4 +1,4 @@
-use patch::{File, FileMetadata, Hunk, Line, ParseError, Patch, Range};
+use patch::{File, FileMetadata, FileOp, Hunk, Line, ParseError, Patch, Range};
 
 use pretty_assertions::assert_eq;
 
@@ -11,7 +11,7 @@ fn hunk_header_context_is_not_a_line_15() -> Result<(), ParseError<'static>> {
  x
 ";
     let patch = Patch::from_single(sample)?;
-    assert_eq!(patch.hunks[0].lines, [Line::Context("x")]);
+    assert_eq!(patch.hunks[0].lines, [Line::Context("x".into())]);
     Ok(())
 }
 
@@ -35,13 +35,21 @@ fn crlf_breaks_stuff_17() -> Result<(), ParseError<'static>> {
                 path: "new.txt".into(),
                 meta: None
             },
+            operation: FileOp::Modify,
+            git: None,
+            svn: None,
+            binary: None,
+            opaque_binary: false,
+            preamble: None,
+            raw: None,
             hunks: vec![Hunk {
                 old_range: Range { start: 0, count: 0 },
                 new_range: Range { start: 0, count: 0 },
-                range_hint: "",
-                lines: vec![Line::Context("x")],
+                range_hint: "".into(),
+                lines: vec![Line::Context("x".into())],
             }],
-            end_newline: true,
+            old_end_newline: true,
+            new_end_newline: true,
         }
     );
     Ok(())